@@ -1,8 +1,11 @@
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
-use clap::Parser;
+use anyhow::Context;
+use clap::error::ErrorKind;
+use clap::{CommandFactory, Parser};
 use nfs3_server::memfs::MemFs;
-use nfs3_server::tcp::NFSTcp;
+use nfs3_server::nfs3_types::nfs3::nfstime3;
+use nfs3_server::tcp::{FsInfoConfig, NFSTcp};
 use nfs3_server::vfs::NfsFileSystem;
 use nfs3_server::vfs::adapters::ReadOnlyAdapter;
 use tracing_appender::non_blocking::WorkerGuard;
@@ -10,6 +13,7 @@ use tracing_appender::non_blocking::WorkerGuard;
 mod logging;
 mod memfs;
 mod mirror;
+mod state;
 mod string_ext;
 mod threshold_logger;
 
@@ -20,7 +24,7 @@ mod threshold_logger;
 #[command(about = "A simple NFSv3 server implementation")]
 struct Args {
     /// Path to the directory to serve for `MirrorFs`
-    #[arg(long)]
+    #[arg(long, required_unless_present = "memfs")]
     path: Option<String>,
 
     /// Export path
@@ -54,47 +58,159 @@ struct Args {
     /// Disable console logging
     #[arg(long)]
     quiet: bool,
+
+    /// Directory to keep the server state in, so that file handles stay valid across restarts.
+    /// Not supported with `--memfs`
+    #[arg(long, conflicts_with = "memfs")]
+    state_dir: Option<PathBuf>,
+
+    /// Sign file handles so that clients can't forge them. The secret is kept in `--state-dir`
+    /// if set, otherwise a new one is generated on every start
+    #[arg(long)]
+    sign_handles: bool,
+
+    /// Keep clients inside the subdirectory of the export they mounted. Implies `--sign-handles`
+    #[arg(long)]
+    subtree_check: bool,
+
+    /// Maximum size of a READ request in bytes advertised to clients
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+    max_read_size: Option<u32>,
+
+    /// Maximum size of a WRITE request in bytes advertised to clients
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+    max_write_size: Option<u32>,
+
+    /// Preferred size of a READDIR request in bytes advertised to clients
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+    readdir_size: Option<u32>,
+
+    /// Maximum file size in bytes advertised to clients
+    #[arg(long)]
+    max_file_size: Option<u64>,
+
+    /// Granularity of the file times in nanoseconds advertised to clients
+    #[arg(long)]
+    time_delta: Option<u64>,
+}
+
+/// Listener settings that depend on the command line and the server state
+#[derive(Default)]
+struct ListenerOptions {
+    instance_id: Option<u64>,
+    handle_secret: Option<Vec<u8>>,
+    subtree_check: bool,
+    fsinfo: FsInfoConfig,
 }
 
 #[tokio::main]
-async fn main() {
+async fn main() -> anyhow::Result<()> {
     let args = Args::parse();
     let guards = logging::init_logging(&args.log_level, args.log_file.as_deref(), !args.quiet);
 
     let bind_addr = format!("{}:{}", args.bind_ip, args.bind_port);
     let export_path = args.export_name;
+    let mut options = ListenerOptions {
+        subtree_check: args.subtree_check,
+        fsinfo: FsInfoConfig {
+            rtmax: args.max_read_size,
+            wtmax: args.max_write_size,
+            dtpref: args.readdir_size,
+            maxfilesize: args.max_file_size,
+            time_delta: args.time_delta.map(nfstime_from_nanos),
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    if args.sign_handles || args.subtree_check {
+        let secret = match args.state_dir.as_deref() {
+            Some(state_dir) => {
+                create_state_dir(state_dir)?;
+                state::load_or_create_handle_secret(state_dir)
+                    .context("failed to load handle secret")?
+            }
+            None => state::random_handle_secret(),
+        };
+        options.handle_secret = Some(secret);
+    }
 
     if args.memfs {
         let memfs = MemFs::new(memfs::default_config(args.readonly))
-            .expect("failed to create memfs instance");
+            .map_err(|e| anyhow::anyhow!("failed to create memfs instance: {e:?}"))?;
         if args.readonly {
-            start_server(bind_addr, export_path, ReadOnlyAdapter::new(memfs), guards).await;
+            start_server(
+                bind_addr,
+                export_path,
+                options,
+                ReadOnlyAdapter::new(memfs),
+                guards,
+            )
+            .await
         } else {
-            start_server(bind_addr, export_path, memfs, guards).await;
+            start_server(bind_addr, export_path, options, memfs, guards).await
         }
     } else {
         let path = args
             .path
             .as_deref()
-            .unwrap_or_else(|| panic!("--path is required when not using --memfs"));
-
-        assert!(Path::new(path).exists(), "path [{path}] does not exist");
+            .expect("clap requires --path without --memfs");
+        if !Path::new(path).exists() {
+            Args::command()
+                .error(
+                    ErrorKind::ValueValidation,
+                    format!("path [{path}] does not exist"),
+                )
+                .exit();
+        }
 
-        let mirror_fs = mirror::Fs::new(path);
+        let (mirror_fs, instance_id) = open_mirror_fs(path, args.state_dir.as_deref())?;
+        options.instance_id = instance_id;
         if args.readonly {
             start_server(
                 bind_addr,
                 export_path,
+                options,
                 ReadOnlyAdapter::new(mirror_fs),
                 guards,
             )
-            .await;
+            .await
         } else {
-            start_server(bind_addr, export_path, mirror_fs, guards).await;
+            start_server(bind_addr, export_path, options, mirror_fs, guards).await
         }
     }
 }
 
+fn nfstime_from_nanos(nanos: u64) -> nfstime3 {
+    let delta = std::time::Duration::from_nanos(nanos);
+    nfstime3 {
+        seconds: u32::try_from(delta.as_secs()).unwrap_or(u32::MAX),
+        nseconds: delta.subsec_nanos(),
+    }
+}
+
+fn create_state_dir(state_dir: &Path) -> anyhow::Result<()> {
+    std::fs::create_dir_all(state_dir)
+        .with_context(|| format!("failed to create state directory {}", state_dir.display()))
+}
+
+/// Opens the mirror file system. With a state directory, the file handles and
+/// the server instance id are persisted so that clients survive restarts.
+fn open_mirror_fs(
+    path: &str,
+    state_dir: Option<&Path>,
+) -> anyhow::Result<(mirror::Fs, Option<u64>)> {
+    let Some(state_dir) = state_dir else {
+        return Ok((mirror::Fs::new(path), None));
+    };
+
+    create_state_dir(state_dir)?;
+    let instance_id = state::load_or_create_instance_id(state_dir)
+        .context("failed to load server instance id")?;
+    let mirror_fs = mirror::Fs::with_handle_database(path, state_dir.join(state::HANDLE_DATABASE))
+        .context("failed to open handle database")?;
+    Ok((mirror_fs, Some(instance_id)))
+}
+
 #[expect(
     clippy::collection_is_never_read,
     reason = "it's not expected to be read"
@@ -102,9 +218,10 @@ async fn main() {
 async fn start_server(
     bind_addr: String,
     export_name: String,
+    options: ListenerOptions,
     fs: impl NfsFileSystem + 'static,
     mut guards: Vec<WorkerGuard>,
-) {
+) -> anyhow::Result<()> {
     use nfs3_server::tcp::NFSTcpListener;
 
     let (tx, rx) = tokio::sync::oneshot::channel();
@@ -116,12 +233,24 @@ async fn start_server(
             let _ = tx.send(());
         }
     })
-    .expect("Error setting Ctrl-C handler");
+    .context("failed to set the Ctrl-C handler")?;
 
     let mut listener = NFSTcpListener::bind(&bind_addr, fs)
         .await
-        .expect("failed to bind server");
+        .with_context(|| format!("failed to bind to {bind_addr}"))?;
     listener.with_export_name(export_name);
+    if let Some(instance_id) = options.instance_id {
+        listener.with_instance_id(instance_id);
+    }
+    if let Some(secret) = options.handle_secret {
+        listener.with_handle_secret(secret);
+    }
+    if options.subtree_check {
+        listener.with_subtree_check();
+    }
+    listener
+        .with_fsinfo(options.fsinfo)
+        .context("invalid FSINFO settings")?;
     let handle_future = listener.handle_forever();
 
     tokio::select! {
@@ -133,4 +262,5 @@ async fn start_server(
         }
         _ = rx => { }
     }
+    Ok(())
 }
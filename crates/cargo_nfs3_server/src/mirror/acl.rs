@@ -0,0 +1,191 @@
+//! POSIX ACLs stored in the `system.posix_acl_access` and `system.posix_acl_default`
+//! extended attributes.
+//!
+//! The attribute value is a little-endian header with the version followed by
+//! `(tag: u16, perm: u16, id: u32)` entries. Tags use the same values as NFSACL.
+
+use std::io;
+use std::path::Path;
+
+use nfs3_server::nfs3_types::nfsacl::{ACL_GROUP, ACL_USER, NFS_ACL_MAX_ENTRIES, aclent};
+use nfs3_server::vfs::PosixAcl;
+use rustix::io::Errno;
+
+const ACCESS_ACL_XATTR: &str = "system.posix_acl_access";
+const DEFAULT_ACL_XATTR: &str = "system.posix_acl_default";
+
+const XATTR_VERSION: u32 = 2;
+const UNDEFINED_ID: u32 = u32::MAX;
+const HEADER_SIZE: usize = 4;
+const ENTRY_SIZE: usize = 8;
+
+/// Reads the ACLs of `path`. Missing ACLs, or a file system without ACL support,
+/// result in empty ACLs.
+pub fn read_acl(path: &Path) -> io::Result<PosixAcl> {
+    let access = read_xattr(path, ACCESS_ACL_XATTR)?;
+    let default = if path.symlink_metadata()?.is_dir() {
+        read_xattr(path, DEFAULT_ACL_XATTR)?
+    } else {
+        Vec::new()
+    };
+    Ok(PosixAcl { access, default })
+}
+
+/// Replaces the ACLs of `path`. Empty ACLs are removed.
+pub fn write_acl(path: &Path, acl: &PosixAcl) -> io::Result<()> {
+    write_xattr(path, ACCESS_ACL_XATTR, &acl.access)?;
+    if path.symlink_metadata()?.is_dir() {
+        write_xattr(path, DEFAULT_ACL_XATTR, &acl.default)?;
+    }
+    Ok(())
+}
+
+fn read_xattr(path: &Path, name: &str) -> io::Result<Vec<aclent>> {
+    let mut buf = vec![0; HEADER_SIZE + ENTRY_SIZE * NFS_ACL_MAX_ENTRIES];
+    match rustix::fs::lgetxattr(path, name, &mut buf[..]) {
+        Ok(size) => decode(&buf[..size]),
+        Err(Errno::NODATA | Errno::NOTSUP) => Ok(Vec::new()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+fn write_xattr(path: &Path, name: &str, entries: &[aclent]) -> io::Result<()> {
+    if !entries.is_empty() {
+        let value = encode(entries);
+        return rustix::fs::lsetxattr(path, name, &value, rustix::fs::XattrFlags::empty())
+            .map_err(Into::into);
+    }
+    match rustix::fs::lremovexattr(path, name) {
+        Ok(()) | Err(Errno::NODATA) => Ok(()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+fn encode(entries: &[aclent]) -> Vec<u8> {
+    let mut value = Vec::with_capacity(HEADER_SIZE + ENTRY_SIZE * entries.len());
+    value.extend_from_slice(&XATTR_VERSION.to_le_bytes());
+    for entry in entries {
+        let id = match entry.type_ {
+            ACL_USER | ACL_GROUP => entry.id,
+            _ => UNDEFINED_ID,
+        };
+        #[allow(clippy::cast_possible_truncation)] // validated by the server
+        {
+            value.extend_from_slice(&(entry.type_ as u16).to_le_bytes());
+            value.extend_from_slice(&(entry.perm as u16).to_le_bytes());
+        }
+        value.extend_from_slice(&id.to_le_bytes());
+    }
+    value
+}
+
+fn decode(value: &[u8]) -> io::Result<Vec<aclent>> {
+    let invalid = || io::Error::new(io::ErrorKind::InvalidData, "malformed ACL attribute");
+    let (header, entries) = value.split_at_checked(HEADER_SIZE).ok_or_else(invalid)?;
+    if header != XATTR_VERSION.to_le_bytes() || entries.len() % ENTRY_SIZE != 0 {
+        return Err(invalid());
+    }
+
+    Ok(entries
+        .chunks_exact(ENTRY_SIZE)
+        .map(|chunk| {
+            let type_ = u32::from(u16::from_le_bytes([chunk[0], chunk[1]]));
+            let perm = u32::from(u16::from_le_bytes([chunk[2], chunk[3]]));
+            let id = match type_ {
+                ACL_USER | ACL_GROUP => {
+                    u32::from_le_bytes([chunk[4], chunk[5], chunk[6], chunk[7]])
+                }
+                _ => 0,
+            };
+            aclent { type_, id, perm }
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use nfs3_server::nfs3_types::nfsacl::{ACL_GROUP_OBJ, ACL_MASK, ACL_OTHER, ACL_USER_OBJ};
+
+    use super::*;
+
+    fn entries() -> Vec<aclent> {
+        vec![
+            aclent {
+                type_: ACL_USER_OBJ,
+                id: 0,
+                perm: 7,
+            },
+            aclent {
+                type_: ACL_USER,
+                id: 1000,
+                perm: 6,
+            },
+            aclent {
+                type_: ACL_GROUP_OBJ,
+                id: 0,
+                perm: 5,
+            },
+            aclent {
+                type_: ACL_MASK,
+                id: 0,
+                perm: 7,
+            },
+            aclent {
+                type_: ACL_OTHER,
+                id: 0,
+                perm: 4,
+            },
+        ]
+    }
+
+    #[test]
+    fn test_encode_decode() {
+        let value = encode(&entries());
+        assert_eq!(value.len(), HEADER_SIZE + ENTRY_SIZE * 5);
+        assert_eq!(&value[..4], &[2, 0, 0, 0]);
+        // named user entry
+        assert_eq!(&value[12..20], &[2, 0, 6, 0, 0xe8, 0x03, 0, 0]);
+        // owner entry has no id
+        assert_eq!(&value[8..12], &[0xff; 4]);
+        assert_eq!(decode(&value).expect("valid ACL"), entries());
+    }
+
+    #[test]
+    fn test_decode_malformed() {
+        assert!(decode(&[]).is_err());
+        assert!(decode(&[1, 0, 0, 0]).is_err());
+        assert!(decode(&[2, 0, 0, 0, 1, 0]).is_err());
+        assert_eq!(decode(&[2, 0, 0, 0]).expect("valid ACL"), Vec::new());
+    }
+
+    #[test]
+    fn test_write_read_acl() {
+        let dir = tempfile::tempdir().expect("failed to create temp directory");
+        let path = dir.path().join("file");
+        std::fs::write(&path, b"content").expect("failed to write test file");
+
+        match write_acl(
+            &path,
+            &PosixAcl {
+                access: entries(),
+                default: Vec::new(),
+            },
+        ) {
+            Ok(()) => {}
+            Err(e) if e.kind() == io::ErrorKind::Unsupported => return,
+            Err(e) => panic!("failed to write ACL: {e}"),
+        }
+        assert_eq!(
+            read_acl(&path).expect("failed to read ACL").access,
+            entries()
+        );
+
+        write_acl(&path, &PosixAcl::default()).expect("failed to remove ACL");
+        assert!(
+            read_acl(&path)
+                .expect("failed to read ACL")
+                .access
+                .is_empty()
+        );
+    }
+}
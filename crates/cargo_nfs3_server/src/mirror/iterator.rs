@@ -1,3 +1,4 @@
+use std::ffi::OsString;
 use std::path::PathBuf;
 use std::sync::Arc;
 use std::time::Instant;
@@ -21,6 +22,10 @@ pub struct MirrorFsIterator {
     read_dir: Option<ReadDir>,
     /// Cookie starts as base (with unique counter) and gets incremented for each entry
     cookie: u64,
+    /// Name of the entry with `cookie`, kept so that the listing can resume before it
+    last: Option<OsString>,
+    /// Entry that was read before the iterator was cached and must be returned first
+    replay: Option<OsString>,
     /// Direct reference to the iterator cache for Drop implementation
     iterator_cache: Arc<IteratorCache>,
 }
@@ -33,6 +38,7 @@ impl MirrorFsIterator {
         dirid: FileHandleU64,
         read_dir: Option<ReadDir>,
         cookie: u64,
+        replay: Option<OsString>,
     ) -> Self {
         Self {
             root_path,
@@ -40,6 +46,8 @@ impl MirrorFsIterator {
             dirid,
             read_dir,
             cookie,
+            last: None,
+            replay,
             iterator_cache,
         }
     }
@@ -50,10 +58,15 @@ impl MirrorFsIterator {
             return NextResult::Eof;
         };
 
-        match read_dir.next_entry().await {
-            Ok(Some(entry)) => {
-                let name = entry.file_name();
-
+        let name = match self.replay.take() {
+            Some(name) => Ok(Some(name)),
+            None => read_dir
+                .next_entry()
+                .await
+                .map(|entry| entry.map(|entry| entry.file_name())),
+        };
+        match name {
+            Ok(Some(name)) => {
                 let handle = match self.cache.symbols_path(self.dirid) {
                     Ok(parent_symbols) => match self.cache.lookup(&parent_symbols, &name, false) {
                         Ok(handle) => handle,
@@ -63,6 +76,7 @@ impl MirrorFsIterator {
                 };
 
                 self.cookie += 1;
+                self.last = Some(name.clone());
 
                 NextResult::Ok((handle, name, self.cookie))
             }
@@ -78,8 +92,13 @@ impl MirrorFsIterator {
 impl Drop for MirrorFsIterator {
     fn drop(&mut self) {
         if let Some(read_dir) = self.read_dir.take() {
-            self.iterator_cache
-                .cache_state(self.dirid, self.cookie, read_dir, Instant::now());
+            self.iterator_cache.cache_state(
+                self.dirid,
+                self.cookie,
+                read_dir,
+                self.last.take(),
+                Instant::now(),
+            );
             debug!(
                 "Cached iterator state for dir_id: {} at cookie: {:#018x}",
                 self.dirid.as_u64(),
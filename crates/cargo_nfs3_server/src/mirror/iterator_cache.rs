@@ -1,8 +1,10 @@
 use std::collections::HashMap;
+use std::ffi::OsString;
 use std::sync::RwLock;
 use std::sync::atomic::{AtomicU32, Ordering};
-use std::time::{Duration, Instant};
+use std::time::{Duration, Instant, SystemTime};
 
+use nfs3_server::nfs3_types::nfs3::cookieverf3;
 use nfs3_server::vfs::FileHandleU64;
 use tokio::fs::ReadDir;
 
@@ -12,6 +14,9 @@ pub struct CachedIteratorInfo {
     pub cookie: u64,
     pub cached_at: Instant,
     pub read_dir: ReadDir,
+    /// Name of the entry with `cookie`. The server reads one entry more than fits into
+    /// a reply, so the listing may resume before it and the entry is returned again.
+    pub last: Option<OsString>,
 }
 
 /// Simple iterator cache that tracks valid iterator positions
@@ -23,6 +28,9 @@ pub struct IteratorCache {
     max_cached_per_dir: u16,
     /// Atomic counter for generating unique cookies
     cookie_counter: AtomicU32,
+    /// Cookies can only be resumed by the cache that issued them, so the verifier
+    /// identifies the cache instance rather than the directory state
+    verifier: cookieverf3,
 }
 
 impl IteratorCache {
@@ -34,9 +42,25 @@ impl IteratorCache {
             cache: RwLock::new(HashMap::new()),
             max_cached_per_dir,
             cookie_counter: AtomicU32::new(0),
+            verifier: Self::make_verifier(),
         }
     }
 
+    /// Returns the cookie verifier for the cookies generated by this cache
+    pub const fn cookieverf(&self) -> cookieverf3 {
+        self.verifier
+    }
+
+    #[allow(clippy::cast_possible_truncation)] // only the low bits matter
+    fn make_verifier() -> cookieverf3 {
+        let nanos = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .expect("failed to get system time")
+            .as_nanos() as u64;
+        // zero verifier means that cookies never expire
+        cookieverf3(nanos.max(1).to_be_bytes())
+    }
+
     /// Generates a unique base cookie for an iterator
     pub fn generate_base_cookie(&self) -> u64 {
         let counter = self.cookie_counter.fetch_add(1, Ordering::SeqCst);
@@ -44,26 +68,39 @@ impl IteratorCache {
         (u64::from(counter)) << 32
     }
 
-    /// Check if an iterator position is cached and remove it if so
+    /// Check if an iterator position is cached and remove it if so. The iterator is either
+    /// at `cookie` or one entry past it, in which case `last` holds the entry to return again.
     pub fn pop_state(&self, dir_id: FileHandleU64, cookie: u64) -> Option<CachedIteratorInfo> {
         let mut cache = self.cache.write().expect("lock is poisoned");
 
-        cache.get_mut(&dir_id).and_then(|iterators| {
-            iterators
-                .iter()
-                .position(|info| info.cookie == cookie)
-                .map(|index| iterators.swap_remove(index))
-        })
+        let iterators = cache.get_mut(&dir_id)?;
+        if let Some(index) = iterators.iter().position(|info| info.cookie == cookie) {
+            let mut info = iterators.swap_remove(index);
+            info.last = None;
+            return Some(info);
+        }
+        iterators
+            .iter()
+            .position(|info| info.last.is_some() && info.cookie.checked_sub(1) == Some(cookie))
+            .map(|index| iterators.swap_remove(index))
     }
 
     /// Cache an iterator state with `ReadDir` object
-    pub fn cache_state(&self, dir_id: FileHandleU64, cookie: u64, read_dir: ReadDir, now: Instant) {
+    pub fn cache_state(
+        &self,
+        dir_id: FileHandleU64,
+        cookie: u64,
+        read_dir: ReadDir,
+        last: Option<OsString>,
+        now: Instant,
+    ) {
         let mut cache = self.cache.write().expect("lock is poisoned");
 
         let info = CachedIteratorInfo {
             cookie,
             cached_at: now,
             read_dir,
+            last,
         };
 
         cache.entry(dir_id).or_default().push(info);
@@ -1,7 +1,10 @@
+#[cfg(target_os = "linux")]
+mod acl;
 mod iterator;
 mod iterator_cache;
 mod symbols_cache;
 
+use std::ffi::OsString;
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use std::time::Duration;
@@ -10,9 +13,11 @@ use iterator::MirrorFsIterator;
 use iterator_cache::{IteratorCache, IteratorCacheCleaner};
 use nfs3_server::fs_util::metadata_to_fattr3;
 use nfs3_server::nfs3_types::nfs3::{
-    createverf3, fattr3, filename3, nfspath3, nfsstat3, sattr3, set_gid3, set_mode3, set_size3,
-    set_uid3,
+    cookieverf3, createverf3, fattr3, filename3, nfspath3, nfsstat3, sattr3, set_gid3, set_mode3,
+    set_size3, set_uid3,
 };
+#[cfg(target_os = "linux")]
+use nfs3_server::vfs::PosixAcl;
 use nfs3_server::vfs::{
     FileHandleU64, NfsFileSystem, NfsReadFileSystem, ReadDirIterator, ReadDirPlusIterator,
     VFSCapabilities,
@@ -35,7 +40,23 @@ pub struct Fs {
 impl Fs {
     pub fn new(root: impl AsRef<Path>) -> Self {
         let root = root.as_ref().to_path_buf();
-        let cache = Arc::new(SymbolsCache::new(root.clone()));
+        let cache = SymbolsCache::new(root.clone());
+        Self::with_cache(root, cache)
+    }
+
+    /// Creates a file system that keeps file handles stable across restarts
+    /// by persisting them in a database at `db_path`.
+    pub fn with_handle_database(
+        root: impl AsRef<Path>,
+        db_path: impl AsRef<Path>,
+    ) -> std::io::Result<Self> {
+        let root = root.as_ref().to_path_buf();
+        let cache = SymbolsCache::with_database(root.clone(), db_path.as_ref())?;
+        Ok(Self::with_cache(root, cache))
+    }
+
+    fn with_cache(root: PathBuf, cache: SymbolsCache) -> Self {
+        let cache = Arc::new(cache);
         let iterator_cache = Arc::new(IteratorCache::new(Duration::from_secs(60), 20));
         let cleaner =
             IteratorCacheCleaner::new(Arc::clone(&iterator_cache), Duration::from_secs(30));
@@ -75,12 +96,31 @@ impl Fs {
         Ok((buf, start + count >= len))
     }
 
+    async fn read_into(
+        &self,
+        path: PathBuf,
+        start: u64,
+        buf: &mut [u8],
+    ) -> std::io::Result<(usize, bool)> {
+        let mut f = File::open(&path).await?;
+        let len = f.metadata().await?.len();
+        if start >= len || buf.is_empty() {
+            return Ok((0, start >= len));
+        }
+
+        let count = usize::try_from(len - start).map_or(buf.len(), |left| left.min(buf.len()));
+        f.seek(SeekFrom::Start(start)).await?;
+        f.read_exact(&mut buf[..count]).await?;
+
+        Ok((count, start + count as u64 >= len))
+    }
+
     async fn get_or_create_iterator(
         &self,
         dirid: FileHandleU64,
         cookie: u64,
     ) -> Result<MirrorFsIterator, nfsstat3> {
-        let (read_dir, cookie_value) = self.initialize_read_dir(dirid, cookie).await?;
+        let (read_dir, cookie_value, replay) = self.initialize_read_dir(dirid, cookie).await?;
 
         Ok(MirrorFsIterator::new(
             self.root.clone(),
@@ -89,16 +129,17 @@ impl Fs {
             dirid,
             Some(read_dir),
             cookie_value,
+            replay,
         ))
     }
 
     /// Initialize the `ReadDir` state based on the cookie value.
-    /// Returns (`ReadDir`, `actual_cookie`) tuple.
+    /// Returns (`ReadDir`, `actual_cookie`, `entry_to_replay`) tuple.
     async fn initialize_read_dir(
         &self,
         dirid: FileHandleU64,
         cookie: u64,
-    ) -> Result<(ReadDir, u64), nfsstat3> {
+    ) -> Result<(ReadDir, u64, Option<OsString>), nfsstat3> {
         let dir_path = {
             let relative_path = self.cache.handle_to_path(dirid)?;
             self.root.join(&relative_path)
@@ -120,13 +161,13 @@ impl Fs {
                 .await
                 .map_err(|_| nfsstat3::NFS3ERR_IO)?;
             let base = self.iterator_cache.generate_base_cookie();
-            Ok((read_dir, base))
+            Ok((read_dir, base, None))
         } else if let Some(cached_info) = self.iterator_cache.pop_state(dirid, cookie) {
             debug!(
                 "Reusing cached ReadDir for directory: {} at cookie: {cookie}",
                 dir_path.display()
             );
-            Ok((cached_info.read_dir, cached_info.cookie))
+            Ok((cached_info.read_dir, cookie, cached_info.last))
         } else {
             debug!("No cached ReadDir found for cookie {cookie}, returning BAD_COOKIE error");
             Err(nfsstat3::NFS3ERR_BAD_COOKIE)
@@ -168,6 +209,18 @@ impl NfsReadFileSystem for Fs {
         self.read(path, offset, count).await.map_err(map_io_error)
     }
 
+    async fn read_into(
+        &self,
+        id: &Self::Handle,
+        offset: u64,
+        buf: &mut [u8],
+    ) -> Result<(usize, bool), nfsstat3> {
+        let path = self.path(*id)?;
+        self.read_into(path, offset, buf)
+            .await
+            .map_err(map_io_error)
+    }
+
     async fn readdir(
         &self,
         dirid: &Self::Handle,
@@ -184,6 +237,10 @@ impl NfsReadFileSystem for Fs {
         self.get_or_create_iterator(*dirid, cookie).await
     }
 
+    async fn cookieverf(&self, _dirid: &Self::Handle) -> Result<cookieverf3, nfsstat3> {
+        Ok(self.iterator_cache.cookieverf())
+    }
+
     async fn readlink(&self, id: &Self::Handle) -> Result<nfspath3<'_>, nfsstat3> {
         let path = self.path(*id)?;
         match tokio::fs::read_link(&path).await {
@@ -198,6 +255,15 @@ impl NfsReadFileSystem for Fs {
             }
         }
     }
+
+    #[cfg(target_os = "linux")]
+    async fn getacl(&self, id: &Self::Handle) -> Result<PosixAcl, nfsstat3> {
+        let path = self.path(*id)?;
+        tokio::task::spawn_blocking(move || acl::read_acl(&path))
+            .await
+            .map_err(|_| nfsstat3::NFS3ERR_IO)?
+            .map_err(map_io_error)
+    }
 }
 
 #[expect(clippy::needless_pass_by_value)]
@@ -221,6 +287,15 @@ impl NfsFileSystem for Fs {
         VFSCapabilities::ReadWrite
     }
 
+    #[cfg(target_os = "linux")]
+    async fn setacl(&self, id: &Self::Handle, acl: PosixAcl) -> Result<(), nfsstat3> {
+        let path = self.path(*id)?;
+        tokio::task::spawn_blocking(move || acl::write_acl(&path, &acl))
+            .await
+            .map_err(|_| nfsstat3::NFS3ERR_IO)?
+            .map_err(map_io_error)
+    }
+
     async fn setattr(&self, id: &Self::Handle, setattr: sattr3) -> Result<fattr3, nfsstat3> {
         let path = self.path(*id)?;
         nfs3_server::fs_util::path_setattr(&path, &setattr).await?;
@@ -581,6 +656,48 @@ mod tests {
         assert_eq!(entries, entries2, "Results should be consistent");
     }
 
+    #[tokio::test]
+    async fn test_resume_before_the_last_entry() {
+        let (_temp_dir, fs, root_handle) =
+            create_test_fs_with_files(&["a.txt", "b.txt", "c.txt"]).await;
+        // a listing that doesn't start at the first base cookie
+        drop(
+            fs.readdir(&root_handle, 0)
+                .await
+                .expect("failed to create iterator"),
+        );
+
+        let mut iter = fs
+            .readdir(&root_handle, 0)
+            .await
+            .expect("failed to create iterator");
+        let NextResult::Ok(first) = iter.next().await else {
+            panic!("Expected an entry");
+        };
+        // the server reads one entry more than fits into the reply
+        let NextResult::Ok(second) = iter.next().await else {
+            panic!("Expected an entry");
+        };
+        assert!(first.cookie > u64::from(u32::MAX));
+        drop(iter);
+
+        let mut iter = fs
+            .readdir(&root_handle, first.cookie)
+            .await
+            .expect("Should resume before the last entry");
+        let mut names = Vec::new();
+        loop {
+            match iter.next().await {
+                NextResult::Ok(entry) => names.push(entry.name),
+                NextResult::Eof => break,
+                NextResult::Err(e) => panic!("Unexpected error: {e}"),
+            }
+        }
+        assert_eq!(names.len(), 2);
+        assert_eq!(names[0], second.name);
+        assert_ne!(names[1], first.name);
+    }
+
     #[tokio::test]
     async fn test_cookie_uniqueness() {
         let (_temp_dir, fs, root_handle) =
@@ -627,4 +744,31 @@ mod tests {
             "Should have generated unique cookies across multiple iterations"
         );
     }
+
+    #[tokio::test]
+    async fn test_cookieverf_is_stable() {
+        let (temp_dir, fs, root_handle) = create_test_fs_with_files(&["file1.txt"]).await;
+
+        let verf = fs
+            .cookieverf(&root_handle)
+            .await
+            .expect("failed to get cookie verifier");
+        assert_ne!(
+            verf,
+            cookieverf3::default(),
+            "zero verifier means that cookies never expire"
+        );
+
+        fs::write(temp_dir.path().join("file2.txt"), "content")
+            .await
+            .expect("failed to write test file");
+        let verf2 = fs
+            .cookieverf(&root_handle)
+            .await
+            .expect("failed to get cookie verifier");
+        assert_eq!(
+            verf, verf2,
+            "Verifier should not depend on directory content"
+        );
+    }
 }
@@ -0,0 +1,71 @@
+//! Append-only database that persists the handle ids assigned to paths.
+//!
+//! Each record is `id: u64 LE | len: u32 LE | path bytes`, where the path is relative to the
+//! exported root. A record truncated by a crash is dropped on load.
+
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+
+use crate::string_ext::IntoOsString;
+
+const HEADER_LEN: usize = 12;
+
+#[derive(Debug)]
+pub struct HandleDatabase {
+    file: File,
+}
+
+impl HandleDatabase {
+    /// Opens or creates the database and returns the records stored in it
+    pub fn open(path: &Path) -> io::Result<(Self, Vec<(u64, PathBuf)>)> {
+        let mut file = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .create(true)
+            .open(path)?;
+
+        let mut data = Vec::new();
+        file.read_to_end(&mut data)?;
+        let (records, valid_len) = Self::parse(&data);
+        if valid_len < data.len() {
+            tracing::warn!("dropping truncated record at the end of the handle database");
+            file.set_len(valid_len as u64)?;
+        }
+
+        Ok((Self { file }, records))
+    }
+
+    /// Appends a single record. The record is written with one call, so a crash can only
+    /// leave a truncated record at the end of the file.
+    pub fn append(&mut self, id: u64, path: &Path) -> io::Result<()> {
+        let path = path.as_os_str().as_encoded_bytes();
+        let len = u32::try_from(path.len())
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "path is too long"))?;
+
+        let mut record = Vec::with_capacity(HEADER_LEN + path.len());
+        record.extend_from_slice(&id.to_le_bytes());
+        record.extend_from_slice(&len.to_le_bytes());
+        record.extend_from_slice(path);
+        self.file.write_all(&record)
+    }
+
+    /// Returns the parsed records and the length of the data they occupy
+    fn parse(data: &[u8]) -> (Vec<(u64, PathBuf)>, usize) {
+        let mut records = Vec::new();
+        let mut rest = data;
+        while rest.len() >= HEADER_LEN {
+            let (id, tail) = rest.split_at(8);
+            let (len, tail) = tail.split_at(4);
+            let id = u64::from_le_bytes(id.try_into().expect("slice has 8 bytes"));
+            let len = u32::from_le_bytes(len.try_into().expect("slice has 4 bytes")) as usize;
+            if tail.len() < len {
+                break;
+            }
+            let (path, tail) = tail.split_at(len);
+            records.push((id, PathBuf::from(path.as_os_str())));
+            rest = tail;
+        }
+        (records, data.len() - rest.len())
+    }
+}
@@ -1,12 +1,14 @@
+mod database;
 mod path;
 mod table;
 
 use std::collections::HashMap;
 use std::ffi::OsStr;
-use std::path::PathBuf;
+use std::path::{Component, Path, PathBuf};
 use std::sync::RwLock;
 use std::sync::atomic::{AtomicU64, Ordering};
 
+use database::HandleDatabase;
 use nfs3_server::nfs3_types::nfs3::nfsstat3;
 use nfs3_server::vfs::FileHandleU64;
 use path::SymbolsPath;
@@ -20,6 +22,7 @@ struct SymbolsCacheInner {
     path_to_id: HashMap<SymbolsPath, FileHandleU64>,
     id_to_path: HashMap<FileHandleU64, SymbolsPath>,
     next_id: AtomicU64,
+    database: Option<HandleDatabase>,
 
     symbols_logger: ThresholdLogger,
     path_to_id_logger: ThresholdLogger,
@@ -36,12 +39,19 @@ impl SymbolsCacheInner {
         use std::collections::hash_map::Entry;
 
         let inner = self;
-        let symbol = inner.symbols.insert_or_resolve(name.into());
-        inner.symbols_logger.check_and_log(inner.symbols.len());
-
-        let item = parent.join(symbol);
+        // "." and ".." resolve to the ids of the directories they name, so that
+        // there is a single id per object and ".." never leaves the root
+        let item = if name == "." {
+            parent.clone()
+        } else if name == ".." {
+            parent.parent()
+        } else {
+            let symbol = inner.symbols.insert_or_resolve(name.into());
+            inner.symbols_logger.check_and_log(inner.symbols.len());
+            parent.join(symbol)
+        };
 
-        let entry = inner.path_to_id.entry(item);
+        let entry = inner.path_to_id.entry(item.clone());
         let vacant_entry = match entry {
             Entry::Occupied(entry) => return Ok(*entry.get()),
             Entry::Vacant(entry) => entry,
@@ -50,19 +60,23 @@ impl SymbolsCacheInner {
         // Entry doesn't exist, need to create it
         if check_path {
             let test_path_parent = inner.symbols.resolve_path(parent);
-            let mut test_path = root.join(test_path_parent);
-            if !test_path.exists() {
+            if !root.join(test_path_parent).exists() {
                 return Err(nfsstat3::NFS3ERR_BADHANDLE);
             }
-            test_path.push(name);
-            if !test_path.exists() {
+            if !root.join(inner.symbols.resolve_path(&item)).exists() {
                 return Err(nfsstat3::NFS3ERR_NOENT);
             }
         }
 
         let id = FileHandleU64::new(inner.next_id.fetch_add(1, Ordering::Relaxed));
         vacant_entry.insert(id);
-        inner.id_to_path.insert(id, parent.join(symbol));
+        if let Some(database) = &mut inner.database {
+            let path = inner.symbols.resolve_path(&item);
+            if let Err(e) = database.append(id.as_u64(), &path) {
+                tracing::warn!(id = id.as_u64(), path = %path.display(), error = %e, "failed to persist handle");
+            }
+        }
+        inner.id_to_path.insert(id, item);
 
         inner
             .path_to_id_logger
@@ -70,6 +84,21 @@ impl SymbolsCacheInner {
 
         Ok(id)
     }
+
+    /// Restores an id loaded from the handle database
+    fn restore(&mut self, id: FileHandleU64, path: &Path) {
+        let mut item = SymbolsPath::new();
+        for component in path.components() {
+            let Component::Normal(name) = component else {
+                tracing::warn!(path = %path.display(), "ignoring invalid path in handle database");
+                return;
+            };
+            item = item.join(self.symbols.insert_or_resolve(name.into()));
+        }
+        self.path_to_id.insert(item.clone(), id);
+        self.id_to_path.insert(id, item);
+        self.next_id.fetch_max(id.as_u64() + 1, Ordering::Relaxed);
+    }
 }
 
 #[derive(Debug)]
@@ -95,6 +124,7 @@ impl SymbolsCache {
             path_to_id,
             id_to_path,
             next_id: AtomicU64::new(Self::ROOT_ID.as_u64() + 1),
+            database: None,
             symbols_logger: ThresholdLogger::new("symbols_table"),
             path_to_id_logger: ThresholdLogger::new("path_to_id_map"),
         };
@@ -105,6 +135,26 @@ impl SymbolsCache {
         }
     }
 
+    /// Creates a cache that persists the assigned ids in a database at `db_path`,
+    /// so that the same paths get the same handles after a restart.
+    pub fn with_database(root: PathBuf, db_path: &Path) -> std::io::Result<Self> {
+        let (database, records) = HandleDatabase::open(db_path)?;
+        let cache = Self::new(root);
+        {
+            let mut inner = cache.inner.write().expect("lock is poisoned");
+            for (id, path) in &records {
+                inner.restore(FileHandleU64::new(*id), path);
+            }
+            inner.database = Some(database);
+        }
+        tracing::info!(
+            "loaded {} handles from {}",
+            records.len(),
+            db_path.display()
+        );
+        Ok(cache)
+    }
+
     pub fn symbols_path(&self, id: FileHandleU64) -> Result<SymbolsPath, nfsstat3> {
         let inner = self.inner.read().expect("lock is poisoned");
         inner
@@ -146,3 +196,122 @@ impl SymbolsCache {
             .lookup(&self.root, parent, name, check_path)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use tempfile::tempdir;
+
+    use super::*;
+
+    #[test]
+    fn test_handles_survive_restart() {
+        let root = tempdir().expect("failed to create temp directory");
+        let state = tempdir().expect("failed to create temp directory");
+        let db_path = state.path().join("handles.db");
+        std::fs::create_dir(root.path().join("dir")).expect("failed to create dir");
+        std::fs::write(root.path().join("dir/file"), "content").expect("failed to write file");
+
+        let (dir_id, file_id) = {
+            let cache = SymbolsCache::with_database(root.path().to_path_buf(), &db_path)
+                .expect("failed to open database");
+            let dir_id = cache
+                .lookup_by_id(SymbolsCache::ROOT_ID, OsStr::new("dir"), true)
+                .expect("failed to lookup dir");
+            let file_id = cache
+                .lookup_by_id(dir_id, OsStr::new("file"), true)
+                .expect("failed to lookup file");
+            (dir_id, file_id)
+        };
+
+        let cache = SymbolsCache::with_database(root.path().to_path_buf(), &db_path)
+            .expect("failed to reopen database");
+        assert_eq!(
+            cache
+                .handle_to_path(file_id)
+                .expect("handle is not restored"),
+            Path::new("dir/file")
+        );
+        assert_eq!(
+            cache
+                .lookup_by_id(SymbolsCache::ROOT_ID, OsStr::new("dir"), true)
+                .expect("failed to lookup dir"),
+            dir_id
+        );
+
+        // new ids don't collide with the restored ones
+        std::fs::write(root.path().join("other"), "content").expect("failed to write file");
+        let other_id = cache
+            .lookup_by_id(SymbolsCache::ROOT_ID, OsStr::new("other"), true)
+            .expect("failed to lookup file");
+        assert!(other_id.as_u64() > file_id.as_u64());
+    }
+
+    #[test]
+    fn test_truncated_record_is_dropped() {
+        let root = tempdir().expect("failed to create temp directory");
+        let state = tempdir().expect("failed to create temp directory");
+        let db_path = state.path().join("handles.db");
+        std::fs::write(root.path().join("file"), "content").expect("failed to write file");
+
+        let file_id = {
+            let cache = SymbolsCache::with_database(root.path().to_path_buf(), &db_path)
+                .expect("failed to open database");
+            cache
+                .lookup_by_id(SymbolsCache::ROOT_ID, OsStr::new("file"), true)
+                .expect("failed to lookup file")
+        };
+        let mut data = std::fs::read(&db_path).expect("failed to read database");
+        let valid_len = data.len();
+        data.extend_from_slice(&[1, 2, 3]);
+        std::fs::write(&db_path, data).expect("failed to write database");
+
+        let cache = SymbolsCache::with_database(root.path().to_path_buf(), &db_path)
+            .expect("failed to reopen database");
+        assert_eq!(
+            cache
+                .handle_to_path(file_id)
+                .expect("handle is not restored"),
+            Path::new("file")
+        );
+        assert_eq!(
+            std::fs::metadata(&db_path)
+                .expect("failed to stat database")
+                .len(),
+            valid_len as u64
+        );
+    }
+
+    #[test]
+    fn test_dot_entries_resolve_to_existing_ids() {
+        let root = tempdir().expect("failed to create temp directory");
+        std::fs::create_dir_all(root.path().join("dir/sub")).expect("failed to create dirs");
+        let cache = SymbolsCache::new(root.path().to_path_buf());
+        let root_id = SymbolsCache::ROOT_ID;
+
+        let dir_id = cache
+            .lookup_by_id(root_id, OsStr::new("dir"), true)
+            .expect("failed to lookup dir");
+        let sub_id = cache
+            .lookup_by_id(dir_id, OsStr::new("sub"), true)
+            .expect("failed to lookup sub");
+        let lookup = |id, name| {
+            cache
+                .lookup_by_id(id, OsStr::new(name), true)
+                .expect("failed to lookup")
+        };
+        assert_eq!(lookup(dir_id, "."), dir_id);
+        assert_eq!(lookup(sub_id, ".."), dir_id);
+        assert_eq!(lookup(lookup(sub_id, "."), ".."), dir_id);
+        assert_eq!(lookup(dir_id, ".."), root_id);
+
+        // ".." of the root is the root itself, not the directory above it
+        assert_eq!(lookup(root_id, ".."), root_id);
+        assert_eq!(lookup(root_id, "."), root_id);
+        assert_eq!(
+            cache
+                .handle_to_path(lookup(root_id, ".."))
+                .expect("no path"),
+            Path::new("")
+        );
+    }
+}
@@ -16,6 +16,13 @@ impl SymbolsPath {
         new_path
     }
 
+    /// Returns the parent path, or the path itself for the root
+    pub fn parent(&self) -> Self {
+        let mut parent = self.clone();
+        parent.0.pop();
+        parent
+    }
+
     pub fn symbols(&self) -> impl Iterator<Item = Symbol> {
         self.0.iter().copied()
     }
@@ -0,0 +1,64 @@
+//! Server state that is kept between restarts in the `--state-dir` directory
+
+use std::io::{self, Write};
+use std::path::Path;
+use std::time::SystemTime;
+
+const INSTANCE_ID: &str = "instance_id";
+const HANDLE_SECRET: &str = "handle_secret";
+const HANDLE_SECRET_LEN: usize = 32;
+pub const HANDLE_DATABASE: &str = "handles.db";
+
+/// Reads the server instance id from the state directory.
+/// A new id is generated and stored if the directory doesn't have one yet.
+#[allow(clippy::cast_possible_truncation)] // it's ok to truncate the instance id
+pub fn load_or_create_instance_id(state_dir: &Path) -> io::Result<u64> {
+    let path = state_dir.join(INSTANCE_ID);
+    match std::fs::read_to_string(&path) {
+        Ok(content) => content
+            .trim()
+            .parse()
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e)),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            let instance_id = SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .expect("failed to get system time")
+                .as_millis() as u64;
+            std::fs::write(&path, format!("{instance_id}\n"))?;
+            tracing::info!("created new server instance id {instance_id}");
+            Ok(instance_id)
+        }
+        Err(e) => Err(e),
+    }
+}
+
+/// Generates a random secret for signing file handles
+pub fn random_handle_secret() -> Vec<u8> {
+    let mut secret = vec![0; HANDLE_SECRET_LEN];
+    getrandom::fill(&mut secret).expect("failed to generate handle secret");
+    secret
+}
+
+/// Reads the file handle secret from the state directory.
+/// A new random secret is generated and stored if the directory doesn't have one yet.
+pub fn load_or_create_handle_secret(state_dir: &Path) -> io::Result<Vec<u8>> {
+    let path = state_dir.join(HANDLE_SECRET);
+    match std::fs::read(&path) {
+        Ok(secret) if secret.is_empty() => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "handle secret is empty",
+        )),
+        Ok(secret) => Ok(secret),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            let secret = random_handle_secret();
+            let mut options = std::fs::OpenOptions::new();
+            options.write(true).create_new(true);
+            #[cfg(unix)]
+            std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+            options.open(&path)?.write_all(&secret)?;
+            tracing::info!("created new handle secret");
+            Ok(secret)
+        }
+        Err(e) => Err(e),
+    }
+}
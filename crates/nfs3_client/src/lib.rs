@@ -7,6 +7,8 @@ pub mod io;
 pub(crate) mod mount;
 pub mod net;
 pub(crate) mod nfs;
+pub(crate) mod nlm;
+pub(crate) mod nsm;
 pub(crate) mod portmapper;
 pub mod rpc;
 
@@ -18,9 +20,15 @@ pub mod tokio;
 #[cfg_attr(docsrs, doc(cfg(feature = "smol")))]
 pub mod smol;
 
+#[cfg(feature = "tls")]
+#[cfg_attr(docsrs, doc(cfg(feature = "tls")))]
+pub mod tls;
+
 pub use connect::*;
 pub use mount::*;
 pub use nfs::*;
 /// Re-export of `nfs3_types` for convenience
 pub use nfs3_types;
+pub use nlm::*;
+pub use nsm::*;
 pub use portmapper::*;
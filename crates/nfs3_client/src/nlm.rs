@@ -0,0 +1,122 @@
+use nfs3_types::nlm::{
+    NLM_PROGRAM, PROGRAM, VERSION, nlm4_cancargs, nlm4_lockargs, nlm4_notify, nlm4_res,
+    nlm4_shareargs, nlm4_shareres, nlm4_testargs, nlm4_testres, nlm4_unlockargs,
+};
+use nfs3_types::rpc::opaque_auth;
+use nfs3_types::xdr_codec::{Pack, Unpack, Void};
+
+use crate::error::Error;
+use crate::io::{AsyncRead, AsyncWrite};
+use crate::rpc::RpcClient;
+
+/// Client for the Network Lock Manager (NLM v4) service
+#[derive(Debug)]
+pub struct NlmClient<IO> {
+    rpc: RpcClient<IO>,
+}
+
+impl<IO> NlmClient<IO>
+where
+    IO: AsyncRead + AsyncWrite + Send,
+{
+    /// Create a new NLM client.
+    pub fn new(io: IO) -> Self {
+        Self {
+            rpc: RpcClient::new(io),
+        }
+    }
+
+    /// Create a new NLM client with custom credential and verifier.
+    pub fn new_with_auth(
+        io: IO,
+        credential: opaque_auth<'static>,
+        verifier: opaque_auth<'static>,
+    ) -> Self {
+        Self {
+            rpc: RpcClient::new_with_auth(io, credential, verifier),
+        }
+    }
+
+    pub async fn null(&mut self) -> Result<(), Error> {
+        let _ = self
+            .call::<Void, Void>(NLM_PROGRAM::NLMPROC4_NULL, &Void)
+            .await?;
+        Ok(())
+    }
+
+    pub async fn test(
+        &mut self,
+        args: &nlm4_testargs<'_, '_, '_, '_>,
+    ) -> Result<nlm4_testres<'static, 'static>, Error> {
+        self.call(NLM_PROGRAM::NLMPROC4_TEST, args).await
+    }
+
+    pub async fn lock(
+        &mut self,
+        args: &nlm4_lockargs<'_, '_, '_, '_>,
+    ) -> Result<nlm4_res<'static>, Error> {
+        self.call(NLM_PROGRAM::NLMPROC4_LOCK, args).await
+    }
+
+    pub async fn cancel(
+        &mut self,
+        args: &nlm4_cancargs<'_, '_, '_, '_>,
+    ) -> Result<nlm4_res<'static>, Error> {
+        self.call(NLM_PROGRAM::NLMPROC4_CANCEL, args).await
+    }
+
+    pub async fn unlock(
+        &mut self,
+        args: &nlm4_unlockargs<'_, '_, '_, '_>,
+    ) -> Result<nlm4_res<'static>, Error> {
+        self.call(NLM_PROGRAM::NLMPROC4_UNLOCK, args).await
+    }
+
+    pub async fn granted(
+        &mut self,
+        args: &nlm4_testargs<'_, '_, '_, '_>,
+    ) -> Result<nlm4_res<'static>, Error> {
+        self.call(NLM_PROGRAM::NLMPROC4_GRANTED, args).await
+    }
+
+    pub async fn share(
+        &mut self,
+        args: &nlm4_shareargs<'_, '_, '_, '_>,
+    ) -> Result<nlm4_shareres<'static>, Error> {
+        self.call(NLM_PROGRAM::NLMPROC4_SHARE, args).await
+    }
+
+    pub async fn unshare(
+        &mut self,
+        args: &nlm4_shareargs<'_, '_, '_, '_>,
+    ) -> Result<nlm4_shareres<'static>, Error> {
+        self.call(NLM_PROGRAM::NLMPROC4_UNSHARE, args).await
+    }
+
+    /// Acquires a lock without monitoring the client host, so the lock isn't released
+    /// when the client reboots
+    pub async fn nm_lock(
+        &mut self,
+        args: &nlm4_lockargs<'_, '_, '_, '_>,
+    ) -> Result<nlm4_res<'static>, Error> {
+        self.call(NLM_PROGRAM::NLMPROC4_NM_LOCK, args).await
+    }
+
+    /// Releases all locks and share reservations of a host
+    pub async fn free_all(&mut self, args: &nlm4_notify<'_>) -> Result<(), Error> {
+        let _ = self
+            .call::<nlm4_notify, Void>(NLM_PROGRAM::NLMPROC4_FREE_ALL, args)
+            .await?;
+        Ok(())
+    }
+
+    async fn call<C, R>(&mut self, proc: NLM_PROGRAM, args: &C) -> Result<R, Error>
+    where
+        R: Unpack,
+        C: Pack + Send + Sync,
+    {
+        self.rpc
+            .call::<C, R>(PROGRAM, VERSION, proc as u32, args)
+            .await
+    }
+}
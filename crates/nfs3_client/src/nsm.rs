@@ -0,0 +1,65 @@
+use nfs3_types::nsm::{
+    PROGRAM, SM_PROGRAM, VERSION, mon, mon_id, my_id, sm_name, sm_stat, sm_stat_res, stat_chge,
+};
+use nfs3_types::xdr_codec::{Pack, Unpack, Void};
+
+use crate::error::Error;
+use crate::io::{AsyncRead, AsyncWrite};
+use crate::rpc::RpcClient;
+
+/// Client for the Network Status Monitor service
+#[derive(Debug)]
+pub struct NsmClient<IO> {
+    rpc: RpcClient<IO>,
+}
+
+impl<IO> NsmClient<IO>
+where
+    IO: AsyncRead + AsyncWrite + Send,
+{
+    /// Create a new NSM client.
+    pub fn new(io: IO) -> Self {
+        Self {
+            rpc: RpcClient::new(io),
+        }
+    }
+
+    pub async fn null(&mut self) -> Result<(), Error> {
+        let _ = self.call::<Void, Void>(SM_PROGRAM::SM_NULL, &Void).await?;
+        Ok(())
+    }
+
+    pub async fn stat(&mut self, args: &sm_name<'_>) -> Result<sm_stat_res, Error> {
+        self.call(SM_PROGRAM::SM_STAT, args).await
+    }
+
+    pub async fn mon(&mut self, args: &mon<'_, '_>) -> Result<sm_stat_res, Error> {
+        self.call(SM_PROGRAM::SM_MON, args).await
+    }
+
+    pub async fn unmon(&mut self, args: &mon_id<'_, '_>) -> Result<sm_stat, Error> {
+        self.call(SM_PROGRAM::SM_UNMON, args).await
+    }
+
+    pub async fn unmon_all(&mut self, args: &my_id<'_>) -> Result<sm_stat, Error> {
+        self.call(SM_PROGRAM::SM_UNMON_ALL, args).await
+    }
+
+    /// Tells the status monitor that a host changed its state, e.g. rebooted
+    pub async fn notify(&mut self, args: &stat_chge<'_>) -> Result<(), Error> {
+        let _ = self
+            .call::<stat_chge, Void>(SM_PROGRAM::SM_NOTIFY, args)
+            .await?;
+        Ok(())
+    }
+
+    async fn call<C, R>(&mut self, proc: SM_PROGRAM, args: &C) -> Result<R, Error>
+    where
+        R: Unpack,
+        C: Pack + Send + Sync,
+    {
+        self.rpc
+            .call::<C, R>(PROGRAM, VERSION, proc as u32, args)
+            .await
+    }
+}
@@ -3,10 +3,10 @@
 use std::fmt::Debug;
 
 use nfs3_types::rpc::{
-    RPC_VERSION_2, accept_stat_data, call_body, fragment_header, msg_body, opaque_auth, reply_body,
-    rpc_msg,
+    RPC_VERSION_2, STARTTLS_VERIFIER, accept_stat_data, auth_flavor, call_body, fragment_header,
+    msg_body, opaque_auth, reply_body, rpc_msg,
 };
-use nfs3_types::xdr_codec::{Pack, Unpack};
+use nfs3_types::xdr_codec::{Opaque, Pack, Unpack, Void};
 
 use crate::error::{Error, RpcError};
 use crate::io::{AsyncRead, AsyncWrite};
@@ -79,7 +79,39 @@ where
         self.xid = self.xid.wrapping_add(1);
 
         Self::send_call(&mut self.io, &msg, args).await?;
-        Self::recv_reply::<R>(&mut self.io, msg.xid).await
+        let (reply, _) = Self::recv_reply::<R>(&mut self.io, msg.xid).await?;
+        Ok(reply)
+    }
+
+    /// Sends an `AUTH_TLS` probe as described in RFC 9289
+    ///
+    /// Returns `true` if the server replied with the `STARTTLS` verifier. In this case,
+    /// the caller must start a TLS handshake on the same connection.
+    pub async fn probe_tls(&mut self, prog: u32, vers: u32) -> Result<bool, Error> {
+        let call = call_body {
+            rpcvers: RPC_VERSION_2,
+            prog,
+            vers,
+            proc: 0,
+            cred: opaque_auth {
+                flavor: auth_flavor::AUTH_TLS,
+                body: Opaque::borrowed(&[]),
+            },
+            verf: opaque_auth::default(),
+        };
+        let msg = rpc_msg {
+            xid: self.xid,
+            body: msg_body::CALL(call),
+        };
+        self.xid = self.xid.wrapping_add(1);
+
+        Self::send_call(&mut self.io, &msg, &Void).await?;
+        match Self::recv_reply::<Void>(&mut self.io, msg.xid).await {
+            Ok((Void, verifier)) => Ok(verifier.body.as_ref() == STARTTLS_VERIFIER),
+            // servers without TLS support may reject the unknown flavor
+            Err(Error::Rpc(RpcError::Auth)) => Ok(false),
+            Err(e) => Err(e),
+        }
     }
 
     async fn send_call<T>(io: &mut IO, msg: &rpc_msg<'_, '_>, args: &T) -> Result<(), Error>
@@ -106,7 +138,7 @@ where
         Ok(())
     }
 
-    async fn recv_reply<T>(io: &mut IO, xid: u32) -> Result<T, Error>
+    async fn recv_reply<T>(io: &mut IO, xid: u32) -> Result<(T, opaque_auth<'static>), Error>
     where
         T: Unpack,
     {
@@ -150,6 +182,6 @@ where
             }
             .into());
         }
-        Ok(final_value)
+        Ok((final_value, reply.verf))
     }
 }
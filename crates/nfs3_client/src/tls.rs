@@ -0,0 +1,89 @@
+//! RPC-with-TLS (RFC 9289) connector based on Tokio and rustls
+
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use tokio::net::TcpStream;
+use tokio_rustls::client::TlsStream;
+pub use tokio_rustls::rustls;
+use tokio_rustls::rustls::ClientConfig;
+use tokio_rustls::rustls::pki_types::ServerName;
+
+use crate::net::Connector;
+use crate::rpc::RpcClient;
+use crate::tokio::{TokioConnector, TokioIo};
+
+/// Connector for RPC-with-TLS
+///
+/// Every connection starts with an `AUTH_TLS` probe. If the server answers with `STARTTLS`,
+/// the connector runs a TLS handshake and returns the encrypted stream. Servers without
+/// TLS support are reported as [`std::io::ErrorKind::Unsupported`] errors, the connector
+/// never falls back to plain text.
+///
+/// For mutual authentication, configure a client certificate in the [`ClientConfig`].
+pub struct TlsConnector {
+    config: Arc<ClientConfig>,
+    server_name: ServerName<'static>,
+    probe_program: (u32, u32),
+}
+
+impl TlsConnector {
+    /// Creates a new connector. `server_name` is the name the server certificate is
+    /// verified against.
+    #[must_use]
+    pub const fn new(config: Arc<ClientConfig>, server_name: ServerName<'static>) -> Self {
+        Self {
+            config,
+            server_name,
+            probe_program: (nfs3_types::nfs3::PROGRAM, nfs3_types::nfs3::VERSION),
+        }
+    }
+
+    /// Sets the RPC program and version used for the `AUTH_TLS` probe.
+    /// The default is `NFSv3`.
+    #[must_use]
+    pub const fn probe_program(mut self, prog: u32, vers: u32) -> Self {
+        self.probe_program = (prog, vers);
+        self
+    }
+
+    async fn starttls(&self, mut stream: TcpStream) -> std::io::Result<TlsStream<TcpStream>> {
+        let (prog, vers) = self.probe_program;
+        let mut rpc = RpcClient::new(TokioIo::new(&mut stream));
+        let accepted = rpc
+            .probe_tls(prog, vers)
+            .await
+            .map_err(std::io::Error::other)?;
+        if !accepted {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::Unsupported,
+                "server does not support RPC-with-TLS",
+            ));
+        }
+
+        tokio_rustls::TlsConnector::from(Arc::clone(&self.config))
+            .connect(self.server_name.clone(), stream)
+            .await
+    }
+}
+
+impl Connector for TlsConnector {
+    type Connection = TokioIo<TlsStream<TcpStream>>;
+
+    async fn connect(&self, addr: SocketAddr) -> std::io::Result<Self::Connection> {
+        let stream = TokioConnector.connect(addr).await?.into_inner();
+        self.starttls(stream).await.map(TokioIo::new)
+    }
+
+    async fn connect_with_port(
+        &self,
+        addr: SocketAddr,
+        local_port: u16,
+    ) -> std::io::Result<Self::Connection> {
+        let stream = TokioConnector
+            .connect_with_port(addr, local_port)
+            .await?
+            .into_inner();
+        self.starttls(stream).await.map(TokioIo::new)
+    }
+}
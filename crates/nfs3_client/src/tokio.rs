@@ -18,6 +18,11 @@ impl<T> TokioIo<T> {
     pub const fn new(inner: T) -> Self {
         Self(inner)
     }
+
+    /// Returns the wrapped value
+    pub fn into_inner(self) -> T {
+        self.0
+    }
 }
 
 impl<T> AsyncRead for TokioIo<T>
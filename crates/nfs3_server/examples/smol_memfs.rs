@@ -0,0 +1,52 @@
+use nfs3_server::server::NFSServer;
+use nfs3_server::smol::{SmolIo, SmolRuntime};
+use smol::net::TcpListener;
+
+const HOSTPORT: u16 = 11111;
+
+// Same as the `memfs` example, but runs on smol instead of Tokio.
+//
+// To mount the NFS server on Linux, use the following command:
+// mount -t nfs -o nolock,vers=3,tcp,port=11111,mountport=11111,soft 127.0.0.1:/ /mnt/nfs
+//
+// Usage:
+// cargo run --example smol_memfs --no-default-features --features memfs,smol -- [bind_ip] [bind_port]
+fn main() -> anyhow::Result<()> {
+    tracing_subscriber::fmt()
+        .with_max_level(tracing::Level::DEBUG)
+        .with_writer(std::io::stderr)
+        .init();
+
+    let args = std::env::args().collect::<Vec<_>>();
+    let bind_ip = args.get(1).map_or("0.0.0.0", std::string::String::as_str);
+
+    let bind_port = args
+        .get(2)
+        .and_then(|s| s.parse::<u16>().ok())
+        .unwrap_or(HOSTPORT);
+
+    let mut config = nfs3_server::memfs::MemFsConfig::default();
+    config.add_file("/a.txt", b"hello world\n");
+    config.add_dir("/a directory");
+    let memfs = nfs3_server::memfs::MemFs::new(config).expect("failed to create memfs instance");
+
+    smol::block_on(async {
+        let listener = TcpListener::bind((bind_ip, bind_port)).await?;
+        let mut server = NFSServer::new(memfs);
+        server.set_local_port(listener.local_addr()?.port());
+        // needed only when the `tokio` feature is enabled too, smol is the default otherwise
+        server.with_runtime(SmolRuntime::new());
+
+        loop {
+            let (stream, peer_addr) = listener.accept().await?;
+            stream.set_nodelay(true)?;
+            let connection = server.serve_connection(SmolIo::new(stream), peer_addr.to_string());
+            smol::spawn(async move {
+                if let Err(e) = connection.await {
+                    tracing::debug!("Connection closed: {e}");
+                }
+            })
+            .detach();
+        }
+    })
+}
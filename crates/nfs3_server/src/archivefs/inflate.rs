@@ -0,0 +1,207 @@
+//! Deflate and gzip decoder that can resume from snapshots of its state
+
+use std::io::{self, BufRead, Read};
+use std::ops::Range;
+use std::sync::Arc;
+
+use miniz_oxide::inflate::TINFLStatus;
+use miniz_oxide::inflate::core::inflate_flags::TINFL_FLAG_HAS_MORE_INPUT;
+use miniz_oxide::inflate::core::{DecompressorOxide, TINFL_LZ_DICT_SIZE, decompress};
+
+use super::stream::{Input, SeekPoint, SeekPoints};
+
+/// Size of the output window, the decoder needs the last 32 KiB of output to continue
+const WINDOW: usize = TINFL_LZ_DICT_SIZE;
+
+const GZIP_MAGIC: [u8; 3] = [0x1f, 0x8b, 8];
+const FHCRC: u8 = 0x02;
+const FEXTRA: u8 = 0x04;
+const FNAME: u8 = 0x08;
+const FCOMMENT: u8 = 0x10;
+
+/// State of the decoder in the middle of a deflate stream
+pub(super) struct Snapshot {
+    state: DecompressorOxide,
+    window: Box<[u8]>,
+}
+
+enum Phase {
+    /// At the start of a gzip member
+    Member,
+    Deflate,
+    Eof,
+}
+
+/// Streaming decoder built on the low level API of `miniz_oxide`
+///
+/// The output goes through a wrapping window of 32 KiB, so the decoder and the window are all
+/// that is needed to continue decoding from the current position of the input.
+pub(super) struct Inflater {
+    input: Input,
+    gzip: bool,
+    state: Box<DecompressorOxide>,
+    window: Box<[u8]>,
+    /// Number of decoded bytes
+    total: u64,
+    /// Decoded bytes in the window that were not returned yet
+    pending: Range<usize>,
+    phase: Phase,
+    points: Arc<SeekPoints>,
+}
+
+impl Inflater {
+    pub fn new(input: Input, gzip: bool, point: &SeekPoint, points: Arc<SeekPoints>) -> Self {
+        let (state, window, phase) = point.snapshot.as_ref().map_or_else(
+            || {
+                let phase = if gzip { Phase::Member } else { Phase::Deflate };
+                (Box::default(), vec![0; WINDOW].into_boxed_slice(), phase)
+            },
+            |snapshot| {
+                let state = Box::new(snapshot.state.clone());
+                (state, snapshot.window.clone(), Phase::Deflate)
+            },
+        );
+        Self {
+            input,
+            gzip,
+            state,
+            window,
+            total: point.output,
+            pending: 0..0,
+            phase,
+            points,
+        }
+    }
+
+    /// Returns the offset in the decoded stream of the next byte
+    pub fn position(&self) -> u64 {
+        self.total - self.pending.len() as u64
+    }
+
+    /// Starts the next gzip member, returns `false` at the end of the stream
+    fn start_member(&mut self) -> io::Result<bool> {
+        let input = self.input.offset();
+        if !read_gzip_header(&mut self.input)? {
+            return Ok(false);
+        }
+        *self.state = DecompressorOxide::default();
+        self.points.push(SeekPoint {
+            input,
+            output: self.total,
+            snapshot: None,
+        });
+        Ok(true)
+    }
+
+    #[allow(clippy::cast_possible_truncation)] // the remainder is less than the window size
+    fn inflate(&mut self) -> io::Result<()> {
+        let out_pos = (self.total % WINDOW as u64) as usize;
+        let data = self.input.fill_buf()?;
+        let exhausted = data.is_empty();
+        let (status, consumed, written) = decompress(
+            &mut self.state,
+            data,
+            &mut self.window,
+            out_pos,
+            TINFL_FLAG_HAS_MORE_INPUT,
+        );
+        self.input.consume(consumed);
+        self.pending = out_pos..out_pos + written;
+        self.total += written as u64;
+
+        match status {
+            TINFLStatus::Done if self.gzip => {
+                // CRC32 and size of the member
+                self.input.skip(8)?;
+                self.phase = Phase::Member;
+            }
+            TINFLStatus::Done => self.phase = Phase::Eof,
+            TINFLStatus::NeedsMoreInput if exhausted => {
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            }
+            TINFLStatus::NeedsMoreInput | TINFLStatus::HasMoreOutput => {
+                if self.points.is_due(self.total) {
+                    self.points.push(SeekPoint {
+                        input: self.input.offset(),
+                        output: self.total,
+                        snapshot: Some(Arc::new(Snapshot {
+                            state: (*self.state).clone(),
+                            window: self.window.clone(),
+                        })),
+                    });
+                }
+            }
+            status => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("invalid deflate stream: {status:?}"),
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Read for Inflater {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.pending.is_empty() {
+            match self.phase {
+                Phase::Eof => return Ok(0),
+                Phase::Member => {
+                    self.phase = if self.start_member()? {
+                        Phase::Deflate
+                    } else {
+                        Phase::Eof
+                    };
+                }
+                Phase::Deflate => self.inflate()?,
+            }
+        }
+        let len = self.pending.len().min(buf.len());
+        let start = self.pending.start;
+        buf[..len].copy_from_slice(&self.window[start..start + len]);
+        self.pending.start += len;
+        Ok(len)
+    }
+}
+
+/// Reads the header of a gzip member, returns `false` at the end of the stream
+fn read_gzip_header(input: &mut Input) -> io::Result<bool> {
+    let data = input.fill_buf()?;
+    match data.first() {
+        None => return Ok(false),
+        Some(&byte) if byte != GZIP_MAGIC[0] => {
+            // some tools pad the stream with zeros
+            if byte != 0 {
+                tracing::warn!("ignoring trailing data after the gzip stream");
+            }
+            return Ok(false);
+        }
+        Some(_) => {}
+    }
+
+    let mut header = [0; 10];
+    input.read_exact(&mut header)?;
+    if header[..3] != GZIP_MAGIC {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "invalid gzip header",
+        ));
+    }
+    let flags = header[3];
+    if flags & FEXTRA != 0 {
+        let mut len = [0; 2];
+        input.read_exact(&mut len)?;
+        input.skip(u16::from_le_bytes(len).into())?;
+    }
+    if flags & FNAME != 0 {
+        input.skip_until(0)?;
+    }
+    if flags & FCOMMENT != 0 {
+        input.skip_until(0)?;
+    }
+    if flags & FHCRC != 0 {
+        input.skip(2)?;
+    }
+    Ok(true)
+}
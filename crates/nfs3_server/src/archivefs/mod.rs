@@ -0,0 +1,255 @@
+//! Read-only file system that serves the contents of an archive.
+//!
+//! [`ArchiveFs`] exports a tar archive, optionally compressed with gzip or zstd, or a zip archive
+//! without unpacking it. The archive is indexed once when it's opened, then directories, files,
+//! symlinks, devices and their modes, owners and modification times are served from the index.
+//!
+//! # Random access
+//!
+//! - Uncompressed tar archives and stored zip entries are read directly from the file.
+//! - Deflate streams, which are `.tar.gz` archives and deflated zip entries, get a seek point
+//!   every [`DEFAULT_SEEK_INTERVAL`] bytes of decoded data. A seek point holds the state of the
+//!   decoder and the last 32 KiB of output, so a read decodes at most one interval of data that
+//!   it doesn't return.
+//! - Zstd streams can only be decoded from the start of a frame, so every frame is a seek point.
+//!   Archives written as many small frames, like the zstd seekable format, support fast random
+//!   access. Archives written as a single frame are decoded from the start for every backward
+//!   seek.
+//! - A few decoders are kept alive for every stream, so sequential reads continue where the
+//!   previous read stopped.
+//!
+//! The seek points of compressed tar archives are recorded while the archive is indexed. The
+//! ones of deflated zip entries are recorded the first time an entry is read.
+//!
+//! # Limitations
+//!
+//! - Sparse files of GNU tar are skipped.
+//! - Only stored and deflated zip entries can be read. Reads of encrypted entries or entries
+//!   compressed with other methods return `NFS3ERR_NOTSUPP`.
+//! - The decoders don't verify checksums, since reads usually cover only a part of a stream.
+//!
+//! # Examples
+//!
+//! [`ArchiveFs`] implements [`BlockingNfsReadFileSystem`], so it's served through a
+//! [`BlockingAdapter`](crate::vfs::adapters::BlockingAdapter):
+//!
+//! ```no_run
+//! use nfs3_server::archivefs::ArchiveFs;
+//! use nfs3_server::tcp::{NFSTcp, NFSTcpListener};
+//! use nfs3_server::vfs::adapters::BlockingAdapter;
+//!
+//! async fn run() -> anyhow::Result<()> {
+//!     let archive = ArchiveFs::open("artifacts.tar.gz")?;
+//!     let fs = BlockingAdapter::new(archive);
+//!     let listener = NFSTcpListener::bind_ro("0.0.0.0:11111", fs).await?;
+//!     listener.handle_forever().await?;
+//!     Ok(())
+//! }
+//! ```
+
+mod inflate;
+mod stream;
+mod tarball;
+mod tree;
+mod zipfile;
+mod zstd;
+
+use std::fs::File;
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::UNIX_EPOCH;
+
+use nfs3_types::nfs3::{fattr3, filename3, nfspath3, nfsstat3};
+use stream::{Codec, Stream};
+use tree::{Kind, Meta, Tree};
+
+use crate::vfs::{BlockingNfsReadFileSystem, DirEntryPlus, FileHandleU64};
+
+/// Default distance between the seek points of deflate streams, in bytes of decoded data
+pub const DEFAULT_SEEK_INTERVAL: u64 = 4 * 1024 * 1024;
+
+/// Format of an archive, detected from its first bytes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArchiveFormat {
+    Tar,
+    TarGzip,
+    TarZstd,
+    Zip,
+}
+
+impl ArchiveFormat {
+    const fn detect(magic: &[u8]) -> Self {
+        match magic {
+            [0x1f, 0x8b, ..] => Self::TarGzip,
+            // a frame or a skippable frame
+            [0x28, 0xb5, 0x2f, 0xfd] | [0x50..=0x5f, 0x2a, 0x4d, 0x18] => Self::TarZstd,
+            // a local file header or the end of an empty archive
+            [b'P', b'K', 3, 4] | [b'P', b'K', 5, 6] => Self::Zip,
+            _ => Self::Tar,
+        }
+    }
+
+    const fn codec(self) -> Codec {
+        match self {
+            Self::Tar | Self::Zip => Codec::Plain,
+            Self::TarGzip => Codec::Gzip,
+            Self::TarZstd => Codec::Zstd,
+        }
+    }
+}
+
+/// `ArchiveFs` implements [`BlockingNfsReadFileSystem`] on top of a tar or zip archive
+pub struct ArchiveFs {
+    format: ArchiveFormat,
+    archive: Stream,
+    /// Shared with the directory listings, which outlive the calls that start them
+    tree: Arc<Tree>,
+}
+
+impl ArchiveFs {
+    /// Opens and indexes an archive with seek points every [`DEFAULT_SEEK_INTERVAL`] bytes
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::with_seek_interval(path, DEFAULT_SEEK_INTERVAL)
+    }
+
+    /// Opens and indexes an archive with seek points every `interval` bytes
+    ///
+    /// Every seek point of a deflate stream takes about 43 KiB of memory, a shorter interval
+    /// makes random reads faster at the cost of more memory.
+    pub fn with_seek_interval(path: impl AsRef<Path>, interval: u64) -> io::Result<Self> {
+        let file = File::open(path)?;
+        let metadata = file.metadata()?;
+        let mtime = metadata
+            .modified()
+            .ok()
+            .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+            .map_or(0, |since| since.as_secs());
+        // used for the directories that are missing from the archive
+        let implicit = Meta {
+            mode: 0o755,
+            mtime,
+            ..Meta::default()
+        };
+
+        let file = Arc::new(file);
+        let raw = Stream::new(file, Codec::Plain, 0, metadata.len(), interval);
+        let mut magic = [0; 4];
+        let read = raw.read_at(0, &mut magic)?;
+        let format = ArchiveFormat::detect(&magic[..read]);
+        let archive = raw.slice(format.codec(), 0, metadata.len(), interval);
+
+        let mut tree = Tree::new(implicit.clone());
+        if format == ArchiveFormat::Zip {
+            zipfile::index(&archive, interval, &mut tree, &implicit)?;
+        } else {
+            tarball::index(&archive, format.codec(), &mut tree)?;
+        }
+        tree.finish();
+        Ok(Self {
+            format,
+            archive,
+            tree: Arc::new(tree),
+        })
+    }
+
+    /// Returns the detected format of the archive
+    #[must_use]
+    pub const fn format(&self) -> ArchiveFormat {
+        self.format
+    }
+}
+
+impl BlockingNfsReadFileSystem for ArchiveFs {
+    type Handle = FileHandleU64;
+
+    fn root_dir(&self) -> FileHandleU64 {
+        Tree::root_dir()
+    }
+
+    fn lookup(
+        &self,
+        dirid: &FileHandleU64,
+        filename: &filename3<'_>,
+    ) -> Result<FileHandleU64, nfsstat3> {
+        self.tree.lookup(*dirid, filename.as_ref())
+    }
+
+    fn getattr(&self, id: &FileHandleU64) -> Result<fattr3, nfsstat3> {
+        Ok(self.tree.get(*id)?.attr.clone())
+    }
+
+    #[allow(clippy::cast_possible_truncation)] // the length is at most `count`
+    fn read(
+        &self,
+        id: &FileHandleU64,
+        offset: u64,
+        count: u32,
+    ) -> Result<(Vec<u8>, bool), nfsstat3> {
+        let node = self.tree.get(*id)?;
+        let data = match &node.kind {
+            Kind::File(data) => data,
+            Kind::Dir(_) => return Err(nfsstat3::NFS3ERR_ISDIR),
+            _ => return Err(nfsstat3::NFS3ERR_INVAL),
+        };
+        let size = node.attr.size;
+        let len = size.saturating_sub(offset).min(u64::from(count));
+        if len == 0 {
+            return Ok((Vec::new(), true));
+        }
+
+        let mut buf = vec![0; len as usize];
+        let read = data
+            .read_at(&self.archive, offset, &mut buf)
+            .map_err(|err| {
+                if err.kind() == io::ErrorKind::Unsupported {
+                    return nfsstat3::NFS3ERR_NOTSUPP;
+                }
+                tracing::error!("failed to read {id}: {err}");
+                nfsstat3::NFS3ERR_IO
+            })?;
+        if read < buf.len() {
+            tracing::error!("failed to read {id}: the archive is truncated");
+            return Err(nfsstat3::NFS3ERR_IO);
+        }
+        Ok((buf, offset + len >= size))
+    }
+
+    fn readdirplus(
+        &self,
+        dirid: &FileHandleU64,
+        cookie: u64,
+    ) -> Result<
+        impl Iterator<Item = Result<DirEntryPlus<FileHandleU64>, nfsstat3>> + Send + use<>,
+        nfsstat3,
+    > {
+        // cookies are positions in the directory, which never changes
+        let dir = self.tree.dir(*dirid)?;
+        let start = usize::try_from(cookie)
+            .ok()
+            .filter(|start| *start <= dir.entries.len())
+            .ok_or(nfsstat3::NFS3ERR_BAD_COOKIE)?;
+        let tree = Arc::clone(&self.tree);
+        let dirid = *dirid;
+        let entries = (start..dir.entries.len())
+            .zip(cookie + 1..)
+            .map(move |(index, cookie)| {
+                let (name, id) = &tree.dir(dirid)?.entries[index];
+                Ok(DirEntryPlus {
+                    fileid: (*id).into(),
+                    name: name.clone(),
+                    cookie,
+                    name_attributes: tree.get(*id).ok().map(|node| node.attr.clone()),
+                    name_handle: Some(*id),
+                })
+            });
+        Ok(entries)
+    }
+
+    fn readlink(&self, id: &FileHandleU64) -> Result<nfspath3<'static>, nfsstat3> {
+        match &self.tree.get(*id)?.kind {
+            Kind::Symlink(target) => Ok(target.clone().into()),
+            _ => Err(nfsstat3::NFS3ERR_INVAL),
+        }
+    }
+}
@@ -0,0 +1,327 @@
+//! Random access to the decoded contents of an archive file
+
+use std::fs::File;
+use std::io::{self, BufRead, Read, Seek, SeekFrom};
+use std::sync::{Arc, Mutex};
+
+use super::inflate::{Inflater, Snapshot};
+use super::zstd::ZstdCursor;
+
+/// Size of the buffer used to read the archive file
+const INPUT_BUFFER: usize = 64 * 1024;
+
+/// Number of decoders kept per stream to continue sequential reads
+const MAX_CURSORS: usize = 4;
+
+/// Encoding of a stream
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum Codec {
+    Plain,
+    /// One or more gzip members
+    Gzip,
+    /// A raw deflate stream, as used by zip
+    Deflate,
+    /// One or more zstd frames
+    Zstd,
+}
+
+/// A position where decoding of a compressed stream can start
+#[derive(Clone)]
+pub(super) struct SeekPoint {
+    /// Offset in the archive file
+    pub input: u64,
+    /// Offset in the decoded stream
+    pub output: u64,
+    /// State of the decoder if the point is in the middle of a deflate stream
+    pub snapshot: Option<Arc<Snapshot>>,
+}
+
+/// Seek points of a stream
+///
+/// Points are recorded by the decoders while they go through the stream for the first time,
+/// so the index grows as the stream is read.
+pub(super) struct SeekPoints {
+    interval: u64,
+    points: Mutex<Vec<SeekPoint>>,
+}
+
+impl SeekPoints {
+    fn new(input: u64, interval: u64) -> Self {
+        let start = SeekPoint {
+            input,
+            output: 0,
+            snapshot: None,
+        };
+        Self {
+            interval,
+            points: Mutex::new(vec![start]),
+        }
+    }
+
+    /// Returns the last point at or before `offset`
+    fn nearest(&self, offset: u64) -> SeekPoint {
+        let points = self.points.lock().expect("lock is poisoned");
+        // the first point is at offset 0, so the index is never zero
+        let index = points.partition_point(|point| point.output <= offset);
+        points[index - 1].clone()
+    }
+
+    /// Checks if a snapshot taken at `output` is far enough from the last point
+    pub fn is_due(&self, output: u64) -> bool {
+        let points = self.points.lock().expect("lock is poisoned");
+        points
+            .last()
+            .is_some_and(|last| last.output.saturating_add(self.interval) <= output)
+    }
+
+    /// Records a point if it's past the last one
+    pub fn push(&self, point: SeekPoint) {
+        let mut points = self.points.lock().expect("lock is poisoned");
+        if points.last().is_none_or(|last| last.output < point.output) {
+            points.push(point);
+        }
+    }
+}
+
+/// Decoded view of a range of the archive file
+pub(super) struct Stream {
+    file: Arc<File>,
+    codec: Codec,
+    start: u64,
+    end: u64,
+    points: Arc<SeekPoints>,
+    /// Decoders left by previous reads, the most recently used is the last one
+    cursors: Mutex<Vec<Cursor>>,
+}
+
+impl Stream {
+    pub fn new(file: Arc<File>, codec: Codec, start: u64, end: u64, interval: u64) -> Self {
+        Self {
+            file,
+            codec,
+            start,
+            end,
+            points: Arc::new(SeekPoints::new(start, interval)),
+            cursors: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Returns a stream of another range of the same file
+    pub fn slice(&self, codec: Codec, start: u64, end: u64, interval: u64) -> Self {
+        Self::new(self.file.clone(), codec, start, end, interval)
+    }
+
+    /// Returns a reader of the raw bytes of the stream
+    pub fn input(&self) -> Input {
+        Input::new(self.file.clone(), self.start, self.end)
+    }
+
+    /// Returns a decoder positioned at the start of the stream
+    pub fn cursor(&self) -> Cursor {
+        self.cursor_at(&self.points.nearest(0))
+    }
+
+    /// Reads decoded bytes at `offset` until `buf` is full or the stream ends
+    pub fn read_at(&self, offset: u64, buf: &mut [u8]) -> io::Result<usize> {
+        if self.codec == Codec::Plain {
+            let available = (self.end - self.start).saturating_sub(offset);
+            let len = usize::try_from(available).map_or(buf.len(), |a| a.min(buf.len()));
+            let mut filled = 0;
+            while filled < len {
+                let pos = self.start + offset + filled as u64;
+                match read_at(&self.file, &mut buf[filled..len], pos)? {
+                    0 => break,
+                    read => filled += read,
+                }
+            }
+            return Ok(filled);
+        }
+
+        let mut cursor = self.take_cursor(offset);
+        let skip = offset - cursor.position();
+        let skipped = io::copy(&mut (&mut cursor).take(skip), &mut io::sink())?;
+        if skipped < skip {
+            return Ok(0);
+        }
+        let read = read_full(&mut cursor, buf)?;
+        let mut cursors = self.cursors.lock().expect("lock is poisoned");
+        if cursors.len() >= MAX_CURSORS {
+            cursors.remove(0);
+        }
+        cursors.push(cursor);
+        Ok(read)
+    }
+
+    /// Returns the decoder that reaches `offset` with the least work
+    fn take_cursor(&self, offset: u64) -> Cursor {
+        let point = self.points.nearest(offset);
+        let mut cursors = self.cursors.lock().expect("lock is poisoned");
+        let best = cursors
+            .iter()
+            .enumerate()
+            .filter(|(_, cursor)| (point.output..=offset).contains(&cursor.position()))
+            .max_by_key(|(_, cursor)| cursor.position())
+            .map(|(index, _)| index);
+        if let Some(index) = best {
+            return cursors.remove(index);
+        }
+        drop(cursors);
+        self.cursor_at(&point)
+    }
+
+    fn cursor_at(&self, point: &SeekPoint) -> Cursor {
+        let input = Input::new(self.file.clone(), point.input, self.end);
+        match self.codec {
+            Codec::Plain => Cursor::Plain {
+                input,
+                start: self.start,
+            },
+            Codec::Gzip | Codec::Deflate => Cursor::Inflate(Box::new(Inflater::new(
+                input,
+                self.codec == Codec::Gzip,
+                point,
+                self.points.clone(),
+            ))),
+            Codec::Zstd => Cursor::Zstd(Box::new(ZstdCursor::new(
+                input,
+                point.output,
+                self.points.clone(),
+            ))),
+        }
+    }
+}
+
+/// Sequential decoder of a stream
+pub(super) enum Cursor {
+    Plain { input: Input, start: u64 },
+    Inflate(Box<Inflater>),
+    Zstd(Box<ZstdCursor>),
+}
+
+impl Cursor {
+    /// Returns the offset in the decoded stream of the next byte
+    fn position(&self) -> u64 {
+        match self {
+            Self::Plain { input, start } => input.offset() - start,
+            Self::Inflate(inflater) => inflater.position(),
+            Self::Zstd(cursor) => cursor.position(),
+        }
+    }
+}
+
+impl Read for Cursor {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Self::Plain { input, .. } => input.read(buf),
+            Self::Inflate(inflater) => inflater.read(buf),
+            Self::Zstd(cursor) => cursor.read(buf),
+        }
+    }
+}
+
+/// Buffered reader of a range of the archive file
+///
+/// It uses positional reads, so any number of readers can share the file.
+pub(super) struct Input {
+    file: Arc<File>,
+    /// Offset of the end of the buffered data
+    pos: u64,
+    end: u64,
+    buf: Box<[u8]>,
+    start: usize,
+    filled: usize,
+}
+
+impl Input {
+    fn new(file: Arc<File>, pos: u64, end: u64) -> Self {
+        Self {
+            file,
+            pos,
+            end,
+            buf: vec![0; INPUT_BUFFER].into_boxed_slice(),
+            start: 0,
+            filled: 0,
+        }
+    }
+
+    /// Returns the offset in the file of the next byte
+    pub const fn offset(&self) -> u64 {
+        self.pos - (self.filled - self.start) as u64
+    }
+
+    /// Skips `len` bytes
+    pub fn skip(&mut self, len: u64) -> io::Result<()> {
+        let skipped = io::copy(&mut self.take(len), &mut io::sink())?;
+        if skipped < len {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        Ok(())
+    }
+}
+
+impl Read for Input {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let data = self.fill_buf()?;
+        let len = data.len().min(buf.len());
+        buf[..len].copy_from_slice(&data[..len]);
+        self.consume(len);
+        Ok(len)
+    }
+}
+
+impl BufRead for Input {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        if self.start == self.filled {
+            let remaining = self.end.saturating_sub(self.pos);
+            let len = usize::try_from(remaining).map_or(self.buf.len(), |r| r.min(self.buf.len()));
+            let read = read_at(&self.file, &mut self.buf[..len], self.pos)?;
+            self.pos += read as u64;
+            self.start = 0;
+            self.filled = read;
+        }
+        Ok(&self.buf[self.start..self.filled])
+    }
+
+    fn consume(&mut self, amount: usize) {
+        self.start = (self.start + amount).min(self.filled);
+    }
+}
+
+impl Seek for Input {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let target = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::Current(delta) => self.offset().checked_add_signed(delta),
+            SeekFrom::End(delta) => self.end.checked_add_signed(delta),
+        }
+        .ok_or(io::ErrorKind::InvalidInput)?;
+        self.pos = target;
+        self.start = 0;
+        self.filled = 0;
+        Ok(target)
+    }
+}
+
+/// Reads until `buf` is full or the reader ends
+fn read_full(reader: &mut impl Read, buf: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..]) {
+            Ok(0) => break,
+            Ok(read) => filled += read,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(filled)
+}
+
+#[cfg(unix)]
+fn read_at(file: &File, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+    std::os::unix::fs::FileExt::read_at(file, buf, offset)
+}
+
+#[cfg(windows)]
+fn read_at(file: &File, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+    std::os::windows::fs::FileExt::seek_read(file, buf, offset)
+}
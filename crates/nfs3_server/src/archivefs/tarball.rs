@@ -0,0 +1,86 @@
+//! Index of tar archives
+
+use std::io::{self, Read};
+
+use nfs3_types::nfs3::{ftype3, specdata3};
+use tar::{Entries, EntryType};
+
+use super::stream::{Codec, Stream};
+use super::tree::{Data, Dir, Kind, Meta, Tree};
+
+/// Reads the headers of all entries of the archive
+///
+/// Plain archives are read with seeks over the contents. Compressed archives are decoded from
+/// start to end, which also records the seek points of the stream.
+pub(super) fn index(stream: &Stream, codec: Codec, tree: &mut Tree) -> io::Result<()> {
+    if codec == Codec::Plain {
+        let mut archive = tar::Archive::new(stream.input());
+        add_entries(tree, archive.entries_with_seek()?)
+    } else {
+        let mut archive = tar::Archive::new(stream.cursor());
+        add_entries(tree, archive.entries()?)
+    }
+}
+
+fn add_entries<R: Read>(tree: &mut Tree, entries: Entries<'_, R>) -> io::Result<()> {
+    for entry in entries {
+        let entry = entry?;
+        let header = entry.header();
+        let path = entry.path_bytes();
+        // some writers leave the fields of links empty
+        let mut meta = Meta {
+            mode: header.mode().unwrap_or(0o644),
+            uid: header
+                .uid()
+                .map_or(0, |uid| u32::try_from(uid).unwrap_or(u32::MAX)),
+            gid: header
+                .gid()
+                .map_or(0, |gid| u32::try_from(gid).unwrap_or(u32::MAX)),
+            mtime: header.mtime().unwrap_or_default(),
+            size: 0,
+            rdev: specdata3::default(),
+        };
+
+        let kind = match header.entry_type() {
+            // old archives mark directories with a trailing slash only
+            EntryType::Regular if path.ends_with(b"/") => Kind::Dir(Dir::default()),
+            EntryType::Regular | EntryType::Continuous => {
+                meta.size = entry.size();
+                Kind::File(Data::Archive(entry.raw_file_position()))
+            }
+            EntryType::Directory => Kind::Dir(Dir::default()),
+            EntryType::Symlink => {
+                let target = entry.link_name_bytes().unwrap_or_default().into_owned();
+                meta.size = target.len() as u64;
+                Kind::Symlink(target)
+            }
+            EntryType::Link => {
+                let Some(target) = entry.link_name_bytes() else {
+                    tracing::warn!("skipping link {} without a target", path.escape_ascii());
+                    continue;
+                };
+                tree.link(&path, &target);
+                continue;
+            }
+            type_ @ (EntryType::Char | EntryType::Block | EntryType::Fifo) => {
+                meta.rdev = specdata3 {
+                    specdata1: header.device_major().ok().flatten().unwrap_or_default(),
+                    specdata2: header.device_minor().ok().flatten().unwrap_or_default(),
+                };
+                Kind::Special(match type_ {
+                    EntryType::Char => ftype3::NF3CHR,
+                    EntryType::Block => ftype3::NF3BLK,
+                    _ => ftype3::NF3FIFO,
+                })
+            }
+            EntryType::GNUSparse => {
+                tracing::warn!("skipping sparse file {}", path.escape_ascii());
+                continue;
+            }
+            // extension headers are applied by the tar crate
+            _ => continue,
+        };
+        tree.insert(&path, &meta, kind);
+    }
+    Ok(())
+}
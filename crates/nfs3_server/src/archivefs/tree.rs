@@ -0,0 +1,303 @@
+//! Directory tree built from the entries of an archive
+
+use std::collections::HashMap;
+use std::io;
+
+use nfs3_types::nfs3::{fattr3, filename3, ftype3, nfsstat3, nfstime3, specdata3};
+
+use super::stream::Stream;
+use crate::vfs::FileHandleU64;
+
+/// Id of the root directory
+const ROOT: u64 = 1;
+
+/// Attributes of an archive entry
+#[derive(Debug, Clone, Default)]
+pub(super) struct Meta {
+    pub mode: u32,
+    pub uid: u32,
+    pub gid: u32,
+    pub mtime: u64,
+    pub size: u64,
+    pub rdev: specdata3,
+}
+
+/// Location of the contents of a file
+pub(super) enum Data {
+    /// Stored at an offset of the decoded archive
+    Archive(u64),
+    /// Stored in a stream of its own, like a compressed zip entry
+    Stream(Box<Stream>),
+    /// Stored in a way that isn't supported
+    Unsupported,
+}
+
+impl Data {
+    /// Reads the contents at `offset` until `buf` is full or the contents end
+    pub fn read_at(&self, archive: &Stream, offset: u64, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Self::Archive(start) => archive.read_at(start + offset, buf),
+            Self::Stream(stream) => stream.read_at(offset, buf),
+            Self::Unsupported => Err(io::ErrorKind::Unsupported.into()),
+        }
+    }
+}
+
+#[derive(Default)]
+pub(super) struct Dir {
+    pub entries: Vec<(filename3<'static>, FileHandleU64)>,
+    index: HashMap<Box<[u8]>, usize>,
+}
+
+pub(super) enum Kind {
+    Dir(Dir),
+    File(Data),
+    Symlink(Vec<u8>),
+    /// Devices and FIFOs
+    Special(ftype3),
+}
+
+pub(super) struct Node {
+    pub parent: FileHandleU64,
+    pub attr: fattr3,
+    pub kind: Kind,
+}
+
+/// Entries of an archive indexed by [`FileHandleU64`]
+pub(super) struct Tree {
+    nodes: Vec<Node>,
+    /// Attributes of the directories that are missing from the archive
+    implicit: Meta,
+}
+
+impl Tree {
+    pub fn new(implicit: Meta) -> Self {
+        let root = Node {
+            parent: ROOT.into(),
+            attr: make_attr(ROOT, ftype3::NF3DIR, &implicit),
+            kind: Kind::Dir(Dir::default()),
+        };
+        Self {
+            nodes: vec![root],
+            implicit,
+        }
+    }
+
+    pub const fn root_dir() -> FileHandleU64 {
+        FileHandleU64::new(ROOT)
+    }
+
+    pub fn get(&self, id: FileHandleU64) -> Result<&Node, nfsstat3> {
+        usize::try_from(u64::from(id))
+            .ok()
+            .and_then(|id| id.checked_sub(1))
+            .and_then(|index| self.nodes.get(index))
+            .ok_or(nfsstat3::NFS3ERR_STALE)
+    }
+
+    pub fn dir(&self, id: FileHandleU64) -> Result<&Dir, nfsstat3> {
+        match &self.get(id)?.kind {
+            Kind::Dir(dir) => Ok(dir),
+            _ => Err(nfsstat3::NFS3ERR_NOTDIR),
+        }
+    }
+
+    pub fn lookup(&self, dirid: FileHandleU64, name: &[u8]) -> Result<FileHandleU64, nfsstat3> {
+        let dir = self.dir(dirid)?;
+        match name {
+            b"." => Ok(dirid),
+            b".." => Ok(self.get(dirid)?.parent),
+            name => dir
+                .index
+                .get(name)
+                .map(|&index| dir.entries[index].1)
+                .ok_or(nfsstat3::NFS3ERR_NOENT),
+        }
+    }
+
+    /// Adds an entry, creating the missing parent directories
+    ///
+    /// An existing entry with the same path is replaced, except that a directory entry only
+    /// updates the attributes of an existing directory.
+    pub fn insert(&mut self, path: &[u8], meta: &Meta, kind: Kind) {
+        let Some(components) = components(path) else {
+            tracing::warn!("skipping entry {}", path.escape_ascii());
+            return;
+        };
+        let mut dirid = Self::root_dir();
+        let Some((name, parents)) = components.split_last() else {
+            // an entry for the root itself, like `./`
+            if matches!(kind, Kind::Dir(_)) {
+                self.nodes[0].attr = make_attr(ROOT, ftype3::NF3DIR, meta);
+            }
+            return;
+        };
+        for parent in parents {
+            dirid = self.child_dir(dirid, parent);
+        }
+
+        if let (Ok(id), Kind::Dir(_)) = (self.lookup(dirid, name), &kind) {
+            if let Ok(
+                node @ Node {
+                    kind: Kind::Dir(_), ..
+                },
+            ) = self.node_mut(id)
+            {
+                node.attr = make_attr(id.into(), ftype3::NF3DIR, meta);
+                return;
+            }
+        }
+        let id = self.push(dirid, meta, kind);
+        self.add_name(dirid, name, id);
+    }
+
+    /// Adds `path` as another name of the regular file at `target`
+    ///
+    /// Links to a missing entry or to anything but a regular file are skipped.
+    pub fn link(&mut self, path: &[u8], target: &[u8]) {
+        let id = components(target)
+            .filter(|target| !target.is_empty())
+            .and_then(|target| {
+                target.iter().try_fold(Self::root_dir(), |dirid, name| {
+                    self.lookup(dirid, name).ok()
+                })
+            });
+        let Some(id) = id else {
+            tracing::warn!(
+                "skipping link {} to a missing entry {}",
+                path.escape_ascii(),
+                target.escape_ascii()
+            );
+            return;
+        };
+        if !matches!(self.get(id).map(|node| &node.kind), Ok(Kind::File(_))) {
+            tracing::warn!(
+                "skipping link {} to {}, which isn't a regular file",
+                path.escape_ascii(),
+                target.escape_ascii()
+            );
+            return;
+        }
+        let components = components(path).unwrap_or_default();
+        let Some((name, parents)) = components.split_last() else {
+            tracing::warn!("skipping link {}", path.escape_ascii());
+            return;
+        };
+        let mut dirid = Self::root_dir();
+        for parent in parents {
+            dirid = self.child_dir(dirid, parent);
+        }
+        self.add_name(dirid, name, id);
+        if let Ok(node) = self.node_mut(id) {
+            node.attr.nlink += 1;
+        }
+    }
+
+    /// Sets the link counts of the directories
+    pub fn finish(&mut self) {
+        let counts = self
+            .nodes
+            .iter()
+            .map(|node| match &node.kind {
+                Kind::Dir(dir) => dir
+                    .entries
+                    .iter()
+                    .filter(|(_, id)| matches!(self.get(*id).map(|n| &n.kind), Ok(Kind::Dir(_))))
+                    .count(),
+                _ => 0,
+            })
+            .collect::<Vec<_>>();
+        for (node, count) in self.nodes.iter_mut().zip(counts) {
+            if matches!(node.kind, Kind::Dir(_)) {
+                node.attr.nlink = 2 + u32::try_from(count).unwrap_or(u32::MAX - 2);
+            }
+        }
+    }
+
+    fn node_mut(&mut self, id: FileHandleU64) -> Result<&mut Node, nfsstat3> {
+        usize::try_from(u64::from(id))
+            .ok()
+            .and_then(|id| id.checked_sub(1))
+            .and_then(|index| self.nodes.get_mut(index))
+            .ok_or(nfsstat3::NFS3ERR_STALE)
+    }
+
+    /// Returns the subdirectory `name`, creating it if it's missing
+    fn child_dir(&mut self, dirid: FileHandleU64, name: &[u8]) -> FileHandleU64 {
+        if let Ok(id) = self.lookup(dirid, name) {
+            if matches!(self.get(id).map(|node| &node.kind), Ok(Kind::Dir(_))) {
+                return id;
+            }
+        }
+        let meta = self.implicit.clone();
+        let id = self.push(dirid, &meta, Kind::Dir(Dir::default()));
+        self.add_name(dirid, name, id);
+        id
+    }
+
+    fn push(&mut self, parent: FileHandleU64, meta: &Meta, kind: Kind) -> FileHandleU64 {
+        let id = self.nodes.len() as u64 + 1;
+        let type_ = match &kind {
+            Kind::Dir(_) => ftype3::NF3DIR,
+            Kind::File(_) => ftype3::NF3REG,
+            Kind::Symlink(_) => ftype3::NF3LNK,
+            Kind::Special(type_) => *type_,
+        };
+        self.nodes.push(Node {
+            parent,
+            attr: make_attr(id, type_, meta),
+            kind,
+        });
+        id.into()
+    }
+
+    fn add_name(&mut self, dirid: FileHandleU64, name: &[u8], id: FileHandleU64) {
+        let Ok(Node {
+            kind: Kind::Dir(dir),
+            ..
+        }) = self.node_mut(dirid)
+        else {
+            return;
+        };
+        if let Some(&index) = dir.index.get(name) {
+            dir.entries[index].1 = id;
+        } else {
+            dir.index.insert(name.into(), dir.entries.len());
+            dir.entries.push((name.to_vec().into(), id));
+        }
+    }
+}
+
+/// Splits a path into its names, returns `None` for paths that leave the archive
+fn components(path: &[u8]) -> Option<Vec<&[u8]>> {
+    let components = path
+        .split(|&b| b == b'/')
+        .filter(|name| !name.is_empty() && *name != b".")
+        .collect::<Vec<_>>();
+    if components.contains(&b"..".as_slice()) {
+        return None;
+    }
+    Some(components)
+}
+
+fn make_attr(id: u64, type_: ftype3, meta: &Meta) -> fattr3 {
+    let time = nfstime3 {
+        seconds: u32::try_from(meta.mtime).unwrap_or(u32::MAX),
+        nseconds: 0,
+    };
+    fattr3 {
+        type_,
+        mode: meta.mode & 0o7777,
+        nlink: 1,
+        uid: meta.uid,
+        gid: meta.gid,
+        size: meta.size,
+        used: meta.size,
+        rdev: meta.rdev.clone(),
+        fsid: 0,
+        fileid: id,
+        atime: time,
+        mtime: time,
+        ctime: time,
+    }
+}
@@ -0,0 +1,100 @@
+//! Index of zip archives
+
+use std::io;
+
+use zip::extra_fields::ExtraField;
+use zip::read::ZipFile;
+use zip::{CompressionMethod, DateTime, ZipArchive};
+
+use super::stream::{Codec, Input, Stream};
+use super::tree::{Data, Dir, Kind, Meta, Tree};
+
+/// Reads the central directory of the archive
+///
+/// Stored entries are read directly from the archive. Deflated entries get a stream of their
+/// own, which records its seek points the first time it's read.
+pub(super) fn index(
+    stream: &Stream,
+    interval: u64,
+    tree: &mut Tree,
+    owner: &Meta,
+) -> io::Result<()> {
+    let mut archive = ZipArchive::new(stream.input())?;
+    for index in 0..archive.len() {
+        let entry = archive.by_index_raw(index)?;
+        let path = entry.name_raw().to_vec();
+        let is_dir = entry.is_dir();
+        let default_mode = if is_dir { 0o755 } else { 0o644 };
+        let meta = Meta {
+            mode: entry.unix_mode().unwrap_or(default_mode),
+            uid: owner.uid,
+            gid: owner.gid,
+            mtime: modified(&entry).unwrap_or(owner.mtime),
+            size: if is_dir { 0 } else { entry.size() },
+            ..Meta::default()
+        };
+        if is_dir {
+            tree.insert(&path, &meta, Kind::Dir(Dir::default()));
+            continue;
+        }
+
+        let start = entry.data_start();
+        let method = entry.compression();
+        let data = if entry.encrypted() {
+            tracing::warn!("{} is encrypted", path.escape_ascii());
+            Data::Unsupported
+        } else if method == CompressionMethod::STORE {
+            Data::Archive(start)
+        } else if method == CompressionMethod::DEFLATE {
+            let end = start + entry.compressed_size();
+            Data::Stream(Box::new(stream.slice(Codec::Deflate, start, end, interval)))
+        } else {
+            tracing::warn!(
+                "{} is compressed with an unsupported method {method}",
+                path.escape_ascii()
+            );
+            Data::Unsupported
+        };
+        let kind = if entry.is_symlink() {
+            drop(entry);
+            let len = usize::try_from(meta.size).map_err(|_| io::ErrorKind::InvalidData)?;
+            let mut target = vec![0; len];
+            if data.read_at(stream, 0, &mut target)? != len {
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            }
+            Kind::Symlink(target)
+        } else {
+            Kind::File(data)
+        };
+        tree.insert(&path, &meta, kind);
+    }
+    Ok(())
+}
+
+/// Returns the modification time in seconds since the epoch
+fn modified(entry: &ZipFile<'_, Input>) -> Option<u64> {
+    let extended = entry.extra_data_fields().find_map(|field| match field {
+        ExtraField::ExtendedTimestamp(timestamp) => timestamp.mod_time(),
+        ExtraField::Ntfs(_) => None,
+    });
+    extended
+        .map(u64::from)
+        .or_else(|| entry.last_modified().and_then(unix_time))
+}
+
+/// Converts a DOS time, which has no time zone, as if it was UTC
+fn unix_time(time: DateTime) -> Option<u64> {
+    // days from civil, see http://howardhinnant.github.io/date_algorithms.html
+    let month = i64::from(time.month());
+    let year = i64::from(time.year()) - i64::from(month <= 2);
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + i64::from(time.day()) - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    let days = era * 146_097 + day_of_era - 719_468;
+    let seconds = days * 86_400
+        + i64::from(time.hour()) * 3600
+        + i64::from(time.minute()) * 60
+        + i64::from(time.second());
+    u64::try_from(seconds).ok()
+}
@@ -0,0 +1,96 @@
+//! Zstandard decoder that records the start of every frame
+
+use std::io::{self, BufRead, Read};
+use std::sync::Arc;
+
+use ruzstd::decoding::errors::{FrameDecoderError, ReadFrameHeaderError};
+use ruzstd::decoding::{BlockDecodingStrategy, FrameDecoder};
+
+use super::stream::{Input, SeekPoint, SeekPoints};
+
+/// Streaming decoder of a sequence of frames
+///
+/// Frames are decoded independently, so each of them is a seek point. Skippable frames, like the
+/// seek table of the seekable format, are ignored.
+pub(super) struct ZstdCursor {
+    input: Input,
+    decoder: Box<FrameDecoder>,
+    in_frame: bool,
+    eof: bool,
+    /// Number of decoded bytes
+    total: u64,
+    points: Arc<SeekPoints>,
+}
+
+impl ZstdCursor {
+    pub fn new(input: Input, output: u64, points: Arc<SeekPoints>) -> Self {
+        Self {
+            input,
+            decoder: Box::new(FrameDecoder::new()),
+            in_frame: false,
+            eof: false,
+            total: output,
+            points,
+        }
+    }
+
+    /// Returns the offset in the decoded stream of the next byte
+    pub const fn position(&self) -> u64 {
+        self.total
+    }
+
+    /// Starts the next frame, returns `false` at the end of the stream
+    fn start_frame(&mut self) -> io::Result<bool> {
+        loop {
+            if self.input.fill_buf()?.is_empty() {
+                return Ok(false);
+            }
+            let input = self.input.offset();
+            match self.decoder.reset(&mut self.input) {
+                Ok(()) => {
+                    self.points.push(SeekPoint {
+                        input,
+                        output: self.total,
+                        snapshot: None,
+                    });
+                    return Ok(true);
+                }
+                Err(FrameDecoderError::ReadFrameHeaderError(ReadFrameHeaderError::SkipFrame {
+                    length,
+                    ..
+                })) => self.input.skip(length.into())?,
+                Err(err) => return Err(io::Error::new(io::ErrorKind::InvalidData, err)),
+            }
+        }
+    }
+}
+
+impl Read for ZstdCursor {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        loop {
+            if self.in_frame {
+                let decoder = &mut self.decoder;
+                while decoder.can_collect() < buf.len() && !decoder.is_finished() {
+                    let needed = buf.len() - decoder.can_collect();
+                    decoder
+                        .decode_blocks(&mut self.input, BlockDecodingStrategy::UptoBytes(needed))
+                        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+                }
+                let read = decoder.read(buf)?;
+                if read > 0 {
+                    self.total += read as u64;
+                    return Ok(read);
+                }
+                self.in_frame = false;
+            }
+            if self.eof || !self.start_frame()? {
+                self.eof = true;
+                return Ok(0);
+            }
+            self.in_frame = true;
+        }
+    }
+}
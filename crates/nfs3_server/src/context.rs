@@ -4,6 +4,12 @@ use std::sync::Arc;
 use nfs3_types::rpc::auth_unix;
 use tokio::sync::mpsc;
 
+use crate::fsinfo::FsInfoState;
+use crate::lock_manager::LockManager;
+use crate::nfs2_cookies::Nfs2Cookies;
+#[cfg(feature = "nfs4")]
+use crate::nfs4_state::StateManager;
+use crate::runtime::Runtime;
 use crate::transaction_tracker::TransactionTracker;
 use crate::vfs::handle::FileHandleConverter;
 
@@ -16,6 +22,14 @@ pub struct RPCContext<T: crate::vfs::NfsFileSystem> {
     pub export_name: Arc<String>,
     pub transaction_tracker: Arc<TransactionTracker>,
     pub(crate) file_handle_converter: FileHandleConverter,
+    pub(crate) fsinfo: Arc<FsInfoState>,
+    pub(crate) lock_manager: Arc<LockManager>,
+    pub(crate) nfs2_cookies: Arc<Nfs2Cookies>,
+    #[cfg(feature = "nfs4")]
+    pub(crate) nfs4_state: Arc<StateManager>,
+    /// Identity of the client established by the transport, e.g. a TLS client certificate
+    pub(crate) peer_auth: Option<Arc<auth_unix>>,
+    pub(crate) runtime: Arc<dyn Runtime>,
 }
 
 #[allow(clippy::missing_fields_in_debug)]
@@ -48,7 +62,14 @@ where
             mount_signal: self.mount_signal.clone(),
             export_name: Arc::clone(&self.export_name),
             transaction_tracker: Arc::clone(&self.transaction_tracker),
-            file_handle_converter: self.file_handle_converter,
+            file_handle_converter: self.file_handle_converter.clone(),
+            fsinfo: Arc::clone(&self.fsinfo),
+            lock_manager: Arc::clone(&self.lock_manager),
+            nfs2_cookies: Arc::clone(&self.nfs2_cookies),
+            #[cfg(feature = "nfs4")]
+            nfs4_state: Arc::clone(&self.nfs4_state),
+            peer_auth: self.peer_auth.clone(),
+            runtime: Arc::clone(&self.runtime),
         }
     }
 }
@@ -73,10 +94,28 @@ where
                 1024,
             )),
             file_handle_converter: FileHandleConverter::new(),
+            fsinfo: Arc::default(),
+            lock_manager: Arc::default(),
+            nfs2_cookies: Arc::default(),
+            #[cfg(feature = "nfs4")]
+            nfs4_state: Arc::default(),
+            peer_auth: None,
+            runtime: crate::runtime::default_runtime().expect("no runtime feature is enabled"),
         }
     }
+    pub fn enable_subtree_check(&mut self) {
+        self.file_handle_converter
+            .set_secret(Some(b"test secret"), &self.export_name);
+        self.file_handle_converter.set_subtree_check(true);
+    }
+    pub fn set_fsinfo(&mut self, config: crate::fsinfo::FsInfoConfig) -> std::io::Result<()> {
+        self.fsinfo = Arc::new(FsInfoState::new(config)?);
+        Ok(())
+    }
     #[must_use]
     pub fn root_dir(&self) -> nfs3_types::nfs3::nfs_fh3 {
-        self.file_handle_converter.fh_to_nfs(&self.vfs.root_dir())
+        self.file_handle_converter
+            .fh_to_nfs(&self.vfs.root_dir())
+            .expect("the root handle fits into NFS3_FHSIZE")
     }
 }
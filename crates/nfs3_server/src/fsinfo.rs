@@ -0,0 +1,208 @@
+use std::io;
+
+use nfs3_types::nfs3::{FSINFO3resok as fsinfo3, nfsstat3, nfstime3};
+use tokio::sync::OnceCell;
+
+use crate::vfs::NfsReadFileSystem;
+
+/// Overrides for the values reported by FSINFO
+///
+/// Every field that is set replaces the value returned by
+/// [`NfsReadFileSystem::fsinfo`]. The preferred sizes are clamped to the maximums,
+/// so setting only `rtmax` or `wtmax` is enough to limit the transfer size.
+///
+/// READ and WRITE requests larger than the advertised `rtmax` and `wtmax`
+/// are rejected with `NFS3ERR_INVAL`. The transfer sizes must not be zero.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FsInfoConfig {
+    /// Maximum size of a READ request
+    pub rtmax: Option<u32>,
+    /// Preferred size of a READ request
+    pub rtpref: Option<u32>,
+    /// Maximum size of a WRITE request
+    pub wtmax: Option<u32>,
+    /// Preferred size of a WRITE request
+    pub wtpref: Option<u32>,
+    /// Preferred size of a READDIR request
+    pub dtpref: Option<u32>,
+    /// Maximum size of a file
+    pub maxfilesize: Option<u64>,
+    /// Granularity of the file times
+    pub time_delta: Option<nfstime3>,
+    /// `FSF3_*` flags
+    pub properties: Option<u32>,
+}
+
+impl FsInfoConfig {
+    fn validate(&self) -> io::Result<()> {
+        let sizes = [
+            ("rtmax", self.rtmax),
+            ("rtpref", self.rtpref),
+            ("wtmax", self.wtmax),
+            ("wtpref", self.wtpref),
+            ("dtpref", self.dtpref),
+        ];
+        for (name, size) in sizes {
+            if size == Some(0) {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("{name} must not be zero"),
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    fn apply(&self, fsinfo: &mut fsinfo3) {
+        fsinfo.rtmax = self.rtmax.unwrap_or(fsinfo.rtmax);
+        fsinfo.rtpref = self.rtpref.unwrap_or(fsinfo.rtpref).min(fsinfo.rtmax);
+        fsinfo.rtmult = fsinfo.rtmult.min(fsinfo.rtmax);
+        fsinfo.wtmax = self.wtmax.unwrap_or(fsinfo.wtmax);
+        fsinfo.wtpref = self.wtpref.unwrap_or(fsinfo.wtpref).min(fsinfo.wtmax);
+        fsinfo.wtmult = fsinfo.wtmult.min(fsinfo.wtmax);
+        fsinfo.dtpref = self.dtpref.unwrap_or(fsinfo.dtpref);
+        fsinfo.maxfilesize = self.maxfilesize.unwrap_or(fsinfo.maxfilesize);
+        fsinfo.time_delta = self.time_delta.unwrap_or(fsinfo.time_delta);
+        fsinfo.properties = self.properties.unwrap_or(fsinfo.properties);
+    }
+}
+
+/// Maximum READ and WRITE sizes advertised to the clients
+#[derive(Debug, Clone, Copy)]
+pub struct TransferLimits {
+    pub rtmax: u32,
+    pub wtmax: u32,
+}
+
+/// FSINFO settings shared by all connections of a listener
+#[derive(Debug, Default)]
+pub struct FsInfoState {
+    config: FsInfoConfig,
+    limits: OnceCell<TransferLimits>,
+}
+
+impl FsInfoState {
+    pub fn new(config: FsInfoConfig) -> io::Result<Self> {
+        config.validate()?;
+        Ok(Self {
+            config,
+            limits: OnceCell::new(),
+        })
+    }
+
+    /// Returns the file system information with the configured overrides applied
+    pub async fn fsinfo<T: NfsReadFileSystem>(
+        &self,
+        vfs: &T,
+        id: &T::Handle,
+    ) -> Result<fsinfo3, nfsstat3> {
+        let mut fsinfo = vfs.fsinfo(id).await?;
+        self.config.apply(&mut fsinfo);
+        Ok(fsinfo)
+    }
+
+    /// Returns the transfer limits advertised for the root directory.
+    /// They are queried once and cached for the lifetime of the listener.
+    pub async fn limits<T: NfsReadFileSystem>(&self, vfs: &T) -> TransferLimits {
+        *self
+            .limits
+            .get_or_init(|| async {
+                match self.fsinfo(vfs, &vfs.root_dir()).await {
+                    Ok(fsinfo) => TransferLimits {
+                        rtmax: fsinfo.rtmax,
+                        wtmax: fsinfo.wtmax,
+                    },
+                    Err(stat) => {
+                        tracing::warn!("cannot get fsinfo of the root directory: {stat}");
+                        TransferLimits {
+                            rtmax: self.config.rtmax.unwrap_or(u32::MAX),
+                            wtmax: self.config.wtmax.unwrap_or(u32::MAX),
+                        }
+                    }
+                }
+            })
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nfs3_types::nfs3::post_op_attr;
+
+    use super::*;
+
+    fn default_fsinfo() -> fsinfo3 {
+        fsinfo3 {
+            obj_attributes: post_op_attr::None,
+            rtmax: 1024,
+            rtpref: 1024,
+            rtmult: 1024,
+            wtmax: 1024,
+            wtpref: 1024,
+            wtmult: 1024,
+            dtpref: 1024,
+            maxfilesize: 4096,
+            time_delta: nfstime3::default(),
+            properties: 0,
+        }
+    }
+
+    #[test]
+    fn test_empty_config() {
+        let mut fsinfo = default_fsinfo();
+        FsInfoConfig::default().apply(&mut fsinfo);
+        assert_eq!(format!("{fsinfo:?}"), format!("{:?}", default_fsinfo()));
+    }
+
+    #[test]
+    fn test_overrides() {
+        let config = FsInfoConfig {
+            rtmax: Some(512),
+            wtmax: Some(2048),
+            wtpref: Some(2048),
+            dtpref: Some(256),
+            maxfilesize: Some(8192),
+            time_delta: Some(nfstime3 {
+                seconds: 1,
+                nseconds: 0,
+            }),
+            properties: Some(1),
+            ..Default::default()
+        };
+        let mut fsinfo = default_fsinfo();
+        config.apply(&mut fsinfo);
+
+        assert_eq!(fsinfo.rtmax, 512);
+        assert_eq!(fsinfo.rtpref, 512);
+        assert_eq!(fsinfo.rtmult, 512);
+        assert_eq!(fsinfo.wtmax, 2048);
+        assert_eq!(fsinfo.wtpref, 2048);
+        assert_eq!(fsinfo.wtmult, 1024);
+        assert_eq!(fsinfo.dtpref, 256);
+        assert_eq!(fsinfo.maxfilesize, 8192);
+        assert_eq!(fsinfo.time_delta.seconds, 1);
+        assert_eq!(fsinfo.properties, 1);
+    }
+
+    #[test]
+    fn test_zero_sizes_are_rejected() {
+        for config in [
+            FsInfoConfig {
+                rtmax: Some(0),
+                ..Default::default()
+            },
+            FsInfoConfig {
+                wtmax: Some(0),
+                ..Default::default()
+            },
+            FsInfoConfig {
+                dtpref: Some(0),
+                ..Default::default()
+            },
+        ] {
+            let err = FsInfoState::new(config).expect_err("zero sizes are rejected");
+            assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        }
+        assert!(FsInfoState::new(FsInfoConfig::default()).is_ok());
+    }
+}
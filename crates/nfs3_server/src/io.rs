@@ -0,0 +1,36 @@
+//! Asynchronous I/O traits for reading and writing bytes.
+//!
+//! Connections served by [`NFSServer::serve_connection`](crate::server::NFSServer::serve_connection)
+//! implement both traits. Wrappers for Tokio and smol streams are available with the `tokio` and
+//! `smol` features.
+
+/// Trait to read bytes asynchronously.
+pub trait AsyncRead: Send {
+    /// Read bytes from the stream into the provided buffer.
+    ///
+    /// Returns 0 when the stream is closed. The future must be cancel safe: if it's dropped
+    /// before completion, no data has been read.
+    fn async_read(&mut self, buf: &mut [u8])
+    -> impl Future<Output = std::io::Result<usize>> + Send;
+}
+
+/// Trait to write bytes asynchronously.
+pub trait AsyncWrite: Send {
+    /// Write bytes to the stream from the provided buffer.
+    fn async_write(&mut self, buf: &[u8]) -> impl Future<Output = std::io::Result<usize>> + Send;
+
+    /// Write all bytes to the stream from the provided buffer.
+    fn async_write_all(&mut self, buf: &[u8]) -> impl Future<Output = std::io::Result<()>> + Send {
+        async move {
+            let mut buf = buf;
+            while !buf.is_empty() {
+                let n = self.async_write(buf).await?;
+                if n == 0 {
+                    return Err(std::io::ErrorKind::WriteZero.into());
+                }
+                buf = &buf[n..];
+            }
+            Ok(())
+        }
+    }
+}
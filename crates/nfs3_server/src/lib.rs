@@ -2,19 +2,54 @@
 #![doc = include_str!("../README.md")]
 
 mod context;
+mod fsinfo;
+pub mod io;
+mod lock_manager;
 mod mount_handlers;
+mod nfs2_cookies;
+mod nfs2_handlers;
+#[cfg(feature = "nfs4")]
+mod nfs4_handlers;
+#[cfg(feature = "nfs4")]
+mod nfs4_state;
 pub(crate) mod nfs_ext;
 mod nfs_handlers;
+mod nfsacl_handlers;
+mod nlm_handlers;
+mod nsm_handlers;
 mod portmap_handlers;
 mod rpcwire;
+pub mod runtime;
+#[cfg(feature = "s3")]
+#[cfg_attr(docsrs, doc(cfg(feature = "s3")))]
+pub mod s3fs;
+pub mod server;
+
+#[cfg(feature = "archive")]
+#[cfg_attr(docsrs, doc(cfg(feature = "archive")))]
+pub mod archivefs;
 
 #[cfg(feature = "fs_util")]
 #[cfg_attr(docsrs, doc(cfg(feature = "fs_util")))]
 pub mod fs_util;
 
+#[cfg(feature = "smol")]
+#[cfg_attr(docsrs, doc(cfg(feature = "smol")))]
+pub mod smol;
+#[cfg(feature = "tokio")]
+#[cfg_attr(docsrs, doc(cfg(feature = "tokio")))]
 pub mod tcp;
+#[cfg(feature = "tls")]
+#[cfg_attr(docsrs, doc(cfg(feature = "tls")))]
+pub mod tls;
+#[cfg(feature = "tokio")]
+#[cfg_attr(docsrs, doc(cfg(feature = "tokio")))]
+pub mod tokio;
 mod transaction_tracker;
 pub(crate) mod units;
+#[cfg(all(unix, feature = "tokio"))]
+#[cfg_attr(docsrs, doc(cfg(all(unix, feature = "tokio"))))]
+pub mod unix;
 pub mod vfs;
 
 #[cfg(feature = "memfs")]
@@ -36,9 +71,9 @@ pub mod test_reexports {
         context: RPCContext<T>,
     ) -> Result<(), anyhow::Error>
     where
-        IO: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin + 'static,
+        IO: crate::io::AsyncRead + crate::io::AsyncWrite + 'static,
         T: crate::vfs::NfsFileSystem + 'static,
     {
-        crate::tcp::process_socket(socket, context).await
+        crate::server::process_socket(socket, context).await
     }
 }
@@ -0,0 +1,339 @@
+use std::collections::{HashMap, HashSet};
+use std::net::IpAddr;
+use std::sync::Mutex;
+
+/// The owner of a byte range lock: a process on a client host
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LockOwner {
+    pub host: Vec<u8>,
+    pub svid: i32,
+    pub oh: Vec<u8>,
+}
+
+/// A byte range lock. `end` is inclusive, `u64::MAX` means "up to the end of the file".
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Lock {
+    pub owner: LockOwner,
+    pub exclusive: bool,
+    pub start: u64,
+    pub end: u64,
+}
+
+impl Lock {
+    /// Converts the offset and length used by NLM, where zero length means "up to the end
+    /// of the file", to an inclusive range
+    pub const fn range(offset: u64, len: u64) -> (u64, u64) {
+        if len == 0 {
+            (offset, u64::MAX)
+        } else {
+            (offset, offset.saturating_add(len - 1))
+        }
+    }
+
+    /// Returns the length of the lock as used by NLM
+    pub const fn len(&self) -> u64 {
+        if self.end == u64::MAX {
+            0
+        } else {
+            self.end - self.start + 1
+        }
+    }
+
+    const fn overlaps(&self, start: u64, end: u64) -> bool {
+        self.start <= end && start <= self.end
+    }
+
+    fn conflicts_with(&self, owner: &LockOwner, exclusive: bool, start: u64, end: u64) -> bool {
+        (self.exclusive || exclusive) && self.owner != *owner && self.overlaps(start, end)
+    }
+}
+
+/// A DOS-style share reservation. `mode` is the access denied to others and `access` is
+/// the access requested, both as read (1) and write (2) bits.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Share {
+    pub host: Vec<u8>,
+    pub oh: Vec<u8>,
+    pub mode: u32,
+    pub access: u32,
+}
+
+impl Share {
+    const fn conflicts_with(&self, other: &Self) -> bool {
+        self.mode & other.access != 0 || self.access & other.mode != 0
+    }
+}
+
+#[derive(Debug, Default)]
+struct FileLocks {
+    locks: Vec<Lock>,
+    shares: Vec<Share>,
+}
+
+impl FileLocks {
+    const fn is_empty(&self) -> bool {
+        self.locks.is_empty() && self.shares.is_empty()
+    }
+
+    /// Releases the range from the locks of `owner`, splitting the locks that cover it partially
+    fn unlock(&mut self, owner: &LockOwner, start: u64, end: u64) {
+        let mut remaining = Vec::with_capacity(self.locks.len());
+        for lock in self.locks.drain(..) {
+            if lock.owner != *owner || !lock.overlaps(start, end) {
+                remaining.push(lock);
+                continue;
+            }
+            if lock.start < start {
+                remaining.push(Lock {
+                    end: start - 1,
+                    ..lock.clone()
+                });
+            }
+            if lock.end > end {
+                remaining.push(Lock {
+                    start: end + 1,
+                    ..lock
+                });
+            }
+        }
+        self.locks = remaining;
+    }
+}
+
+/// In-memory table of the advisory locks and share reservations held by NLM clients,
+/// keyed by file handle
+///
+/// Locks follow POSIX semantics: locks of the same owner never conflict, and a new lock
+/// replaces the locks the owner already holds on the same range.
+#[derive(Debug, Default)]
+pub struct LockManager {
+    files: Mutex<HashMap<Vec<u8>, FileLocks>>,
+    /// Addresses that locks and share reservations were taken from, by client host
+    addresses: Mutex<HashMap<Vec<u8>, HashSet<IpAddr>>>,
+}
+
+impl LockManager {
+    /// Returns a lock that conflicts with the described one, if any
+    pub fn test(
+        &self,
+        file: &[u8],
+        owner: &LockOwner,
+        exclusive: bool,
+        start: u64,
+        end: u64,
+    ) -> Option<Lock> {
+        let files = self.files.lock().expect("lock is poisoned");
+        files.get(file).and_then(|locks| {
+            locks
+                .locks
+                .iter()
+                .find(|lock| lock.conflicts_with(owner, exclusive, start, end))
+                .cloned()
+        })
+    }
+
+    /// Acquires the lock or returns the lock it conflicts with
+    pub fn lock(&self, file: &[u8], lock: Lock) -> Result<(), Lock> {
+        let mut files = self.files.lock().expect("lock is poisoned");
+        let locks = files.entry(file.to_vec()).or_default();
+        if let Some(conflict) = locks
+            .locks
+            .iter()
+            .find(|other| other.conflicts_with(&lock.owner, lock.exclusive, lock.start, lock.end))
+        {
+            return Err(conflict.clone());
+        }
+        locks.unlock(&lock.owner, lock.start, lock.end);
+        locks.locks.push(lock);
+        Ok(())
+    }
+
+    /// Releases the range from the locks of `owner`
+    pub fn unlock(&self, file: &[u8], owner: &LockOwner, start: u64, end: u64) {
+        let mut files = self.files.lock().expect("lock is poisoned");
+        if let Some(locks) = files.get_mut(file) {
+            locks.unlock(owner, start, end);
+            if locks.is_empty() {
+                files.remove(file);
+            }
+        }
+    }
+
+    /// Adds a share reservation. Returns `false` if it conflicts with a reservation of
+    /// another owner.
+    pub fn share(&self, file: &[u8], share: Share) -> bool {
+        let mut files = self.files.lock().expect("lock is poisoned");
+        let locks = files.entry(file.to_vec()).or_default();
+        let is_same_owner = |other: &Share| other.host == share.host && other.oh == share.oh;
+        if locks
+            .shares
+            .iter()
+            .any(|other| !is_same_owner(other) && other.conflicts_with(&share))
+        {
+            if locks.is_empty() {
+                files.remove(file);
+            }
+            return false;
+        }
+        locks.shares.retain(|other| !is_same_owner(other));
+        locks.shares.push(share);
+        true
+    }
+
+    /// Removes the share reservation of the owner
+    pub fn unshare(&self, file: &[u8], host: &[u8], oh: &[u8]) {
+        let mut files = self.files.lock().expect("lock is poisoned");
+        if let Some(locks) = files.get_mut(file) {
+            locks
+                .shares
+                .retain(|share| share.host != host || share.oh != oh);
+            if locks.is_empty() {
+                files.remove(file);
+            }
+        }
+    }
+
+    /// Releases all locks and share reservations of a client host, e.g. after it rebooted
+    pub fn free_all(&self, host: &[u8]) {
+        let mut files = self.files.lock().expect("lock is poisoned");
+        files.retain(|_, locks| {
+            locks.locks.retain(|lock| lock.owner.host != host);
+            locks.shares.retain(|share| share.host != host);
+            !locks.is_empty()
+        });
+        drop(files);
+        self.addresses
+            .lock()
+            .expect("lock is poisoned")
+            .remove(host);
+    }
+
+    /// Remembers that `host` took a lock or a share reservation from `addr`
+    pub fn add_address(&self, host: &[u8], addr: IpAddr) {
+        let mut addresses = self.addresses.lock().expect("lock is poisoned");
+        addresses.entry(host.to_vec()).or_default().insert(addr);
+    }
+
+    /// Returns `true` if `host` took a lock or a share reservation from `addr`
+    pub fn is_host_address(&self, host: &[u8], addr: IpAddr) -> bool {
+        let addresses = self.addresses.lock().expect("lock is poisoned");
+        addresses
+            .get(host)
+            .is_some_and(|addresses| addresses.contains(&addr))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #![allow(clippy::unwrap_used)]
+
+    use super::*;
+
+    fn owner(host: &str, svid: i32) -> LockOwner {
+        LockOwner {
+            host: host.as_bytes().to_vec(),
+            svid,
+            oh: format!("{svid}@{host}").into_bytes(),
+        }
+    }
+
+    fn lock(owner: &LockOwner, exclusive: bool, offset: u64, len: u64) -> Lock {
+        let (start, end) = Lock::range(offset, len);
+        Lock {
+            owner: owner.clone(),
+            exclusive,
+            start,
+            end,
+        }
+    }
+
+    #[test]
+    fn test_range() {
+        assert_eq!(Lock::range(10, 0), (10, u64::MAX));
+        assert_eq!(Lock::range(10, 5), (10, 14));
+        assert_eq!(Lock::range(u64::MAX - 1, 10), (u64::MAX - 1, u64::MAX));
+        assert_eq!(lock(&owner("a", 1), true, 10, 5).len(), 5);
+        assert_eq!(lock(&owner("a", 1), true, 10, 0).len(), 0);
+    }
+
+    #[test]
+    fn test_host_addresses() {
+        let manager = LockManager::default();
+        let addr = IpAddr::from([192, 0, 2, 1]);
+        manager.add_address(b"alice", addr);
+        assert!(manager.is_host_address(b"alice", addr));
+        assert!(!manager.is_host_address(b"bob", addr));
+        assert!(!manager.is_host_address(b"alice", IpAddr::from([192, 0, 2, 2])));
+        manager.free_all(b"alice");
+        assert!(!manager.is_host_address(b"alice", addr));
+    }
+
+    #[test]
+    fn test_conflicts() {
+        let manager = LockManager::default();
+        let alice = owner("alice", 1);
+        let bob = owner("bob", 1);
+
+        manager.lock(b"f", lock(&alice, false, 0, 100)).unwrap();
+        // shared locks don't conflict
+        manager.lock(b"f", lock(&bob, false, 50, 100)).unwrap();
+        let conflict = manager.lock(b"f", lock(&bob, true, 0, 10)).unwrap_err();
+        assert_eq!(conflict.owner, alice);
+        assert_eq!(
+            manager.test(b"f", &alice, true, 100, 149).unwrap().owner,
+            bob
+        );
+        assert!(manager.test(b"f", &alice, true, 150, u64::MAX).is_none());
+        // other files are independent
+        manager.lock(b"g", lock(&bob, true, 0, 0)).unwrap();
+        // the owner can upgrade its own lock when nobody else holds the range
+        manager.lock(b"f", lock(&bob, true, 100, 50)).unwrap();
+    }
+
+    #[test]
+    fn test_unlock_splits_locks() {
+        let manager = LockManager::default();
+        let alice = owner("alice", 1);
+        let bob = owner("bob", 2);
+
+        manager.lock(b"f", lock(&alice, true, 0, 0)).unwrap();
+        let (start, end) = Lock::range(10, 10);
+        manager.unlock(b"f", &alice, start, end);
+
+        assert!(manager.test(b"f", &bob, true, 10, 19).is_none());
+        assert!(manager.test(b"f", &bob, true, 9, 9).is_some());
+        assert_eq!(
+            manager.test(b"f", &bob, true, 20, 20).unwrap().end,
+            u64::MAX
+        );
+
+        manager.unlock(b"f", &alice, 0, u64::MAX);
+        assert!(manager.files.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_shares_and_free_all() {
+        let manager = LockManager::default();
+        let share = |host: &str, mode, access| Share {
+            host: host.as_bytes().to_vec(),
+            oh: b"oh".to_vec(),
+            mode,
+            access,
+        };
+
+        // read access, deny write
+        assert!(manager.share(b"f", share("alice", 2, 1)));
+        assert!(manager.share(b"f", share("bob", 0, 1)));
+        assert!(!manager.share(b"f", share("carol", 0, 2)));
+
+        manager.unshare(b"f", b"alice", b"oh");
+        assert!(manager.share(b"f", share("carol", 0, 2)));
+
+        manager
+            .lock(b"g", lock(&owner("bob", 1), true, 0, 0))
+            .unwrap();
+        manager.free_all(b"bob");
+        manager.free_all(b"carol");
+        assert!(manager.files.lock().unwrap().is_empty());
+    }
+}
@@ -36,13 +36,14 @@ use std::time::SystemTime;
 
 pub use config::MemFsConfig;
 use nfs3_types::nfs3::{
-    self as nfs, cookie3, createverf3, fattr3, filename3, ftype3, nfspath3, nfsstat3, nfstime3,
-    sattr3, specdata3,
+    self as nfs, cookie3, cookieverf3, createverf3, fattr3, filename3, ftype3, nfspath3, nfsstat3,
+    nfstime3, sattr3, specdata3,
 };
+use nfs3_types::nfsacl::{ACL_GROUP_OBJ, ACL_MASK, ACL_OTHER, ACL_USER_OBJ, aclent};
 use nfs3_types::xdr_codec::Opaque;
 
 use crate::vfs::{
-    DirEntry, DirEntryPlus, FileHandleU64, NextResult, NfsFileSystem, NfsReadFileSystem,
+    DirEntry, DirEntryPlus, FileHandleU64, NextResult, NfsFileSystem, NfsReadFileSystem, PosixAcl,
     ReadDirIterator, ReadDirPlusIterator,
 };
 
@@ -53,7 +54,11 @@ struct Dir {
     name: filename3<'static>,
     parent: FileHandleU64,
     attr: fattr3,
+    acl: PosixAcl,
     content: HashSet<FileHandleU64>,
+    /// Bumped every time `content` changes. Cookies are positions in `content`,
+    /// so they can't be interpreted after a change.
+    generation: u64,
 }
 
 impl Dir {
@@ -78,7 +83,9 @@ impl Dir {
             name,
             parent,
             attr,
+            acl: PosixAcl::default(),
             content: HashSet::new(),
+            generation: 1,
         }
     }
 
@@ -89,7 +96,23 @@ impl Dir {
     }
 
     fn add_entry(&mut self, entry: FileHandleU64) -> bool {
-        self.content.insert(entry)
+        let added = self.content.insert(entry);
+        if added {
+            self.generation += 1;
+        }
+        added
+    }
+
+    fn remove_entry(&mut self, entry: FileHandleU64) -> bool {
+        let removed = self.content.remove(&entry);
+        if removed {
+            self.generation += 1;
+        }
+        removed
+    }
+
+    const fn cookieverf(&self) -> cookieverf3 {
+        cookieverf3(self.generation.to_be_bytes())
     }
 }
 
@@ -97,6 +120,7 @@ impl Dir {
 struct File {
     name: filename3<'static>,
     attr: fattr3,
+    acl: PosixAcl,
     content: Vec<u8>,
     verf: createverf3,
 }
@@ -127,6 +151,7 @@ impl File {
         Self {
             name,
             attr,
+            acl: PosixAcl::default(),
             content,
             verf,
         }
@@ -144,17 +169,22 @@ impl File {
     }
 
     fn read(&self, offset: u64, count: u32) -> (Vec<u8>, bool) {
-        let mut start = usize::try_from(offset).unwrap_or(usize::MAX);
-        let mut end = start + count as usize;
-        let bytes = &self.content;
-        let eof = end >= bytes.len();
-        if start >= bytes.len() {
-            start = bytes.len();
-        }
-        if end > bytes.len() {
-            end = bytes.len();
-        }
-        (bytes[start..end].to_vec(), eof)
+        let (range, eof) = self.read_range(offset, count as usize);
+        (self.content[range].to_vec(), eof)
+    }
+
+    fn read_into(&self, offset: u64, buf: &mut [u8]) -> (usize, bool) {
+        let (range, eof) = self.read_range(offset, buf.len());
+        let len = range.len();
+        buf[..len].copy_from_slice(&self.content[range]);
+        (len, eof)
+    }
+
+    fn read_range(&self, offset: u64, count: usize) -> (std::ops::Range<usize>, bool) {
+        let len = self.content.len();
+        let start = usize::try_from(offset).unwrap_or(usize::MAX);
+        let end = start.saturating_add(count);
+        (start.min(len)..end.min(len), end >= len)
     }
 
     #[allow(clippy::cast_possible_truncation)]
@@ -267,6 +297,68 @@ impl Entry {
         }
     }
 
+    const fn acl(&self) -> &PosixAcl {
+        match self {
+            Self::File(file) => &file.acl,
+            Self::Dir(dir) => &dir.acl,
+        }
+    }
+
+    /// Replaces the ACLs and updates the mode bits to match the access ACL
+    fn set_acl(&mut self, mut acl: PosixAcl) -> Result<(), nfsstat3> {
+        let is_dir = matches!(self, Self::Dir(_));
+        if !is_dir && !acl.default.is_empty() {
+            return Err(nfsstat3::NFS3ERR_INVAL);
+        }
+        let attr = self.attr_mut();
+        if let Some(mode) = acl.access_mode() {
+            attr.mode = (attr.mode & !0o777) | mode;
+        }
+        attr.ctime = current_time();
+        // an ACL without named entries and a mask is fully described by the mode
+        let is_minimal = acl
+            .access
+            .iter()
+            .all(|entry| matches!(entry.type_, ACL_USER_OBJ | ACL_GROUP_OBJ | ACL_OTHER));
+        if is_minimal {
+            acl.access.clear();
+        }
+        match self {
+            Self::File(file) => file.acl = acl,
+            Self::Dir(dir) => dir.acl = acl,
+        }
+        Ok(())
+    }
+
+    /// Applies the default ACL of the parent directory to a new entry
+    ///
+    /// The permissions of the inherited access ACL are limited by the mode the entry
+    /// was created with.
+    fn inherit_acl(&mut self, parent_default: &[aclent]) {
+        if parent_default.is_empty() {
+            return;
+        }
+        let default = if matches!(self, Self::Dir(_)) {
+            parent_default.to_vec()
+        } else {
+            Vec::new()
+        };
+        let mut access = parent_default.to_vec();
+        update_mode_entries(&mut access, self.attr().mode, |perm, bits| perm & bits);
+        let _ = self.set_acl(PosixAcl { access, default });
+    }
+
+    /// Changes the permission bits and the entries of the access ACL that follow them
+    fn chmod(&mut self, mode: u32) {
+        let attr = self.attr_mut();
+        attr.mode = (attr.mode & !0o7777) | (mode & 0o7777);
+        let acl = match self {
+            Self::File(file) => &mut file.acl,
+            Self::Dir(dir) => &mut dir.acl,
+        };
+        update_mode_entries(&mut acl.access, mode, |_, bits| bits);
+    }
+
     fn set_attr(&mut self, setattr: &sattr3) {
         {
             let attr = self.attr_mut();
@@ -295,6 +387,9 @@ impl Entry {
                 attr.gid = u;
             }
         }
+        if let nfs::set_mode3::Some(mode) = setattr.mode {
+            self.chmod(mode);
+        }
         if let nfs::set_size3::Some(s) = setattr.size {
             if let Self::File(file) = self {
                 file.resize(s);
@@ -303,6 +398,23 @@ impl Entry {
     }
 }
 
+/// Updates the entries of an access ACL that correspond to the permission bits of `mode`
+///
+/// Like with POSIX ACLs, the group bits belong to the mask entry if there is one.
+fn update_mode_entries(access: &mut [aclent], mode: u32, update: impl Fn(u32, u32) -> u32) {
+    let has_mask = access.iter().any(|entry| entry.type_ == ACL_MASK);
+    for entry in access {
+        let bits = match entry.type_ {
+            ACL_USER_OBJ => mode >> 6,
+            ACL_MASK => mode >> 3,
+            ACL_GROUP_OBJ if !has_mask => mode >> 3,
+            ACL_OTHER => mode,
+            _ => continue,
+        };
+        entry.perm = update(entry.perm, bits & 0o7);
+    }
+}
+
 #[derive(Debug)]
 struct Fs {
     entries: HashMap<FileHandleU64, Entry>,
@@ -321,10 +433,15 @@ impl Fs {
         }
     }
 
-    fn push(&mut self, parent: FileHandleU64, entry: Entry) -> Result<(), nfsstat3> {
+    /// Adds a new entry to a directory and returns its attributes
+    fn push(&mut self, parent: FileHandleU64, mut entry: Entry) -> Result<fattr3, nfsstat3> {
         use std::collections::hash_map::Entry as MapEntry;
 
         let id = entry.fileid();
+        if let Some(Entry::Dir(dir)) = self.entries.get(&parent) {
+            entry.inherit_acl(&dir.acl.default);
+        }
+        let attr = entry.attr().clone();
 
         let map_entry = self.entries.entry(id);
         match map_entry {
@@ -352,7 +469,7 @@ impl Fs {
             Some(Entry::Dir(dir)) => {
                 let added = dir.add_entry(id);
                 assert!(added, "failed to add a new entry to directory");
-                Ok(())
+                Ok(attr)
             }
         }
     }
@@ -387,8 +504,7 @@ impl Fs {
             .get_mut(&dirid)
             .expect("entry not found")
             .as_dir_mut()?
-            .content
-            .remove(&object_id);
+            .remove_entry(object_id);
         Ok(())
     }
 
@@ -518,7 +634,7 @@ impl Fs {
             let from_dir = self
                 .get_mut(from_dirid)
                 .ok_or(nfsstat3::NFS3ERR_SERVERFAULT)?;
-            from_dir.as_dir_mut()?.content.remove(&from_id);
+            from_dir.as_dir_mut()?.remove_entry(from_id);
         }
 
         // Add to new parent directory
@@ -526,7 +642,7 @@ impl Fs {
             let to_dir = self
                 .get_mut(to_dirid)
                 .ok_or(nfsstat3::NFS3ERR_SERVERFAULT)?;
-            let added = to_dir.as_dir_mut()?.content.insert(from_id);
+            let added = to_dir.as_dir_mut()?.add_entry(from_id);
             if !added {
                 tracing::error!("failed to add entry to target directory");
                 return Err(nfsstat3::NFS3ERR_SERVERFAULT);
@@ -597,9 +713,8 @@ impl MemFs {
             .into();
 
         let dir = Entry::new_dir(dirname, newid, dirid);
-        let attr = dir.attr().clone();
-
-        self.fs
+        let attr = self
+            .fs
             .write()
             .expect("lock is poisoned")
             .push(dirid, dir)?;
@@ -642,7 +757,7 @@ impl MemFs {
                 return Err(e);
             }
         }
-        fs_lock.push(dirid, file)?;
+        let attr = fs_lock.push(dirid, file)?;
 
         Ok((newid, attr))
     }
@@ -717,6 +832,18 @@ impl NfsReadFileSystem for MemFs {
         Ok(file.read(offset, count))
     }
 
+    async fn read_into(
+        &self,
+        id: &FileHandleU64,
+        offset: u64,
+        buf: &mut [u8],
+    ) -> Result<(usize, bool), nfsstat3> {
+        let fs = self.fs.read().expect("lock is poisoned");
+        let entry = fs.get(*id).ok_or(nfsstat3::NFS3ERR_NOENT)?;
+        let file = entry.as_file()?;
+        Ok(file.read_into(offset, buf))
+    }
+
     async fn readdir(
         &self,
         dirid: &FileHandleU64,
@@ -735,6 +862,18 @@ impl NfsReadFileSystem for MemFs {
         Ok(iter)
     }
 
+    async fn cookieverf(&self, dirid: &FileHandleU64) -> Result<cookieverf3, nfsstat3> {
+        let fs = self.fs.read().expect("lock is poisoned");
+        let entry = fs.get(*dirid).ok_or(nfsstat3::NFS3ERR_NOENT)?;
+        Ok(entry.as_dir()?.cookieverf())
+    }
+
+    async fn getacl(&self, id: &FileHandleU64) -> Result<PosixAcl, nfsstat3> {
+        let fs = self.fs.read().expect("lock is poisoned");
+        let entry = fs.get(*id).ok_or(nfsstat3::NFS3ERR_NOENT)?;
+        Ok(entry.acl().clone())
+    }
+
     async fn readlink(&self, _id: &FileHandleU64) -> Result<nfspath3<'_>, nfsstat3> {
         tracing::warn!("readlink not implemented");
         Err(nfsstat3::NFS3ERR_NOTSUPP)
@@ -821,6 +960,12 @@ impl NfsFileSystem for MemFs {
         fs.rename(*from_dirid, from_filename, *to_dirid, to_filename)
     }
 
+    async fn setacl(&self, id: &FileHandleU64, acl: PosixAcl) -> Result<(), nfsstat3> {
+        let mut fs = self.fs.write().expect("lock is poisoned");
+        let entry = fs.get_mut(*id).ok_or(nfsstat3::NFS3ERR_NOENT)?;
+        entry.set_acl(acl)
+    }
+
     async fn symlink<'a>(
         &self,
         _dirid: &FileHandleU64,
@@ -1,7 +1,8 @@
 use nfs3_types::mount::{
-    MOUNT_PROGRAM, VERSION, dirpath, export_node, exports, fhandle3, mountres3, mountres3_ok,
-    mountstat3,
+    MOUNT_PROGRAM, VERSION, VERSION_1, dirpath, export_node, exports, fhandle3, fhstatus,
+    mountres3, mountres3_ok, mountstat3,
 };
+use nfs3_types::nfs3::nfs_fh3;
 use nfs3_types::rpc::{accept_stat_data, auth_flavor};
 use nfs3_types::xdr_codec::{List, Opaque, Void};
 use tracing::{debug, error, warn};
@@ -25,10 +26,11 @@ where
     let xid = message.xid();
 
     debug!("handle_nfs({xid}, {call:?}");
-    if call.vers != VERSION {
-        warn!("Invalid Mount Version number {} != {VERSION}", call.vers);
+    let vers = call.vers;
+    if vers != VERSION && vers != VERSION_1 {
+        warn!("Invalid Mount Version number {vers} != {VERSION}");
         return message.into_error_reply(accept_stat_data::PROG_MISMATCH {
-            low: VERSION,
+            low: VERSION_1,
             high: VERSION,
         });
     }
@@ -41,6 +43,7 @@ where
     debug!("{proc}({})", message.xid());
     match proc {
         MOUNTPROC3_NULL => handle(context, message, mountproc3_null).await,
+        MOUNTPROC3_MNT if vers == VERSION_1 => handle(context, message, mountproc1_mnt).await,
         MOUNTPROC3_MNT => handle(context, message, mountproc3_mnt).await,
         MOUNTPROC3_UMNT => handle(context, message, mountproc3_umnt).await,
         MOUNTPROC3_UMNTALL => handle(context, message, mountproc3_umnt_all).await,
@@ -64,6 +67,49 @@ async fn mountproc3_mnt<T>(
     xid: u32,
     path: dirpath<'_>,
 ) -> mountres3<'static>
+where
+    T: NfsFileSystem,
+{
+    match mount(&context, xid, &path).await {
+        Ok(root) => {
+            let response = mountres3_ok {
+                fhandle: fhandle3(root.data),
+                auth_flavors: vec![auth_flavor::AUTH_NULL as u32, auth_flavor::AUTH_UNIX as u32],
+            };
+            debug!("{xid} --> {response:?}");
+            mountres3::Ok(response)
+        }
+        Err(stat) => mountres3::Err(stat),
+    }
+}
+
+/// `MNT` of version 1 of the protocol, which returns an `NFSv2` handle
+async fn mountproc1_mnt<T>(context: RPCContext<T>, xid: u32, path: dirpath<'_>) -> fhstatus
+where
+    T: NfsFileSystem,
+{
+    let root = match mount(&context, xid, &path).await {
+        Ok(root) => root,
+        Err(stat) => return fhstatus::Err(stat),
+    };
+    match crate::nfs2_handlers::fh_to_nfs2(&root) {
+        Ok(fhandle) => {
+            debug!("{xid} --> {fhandle:?}");
+            fhstatus::Ok(fhandle)
+        }
+        Err(stat) => {
+            error!("{xid} --> cannot create an NFSv2 handle: {stat}");
+            fhstatus::Err(mountstat3::MNT3ERR_IO)
+        }
+    }
+}
+
+/// Resolves the mount path and returns the handle of the mounted directory
+async fn mount<T>(
+    context: &RPCContext<T>,
+    xid: u32,
+    path: &dirpath<'_>,
+) -> Result<nfs_fh3, mountstat3>
 where
     T: NfsFileSystem,
 {
@@ -72,7 +118,7 @@ where
         Ok(path) => path,
         Err(e) => {
             tracing::error!("{xid} --> invalid mount path: {e}");
-            return mountres3::Err(mountstat3::MNT3ERR_INVAL);
+            return Err(mountstat3::MNT3ERR_INVAL);
         }
     };
 
@@ -82,25 +128,23 @@ where
     } else {
         // invalid export
         debug!("{xid} --> no matching export");
-        return mountres3::Err(mountstat3::MNT3ERR_NOENT);
+        return Err(mountstat3::MNT3ERR_NOENT);
     };
 
     match context.vfs.lookup_by_path(path).await {
         Ok(fileid) => {
-            let root = context.file_handle_converter.fh_to_nfs(&fileid);
-            let response = mountres3_ok {
-                fhandle: fhandle3(root.data),
-                auth_flavors: vec![auth_flavor::AUTH_NULL as u32, auth_flavor::AUTH_UNIX as u32],
-            };
-            debug!("{xid} --> {response:?}");
+            let root = context
+                .file_handle_converter
+                .fh_to_nfs(&fileid)
+                .map_err(|_| mountstat3::MNT3ERR_SERVERFAULT)?;
             if let Some(ref chan) = context.mount_signal {
                 let _ = chan.send(true).await;
             }
-            mountres3::Ok(response)
+            Ok(root)
         }
         Err(e) => {
             debug!("{xid} --> MNT3ERR_NOENT({e:?})");
-            mountres3::Err(mountstat3::MNT3ERR_NOENT)
+            Err(mountstat3::MNT3ERR_NOENT)
         }
     }
 }
@@ -0,0 +1,100 @@
+use std::sync::Mutex;
+
+/// Number of 64-bit cookies remembered at once
+const CAPACITY: u32 = 1 << 16;
+/// Bit set in the `NFSv2` cookies that stand for a remembered 64-bit cookie
+const MAPPED: u32 = 1 << 31;
+
+#[derive(Debug)]
+struct Slot {
+    number: u32,
+    dir: Vec<u8>,
+    cookie: u64,
+}
+
+/// Maps the 64-bit directory cookies of the file system to the 32-bit cookies of `NFSv2`
+///
+/// Cookies below 2^31 are passed through unchanged. Larger ones are stored in a ring of
+/// [`CAPACITY`] slots and replaced with their sequence number with the top bit set, so a
+/// cookie stays valid until that many other cookies have been handed out.
+#[derive(Debug, Default)]
+pub struct Nfs2Cookies {
+    inner: Mutex<Inner>,
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    slots: Vec<Option<Slot>>,
+    next: u32,
+}
+
+impl Nfs2Cookies {
+    /// Returns the `NFSv2` cookie for `cookie` of the directory `dir`
+    pub fn shorten(&self, dir: &[u8], cookie: u64) -> u32 {
+        if let Ok(cookie) = u32::try_from(cookie) {
+            if cookie & MAPPED == 0 {
+                return cookie;
+            }
+        }
+        let mut inner = self.inner.lock().expect("lock is poisoned");
+        let number = inner.next;
+        inner.next = (number + 1) & !MAPPED;
+        if inner.slots.is_empty() {
+            inner.slots.resize_with(CAPACITY as usize, || None);
+        }
+        inner.slots[(number % CAPACITY) as usize] = Some(Slot {
+            number,
+            dir: dir.to_vec(),
+            cookie,
+        });
+        number | MAPPED
+    }
+
+    /// Returns the 64-bit cookie for an `NFSv2` cookie of the directory `dir`, or `None`
+    /// if it has been forgotten or was issued for another directory
+    pub fn resolve(&self, dir: &[u8], cookie: u32) -> Option<u64> {
+        if cookie & MAPPED == 0 {
+            return Some(cookie.into());
+        }
+        let number = cookie & !MAPPED;
+        let inner = self.inner.lock().expect("lock is poisoned");
+        inner
+            .slots
+            .get((number % CAPACITY) as usize)
+            .and_then(Option::as_ref)
+            .filter(|slot| slot.number == number && slot.dir == dir)
+            .map(|slot| slot.cookie)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_small_cookies_pass_through() {
+        let cookies = Nfs2Cookies::default();
+        assert_eq!(cookies.shorten(b"dir", 0), 0);
+        assert_eq!(cookies.shorten(b"dir", 42), 42);
+        assert_eq!(cookies.resolve(b"other", 42), Some(42));
+    }
+
+    #[test]
+    fn test_large_cookies_are_mapped() {
+        let cookies = Nfs2Cookies::default();
+        let first = cookies.shorten(b"dir", 1 << 32 | 1);
+        let second = cookies.shorten(b"dir", u64::from(MAPPED));
+        assert_ne!(first, second);
+        assert_eq!(cookies.resolve(b"dir", first), Some(1 << 32 | 1));
+        assert_eq!(cookies.resolve(b"dir", second), Some(u64::from(MAPPED)));
+        // the cookie belongs to its directory
+        assert_eq!(cookies.resolve(b"other", first), None);
+
+        // old cookies are forgotten once the ring wraps around
+        for i in 0..CAPACITY {
+            cookies.shorten(b"dir", 2 << 32 | u64::from(i));
+        }
+        assert_eq!(cookies.resolve(b"dir", first), None);
+        assert_eq!(cookies.resolve(b"dir", MAPPED | (3 * CAPACITY + 5)), None);
+    }
+}
@@ -0,0 +1,614 @@
+//! `NFSv2` procedures translated onto the [`NfsFileSystem`] traits.
+//!
+//! `NFSv2` has 32-bit sizes and offsets and 32-byte file handles. Sizes that don't fit are
+//! clamped, file ids are folded into 32 bits, and directory cookies that don't fit are
+//! remembered by the server and replaced with shorter ones. The first byte of an `NFSv2`
+//! handle holds the length of the `NFSv3` handle stored after it, so only handles of up to
+//! 31 bytes can be used over `NFSv2`. This covers the default handles, also signed ones, but
+//! not signed handles in the subtree check mode.
+
+#[allow(clippy::wildcard_imports)]
+use nfs3_types::nfs2::*;
+use nfs3_types::nfs3::{
+    fattr3, filename3, nfs_fh3, nfspath3, nfsstat3, nfstime3, sattr3, set_atime, set_gid3,
+    set_mode3, set_mtime, set_size3, set_uid3,
+};
+use nfs3_types::rpc::accept_stat_data;
+use nfs3_types::xdr_codec::{BoundedList, Opaque, Void};
+use tracing::{debug, error, trace, warn};
+
+use crate::context::RPCContext;
+use crate::rpcwire::handle;
+use crate::rpcwire::messages::{HandleResult, IncomingRpcMessage};
+use crate::vfs::{NextResult, NfsFileSystem, ReadDirIterator, VFSCapabilities};
+
+/// The block size reported in the attributes and by STATFS
+const BLOCK_SIZE: u32 = 4096;
+
+/// `useconds` value of `sattr2` times which asks for the server time, as sent by Linux clients
+const SET_TO_SERVER_TIME: u32 = 1_000_000;
+
+#[allow(clippy::enum_glob_use)]
+pub async fn handle_nfs2<T>(
+    context: RPCContext<T>,
+    message: IncomingRpcMessage,
+) -> anyhow::Result<HandleResult>
+where
+    T: NfsFileSystem,
+{
+    use NFS_PROGRAM::*;
+
+    let call = message.body();
+    let xid = message.xid();
+
+    debug!("handle_nfs2({xid}, {call:?}");
+    let Ok(proc) = NFS_PROGRAM::try_from(call.proc) else {
+        error!("invalid NFS2 Program number {}", call.proc);
+        return message.into_error_reply(accept_stat_data::PROC_UNAVAIL);
+    };
+
+    debug!("{proc}({})", message.xid());
+    match proc {
+        NFSPROC_NULL | NFSPROC_ROOT | NFSPROC_WRITECACHE => {
+            handle(context, message, nfsproc_null).await
+        }
+        NFSPROC_GETATTR => handle(context, message, nfsproc_getattr).await,
+        NFSPROC_SETATTR => handle(context, message, nfsproc_setattr).await,
+        NFSPROC_LOOKUP => handle(context, message, nfsproc_lookup).await,
+        NFSPROC_READLINK => handle(context, message, nfsproc_readlink).await,
+        NFSPROC_READ => handle(context, message, nfsproc_read).await,
+        NFSPROC_WRITE => handle(context, message, nfsproc_write).await,
+        NFSPROC_CREATE => handle(context, message, nfsproc_create).await,
+        NFSPROC_REMOVE | NFSPROC_RMDIR => handle(context, message, nfsproc_remove).await,
+        NFSPROC_RENAME => handle(context, message, nfsproc_rename).await,
+        NFSPROC_SYMLINK => handle(context, message, nfsproc_symlink).await,
+        NFSPROC_MKDIR => handle(context, message, nfsproc_mkdir).await,
+        NFSPROC_READDIR => handle(context, message, nfsproc_readdir).await,
+        NFSPROC_STATFS => handle(context, message, nfsproc_statfs).await,
+        NFSPROC_LINK => {
+            warn!("Unimplemented message {proc}");
+            message.into_error_reply(accept_stat_data::PROC_UNAVAIL)
+        }
+    }
+}
+
+/// Packs an `NFSv3` handle into an `NFSv2` one
+pub fn fh_to_nfs2(fh: &nfs_fh3) -> Result<fhandle2, nfsstat2> {
+    let len = fh.data.len();
+    if len >= NFS2_FHSIZE {
+        warn!("file handle of {len} bytes doesn't fit into an NFSv2 handle");
+        return Err(nfsstat2::NFSERR_IO);
+    }
+    let mut data = [0; NFS2_FHSIZE];
+    #[allow(clippy::cast_possible_truncation)] // checked above
+    {
+        data[0] = len as u8;
+    }
+    data[1..=len].copy_from_slice(&fh.data);
+    Ok(fhandle2(data))
+}
+
+/// Unpacks the `NFSv3` handle stored in an `NFSv2` one
+fn fh_from_nfs2(fh: &fhandle2) -> Result<nfs_fh3, nfsstat2> {
+    let len = usize::from(fh.0[0]);
+    if len == 0 || len >= NFS2_FHSIZE {
+        return Err(nfsstat2::NFSERR_STALE);
+    }
+    Ok(nfs_fh3 {
+        data: Opaque::owned(fh.0[1..=len].to_vec()),
+    })
+}
+
+/// Resolves an `NFSv2` handle into the `NFSv3` handle and the id of the object
+fn fh_to_id<T>(context: &RPCContext<T>, fh: &fhandle2) -> Result<(nfs_fh3, T::Handle), nfsstat2>
+where
+    T: NfsFileSystem,
+{
+    let fh = fh_from_nfs2(fh)?;
+    match context.file_handle_converter.fh_from_nfs(&fh) {
+        Ok(id) => Ok((fh, id)),
+        Err(stat) => {
+            warn!("cannot resolve fh: {stat}");
+            Err(stat.into())
+        }
+    }
+}
+
+fn check_writable<T>(context: &RPCContext<T>) -> Result<(), nfsstat2>
+where
+    T: NfsFileSystem,
+{
+    if matches!(context.vfs.capabilities(), VFSCapabilities::ReadWrite) {
+        Ok(())
+    } else {
+        Err(nfsstat2::NFSERR_ROFS)
+    }
+}
+
+/// Logs the result of a procedure and converts it into the reply
+fn reply<R: std::fmt::Debug>(
+    xid: u32,
+    procedure: &str,
+    result: Result<R, nfsstat2>,
+) -> Nfs2Result<R> {
+    match &result {
+        Ok(res) => debug!("{procedure} success {xid} --> {res:?}"),
+        Err(stat) => warn!("{procedure} error {xid} --> {stat}"),
+    }
+    result.into()
+}
+
+/// Folds a 64-bit value into 32 bits. Values that fit into 32 bits are not changed.
+#[allow(clippy::cast_possible_truncation)]
+const fn fold_u64(value: u64) -> u32 {
+    (value ^ (value >> 32)) as u32
+}
+
+/// Type bits of the `NFSv2` mode, which unlike the `NFSv3` one includes the file type
+const fn type_bits(ftype: ftype2) -> u32 {
+    match ftype {
+        ftype2::NFNON | ftype2::NFBAD => 0,
+        ftype2::NFREG => 0o100_000,
+        ftype2::NFDIR => 0o040_000,
+        ftype2::NFBLK => 0o060_000,
+        ftype2::NFCHR => 0o020_000,
+        ftype2::NFLNK => 0o120_000,
+        ftype2::NFSOCK => 0o140_000,
+        ftype2::NFFIFO => 0o010_000,
+    }
+}
+
+fn fattr2_from(attr: &fattr3) -> fattr2 {
+    let type_ = ftype2::from(attr.type_);
+    fattr2 {
+        type_,
+        mode: (attr.mode & 0o7777) | type_bits(type_),
+        nlink: attr.nlink,
+        uid: attr.uid,
+        gid: attr.gid,
+        size: u32::try_from(attr.size).unwrap_or(u32::MAX),
+        blocksize: BLOCK_SIZE,
+        rdev: (attr.rdev.specdata1 << 8) | (attr.rdev.specdata2 & 0xff),
+        blocks: u32::try_from(attr.used.div_ceil(u64::from(BLOCK_SIZE))).unwrap_or(u32::MAX),
+        fsid: fold_u64(attr.fsid),
+        fileid: fold_u64(attr.fileid),
+        atime: attr.atime.into(),
+        mtime: attr.mtime.into(),
+        ctime: attr.ctime.into(),
+    }
+}
+
+const fn is_unset(time: timeval2) -> bool {
+    time.seconds == NFS2_SATTR_UNSET && time.useconds == NFS2_SATTR_UNSET
+}
+
+const fn nfstime3_from(time: timeval2) -> nfstime3 {
+    nfstime3 {
+        seconds: time.seconds,
+        nseconds: time.useconds.saturating_mul(1000),
+    }
+}
+
+fn sattr3_from(attr: sattr2) -> sattr3 {
+    let set = |value: u32| (value != NFS2_SATTR_UNSET).then_some(value);
+    let atime = if is_unset(attr.atime) {
+        set_atime::DONT_CHANGE
+    } else if attr.atime.useconds == SET_TO_SERVER_TIME {
+        set_atime::SET_TO_SERVER_TIME
+    } else {
+        set_atime::SET_TO_CLIENT_TIME(nfstime3_from(attr.atime))
+    };
+    let mtime = if is_unset(attr.mtime) {
+        set_mtime::DONT_CHANGE
+    } else if attr.mtime.useconds == SET_TO_SERVER_TIME {
+        set_mtime::SET_TO_SERVER_TIME
+    } else {
+        set_mtime::SET_TO_CLIENT_TIME(nfstime3_from(attr.mtime))
+    };
+    sattr3 {
+        mode: set(attr.mode).map_or(set_mode3::None, |mode| set_mode3::Some(mode & 0o7777)),
+        uid: set(attr.uid).map_or(set_uid3::None, set_uid3::Some),
+        gid: set(attr.gid).map_or(set_gid3::None, set_gid3::Some),
+        size: set(attr.size).map_or(set_size3::None, |size| set_size3::Some(size.into())),
+        atime,
+        mtime,
+    }
+}
+
+/// Converts the name of a directory entry, rejecting names that aren't a single path
+/// component, e.g. "../x"
+fn entry_name(name: filename2<'_>) -> Result<filename3<'_>, nfsstat2> {
+    if name.0.is_empty() || name.0.contains(&b'/') {
+        warn!("invalid name {name:?}");
+        return Err(nfsstat2::NFSERR_ACCES);
+    }
+    Ok(filename3(name.0))
+}
+
+async fn attributes<T>(context: &RPCContext<T>, id: &T::Handle) -> Result<fattr2, nfsstat2>
+where
+    T: NfsFileSystem,
+{
+    let attr = context.vfs.getattr(id).await?;
+    Ok(fattr2_from(&attr))
+}
+
+async fn nfsproc_null<T>(_: RPCContext<T>, _: u32, _: Void) -> Void
+where
+    T: NfsFileSystem,
+{
+    Void
+}
+
+async fn nfsproc_getattr<T>(context: RPCContext<T>, xid: u32, file: fhandle2) -> attrstat2
+where
+    T: NfsFileSystem,
+{
+    let result = async {
+        let (_, id) = fh_to_id(&context, &file)?;
+        attributes(&context, &id).await
+    };
+    reply(xid, "getattr", result.await)
+}
+
+async fn nfsproc_setattr<T>(context: RPCContext<T>, xid: u32, args: SETATTR2args) -> attrstat2
+where
+    T: NfsFileSystem,
+{
+    let result = async {
+        check_writable(&context)?;
+        let (_, id) = fh_to_id(&context, &args.file)?;
+        let attr = context
+            .vfs
+            .setattr(&id, sattr3_from(args.attributes))
+            .await?;
+        Ok(fattr2_from(&attr))
+    };
+    reply(xid, "setattr", result.await)
+}
+
+async fn nfsproc_lookup<T>(context: RPCContext<T>, xid: u32, args: diropargs2<'_>) -> DIROP2res
+where
+    T: NfsFileSystem,
+{
+    let result = async {
+        let (dir, dirid) = fh_to_id(&context, &args.dir)?;
+        let name = entry_name(args.name)?;
+        if name.as_ref() == b".." && context.file_handle_converter.is_scope_root(&dir, &dirid) {
+            warn!("lookup error {xid} --> refusing to leave the mounted subtree");
+            return Err(nfsstat2::NFSERR_ACCES);
+        }
+        // "." is answered here, so the backend can't hand out another id for the directory
+        let id = if name.as_ref() == b"." {
+            dirid
+        } else {
+            context.vfs.lookup(&dirid, &name).await?
+        };
+        let attributes = attributes(&context, &id).await?;
+        let file = fh_to_nfs2(&context.file_handle_converter.fh_to_nfs_child(&dir, &id)?)?;
+        Ok(DIROP2resok { file, attributes })
+    };
+    reply(xid, "lookup", result.await)
+}
+
+async fn nfsproc_readlink<T>(
+    context: RPCContext<T>,
+    xid: u32,
+    file: fhandle2,
+) -> READLINK2res<'static>
+where
+    T: NfsFileSystem,
+{
+    let result = async {
+        let (_, id) = fh_to_id(&context, &file)?;
+        let path = context.vfs.readlink(&id).await?;
+        Ok(nfspath2(Opaque::owned(path.0.to_vec())))
+    };
+    reply(xid, "readlink", result.await)
+}
+
+async fn nfsproc_read<T>(context: RPCContext<T>, xid: u32, args: READ2args) -> READ2res<'static>
+where
+    T: NfsFileSystem,
+{
+    let result = async {
+        let (_, id) = fh_to_id(&context, &args.file)?;
+        let count = args.count.min(NFS2_MAXDATA);
+        let (data, _) = context.vfs.read(&id, args.offset.into(), count).await?;
+        let attributes = attributes(&context, &id).await?;
+        debug!(" {xid} --> read {} bytes", data.len());
+        Ok(READ2resok {
+            attributes,
+            data: Opaque::owned(data),
+        })
+    };
+    match result.await {
+        Ok(res) => READ2res::Ok(res),
+        Err(stat) => {
+            error!("read error {xid} --> {stat}");
+            READ2res::Err(stat)
+        }
+    }
+}
+
+async fn nfsproc_write<T>(context: RPCContext<T>, xid: u32, args: WRITE2args<'_>) -> attrstat2
+where
+    T: NfsFileSystem,
+{
+    let result = async {
+        check_writable(&context)?;
+        if args.data.len() > NFS2_MAXDATA as usize {
+            error!(
+                "write error {xid} --> {} bytes exceed {NFS2_MAXDATA}",
+                args.data.len()
+            );
+            return Err(nfsstat2::NFSERR_IO);
+        }
+        let end = u64::from(args.offset) + args.data.len() as u64;
+        if end > u64::from(u32::MAX) {
+            return Err(nfsstat2::NFSERR_FBIG);
+        }
+        let (_, id) = fh_to_id(&context, &args.file)?;
+        let attr = context
+            .vfs
+            .write(&id, args.offset.into(), &args.data)
+            .await?;
+        // NFSv2 writes are always stable
+        if context.vfs.buffers_writes() {
+            let attr = context.vfs.commit(&id).await?;
+            return Ok(fattr2_from(&attr));
+        }
+        Ok(fattr2_from(&attr))
+    };
+    reply(xid, "write", result.await)
+}
+
+async fn nfsproc_create<T>(context: RPCContext<T>, xid: u32, args: CREATE2args<'_>) -> DIROP2res
+where
+    T: NfsFileSystem,
+{
+    let result = async {
+        check_writable(&context)?;
+        let (dir, dirid) = fh_to_id(&context, &args.where_.dir)?;
+        let (id, attr) = context
+            .vfs
+            .create(
+                &dirid,
+                &entry_name(args.where_.name)?,
+                sattr3_from(args.attributes),
+            )
+            .await?;
+        let file = fh_to_nfs2(&context.file_handle_converter.fh_to_nfs_child(&dir, &id)?)?;
+        Ok(DIROP2resok {
+            file,
+            attributes: fattr2_from(&attr),
+        })
+    };
+    reply(xid, "create", result.await)
+}
+
+async fn nfsproc_mkdir<T>(context: RPCContext<T>, xid: u32, args: CREATE2args<'_>) -> DIROP2res
+where
+    T: NfsFileSystem,
+{
+    let result = async {
+        check_writable(&context)?;
+        let (dir, dirid) = fh_to_id(&context, &args.where_.dir)?;
+        let (id, attr) = context
+            .vfs
+            .mkdir(&dirid, &entry_name(args.where_.name)?)
+            .await?;
+        let file = fh_to_nfs2(&context.file_handle_converter.fh_to_nfs_child(&dir, &id)?)?;
+        Ok(DIROP2resok {
+            file,
+            attributes: fattr2_from(&attr),
+        })
+    };
+    reply(xid, "mkdir", result.await)
+}
+
+async fn nfsproc_remove<T>(context: RPCContext<T>, xid: u32, args: diropargs2<'_>) -> nfsstat2
+where
+    T: NfsFileSystem,
+{
+    let result = async {
+        check_writable(&context)?;
+        let (_, dirid) = fh_to_id(&context, &args.dir)?;
+        context.vfs.remove(&dirid, &entry_name(args.name)?).await?;
+        Ok(())
+    };
+    reply(xid, "remove", result.await).status()
+}
+
+async fn nfsproc_rename<T>(context: RPCContext<T>, xid: u32, args: RENAME2args<'_, '_>) -> nfsstat2
+where
+    T: NfsFileSystem,
+{
+    let result = async {
+        check_writable(&context)?;
+        let (from_dir, from_dirid) = fh_to_id(&context, &args.from.dir)?;
+        let (to_dir, to_dirid) = fh_to_id(&context, &args.to.dir)?;
+        if !context.file_handle_converter.same_scope(&from_dir, &to_dir) {
+            warn!("rename error {xid} --> directories belong to different mounts");
+            return Err(nfsstat2::NFSERR_ACCES);
+        }
+        context
+            .vfs
+            .rename(
+                &from_dirid,
+                &entry_name(args.from.name)?,
+                &to_dirid,
+                &entry_name(args.to.name)?,
+            )
+            .await?;
+        Ok(())
+    };
+    reply(xid, "rename", result.await).status()
+}
+
+async fn nfsproc_symlink<T>(
+    context: RPCContext<T>,
+    xid: u32,
+    args: SYMLINK2args<'_, '_>,
+) -> nfsstat2
+where
+    T: NfsFileSystem,
+{
+    let result = async {
+        check_writable(&context)?;
+        let (_, dirid) = fh_to_id(&context, &args.from.dir)?;
+        context
+            .vfs
+            .symlink(
+                &dirid,
+                &entry_name(args.from.name)?,
+                &nfspath3(args.to.0),
+                &sattr3_from(args.attributes),
+            )
+            .await?;
+        Ok(())
+    };
+    reply(xid, "symlink", result.await).status()
+}
+
+async fn nfsproc_readdir<T>(
+    context: RPCContext<T>,
+    xid: u32,
+    args: READDIR2args,
+) -> READDIR2res<'static>
+where
+    T: NfsFileSystem,
+{
+    let result = async {
+        let (dir, dirid) = fh_to_id(&context, &args.dir)?;
+        let cookies = &context.nfs2_cookies;
+        let Some(cookie) = cookies.resolve(&dir.data, u32::from_be_bytes(args.cookie)) else {
+            warn!("readdir error {xid} --> unknown cookie");
+            return Err(nfsstat3::NFS3ERR_BAD_COOKIE.into());
+        };
+        // the status and the eof flag
+        let Some(max_bytes_allowed) = (args.count as usize).checked_sub(8) else {
+            return Err(nfsstat2::NFSERR_IO);
+        };
+
+        let mut iter = context.vfs.readdir(&dirid, cookie).await?;
+        let mut entries = BoundedList::new(max_bytes_allowed);
+        let eof;
+        loop {
+            match iter.next().await {
+                NextResult::Ok(entry) => {
+                    let entry = entry2 {
+                        fileid: fold_u64(entry.fileid),
+                        name: filename2(entry.name.0),
+                        cookie: cookies.shorten(&dir.data, entry.cookie).to_be_bytes(),
+                    };
+                    if entries.try_push(entry).is_err() {
+                        trace!(" -- insufficient space. truncating");
+                        eof = false;
+                        break;
+                    }
+                }
+                NextResult::Eof => {
+                    eof = true;
+                    break;
+                }
+                NextResult::Err(stat) => return Err(stat.into()),
+            }
+        }
+
+        let entries = entries.into_inner();
+        if entries.0.is_empty() && !eof {
+            return Err(nfsstat2::NFSERR_IO);
+        }
+        Ok(READDIR2resok { entries, eof })
+    };
+    reply(xid, "readdir", result.await)
+}
+
+async fn nfsproc_statfs<T>(context: RPCContext<T>, xid: u32, file: fhandle2) -> STATFS2res
+where
+    T: NfsFileSystem,
+{
+    let blocks = |bytes: u64| u32::try_from(bytes / u64::from(BLOCK_SIZE)).unwrap_or(u32::MAX);
+    let result = async {
+        let (_, id) = fh_to_id(&context, &file)?;
+        let fsstat = context.vfs.fsstat(&id).await?;
+        Ok(STATFS2resok {
+            tsize: NFS2_MAXDATA,
+            bsize: BLOCK_SIZE,
+            blocks: blocks(fsstat.tbytes),
+            bfree: blocks(fsstat.fbytes),
+            bavail: blocks(fsstat.abytes),
+        })
+    };
+    reply(xid, "statfs", result.await)
+}
+
+#[cfg(test)]
+mod tests {
+    #![expect(clippy::unwrap_used)]
+
+    use nfs3_types::nfs3::{ftype3, nfstime3, specdata3};
+
+    use super::*;
+
+    #[test]
+    fn handle_roundtrip() {
+        let fh = nfs_fh3 {
+            data: Opaque::owned((1..=31).collect()),
+        };
+        let fh2 = fh_to_nfs2(&fh).unwrap();
+        assert_eq!(fh2.0[0], 31);
+        assert_eq!(fh_from_nfs2(&fh2).unwrap(), fh);
+
+        let too_long = nfs_fh3 {
+            data: Opaque::owned(vec![0; NFS2_FHSIZE]),
+        };
+        assert_eq!(fh_to_nfs2(&too_long), Err(nfsstat2::NFSERR_IO));
+    }
+
+    #[test]
+    fn attributes_are_clamped() {
+        let attr = fattr3 {
+            type_: ftype3::NF3REG,
+            mode: 0o644,
+            nlink: 1,
+            uid: 1000,
+            gid: 1000,
+            size: 5 << 30,
+            used: 5 << 30,
+            rdev: specdata3::default(),
+            fsid: 1,
+            fileid: (1 << 32) | 7,
+            atime: nfstime3::default(),
+            mtime: nfstime3 {
+                seconds: 10,
+                nseconds: 2_500_000,
+            },
+            ctime: nfstime3::default(),
+        };
+        let attr = fattr2_from(&attr);
+        assert_eq!(attr.mode, 0o100_644);
+        assert_eq!(attr.size, u32::MAX);
+        assert_eq!(attr.blocks, 1_310_720);
+        assert_eq!(attr.fileid, 6);
+        assert_eq!(attr.mtime.useconds, 2500);
+    }
+
+    #[test]
+    fn unset_attributes_are_not_changed() {
+        let attr = sattr3_from(sattr2::default());
+        assert!(matches!(attr.mode, set_mode3::None));
+        assert!(matches!(attr.size, set_size3::None));
+        assert!(matches!(attr.atime, set_atime::DONT_CHANGE));
+
+        let attr = sattr3_from(sattr2 {
+            mtime: timeval2 {
+                seconds: 0,
+                useconds: SET_TO_SERVER_TIME,
+            },
+            ..sattr2::default()
+        });
+        assert!(matches!(attr.mtime, set_mtime::SET_TO_SERVER_TIME));
+    }
+}
@@ -0,0 +1,740 @@
+//! `NFSv4.0` COMPOUND procedure on top of [`NfsFileSystem`]
+//!
+//! The operations of a COMPOUND are executed in order until the first one fails. They
+//! work on the current file handle, which is set by PUTROOTFH, PUTFH, LOOKUP, OPEN, etc.
+//! The root file handle is the root of the file system, there is no pseudo file system.
+//!
+//! Only the operations needed to browse and modify files are supported. The others,
+//! including locks and delegations, return `NFS4ERR_NOTSUPP`.
+
+mod attrs;
+
+use nfs3_types::nfs3::{
+    createverf3, fattr3, filename3, ftype3, nfs_fh3, nfspath3, sattr3, set_size3,
+};
+#[allow(clippy::wildcard_imports)]
+use nfs3_types::nfs4::*;
+use nfs3_types::rpc::accept_stat_data;
+use nfs3_types::xdr_codec::{BoundedList, Opaque, Void};
+use tracing::{debug, error, trace, warn};
+
+use self::attrs::FsAttrs;
+use crate::context::RPCContext;
+use crate::rpcwire::handle;
+use crate::rpcwire::messages::{HandleResult, IncomingRpcMessage};
+use crate::vfs::{FileHandle, NextResult, NfsFileSystem, VFSCapabilities};
+
+/// Size of the READDIR reply without the entries: status, verifier and eof
+const READDIR_REPLY_OVERHEAD: usize = 16;
+
+/// Cookies 1 and 2 are reserved, so the cookies of the file system are shifted by 2
+const COOKIE_OFFSET: u64 = 2;
+
+#[allow(clippy::enum_glob_use)]
+pub async fn handle_nfs4<T>(
+    context: RPCContext<T>,
+    message: IncomingRpcMessage,
+) -> anyhow::Result<HandleResult>
+where
+    T: NfsFileSystem,
+{
+    use NFS4_PROGRAM::*;
+
+    let call = message.body();
+    let xid = message.xid();
+
+    debug!("handle_nfs4({xid}, {call:?}");
+    let Ok(proc) = NFS4_PROGRAM::try_from(call.proc) else {
+        error!("invalid NFS4 Program number {}", call.proc);
+        return message.into_error_reply(accept_stat_data::PROC_UNAVAIL);
+    };
+
+    debug!("{proc}({})", message.xid());
+    match proc {
+        NFSPROC4_NULL => handle(context, message, nfsproc4_null).await,
+        NFSPROC4_COMPOUND => handle(context, message, nfsproc4_compound).await,
+    }
+}
+
+async fn nfsproc4_null<T>(_: RPCContext<T>, _: u32, _: Void) -> Void
+where
+    T: NfsFileSystem,
+{
+    Void
+}
+
+async fn nfsproc4_compound<T>(
+    context: RPCContext<T>,
+    xid: u32,
+    args: COMPOUND4args<'_>,
+) -> COMPOUND4res<'static>
+where
+    T: NfsFileSystem,
+{
+    let tag = Opaque::owned(args.tag.to_vec());
+    if args.minorversion != 0 {
+        warn!(
+            "compound error {xid} --> unsupported minor version {}",
+            args.minorversion
+        );
+        return COMPOUND4res {
+            status: nfsstat4::NFS4ERR_MINOR_VERS_MISMATCH,
+            tag,
+            resarray: Vec::new(),
+        };
+    }
+
+    let mut compound = Compound {
+        context,
+        current: None,
+        saved: None,
+    };
+    let mut status = nfsstat4::NFS4_OK;
+    let mut resarray = Vec::with_capacity(args.argarray.len());
+    for op in args.argarray {
+        let opnum = op.opnum();
+        let res = compound.execute(op).await;
+        status = res.status();
+        resarray.push(res);
+        if status != nfsstat4::NFS4_OK {
+            warn!("compound error {xid} --> {opnum} failed with {status}");
+            break;
+        }
+        trace!(" {xid} --> {opnum} ok");
+    }
+
+    COMPOUND4res {
+        status,
+        tag,
+        resarray,
+    }
+}
+
+/// The state of a COMPOUND request
+struct Compound<T: NfsFileSystem> {
+    context: RPCContext<T>,
+    current: Option<nfs_fh3>,
+    saved: Option<nfs_fh3>,
+}
+
+impl<T: NfsFileSystem> Compound<T> {
+    async fn execute(&mut self, op: nfs_argop4<'_>) -> nfs_resop4<'static> {
+        match op {
+            nfs_argop4::OP_ACCESS(args) => nfs_resop4::OP_ACCESS(self.access(&args).into()),
+            nfs_argop4::OP_CLOSE(args) => nfs_resop4::OP_CLOSE(self.close(&args).into()),
+            nfs_argop4::OP_COMMIT(_) => nfs_resop4::OP_COMMIT(self.commit().await.into()),
+            nfs_argop4::OP_CREATE(args) => nfs_resop4::OP_CREATE(self.create(args).await.into()),
+            nfs_argop4::OP_GETATTR(args) => {
+                nfs_resop4::OP_GETATTR(self.getattr(&args).await.into())
+            }
+            nfs_argop4::OP_GETFH(_) => nfs_resop4::OP_GETFH(self.getfh().into()),
+            nfs_argop4::OP_LOOKUP(args) => nfs_resop4::OP_LOOKUP(self.lookup(&args).await.into()),
+            nfs_argop4::OP_LOOKUPP(_) => nfs_resop4::OP_LOOKUPP(self.lookupp().await.into()),
+            nfs_argop4::OP_OPEN(args) => nfs_resop4::OP_OPEN(self.open(args).await.into()),
+            nfs_argop4::OP_OPEN_CONFIRM(args) => {
+                nfs_resop4::OP_OPEN_CONFIRM(self.open_confirm(&args).into())
+            }
+            nfs_argop4::OP_PUTFH(args) => nfs_resop4::OP_PUTFH(self.putfh(&args).into()),
+            nfs_argop4::OP_PUTPUBFH(_) => nfs_resop4::OP_PUTPUBFH(self.putrootfh().into()),
+            nfs_argop4::OP_PUTROOTFH(_) => nfs_resop4::OP_PUTROOTFH(self.putrootfh().into()),
+            nfs_argop4::OP_READ(args) => nfs_resop4::OP_READ(self.read(&args).await.into()),
+            nfs_argop4::OP_READDIR(args) => {
+                nfs_resop4::OP_READDIR(self.readdir(&args).await.into())
+            }
+            nfs_argop4::OP_READLINK(_) => nfs_resop4::OP_READLINK(self.readlink().await.into()),
+            nfs_argop4::OP_REMOVE(args) => nfs_resop4::OP_REMOVE(self.remove(&args).await.into()),
+            nfs_argop4::OP_RENAME(args) => nfs_resop4::OP_RENAME(self.rename(&args).await.into()),
+            nfs_argop4::OP_RENEW(args) => nfs_resop4::OP_RENEW(self.renew(&args).into()),
+            nfs_argop4::OP_RESTOREFH(_) => nfs_resop4::OP_RESTOREFH(self.restorefh().into()),
+            nfs_argop4::OP_SAVEFH(_) => nfs_resop4::OP_SAVEFH(self.savefh().into()),
+            nfs_argop4::OP_SETATTR(args) => nfs_resop4::OP_SETATTR(self.setattr(&args).await),
+            nfs_argop4::OP_SETCLIENTID(args) => {
+                nfs_resop4::OP_SETCLIENTID(Nfs4Result::Ok(self.setclientid(&args)))
+            }
+            nfs_argop4::OP_SETCLIENTID_CONFIRM(args) => {
+                nfs_resop4::OP_SETCLIENTID_CONFIRM(self.setclientid_confirm(&args).into())
+            }
+            nfs_argop4::OP_WRITE(args) => nfs_resop4::OP_WRITE(self.write(&args).await.into()),
+            nfs_argop4::OP_RELEASE_LOCKOWNER(_) => {
+                // locks aren't supported, so the lock owner has no state
+                nfs_resop4::OP_RELEASE_LOCKOWNER(Nfs4Result::Ok(Void))
+            }
+            nfs_argop4::Unsupported(nfs_opnum4::OP_ILLEGAL) => {
+                nfs_resop4::Unsupported(nfs_opnum4::OP_ILLEGAL, nfsstat4::NFS4ERR_OP_ILLEGAL)
+            }
+            nfs_argop4::Unsupported(op) => {
+                warn!("Unimplemented operation {op}");
+                nfs_resop4::Unsupported(op, nfsstat4::NFS4ERR_NOTSUPP)
+            }
+        }
+    }
+
+    /// Returns the current file handle and the id it refers to
+    fn current(&self) -> Result<(nfs_fh3, T::Handle), nfsstat4> {
+        let fh = self.current.clone().ok_or(nfsstat4::NFS4ERR_NOFILEHANDLE)?;
+        let id = self.context.file_handle_converter.fh_from_nfs(&fh)?;
+        Ok((fh, id))
+    }
+
+    fn check_writable(&self) -> Result<(), nfsstat4> {
+        if matches!(self.context.vfs.capabilities(), VFSCapabilities::ReadWrite) {
+            Ok(())
+        } else {
+            warn!("No write capabilities.");
+            Err(nfsstat4::NFS4ERR_ROFS)
+        }
+    }
+
+    /// Returns the change attribute of a directory. The change info of the directory
+    /// operations is not atomic, so it's only a hint for the client.
+    async fn change(&self, dirid: &T::Handle) -> changeid4 {
+        self.context
+            .vfs
+            .getattr(dirid)
+            .await
+            .map_or(0, |attr| attrs::change(&attr))
+    }
+
+    /// Returns the file system attributes if any of them is requested
+    async fn fs_attrs(&self, request: &[u32]) -> FsAttrs {
+        const FS_ATTRS: [u32; 4] = [
+            FATTR4_MAXFILESIZE,
+            FATTR4_MAXREAD,
+            FATTR4_MAXWRITE,
+            FATTR4_TIME_DELTA,
+        ];
+
+        if !attrs::any_set(request, &FS_ATTRS) {
+            return FsAttrs::default();
+        }
+        let vfs = self.context.vfs.as_ref();
+        match self.context.fsinfo.fsinfo(vfs, &vfs.root_dir()).await {
+            Ok(fsinfo) => FsAttrs {
+                maxread: fsinfo.rtmax.into(),
+                maxwrite: fsinfo.wtmax.into(),
+                maxfilesize: fsinfo.maxfilesize,
+                time_delta: fsinfo.time_delta,
+            },
+            Err(stat) => {
+                warn!("cannot get fsinfo of the root directory: {stat}");
+                FsAttrs::default()
+            }
+        }
+    }
+
+    fn access(&self, args: &ACCESS4args) -> Result<ACCESS4resok, nfsstat4> {
+        self.current()?;
+        let supported = args.access
+            & (ACCESS4_READ
+                | ACCESS4_LOOKUP
+                | ACCESS4_MODIFY
+                | ACCESS4_EXTEND
+                | ACCESS4_DELETE
+                | ACCESS4_EXECUTE);
+        let mut access = supported;
+        if !matches!(self.context.vfs.capabilities(), VFSCapabilities::ReadWrite) {
+            access &= ACCESS4_READ | ACCESS4_LOOKUP | ACCESS4_EXECUTE;
+        }
+        Ok(ACCESS4resok { supported, access })
+    }
+
+    fn close(&self, args: &CLOSE4args) -> Result<stateid4, nfsstat4> {
+        self.current()?;
+        self.context.nfs4_state.close(&args.open_stateid)
+    }
+
+    async fn commit(&self) -> Result<COMMIT4resok, nfsstat4> {
+        let (_, id) = self.current()?;
+        self.context.vfs.commit(&id).await?;
+        Ok(COMMIT4resok {
+            writeverf: self.context.file_handle_converter.verf().0,
+        })
+    }
+
+    async fn create(&mut self, args: CREATE4args<'_>) -> Result<CREATE4resok, nfsstat4> {
+        let (dir_fh, dirid) = self.current()?;
+        let name = check_name(&args.objname)?;
+        let sattr = attrs::decode(&args.createattrs)?;
+        self.check_writable()?;
+
+        let before = self.change(&dirid).await;
+        let vfs = &self.context.vfs;
+        let id = match args.objtype {
+            createtype4::NF4DIR => {
+                let (id, _) = vfs.mkdir(&dirid, &name).await?;
+                if args.createattrs.attrmask.iter().any(|&word| word != 0) {
+                    vfs.setattr(&id, sattr).await?;
+                }
+                id
+            }
+            createtype4::NF4LNK(link) => {
+                let (id, _) = vfs.symlink(&dirid, &name, &nfspath3(link), &sattr).await?;
+                id
+            }
+            createtype4::NF4BLK(_)
+            | createtype4::NF4CHR(_)
+            | createtype4::NF4SOCK
+            | createtype4::NF4FIFO => return Err(nfsstat4::NFS4ERR_BADTYPE),
+        };
+        let after = self.change(&dirid).await;
+
+        self.current = Some(
+            self.context
+                .file_handle_converter
+                .fh_to_nfs_child(&dir_fh, &id)?,
+        );
+        Ok(CREATE4resok {
+            cinfo: change_info4 {
+                atomic: false,
+                before,
+                after,
+            },
+            attrset: args.createattrs.attrmask,
+        })
+    }
+
+    async fn getattr(&self, args: &GETATTR4args) -> Result<GETATTR4resok<'static>, nfsstat4> {
+        let (fh, id) = self.current()?;
+        let attr = self.context.vfs.getattr(&id).await?;
+        let fs = self.fs_attrs(&args.attr_request).await;
+        Ok(GETATTR4resok {
+            obj_attributes: attrs::encode(&args.attr_request, &attr, &fh, &fs),
+        })
+    }
+
+    fn getfh(&self) -> Result<GETFH4resok<'static>, nfsstat4> {
+        let (fh, _) = self.current()?;
+        Ok(GETFH4resok { object: fh.data })
+    }
+
+    async fn lookup(&mut self, args: &LOOKUP4args<'_>) -> Result<Void, nfsstat4> {
+        let (dir_fh, dirid) = self.current()?;
+        let name = check_name(&args.objname)?;
+        let id = self.context.vfs.lookup(&dirid, &name).await?;
+        self.current = Some(
+            self.context
+                .file_handle_converter
+                .fh_to_nfs_child(&dir_fh, &id)?,
+        );
+        Ok(Void)
+    }
+
+    async fn lookupp(&mut self) -> Result<Void, nfsstat4> {
+        let (fh, id) = self.current()?;
+        let converter = &self.context.file_handle_converter;
+        if id.as_bytes() == self.context.vfs.root_dir().as_bytes()
+            || converter.is_scope_root(&fh, &id)
+        {
+            return Err(nfsstat4::NFS4ERR_NOENT);
+        }
+        let parent = self
+            .context
+            .vfs
+            .lookup(&id, &b"..".as_slice().into())
+            .await?;
+        self.current = Some(converter.fh_to_nfs_child(&fh, &parent)?);
+        Ok(Void)
+    }
+
+    #[allow(clippy::too_many_lines)]
+    async fn open(&mut self, args: OPEN4args<'_>) -> Result<OPEN4resok, nfsstat4> {
+        let (dir_fh, dirid) = self.current()?;
+        let name = match &args.claim {
+            open_claim4::CLAIM_NULL(name) => check_name(name)?,
+            open_claim4::CLAIM_PREVIOUS(_) => return Err(nfsstat4::NFS4ERR_NO_GRACE),
+            open_claim4::CLAIM_DELEGATE_CUR(_) | open_claim4::CLAIM_DELEGATE_PREV(_) => {
+                return Err(nfsstat4::NFS4ERR_NOTSUPP);
+            }
+        };
+        if args.share_access & !OPEN4_SHARE_ACCESS_BOTH != 0
+            || args.share_access == 0
+            || args.share_deny & !OPEN4_SHARE_DENY_BOTH != 0
+        {
+            return Err(nfsstat4::NFS4ERR_INVAL);
+        }
+        if args.share_access & OPEN4_SHARE_ACCESS_WRITE != 0
+            || matches!(args.openhow, openflag4::OPEN4_CREATE(_))
+        {
+            self.check_writable()?;
+        }
+        // fail early if the client has no valid lease
+        let state = &self.context.nfs4_state;
+        state.renew(args.owner.clientid)?;
+
+        let vfs = &self.context.vfs;
+        let before = self.change(&dirid).await;
+        let existing = match vfs.lookup(&dirid, &name).await {
+            Ok(id) => Some(id),
+            Err(nfs3_types::nfs3::nfsstat3::NFS3ERR_NOENT) => None,
+            Err(stat) => return Err(stat.into()),
+        };
+        let mut attrset = bitmap4::new();
+        let id = match (args.openhow, existing) {
+            (openflag4::OPEN4_NOCREATE, Some(id)) => id,
+            (openflag4::OPEN4_NOCREATE, None) => return Err(nfsstat4::NFS4ERR_NOENT),
+            (openflag4::OPEN4_CREATE(createhow4::GUARDED4(_)), Some(_)) => {
+                return Err(nfsstat4::NFS4ERR_EXIST);
+            }
+            (openflag4::OPEN4_CREATE(createhow4::UNCHECKED4(attrs)), Some(id)) => {
+                // only the size of an existing file is changed
+                let sattr = attrs::decode(&attrs)?;
+                if let set_size3::Some(size) = sattr.size {
+                    check_regular(&vfs.getattr(&id).await?)?;
+                    let sattr = sattr3 {
+                        size: set_size3::Some(size),
+                        ..Default::default()
+                    };
+                    vfs.setattr(&id, sattr).await?;
+                    attrs::set(&mut attrset, FATTR4_SIZE);
+                }
+                id
+            }
+            (
+                openflag4::OPEN4_CREATE(
+                    createhow4::UNCHECKED4(attrs) | createhow4::GUARDED4(attrs),
+                ),
+                None,
+            ) => {
+                let sattr = attrs::decode(&attrs)?;
+                let (id, _) = vfs.create(&dirid, &name, sattr).await?;
+                attrset = attrs.attrmask;
+                id
+            }
+            (openflag4::OPEN4_CREATE(createhow4::EXCLUSIVE4(verf)), _) => {
+                vfs.create_exclusive(&dirid, &name, createverf3(verf))
+                    .await?
+            }
+        };
+        check_regular(&vfs.getattr(&id).await?)?;
+        let after = self.change(&dirid).await;
+
+        let fh = self
+            .context
+            .file_handle_converter
+            .fh_to_nfs_child(&dir_fh, &id)?;
+        let stateid = state.open(
+            args.owner.clientid,
+            &args.owner.owner,
+            &fh.data,
+            args.share_access,
+            args.share_deny,
+        )?;
+        self.current = Some(fh);
+        Ok(OPEN4resok {
+            stateid,
+            cinfo: change_info4 {
+                atomic: false,
+                before,
+                after,
+            },
+            rflags: OPEN4_RESULT_LOCKTYPE_POSIX,
+            attrset,
+            delegation: open_delegation4::OPEN_DELEGATE_NONE,
+        })
+    }
+
+    fn open_confirm(&self, args: &OPEN_CONFIRM4args) -> Result<OPEN_CONFIRM4resok, nfsstat4> {
+        self.current()?;
+        let open_stateid = self.context.nfs4_state.confirm_open(&args.open_stateid)?;
+        Ok(OPEN_CONFIRM4resok { open_stateid })
+    }
+
+    fn putfh(&mut self, args: &PUTFH4args<'_>) -> Result<Void, nfsstat4> {
+        if args.object.len() > NFS4_FHSIZE {
+            return Err(nfsstat4::NFS4ERR_BADHANDLE);
+        }
+        let fh = nfs_fh3 {
+            data: Opaque::owned(args.object.to_vec()),
+        };
+        self.context
+            .file_handle_converter
+            .fh_from_nfs::<T::Handle>(&fh)?;
+        self.current = Some(fh);
+        Ok(Void)
+    }
+
+    fn putrootfh(&mut self) -> Result<Void, nfsstat4> {
+        let root = self.context.vfs.root_dir();
+        self.current = Some(self.context.file_handle_converter.fh_to_nfs(&root)?);
+        Ok(Void)
+    }
+
+    async fn read(&self, args: &READ4args) -> Result<READ4resok<'static>, nfsstat4> {
+        let (fh, id) = self.current()?;
+        self.context
+            .nfs4_state
+            .check(&args.stateid, &fh.data, false)?;
+        check_regular(&self.context.vfs.getattr(&id).await?)?;
+        let limits = self.context.fsinfo.limits(self.context.vfs.as_ref()).await;
+        let count = args.count.min(limits.rtmax);
+        let (data, eof) = self.context.vfs.read(&id, args.offset, count).await?;
+        debug!("read {id:?} at {} --> {} bytes", args.offset, data.len());
+        Ok(READ4resok {
+            eof,
+            data: Opaque::owned(data),
+        })
+    }
+
+    async fn readdir(&self, args: &READDIR4args) -> Result<READDIR4resok<'static>, nfsstat4> {
+        use crate::vfs::ReadDirPlusIterator;
+
+        let (dir_fh, dirid) = self.current()?;
+        let vfs = &self.context.vfs;
+        let verf = vfs.cookieverf(&dirid).await?;
+        let cookie = match args.cookie {
+            0 => 0,
+            1 | 2 => return Err(nfsstat4::NFS4ERR_BAD_COOKIE),
+            cookie => {
+                if verf.0 != [0; 8] && args.cookieverf != verf.0 {
+                    warn!(" -- Dir version mismatch. Received {:?}", args.cookieverf);
+                    return Err(nfsstat4::NFS4ERR_NOT_SAME);
+                }
+                cookie - COOKIE_OFFSET
+            }
+        };
+        let max_bytes = (args.maxcount as usize)
+            .checked_sub(READDIR_REPLY_OVERHEAD)
+            .ok_or(nfsstat4::NFS4ERR_TOOSMALL)?;
+
+        let fs = self.fs_attrs(&args.attr_request).await;
+        let converter = &self.context.file_handle_converter;
+        let mut iter = vfs.readdirplus(&dirid, cookie).await?;
+        let mut entries = BoundedList::new(max_bytes);
+        let eof = loop {
+            let entry = match iter.next().await {
+                NextResult::Ok(entry) => entry,
+                NextResult::Eof => break true,
+                NextResult::Err(stat) => return Err(stat.into()),
+            };
+            if entry.name.as_ref() == b"." || entry.name.as_ref() == b".." {
+                continue;
+            }
+            let cookie = entry
+                .cookie
+                .checked_add(COOKIE_OFFSET)
+                .ok_or(nfsstat4::NFS4ERR_SERVERFAULT)?;
+            let attrs = if args.attr_request.is_empty() {
+                fattr4 {
+                    attrmask: bitmap4::new(),
+                    attr_vals: Opaque::owned(Vec::new()),
+                }
+            } else {
+                let attr = match (entry.name_handle, entry.name_attributes) {
+                    (Some(id), Some(attr)) => Ok((id, attr)),
+                    (Some(id), None) => vfs.getattr(&id).await.map(|attr| (id, attr)),
+                    (None, _) => match vfs.lookup(&dirid, &entry.name).await {
+                        Ok(id) => vfs.getattr(&id).await.map(|attr| (id, attr)),
+                        Err(stat) => Err(stat),
+                    },
+                }
+                .and_then(|(id, attr)| Ok((converter.fh_to_nfs_child(&dir_fh, &id)?, attr)));
+                match attr {
+                    Ok((fh, attr)) => attrs::encode(&args.attr_request, &attr, &fh, &fs),
+                    Err(stat) => attrs::encode_error(&args.attr_request, stat.into())
+                        .ok_or_else(|| nfsstat4::from(stat))?,
+                }
+            };
+            let entry = entry4 {
+                cookie,
+                name: entry.name.0,
+                attrs,
+            };
+            if entries.try_push(entry).is_err() {
+                trace!(" -- insufficient space. truncating");
+                break false;
+            }
+        };
+
+        let entries = entries.into_inner();
+        if entries.0.is_empty() && !eof {
+            return Err(nfsstat4::NFS4ERR_TOOSMALL);
+        }
+        debug!(
+            "readdir {dirid:?}, start at {}, flushing {} entries, complete {eof}",
+            args.cookie,
+            entries.0.len()
+        );
+        Ok(READDIR4resok {
+            cookieverf: verf.0,
+            reply: dirlist4 { entries, eof },
+        })
+    }
+
+    async fn readlink(&self) -> Result<READLINK4resok<'static>, nfsstat4> {
+        let (_, id) = self.current()?;
+        let link = self.context.vfs.readlink(&id).await?;
+        Ok(READLINK4resok {
+            link: Opaque::owned(link.0.to_vec()),
+        })
+    }
+
+    async fn remove(&self, args: &REMOVE4args<'_>) -> Result<REMOVE4resok, nfsstat4> {
+        let (_, dirid) = self.current()?;
+        let name = check_name(&args.target)?;
+        self.check_writable()?;
+        let before = self.change(&dirid).await;
+        self.context.vfs.remove(&dirid, &name).await?;
+        let after = self.change(&dirid).await;
+        Ok(REMOVE4resok {
+            cinfo: change_info4 {
+                atomic: false,
+                before,
+                after,
+            },
+        })
+    }
+
+    /// Renames an entry of the saved directory into the current directory
+    async fn rename(&self, args: &RENAME4args<'_>) -> Result<RENAME4resok, nfsstat4> {
+        let (to_fh, to_dirid) = self.current()?;
+        let from_fh = self.saved.clone().ok_or(nfsstat4::NFS4ERR_NOFILEHANDLE)?;
+        let from_dirid = self.context.file_handle_converter.fh_from_nfs(&from_fh)?;
+        let from_name = check_name(&args.oldname)?;
+        let to_name = check_name(&args.newname)?;
+        if !self
+            .context
+            .file_handle_converter
+            .same_scope(&from_fh, &to_fh)
+        {
+            return Err(nfsstat4::NFS4ERR_XDEV);
+        }
+        self.check_writable()?;
+
+        let source_before = self.change(&from_dirid).await;
+        let target_before = self.change(&to_dirid).await;
+        self.context
+            .vfs
+            .rename(&from_dirid, &from_name, &to_dirid, &to_name)
+            .await?;
+        Ok(RENAME4resok {
+            source_cinfo: change_info4 {
+                atomic: false,
+                before: source_before,
+                after: self.change(&from_dirid).await,
+            },
+            target_cinfo: change_info4 {
+                atomic: false,
+                before: target_before,
+                after: self.change(&to_dirid).await,
+            },
+        })
+    }
+
+    fn renew(&self, args: &RENEW4args) -> Result<Void, nfsstat4> {
+        self.context.nfs4_state.renew(args.clientid)?;
+        Ok(Void)
+    }
+
+    fn restorefh(&mut self) -> Result<Void, nfsstat4> {
+        self.current = Some(self.saved.clone().ok_or(nfsstat4::NFS4ERR_RESTOREFH)?);
+        Ok(Void)
+    }
+
+    fn savefh(&mut self) -> Result<Void, nfsstat4> {
+        self.saved = Some(self.current.clone().ok_or(nfsstat4::NFS4ERR_NOFILEHANDLE)?);
+        Ok(Void)
+    }
+
+    async fn setattr(&self, args: &SETATTR4args<'_>) -> SETATTR4res {
+        let status = match self.try_setattr(args).await {
+            Ok(()) => {
+                return SETATTR4res {
+                    status: nfsstat4::NFS4_OK,
+                    attrsset: args.obj_attributes.attrmask.clone(),
+                };
+            }
+            Err(stat) => stat,
+        };
+        SETATTR4res {
+            status,
+            attrsset: bitmap4::new(),
+        }
+    }
+
+    async fn try_setattr(&self, args: &SETATTR4args<'_>) -> Result<(), nfsstat4> {
+        let (fh, id) = self.current()?;
+        let sattr = attrs::decode(&args.obj_attributes)?;
+        self.check_writable()?;
+        if matches!(sattr.size, set_size3::Some(_)) {
+            self.context
+                .nfs4_state
+                .check(&args.stateid, &fh.data, true)?;
+            check_regular(&self.context.vfs.getattr(&id).await?)?;
+        }
+        self.context.vfs.setattr(&id, sattr).await?;
+        Ok(())
+    }
+
+    fn setclientid(&self, args: &SETCLIENTID4args<'_>) -> SETCLIENTID4resok {
+        let (clientid, setclientid_confirm) = self
+            .context
+            .nfs4_state
+            .set_clientid(&args.client.id, args.client.verifier);
+        SETCLIENTID4resok {
+            clientid,
+            setclientid_confirm,
+        }
+    }
+
+    fn setclientid_confirm(&self, args: &SETCLIENTID_CONFIRM4args) -> Result<Void, nfsstat4> {
+        self.context
+            .nfs4_state
+            .confirm_clientid(args.clientid, args.setclientid_confirm)?;
+        Ok(Void)
+    }
+
+    async fn write(&self, args: &WRITE4args<'_>) -> Result<WRITE4resok, nfsstat4> {
+        let (fh, id) = self.current()?;
+        self.check_writable()?;
+        self.context
+            .nfs4_state
+            .check(&args.stateid, &fh.data, true)?;
+        check_regular(&self.context.vfs.getattr(&id).await?)?;
+        let limits = self.context.fsinfo.limits(self.context.vfs.as_ref()).await;
+        let count = u32::try_from(args.data.len())
+            .ok()
+            .filter(|&count| count <= limits.wtmax)
+            .ok_or(nfsstat4::NFS4ERR_INVAL)?;
+        self.context.vfs.write(&id, args.offset, &args.data).await?;
+        let committed = if !self.context.vfs.buffers_writes() {
+            stable_how4::FILE_SYNC4
+        } else if args.stable == stable_how4::UNSTABLE4 {
+            stable_how4::UNSTABLE4
+        } else {
+            self.context.vfs.commit(&id).await?;
+            stable_how4::FILE_SYNC4
+        };
+        Ok(WRITE4resok {
+            count,
+            committed,
+            writeverf: self.context.file_handle_converter.verf().0,
+        })
+    }
+}
+
+/// Validates a name of a directory entry
+fn check_name<'a>(name: &'a component4<'_>) -> Result<filename3<'a>, nfsstat4> {
+    if name.is_empty() {
+        return Err(nfsstat4::NFS4ERR_INVAL);
+    }
+    if name.len() > attrs::MAXNAME as usize {
+        return Err(nfsstat4::NFS4ERR_NAMETOOLONG);
+    }
+    if std::str::from_utf8(name).is_err() {
+        return Err(nfsstat4::NFS4ERR_INVAL);
+    }
+    if name.as_ref() == b"." || name.as_ref() == b".." || name.contains(&b'/') {
+        return Err(nfsstat4::NFS4ERR_BADNAME);
+    }
+    Ok(filename3(Opaque::borrowed(name)))
+}
+
+/// Checks that READ, WRITE and OPEN are used with a regular file
+const fn check_regular(attr: &fattr3) -> Result<(), nfsstat4> {
+    match attr.type_ {
+        ftype3::NF3REG => Ok(()),
+        ftype3::NF3DIR => Err(nfsstat4::NFS4ERR_ISDIR),
+        ftype3::NF3LNK => Err(nfsstat4::NFS4ERR_SYMLINK),
+        _ => Err(nfsstat4::NFS4ERR_INVAL),
+    }
+}
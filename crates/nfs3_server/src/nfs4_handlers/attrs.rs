@@ -0,0 +1,406 @@
+//! Conversion between the `NFSv4` attributes and the `NFSv3` attributes of the file system
+
+use std::io::Cursor;
+
+use nfs3_types::nfs3::{
+    fattr3, nfs_fh3, nfstime3, sattr3, set_atime, set_gid3, set_mode3, set_mtime, set_size3,
+    set_uid3,
+};
+#[allow(clippy::wildcard_imports)]
+use nfs3_types::nfs4::*;
+use nfs3_types::xdr_codec::{Opaque, Pack, Unpack};
+
+use crate::nfs4_state::LEASE_TIME;
+
+/// Attributes reported by the server, in ascending order
+const SUPPORTED_ATTRS: [u32; 37] = [
+    FATTR4_SUPPORTED_ATTRS,
+    FATTR4_TYPE,
+    FATTR4_FH_EXPIRE_TYPE,
+    FATTR4_CHANGE,
+    FATTR4_SIZE,
+    FATTR4_LINK_SUPPORT,
+    FATTR4_SYMLINK_SUPPORT,
+    FATTR4_NAMED_ATTR,
+    FATTR4_FSID,
+    FATTR4_UNIQUE_HANDLES,
+    FATTR4_LEASE_TIME,
+    FATTR4_RDATTR_ERROR,
+    FATTR4_CANSETTIME,
+    FATTR4_CASE_INSENSITIVE,
+    FATTR4_CASE_PRESERVING,
+    FATTR4_CHOWN_RESTRICTED,
+    FATTR4_FILEHANDLE,
+    FATTR4_FILEID,
+    FATTR4_HOMOGENEOUS,
+    FATTR4_MAXFILESIZE,
+    FATTR4_MAXNAME,
+    FATTR4_MAXREAD,
+    FATTR4_MAXWRITE,
+    FATTR4_MODE,
+    FATTR4_NO_TRUNC,
+    FATTR4_NUMLINKS,
+    FATTR4_OWNER,
+    FATTR4_OWNER_GROUP,
+    FATTR4_RAWDEV,
+    FATTR4_SPACE_USED,
+    FATTR4_TIME_ACCESS,
+    FATTR4_TIME_ACCESS_SET,
+    FATTR4_TIME_DELTA,
+    FATTR4_TIME_METADATA,
+    FATTR4_TIME_MODIFY,
+    FATTR4_TIME_MODIFY_SET,
+    FATTR4_MOUNTED_ON_FILEID,
+];
+
+/// Maximum length of a file name
+pub const MAXNAME: u32 = 255;
+
+/// Attributes of the file system that don't depend on the object
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FsAttrs {
+    pub maxread: u64,
+    pub maxwrite: u64,
+    pub maxfilesize: u64,
+    pub time_delta: nfstime3,
+}
+
+/// Returns true if the attribute is set in the bitmap
+pub fn is_set(bitmap: &[u32], attr: u32) -> bool {
+    bitmap
+        .get((attr / 32) as usize)
+        .is_some_and(|word| word & (1 << (attr % 32)) != 0)
+}
+
+/// Sets the attribute in the bitmap
+pub fn set(bitmap: &mut bitmap4, attr: u32) {
+    let word = (attr / 32) as usize;
+    if bitmap.len() <= word {
+        bitmap.resize(word + 1, 0);
+    }
+    bitmap[word] |= 1 << (attr % 32);
+}
+
+/// Returns true if any of the attributes is set in the bitmap
+pub fn any_set(bitmap: &[u32], attrs: &[u32]) -> bool {
+    attrs.iter().any(|&attr| is_set(bitmap, attr))
+}
+
+fn supported_attrs() -> bitmap4 {
+    let mut bitmap = bitmap4::new();
+    for attr in SUPPORTED_ATTRS {
+        set(&mut bitmap, attr);
+    }
+    bitmap
+}
+
+fn put(buf: &mut Vec<u8>, value: &impl Pack) {
+    value.pack(buf).expect("cannot encode attribute");
+}
+
+const fn time4(time: nfstime3) -> nfstime4 {
+    nfstime4 {
+        seconds: time.seconds as i64,
+        nseconds: time.nseconds,
+    }
+}
+
+fn time3(time: nfstime4) -> Result<nfstime3, nfsstat4> {
+    match u32::try_from(time.seconds) {
+        Ok(seconds) if time.nseconds < 1_000_000_000 => Ok(nfstime3 {
+            seconds,
+            nseconds: time.nseconds,
+        }),
+        _ => Err(nfsstat4::NFS4ERR_INVAL),
+    }
+}
+
+/// Returns the change attribute, which is derived from the change time
+pub fn change(attr: &fattr3) -> changeid4 {
+    (u64::from(attr.ctime.seconds) << 32) | u64::from(attr.ctime.nseconds)
+}
+
+fn owner(id: u32) -> Opaque<'static> {
+    Opaque::owned(id.to_string().into_bytes())
+}
+
+/// Parses a numeric owner or group. Names aren't mapped to ids.
+fn parse_owner(owner: &[u8]) -> Result<u32, nfsstat4> {
+    std::str::from_utf8(owner)
+        .ok()
+        .and_then(|owner| owner.parse().ok())
+        .ok_or(nfsstat4::NFS4ERR_BADOWNER)
+}
+
+/// Encodes the requested attributes of an object. Attributes that aren't supported or
+/// are write-only are skipped.
+pub fn encode(request: &[u32], attr: &fattr3, fh: &nfs_fh3, fs: &FsAttrs) -> fattr4<'static> {
+    let mut attrmask = bitmap4::new();
+    let mut buf = Vec::new();
+    for attr_id in SUPPORTED_ATTRS {
+        if !is_set(request, attr_id) {
+            continue;
+        }
+        match attr_id {
+            FATTR4_SUPPORTED_ATTRS => put(&mut buf, &supported_attrs()),
+            FATTR4_TYPE => put(&mut buf, &(attr.type_ as u32)),
+            FATTR4_FH_EXPIRE_TYPE => put(&mut buf, &FH4_PERSISTENT),
+            FATTR4_CHANGE => put(&mut buf, &change(attr)),
+            FATTR4_SIZE => put(&mut buf, &attr.size),
+            FATTR4_LINK_SUPPORT | FATTR4_NAMED_ATTR | FATTR4_CASE_INSENSITIVE => {
+                put(&mut buf, &false);
+            }
+            FATTR4_SYMLINK_SUPPORT
+            | FATTR4_UNIQUE_HANDLES
+            | FATTR4_CANSETTIME
+            | FATTR4_CASE_PRESERVING
+            | FATTR4_CHOWN_RESTRICTED
+            | FATTR4_HOMOGENEOUS
+            | FATTR4_NO_TRUNC => put(&mut buf, &true),
+            FATTR4_FSID => put(
+                &mut buf,
+                &fsid4 {
+                    major: attr.fsid,
+                    minor: 0,
+                },
+            ),
+            #[allow(clippy::cast_possible_truncation)] // the lease time is 90 seconds
+            FATTR4_LEASE_TIME => put(&mut buf, &(LEASE_TIME.as_secs() as u32)),
+            FATTR4_RDATTR_ERROR => put(&mut buf, &nfsstat4::NFS4_OK),
+            FATTR4_FILEHANDLE => put(&mut buf, &fh.data),
+            FATTR4_FILEID | FATTR4_MOUNTED_ON_FILEID => put(&mut buf, &attr.fileid),
+            FATTR4_MAXFILESIZE => put(&mut buf, &fs.maxfilesize),
+            FATTR4_MAXNAME => put(&mut buf, &MAXNAME),
+            FATTR4_MAXREAD => put(&mut buf, &fs.maxread),
+            FATTR4_MAXWRITE => put(&mut buf, &fs.maxwrite),
+            FATTR4_MODE => put(&mut buf, &attr.mode),
+            FATTR4_NUMLINKS => put(&mut buf, &attr.nlink),
+            FATTR4_OWNER => put(&mut buf, &owner(attr.uid)),
+            FATTR4_OWNER_GROUP => put(&mut buf, &owner(attr.gid)),
+            FATTR4_RAWDEV => put(
+                &mut buf,
+                &specdata4 {
+                    specdata1: attr.rdev.specdata1,
+                    specdata2: attr.rdev.specdata2,
+                },
+            ),
+            FATTR4_SPACE_USED => put(&mut buf, &attr.used),
+            FATTR4_TIME_ACCESS => put(&mut buf, &time4(attr.atime)),
+            FATTR4_TIME_DELTA => put(&mut buf, &time4(fs.time_delta)),
+            FATTR4_TIME_METADATA => put(&mut buf, &time4(attr.ctime)),
+            FATTR4_TIME_MODIFY => put(&mut buf, &time4(attr.mtime)),
+            // write-only
+            _ => continue,
+        }
+        set(&mut attrmask, attr_id);
+    }
+    fattr4 {
+        attrmask,
+        attr_vals: Opaque::owned(buf),
+    }
+}
+
+/// Encodes the error of reading the attributes of a directory entry, if the client
+/// requested it with `FATTR4_RDATTR_ERROR`
+pub fn encode_error(request: &[u32], stat: nfsstat4) -> Option<fattr4<'static>> {
+    if !is_set(request, FATTR4_RDATTR_ERROR) {
+        return None;
+    }
+    let mut attrmask = bitmap4::new();
+    set(&mut attrmask, FATTR4_RDATTR_ERROR);
+    let mut buf = Vec::new();
+    put(&mut buf, &stat);
+    Some(fattr4 {
+        attrmask,
+        attr_vals: Opaque::owned(buf),
+    })
+}
+
+fn unpack<T: Unpack>(cursor: &mut Cursor<&[u8]>) -> Result<T, nfsstat4> {
+    T::unpack(cursor)
+        .map(|(value, _)| value)
+        .map_err(|_| nfsstat4::NFS4ERR_BADXDR)
+}
+
+fn settime(cursor: &mut Cursor<&[u8]>) -> Result<Option<nfstime3>, nfsstat4> {
+    match unpack::<settime4>(cursor)? {
+        settime4::SET_TO_SERVER_TIME4 => Ok(None),
+        settime4::SET_TO_CLIENT_TIME4(time) => time3(time).map(Some),
+    }
+}
+
+/// Decodes the attributes set by SETATTR, OPEN and CREATE
+///
+/// Returns `NFS4ERR_ATTRNOTSUPP` for attributes that aren't supported and `NFS4ERR_INVAL`
+/// for read-only attributes.
+pub fn decode(attrs: &fattr4<'_>) -> Result<sattr3, nfsstat4> {
+    let mut sattr = sattr3::default();
+    let mut cursor = Cursor::new(attrs.attr_vals.as_ref());
+    #[allow(clippy::cast_possible_truncation)] // a bitmap can't have 2^27 words
+    let attr_count = attrs.attrmask.len() as u32 * 32;
+    for attr_id in (0..attr_count).filter(|&attr_id| is_set(&attrs.attrmask, attr_id)) {
+        match attr_id {
+            FATTR4_SIZE => sattr.size = set_size3::Some(unpack(&mut cursor)?),
+            FATTR4_MODE => sattr.mode = set_mode3::Some(unpack(&mut cursor)?),
+            FATTR4_OWNER => {
+                let owner: Opaque<'static> = unpack(&mut cursor)?;
+                sattr.uid = set_uid3::Some(parse_owner(&owner)?);
+            }
+            FATTR4_OWNER_GROUP => {
+                let group: Opaque<'static> = unpack(&mut cursor)?;
+                sattr.gid = set_gid3::Some(parse_owner(&group)?);
+            }
+            FATTR4_TIME_ACCESS_SET => {
+                sattr.atime = settime(&mut cursor)?
+                    .map_or(set_atime::SET_TO_SERVER_TIME, set_atime::SET_TO_CLIENT_TIME);
+            }
+            FATTR4_TIME_MODIFY_SET => {
+                sattr.mtime = settime(&mut cursor)?
+                    .map_or(set_mtime::SET_TO_SERVER_TIME, set_mtime::SET_TO_CLIENT_TIME);
+            }
+            _ if SUPPORTED_ATTRS.contains(&attr_id) => return Err(nfsstat4::NFS4ERR_INVAL),
+            _ => return Err(nfsstat4::NFS4ERR_ATTRNOTSUPP),
+        }
+    }
+    if cursor.position() != attrs.attr_vals.len() as u64 {
+        return Err(nfsstat4::NFS4ERR_BADXDR);
+    }
+    Ok(sattr)
+}
+
+#[cfg(test)]
+mod tests {
+    #![allow(clippy::unwrap_used)]
+
+    use nfs3_types::nfs3::{ftype3, specdata3};
+    use nfs3_types::xdr_codec::Opaque;
+
+    use super::*;
+
+    fn bitmap(attrs: &[u32]) -> bitmap4 {
+        let mut bitmap = bitmap4::new();
+        for &attr in attrs {
+            set(&mut bitmap, attr);
+        }
+        bitmap
+    }
+
+    #[test]
+    fn test_bitmap() {
+        let bitmap = bitmap(&[FATTR4_TYPE, FATTR4_MODE, FATTR4_MOUNTED_ON_FILEID]);
+        assert_eq!(bitmap, vec![1 << 1, (1 << 1) | (1 << 23)]);
+        assert!(is_set(&bitmap, FATTR4_MODE));
+        assert!(!is_set(&bitmap, FATTR4_SIZE));
+        assert!(!is_set(&bitmap, 100));
+        assert_eq!(supported_attrs().len(), 2);
+    }
+
+    #[test]
+    fn test_encode() {
+        let attr = fattr3 {
+            type_: ftype3::NF3DIR,
+            mode: 0o755,
+            nlink: 2,
+            uid: 1000,
+            gid: 1000,
+            size: 4096,
+            used: 4096,
+            rdev: specdata3::default(),
+            fsid: 0,
+            fileid: 1,
+            atime: nfstime3::default(),
+            mtime: nfstime3::default(),
+            ctime: nfstime3::default(),
+        };
+        let fh = nfs_fh3 {
+            data: Opaque::owned(vec![1, 2, 3]),
+        };
+        let fs = FsAttrs {
+            maxread: 1024,
+            maxwrite: 1024,
+            maxfilesize: 1 << 40,
+            time_delta: nfstime3::default(),
+        };
+        let request = bitmap(&[
+            FATTR4_TYPE,
+            FATTR4_SIZE,
+            FATTR4_ACL,
+            FATTR4_FILEHANDLE,
+            FATTR4_MODE,
+            FATTR4_OWNER,
+            FATTR4_TIME_MODIFY_SET,
+        ]);
+        let encoded = encode(&request, &attr, &fh, &fs);
+        assert_eq!(
+            encoded.attrmask,
+            bitmap(&[
+                FATTR4_TYPE,
+                FATTR4_SIZE,
+                FATTR4_FILEHANDLE,
+                FATTR4_MODE,
+                FATTR4_OWNER
+            ])
+        );
+        let mut expected = Vec::new();
+        put(&mut expected, &(nfs_ftype4::NF4DIR as u32));
+        put(&mut expected, &4096u64);
+        put(&mut expected, &fh.data);
+        put(&mut expected, &0o755u32);
+        put(&mut expected, &Opaque::borrowed(b"1000"));
+        assert_eq!(encoded.attr_vals.as_ref(), expected);
+    }
+
+    #[test]
+    fn test_decode() {
+        let mut vals = Vec::new();
+        put(&mut vals, &0u64);
+        put(&mut vals, &0o600u32);
+        put(&mut vals, &Opaque::borrowed(b"42"));
+        put(&mut vals, &settime4::SET_TO_SERVER_TIME4);
+        let attrs = fattr4 {
+            attrmask: bitmap(&[
+                FATTR4_SIZE,
+                FATTR4_MODE,
+                FATTR4_OWNER_GROUP,
+                FATTR4_TIME_MODIFY_SET,
+            ]),
+            attr_vals: Opaque::owned(vals.clone()),
+        };
+        let sattr = decode(&attrs).unwrap();
+        assert!(matches!(sattr.size, set_size3::Some(0)));
+        assert!(matches!(sattr.mode, set_mode3::Some(0o600)));
+        assert!(matches!(sattr.uid, set_uid3::None));
+        assert!(matches!(sattr.gid, set_gid3::Some(42)));
+        assert!(matches!(sattr.mtime, set_mtime::SET_TO_SERVER_TIME));
+
+        let error = |attrmask, vals: &[u8]| {
+            decode(&fattr4 {
+                attrmask,
+                attr_vals: Opaque::owned(vals.to_vec()),
+            })
+            .unwrap_err()
+        };
+        assert_eq!(
+            error(bitmap(&[FATTR4_SIZE]), &vals[..4]),
+            nfsstat4::NFS4ERR_BADXDR
+        );
+        assert_eq!(
+            error(bitmap(&[FATTR4_SIZE]), &vals),
+            nfsstat4::NFS4ERR_BADXDR
+        );
+        assert_eq!(
+            error(bitmap(&[FATTR4_TYPE]), &vals),
+            nfsstat4::NFS4ERR_INVAL
+        );
+        assert_eq!(
+            error(bitmap(&[FATTR4_ACL]), &vals),
+            nfsstat4::NFS4ERR_ATTRNOTSUPP
+        );
+        let mut owner = Vec::new();
+        put(&mut owner, &Opaque::borrowed(b"user@domain"));
+        assert_eq!(
+            error(bitmap(&[FATTR4_OWNER]), &owner),
+            nfsstat4::NFS4ERR_BADOWNER
+        );
+    }
+}
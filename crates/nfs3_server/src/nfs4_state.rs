@@ -0,0 +1,541 @@
+//! The state of `NFSv4` clients: client ids, open files and leases
+//!
+//! Clients register with SETCLIENTID and keep their state alive by renewing the lease.
+//! The state of a client whose lease expired is dropped before OPEN checks for conflicts,
+//! and by a periodic sweep, so it doesn't keep other clients out.
+//! Every open file is identified by a stateid that is checked by READ, WRITE and SETATTR.
+//! The state isn't persisted and there is no grace period, so after a restart clients
+//! receive `NFS4ERR_STALE_CLIENTID` and `NFS4ERR_STALE_STATEID` and establish new state.
+//!
+//! The sequence ids of the open owners aren't checked and delegations aren't granted.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use nfs3_types::nfs4::{
+    NFS4_OTHER_SIZE, OPEN4_SHARE_ACCESS_WRITE, clientid4, nfsstat4, stateid4, verifier4,
+};
+
+/// The time a client has to renew its lease before its state is dropped
+pub const LEASE_TIME: Duration = Duration::from_secs(90);
+
+/// The anonymous stateid, which can be used to access files without opening them
+const ANONYMOUS_STATEID: [u8; NFS4_OTHER_SIZE] = [0; NFS4_OTHER_SIZE];
+/// The READ bypass stateid
+const BYPASS_STATEID: [u8; NFS4_OTHER_SIZE] = [0xff; NFS4_OTHER_SIZE];
+
+#[derive(Debug)]
+struct Client {
+    /// The id chosen by the client, which survives its reboots
+    id: Vec<u8>,
+    verifier: verifier4,
+    confirm: verifier4,
+    confirmed: bool,
+    renewed: Instant,
+}
+
+#[derive(Debug)]
+struct OpenFile {
+    clientid: clientid4,
+    owner: Vec<u8>,
+    file: Vec<u8>,
+    seqid: u32,
+    access: u32,
+    deny: u32,
+}
+
+impl OpenFile {
+    const fn stateid(&self, other: [u8; NFS4_OTHER_SIZE]) -> stateid4 {
+        stateid4 {
+            seqid: self.seqid,
+            other,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct State {
+    next_id: u64,
+    clients: HashMap<clientid4, Client>,
+    opens: HashMap<[u8; NFS4_OTHER_SIZE], OpenFile>,
+}
+
+impl State {
+    const fn next_id(&mut self) -> u64 {
+        self.next_id += 1;
+        self.next_id
+    }
+
+    /// Drops the client and its open files
+    fn remove_client(&mut self, clientid: clientid4) {
+        self.clients.remove(&clientid);
+        self.opens.retain(|_, open| open.clientid != clientid);
+    }
+
+    /// Drops the clients whose lease expired, with their open files
+    fn expire(&mut self, now: Instant) {
+        let expired: Vec<_> = self
+            .clients
+            .iter()
+            .filter(|(_, client)| now.duration_since(client.renewed) > LEASE_TIME)
+            .map(|(&clientid, _)| clientid)
+            .collect();
+        for clientid in expired {
+            tracing::debug!("lease of client {clientid:x} expired");
+            self.remove_client(clientid);
+        }
+    }
+
+    /// Checks that the client is known, confirmed and its lease hasn't expired, and renews
+    /// the lease
+    fn renew(&mut self, clientid: clientid4, now: Instant) -> Result<(), nfsstat4> {
+        let Some(client) = self.clients.get_mut(&clientid) else {
+            return Err(nfsstat4::NFS4ERR_STALE_CLIENTID);
+        };
+        if !client.confirmed {
+            return Err(nfsstat4::NFS4ERR_STALE_CLIENTID);
+        }
+        if now.duration_since(client.renewed) > LEASE_TIME {
+            self.remove_client(clientid);
+            return Err(nfsstat4::NFS4ERR_EXPIRED);
+        }
+        client.renewed = now;
+        Ok(())
+    }
+}
+
+/// Tracks the clients and their open files
+///
+/// Client ids and stateids include the boot time of the server, so ids issued before
+/// a restart are reported as stale.
+#[derive(Debug)]
+pub struct StateManager {
+    boot: u32,
+    state: Mutex<State>,
+}
+
+impl Default for StateManager {
+    fn default() -> Self {
+        #[allow(clippy::cast_possible_truncation)] // only the low bits are used
+        let boot = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs() as u32);
+        Self::new(boot)
+    }
+}
+
+impl StateManager {
+    /// Creates the state manager with the given boot id
+    pub fn new(boot: u32) -> Self {
+        Self {
+            boot,
+            state: Mutex::default(),
+        }
+    }
+
+    fn clientid(&self, id: u64) -> clientid4 {
+        (u64::from(self.boot) << 32) | (id & u64::from(u32::MAX))
+    }
+
+    fn other(&self, id: u64) -> [u8; NFS4_OTHER_SIZE] {
+        let mut other = [0; NFS4_OTHER_SIZE];
+        other[..4].copy_from_slice(&self.boot.to_be_bytes());
+        other[4..].copy_from_slice(&id.to_be_bytes());
+        other
+    }
+
+    fn is_stale(&self, other: &[u8; NFS4_OTHER_SIZE]) -> bool {
+        other[..4] != self.boot.to_be_bytes()
+    }
+
+    /// Registers a client, returning its client id and the verifier for
+    /// `SETCLIENTID_CONFIRM`
+    pub fn set_clientid(&self, id: &[u8], verifier: verifier4) -> (clientid4, verifier4) {
+        let mut state = self.state.lock().expect("lock is poisoned");
+        let confirm = state.next_id().to_be_bytes();
+        // a confirmed client that didn't reboot keeps its client id
+        let existing = state
+            .clients
+            .iter_mut()
+            .find(|(_, client)| client.confirmed && client.id == id && client.verifier == verifier);
+        if let Some((&clientid, client)) = existing {
+            client.confirm = confirm;
+            return (clientid, confirm);
+        }
+
+        // the state of a rebooted client is dropped when the new client id is confirmed
+        let unconfirmed: Vec<_> = state
+            .clients
+            .iter()
+            .filter(|(_, client)| !client.confirmed && client.id == id)
+            .map(|(&clientid, _)| clientid)
+            .collect();
+        for clientid in unconfirmed {
+            state.remove_client(clientid);
+        }
+        let clientid = self.clientid(state.next_id());
+        state.clients.insert(
+            clientid,
+            Client {
+                id: id.to_vec(),
+                verifier,
+                confirm,
+                confirmed: false,
+                renewed: Instant::now(),
+            },
+        );
+        (clientid, confirm)
+    }
+
+    /// Confirms the client id returned by [`Self::set_clientid`]
+    pub fn confirm_clientid(
+        &self,
+        clientid: clientid4,
+        confirm: verifier4,
+    ) -> Result<(), nfsstat4> {
+        let mut state = self.state.lock().expect("lock is poisoned");
+        let client = state
+            .clients
+            .get_mut(&clientid)
+            .filter(|client| client.confirm == confirm)
+            .ok_or(nfsstat4::NFS4ERR_STALE_CLIENTID)?;
+        client.confirmed = true;
+        client.renewed = Instant::now();
+        let id = client.id.clone();
+
+        let previous: Vec<_> = state
+            .clients
+            .iter()
+            .filter(|&(&other, client)| other != clientid && client.id == id)
+            .map(|(&other, _)| other)
+            .collect();
+        for other in previous {
+            state.remove_client(other);
+        }
+        Ok(())
+    }
+
+    /// Renews the lease of the client
+    pub fn renew(&self, clientid: clientid4) -> Result<(), nfsstat4> {
+        let mut state = self.state.lock().expect("lock is poisoned");
+        state.renew(clientid, Instant::now())
+    }
+
+    /// Drops the clients whose lease expired, with their open files
+    pub fn expire_leases(&self) {
+        let mut state = self.state.lock().expect("lock is poisoned");
+        state.expire(Instant::now());
+    }
+
+    /// Opens a file for the open owner, or upgrades its existing open of the file
+    ///
+    /// Returns `NFS4ERR_SHARE_DENIED` if the access conflicts with the deny mode of another
+    /// open, or the other way around.
+    pub fn open(
+        &self,
+        clientid: clientid4,
+        owner: &[u8],
+        file: &[u8],
+        access: u32,
+        deny: u32,
+    ) -> Result<stateid4, nfsstat4> {
+        let mut state = self.state.lock().expect("lock is poisoned");
+        let now = Instant::now();
+        state.renew(clientid, now)?;
+        // the opens of expired clients don't conflict anymore
+        state.expire(now);
+
+        let mut existing = None;
+        for (other, open) in &state.opens {
+            if open.file != file {
+                continue;
+            }
+            if open.clientid == clientid && open.owner == owner {
+                existing = Some(*other);
+            } else if open.deny & access != 0 || open.access & deny != 0 {
+                return Err(nfsstat4::NFS4ERR_SHARE_DENIED);
+            }
+        }
+
+        if let Some(other) = existing {
+            let open = state.opens.get_mut(&other).expect("open exists");
+            open.seqid += 1;
+            open.access |= access;
+            open.deny |= deny;
+            return Ok(open.stateid(other));
+        }
+
+        let other = self.other(state.next_id());
+        let open = OpenFile {
+            clientid,
+            owner: owner.to_vec(),
+            file: file.to_vec(),
+            seqid: 1,
+            access,
+            deny,
+        };
+        let stateid = open.stateid(other);
+        state.opens.insert(other, open);
+        Ok(stateid)
+    }
+
+    /// Confirms an open, returning the new stateid
+    pub fn confirm_open(&self, stateid: &stateid4) -> Result<stateid4, nfsstat4> {
+        let mut state = self.state.lock().expect("lock is poisoned");
+        let open = self.find_open(&mut state, stateid)?;
+        open.seqid += 1;
+        Ok(open.stateid(stateid.other))
+    }
+
+    /// Closes an open file, returning the final stateid
+    pub fn close(&self, stateid: &stateid4) -> Result<stateid4, nfsstat4> {
+        let mut state = self.state.lock().expect("lock is poisoned");
+        let open = self.find_open(&mut state, stateid)?;
+        let closed = stateid4 {
+            seqid: open.seqid + 1,
+            other: stateid.other,
+        };
+        state.opens.remove(&stateid.other);
+        Ok(closed)
+    }
+
+    /// Checks that the stateid permits the access to the file
+    ///
+    /// The special stateids grant access without an open, as if the client used `NFSv3`.
+    pub fn check(&self, stateid: &stateid4, file: &[u8], write: bool) -> Result<(), nfsstat4> {
+        if stateid.other == ANONYMOUS_STATEID || stateid.other == BYPASS_STATEID {
+            return Ok(());
+        }
+        let mut state = self.state.lock().expect("lock is poisoned");
+        let open = self.find_open(&mut state, stateid)?;
+        if open.file != file {
+            return Err(nfsstat4::NFS4ERR_BAD_STATEID);
+        }
+        if write && open.access & OPEN4_SHARE_ACCESS_WRITE == 0 {
+            return Err(nfsstat4::NFS4ERR_OPENMODE);
+        }
+        Ok(())
+    }
+
+    /// Finds the open file of the stateid and renews the lease of its client
+    fn find_open<'a>(
+        &self,
+        state: &'a mut State,
+        stateid: &stateid4,
+    ) -> Result<&'a mut OpenFile, nfsstat4> {
+        if self.is_stale(&stateid.other) {
+            return Err(nfsstat4::NFS4ERR_STALE_STATEID);
+        }
+        let clientid = state
+            .opens
+            .get(&stateid.other)
+            .ok_or(nfsstat4::NFS4ERR_BAD_STATEID)?
+            .clientid;
+        state.renew(clientid, Instant::now()).map_err(|stat| {
+            if stat == nfsstat4::NFS4ERR_STALE_CLIENTID {
+                nfsstat4::NFS4ERR_BAD_STATEID
+            } else {
+                stat
+            }
+        })?;
+        let open = state
+            .opens
+            .get_mut(&stateid.other)
+            .ok_or(nfsstat4::NFS4ERR_BAD_STATEID)?;
+        match stateid.seqid.cmp(&open.seqid) {
+            std::cmp::Ordering::Less => Err(nfsstat4::NFS4ERR_OLD_STATEID),
+            std::cmp::Ordering::Greater => Err(nfsstat4::NFS4ERR_BAD_STATEID),
+            std::cmp::Ordering::Equal => Ok(open),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #![allow(clippy::unwrap_used)]
+
+    use nfs3_types::nfs4::{OPEN4_SHARE_ACCESS_BOTH, OPEN4_SHARE_ACCESS_READ};
+
+    use super::*;
+
+    fn client(manager: &StateManager, id: &[u8]) -> clientid4 {
+        let (clientid, confirm) = manager.set_clientid(id, [1; 8]);
+        assert_eq!(
+            manager.open(clientid, b"owner", b"f", OPEN4_SHARE_ACCESS_READ, 0),
+            Err(nfsstat4::NFS4ERR_STALE_CLIENTID)
+        );
+        manager.confirm_clientid(clientid, confirm).unwrap();
+        clientid
+    }
+
+    #[test]
+    fn test_clientid() {
+        let manager = StateManager::new(1);
+        let (clientid, confirm) = manager.set_clientid(b"client", [1; 8]);
+        assert_eq!(
+            manager.confirm_clientid(clientid, [0; 8]),
+            Err(nfsstat4::NFS4ERR_STALE_CLIENTID)
+        );
+        assert_eq!(
+            manager.renew(clientid),
+            Err(nfsstat4::NFS4ERR_STALE_CLIENTID)
+        );
+        manager.confirm_clientid(clientid, confirm).unwrap();
+        manager.renew(clientid).unwrap();
+
+        // the same incarnation keeps the client id
+        let (same, confirm) = manager.set_clientid(b"client", [1; 8]);
+        assert_eq!(same, clientid);
+        manager.confirm_clientid(same, confirm).unwrap();
+
+        // a rebooted client loses its state
+        let stateid = manager
+            .open(clientid, b"owner", b"f", OPEN4_SHARE_ACCESS_READ, 0)
+            .unwrap();
+        let (rebooted, confirm) = manager.set_clientid(b"client", [2; 8]);
+        assert_ne!(rebooted, clientid);
+        manager.confirm_clientid(rebooted, confirm).unwrap();
+        assert_eq!(
+            manager.renew(clientid),
+            Err(nfsstat4::NFS4ERR_STALE_CLIENTID)
+        );
+        assert_eq!(
+            manager.check(&stateid, b"f", false),
+            Err(nfsstat4::NFS4ERR_BAD_STATEID)
+        );
+
+        // ids of another server instance are stale
+        let other = StateManager::new(2);
+        assert_eq!(
+            other.check(&stateid, b"f", false),
+            Err(nfsstat4::NFS4ERR_STALE_STATEID)
+        );
+    }
+
+    #[test]
+    fn test_open_and_close() {
+        let manager = StateManager::new(1);
+        let alice = client(&manager, b"alice");
+        let bob = client(&manager, b"bob");
+
+        let stateid = manager
+            .open(alice, b"owner", b"f", OPEN4_SHARE_ACCESS_READ, 0)
+            .unwrap();
+        manager.check(&stateid, b"f", false).unwrap();
+        assert_eq!(
+            manager.check(&stateid, b"f", true),
+            Err(nfsstat4::NFS4ERR_OPENMODE)
+        );
+        assert_eq!(
+            manager.check(&stateid, b"g", false),
+            Err(nfsstat4::NFS4ERR_BAD_STATEID)
+        );
+        manager
+            .check(&stateid4::default(), b"g", true)
+            .expect("anonymous stateid");
+
+        // the same owner upgrades the open
+        let upgraded = manager
+            .open(
+                alice,
+                b"owner",
+                b"f",
+                OPEN4_SHARE_ACCESS_BOTH,
+                OPEN4_SHARE_ACCESS_WRITE,
+            )
+            .unwrap();
+        assert_eq!(upgraded.other, stateid.other);
+        assert_eq!(upgraded.seqid, stateid.seqid + 1);
+        assert_eq!(
+            manager.check(&stateid, b"f", false),
+            Err(nfsstat4::NFS4ERR_OLD_STATEID)
+        );
+        manager.check(&upgraded, b"f", true).unwrap();
+
+        // alice denies writes
+        assert_eq!(
+            manager.open(bob, b"owner", b"f", OPEN4_SHARE_ACCESS_BOTH, 0),
+            Err(nfsstat4::NFS4ERR_SHARE_DENIED)
+        );
+        manager
+            .open(bob, b"owner", b"f", OPEN4_SHARE_ACCESS_READ, 0)
+            .unwrap();
+
+        let closed = manager.close(&upgraded).unwrap();
+        assert_eq!(closed.seqid, upgraded.seqid + 1);
+        assert_eq!(
+            manager.check(&upgraded, b"f", false),
+            Err(nfsstat4::NFS4ERR_BAD_STATEID)
+        );
+        manager
+            .open(bob, b"other owner", b"f", OPEN4_SHARE_ACCESS_BOTH, 0)
+            .unwrap();
+    }
+
+    fn expire(manager: &StateManager, clientid: clientid4) {
+        let mut state = manager.state.lock().unwrap();
+        let client = state.clients.get_mut(&clientid).unwrap();
+        client.renewed = Instant::now()
+            .checked_sub(LEASE_TIME + Duration::from_secs(1))
+            .unwrap();
+    }
+
+    #[test]
+    fn test_expired_opens_dont_conflict() {
+        let manager = StateManager::new(1);
+        let alice = client(&manager, b"alice");
+        let bob = client(&manager, b"bob");
+
+        let stateid = manager
+            .open(
+                alice,
+                b"owner",
+                b"f",
+                OPEN4_SHARE_ACCESS_BOTH,
+                OPEN4_SHARE_ACCESS_BOTH,
+            )
+            .unwrap();
+        assert_eq!(
+            manager.open(bob, b"owner", b"f", OPEN4_SHARE_ACCESS_READ, 0),
+            Err(nfsstat4::NFS4ERR_SHARE_DENIED)
+        );
+
+        // alice stops renewing her lease
+        expire(&manager, alice);
+        manager
+            .open(
+                bob,
+                b"owner",
+                b"f",
+                OPEN4_SHARE_ACCESS_BOTH,
+                OPEN4_SHARE_ACCESS_BOTH,
+            )
+            .unwrap();
+        assert_eq!(
+            manager.check(&stateid, b"f", false),
+            Err(nfsstat4::NFS4ERR_BAD_STATEID)
+        );
+        assert_eq!(manager.renew(alice), Err(nfsstat4::NFS4ERR_STALE_CLIENTID));
+    }
+
+    #[test]
+    fn test_expire_leases() {
+        let manager = StateManager::new(1);
+        let alice = client(&manager, b"alice");
+        let bob = client(&manager, b"bob");
+        manager
+            .open(alice, b"owner", b"f", OPEN4_SHARE_ACCESS_READ, 0)
+            .unwrap();
+
+        expire(&manager, alice);
+        manager.expire_leases();
+        {
+            let state = manager.state.lock().unwrap();
+            assert!(!state.clients.contains_key(&alice));
+            assert!(state.clients.contains_key(&bob));
+            assert!(state.opens.is_empty());
+        }
+        manager.renew(bob).unwrap();
+    }
+}
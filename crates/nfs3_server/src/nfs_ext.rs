@@ -1,33 +1,16 @@
-use nfs3_types::nfs3::{cookieverf3, entryplus3, post_op_attr};
+use nfs3_types::nfs3::{cookieverf3, entryplus3};
 use nfs3_types::xdr_codec::{BoundedList, List, Pack};
 
 pub trait CookieVerfExt {
     const NONE_COOKIE_VERF: cookieverf3 = cookieverf3(0u64.to_be_bytes());
-    const SOME_COOKIE_VERF: cookieverf3 = cookieverf3(0xFFCC_FFCC_FFCC_FFCCu64.to_be_bytes());
 
-    fn from_attr(dir_attr: &post_op_attr) -> Self;
     fn is_none(&self) -> bool;
-    fn is_some(&self) -> bool;
 }
 
 impl CookieVerfExt for cookieverf3 {
-    fn from_attr(dir_attr: &post_op_attr) -> Self {
-        if let post_op_attr::Some(attr) = dir_attr {
-            let cvf_version =
-                (u64::from(attr.mtime.seconds) << 32) | u64::from(attr.mtime.nseconds);
-            Self(cvf_version.to_be_bytes())
-        } else {
-            Self::SOME_COOKIE_VERF
-        }
-    }
-
     fn is_none(&self) -> bool {
         self == &Self::NONE_COOKIE_VERF
     }
-
-    fn is_some(&self) -> bool {
-        !self.is_none()
-    }
 }
 pub struct BoundedEntryPlusList {
     entries: BoundedList<entryplus3<'static>>,
@@ -69,7 +52,7 @@ impl BoundedEntryPlusList {
 
 #[cfg(test)]
 mod tests {
-    use nfs3_types::nfs3::{filename3, post_op_fh3};
+    use nfs3_types::nfs3::{filename3, post_op_attr, post_op_fh3};
     use nfs3_types::xdr_codec::Opaque;
 
     use super::*;
@@ -3,16 +3,21 @@
 #[allow(clippy::wildcard_imports)]
 use nfs3_types::nfs3::*;
 use nfs3_types::rpc::accept_stat_data;
-use nfs3_types::xdr_codec::{BoundedList, Opaque, Pack, Unpack, Void};
+use nfs3_types::xdr_codec::{BoundedList, Pack, Unpack, Void};
 use tracing::{debug, error, trace, warn};
 
 use crate::context::RPCContext;
 use crate::nfs_ext::{BoundedEntryPlusList, CookieVerfExt};
-use crate::rpcwire::handle;
 use crate::rpcwire::messages::{HandleResult, IncomingRpcMessage};
-use crate::units::{GIBIBYTE, TEBIBYTE};
+use crate::rpcwire::{handle, handle_packed};
 use crate::vfs::{NextResult, NfsFileSystem, VFSCapabilities};
 
+/// The highest version of the NFS program served
+#[cfg(feature = "nfs4")]
+const HIGHEST_VERSION: u32 = nfs3_types::nfs4::VERSION;
+#[cfg(not(feature = "nfs4"))]
+const HIGHEST_VERSION: u32 = VERSION;
+
 #[allow(clippy::enum_glob_use)]
 pub async fn handle_nfs<T>(
     context: RPCContext<T>,
@@ -30,8 +35,8 @@ where
     if call.vers != VERSION {
         error!("Invalid NFSv3 Version number {} != {VERSION}", call.vers,);
         return message.into_error_reply(accept_stat_data::PROG_MISMATCH {
-            low: VERSION,
-            high: VERSION,
+            low: nfs3_types::nfs2::VERSION,
+            high: HIGHEST_VERSION,
         });
     }
 
@@ -45,7 +50,7 @@ where
         NFSPROC3_NULL => handle(context, message, nfsproc3_null).await,
         NFSPROC3_GETATTR => handle(context, message, nfsproc3_getattr).await,
         NFSPROC3_LOOKUP => handle(context, message, nfsproc3_lookup).await,
-        NFSPROC3_READ => handle(context, message, nfsproc3_read).await,
+        NFSPROC3_READ => handle_packed(context, message, nfsproc3_read).await,
         NFSPROC3_FSINFO => handle(context, message, nfsproc3_fsinfo).await,
         NFSPROC3_ACCESS => handle(context, message, nfsproc3_access).await,
         NFSPROC3_PATHCONF => handle(context, message, nfsproc3_pathconf).await,
@@ -60,6 +65,9 @@ where
         NFSPROC3_MKDIR => handle(context, message, nfsproc3_mkdir).await,
         NFSPROC3_SYMLINK => handle(context, message, nfsproc3_symlink).await,
         NFSPROC3_READLINK => handle(context, message, nfsproc3_readlink).await,
+        NFSPROC3_COMMIT if matches!(context.vfs.capabilities(), VFSCapabilities::ReadWrite) => {
+            handle(context, message, nfsproc3_commit).await
+        }
         NFSPROC3_MKNOD | NFSPROC3_LINK | NFSPROC3_COMMIT => {
             warn!("Unimplemented message {proc}");
             message.into_error_reply(accept_stat_data::PROC_UNAVAIL)
@@ -120,12 +128,39 @@ where
     let dirops = lookup3args.what;
     let dirid = fh_to_id!(context, &dirops.dir);
     let dir_attributes = nfs_option_from_result(context.vfs.getattr(&dirid).await);
-    match context.vfs.lookup(&dirid, &dirops.name).await {
+    if let Err(stat) = check_name(&dirops.name) {
+        warn!("lookup error {xid}({:?}) --> {stat}", dirops.name);
+        return LOOKUP3res::Err((stat, LOOKUP3resfail { dir_attributes }));
+    }
+    if dirops.name.as_ref() == b".."
+        && context
+            .file_handle_converter
+            .is_scope_root(&dirops.dir, &dirid)
+    {
+        warn!("lookup error {xid} --> refusing to leave the mounted subtree");
+        return LOOKUP3res::Err((nfsstat3::NFS3ERR_ACCES, LOOKUP3resfail { dir_attributes }));
+    }
+    // "." is answered here, so the backend can't hand out another id for the directory
+    let fid = if dirops.name.as_ref() == b"." {
+        Ok(dirid.clone())
+    } else {
+        context.vfs.lookup(&dirid, &dirops.name).await
+    };
+    match fid {
         Ok(fid) => {
+            let object = match context
+                .file_handle_converter
+                .fh_to_nfs_child(&dirops.dir, &fid)
+            {
+                Ok(object) => object,
+                Err(stat) => {
+                    return LOOKUP3res::Err((stat, LOOKUP3resfail { dir_attributes }));
+                }
+            };
             let obj_attributes = nfs_option_from_result(context.vfs.getattr(&fid).await);
             debug!("lookup success {} --> {:?}", xid, obj_attributes);
             LOOKUP3res::Ok(LOOKUP3resok {
-                object: context.file_handle_converter.fh_to_nfs(&fid),
+                object,
                 obj_attributes,
                 dir_attributes,
             })
@@ -137,34 +172,69 @@ where
     }
 }
 
+/// READ packs the data straight into the reply buffer, so it doesn't go through [`READ3res`]
+/// on success. The layout of the reply is the same as of [`READ3resok`].
 async fn nfsproc3_read<T>(
     context: RPCContext<T>,
-    xid: u32,
+    message: IncomingRpcMessage,
     read3args: READ3args,
-) -> READ3res<'static>
+) -> anyhow::Result<HandleResult>
 where
     T: NfsFileSystem,
 {
-    let handle = read3args.file;
-    let id = fh_to_id!(context, &handle);
+    let xid = message.xid();
+    let id = match context.file_handle_converter.fh_from_nfs(&read3args.file) {
+        Ok(id) => id,
+        Err(stat) => {
+            warn!("cannot resolve fh: {stat}");
+            return message.into_success_reply(&READ3res::Err((stat, READ3resfail::default())));
+        }
+    };
     let file_attributes = nfs_option_from_result(context.vfs.getattr(&id).await);
+    let limits = context.fsinfo.limits(context.vfs.as_ref()).await;
+    if read3args.count > limits.rtmax {
+        error!(
+            "read error {xid} --> count {} exceeds rtmax {}",
+            read3args.count, limits.rtmax
+        );
+        let res = READ3res::Err((nfsstat3::NFS3ERR_INVAL, READ3resfail { file_attributes }));
+        return message.into_success_reply(&res);
+    }
+
+    // status, attributes, count, eof and the length of the opaque data
+    let header_size = 4 + file_attributes.packed_size() + 4 + 4 + 4;
+    let count = read3args.count as usize;
+    let mut buf = message.success_reply_buffer(header_size + count + 3)?;
+    let header_start = buf.len();
+    let data_start = header_start + header_size;
+    buf.resize(data_start + count, 0);
+
     match context
         .vfs
-        .read(&id, read3args.offset, read3args.count)
+        .read_into(&id, read3args.offset, &mut buf[data_start..])
         .await
     {
-        Ok((bytes, eof)) => {
-            debug!(" {xid} --> read {} bytes, eof: {eof}", bytes.len());
-            READ3res::Ok(READ3resok {
-                file_attributes,
-                count: u32::try_from(bytes.len()).expect("buffer is too big"),
-                eof,
-                data: Opaque::owned(bytes),
-            })
+        Ok((len, eof)) => {
+            debug!(" {xid} --> read {len} bytes, eof: {eof}");
+            let len = len.min(count);
+            buf.truncate(data_start + len);
+            let len = u32::try_from(len).expect("buffer is too big");
+
+            let mut header = &mut buf[header_start..data_start];
+            nfsstat3::NFS3_OK.pack(&mut header)?;
+            file_attributes.pack(&mut header)?;
+            len.pack(&mut header)?;
+            eof.pack(&mut header)?;
+            len.pack(&mut header)?;
+            debug_assert!(header.is_empty());
+
+            let padding = (4 - len as usize % 4) % 4;
+            buf.extend_from_slice(&[0; 3][..padding]);
+            message.into_packed_reply(buf)
         }
         Err(stat) => {
             error!("read error {} --> {stat}", xid);
-            READ3res::Err((stat, READ3resfail { file_attributes }))
+            message.into_success_reply(&READ3res::Err((stat, READ3resfail { file_attributes })))
         }
     }
 }
@@ -175,7 +245,7 @@ where
 {
     let handle = args.fsroot;
     let id = fh_to_id!(context, &handle);
-    match context.vfs.fsinfo(&id).await {
+    match context.fsinfo.fsinfo(context.vfs.as_ref(), &id).await {
         Ok(fsinfo) => {
             debug!("fsinfo success {xid} --> {fsinfo:?}");
             FSINFO3res::Ok(fsinfo)
@@ -220,20 +290,17 @@ where
     let handle = args.object;
     debug!("nfsproc3_pathconf({xid}, {handle:?})");
     let id = fh_to_id!(context, &handle);
-    let obj_attr = nfs_option_from_result(context.vfs.getattr(&id).await);
-
-    let res = PATHCONF3resok {
-        obj_attributes: obj_attr,
-        linkmax: 0,
-        name_max: 32768,
-        no_trunc: true,
-        chown_restricted: true,
-        case_insensitive: false,
-        case_preserving: true,
-    };
-
-    debug!("pathconf success {xid} --> {res:?}");
-    PATHCONF3res::Ok(res)
+    match context.vfs.pathconf(&id).await {
+        Ok(res) => {
+            debug!("pathconf success {xid} --> {res:?}");
+            PATHCONF3res::Ok(res)
+        }
+        Err(stat) => {
+            warn!("pathconf error {xid} --> {stat}");
+            let obj_attributes = nfs_option_from_result(context.vfs.getattr(&id).await);
+            PATHCONF3res::Err((stat, PATHCONF3resfail { obj_attributes }))
+        }
+    }
 }
 
 async fn nfsproc3_fsstat<T>(context: RPCContext<T>, xid: u32, args: FSSTAT3args) -> FSSTAT3res
@@ -242,20 +309,17 @@ where
 {
     let handle = args.fsroot;
     let id = fh_to_id!(context, &handle);
-    let obj_attr = nfs_option_from_result(context.vfs.getattr(&id).await);
-    let fsstat = FSSTAT3resok {
-        obj_attributes: obj_attr,
-        tbytes: TEBIBYTE,
-        fbytes: TEBIBYTE,
-        abytes: TEBIBYTE,
-        tfiles: GIBIBYTE,
-        ffiles: GIBIBYTE,
-        afiles: GIBIBYTE,
-        invarsec: u32::MAX,
-    };
-
-    debug!("fsstat success {xid} --> {fsstat:?}");
-    FSSTAT3res::Ok(fsstat)
+    match context.vfs.fsstat(&id).await {
+        Ok(fsstat) => {
+            debug!("fsstat success {xid} --> {fsstat:?}");
+            FSSTAT3res::Ok(fsstat)
+        }
+        Err(stat) => {
+            warn!("fsstat error {xid} --> {stat}");
+            let obj_attributes = nfs_option_from_result(context.vfs.getattr(&id).await);
+            FSSTAT3res::Err((stat, FSSTAT3resfail { obj_attributes }))
+        }
+    }
 }
 
 async fn nfsproc3_readdirplus<T>(
@@ -273,71 +337,18 @@ where
 
     let dir_attributes = dir_attr_maybe.map_or(post_op_attr::None, post_op_attr::Some);
 
-    let dirversion = cookieverf3::from_attr(&dir_attributes);
     debug!(" -- Dir attr {dir_attributes:?}");
+    let dirversion = match context.vfs.cookieverf(&dirid).await {
+        Ok(verf) => verf,
+        Err(stat) => {
+            error!("readdirplus error {xid} --> {stat}");
+            return READDIRPLUS3res::Err((stat, READDIRPLUS3resfail { dir_attributes }));
+        }
+    };
     debug!(" -- Dir version {dirversion:?}");
-    let has_version = args.cookieverf.is_some();
-    // initial call should have empty cookie verf
-    // subsequent calls should have cvf_version as defined above
-    // which is based off the mtime.
-    //
-    // TODO: This is *far* too aggressive. and unnecessary.
-    // The client should maintain this correctly typically.
-    //
-    // The way cookieverf is handled is quite interesting...
-    //
-    // There are 2 notes in the RFC of interest:
-    // 1. If the
-    // server detects that the cookie is no longer valid, the
-    // server will reject the READDIR request with the status,
-    // NFS3ERR_BAD_COOKIE. The client should be careful to
-    // avoid holding directory entry cookies across operations
-    // that modify the directory contents, such as REMOVE and
-    // CREATE.
-    //
-    // 2. One implementation of the cookie-verifier mechanism might
-    //  be for the server to use the modification time of the
-    //  directory. This might be overly restrictive, however. A
-    //  better approach would be to record the time of the last
-    //  directory modification that changed the directory
-    //  organization in a way that would make it impossible to
-    //  reliably interpret a cookie. Servers in which directory
-    //  cookies are always valid are free to use zero as the
-    //  verifier always.
-    //
-    //  Basically, as long as the cookie is "kinda" intepretable,
-    //  we should keep accepting it.
-    //  On testing, the Mac NFS client pretty much expects that
-    //  especially on highly concurrent modifications to the directory.
-    //
-    //  1. If part way through a directory enumeration we fail with BAD_COOKIE
-    //  if the directory contents change, the client listing may fail resulting
-    //  in a "no such file or directory" error.
-    //  2. if we cache readdir results. i.e. we think of a readdir as two parts a. enumerating
-    //     everything first b. the cookie is then used to paginate the enumeration we can run into
-    //     file time synchronization issues. i.e. while one listing occurs and another file is
-    //     touched, the listing may report an outdated file status.
-    //
-    //     This cache also appears to have to be *quite* long lasting
-    //     as the client may hold on to a directory enumerator
-    //     with unbounded time.
-    //
-    //  Basically, if we think about how linux directory listing works
-    //  is that you just get an enumerator. There is no mechanic available for
-    //  "restarting" a pagination and this enumerator is assumed to be valid
-    //  even across directory modifications and should reflect changes
-    //  immediately.
-    //
-    //  The best solution is simply to really completely avoid sending
-    //  BAD_COOKIE all together and to ignore the cookie mechanism.
-    //
-    // if args.cookieverf != cookieverf3::default() && args.cookieverf != dirversion {
-    // info!(" -- Dir version mismatch. Received {:?}", args.cookieverf);
-    // make_success_reply(xid).pack(output)?;
-    // nfsstat3::NFS3ERR_BAD_COOKIE.pack(output)?;
-    // dir_attr.pack(output)?;
-    // return Ok(());
-    // }
+    if let Err(stat) = check_cookieverf(args.cookie, args.cookieverf, dirversion) {
+        return READDIRPLUS3res::Err((stat, READDIRPLUS3resfail { dir_attributes }));
+    }
 
     // subtract off the final entryplus* field (which must be false) and the eof
     if args.maxcount < 128 {
@@ -357,6 +368,10 @@ where
 
     let mut iter = iter.unwrap();
     let eof;
+    // the parent of a mount root is outside of the mounted subtree
+    let hide_parent = context
+        .file_handle_converter
+        .is_scope_root(&args.dir, &dirid);
 
     // this is a wrapper around a writer that also just counts the number of bytes
     // written
@@ -364,7 +379,12 @@ where
     loop {
         match iter.next().await {
             NextResult::Ok(dir_entry_plus) => {
-                let entry = dir_entry_plus.into_entry(&context.file_handle_converter);
+                let mut entry =
+                    dir_entry_plus.into_entry(&context.file_handle_converter, &args.dir);
+                if hide_parent && entry.name.as_ref() == b".." {
+                    entry.name_attributes = post_op_attr::None;
+                    entry.name_handle = post_op_fh3::None;
+                }
                 let result = entries_result.try_push(entry);
                 if result.is_err() {
                     trace!(" -- insufficient space. truncating");
@@ -392,8 +412,7 @@ where
 
     debug!("  -- readdirplus eof {eof}");
     debug!(
-        "readdirplus {dirid:?}, has_version {has_version}, start at {}, flushing {} entries, \
-         complete {eof}",
+        "readdirplus {dirid:?}, start at {}, flushing {} entries, complete {eof}",
         args.cookie,
         entries.0.len()
     );
@@ -419,25 +438,15 @@ where
     let dirid = fh_to_id!(context, &readdir3args.dir);
     let dir_attr_maybe = context.vfs.getattr(&dirid).await;
     let dir_attributes = dir_attr_maybe.map_or(post_op_attr::None, post_op_attr::Some);
-    let cookieverf = cookieverf3::from_attr(&dir_attributes);
-
-    if readdir3args.cookieverf.is_none() {
-        if readdir3args.cookie != 0 {
-            warn!(
-                " -- Invalid cookie. Expected 0, got {}",
-                readdir3args.cookie
-            );
-            return READDIR3res::Err((nfsstat3::NFS3ERR_BAD_COOKIE, READDIR3resfail::default()));
+    let cookieverf = match context.vfs.cookieverf(&dirid).await {
+        Ok(verf) => verf,
+        Err(stat) => {
+            error!("readdir error {xid} --> {stat}");
+            return READDIR3res::Err((stat, READDIR3resfail { dir_attributes }));
         }
-        debug!(" -- Start of readdir");
-    } else if readdir3args.cookieverf != cookieverf {
-        warn!(
-            " -- Dir version mismatch. Received {:?}, Expected: {cookieverf:?}",
-            readdir3args.cookieverf,
-        );
-        return READDIR3res::Err((nfsstat3::NFS3ERR_BAD_COOKIE, READDIR3resfail::default()));
-    } else {
-        debug!(" -- Resuming readdir. Cookie {}", readdir3args.cookie);
+    };
+    if let Err(stat) = check_cookieverf(readdir3args.cookie, readdir3args.cookieverf, cookieverf) {
+        return READDIR3res::Err((stat, READDIR3resfail { dir_attributes }));
     }
 
     debug!(" -- Dir attr {dir_attributes:?}");
@@ -517,6 +526,38 @@ where
     Nfs3Result::Ok(resok)
 }
 
+/// Checks the cookie verifier sent by the client against the one provided by the backend.
+///
+/// A listing that starts from the beginning is always accepted. A zero verifier from the
+/// backend means that its cookies are always valid, so the client's verifier is ignored.
+fn check_cookieverf(
+    cookie: cookie3,
+    received: cookieverf3,
+    current: cookieverf3,
+) -> Result<(), nfsstat3> {
+    if cookie == 0 {
+        debug!(" -- Start of readdir");
+        return Ok(());
+    }
+    if current.is_none() || received == current {
+        debug!(" -- Resuming readdir. Cookie {cookie}");
+        return Ok(());
+    }
+    warn!(" -- Dir version mismatch. Received {received:?}, Expected: {current:?}");
+    Err(nfsstat3::NFS3ERR_BAD_COOKIE)
+}
+
+/// Validates a name of a directory entry.
+///
+/// A name is a single path component, so an empty name or one with a '/' would let the
+/// client reach outside of the directory it names.
+fn check_name(name: &filename3<'_>) -> Result<(), nfsstat3> {
+    if name.as_ref().is_empty() || name.as_ref().contains(&b'/') {
+        return Err(nfsstat3::NFS3ERR_INVAL);
+    }
+    Ok(())
+}
+
 async fn nfsproc3_write<T>(
     context: RPCContext<T>,
     xid: u32,
@@ -539,17 +580,37 @@ where
         return WRITE3res::Err((nfsstat3::NFS3ERR_INVAL, WRITE3resfail::default()));
     }
 
+    let limits = context.fsinfo.limits(context.vfs.as_ref()).await;
+    if write3args.count > limits.wtmax {
+        error!(
+            "write error {xid} --> count {} exceeds wtmax {}",
+            write3args.count, limits.wtmax
+        );
+        return WRITE3res::Err((nfsstat3::NFS3ERR_INVAL, WRITE3resfail::default()));
+    }
+
     let id = fh_to_id!(context, &write3args.file);
     let before = get_wcc_attr(&context, &id)
         .await
         .map_or(pre_op_attr::None, pre_op_attr::Some);
 
-    match context
+    let buffered = context.vfs.buffers_writes();
+    let result = match context
         .vfs
         .write(&id, write3args.offset, &write3args.data)
         .await
     {
-        Ok(fattr) => {
+        Ok(_) if buffered && write3args.stable != stable_how::UNSTABLE => context
+            .vfs
+            .commit(&id)
+            .await
+            .map(|fattr| (fattr, stable_how::FILE_SYNC)),
+        Ok(fattr) if buffered => Ok((fattr, stable_how::UNSTABLE)),
+        Ok(fattr) => Ok((fattr, stable_how::FILE_SYNC)),
+        Err(stat) => Err(stat),
+    };
+    match result {
+        Ok((fattr, committed)) => {
             debug!("write success {xid} --> {fattr:?}");
             WRITE3res::Ok(WRITE3resok {
                 file_wcc: wcc_data {
@@ -557,7 +618,7 @@ where
                     after: post_op_attr::Some(fattr),
                 },
                 count: write3args.count,
-                committed: stable_how::FILE_SYNC,
+                committed,
                 verf: context.file_handle_converter.verf(),
             })
         }
@@ -576,6 +637,41 @@ where
     }
 }
 
+async fn nfsproc3_commit<T>(context: RPCContext<T>, xid: u32, args: COMMIT3args) -> COMMIT3res
+where
+    T: NfsFileSystem,
+{
+    let id = fh_to_id!(context, &args.file);
+    let before = get_wcc_attr(&context, &id)
+        .await
+        .map_or(pre_op_attr::None, pre_op_attr::Some);
+
+    match context.vfs.commit(&id).await {
+        Ok(fattr) => {
+            debug!("commit success {xid} --> {fattr:?}");
+            COMMIT3res::Ok(COMMIT3resok {
+                file_wcc: wcc_data {
+                    before,
+                    after: post_op_attr::Some(fattr),
+                },
+                verf: context.file_handle_converter.verf(),
+            })
+        }
+        Err(stat) => {
+            error!("commit error {xid} --> {stat}");
+            COMMIT3res::Err((
+                stat,
+                COMMIT3resfail {
+                    file_wcc: wcc_data {
+                        before,
+                        after: post_op_attr::None,
+                    },
+                },
+            ))
+        }
+    }
+}
+
 #[allow(clippy::collapsible_if, clippy::too_many_lines)]
 async fn nfsproc3_create<T>(context: RPCContext<T>, xid: u32, args: CREATE3args<'_>) -> CREATE3res
 where
@@ -588,6 +684,10 @@ where
 
     let dirops = args.where_;
     let createhow = args.how;
+    if let Err(stat) = check_name(&dirops.name) {
+        warn!("create error {xid}({:?}) --> {stat}", dirops.name);
+        return CREATE3res::Err((stat, CREATE3resfail::default()));
+    }
 
     debug!("nfsproc3_create({xid}, {dirops:?}, {createhow:?})");
     let dirid = fh_to_id!(context, &dirops.dir);
@@ -639,7 +739,11 @@ where
         Ok(fid) => {
             debug!("create success {xid} --> {fid:?}, {postopattr:?}");
             CREATE3res::Ok(CREATE3resok {
-                obj: post_op_fh3::Some(context.file_handle_converter.fh_to_nfs(&fid)),
+                obj: nfs_option_from_result(
+                    context
+                        .file_handle_converter
+                        .fh_to_nfs_child(&dirops.dir, &fid),
+                ),
                 obj_attributes: postopattr,
                 dir_wcc,
             })
@@ -721,6 +825,10 @@ where
         warn!("No write capabilities.");
         return REMOVE3res::Err((nfsstat3::NFS3ERR_ROFS, REMOVE3resfail::default()));
     }
+    if let Err(stat) = check_name(&args.object.name) {
+        warn!("remove error {xid}({:?}) --> {stat}", args.object.name);
+        return REMOVE3res::Err((stat, REMOVE3resfail::default()));
+    }
 
     let dirid = fh_to_id!(context, &args.object.dir);
     let before = match get_wcc_attr(&context, &dirid).await {
@@ -764,9 +872,23 @@ where
         warn!("No write capabilities.");
         return RENAME3res::Err((nfsstat3::NFS3ERR_ROFS, RENAME3resfail::default()));
     }
+    if let Err(stat) = check_name(&args.from.name).and_then(|()| check_name(&args.to.name)) {
+        warn!(
+            "rename error {xid}({:?} -> {:?}) --> {stat}",
+            args.from.name, args.to.name
+        );
+        return RENAME3res::Err((stat, RENAME3resfail::default()));
+    }
 
     let from_dirid = fh_to_id!(context, &args.from.dir);
     let to_dirid = fh_to_id!(context, &args.to.dir);
+    if !context
+        .file_handle_converter
+        .same_scope(&args.from.dir, &args.to.dir)
+    {
+        warn!("rename error {xid} --> directories belong to different mounts");
+        return RENAME3res::Err((nfsstat3::NFS3ERR_XDEV, RENAME3resfail::default()));
+    }
     let pre_from_dir_attr = match get_wcc_attr(&context, &from_dirid).await {
         Ok(v) => pre_op_attr::Some(v),
         Err(stat) => {
@@ -827,6 +949,10 @@ where
         warn!("No write capabilities.");
         return MKDIR3res::Err((nfsstat3::NFS3ERR_ROFS, MKDIR3resfail::default()));
     }
+    if let Err(stat) = check_name(&args.where_.name) {
+        warn!("mkdir error {xid}({:?}) --> {stat}", args.where_.name);
+        return MKDIR3res::Err((stat, MKDIR3resfail::default()));
+    }
 
     let dirid = fh_to_id!(context, &args.where_.dir);
 
@@ -846,7 +972,11 @@ where
         Ok((fid, fattr)) => {
             debug!("mkdir success {xid} --> {fid:?}, {fattr:?}");
             MKDIR3res::Ok(MKDIR3resok {
-                obj: post_op_fh3::Some(context.file_handle_converter.fh_to_nfs(&fid)),
+                obj: nfs_option_from_result(
+                    context
+                        .file_handle_converter
+                        .fh_to_nfs_child(&args.where_.dir, &fid),
+                ),
                 obj_attributes: post_op_attr::Some(fattr),
                 dir_wcc,
             })
@@ -870,6 +1000,10 @@ where
         warn!("No write capabilities.");
         return SYMLINK3res::Err((nfsstat3::NFS3ERR_ROFS, SYMLINK3resfail::default()));
     }
+    if let Err(stat) = check_name(&args.where_.name) {
+        warn!("symlink error {xid}({:?}) --> {stat}", args.where_.name);
+        return SYMLINK3res::Err((stat, SYMLINK3resfail::default()));
+    }
 
     let dirid = fh_to_id!(context, &args.where_.dir);
 
@@ -894,7 +1028,11 @@ where
         Ok((fid, fattr)) => {
             debug!("symlink success {xid} --> {fid:?}, {fattr:?}");
             SYMLINK3res::Ok(SYMLINK3resok {
-                obj: post_op_fh3::Some(context.file_handle_converter.fh_to_nfs(&fid)),
+                obj: nfs_option_from_result(
+                    context
+                        .file_handle_converter
+                        .fh_to_nfs_child(&args.where_.dir, &fid),
+                ),
                 obj_attributes: post_op_attr::Some(fattr),
                 dir_wcc: wcc_data {
                     before: pre_dir_attr,
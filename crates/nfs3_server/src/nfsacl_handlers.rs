@@ -0,0 +1,359 @@
+use nfs3_types::nfs3::{Nfs3Option, Nfs3Result, fattr3, ftype3, nfsstat3};
+#[allow(clippy::wildcard_imports)]
+use nfs3_types::nfsacl::*;
+use nfs3_types::rpc::accept_stat_data;
+use nfs3_types::xdr_codec::Void;
+use tracing::{debug, error, warn};
+
+use crate::context::RPCContext;
+use crate::rpcwire::handle;
+use crate::rpcwire::messages::{HandleResult, IncomingRpcMessage};
+use crate::vfs::{NfsFileSystem, PosixAcl, VFSCapabilities};
+
+#[allow(clippy::enum_glob_use)]
+pub async fn handle_nfsacl<T>(
+    context: RPCContext<T>,
+    message: IncomingRpcMessage,
+) -> anyhow::Result<HandleResult>
+where
+    T: NfsFileSystem,
+{
+    use NFSACL_PROGRAM::*;
+
+    let call = message.body();
+    let xid = message.xid();
+
+    debug!("handle_nfsacl({xid}, {call:?}");
+    if call.vers != VERSION {
+        warn!("Invalid NFSACL Version number {} != {VERSION}", call.vers);
+        return message.into_error_reply(accept_stat_data::PROG_MISMATCH {
+            low: VERSION,
+            high: VERSION,
+        });
+    }
+
+    let Ok(proc) = NFSACL_PROGRAM::try_from(call.proc) else {
+        error!("invalid NFSACL Program number {}", call.proc);
+        return message.into_error_reply(accept_stat_data::PROC_UNAVAIL);
+    };
+
+    debug!("{proc}({})", message.xid());
+    match proc {
+        ACLPROC3_NULL => handle(context, message, aclproc3_null).await,
+        ACLPROC3_GETACL => handle(context, message, aclproc3_getacl).await,
+        ACLPROC3_SETACL => handle(context, message, aclproc3_setacl).await,
+    }
+}
+
+macro_rules! fh_to_id {
+    ($context:expr, $fh:expr) => {
+        match $context.file_handle_converter.fh_from_nfs($fh) {
+            Ok(id) => id,
+            Err(stat) => {
+                warn!("cannot resolve fh: {stat}");
+                return Nfs3Result::Err((stat, Default::default()));
+            }
+        }
+    };
+}
+
+async fn aclproc3_null<T>(_: RPCContext<T>, _: u32, _: Void) -> Void
+where
+    T: NfsFileSystem,
+{
+    Void
+}
+
+async fn aclproc3_getacl<T>(context: RPCContext<T>, xid: u32, args: GETACL3args) -> GETACL3res
+where
+    T: NfsFileSystem,
+{
+    let id = fh_to_id!(context, &args.fh);
+    let attr = match context.vfs.getattr(&id).await {
+        Ok(attr) => attr,
+        Err(stat) => {
+            warn!("getacl error {xid} --> {stat}");
+            return GETACL3res::Err((stat, GETACL3resfail::default()));
+        }
+    };
+    if args.mask & !NFS_ACL_MASK != 0 {
+        warn!("getacl error {xid} --> invalid mask {:#x}", args.mask);
+        return GETACL3res::Err((
+            nfsstat3::NFS3ERR_INVAL,
+            GETACL3resfail {
+                attr: Nfs3Option::Some(attr),
+            },
+        ));
+    }
+
+    let acl = match context.vfs.getacl(&id).await {
+        Ok(acl) => acl,
+        Err(stat) => {
+            warn!("getacl error {xid} --> {stat}");
+            return GETACL3res::Err((
+                stat,
+                GETACL3resfail {
+                    attr: Nfs3Option::Some(attr),
+                },
+            ));
+        }
+    };
+
+    let access = if acl.access.is_empty() {
+        acl_from_mode(&attr)
+    } else {
+        acl.access
+            .into_iter()
+            .map(|entry| with_owner_ids(entry, &attr))
+            .collect()
+    };
+    let default = if matches!(attr.type_, ftype3::NF3DIR) {
+        acl.default
+            .into_iter()
+            .map(|entry| aclent {
+                type_: entry.type_ | NFS_ACL_DEFAULT,
+                ..with_owner_ids(entry, &attr)
+            })
+            .collect()
+    } else {
+        Vec::new()
+    };
+
+    let (aclcnt, access) = select_entries(args.mask, NFS_ACL, NFS_ACLCNT, access);
+    let (dfaclcnt, default) = select_entries(args.mask, NFS_DFACL, NFS_DFACLCNT, default);
+    debug!("getacl success {xid} --> {access:?}, {default:?}");
+    GETACL3res::Ok(GETACL3resok {
+        attr: Nfs3Option::Some(attr),
+        acl: secattr {
+            mask: args.mask,
+            aclcnt,
+            aclent: access,
+            dfaclcnt,
+            dfaclent: default,
+        },
+    })
+}
+
+async fn aclproc3_setacl<T>(context: RPCContext<T>, xid: u32, args: SETACL3args) -> SETACL3res
+where
+    T: NfsFileSystem,
+{
+    if !matches!(context.vfs.capabilities(), VFSCapabilities::ReadWrite) {
+        warn!("No write capabilities.");
+        return SETACL3res::Err((nfsstat3::NFS3ERR_ROFS, SETACL3resfail::default()));
+    }
+
+    let id = fh_to_id!(context, &args.fh);
+    let attr = match context.vfs.getattr(&id).await {
+        Ok(attr) => attr,
+        Err(stat) => {
+            warn!("setacl error {xid} --> {stat}");
+            return SETACL3res::Err((stat, SETACL3resfail::default()));
+        }
+    };
+    let fail = |stat: nfsstat3, attr: fattr3| {
+        SETACL3res::Err((
+            stat,
+            SETACL3resfail {
+                attr: Nfs3Option::Some(attr),
+            },
+        ))
+    };
+
+    let mask = args.acl.mask;
+    if mask & !NFS_ACL_MASK != 0 {
+        warn!("setacl error {xid} --> invalid mask {mask:#x}");
+        return fail(nfsstat3::NFS3ERR_INVAL, attr);
+    }
+    let is_dir = matches!(attr.type_, ftype3::NF3DIR);
+    let default: Vec<_> = args
+        .acl
+        .dfaclent
+        .into_iter()
+        .map(|entry| aclent {
+            type_: entry.type_ & !NFS_ACL_DEFAULT,
+            ..entry
+        })
+        .collect();
+    if (mask & NFS_ACL != 0 && !is_valid_acl(&args.acl.aclent))
+        || (mask & NFS_DFACL != 0 && (!is_valid_acl(&default) || (!is_dir && !default.is_empty())))
+    {
+        warn!("setacl error {xid} --> invalid ACL");
+        return fail(nfsstat3::NFS3ERR_INVAL, attr);
+    }
+
+    // only the parts selected by the mask are replaced
+    let mut acl = if mask & (NFS_ACL | NFS_DFACL) == NFS_ACL | NFS_DFACL {
+        PosixAcl::default()
+    } else {
+        match context.vfs.getacl(&id).await {
+            Ok(acl) => acl,
+            Err(stat) => {
+                warn!("setacl error {xid} --> {stat}");
+                return fail(stat, attr);
+            }
+        }
+    };
+    if mask & NFS_ACL != 0 {
+        acl.access = args.acl.aclent;
+    }
+    if mask & NFS_DFACL != 0 {
+        acl.default = default;
+    }
+
+    if let Err(stat) = context.vfs.setacl(&id, acl).await {
+        warn!("setacl error {xid} --> {stat}");
+        return fail(stat, attr);
+    }
+    let attr = context.vfs.getattr(&id).await;
+    debug!("setacl success {xid} --> {attr:?}");
+    SETACL3res::Ok(SETACL3resok {
+        attr: attr.map_or(Nfs3Option::None, Nfs3Option::Some),
+    })
+}
+
+/// Returns the minimal ACL that is equivalent to the mode bits
+fn acl_from_mode(attr: &fattr3) -> Vec<aclent> {
+    vec![
+        aclent {
+            type_: ACL_USER_OBJ,
+            id: attr.uid,
+            perm: (attr.mode >> 6) & 0o7,
+        },
+        aclent {
+            type_: ACL_GROUP_OBJ,
+            id: attr.gid,
+            perm: (attr.mode >> 3) & 0o7,
+        },
+        aclent {
+            type_: ACL_OTHER,
+            id: 0,
+            perm: attr.mode & 0o7,
+        },
+    ]
+}
+
+/// Fills the ids of the entries that refer to the owner, the owning group, the mask and others.
+/// File systems usually don't store them.
+const fn with_owner_ids(entry: aclent, attr: &fattr3) -> aclent {
+    let id = match entry.type_ {
+        ACL_USER_OBJ => attr.uid,
+        ACL_GROUP_OBJ => attr.gid,
+        ACL_USER | ACL_GROUP => entry.id,
+        _ => 0,
+    };
+    aclent { id, ..entry }
+}
+
+/// Returns the entry count and the entries requested by `mask`
+fn select_entries(
+    mask: u32,
+    entries_bit: u32,
+    count_bit: u32,
+    entries: Vec<aclent>,
+) -> (u32, Vec<aclent>) {
+    #[allow(clippy::cast_possible_truncation)]
+    let count = if mask & (entries_bit | count_bit) == 0 {
+        0
+    } else {
+        entries.len() as u32
+    };
+    let entries = if mask & entries_bit == 0 {
+        Vec::new()
+    } else {
+        entries
+    };
+    (count, entries)
+}
+
+/// Checks that the entries form a valid POSIX ACL. An empty ACL is valid and removes the ACL.
+fn is_valid_acl(entries: &[aclent]) -> bool {
+    if entries.is_empty() {
+        return true;
+    }
+
+    let mut user_obj = 0;
+    let mut group_obj = 0;
+    let mut other = 0;
+    let mut mask = 0;
+    let mut named = false;
+    for (i, entry) in entries.iter().enumerate() {
+        if entry.perm & !(ACL_READ | ACL_WRITE | ACL_EXECUTE) != 0 {
+            return false;
+        }
+        match entry.type_ {
+            ACL_USER_OBJ => user_obj += 1,
+            ACL_GROUP_OBJ => group_obj += 1,
+            ACL_OTHER => other += 1,
+            ACL_MASK => mask += 1,
+            ACL_USER | ACL_GROUP => {
+                named = true;
+                let duplicate = entries[..i]
+                    .iter()
+                    .any(|prev| prev.type_ == entry.type_ && prev.id == entry.id);
+                if duplicate {
+                    return false;
+                }
+            }
+            _ => return false,
+        }
+    }
+    user_obj == 1 && group_obj == 1 && other == 1 && mask <= 1 && (mask == 1 || !named)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const fn entry(type_: u32, id: u32, perm: u32) -> aclent {
+        aclent { type_, id, perm }
+    }
+
+    #[test]
+    fn test_is_valid_acl() {
+        let minimal = [
+            entry(ACL_USER_OBJ, 0, 7),
+            entry(ACL_GROUP_OBJ, 0, 5),
+            entry(ACL_OTHER, 0, 0),
+        ];
+        assert!(is_valid_acl(&[]));
+        assert!(is_valid_acl(&minimal));
+
+        let mut named = minimal.to_vec();
+        named.push(entry(ACL_USER, 1000, 6));
+        assert!(!is_valid_acl(&named), "named entries require a mask");
+        named.push(entry(ACL_MASK, 0, 6));
+        assert!(is_valid_acl(&named));
+        named.push(entry(ACL_USER, 1000, 4));
+        assert!(!is_valid_acl(&named), "duplicate named entry");
+
+        assert!(!is_valid_acl(&minimal[..2]), "missing other entry");
+        assert!(!is_valid_acl(&[
+            entry(ACL_USER_OBJ, 0, 8),
+            entry(ACL_GROUP_OBJ, 0, 0),
+            entry(ACL_OTHER, 0, 0),
+        ]));
+        assert!(!is_valid_acl(&[
+            entry(ACL_USER_OBJ | NFS_ACL_DEFAULT, 0, 7),
+            entry(ACL_GROUP_OBJ, 0, 0),
+            entry(ACL_OTHER, 0, 0),
+        ]));
+    }
+
+    #[test]
+    fn test_select_entries() {
+        let entries = vec![entry(ACL_USER_OBJ, 0, 7)];
+        assert_eq!(
+            select_entries(NFS_ACL, NFS_ACL, NFS_ACLCNT, entries.clone()),
+            (1, entries.clone())
+        );
+        assert_eq!(
+            select_entries(NFS_ACLCNT, NFS_ACL, NFS_ACLCNT, entries.clone()),
+            (1, Vec::new())
+        );
+        assert_eq!(
+            select_entries(NFS_DFACL, NFS_ACL, NFS_ACLCNT, entries),
+            (0, Vec::new())
+        );
+    }
+}
@@ -0,0 +1,320 @@
+use std::net::{IpAddr, SocketAddr};
+
+use nfs3_types::nfs3::nfs_fh3;
+#[allow(clippy::wildcard_imports)]
+use nfs3_types::nlm::*;
+use nfs3_types::rpc::accept_stat_data;
+use nfs3_types::xdr_codec::{Opaque, Void};
+use tracing::{debug, error, warn};
+
+use crate::context::RPCContext;
+use crate::lock_manager::{Lock, LockOwner, Share};
+use crate::rpcwire::handle;
+use crate::rpcwire::messages::{HandleResult, IncomingRpcMessage};
+use crate::vfs::{FileHandle, NfsFileSystem};
+
+#[allow(clippy::enum_glob_use)]
+pub async fn handle_nlm<T>(
+    context: RPCContext<T>,
+    message: IncomingRpcMessage,
+) -> anyhow::Result<HandleResult>
+where
+    T: NfsFileSystem,
+{
+    use NLM_PROGRAM::*;
+
+    let call = message.body();
+    let xid = message.xid();
+
+    debug!("handle_nlm({xid}, {call:?}");
+    if call.vers != VERSION {
+        warn!("Invalid NLM Version number {} != {VERSION}", call.vers);
+        return message.into_error_reply(accept_stat_data::PROG_MISMATCH {
+            low: VERSION,
+            high: VERSION,
+        });
+    }
+
+    let Ok(proc) = NLM_PROGRAM::try_from(call.proc) else {
+        error!("invalid NLM Program number {}", call.proc);
+        return message.into_error_reply(accept_stat_data::PROC_UNAVAIL);
+    };
+
+    debug!("{proc}({})", message.xid());
+    match proc {
+        NLMPROC4_NULL => handle(context, message, nlmproc4_null).await,
+        NLMPROC4_TEST => handle(context, message, nlmproc4_test).await,
+        NLMPROC4_LOCK | NLMPROC4_NM_LOCK => handle(context, message, nlmproc4_lock).await,
+        NLMPROC4_CANCEL => handle(context, message, nlmproc4_cancel).await,
+        NLMPROC4_UNLOCK => handle(context, message, nlmproc4_unlock).await,
+        NLMPROC4_GRANTED => handle(context, message, nlmproc4_granted).await,
+        NLMPROC4_SHARE => handle(context, message, nlmproc4_share).await,
+        NLMPROC4_UNSHARE => handle(context, message, nlmproc4_unshare).await,
+        NLMPROC4_FREE_ALL => handle(context, message, nlmproc4_free_all).await,
+        // the asynchronous procedures require calling the client back
+        _ => {
+            warn!("Unimplemented message {proc}");
+            message.into_error_reply(accept_stat_data::PROC_UNAVAIL)
+        }
+    }
+}
+
+/// Resolves the file handle of a lock to the key of the lock table
+fn file_key<T>(context: &RPCContext<T>, fh: &netobj<'_>) -> Result<Vec<u8>, nlm4_stats>
+where
+    T: NfsFileSystem,
+{
+    let fh = nfs_fh3 {
+        data: Opaque::owned(fh.to_vec()),
+    };
+    match context.file_handle_converter.fh_from_nfs::<T::Handle>(&fh) {
+        Ok(id) => Ok(id.as_bytes().to_vec()),
+        Err(stat) => {
+            warn!("cannot resolve fh: {stat}");
+            Err(nlm4_stats::NLM4_STALE_FH)
+        }
+    }
+}
+
+fn lock_owner(lock: &nlm4_lock<'_, '_, '_>) -> LockOwner {
+    LockOwner {
+        host: lock.caller_name.to_vec(),
+        svid: lock.svid,
+        oh: lock.oh.to_vec(),
+    }
+}
+
+/// Returns the IP address of the client, or `None` for connections that don't come from
+/// an IP network, e.g. Unix domain sockets
+fn client_ip<T>(context: &RPCContext<T>) -> Option<IpAddr>
+where
+    T: NfsFileSystem,
+{
+    let addr = context.client_addr.as_str();
+    addr.parse::<SocketAddr>()
+        .map(|addr| addr.ip())
+        .or_else(|_| addr.parse::<IpAddr>())
+        .ok()
+}
+
+/// Returns `true` if the client may release all locks of `host`. Notifications are
+/// unauthenticated, so they are only accepted from the addresses that `host` took its
+/// locks from. Clients that don't connect over IP can't release locks this way.
+pub fn may_free_all<T>(context: &RPCContext<T>, host: &[u8]) -> bool
+where
+    T: NfsFileSystem,
+{
+    client_ip(context).is_some_and(|addr| context.lock_manager.is_host_address(host, addr))
+}
+
+fn nlm4_res(cookie: &netobj<'_>, stat: nlm4_stats) -> nlm4_res<'static> {
+    nlm4_res {
+        cookie: Opaque::owned(cookie.to_vec()),
+        stat: nlm4_stat { stat },
+    }
+}
+
+async fn nlmproc4_null<T>(_: RPCContext<T>, _: u32, _: Void) -> Void
+where
+    T: NfsFileSystem,
+{
+    Void
+}
+
+async fn nlmproc4_test<T>(
+    context: RPCContext<T>,
+    xid: u32,
+    args: nlm4_testargs<'_, '_, '_, '_>,
+) -> nlm4_testres<'static, 'static>
+where
+    T: NfsFileSystem,
+{
+    let stat = match file_key(&context, &args.alock.fh) {
+        Ok(file) => {
+            let (start, end) = Lock::range(args.alock.l_offset, args.alock.l_len);
+            let owner = lock_owner(&args.alock);
+            context
+                .lock_manager
+                .test(&file, &owner, args.exclusive, start, end)
+                .map_or(nlm4_testrply::Stat(nlm4_stats::NLM4_GRANTED), |holder| {
+                    nlm4_testrply::Denied(nlm4_holder {
+                        exclusive: holder.exclusive,
+                        svid: holder.owner.svid,
+                        l_len: holder.len(),
+                        oh: Opaque::owned(holder.owner.oh),
+                        l_offset: holder.start,
+                    })
+                })
+        }
+        Err(stat) => nlm4_testrply::Stat(stat),
+    };
+    debug!("nlmproc4_test({xid}) --> {stat:?}");
+    nlm4_testres {
+        cookie: Opaque::owned(args.cookie.to_vec()),
+        stat,
+    }
+}
+
+async fn nlmproc4_lock<T>(
+    context: RPCContext<T>,
+    xid: u32,
+    args: nlm4_lockargs<'_, '_, '_, '_>,
+) -> nlm4_res<'static>
+where
+    T: NfsFileSystem,
+{
+    let file = match file_key(&context, &args.alock.fh) {
+        Ok(file) => file,
+        Err(stat) => return nlm4_res(&args.cookie, stat),
+    };
+    let (start, end) = Lock::range(args.alock.l_offset, args.alock.l_len);
+    let lock = Lock {
+        owner: lock_owner(&args.alock),
+        exclusive: args.exclusive,
+        start,
+        end,
+    };
+
+    // The server never calls NLMPROC4_GRANTED back, so a blocking request that conflicts
+    // isn't queued. It's answered with NLM4_BLOCKED all the same, and the client keeps
+    // waiting by polling: Linux resends a blocked request every 30 seconds.
+    let result = match context.lock_manager.lock(&file, lock) {
+        Ok(()) => {
+            if let Some(addr) = client_ip(&context) {
+                context
+                    .lock_manager
+                    .add_address(&args.alock.caller_name, addr);
+            }
+            nlm4_stats::NLM4_GRANTED
+        }
+        Err(_) if args.block => nlm4_stats::NLM4_BLOCKED,
+        Err(_) => nlm4_stats::NLM4_DENIED,
+    };
+    debug!("nlmproc4_lock({xid}) --> {result}");
+    nlm4_res(&args.cookie, result)
+}
+
+async fn nlmproc4_cancel<T>(
+    _: RPCContext<T>,
+    xid: u32,
+    args: nlm4_cancargs<'_, '_, '_, '_>,
+) -> nlm4_res<'static>
+where
+    T: NfsFileSystem,
+{
+    // there are no queued requests to cancel
+    debug!("nlmproc4_cancel({xid})");
+    nlm4_res(&args.cookie, nlm4_stats::NLM4_GRANTED)
+}
+
+async fn nlmproc4_unlock<T>(
+    context: RPCContext<T>,
+    xid: u32,
+    args: nlm4_unlockargs<'_, '_, '_, '_>,
+) -> nlm4_res<'static>
+where
+    T: NfsFileSystem,
+{
+    let stat = match file_key(&context, &args.alock.fh) {
+        Ok(file) => {
+            let (start, end) = Lock::range(args.alock.l_offset, args.alock.l_len);
+            let owner = lock_owner(&args.alock);
+            context.lock_manager.unlock(&file, &owner, start, end);
+            nlm4_stats::NLM4_GRANTED
+        }
+        Err(stat) => stat,
+    };
+    debug!("nlmproc4_unlock({xid}) --> {stat}");
+    nlm4_res(&args.cookie, stat)
+}
+
+async fn nlmproc4_granted<T>(
+    _: RPCContext<T>,
+    xid: u32,
+    args: nlm4_testargs<'_, '_, '_, '_>,
+) -> nlm4_res<'static>
+where
+    T: NfsFileSystem,
+{
+    // the server never waits for locks held by other hosts
+    debug!("nlmproc4_granted({xid}) --> no blocked request");
+    nlm4_res(&args.cookie, nlm4_stats::NLM4_DENIED)
+}
+
+async fn nlmproc4_share<T>(
+    context: RPCContext<T>,
+    xid: u32,
+    args: nlm4_shareargs<'_, '_, '_, '_>,
+) -> nlm4_shareres<'static>
+where
+    T: NfsFileSystem,
+{
+    let stat = match file_key(&context, &args.share.fh) {
+        Ok(file) => {
+            let share = Share {
+                host: args.share.caller_name.to_vec(),
+                oh: args.share.oh.to_vec(),
+                mode: args.share.mode as u32,
+                access: args.share.access as u32,
+            };
+            if context.lock_manager.share(&file, share) {
+                if let Some(addr) = client_ip(&context) {
+                    context
+                        .lock_manager
+                        .add_address(&args.share.caller_name, addr);
+                }
+                nlm4_stats::NLM4_GRANTED
+            } else {
+                nlm4_stats::NLM4_DENIED
+            }
+        }
+        Err(stat) => stat,
+    };
+    debug!("nlmproc4_share({xid}) --> {stat}");
+    nlm4_shareres {
+        cookie: Opaque::owned(args.cookie.to_vec()),
+        stat,
+        sequence: 0,
+    }
+}
+
+async fn nlmproc4_unshare<T>(
+    context: RPCContext<T>,
+    xid: u32,
+    args: nlm4_shareargs<'_, '_, '_, '_>,
+) -> nlm4_shareres<'static>
+where
+    T: NfsFileSystem,
+{
+    let stat = match file_key(&context, &args.share.fh) {
+        Ok(file) => {
+            context
+                .lock_manager
+                .unshare(&file, &args.share.caller_name, &args.share.oh);
+            nlm4_stats::NLM4_GRANTED
+        }
+        Err(stat) => stat,
+    };
+    debug!("nlmproc4_unshare({xid}) --> {stat}");
+    nlm4_shareres {
+        cookie: Opaque::owned(args.cookie.to_vec()),
+        stat,
+        sequence: 0,
+    }
+}
+
+async fn nlmproc4_free_all<T>(context: RPCContext<T>, xid: u32, args: nlm4_notify<'_>) -> Void
+where
+    T: NfsFileSystem,
+{
+    debug!("nlmproc4_free_all({xid}, {:?})", args.name);
+    if may_free_all(&context, &args.name) {
+        context.lock_manager.free_all(&args.name);
+    } else {
+        warn!(
+            "nlmproc4_free_all({xid}) --> ignoring request from {}",
+            context.client_addr
+        );
+    }
+    Void
+}
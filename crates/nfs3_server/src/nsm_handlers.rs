@@ -0,0 +1,120 @@
+//! A minimal status monitor
+//!
+//! The server doesn't monitor clients and doesn't notify them about its restarts. It accepts
+//! monitoring requests, so clients can take locks, and releases the locks of hosts that
+//! report a reboot with `SM_NOTIFY`. Notifications are only accepted from the local machine
+//! and from the addresses the rebooted host took its locks from.
+
+#[allow(clippy::wildcard_imports)]
+use nfs3_types::nsm::*;
+use nfs3_types::rpc::accept_stat_data;
+use nfs3_types::xdr_codec::Void;
+use tracing::{debug, error, warn};
+
+use crate::context::RPCContext;
+use crate::nlm_handlers::may_free_all;
+use crate::rpcwire::handle;
+use crate::rpcwire::messages::{HandleResult, IncomingRpcMessage};
+use crate::vfs::NfsFileSystem;
+
+/// The state number of the server. The state isn't persisted, so it's always the first boot.
+const STATE: i32 = 1;
+
+#[allow(clippy::enum_glob_use)]
+pub async fn handle_nsm<T>(
+    context: RPCContext<T>,
+    message: IncomingRpcMessage,
+) -> anyhow::Result<HandleResult>
+where
+    T: NfsFileSystem,
+{
+    use SM_PROGRAM::*;
+
+    let call = message.body();
+    let xid = message.xid();
+
+    debug!("handle_nsm({xid}, {call:?}");
+    if call.vers != VERSION {
+        warn!("Invalid NSM Version number {} != {VERSION}", call.vers);
+        return message.into_error_reply(accept_stat_data::PROG_MISMATCH {
+            low: VERSION,
+            high: VERSION,
+        });
+    }
+
+    let Ok(proc) = SM_PROGRAM::try_from(call.proc) else {
+        error!("invalid NSM Program number {}", call.proc);
+        return message.into_error_reply(accept_stat_data::PROC_UNAVAIL);
+    };
+
+    debug!("{proc}({})", message.xid());
+    match proc {
+        SM_NULL | SM_SIMU_CRASH => handle(context, message, sm_null).await,
+        SM_STAT => handle(context, message, sm_stat).await,
+        SM_MON => handle(context, message, sm_mon).await,
+        SM_UNMON => handle(context, message, sm_unmon).await,
+        SM_UNMON_ALL => handle(context, message, sm_unmon_all).await,
+        SM_NOTIFY => handle(context, message, sm_notify).await,
+    }
+}
+
+async fn sm_null<T>(_: RPCContext<T>, _: u32, _: Void) -> Void
+where
+    T: NfsFileSystem,
+{
+    Void
+}
+
+async fn sm_stat<T>(_: RPCContext<T>, xid: u32, args: sm_name<'_>) -> sm_stat_res
+where
+    T: NfsFileSystem,
+{
+    debug!("sm_stat({xid}, {:?})", args.mon_name);
+    sm_stat_res {
+        res_stat: res::STAT_SUCC,
+        state: STATE,
+    }
+}
+
+async fn sm_mon<T>(_: RPCContext<T>, xid: u32, args: mon<'_, '_>) -> sm_stat_res
+where
+    T: NfsFileSystem,
+{
+    debug!("sm_mon({xid}, {:?})", args.mon_id.mon_name);
+    sm_stat_res {
+        res_stat: res::STAT_SUCC,
+        state: STATE,
+    }
+}
+
+async fn sm_unmon<T>(_: RPCContext<T>, xid: u32, args: mon_id<'_, '_>) -> sm_stat
+where
+    T: NfsFileSystem,
+{
+    debug!("sm_unmon({xid}, {:?})", args.mon_name);
+    sm_stat { state: STATE }
+}
+
+async fn sm_unmon_all<T>(_: RPCContext<T>, xid: u32, _: my_id<'_>) -> sm_stat
+where
+    T: NfsFileSystem,
+{
+    debug!("sm_unmon_all({xid})");
+    sm_stat { state: STATE }
+}
+
+async fn sm_notify<T>(context: RPCContext<T>, xid: u32, args: stat_chge<'_>) -> Void
+where
+    T: NfsFileSystem,
+{
+    debug!("sm_notify({xid}, {:?}, {})", args.mon_name, args.state);
+    if may_free_all(&context, &args.mon_name) {
+        context.lock_manager.free_all(&args.mon_name);
+    } else {
+        warn!(
+            "sm_notify({xid}) --> ignoring notification from {}",
+            context.client_addr
+        );
+    }
+    Void
+}
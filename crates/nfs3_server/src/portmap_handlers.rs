@@ -1,6 +1,7 @@
-use nfs3_types::portmap::{self, PMAP_PROG, mapping};
+use nfs3_types::portmap::{self, IPPROTO_TCP, PMAP_PROG, mapping, pmaplist};
 use nfs3_types::rpc::accept_stat_data;
-use nfs3_types::xdr_codec::Void;
+use nfs3_types::xdr_codec::{List, Void};
+use nfs3_types::{mount, nfs2, nfs3, nfsacl, nlm, nsm};
 use tracing::{debug, error, warn};
 
 use crate::context::RPCContext;
@@ -8,6 +9,20 @@ use crate::rpcwire::handle;
 use crate::rpcwire::messages::{HandleResult, IncomingRpcMessage};
 use crate::vfs::NfsFileSystem;
 
+/// Programs served on the port of the server, as (program, version)
+const REGISTERED_PROGRAMS: &[(u32, u32)] = &[
+    (portmap::PROGRAM, portmap::VERSION),
+    (mount::PROGRAM, mount::VERSION_1),
+    (mount::PROGRAM, mount::VERSION),
+    (nfs3::PROGRAM, nfs2::VERSION),
+    (nfs3::PROGRAM, nfs3::VERSION),
+    #[cfg(feature = "nfs4")]
+    (nfs3::PROGRAM, nfs3_types::nfs4::VERSION),
+    (nfsacl::PROGRAM, nfsacl::VERSION),
+    (nlm::PROGRAM, nlm::VERSION),
+    (nsm::PROGRAM, nsm::VERSION),
+];
+
 pub async fn handle_portmap<T>(
     context: RPCContext<T>,
     message: IncomingRpcMessage,
@@ -32,6 +47,7 @@ where
     match proc {
         Ok(PMAP_PROG::PMAPPROC_NULL) => handle(context, message, pmapproc_null).await,
         Ok(PMAP_PROG::PMAPPROC_GETPORT) => handle(context, message, pmapproc_getport).await,
+        Ok(PMAP_PROG::PMAPPROC_DUMP) => handle(context, message, pmapproc_dump).await,
         _ => {
             warn!("Unimplemented message {}", call.proc);
             message.into_error_reply(accept_stat_data::PROC_UNAVAIL)
@@ -47,13 +63,35 @@ where
     Void
 }
 
-// We fake a portmapper here. And always direct back to the same host port
+// We fake a portmapper here. All registered programs are served on the same host port
 async fn pmapproc_getport<T>(context: RPCContext<T>, xid: u32, m: mapping) -> u32
 where
     T: crate::vfs::NfsFileSystem,
 {
     debug!("pmapproc_getport({xid}, {m:?})");
-    let port = u32::from(context.local_port);
+    let port = if REGISTERED_PROGRAMS.contains(&(m.prog, m.vers)) {
+        u32::from(context.local_port)
+    } else {
+        0
+    };
     debug!("\t{xid} --> {port}");
     port
 }
+
+async fn pmapproc_dump<T>(context: RPCContext<T>, xid: u32, _: Void) -> pmaplist
+where
+    T: crate::vfs::NfsFileSystem,
+{
+    debug!("pmapproc_dump({xid})");
+    let port = u32::from(context.local_port);
+    let mappings = REGISTERED_PROGRAMS
+        .iter()
+        .map(|&(prog, vers)| mapping {
+            prog,
+            vers,
+            prot: IPPROTO_TCP,
+            port,
+        })
+        .collect();
+    List(mappings)
+}
@@ -1,4 +1,5 @@
 use std::io::Cursor;
+use std::sync::Arc;
 use std::time::Instant;
 
 use anyhow::anyhow;
@@ -7,23 +8,26 @@ use nfs3_types::rpc::{
     RPC_VERSION_2, accept_stat_data, auth_flavor, auth_unix, call_body, fragment_header,
 };
 use nfs3_types::xdr_codec::{Pack, Unpack};
-use nfs3_types::{nfs3 as nfs, portmap};
-use tokio::io::{AsyncWriteExt, DuplexStream};
+use nfs3_types::{nfs3 as nfs, nfsacl, nlm, nsm, portmap};
+use tokio::io::DuplexStream;
 use tokio::sync::mpsc;
 use tracing::{error, info, trace, warn};
 
 use crate::context::RPCContext;
+use crate::io::AsyncWrite;
 use crate::transaction_tracker::{self, TransactionError, TransactionLock};
 use crate::units::KIBIBYTE;
-use crate::vfs::NfsFileSystem;
-use crate::{mount_handlers, nfs_handlers, portmap_handlers};
+use crate::vfs::{NfsFileSystem, caller};
+use crate::{
+    mount_handlers, nfs_handlers, nfs2_handlers, nfsacl_handlers, nlm_handlers, nsm_handlers,
+    portmap_handlers,
+};
 
 pub mod messages;
 
 // Information from RFC 5531
 // https://datatracker.ietf.org/doc/html/rfc5531
 
-const NFS_ACL_PROGRAM: u32 = 100_227;
 const NFS_ID_MAP_PROGRAM: u32 = 100_270;
 const NFS_METADATA_PROGRAM: u32 = 200_024;
 
@@ -44,8 +48,14 @@ where
         return message.into_rpc_mismatch();
     }
 
-    if call.cred.flavor == auth_flavor::AUTH_UNIX {
+    let mut caller = None;
+    if let Some(peer_auth) = &context.peer_auth {
+        // the identity of an authenticated TLS peer takes precedence over the credentials
+        context.auth = peer_auth.as_ref().clone();
+        caller = Some(Arc::clone(peer_auth));
+    } else if call.cred.flavor == auth_flavor::AUTH_UNIX {
         let auth = auth_unix::unpack(&mut Cursor::new(&call.cred.body.0))?.0;
+        caller = Some(Arc::new(auth.clone()));
         context.auth = auth;
     }
 
@@ -65,19 +75,34 @@ where
         }
     }
 
-    match prog {
-        portmap::PROGRAM => portmap_handlers::handle_portmap(context, message).await,
-        nfs3_types::mount::PROGRAM => mount_handlers::handle_mount(context, message).await,
-        nfs::PROGRAM => nfs_handlers::handle_nfs(context, message).await,
-        NFS_ACL_PROGRAM | NFS_ID_MAP_PROGRAM | NFS_METADATA_PROGRAM => {
-            trace!("ignoring NFS_ACL packet");
-            message.into_error_reply(accept_stat_data::PROG_UNAVAIL)
-        }
-        _ => {
-            warn!("Unknown RPC Program number {prog} != {}", nfs::PROGRAM);
-            message.into_error_reply(accept_stat_data::PROG_UNAVAIL)
+    // the file systems can get the credentials with `current_caller`
+    let vers = call.vers;
+    let dispatch = async move {
+        match prog {
+            portmap::PROGRAM => portmap_handlers::handle_portmap(context, message).await,
+            nfs3_types::mount::PROGRAM => mount_handlers::handle_mount(context, message).await,
+            nfs::PROGRAM if vers == nfs3_types::nfs2::VERSION => {
+                nfs2_handlers::handle_nfs2(context, message).await
+            }
+            #[cfg(feature = "nfs4")]
+            nfs::PROGRAM if vers == nfs3_types::nfs4::VERSION => {
+                crate::nfs4_handlers::handle_nfs4(context, message).await
+            }
+            nfs::PROGRAM => nfs_handlers::handle_nfs(context, message).await,
+            nfsacl::PROGRAM => nfsacl_handlers::handle_nfsacl(context, message).await,
+            nlm::PROGRAM => nlm_handlers::handle_nlm(context, message).await,
+            nsm::PROGRAM => nsm_handlers::handle_nsm(context, message).await,
+            NFS_ID_MAP_PROGRAM | NFS_METADATA_PROGRAM => {
+                trace!("ignoring {prog} packet");
+                message.into_error_reply(accept_stat_data::PROG_UNAVAIL)
+            }
+            _ => {
+                warn!("Unknown RPC Program number {prog} != {}", nfs::PROGRAM);
+                message.into_error_reply(accept_stat_data::PROG_UNAVAIL)
+            }
         }
-    }
+    };
+    caller::scope_future(caller, dispatch).await
 }
 
 /// Handles the RPC message and returns a result. The handler is an async function
@@ -91,21 +116,49 @@ where
     O: Pack + Send + 'static,
     T: NfsFileSystem,
 {
+    let Some(args) = unpack_args(&mut message) else {
+        return message.into_error_reply(accept_stat_data::GARBAGE_ARGS);
+    };
+
+    let result = handler(context, message.xid(), args).await;
+    message.into_success_reply(&result)
+}
+
+/// Same as [`handle`], but the handler builds the reply from the message itself.
+///
+/// It's used by procedures that pack large payloads directly into the reply buffer,
+/// see [`IncomingRpcMessage::success_reply_buffer`].
+pub async fn handle_packed<I, T>(
+    context: RPCContext<T>,
+    mut message: IncomingRpcMessage,
+    handler: impl AsyncFnOnce(RPCContext<T>, IncomingRpcMessage, I) -> anyhow::Result<HandleResult>,
+) -> anyhow::Result<HandleResult>
+where
+    I: Unpack,
+    T: NfsFileSystem,
+{
+    let Some(args) = unpack_args(&mut message) else {
+        return message.into_error_reply(accept_stat_data::GARBAGE_ARGS);
+    };
+
+    handler(context, message, args).await
+}
+
+/// Unpacks the arguments of the call. Returns `None` if they are malformed.
+fn unpack_args<I: Unpack>(message: &mut IncomingRpcMessage) -> Option<I> {
     let mut cursor = message.take_data();
     let (args, _) = match I::unpack(&mut cursor) {
         Ok(ok) => ok,
         Err(err) => {
             error!("Failed to unpack message: {err}");
-            return message.into_error_reply(accept_stat_data::GARBAGE_ARGS);
+            return None;
         }
     };
     if cursor.position() != cursor.get_ref().len() as u64 {
         error!("Unpacked message size does not match expected size");
-        return message.into_error_reply(accept_stat_data::GARBAGE_ARGS);
+        return None;
     }
-
-    let result = handler(context, message.xid(), args).await;
-    message.into_success_reply(&result)
+    Some(args)
 }
 
 fn lock_transaction(
@@ -133,7 +186,7 @@ fn lock_transaction(
 }
 
 #[allow(clippy::cast_possible_truncation)]
-pub async fn write_fragment<IO: tokio::io::AsyncWrite + Unpin>(
+pub async fn write_fragment<IO: AsyncWrite>(
     socket: &mut IO,
     buf: CompleteRpcMessage,
 ) -> Result<(), anyhow::Error> {
@@ -142,9 +195,9 @@ pub async fn write_fragment<IO: tokio::io::AsyncWrite + Unpin>(
     assert!(buf.len() < (1 << 31));
     let fragment_header = fragment_header::new(buf.len() as u32, true);
     let header_buf = fragment_header.into_xdr_buf();
-    socket.write_all(&header_buf).await?;
+    socket.async_write_all(&header_buf).await?;
     trace!("Writing fragment length: {}", buf.len());
-    socket.write_all(&buf).await?;
+    socket.async_write_all(&buf).await?;
     Ok(())
 }
 
@@ -204,7 +257,8 @@ where
 
             let context = self.context.clone();
             let send = self.reply_send_channel.clone();
-            tokio::spawn(async move {
+            let runtime = Arc::clone(&context.runtime);
+            runtime.spawn(Box::pin(async move {
                 let result = handle_rpc_message(context, message).await;
 
                 match result {
@@ -219,7 +273,7 @@ where
                         let _ = send.send(Err(anyhow!("Error handling RPC message")));
                     }
                 }
-            });
+            }));
         }
 
         Ok(())
@@ -2,8 +2,8 @@ use std::io::Cursor;
 
 use anyhow::bail;
 use nfs3_types::rpc::{
-    accept_stat_data, accepted_reply, call_body, fragment_header, msg_body, opaque_auth,
-    rejected_reply, reply_body, rpc_msg,
+    accept_stat_data, accepted_reply, auth_flavor, auth_stat, call_body, fragment_header, msg_body,
+    opaque_auth, rejected_reply, reply_body, rpc_msg,
 };
 use nfs3_types::xdr_codec::{Pack, Unpack, Void};
 use tokio::io::{AsyncRead, AsyncReadExt};
@@ -126,6 +126,32 @@ impl IncomingRpcMessage {
         pack(&rpc, message).map(HandleResult::Reply)
     }
 
+    /// Returns a buffer with the header of a successful reply, so that the handler can pack
+    /// the rest of the reply directly into it. `body_size` is the expected size of the rest.
+    ///
+    /// The buffer is turned into the reply with [`into_packed_reply`](Self::into_packed_reply).
+    pub fn success_reply_buffer(&self, body_size: usize) -> anyhow::Result<Vec<u8>> {
+        let rpc = rpc_msg {
+            xid: self.xid,
+            body: msg_body::REPLY(reply_body::MSG_ACCEPTED(accepted_reply {
+                verf: opaque_auth::default(),
+                reply_data: accept_stat_data::SUCCESS,
+            })),
+        };
+
+        let mut buf = Vec::with_capacity(rpc.packed_size() + body_size);
+        rpc.pack(&mut buf)?;
+        Ok(buf)
+    }
+
+    /// Turns a buffer returned by [`success_reply_buffer`](Self::success_reply_buffer) into the reply
+    pub fn into_packed_reply(self, buf: Vec<u8>) -> anyhow::Result<HandleResult> {
+        if !buf.starts_with(&self.xid.to_be_bytes()) {
+            bail!("reply buffer does not belong to xid {}", self.xid);
+        }
+        Ok(HandleResult::Reply(CompleteRpcMessage(buf)))
+    }
+
     pub fn into_rpc_mismatch(self) -> anyhow::Result<HandleResult> {
         use nfs3_types::rpc::RPC_VERSION_2;
 
@@ -139,6 +165,40 @@ impl IncomingRpcMessage {
         pack(&rpc, &Void).map(HandleResult::Reply)
     }
 
+    /// Returns `true` if the message is a NULL call with `AUTH_TLS` credentials,
+    /// which a client sends to find out whether the server supports RPC-with-TLS.
+    #[cfg_attr(not(feature = "tls"), allow(dead_code))]
+    pub fn is_tls_probe(&self) -> bool {
+        self.body.proc == 0 && self.body.cred.flavor == auth_flavor::AUTH_TLS
+    }
+
+    /// Replies to an `AUTH_TLS` probe with the `STARTTLS` verifier
+    #[cfg_attr(not(feature = "tls"), allow(dead_code))]
+    pub fn into_starttls_reply(self) -> anyhow::Result<HandleResult> {
+        let rpc = rpc_msg {
+            xid: self.xid,
+            body: msg_body::REPLY(reply_body::MSG_ACCEPTED(accepted_reply {
+                verf: opaque_auth {
+                    flavor: auth_flavor::AUTH_NULL,
+                    body: nfs3_types::xdr_codec::Opaque::borrowed(
+                        nfs3_types::rpc::STARTTLS_VERIFIER,
+                    ),
+                },
+                reply_data: accept_stat_data::SUCCESS,
+            })),
+        };
+        pack(&rpc, &Void).map(HandleResult::Reply)
+    }
+
+    #[cfg_attr(not(feature = "tls"), allow(dead_code))]
+    pub fn into_auth_error(self, stat: auth_stat) -> anyhow::Result<HandleResult> {
+        let rpc = rpc_msg {
+            xid: self.xid,
+            body: msg_body::REPLY(reply_body::MSG_DENIED(rejected_reply::auth_error(stat))),
+        };
+        pack(&rpc, &Void).map(HandleResult::Reply)
+    }
+
     pub fn into_error_reply(self, err: accept_stat_data) -> anyhow::Result<HandleResult> {
         let rpc = rpc_msg {
             xid: self.xid,
@@ -0,0 +1,55 @@
+//! Async runtime abstraction
+//!
+//! The server spawns a task per RPC call and runs a timer that expires old entries of the
+//! retransmission cache. [`Runtime`] provides both, so the server isn't tied to a specific
+//! executor. Implementations for Tokio and smol are available with the `tokio` and `smol`
+//! features; other executors can be plugged in with
+//! [`NFSServer::with_runtime`](crate::server::NFSServer::with_runtime).
+
+use std::io;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// A boxed future that can be sent between threads
+pub type BoxFuture<T> = Pin<Box<dyn Future<Output = T> + Send + 'static>>;
+
+/// Spawns tasks and creates timers for the server
+pub trait Runtime: Send + Sync + 'static {
+    /// Runs `task` in the background. The task is detached, nobody waits for its result.
+    fn spawn(&self, task: BoxFuture<()>);
+
+    /// Returns a future that completes after `duration`
+    fn sleep(&self, duration: Duration) -> BoxFuture<()>;
+}
+
+/// Returns the runtime used by servers that don't set one explicitly
+///
+/// Tokio is preferred when both features are enabled. Without either feature there is no
+/// default and a runtime has to be set with `with_runtime`.
+#[cfg_attr(
+    any(feature = "tokio", feature = "smol"),
+    expect(
+        clippy::unnecessary_wraps,
+        reason = "there is no default without a runtime feature"
+    )
+)]
+pub(crate) fn default_runtime() -> Option<Arc<dyn Runtime>> {
+    #[cfg(feature = "tokio")]
+    {
+        Some(Arc::new(crate::tokio::TokioRuntime))
+    }
+    #[cfg(all(feature = "smol", not(feature = "tokio")))]
+    {
+        Some(Arc::new(crate::smol::SmolRuntime::new()))
+    }
+    #[cfg(not(any(feature = "tokio", feature = "smol")))]
+    {
+        None
+    }
+}
+
+/// Returns the error reported when no runtime is configured
+pub(crate) fn missing_runtime() -> io::Error {
+    io::Error::other("no async runtime is configured, use `with_runtime` to set one")
+}
@@ -0,0 +1,489 @@
+//! Client of the S3 REST API
+
+use std::fmt::Write;
+use std::io;
+
+use chrono::{DateTime, FixedOffset, Utc};
+use http_body_util::{BodyExt, Full};
+use hyper::body::Bytes;
+use hyper::header::{CONTENT_LENGTH, CONTENT_RANGE, ETAG, HeaderMap, LAST_MODIFIED};
+use hyper::{Method, Request, StatusCode, Uri};
+use hyper_rustls::{HttpsConnector, HttpsConnectorBuilder};
+use hyper_util::client::legacy::Client as HttpClient;
+use hyper_util::client::legacy::connect::HttpConnector;
+use hyper_util::rt::TokioExecutor;
+use nfs3_types::nfs3::{nfsstat3, nfstime3};
+use quick_xml::Reader;
+use quick_xml::escape::escape;
+use quick_xml::events::Event;
+use tracing::warn;
+
+use super::config::S3Config;
+use super::signer::{self, Signer, amz_date, canonical_query, encode, sha256_hex};
+
+/// Size and modification time of an object
+#[derive(Debug, Clone, Default)]
+pub(super) struct ObjectInfo {
+    pub size: u64,
+    pub modified: nfstime3,
+}
+
+/// A page of the objects and common prefixes under a prefix
+#[derive(Debug, Default)]
+pub(super) struct Listing {
+    pub objects: Vec<(String, ObjectInfo)>,
+    /// Common prefixes, with the delimiter at the end
+    pub prefixes: Vec<String>,
+    /// Continuation token of the next page
+    pub next: Option<String>,
+}
+
+enum Http {
+    Plain(HttpClient<HttpConnector, Full<Bytes>>),
+    Tls(HttpClient<HttpsConnector<HttpConnector>, Full<Bytes>>),
+}
+
+struct Response {
+    status: StatusCode,
+    headers: HeaderMap,
+    body: Bytes,
+}
+
+/// Sends path-style requests to a bucket
+pub(super) struct Client {
+    http: Http,
+    scheme: &'static str,
+    host: String,
+    bucket: String,
+    signer: Option<Signer>,
+}
+
+impl Client {
+    pub fn new(config: &S3Config) -> io::Result<Self> {
+        let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidInput, message);
+        let uri = config
+            .endpoint
+            .parse::<Uri>()
+            .map_err(|err| invalid(&format!("invalid endpoint: {err}")))?;
+        let host = uri
+            .authority()
+            .ok_or_else(|| invalid("the endpoint has no host"))?
+            .to_string();
+        if !matches!(uri.path(), "" | "/") || uri.query().is_some() {
+            return Err(invalid("the endpoint must not have a path"));
+        }
+        if config.bucket.is_empty() || config.bucket.contains('/') {
+            return Err(invalid("invalid bucket name"));
+        }
+
+        let builder = HttpClient::builder(TokioExecutor::new());
+        let (http, scheme) = match (uri.scheme_str(), &config.tls) {
+            (Some("http"), _) => (Http::Plain(builder.build_http()), "http"),
+            (Some("https"), Some(tls)) => {
+                let connector = HttpsConnectorBuilder::new()
+                    .with_tls_config((**tls).clone())
+                    .https_only()
+                    .enable_http1()
+                    .build();
+                (Http::Tls(builder.build(connector)), "https")
+            }
+            (Some("https"), None) => {
+                return Err(invalid("https endpoints need a TLS configuration"));
+            }
+            _ => return Err(invalid("the scheme of the endpoint must be http or https")),
+        };
+        Ok(Self {
+            http,
+            scheme,
+            host,
+            bucket: config.bucket.clone(),
+            signer: config
+                .credentials
+                .clone()
+                .map(|credentials| Signer::new(credentials, config.region.clone())),
+        })
+    }
+
+    /// Returns the size of an object, or `None` if it doesn't exist
+    pub async fn head(&self, key: &str) -> Result<Option<ObjectInfo>, nfsstat3> {
+        let response = self
+            .send(Method::HEAD, key, &[], Vec::new(), Bytes::new())
+            .await?;
+        match response.status {
+            StatusCode::OK => Ok(Some(ObjectInfo {
+                size: header(&response.headers, CONTENT_LENGTH)
+                    .and_then(|len| len.parse().ok())
+                    .unwrap_or_default(),
+                modified: header(&response.headers, LAST_MODIFIED)
+                    .and_then(|time| DateTime::parse_from_rfc2822(time).ok())
+                    .map(nfstime)
+                    .unwrap_or_default(),
+            })),
+            StatusCode::NOT_FOUND => Ok(None),
+            _ => Err(error("HEAD", key, &response)),
+        }
+    }
+
+    /// Reads `len` bytes at `offset`, returning them with the size of the object
+    pub async fn get_range(
+        &self,
+        key: &str,
+        offset: u64,
+        len: u64,
+    ) -> Result<(Bytes, u64), nfsstat3> {
+        let last = offset.saturating_add(len.max(1) - 1);
+        let range = vec![("range", format!("bytes={offset}-{last}"))];
+        let response = self
+            .send(Method::GET, key, &[], range, Bytes::new())
+            .await?;
+        let size = || object_size(&response.headers);
+        match response.status {
+            StatusCode::PARTIAL_CONTENT => {
+                let size = size().unwrap_or(offset + response.body.len() as u64);
+                Ok((response.body, size))
+            }
+            // servers may ignore the range and send the whole object
+            StatusCode::OK => {
+                let size = response.body.len();
+                let start = usize::try_from(offset).unwrap_or(usize::MAX).min(size);
+                let end = usize::try_from(last.saturating_add(1))
+                    .unwrap_or(usize::MAX)
+                    .min(size);
+                Ok((response.body.slice(start..end), size as u64))
+            }
+            StatusCode::RANGE_NOT_SATISFIABLE => Ok((Bytes::new(), size().unwrap_or(offset))),
+            _ => Err(error("GET", key, &response)),
+        }
+    }
+
+    pub async fn put(&self, key: &str, body: Bytes) -> Result<(), nfsstat3> {
+        let response = self.send(Method::PUT, key, &[], Vec::new(), body).await?;
+        check("PUT", key, &response)
+    }
+
+    pub async fn delete(&self, key: &str) -> Result<(), nfsstat3> {
+        let response = self
+            .send(Method::DELETE, key, &[], Vec::new(), Bytes::new())
+            .await?;
+        check("DELETE", key, &response)
+    }
+
+    /// Copies an object within the bucket
+    pub async fn copy(&self, from: &str, to: &str) -> Result<(), nfsstat3> {
+        let source = format!("/{}/{}", encode(&self.bucket, true), encode(from, true));
+        let headers = vec![("x-amz-copy-source", source)];
+        let response = self
+            .send(Method::PUT, to, &[], headers, Bytes::new())
+            .await?;
+        check("COPY", from, &response)
+    }
+
+    /// Lists the keys that start with `prefix`
+    ///
+    /// With `delimiter`, the keys that have a `/` after the prefix are grouped into common
+    /// prefixes.
+    pub async fn list(
+        &self,
+        prefix: &str,
+        delimiter: bool,
+        start_after: Option<&str>,
+        token: Option<&str>,
+        max_keys: Option<u32>,
+    ) -> Result<Listing, nfsstat3> {
+        let max_keys = max_keys.map(|max_keys| max_keys.to_string());
+        let mut query = vec![("list-type", "2"), ("prefix", prefix)];
+        if delimiter {
+            query.push(("delimiter", "/"));
+        }
+        if let Some(start_after) = start_after {
+            query.push(("start-after", start_after));
+        }
+        if let Some(token) = token {
+            query.push(("continuation-token", token));
+        }
+        if let Some(max_keys) = &max_keys {
+            query.push(("max-keys", max_keys));
+        }
+        let response = self
+            .send(Method::GET, "", &query, Vec::new(), Bytes::new())
+            .await?;
+        check("LIST", prefix, &response)?;
+
+        let mut listing = Listing::default();
+        let mut truncated = false;
+        parse_xml(&response.body, |path, text| match path {
+            [.., b"Contents", b"Key"] => listing.objects.push((text, ObjectInfo::default())),
+            [.., b"Contents", b"Size"] => {
+                if let Some((_, info)) = listing.objects.last_mut() {
+                    info.size = text.parse().unwrap_or_default();
+                }
+            }
+            [.., b"Contents", b"LastModified"] => {
+                if let Some((_, info)) = listing.objects.last_mut() {
+                    info.modified = DateTime::parse_from_rfc3339(&text)
+                        .map(nfstime)
+                        .unwrap_or_default();
+                }
+            }
+            [.., b"CommonPrefixes", b"Prefix"] => listing.prefixes.push(text),
+            [_, b"IsTruncated"] => truncated = text == "true",
+            [_, b"NextContinuationToken"] => listing.next = Some(text),
+            _ => {}
+        })?;
+        if !truncated {
+            listing.next = None;
+        }
+        Ok(listing)
+    }
+
+    /// Starts a multipart upload, returning its id
+    pub async fn create_multipart(&self, key: &str) -> Result<String, nfsstat3> {
+        let query = [("uploads", "")];
+        let response = self
+            .send(Method::POST, key, &query, Vec::new(), Bytes::new())
+            .await?;
+        check("CREATE UPLOAD", key, &response)?;
+        let mut upload_id = None;
+        parse_xml(&response.body, |path, text| {
+            if let [.., b"UploadId"] = path {
+                upload_id = Some(text);
+            }
+        })?;
+        upload_id.ok_or_else(|| {
+            warn!("CREATE UPLOAD {key}: no upload id in the response");
+            nfsstat3::NFS3ERR_IO
+        })
+    }
+
+    /// Uploads a part, returning its `ETag`
+    pub async fn upload_part(
+        &self,
+        key: &str,
+        upload_id: &str,
+        number: u32,
+        body: Bytes,
+    ) -> Result<String, nfsstat3> {
+        let number = number.to_string();
+        let query = [("partNumber", number.as_str()), ("uploadId", upload_id)];
+        let response = self
+            .send(Method::PUT, key, &query, Vec::new(), body)
+            .await?;
+        check("UPLOAD PART", key, &response)?;
+        header(&response.headers, ETAG)
+            .map(str::to_string)
+            .ok_or_else(|| {
+                warn!("UPLOAD PART {key}: no ETag in the response");
+                nfsstat3::NFS3ERR_IO
+            })
+    }
+
+    /// Completes a multipart upload with the `ETag`s of all parts
+    pub async fn complete_multipart(
+        &self,
+        key: &str,
+        upload_id: &str,
+        etags: &[String],
+    ) -> Result<(), nfsstat3> {
+        let mut body = String::from("<CompleteMultipartUpload>");
+        for (number, etag) in (1..).zip(etags) {
+            let _ = write!(
+                body,
+                "<Part><PartNumber>{number}</PartNumber><ETag>{}</ETag></Part>",
+                escape(etag.as_str())
+            );
+        }
+        body += "</CompleteMultipartUpload>";
+        let query = [("uploadId", upload_id)];
+        let response = self
+            .send(Method::POST, key, &query, Vec::new(), body.into())
+            .await?;
+        check("COMPLETE UPLOAD", key, &response)
+    }
+
+    pub async fn abort_multipart(&self, key: &str, upload_id: &str) -> Result<(), nfsstat3> {
+        let query = [("uploadId", upload_id)];
+        let response = self
+            .send(Method::DELETE, key, &query, Vec::new(), Bytes::new())
+            .await?;
+        check("ABORT UPLOAD", key, &response)
+    }
+
+    async fn send(
+        &self,
+        method: Method,
+        key: &str,
+        query: &[(&str, &str)],
+        mut headers: Vec<(&str, String)>,
+        body: Bytes,
+    ) -> Result<Response, nfsstat3> {
+        let mut path = format!("/{}", encode(&self.bucket, true));
+        if !key.is_empty() {
+            path = format!("{path}/{}", encode(key, true));
+        }
+        let query = canonical_query(query);
+        let payload_hash = sha256_hex(&body);
+        let time = Utc::now();
+        headers.push(("host", self.host.clone()));
+        headers.push(("x-amz-content-sha256", payload_hash.clone()));
+        headers.push(("x-amz-date", amz_date(time)));
+        if let Some(token) = self.signer.as_ref().and_then(Signer::session_token) {
+            headers.push(("x-amz-security-token", token.to_string()));
+        }
+        headers.sort();
+
+        let mut uri = format!("{}://{}{path}", self.scheme, self.host);
+        if !query.is_empty() {
+            uri = format!("{uri}?{query}");
+        }
+        let mut request = Request::builder().method(method.clone()).uri(uri);
+        for (name, value) in &headers {
+            request = request.header(*name, value);
+        }
+        if let Some(signer) = &self.signer {
+            let signed = signer::Request {
+                method: method.as_str(),
+                path: &path,
+                query: &query,
+                headers: &headers,
+                payload_hash: &payload_hash,
+            };
+            request = request.header("authorization", signer.authorization(&signed, time));
+        }
+        let request = request.body(Full::new(body)).map_err(|err| {
+            warn!("{method} {key}: invalid request: {err}");
+            nfsstat3::NFS3ERR_INVAL
+        })?;
+
+        let response = match &self.http {
+            Http::Plain(client) => client.request(request).await,
+            Http::Tls(client) => client.request(request).await,
+        }
+        .map_err(|err| {
+            warn!("{method} {key}: {err}");
+            nfsstat3::NFS3ERR_IO
+        })?;
+        let (parts, body) = response.into_parts();
+        let body = body
+            .collect()
+            .await
+            .map_err(|err| {
+                warn!("{method} {key}: {err}");
+                nfsstat3::NFS3ERR_IO
+            })?
+            .to_bytes();
+        Ok(Response {
+            status: parts.status,
+            headers: parts.headers,
+            body,
+        })
+    }
+}
+
+/// Fails if the request wasn't successful
+///
+/// Some requests can fail after the status was sent, so the body is checked for an error too.
+fn check(op: &str, key: &str, response: &Response) -> Result<(), nfsstat3> {
+    if response.status.is_success() && !response.body.starts_with(b"<?xml") {
+        return Ok(());
+    }
+    if response.status.is_success() && error_code(&response.body).is_none() {
+        return Ok(());
+    }
+    Err(error(op, key, response))
+}
+
+fn error(op: &str, key: &str, response: &Response) -> nfsstat3 {
+    let code = error_code(&response.body).unwrap_or_default();
+    warn!("{op} {key}: {} {code}", response.status);
+    match response.status {
+        StatusCode::NOT_FOUND => nfsstat3::NFS3ERR_NOENT,
+        StatusCode::FORBIDDEN => nfsstat3::NFS3ERR_ACCES,
+        _ => nfsstat3::NFS3ERR_IO,
+    }
+}
+
+/// Returns the code of an error response
+fn error_code(body: &[u8]) -> Option<String> {
+    let mut code = None;
+    parse_xml(body, |path, text| {
+        if let [b"Error", b"Code"] = path {
+            code = Some(text);
+        }
+    })
+    .ok()?;
+    code
+}
+
+fn header(headers: &HeaderMap, name: hyper::header::HeaderName) -> Option<&str> {
+    headers.get(name)?.to_str().ok()
+}
+
+/// Returns the size of the object from the `Content-Range` header
+fn object_size(headers: &HeaderMap) -> Option<u64> {
+    let range = header(headers, CONTENT_RANGE)?;
+    range.rsplit_once('/')?.1.parse().ok()
+}
+
+fn nfstime(time: DateTime<FixedOffset>) -> nfstime3 {
+    nfstime3 {
+        seconds: u32::try_from(time.timestamp()).unwrap_or_default(),
+        nseconds: time.timestamp_subsec_nanos(),
+    }
+}
+
+/// Calls `visit` with the path and the text of every element without children
+fn parse_xml(body: &[u8], mut visit: impl FnMut(&[&[u8]], String)) -> Result<(), nfsstat3> {
+    let invalid = |err: &dyn std::fmt::Display| {
+        warn!("invalid response: {err}");
+        nfsstat3::NFS3ERR_IO
+    };
+    let body = std::str::from_utf8(body).map_err(|err| invalid(&err))?;
+    let mut reader = Reader::from_str(body);
+    let mut path = Vec::new();
+    let mut text = String::new();
+    let mut leaf = false;
+    loop {
+        match reader.read_event().map_err(|err| invalid(&err))? {
+            Event::Start(start) => {
+                path.push(start.local_name().as_ref().to_vec());
+                text.clear();
+                leaf = true;
+            }
+            Event::Empty(empty) => {
+                path.push(empty.local_name().as_ref().to_vec());
+                visit(
+                    &path.iter().map(Vec::as_slice).collect::<Vec<_>>(),
+                    String::new(),
+                );
+                path.pop();
+                leaf = false;
+            }
+            Event::Text(part) => text += &part.decode().map_err(|err| invalid(&err))?,
+            Event::CData(part) => text += &part.decode().map_err(|err| invalid(&err))?,
+            Event::GeneralRef(reference) => {
+                let ch = match reference.resolve_char_ref().map_err(|err| invalid(&err))? {
+                    Some(ch) => ch,
+                    None => match reference.as_ref() {
+                        b"amp" => '&',
+                        b"lt" => '<',
+                        b"gt" => '>',
+                        b"quot" => '"',
+                        b"apos" => '\'',
+                        _ => return Err(invalid(&"unknown entity")),
+                    },
+                };
+                text.push(ch);
+            }
+            Event::End(_) => {
+                if leaf {
+                    let path = path.iter().map(Vec::as_slice).collect::<Vec<_>>();
+                    visit(&path, std::mem::take(&mut text));
+                }
+                path.pop();
+                leaf = false;
+            }
+            Event::Eof => return Ok(()),
+            _ => {}
+        }
+    }
+}
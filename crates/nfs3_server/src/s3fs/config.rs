@@ -0,0 +1,146 @@
+//! Configuration of the S3 file system
+
+use std::fmt;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use rustls::ClientConfig;
+
+/// Default size of the parts of multipart uploads
+pub const DEFAULT_PART_SIZE: u64 = 8 * 1024 * 1024;
+/// The smallest part size accepted by S3
+pub const MIN_PART_SIZE: u64 = 5 * 1024 * 1024;
+
+/// Keys used to sign the requests
+#[derive(Clone)]
+pub struct Credentials {
+    pub(super) access_key_id: String,
+    pub(super) secret_access_key: String,
+    pub(super) session_token: Option<String>,
+}
+
+impl Credentials {
+    pub fn new(access_key_id: impl Into<String>, secret_access_key: impl Into<String>) -> Self {
+        Self {
+            access_key_id: access_key_id.into(),
+            secret_access_key: secret_access_key.into(),
+            session_token: None,
+        }
+    }
+
+    /// Sets the token of temporary credentials
+    #[must_use]
+    pub fn with_session_token(mut self, session_token: impl Into<String>) -> Self {
+        self.session_token = Some(session_token.into());
+        self
+    }
+}
+
+impl fmt::Debug for Credentials {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Credentials")
+            .field("access_key_id", &self.access_key_id)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Configuration of [`S3Fs`](super::S3Fs)
+#[derive(Debug, Clone)]
+pub struct S3Config {
+    pub(super) endpoint: String,
+    pub(super) bucket: String,
+    pub(super) region: String,
+    pub(super) credentials: Option<Credentials>,
+    pub(super) prefix: String,
+    pub(super) part_size: u64,
+    pub(super) staging_dir: PathBuf,
+    pub(super) tls: Option<Arc<ClientConfig>>,
+    pub(super) uid: u32,
+    pub(super) gid: u32,
+    pub(super) file_mode: u32,
+    pub(super) dir_mode: u32,
+}
+
+impl S3Config {
+    /// Creates a configuration for a bucket
+    ///
+    /// The endpoint is the URL of the service without the bucket, e.g.
+    /// `https://s3.eu-west-1.amazonaws.com` or `http://localhost:9000`. The bucket is addressed
+    /// in the path of the requests.
+    pub fn new(endpoint: impl Into<String>, bucket: impl Into<String>) -> Self {
+        Self {
+            endpoint: endpoint.into(),
+            bucket: bucket.into(),
+            region: "us-east-1".to_string(),
+            credentials: None,
+            prefix: String::new(),
+            part_size: DEFAULT_PART_SIZE,
+            staging_dir: std::env::temp_dir(),
+            tls: None,
+            uid: 0,
+            gid: 0,
+            file_mode: 0o644,
+            dir_mode: 0o755,
+        }
+    }
+
+    /// Sets the region used to sign the requests, `us-east-1` by default
+    #[must_use]
+    pub fn with_region(mut self, region: impl Into<String>) -> Self {
+        self.region = region.into();
+        self
+    }
+
+    /// Sets the credentials, the requests are anonymous without them
+    #[must_use]
+    pub fn with_credentials(mut self, credentials: Credentials) -> Self {
+        self.credentials = Some(credentials);
+        self
+    }
+
+    /// Exports only the keys under a prefix, e.g. `exports/home`
+    #[must_use]
+    pub fn with_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.prefix = prefix.into();
+        self
+    }
+
+    /// Sets the size of the parts of multipart uploads, at least [`MIN_PART_SIZE`]
+    #[must_use]
+    pub const fn with_part_size(mut self, part_size: u64) -> Self {
+        self.part_size = part_size;
+        self
+    }
+
+    /// Sets the directory for the local copies of the files being written
+    ///
+    /// It's the temporary directory of the system by default.
+    #[must_use]
+    pub fn with_staging_dir(mut self, staging_dir: impl Into<PathBuf>) -> Self {
+        self.staging_dir = staging_dir.into();
+        self
+    }
+
+    /// Sets the TLS configuration for `https` endpoints
+    #[must_use]
+    pub fn with_tls(mut self, tls: Arc<ClientConfig>) -> Self {
+        self.tls = Some(tls);
+        self
+    }
+
+    /// Sets the owner of all files and directories, root by default
+    #[must_use]
+    pub const fn with_owner(mut self, uid: u32, gid: u32) -> Self {
+        self.uid = uid;
+        self.gid = gid;
+        self
+    }
+
+    /// Sets the mode of all files and directories, 0644 and 0755 by default
+    #[must_use]
+    pub const fn with_modes(mut self, file_mode: u32, dir_mode: u32) -> Self {
+        self.file_mode = file_mode;
+        self.dir_mode = dir_mode;
+        self
+    }
+}
@@ -0,0 +1,803 @@
+//! File system that exports a bucket of an S3-compatible object store.
+//!
+//! [`S3Fs`] maps directories to key prefixes and files to objects: the file `docs/a.txt` is the
+//! object with the key `docs/a.txt`, and the directory `docs` is every key that starts with
+//! `docs/`. Empty directories are kept as zero-length objects with a trailing `/`, which is the
+//! convention of most S3 tools.
+//!
+//! # Reads and writes
+//!
+//! - Reads are served with ranged GET requests, so only the requested bytes are transferred.
+//! - Writes go to a copy of the file in a local staging directory. The existing object is
+//!   downloaded on the first write. The server replies to unstable writes as such, and the
+//!   staged copy is uploaded when the client sends a COMMIT, with a multipart upload for files
+//!   of at least one part and a single PUT for smaller ones.
+//! - Setting the size of a file, renaming it or writing with `FILE_SYNC` uploads the staged copy
+//!   right away.
+//!
+//! # Handles
+//!
+//! The file ids are derived from a hash of the keys, so they don't change when the server
+//! restarts. The keys are remembered for the ids handed out since the server started, older
+//! handles are reported as stale.
+//!
+//! # Limitations
+//!
+//! - Objects are not locked, concurrent writers through other servers overwrite each other.
+//! - Modes and owners come from the [`S3Config`], changing them returns `NFS3ERR_NOTSUPP`.
+//!   Setting the times is ignored.
+//! - Symlinks, hard links and exclusive creation are not supported.
+//! - Renaming a directory copies every object under it, which is slow for large trees.
+//! - The modification times of directories are only updated by changes made through this
+//!   server.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use nfs3_server::s3fs::{Credentials, S3Config, S3Fs};
+//! use nfs3_server::tcp::{NFSTcp, NFSTcpListener};
+//!
+//! async fn run() -> anyhow::Result<()> {
+//!     let config = S3Config::new("http://localhost:9000", "exports")
+//!         .with_credentials(Credentials::new("access key", "secret key"))
+//!         .with_staging_dir("/var/tmp/nfs-staging");
+//!     let fs = S3Fs::new(config)?;
+//!     let listener = NFSTcpListener::bind("0.0.0.0:11111", fs).await?;
+//!     listener.handle_forever().await?;
+//!     Ok(())
+//! }
+//! ```
+
+mod client;
+mod config;
+mod signer;
+mod staging;
+
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::io;
+use std::sync::{Arc, Mutex, RwLock};
+
+use client::{Client, ObjectInfo};
+pub use config::{Credentials, DEFAULT_PART_SIZE, MIN_PART_SIZE, S3Config};
+use hyper::body::Bytes;
+use nfs3_types::nfs3::{
+    Nfs3Option, createverf3, fattr3, filename3, ftype3, nfspath3, nfsstat3, nfstime3, sattr3,
+    specdata3,
+};
+use sha2::{Digest, Sha256};
+use staging::{Staged, now};
+
+use crate::vfs::{
+    DirEntryPlus, FileHandleU64, NextResult, NfsFileSystem, NfsReadFileSystem, ReadDirPlusIterator,
+};
+
+/// The largest number of parts of a multipart upload
+const MAX_PARTS: u64 = 10_000;
+
+type Slot = Arc<tokio::sync::Mutex<Option<Staged>>>;
+
+/// A key that was handed out as a handle
+struct Node {
+    key: String,
+    /// Modification time of a directory
+    mtime: nfstime3,
+}
+
+/// `S3Fs` implements [`NfsFileSystem`] on top of a bucket of an S3-compatible object store
+pub struct S3Fs {
+    client: Client,
+    config: S3Config,
+    root: String,
+    nodes: RwLock<HashMap<u64, Node>>,
+    staged: Mutex<HashMap<u64, Slot>>,
+}
+
+impl S3Fs {
+    /// Creates a file system for the bucket of the configuration
+    ///
+    /// No requests are sent until the file system is used.
+    pub fn new(config: S3Config) -> io::Result<Self> {
+        if config.part_size < MIN_PART_SIZE {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("the part size must be at least {MIN_PART_SIZE} bytes"),
+            ));
+        }
+        let client = Client::new(&config)?;
+        let mut root = config.prefix.trim_matches('/').to_string();
+        if !root.is_empty() {
+            root.push('/');
+        }
+
+        let fs = Self {
+            client,
+            config,
+            root,
+            nodes: RwLock::new(HashMap::new()),
+            staged: Mutex::new(HashMap::new()),
+        };
+        fs.register(&fs.root);
+        Ok(fs)
+    }
+
+    fn register(&self, key: &str) -> FileHandleU64 {
+        let id = key_id(key);
+        let mut nodes = self.nodes.write().expect("lock is poisoned");
+        let node = nodes.entry(id).or_insert_with(|| Node {
+            key: key.to_string(),
+            mtime: now(),
+        });
+        if node.key != key {
+            tracing::error!("keys {key:?} and {:?} have the same id {id}", node.key);
+        }
+        FileHandleU64::new(id)
+    }
+
+    fn key(&self, id: FileHandleU64) -> Result<String, nfsstat3> {
+        let nodes = self.nodes.read().expect("lock is poisoned");
+        let node = nodes.get(&id.as_u64()).ok_or(nfsstat3::NFS3ERR_STALE)?;
+        Ok(node.key.clone())
+    }
+
+    fn dir_key(&self, id: FileHandleU64) -> Result<String, nfsstat3> {
+        let key = self.key(id)?;
+        if self.is_dir(&key) {
+            Ok(key)
+        } else {
+            Err(nfsstat3::NFS3ERR_NOTDIR)
+        }
+    }
+
+    fn file_key(&self, id: FileHandleU64) -> Result<String, nfsstat3> {
+        let key = self.key(id)?;
+        if self.is_dir(&key) {
+            Err(nfsstat3::NFS3ERR_ISDIR)
+        } else {
+            Ok(key)
+        }
+    }
+
+    fn is_dir(&self, key: &str) -> bool {
+        key == self.root || key.ends_with('/')
+    }
+
+    fn parent(&self, key: &str) -> String {
+        if key == self.root {
+            return key.to_string();
+        }
+        let trimmed = key.strip_suffix('/').unwrap_or(key);
+        trimmed
+            .rfind('/')
+            .map_or_else(String::new, |pos| trimmed[..=pos].to_string())
+    }
+
+    /// Updates the modification time of a directory
+    fn touch(&self, id: FileHandleU64) {
+        let mut nodes = self.nodes.write().expect("lock is poisoned");
+        if let Some(node) = nodes.get_mut(&id.as_u64()) {
+            node.mtime = now();
+        }
+    }
+
+    const fn dir_attr(&self, id: u64, mtime: nfstime3) -> fattr3 {
+        self.attr(ftype3::NF3DIR, self.config.dir_mode, id, 0, mtime)
+    }
+
+    const fn file_attr(&self, id: u64, size: u64, mtime: nfstime3) -> fattr3 {
+        self.attr(ftype3::NF3REG, self.config.file_mode, id, size, mtime)
+    }
+
+    const fn attr(&self, type_: ftype3, mode: u32, id: u64, size: u64, mtime: nfstime3) -> fattr3 {
+        fattr3 {
+            type_,
+            mode,
+            nlink: 1,
+            uid: self.config.uid,
+            gid: self.config.gid,
+            size,
+            used: size,
+            rdev: specdata3 {
+                specdata1: 0,
+                specdata2: 0,
+            },
+            fsid: 0,
+            fileid: id,
+            atime: mtime,
+            mtime,
+            ctime: mtime,
+        }
+    }
+
+    async fn dir_exists(&self, key: &str) -> Result<bool, nfsstat3> {
+        let listing = self.client.list(key, false, None, None, Some(1)).await?;
+        Ok(!listing.objects.is_empty())
+    }
+
+    /// Returns all keys that start with `prefix`
+    async fn list_all(&self, prefix: &str) -> Result<Vec<String>, nfsstat3> {
+        let mut keys = Vec::new();
+        let mut token = None;
+        loop {
+            let listing = self
+                .client
+                .list(prefix, false, None, token.as_deref(), None)
+                .await?;
+            keys.extend(listing.objects.into_iter().map(|(key, _)| key));
+            token = listing.next;
+            if token.is_none() {
+                return Ok(keys);
+            }
+        }
+    }
+
+    fn slot(&self, id: u64) -> Option<Slot> {
+        let staged = self.staged.lock().expect("lock is poisoned");
+        staged.get(&id).cloned()
+    }
+
+    fn slot_or_insert(&self, id: u64) -> Slot {
+        let mut staged = self.staged.lock().expect("lock is poisoned");
+        staged.entry(id).or_default().clone()
+    }
+
+    /// Forgets a slot if nobody else is using it
+    fn release(&self, id: u64, slot: &Slot) {
+        let mut staged = self.staged.lock().expect("lock is poisoned");
+        if let Some(current) = staged.get(&id) {
+            // the map and `slot` are the only references
+            if Arc::ptr_eq(current, slot) && Arc::strong_count(slot) == 2 {
+                staged.remove(&id);
+            }
+        }
+    }
+
+    /// Drops the staged copy of a file without uploading it
+    async fn discard(&self, id: u64) {
+        let slot = self.staged.lock().expect("lock is poisoned").remove(&id);
+        if let Some(slot) = slot {
+            slot.lock().await.take();
+        }
+    }
+
+    /// Returns the staged copy of a file, downloading the object if there is none
+    ///
+    /// Only the first `keep` bytes are downloaded if it's set.
+    async fn load<'a>(
+        &self,
+        staged: &'a mut Option<Staged>,
+        key: &str,
+        keep: Option<u64>,
+    ) -> Result<&'a mut Staged, nfsstat3> {
+        if let Some(staged) = staged {
+            return Ok(staged);
+        }
+        let info = self
+            .client
+            .head(key)
+            .await?
+            .ok_or(nfsstat3::NFS3ERR_STALE)?;
+        let size = keep.map_or(info.size, |keep| keep.min(info.size));
+        let mut copy = Staged::create(&self.config.staging_dir).await?;
+        while copy.size < size {
+            let len = (size - copy.size).min(self.config.part_size);
+            let (data, _) = self.client.get_range(key, copy.size, len).await?;
+            if data.is_empty() {
+                break;
+            }
+            copy.write_at(copy.size, &data).await?;
+        }
+        Ok(staged.insert(copy))
+    }
+
+    /// Uploads a staged copy, with a multipart upload if it's at least one part
+    async fn upload(&self, key: &str, staged: &mut Staged) -> Result<(), nfsstat3> {
+        let size = staged.size;
+        if size < self.config.part_size {
+            let data = staged.read_at(0, size).await?;
+            return self.client.put(key, data.into()).await;
+        }
+
+        let part_size = self.config.part_size.max(size.div_ceil(MAX_PARTS));
+        let upload_id = self.client.create_multipart(key).await?;
+        let result = async {
+            let mut etags = Vec::new();
+            let mut offset = 0;
+            while offset < size {
+                let number = u32::try_from(etags.len() + 1).map_err(|_| nfsstat3::NFS3ERR_FBIG)?;
+                let data = staged.read_at(offset, part_size).await?;
+                let etag = self
+                    .client
+                    .upload_part(key, &upload_id, number, data.into());
+                etags.push(etag.await?);
+                offset += part_size;
+            }
+            self.client
+                .complete_multipart(key, &upload_id, &etags)
+                .await
+        }
+        .await;
+        if result.is_err() {
+            if let Err(err) = self.client.abort_multipart(key, &upload_id).await {
+                tracing::warn!("failed to abort the upload of {key}: {err}");
+            }
+        }
+        result
+    }
+
+    /// Uploads the staged copy of a file if it has one
+    async fn flush(&self, id: u64, key: &str) -> Result<(), nfsstat3> {
+        let Some(slot) = self.slot(id) else {
+            return Ok(());
+        };
+        let mut staged = slot.lock().await;
+        if let Some(copy) = staged.as_mut() {
+            self.upload(key, copy).await?;
+            *staged = None;
+        }
+        drop(staged);
+        self.release(id, &slot);
+        Ok(())
+    }
+
+    /// Sets the size of a file and uploads it
+    async fn truncate(&self, id: u64, key: &str, size: u64) -> Result<(), nfsstat3> {
+        let slot = self.slot_or_insert(id);
+        let mut staged = slot.lock().await;
+        let result = async {
+            let copy = self.load(&mut staged, key, Some(size)).await?;
+            copy.set_len(size).await?;
+            self.upload(key, copy).await
+        }
+        .await;
+        if result.is_ok() {
+            *staged = None;
+        }
+        drop(staged);
+        self.release(id, &slot);
+        result
+    }
+
+    async fn staged_attr(&self, id: u64) -> Option<fattr3> {
+        let slot = self.slot(id)?;
+        let staged = slot.lock().await;
+        let copy = staged.as_ref()?;
+        Some(self.file_attr(id, copy.size, copy.mtime))
+    }
+}
+
+impl NfsReadFileSystem for S3Fs {
+    type Handle = FileHandleU64;
+
+    fn root_dir(&self) -> FileHandleU64 {
+        FileHandleU64::new(key_id(&self.root))
+    }
+
+    async fn lookup(
+        &self,
+        dirid: &FileHandleU64,
+        filename: &filename3<'_>,
+    ) -> Result<FileHandleU64, nfsstat3> {
+        let dir = self.dir_key(*dirid)?;
+        match filename.as_ref() {
+            b"." => return Ok(*dirid),
+            b".." => return Ok(self.register(&self.parent(&dir))),
+            _ => {}
+        }
+        let key = child_key(&dir, filename)?;
+        if self.client.head(&key).await?.is_some() {
+            return Ok(self.register(&key));
+        }
+        let key = format!("{key}/");
+        if self.dir_exists(&key).await? {
+            return Ok(self.register(&key));
+        }
+        Err(nfsstat3::NFS3ERR_NOENT)
+    }
+
+    async fn getattr(&self, id: &FileHandleU64) -> Result<fattr3, nfsstat3> {
+        let (key, mtime) = {
+            let nodes = self.nodes.read().expect("lock is poisoned");
+            let node = nodes.get(&id.as_u64()).ok_or(nfsstat3::NFS3ERR_STALE)?;
+            (node.key.clone(), node.mtime)
+        };
+        if self.is_dir(&key) {
+            return Ok(self.dir_attr(id.as_u64(), mtime));
+        }
+        if let Some(attr) = self.staged_attr(id.as_u64()).await {
+            return Ok(attr);
+        }
+        let info = self
+            .client
+            .head(&key)
+            .await?
+            .ok_or(nfsstat3::NFS3ERR_STALE)?;
+        Ok(self.file_attr(id.as_u64(), info.size, info.modified))
+    }
+
+    async fn read(
+        &self,
+        id: &FileHandleU64,
+        offset: u64,
+        count: u32,
+    ) -> Result<(Vec<u8>, bool), nfsstat3> {
+        let key = self.file_key(*id)?;
+        if let Some(slot) = self.slot(id.as_u64()) {
+            let mut staged = slot.lock().await;
+            if let Some(copy) = staged.as_mut() {
+                let data = copy.read_at(offset, u64::from(count)).await?;
+                let eof = offset + data.len() as u64 >= copy.size;
+                return Ok((data, eof));
+            }
+        }
+        if count == 0 {
+            let info = self
+                .client
+                .head(&key)
+                .await?
+                .ok_or(nfsstat3::NFS3ERR_STALE)?;
+            return Ok((Vec::new(), offset >= info.size));
+        }
+        let (data, size) = self
+            .client
+            .get_range(&key, offset, u64::from(count))
+            .await?;
+        let eof = offset + data.len() as u64 >= size;
+        Ok((data.to_vec(), eof))
+    }
+
+    async fn readdirplus(
+        &self,
+        dirid: &FileHandleU64,
+        cookie: u64,
+    ) -> Result<impl ReadDirPlusIterator<FileHandleU64>, nfsstat3> {
+        let dir = self.dir_key(*dirid)?;
+        let start_after = if cookie == 0 {
+            None
+        } else {
+            let nodes = self.nodes.read().expect("lock is poisoned");
+            let node = nodes.get(&cookie).ok_or(nfsstat3::NFS3ERR_BAD_COOKIE)?;
+            if !node.key.starts_with(&dir) || node.key == dir {
+                return Err(nfsstat3::NFS3ERR_BAD_COOKIE);
+            }
+            Some(node.key.clone())
+        };
+        Ok(S3DirIterator::new(self, dir, start_after))
+    }
+
+    async fn readlink(&self, _id: &FileHandleU64) -> Result<nfspath3<'_>, nfsstat3> {
+        Err(nfsstat3::NFS3ERR_NOTSUPP)
+    }
+}
+
+impl NfsFileSystem for S3Fs {
+    async fn setattr(&self, id: &FileHandleU64, setattr: sattr3) -> Result<fattr3, nfsstat3> {
+        let key = self.key(*id)?;
+        let mode = if self.is_dir(&key) {
+            self.config.dir_mode
+        } else {
+            self.config.file_mode
+        };
+        let unchanged = |value: Nfs3Option<u32>, current: u32| match value {
+            Nfs3Option::Some(value) => value == current,
+            Nfs3Option::None => true,
+        };
+        if !unchanged(setattr.mode, mode)
+            || !unchanged(setattr.uid, self.config.uid)
+            || !unchanged(setattr.gid, self.config.gid)
+        {
+            return Err(nfsstat3::NFS3ERR_NOTSUPP);
+        }
+        if let Nfs3Option::Some(size) = setattr.size {
+            let key = self.file_key(*id)?;
+            self.truncate(id.as_u64(), &key, size).await?;
+        }
+        self.getattr(id).await
+    }
+
+    async fn write(
+        &self,
+        id: &FileHandleU64,
+        offset: u64,
+        data: &[u8],
+    ) -> Result<fattr3, nfsstat3> {
+        let key = self.file_key(*id).map_err(|err| match err {
+            nfsstat3::NFS3ERR_ISDIR => nfsstat3::NFS3ERR_INVAL,
+            err => err,
+        })?;
+        let slot = self.slot_or_insert(id.as_u64());
+        let mut staged = slot.lock().await;
+        let copy = self.load(&mut staged, &key, None).await?;
+        copy.write_at(offset, data).await?;
+        Ok(self.file_attr(id.as_u64(), copy.size, copy.mtime))
+    }
+
+    fn buffers_writes(&self) -> bool {
+        true
+    }
+
+    async fn commit(&self, id: &FileHandleU64) -> Result<fattr3, nfsstat3> {
+        let key = self.key(*id)?;
+        if !self.is_dir(&key) {
+            self.flush(id.as_u64(), &key).await?;
+        }
+        self.getattr(id).await
+    }
+
+    async fn create(
+        &self,
+        dirid: &FileHandleU64,
+        filename: &filename3<'_>,
+        attr: sattr3,
+    ) -> Result<(FileHandleU64, fattr3), nfsstat3> {
+        let dir = self.dir_key(*dirid)?;
+        let key = child_key(&dir, filename)?;
+        if self.dir_exists(&format!("{key}/")).await? {
+            return Err(nfsstat3::NFS3ERR_EXIST);
+        }
+        let id = self.register(&key);
+        if self.client.head(&key).await?.is_none() {
+            self.discard(id.as_u64()).await;
+            self.client.put(&key, Bytes::new()).await?;
+            self.touch(*dirid);
+        }
+        // the mode and the owner are fixed, so only the size is applied
+        if let Nfs3Option::Some(size) = attr.size {
+            self.truncate(id.as_u64(), &key, size).await?;
+        }
+        let attr = self.getattr(&id).await?;
+        Ok((id, attr))
+    }
+
+    async fn create_exclusive(
+        &self,
+        _dirid: &FileHandleU64,
+        _filename: &filename3<'_>,
+        _createverf: createverf3,
+    ) -> Result<FileHandleU64, nfsstat3> {
+        Err(nfsstat3::NFS3ERR_NOTSUPP)
+    }
+
+    async fn mkdir(
+        &self,
+        dirid: &FileHandleU64,
+        dirname: &filename3<'_>,
+    ) -> Result<(FileHandleU64, fattr3), nfsstat3> {
+        let dir = self.dir_key(*dirid)?;
+        let key = child_key(&dir, dirname)?;
+        let marker = format!("{key}/");
+        if self.client.head(&key).await?.is_some() || self.dir_exists(&marker).await? {
+            return Err(nfsstat3::NFS3ERR_EXIST);
+        }
+        self.client.put(&marker, Bytes::new()).await?;
+        self.touch(*dirid);
+        let id = self.register(&marker);
+        Ok((id, self.dir_attr(id.as_u64(), now())))
+    }
+
+    async fn remove(
+        &self,
+        dirid: &FileHandleU64,
+        filename: &filename3<'_>,
+    ) -> Result<(), nfsstat3> {
+        let dir = self.dir_key(*dirid)?;
+        let key = child_key(&dir, filename)?;
+        if self.client.head(&key).await?.is_some() {
+            self.discard(key_id(&key)).await;
+            self.client.delete(&key).await?;
+            self.touch(*dirid);
+            return Ok(());
+        }
+
+        let marker = format!("{key}/");
+        let listing = self
+            .client
+            .list(&marker, false, None, None, Some(2))
+            .await?;
+        if listing.objects.is_empty() {
+            return Err(nfsstat3::NFS3ERR_NOENT);
+        }
+        if listing.objects.iter().any(|(key, _)| *key != marker) {
+            return Err(nfsstat3::NFS3ERR_NOTEMPTY);
+        }
+        self.client.delete(&marker).await?;
+        self.nodes
+            .write()
+            .expect("lock is poisoned")
+            .remove(&key_id(&marker));
+        self.touch(*dirid);
+        Ok(())
+    }
+
+    async fn rename<'a>(
+        &self,
+        from_dirid: &FileHandleU64,
+        from_filename: &filename3<'a>,
+        to_dirid: &FileHandleU64,
+        to_filename: &filename3<'a>,
+    ) -> Result<(), nfsstat3> {
+        let from = child_key(&self.dir_key(*from_dirid)?, from_filename)?;
+        let to = child_key(&self.dir_key(*to_dirid)?, to_filename)?;
+        if from == to {
+            return Ok(());
+        }
+
+        if self.client.head(&from).await?.is_some() {
+            if self.dir_exists(&format!("{to}/")).await? {
+                return Err(nfsstat3::NFS3ERR_EXIST);
+            }
+            self.flush(key_id(&from), &from).await?;
+            self.discard(key_id(&to)).await;
+            self.client.copy(&from, &to).await?;
+            self.client.delete(&from).await?;
+        } else {
+            let (from, to) = (format!("{from}/"), format!("{to}/"));
+            if to.starts_with(&from) {
+                return Err(nfsstat3::NFS3ERR_INVAL);
+            }
+            if self.client.head(to.trim_end_matches('/')).await?.is_some() {
+                return Err(nfsstat3::NFS3ERR_EXIST);
+            }
+            let target = self.client.list(&to, false, None, None, Some(2)).await?;
+            if target.objects.iter().any(|(key, _)| *key != to) {
+                return Err(nfsstat3::NFS3ERR_EXIST);
+            }
+
+            // upload the files being written before they are copied
+            let staged = {
+                let nodes = self.nodes.read().expect("lock is poisoned");
+                let staged = self.staged.lock().expect("lock is poisoned");
+                staged
+                    .keys()
+                    .filter_map(|id| Some((*id, nodes.get(id)?.key.clone())))
+                    .filter(|(_, key)| key.starts_with(&from))
+                    .collect::<Vec<_>>()
+            };
+            for (id, key) in staged {
+                self.flush(id, &key).await?;
+            }
+
+            let keys = self.list_all(&from).await?;
+            if keys.is_empty() {
+                return Err(nfsstat3::NFS3ERR_NOENT);
+            }
+            for key in &keys {
+                self.client
+                    .copy(key, &format!("{to}{}", &key[from.len()..]))
+                    .await?;
+            }
+            for key in &keys {
+                self.client.delete(key).await?;
+            }
+        }
+        self.touch(*from_dirid);
+        self.touch(*to_dirid);
+        Ok(())
+    }
+
+    async fn symlink<'a>(
+        &self,
+        _dirid: &FileHandleU64,
+        _linkname: &filename3<'a>,
+        _symlink: &nfspath3<'a>,
+        _attr: &sattr3,
+    ) -> Result<(FileHandleU64, fattr3), nfsstat3> {
+        Err(nfsstat3::NFS3ERR_NOTSUPP)
+    }
+}
+
+/// Lists a directory page by page, with the delimiter `/`
+struct S3DirIterator<'a> {
+    fs: &'a S3Fs,
+    dir: String,
+    start_after: Option<String>,
+    token: Option<String>,
+    done: bool,
+    /// Keys of the current page, with the infos of the objects
+    entries: VecDeque<(String, Option<ObjectInfo>)>,
+    /// Keys without the trailing `/` that were returned, a file hides a directory of the same
+    /// name
+    seen: HashSet<String>,
+}
+
+impl<'a> S3DirIterator<'a> {
+    fn new(fs: &'a S3Fs, dir: String, start_after: Option<String>) -> Self {
+        let seen = start_after
+            .iter()
+            .map(|key| key.trim_end_matches('/').to_string())
+            .collect();
+        Self {
+            fs,
+            dir,
+            start_after,
+            token: None,
+            done: false,
+            entries: VecDeque::new(),
+            seen,
+        }
+    }
+
+    async fn fetch(&mut self) -> Result<(), nfsstat3> {
+        let start_after = if self.token.is_none() {
+            self.start_after.as_deref()
+        } else {
+            None
+        };
+        let listing = self
+            .fs
+            .client
+            .list(&self.dir, true, start_after, self.token.as_deref(), None)
+            .await?;
+        self.token = listing.next;
+        self.done = self.token.is_none();
+
+        let objects = listing
+            .objects
+            .into_iter()
+            .filter(|(key, _)| *key != self.dir)
+            .map(|(key, info)| (key, Some(info)));
+        let prefixes = listing.prefixes.into_iter().map(|key| (key, None));
+        let mut entries = objects.chain(prefixes).collect::<Vec<_>>();
+        entries.sort_by(|a, b| a.0.cmp(&b.0));
+        for (key, info) in entries {
+            if self.seen.insert(key.trim_end_matches('/').to_string()) {
+                self.entries.push_back((key, info));
+            }
+        }
+        Ok(())
+    }
+
+    fn entry(&self, key: &str, info: Option<ObjectInfo>) -> DirEntryPlus<FileHandleU64> {
+        let id = self.fs.register(key);
+        let name = key[self.dir.len()..].trim_end_matches('/');
+        let name_attributes = match info {
+            // the size of a file being written is only known to its staged copy
+            Some(_) if self.fs.slot(id.as_u64()).is_some() => None,
+            Some(info) => Some(self.fs.file_attr(id.as_u64(), info.size, info.modified)),
+            None => {
+                let nodes = self.fs.nodes.read().expect("lock is poisoned");
+                let mtime = nodes.get(&id.as_u64()).map(|node| node.mtime);
+                mtime.map(|mtime| self.fs.dir_attr(id.as_u64(), mtime))
+            }
+        };
+        DirEntryPlus {
+            fileid: id.as_u64(),
+            name: name.as_bytes().to_vec().into(),
+            cookie: id.as_u64(),
+            name_attributes,
+            name_handle: Some(id),
+        }
+    }
+}
+
+impl ReadDirPlusIterator<FileHandleU64> for S3DirIterator<'_> {
+    async fn next(&mut self) -> NextResult<DirEntryPlus<FileHandleU64>> {
+        loop {
+            if let Some((key, info)) = self.entries.pop_front() {
+                return NextResult::Ok(self.entry(&key, info));
+            }
+            if self.done {
+                return NextResult::Eof;
+            }
+            if let Err(err) = self.fetch().await {
+                return NextResult::Err(err);
+            }
+        }
+    }
+}
+
+/// Returns the id of a key, the first 8 bytes of its SHA-256
+fn key_id(key: &str) -> u64 {
+    let hash = Sha256::digest(key.as_bytes());
+    let mut id = [0; 8];
+    id.copy_from_slice(&hash[..8]);
+    // the 0 fileid is reserved
+    u64::from_le_bytes(id).max(1)
+}
+
+fn child_key(dir: &str, name: &filename3<'_>) -> Result<String, nfsstat3> {
+    let name = std::str::from_utf8(name.as_ref()).map_err(|_| nfsstat3::NFS3ERR_INVAL)?;
+    if name.is_empty() || name == "." || name == ".." || name.contains('/') {
+        return Err(nfsstat3::NFS3ERR_INVAL);
+    }
+    Ok(format!("{dir}{name}"))
+}
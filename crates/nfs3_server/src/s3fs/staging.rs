@@ -0,0 +1,99 @@
+//! Local copies of the files being written
+
+use std::io::SeekFrom;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::SystemTime;
+
+use nfs3_types::nfs3::{nfsstat3, nfstime3};
+use tokio::fs::{File, OpenOptions};
+use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
+
+static NEXT_FILE: AtomicU64 = AtomicU64::new(0);
+
+/// A file in the staging directory, removed when it's dropped
+pub(super) struct Staged {
+    file: File,
+    path: PathBuf,
+    pub size: u64,
+    pub mtime: nfstime3,
+}
+
+impl Staged {
+    pub async fn create(dir: &Path) -> Result<Self, nfsstat3> {
+        loop {
+            let number = NEXT_FILE.fetch_add(1, Ordering::Relaxed);
+            let path = dir.join(format!(".nfs3-s3-{}-{number}", std::process::id()));
+            let file = OpenOptions::new()
+                .read(true)
+                .write(true)
+                .create_new(true)
+                .open(&path)
+                .await;
+            match file {
+                Ok(file) => {
+                    return Ok(Self {
+                        file,
+                        path,
+                        size: 0,
+                        mtime: now(),
+                    });
+                }
+                Err(err) if err.kind() == std::io::ErrorKind::AlreadyExists => {}
+                Err(err) => {
+                    tracing::warn!("failed to create {}: {err}", path.display());
+                    return Err(nfsstat3::NFS3ERR_IO);
+                }
+            }
+        }
+    }
+
+    pub async fn write_at(&mut self, offset: u64, data: &[u8]) -> Result<(), nfsstat3> {
+        self.file
+            .seek(SeekFrom::Start(offset))
+            .await
+            .map_err(io_error)?;
+        self.file.write_all(data).await.map_err(io_error)?;
+        self.file.flush().await.map_err(io_error)?;
+        self.size = self.size.max(offset + data.len() as u64);
+        self.mtime = now();
+        Ok(())
+    }
+
+    /// Reads up to `len` bytes at `offset`
+    pub async fn read_at(&mut self, offset: u64, len: u64) -> Result<Vec<u8>, nfsstat3> {
+        let len = len.min(self.size.saturating_sub(offset));
+        let mut data = vec![0; usize::try_from(len).map_err(|_| nfsstat3::NFS3ERR_FBIG)?];
+        self.file
+            .seek(SeekFrom::Start(offset))
+            .await
+            .map_err(io_error)?;
+        self.file.read_exact(&mut data).await.map_err(io_error)?;
+        Ok(data)
+    }
+
+    pub async fn set_len(&mut self, size: u64) -> Result<(), nfsstat3> {
+        self.file.set_len(size).await.map_err(io_error)?;
+        self.size = size;
+        self.mtime = now();
+        Ok(())
+    }
+}
+
+impl Drop for Staged {
+    fn drop(&mut self) {
+        if let Err(err) = std::fs::remove_file(&self.path) {
+            tracing::warn!("failed to remove {}: {err}", self.path.display());
+        }
+    }
+}
+
+pub(super) fn now() -> nfstime3 {
+    nfstime3::try_from(SystemTime::now()).unwrap_or_default()
+}
+
+#[allow(clippy::needless_pass_by_value)]
+fn io_error(err: std::io::Error) -> nfsstat3 {
+    tracing::warn!("staging failed: {err}");
+    nfsstat3::NFS3ERR_IO
+}
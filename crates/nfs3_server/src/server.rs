@@ -0,0 +1,367 @@
+//! Transport independent NFS server
+//!
+//! [`NFSServer`] holds the file system and the settings shared by all connections.
+//! It can serve a single connection on any stream with [`NFSServer::serve_connection`],
+//! which allows running the server behind a proxy or over a custom transport.
+//! Listeners such as `NFSTcpListener` are built on top of it.
+//!
+//! The server doesn't depend on a specific async runtime. Tasks are spawned with the
+//! [`Runtime`] set by [`NFSServer::with_runtime`], which defaults to Tokio if the `tokio`
+//! feature is enabled and to smol otherwise.
+
+use std::io;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+use tokio::io::AsyncWriteExt;
+use tokio::sync::mpsc;
+use tracing::{debug, error};
+
+use crate::context::RPCContext;
+pub use crate::fsinfo::FsInfoConfig;
+use crate::fsinfo::FsInfoState;
+use crate::io::{AsyncRead, AsyncWrite};
+use crate::lock_manager::LockManager;
+use crate::nfs2_cookies::Nfs2Cookies;
+#[cfg(feature = "nfs4")]
+use crate::nfs4_state::StateManager;
+use crate::rpcwire::{SocketMessageHandler, write_fragment};
+use crate::runtime::Runtime;
+use crate::transaction_tracker::{Cleaner, TransactionTracker};
+use crate::units::KIBIBYTE;
+use crate::vfs::adapters::ReadOnlyAdapter;
+use crate::vfs::handle::FileHandleConverter;
+use crate::vfs::{NfsFileSystem, NfsReadFileSystem};
+
+/// A NFS server that isn't bound to any transport
+pub struct NFSServer<T: NfsFileSystem + 'static> {
+    arcfs: Arc<T>,
+    local_port: u16,
+    mount_signal: Option<mpsc::Sender<bool>>,
+    export_name: Arc<String>,
+    transaction_tracker: Arc<TransactionTracker>,
+    file_handle_converter: FileHandleConverter,
+    handle_secret: Option<Vec<u8>>,
+    fsinfo: Arc<FsInfoState>,
+    lock_manager: Arc<LockManager>,
+    nfs2_cookies: Arc<Nfs2Cookies>,
+    #[cfg(feature = "nfs4")]
+    nfs4_state: Arc<StateManager>,
+    runtime: Option<Arc<dyn Runtime>>,
+    cleaner_started: AtomicBool,
+    stop_notify: Arc<tokio::sync::Notify>,
+}
+
+impl<T: NfsFileSystem + 'static> Drop for NFSServer<T> {
+    fn drop(&mut self) {
+        self.stop_notify.notify_waiters();
+    }
+}
+
+impl<RO> NFSServer<ReadOnlyAdapter<RO>>
+where
+    RO: NfsReadFileSystem + 'static,
+{
+    /// Create a new `NFSServer` with a read-only file system.
+    pub fn new_ro(fs: RO) -> Self {
+        Self::new(ReadOnlyAdapter::new(fs))
+    }
+}
+
+impl<T: NfsFileSystem + 'static> NFSServer<T> {
+    /// Create a new `NFSServer`.
+    ///
+    /// `fs` is an instance of an implementation of [`NfsFileSystem`].
+    pub fn new(fs: T) -> Self {
+        Self::from_arc(Arc::new(fs))
+    }
+
+    pub(crate) fn from_arc(arcfs: Arc<T>) -> Self {
+        Self {
+            arcfs,
+            local_port: 0,
+            mount_signal: None,
+            export_name: Arc::from("/".to_string()),
+            transaction_tracker: Self::new_transaction_tracker(),
+            file_handle_converter: FileHandleConverter::new(),
+            handle_secret: None,
+            fsinfo: Arc::default(),
+            lock_manager: Arc::default(),
+            nfs2_cookies: Arc::default(),
+            #[cfg(feature = "nfs4")]
+            nfs4_state: Arc::default(),
+            runtime: crate::runtime::default_runtime(),
+            cleaner_started: AtomicBool::new(false),
+            stop_notify: Arc::new(tokio::sync::Notify::new()),
+        }
+    }
+
+    fn new_transaction_tracker() -> Arc<TransactionTracker> {
+        const TRANSACTION_LIFETIME: Duration = Duration::from_secs(60);
+        const MAX_ACTIVE_TRANSACTIONS: u16 = 256;
+        const TRANSACTION_TRIM_THRESHOLD: usize = 2048;
+
+        Arc::new(TransactionTracker::new(
+            TRANSACTION_LIFETIME,
+            MAX_ACTIVE_TRANSACTIONS,
+            TRANSACTION_TRIM_THRESHOLD,
+        ))
+    }
+
+    /// Sets the port returned by the portmapper `GETPORT` call.
+    ///
+    /// Listeners set it to the port they are bound to. Defaults to 0.
+    pub const fn set_local_port(&mut self, port: u16) {
+        self.local_port = port;
+    }
+
+    /// Sets a mount listener. A "true" signal will be sent on a mount
+    /// and a "false" will be sent on an unmount
+    pub fn set_mount_listener(&mut self, signal: mpsc::Sender<bool>) {
+        self.mount_signal = Some(signal);
+    }
+
+    /// Sets an optional NFS export name.
+    ///
+    /// - `export_name`: The desired export name without slashes.
+    ///
+    /// Example: Name `foo` results in the export path `/foo`.
+    /// Default path is `/` if not set.
+    pub fn with_export_name<S: AsRef<str>>(&mut self, export_name: S) {
+        self.export_name = Arc::new(format!(
+            "/{}",
+            export_name
+                .as_ref()
+                .trim_end_matches('/')
+                .trim_start_matches('/')
+        ));
+        self.update_handle_auth();
+    }
+
+    /// Sets a fixed server instance id.
+    ///
+    /// The instance id is embedded into every file handle. By default it's derived from
+    /// the server startup time, so all handles become stale (`NFS3ERR_STALE`) after a restart
+    /// and clients have to remount. With a persisted instance id, clients survive restarts
+    /// transparently as long as the file system returns the same handles for the same objects.
+    ///
+    /// Handles issued with a smaller instance id are reported as stale.
+    pub const fn with_instance_id(&mut self, instance_id: u64) {
+        self.file_handle_converter.set_instance_id(instance_id);
+    }
+
+    /// Enables file handle authentication.
+    ///
+    /// Every handle sent to clients is extended with a truncated HMAC-SHA256 over the
+    /// handle bytes and the export name, keyed by `secret`. Handles that were not issued
+    /// by this server, or were modified by the client, are rejected with `NFS3ERR_BADHANDLE`.
+    /// This prevents clients from guessing handles of objects they never looked up.
+    ///
+    /// The tag takes 8 bytes of the handle, so the file system handles must not be longer
+    /// than 48 bytes. Use the same secret after a restart to keep issued handles valid.
+    pub fn with_handle_secret(&mut self, secret: impl AsRef<[u8]>) {
+        self.handle_secret = Some(secret.as_ref().to_vec());
+        self.update_handle_auth();
+    }
+
+    /// Overrides the values the file system reports in FSINFO, such as the maximum
+    /// READ and WRITE sizes. See [`FsInfoConfig`] for details.
+    ///
+    /// # Errors
+    ///
+    /// Returns `InvalidInput` if one of the transfer sizes is zero.
+    pub fn with_fsinfo(&mut self, config: FsInfoConfig) -> io::Result<()> {
+        self.fsinfo = Arc::new(FsInfoState::new(config)?);
+        Ok(())
+    }
+
+    /// Restricts clients to the subtree they mounted.
+    ///
+    /// By default a client that mounted a subdirectory of the export can still walk up with
+    /// `LOOKUP ..` and use handles of objects outside of it. In subtree check mode every handle
+    /// remembers the mount it was reached from: `..` at the mount root is rejected with
+    /// `NFS3ERR_ACCES`, handles of other mounts can't be forged, and RENAME between two mounts
+    /// fails with `NFS3ERR_XDEV`. Handles of a mount of the whole export still cover everything.
+    ///
+    /// The mode requires signed handles. If no secret was set with
+    /// [`Self::with_handle_secret`], a random one is generated, so handles don't survive
+    /// a restart.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the random secret can't be generated.
+    pub fn with_subtree_check(&mut self) {
+        self.file_handle_converter.set_subtree_check(true);
+        if self.handle_secret.is_none() {
+            let mut secret = vec![0; 32];
+            getrandom::fill(&mut secret).expect("failed to generate handle secret");
+            self.handle_secret = Some(secret);
+        }
+        self.update_handle_auth();
+    }
+
+    /// Sets the runtime used to spawn the tasks of the server.
+    ///
+    /// Defaults to `TokioRuntime` if the `tokio` feature is enabled, otherwise to `SmolRuntime`
+    /// with smol's global executor. Without either feature it must be set before serving
+    /// connections.
+    pub fn with_runtime(&mut self, runtime: impl Runtime) {
+        self.runtime = Some(Arc::new(runtime));
+    }
+
+    /// Serves a single established connection until it's closed.
+    ///
+    /// `stream` can be any bidirectional byte stream carrying RPC record marking, e.g.
+    /// a Unix domain socket, a pipe, or a connection accepted by a proxy. `client_addr`
+    /// identifies the client in logs and in retransmission detection, so it should be
+    /// unique for every client.
+    ///
+    /// Tokio and smol streams can be wrapped with `TokioIo` and `SmolIo`.
+    ///
+    /// The returned future doesn't borrow the server, so it can be spawned as a task.
+    ///
+    /// # Errors
+    ///
+    /// The future fails if no runtime is configured, see [`NFSServer::with_runtime`].
+    pub fn serve_connection<IO>(
+        &self,
+        stream: IO,
+        client_addr: impl Into<String>,
+    ) -> impl Future<Output = io::Result<()>> + Send + 'static
+    where
+        IO: AsyncRead + AsyncWrite + 'static,
+    {
+        let context = self.connection_context(client_addr.into());
+        let cleaner = context.as_ref().ok().and_then(|_| self.take_cleaner());
+        async move {
+            let context = context?;
+            if let Some(cleaner) = cleaner {
+                context.runtime.spawn(Box::pin(cleaner.run()));
+            }
+            process_socket(stream, context)
+                .await
+                .map_err(|e| e.downcast::<io::Error>().unwrap_or_else(io::Error::other))
+        }
+    }
+
+    /// Returns the runtime that spawns the tasks of the server
+    pub(crate) fn runtime(&self) -> io::Result<&Arc<dyn Runtime>> {
+        self.runtime
+            .as_ref()
+            .ok_or_else(crate::runtime::missing_runtime)
+    }
+
+    /// Starts the task that expires old entries of the transaction tracker
+    #[cfg(feature = "tokio")]
+    pub(crate) fn spawn_cleaner(&self) -> io::Result<()> {
+        let runtime = self.runtime()?;
+        if let Some(cleaner) = self.take_cleaner() {
+            runtime.spawn(Box::pin(cleaner.run()));
+        }
+        Ok(())
+    }
+
+    /// Returns the cleaner of the transaction tracker, if it wasn't started yet
+    fn take_cleaner(&self) -> Option<Cleaner> {
+        let runtime = self.runtime.as_ref()?;
+        if self.cleaner_started.swap(true, Ordering::AcqRel) {
+            return None;
+        }
+        let cleaner = Cleaner::new(
+            self.transaction_tracker.clone(),
+            Duration::from_secs(10),
+            Arc::clone(&self.stop_notify),
+            Arc::clone(runtime),
+        );
+        #[cfg(feature = "nfs4")]
+        let cleaner = cleaner.with_nfs4_state(Arc::clone(&self.nfs4_state));
+        Some(cleaner)
+    }
+
+    /// Creates the context for a new connection
+    ///
+    /// Fails if no runtime is configured.
+    pub(crate) fn connection_context(&self, client_addr: String) -> io::Result<RPCContext<T>> {
+        Ok(RPCContext {
+            local_port: self.local_port,
+            client_addr,
+            auth: nfs3_types::rpc::auth_unix::default(),
+            vfs: self.arcfs.clone(),
+            mount_signal: self.mount_signal.clone(),
+            export_name: self.export_name.clone(),
+            transaction_tracker: self.transaction_tracker.clone(),
+            file_handle_converter: self.file_handle_converter.clone(),
+            fsinfo: Arc::clone(&self.fsinfo),
+            lock_manager: Arc::clone(&self.lock_manager),
+            nfs2_cookies: Arc::clone(&self.nfs2_cookies),
+            #[cfg(feature = "nfs4")]
+            nfs4_state: Arc::clone(&self.nfs4_state),
+            peer_auth: None,
+            runtime: Arc::clone(self.runtime()?),
+        })
+    }
+
+    fn update_handle_auth(&mut self) {
+        self.file_handle_converter
+            .set_secret(self.handle_secret.as_deref(), &self.export_name);
+    }
+}
+
+/// processes an established socket
+pub(crate) async fn process_socket<IO, T>(
+    mut socket: IO,
+    context: RPCContext<T>,
+) -> Result<(), anyhow::Error>
+where
+    IO: AsyncRead + AsyncWrite + 'static,
+    T: NfsFileSystem + 'static,
+{
+    let (mut message_handler, mut socksend, mut msgrecvchan) =
+        SocketMessageHandler::new(context.clone());
+
+    context.runtime.spawn(Box::pin(async move {
+        loop {
+            if let Err(e) = message_handler.read().await {
+                debug!("Message loop broken due to {e}");
+                break;
+            }
+        }
+    }));
+    let mut buf = vec![0u8; 128 * KIBIBYTE as usize].into_boxed_slice();
+    loop {
+        tokio::select! {
+            result = socket.async_read(&mut buf) => {
+                match result {
+                    Ok(0) => {
+                        return Ok(());
+                    }
+                    Ok(n) => {
+                        let _ = socksend.write_all(&buf[..n]).await;
+                    }
+                    Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {}
+                    Err(e) => {
+                        debug!("Message handling closed : {e}");
+                        return Err(e.into());
+                    }
+                }
+            },
+            reply = msgrecvchan.recv() => {
+                match reply {
+                    Some(Err(e)) => {
+                        debug!("Message handling closed : {e}");
+                        return Err(e);
+                    }
+                    Some(Ok(msg)) => {
+                        if let Err(e) = write_fragment(&mut socket, msg).await {
+                            error!("Write error {e}");
+                        }
+                    }
+                    None => {
+                        return Err(anyhow::anyhow!("Unexpected socket context termination"));
+                    }
+                }
+            }
+        }
+    }
+}
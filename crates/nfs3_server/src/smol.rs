@@ -0,0 +1,90 @@
+//! Provides wrappers for smol's types
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use smol::io::{AsyncRead as SmolAsyncRead, AsyncWrite as SmolAsyncWrite};
+
+use crate::io::{AsyncRead, AsyncWrite};
+use crate::runtime::{BoxFuture, Runtime};
+
+/// Wrapper for Smol types
+///
+/// Wraps a Smol's [`AsyncRead`](SmolAsyncRead) and [`AsyncWrite`](SmolAsyncWrite) implementor
+/// to provide an [`AsyncRead`] and [`AsyncWrite`] implementation.
+pub struct SmolIo<T>(T);
+
+impl<T> SmolIo<T> {
+    pub const fn new(inner: T) -> Self {
+        Self(inner)
+    }
+
+    /// Returns the wrapped value
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T> AsyncRead for SmolIo<T>
+where
+    T: SmolAsyncRead + Unpin + Send,
+{
+    async fn async_read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        smol::io::AsyncReadExt::read(&mut self.0, buf).await
+    }
+}
+
+impl<T> AsyncWrite for SmolIo<T>
+where
+    T: SmolAsyncWrite + Unpin + Send,
+{
+    async fn async_write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        smol::io::AsyncWriteExt::write(&mut self.0, buf).await
+    }
+
+    async fn async_write_all(&mut self, buf: &[u8]) -> std::io::Result<()> {
+        smol::io::AsyncWriteExt::write_all(&mut self.0, buf).await
+    }
+}
+
+/// Runtime for smol
+///
+/// By default tasks are spawned on smol's global executor. An application that runs its own
+/// executor can pass it with [`SmolRuntime::with_executor`].
+#[derive(Debug, Default, Clone)]
+pub struct SmolRuntime {
+    executor: Option<Arc<smol::Executor<'static>>>,
+}
+
+impl SmolRuntime {
+    /// Creates a runtime that uses smol's global executor
+    #[must_use]
+    pub const fn new() -> Self {
+        Self { executor: None }
+    }
+
+    /// Creates a runtime that spawns tasks on `executor`
+    ///
+    /// The application is responsible for running the executor.
+    #[must_use]
+    pub const fn with_executor(executor: Arc<smol::Executor<'static>>) -> Self {
+        Self {
+            executor: Some(executor),
+        }
+    }
+}
+
+impl Runtime for SmolRuntime {
+    fn spawn(&self, task: BoxFuture<()>) {
+        match &self.executor {
+            Some(executor) => executor.spawn(task).detach(),
+            None => smol::spawn(task).detach(),
+        }
+    }
+
+    fn sleep(&self, duration: Duration) -> BoxFuture<()> {
+        Box::pin(async move {
+            smol::Timer::after(duration).await;
+        })
+    }
+}
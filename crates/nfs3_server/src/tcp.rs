@@ -1,37 +1,21 @@
 use std::io;
 use std::net::{IpAddr, SocketAddr};
 use std::sync::Arc;
-use std::time::Duration;
 
-use anyhow;
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
 use tokio::net::TcpListener;
 use tokio::sync::mpsc;
-use tracing::{debug, error, info};
+use tracing::{debug, info};
 
-use crate::context::RPCContext;
-use crate::rpcwire::{SocketMessageHandler, write_fragment};
-use crate::transaction_tracker::{Cleaner, TransactionTracker};
-use crate::units::KIBIBYTE;
+pub use crate::fsinfo::FsInfoConfig;
+use crate::server::{NFSServer, process_socket};
+use crate::tokio::TokioIo;
 use crate::vfs::adapters::ReadOnlyAdapter;
 use crate::vfs::{NfsFileSystem, NfsReadFileSystem};
 
 /// A NFS Tcp Connection Handler
 pub struct NFSTcpListener<T: NfsFileSystem + 'static> {
-    listener: TcpListener,
-    port: u16,
-    arcfs: Arc<T>,
-    mount_signal: Option<mpsc::Sender<bool>>,
-    export_name: Arc<String>,
-    transaction_tracker: Arc<TransactionTracker>,
-    file_handle_converter: crate::vfs::handle::FileHandleConverter,
-    stop_notify: Arc<tokio::sync::Notify>,
-}
-
-impl<T: NfsFileSystem + 'static> Drop for NFSTcpListener<T> {
-    fn drop(&mut self) {
-        self.stop_notify.notify_waiters();
-    }
+    pub(crate) listener: TcpListener,
+    pub(crate) server: NFSServer<T>,
 }
 
 #[must_use]
@@ -43,64 +27,6 @@ pub fn generate_host_ip(hostnum: u16) -> String {
     )
 }
 
-/// processes an established socket
-pub(crate) async fn process_socket<IO, T>(
-    mut socket: IO,
-    context: RPCContext<T>,
-) -> Result<(), anyhow::Error>
-where
-    IO: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin + 'static,
-    T: NfsFileSystem + 'static,
-{
-    let (mut message_handler, mut socksend, mut msgrecvchan) =
-        SocketMessageHandler::new(context.clone());
-
-    tokio::spawn(async move {
-        loop {
-            if let Err(e) = message_handler.read().await {
-                debug!("Message loop broken due to {e}");
-                break;
-            }
-        }
-    });
-    let mut buf = vec![0u8; 128 * KIBIBYTE as usize].into_boxed_slice();
-    loop {
-        tokio::select! {
-            result = socket.read(&mut buf) => {
-                match result {
-                    Ok(0) => {
-                        return Ok(());
-                    }
-                    Ok(n) => {
-                        let _ = socksend.write_all(&buf[..n]).await;
-                    }
-                    Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {}
-                    Err(e) => {
-                        debug!("Message handling closed : {e}");
-                        return Err(e.into());
-                    }
-                }
-            },
-            reply = msgrecvchan.recv() => {
-                match reply {
-                    Some(Err(e)) => {
-                        debug!("Message handling closed : {e}");
-                        return Err(e);
-                    }
-                    Some(Ok(msg)) => {
-                        if let Err(e) = write_fragment(&mut socket, msg).await {
-                            error!("Write error {e}");
-                        }
-                    }
-                    None => {
-                        return Err(anyhow::anyhow!("Unexpected socket context termination"));
-                    }
-                }
-            }
-        }
-    }
-}
-
 pub trait NFSTcp: Send + Sync {
     /// Gets the true listening port. Useful if the bound port number is 0
     fn get_listen_port(&self) -> u16;
@@ -188,28 +114,9 @@ impl<T: NfsFileSystem + 'static> NFSTcpListener<T> {
             SocketAddr::V4(s) => s.port(),
             SocketAddr::V6(s) => s.port(),
         };
-        Ok(Self {
-            listener,
-            port,
-            arcfs,
-            mount_signal: None,
-            export_name: Arc::from("/".to_string()),
-            transaction_tracker: Self::new_transaction_tracker(),
-            stop_notify: Arc::new(tokio::sync::Notify::new()),
-            file_handle_converter: crate::vfs::handle::FileHandleConverter::new(),
-        })
-    }
-
-    fn new_transaction_tracker() -> Arc<TransactionTracker> {
-        const TRANSACTION_LIFETIME: Duration = Duration::from_secs(60);
-        const MAX_ACTIVE_TRANSACTIONS: u16 = 256;
-        const TRANSACTION_TRIM_THRESHOLD: usize = 2048;
-
-        Arc::new(TransactionTracker::new(
-            TRANSACTION_LIFETIME,
-            MAX_ACTIVE_TRANSACTIONS,
-            TRANSACTION_TRIM_THRESHOLD,
-        ))
+        let mut server = NFSServer::from_arc(arcfs);
+        server.set_local_port(port);
+        Ok(Self { listener, server })
     }
 
     /// Sets an optional NFS export name.
@@ -219,13 +126,37 @@ impl<T: NfsFileSystem + 'static> NFSTcpListener<T> {
     /// Example: Name `foo` results in the export path `/foo`.
     /// Default path is `/` if not set.
     pub fn with_export_name<S: AsRef<str>>(&mut self, export_name: S) {
-        self.export_name = Arc::new(format!(
-            "/{}",
-            export_name
-                .as_ref()
-                .trim_end_matches('/')
-                .trim_start_matches('/')
-        ));
+        self.server.with_export_name(export_name);
+    }
+
+    /// Sets a fixed server instance id. See [`NFSServer::with_instance_id`].
+    pub const fn with_instance_id(&mut self, instance_id: u64) {
+        self.server.with_instance_id(instance_id);
+    }
+
+    /// Enables file handle authentication. See [`NFSServer::with_handle_secret`].
+    pub fn with_handle_secret(&mut self, secret: impl AsRef<[u8]>) {
+        self.server.with_handle_secret(secret);
+    }
+
+    /// Overrides the values the file system reports in FSINFO, such as the maximum
+    /// READ and WRITE sizes. See [`FsInfoConfig`] for details.
+    ///
+    /// # Errors
+    ///
+    /// Returns `InvalidInput` if one of the transfer sizes is zero.
+    pub fn with_fsinfo(&mut self, config: FsInfoConfig) -> io::Result<()> {
+        self.server.with_fsinfo(config)
+    }
+
+    /// Restricts clients to the subtree they mounted. See [`NFSServer::with_subtree_check`].
+    pub fn with_subtree_check(&mut self) {
+        self.server.with_subtree_check();
+    }
+
+    /// Returns the server that handles the accepted connections
+    pub const fn server(&self) -> &NFSServer<T> {
+        &self.server
     }
 }
 
@@ -250,40 +181,28 @@ impl<T: NfsFileSystem + 'static> NFSTcp for NFSTcpListener<T> {
     /// Sets a mount listener. A "true" signal will be sent on a mount
     /// and a "false" will be sent on an unmount
     fn set_mount_listener(&mut self, signal: mpsc::Sender<bool>) {
-        self.mount_signal = Some(signal);
+        self.server.set_mount_listener(signal);
     }
 
     /// Loops forever and never returns handling all incoming connections.
     async fn handle_forever(&self) -> io::Result<()> {
-        let cleaner_future = Cleaner::new(
-            self.transaction_tracker.clone(),
-            Duration::from_secs(10),
-            Arc::clone(&self.stop_notify),
-        )
-        .run();
-        tokio::spawn(cleaner_future);
+        self.server.spawn_cleaner()?;
 
         loop {
             let (socket, _) = self.listener.accept().await?;
-            let context = RPCContext {
-                local_port: self.port,
-                client_addr: socket
+            let context = self.server.connection_context(
+                socket
                     .peer_addr()
                     .expect("failed to get peer address")
                     .to_string(),
-                auth: nfs3_types::rpc::auth_unix::default(),
-                vfs: self.arcfs.clone(),
-                mount_signal: self.mount_signal.clone(),
-                export_name: self.export_name.clone(),
-                transaction_tracker: self.transaction_tracker.clone(),
-                file_handle_converter: self.file_handle_converter,
-            };
+            )?;
             info!("Accepting connection from {}", context.client_addr);
             debug!("Accepting socket {:?} {:?}", socket, context);
-            tokio::spawn(async move {
+            let runtime = Arc::clone(&context.runtime);
+            runtime.spawn(Box::pin(async move {
                 let _ = socket.set_nodelay(true);
-                let _ = process_socket(socket, context).await;
-            });
+                let _ = process_socket(TokioIo::new(socket), context).await;
+            }));
         }
     }
 }
@@ -0,0 +1,187 @@
+//! RPC-with-TLS (RFC 9289) support
+//!
+//! A client that wants an encrypted connection sends a NULL call with `AUTH_TLS`
+//! credentials first. The server answers with the `STARTTLS` verifier, and both sides
+//! run a TLS handshake on the same connection. All following RPC messages are sent
+//! over TLS.
+//!
+//! [`NFSTlsListener`] accepts only such connections. Optionally, clients can be
+//! authenticated with certificates, and the certificate can be mapped to the identity
+//! the server uses for all the calls of the connection.
+
+use std::io;
+use std::net::IpAddr;
+use std::sync::Arc;
+
+use nfs3_types::rpc::{auth_stat, auth_unix};
+use tokio::net::TcpStream;
+use tokio::sync::mpsc;
+use tokio_rustls::TlsAcceptor;
+pub use tokio_rustls::rustls;
+use tokio_rustls::rustls::ServerConfig;
+use tokio_rustls::rustls::pki_types::CertificateDer;
+use tracing::{debug, info, warn};
+
+use crate::context::RPCContext;
+use crate::rpcwire::messages::{HandleResult, IncomingRpcMessage, PackedRpcMessage};
+use crate::rpcwire::write_fragment;
+use crate::server::process_socket;
+use crate::tcp::{NFSTcp, NFSTcpListener};
+use crate::tokio::TokioIo;
+use crate::vfs::NfsFileSystem;
+
+/// Maps a client certificate to the identity used for the calls of the connection.
+/// Returning `None` rejects the client.
+pub type IdentityMapper = dyn Fn(&CertificateDer<'_>) -> Option<auth_unix> + Send + Sync;
+
+/// TLS settings of [`NFSTlsListener`]
+#[derive(Clone)]
+pub struct TlsConfig {
+    server_config: Arc<ServerConfig>,
+    identity_mapper: Option<Arc<IdentityMapper>>,
+}
+
+impl TlsConfig {
+    /// Creates a new TLS configuration.
+    ///
+    /// To require client certificates, configure a client certificate verifier in
+    /// `server_config`.
+    #[must_use]
+    pub const fn new(server_config: Arc<ServerConfig>) -> Self {
+        Self {
+            server_config,
+            identity_mapper: None,
+        }
+    }
+
+    /// Sets a function that maps the end-entity certificate of a client to an identity.
+    ///
+    /// The identity replaces the credentials the client sends with every call. Clients that
+    /// present a certificate the function returns `None` for are disconnected. Clients without
+    /// a certificate (if the verifier allows them) keep using their own credentials.
+    #[must_use]
+    pub fn with_identity_mapper(
+        mut self,
+        mapper: impl Fn(&CertificateDer<'_>) -> Option<auth_unix> + Send + Sync + 'static,
+    ) -> Self {
+        self.identity_mapper = Some(Arc::new(mapper));
+        self
+    }
+}
+
+impl std::fmt::Debug for TlsConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TlsConfig")
+            .field("server_config", &self.server_config)
+            .field("identity_mapper", &self.identity_mapper.is_some())
+            .finish()
+    }
+}
+
+/// A NFS connection handler that serves clients over RPC-with-TLS
+///
+/// It's configured as a regular [`NFSTcpListener`], which is then wrapped with
+/// [`NFSTlsListener::new`]. Clients that don't start TLS are rejected with `AUTH_TOOWEAK`.
+pub struct NFSTlsListener<T: NfsFileSystem + 'static> {
+    inner: NFSTcpListener<T>,
+    acceptor: TlsAcceptor,
+    identity_mapper: Option<Arc<IdentityMapper>>,
+}
+
+impl<T: NfsFileSystem + 'static> NFSTlsListener<T> {
+    /// Wraps a bound listener to serve RPC-with-TLS clients
+    pub fn new(listener: NFSTcpListener<T>, config: TlsConfig) -> Self {
+        Self {
+            inner: listener,
+            acceptor: TlsAcceptor::from(config.server_config),
+            identity_mapper: config.identity_mapper,
+        }
+    }
+}
+
+impl<T: NfsFileSystem + 'static> NFSTcp for NFSTlsListener<T> {
+    fn get_listen_port(&self) -> u16 {
+        self.inner.get_listen_port()
+    }
+
+    fn get_listen_ip(&self) -> IpAddr {
+        self.inner.get_listen_ip()
+    }
+
+    fn set_mount_listener(&mut self, signal: mpsc::Sender<bool>) {
+        self.inner.set_mount_listener(signal);
+    }
+
+    async fn handle_forever(&self) -> io::Result<()> {
+        self.inner.server.spawn_cleaner()?;
+
+        loop {
+            let (socket, peer_addr) = self.inner.listener.accept().await?;
+            let context = self
+                .inner
+                .server
+                .connection_context(peer_addr.to_string())?;
+            info!("Accepting TLS connection from {}", context.client_addr);
+            let acceptor = self.acceptor.clone();
+            let identity_mapper = self.identity_mapper.clone();
+            let runtime = Arc::clone(&context.runtime);
+            runtime.spawn(Box::pin(async move {
+                let _ = socket.set_nodelay(true);
+                let client_addr = context.client_addr.clone();
+                if let Err(e) = serve_tls(socket, context, acceptor, identity_mapper).await {
+                    debug!("TLS connection from {client_addr} closed: {e}");
+                }
+            }));
+        }
+    }
+}
+
+/// Performs the STARTTLS exchange and serves the connection over TLS
+async fn serve_tls<T>(
+    mut socket: TcpStream,
+    mut context: RPCContext<T>,
+    acceptor: TlsAcceptor,
+    identity_mapper: Option<Arc<IdentityMapper>>,
+) -> anyhow::Result<()>
+where
+    T: NfsFileSystem + 'static,
+{
+    let mut probe = PackedRpcMessage::new();
+    while !probe.recv(&mut socket).await? {}
+    let PackedRpcMessage::Complete(probe) = probe else {
+        unreachable!("the message is complete");
+    };
+    let probe = IncomingRpcMessage::try_from(probe)?;
+
+    if !probe.is_tls_probe() {
+        warn!(
+            "{} sent a call without starting TLS, closing connection",
+            context.client_addr
+        );
+        if let HandleResult::Reply(reply) = probe.into_auth_error(auth_stat::AUTH_TOOWEAK)? {
+            write_fragment(&mut TokioIo::new(&mut socket), reply).await?;
+        }
+        return Ok(());
+    }
+    if let HandleResult::Reply(reply) = probe.into_starttls_reply()? {
+        write_fragment(&mut TokioIo::new(&mut socket), reply).await?;
+    }
+
+    let stream = acceptor.accept(socket).await?;
+    if let Some(mapper) = identity_mapper {
+        let (_, connection) = stream.get_ref();
+        if let Some(cert) = connection.peer_certificates().and_then(<[_]>::first) {
+            let Some(auth) = mapper(cert) else {
+                warn!(
+                    "no identity for the certificate of {}, closing connection",
+                    context.client_addr
+                );
+                return Ok(());
+            };
+            debug!("{} is authenticated as {auth:?}", context.client_addr);
+            context.peer_auth = Some(Arc::new(auth));
+        }
+    }
+
+    process_socket(TokioIo::new(stream), context).await
+}
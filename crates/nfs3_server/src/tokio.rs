@@ -0,0 +1,63 @@
+//! Provides wrappers for tokio's types
+
+use std::time::Duration;
+
+use tokio::io::{AsyncRead as TokioAsyncRead, AsyncWrite as TokioAsyncWrite};
+
+use crate::io::{AsyncRead, AsyncWrite};
+use crate::runtime::{BoxFuture, Runtime};
+
+/// Wrapper for Tokio types
+///
+/// Wraps a Tokio's [`AsyncRead`](TokioAsyncRead) and [`AsyncWrite`](TokioAsyncWrite) implementor
+/// to provide an [`AsyncRead`] and [`AsyncWrite`] implementation.
+pub struct TokioIo<T>(T);
+
+impl<T> TokioIo<T> {
+    pub const fn new(inner: T) -> Self {
+        Self(inner)
+    }
+
+    /// Returns the wrapped value
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T> AsyncRead for TokioIo<T>
+where
+    T: TokioAsyncRead + Unpin + Send,
+{
+    async fn async_read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        tokio::io::AsyncReadExt::read(&mut self.0, buf).await
+    }
+}
+
+impl<T> AsyncWrite for TokioIo<T>
+where
+    T: TokioAsyncWrite + Unpin + Send,
+{
+    async fn async_write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        tokio::io::AsyncWriteExt::write(&mut self.0, buf).await
+    }
+
+    async fn async_write_all(&mut self, buf: &[u8]) -> std::io::Result<()> {
+        tokio::io::AsyncWriteExt::write_all(&mut self.0, buf).await
+    }
+}
+
+/// Runtime for Tokio
+///
+/// Spawns tasks with [`tokio::spawn`], so the server must be used within a Tokio runtime.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct TokioRuntime;
+
+impl Runtime for TokioRuntime {
+    fn spawn(&self, task: BoxFuture<()>) {
+        tokio::spawn(task);
+    }
+
+    fn sleep(&self, duration: Duration) -> BoxFuture<()> {
+        Box::pin(tokio::time::sleep(duration))
+    }
+}
@@ -2,6 +2,8 @@ use std::collections::{HashMap, VecDeque};
 use std::sync::{Arc, Mutex, RwLock};
 use std::time::{Duration, Instant};
 
+use crate::runtime::Runtime;
+
 /// `TransactionTracker` tracks the state of transactions to detect retransmissions.
 #[derive(Debug)]
 pub struct TransactionTracker {
@@ -288,10 +290,14 @@ impl Drop for TransactionLock {
     }
 }
 
+/// Periodically drops old transactions and, with the `nfs4` feature, expired leases
 pub struct Cleaner {
     tracker: Arc<TransactionTracker>,
     interval: Duration,
     stop: Arc<tokio::sync::Notify>,
+    runtime: Arc<dyn Runtime>,
+    #[cfg(feature = "nfs4")]
+    nfs4_state: Option<Arc<crate::nfs4_state::StateManager>>,
 }
 
 impl Cleaner {
@@ -299,21 +305,37 @@ impl Cleaner {
         tracker: Arc<TransactionTracker>,
         interval: Duration,
         stop: Arc<tokio::sync::Notify>,
+        runtime: Arc<dyn Runtime>,
     ) -> Self {
         Self {
             tracker,
             interval,
             stop,
+            runtime,
+            #[cfg(feature = "nfs4")]
+            nfs4_state: None,
         }
     }
 
+    /// Also expires the leases of the `NFSv4` clients
+    #[cfg(feature = "nfs4")]
+    #[must_use]
+    pub fn with_nfs4_state(mut self, state: Arc<crate::nfs4_state::StateManager>) -> Self {
+        self.nfs4_state = Some(state);
+        self
+    }
+
     pub async fn run(self) {
         tracing::debug!("Transaction tracker cleaner started");
         loop {
             tokio::select! {
                 () = self.stop.notified() => break,
-                () = tokio::time::sleep(self.interval) => {
+                () = self.runtime.sleep(self.interval) => {
                     self.tracker.cleanup(Instant::now());
+                    #[cfg(feature = "nfs4")]
+                    if let Some(state) = &self.nfs4_state {
+                        state.expire_leases();
+                    }
                 }
             }
         }
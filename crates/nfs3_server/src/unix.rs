@@ -0,0 +1,86 @@
+//! Unix domain socket listener
+//!
+//! Serves clients that connect through a socket file instead of a TCP port, e.g.
+//! a proxy or a sidecar container sharing a volume with the server.
+
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use tokio::net::UnixListener;
+use tracing::{debug, info};
+
+use crate::server::NFSServer;
+use crate::tokio::TokioIo;
+use crate::vfs::NfsFileSystem;
+
+/// A NFS Unix domain socket connection handler
+///
+/// The socket file is created by [`NFSUnixListener::bind`] and removed when the
+/// listener is dropped.
+pub struct NFSUnixListener<T: NfsFileSystem + 'static> {
+    listener: UnixListener,
+    path: PathBuf,
+    server: NFSServer<T>,
+    next_connection: AtomicU64,
+}
+
+impl<T: NfsFileSystem + 'static> Drop for NFSUnixListener<T> {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+impl<T: NfsFileSystem + 'static> NFSUnixListener<T> {
+    /// Creates a socket file at `path` and serves it with `server`.
+    ///
+    /// Fails if the file already exists. Must be called within a Tokio runtime.
+    pub fn bind(path: impl AsRef<Path>, server: NFSServer<T>) -> io::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let listener = UnixListener::bind(&path)?;
+        info!("Listening on {}", path.display());
+        Ok(Self {
+            listener,
+            path,
+            server,
+            next_connection: AtomicU64::new(0),
+        })
+    }
+
+    /// Gets the path of the socket file
+    pub fn local_path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns the server that handles the accepted connections
+    pub const fn server(&self) -> &NFSServer<T> {
+        &self.server
+    }
+
+    /// Loops forever and never returns handling all incoming connections.
+    pub async fn handle_forever(&self) -> io::Result<()> {
+        loop {
+            let (socket, _) = self.listener.accept().await?;
+            // peers of a Unix socket are usually unnamed, so every connection gets its own
+            // address to keep the retransmission caches of the clients apart
+            let id = self.next_connection.fetch_add(1, Ordering::Relaxed);
+            let client_addr = socket
+                .peer_cred()
+                .ok()
+                .and_then(|cred| cred.pid())
+                .map_or_else(
+                    || format!("unix:{id}"),
+                    |pid| format!("unix:{id}:pid={pid}"),
+                );
+            info!("Accepting connection from {client_addr}");
+            let connection = self
+                .server
+                .serve_connection(TokioIo::new(socket), client_addr);
+            self.server.runtime()?.spawn(Box::pin(async move {
+                if let Err(e) = connection.await {
+                    debug!("Connection closed: {e}");
+                }
+            }));
+        }
+    }
+}
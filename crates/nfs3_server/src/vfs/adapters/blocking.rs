@@ -0,0 +1,392 @@
+use std::collections::VecDeque;
+use std::panic::{AssertUnwindSafe, catch_unwind};
+use std::sync::mpsc::{Receiver, Sender, channel};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use nfs3_types::nfs3::{
+    FSINFO3resok as fsinfo3, FSSTAT3resok as fsstat3, PATHCONF3resok as pathconf3, cookieverf3,
+    createverf3, fattr3, filename3, nfspath3, nfsstat3, sattr3,
+};
+use tokio::sync::oneshot;
+use tracing::error;
+
+use crate::vfs::caller::{self, current_caller};
+use crate::vfs::{
+    BlockingNfsFileSystem, BlockingNfsReadFileSystem, DirEntryPlus, FileHandle, NextResult,
+    NfsFileSystem, NfsReadFileSystem, PosixAcl, ReadDirPlusIterator, VFSCapabilities,
+};
+
+/// Number of threads used by [`BlockingAdapter::new`]
+const DEFAULT_THREADS: usize = 16;
+
+/// Number of directory entries fetched from a blocking iterator at once
+const READDIR_BATCH: usize = 64;
+
+type Job = Box<dyn FnOnce() + Send>;
+
+/// Serves a [`BlockingNfsReadFileSystem`] or [`BlockingNfsFileSystem`] as an async file system.
+///
+/// Every call is executed on a dedicated pool with a fixed number of threads, so a slow backend
+/// can't block the async runtime and the number of concurrent calls into it is bounded. Calls
+/// that arrive while all threads are busy wait in a queue.
+///
+/// Directory listings are pulled from the blocking iterators in batches and exposed as
+/// [`ReadDirPlusIterator`]s.
+///
+/// A read-only file system can be served with [`NFSServer::new_ro`][1].
+///
+/// [1]: crate::server::NFSServer::new_ro
+pub struct BlockingAdapter<T> {
+    fs: Arc<T>,
+    pool: BlockingPool,
+}
+
+impl<T> BlockingAdapter<T>
+where
+    T: BlockingNfsReadFileSystem,
+{
+    /// Creates an adapter with a pool of 16 threads
+    pub fn new(fs: T) -> Self {
+        Self::with_threads(fs, DEFAULT_THREADS)
+    }
+
+    /// Creates an adapter with a pool of `threads` threads
+    ///
+    /// # Panics
+    ///
+    /// Panics if `threads` is zero or a thread can't be spawned.
+    pub fn with_threads(fs: T, threads: usize) -> Self {
+        assert!(threads > 0, "the pool needs at least one thread");
+        Self {
+            fs: Arc::new(fs),
+            pool: BlockingPool::new(threads),
+        }
+    }
+
+    /// Returns the wrapped file system
+    #[must_use]
+    pub fn inner(&self) -> &T {
+        &self.fs
+    }
+
+    /// Runs `f` with the file system on the pool
+    async fn run<R>(&self, f: impl FnOnce(&T) -> R + Send + 'static) -> Result<R, nfsstat3>
+    where
+        R: Send + 'static,
+    {
+        let fs = Arc::clone(&self.fs);
+        let caller = current_caller();
+        self.pool
+            .run(move || caller::scope(caller, || f(&fs)))
+            .await
+    }
+}
+
+impl<T> NfsReadFileSystem for BlockingAdapter<T>
+where
+    T: BlockingNfsReadFileSystem,
+{
+    type Handle = T::Handle;
+
+    fn root_dir(&self) -> Self::Handle {
+        self.fs.root_dir()
+    }
+
+    async fn lookup(
+        &self,
+        dirid: &Self::Handle,
+        filename: &filename3<'_>,
+    ) -> Result<Self::Handle, nfsstat3> {
+        let dirid = dirid.clone();
+        let filename = filename.clone_to_owned();
+        self.run(move |fs| fs.lookup(&dirid, &filename)).await?
+    }
+
+    async fn lookup_by_path(&self, path: &str) -> Result<Self::Handle, nfsstat3> {
+        let path = path.to_owned();
+        self.run(move |fs| fs.lookup_by_path(&path)).await?
+    }
+
+    async fn getattr(&self, id: &Self::Handle) -> Result<fattr3, nfsstat3> {
+        let id = id.clone();
+        self.run(move |fs| fs.getattr(&id)).await?
+    }
+
+    async fn read(
+        &self,
+        id: &Self::Handle,
+        offset: u64,
+        count: u32,
+    ) -> Result<(Vec<u8>, bool), nfsstat3> {
+        let id = id.clone();
+        self.run(move |fs| fs.read(&id, offset, count)).await?
+    }
+
+    async fn readdirplus(
+        &self,
+        dirid: &Self::Handle,
+        cookie: u64,
+    ) -> Result<impl ReadDirPlusIterator<Self::Handle>, nfsstat3> {
+        let dirid = dirid.clone();
+        let iter = self.run(move |fs| fs.readdirplus(&dirid, cookie)).await??;
+        Ok(BlockingIterator::new(iter, self.pool.clone()))
+    }
+
+    async fn cookieverf(&self, dirid: &Self::Handle) -> Result<cookieverf3, nfsstat3> {
+        let dirid = dirid.clone();
+        self.run(move |fs| fs.cookieverf(&dirid)).await?
+    }
+
+    async fn getacl(&self, id: &Self::Handle) -> Result<PosixAcl, nfsstat3> {
+        let id = id.clone();
+        self.run(move |fs| fs.getacl(&id)).await?
+    }
+
+    async fn readlink(&self, id: &Self::Handle) -> Result<nfspath3<'_>, nfsstat3> {
+        let id = id.clone();
+        self.run(move |fs| fs.readlink(&id)).await?
+    }
+
+    async fn fsinfo(&self, root_fileid: &Self::Handle) -> Result<fsinfo3, nfsstat3> {
+        let root_fileid = root_fileid.clone();
+        self.run(move |fs| fs.fsinfo(&root_fileid)).await?
+    }
+
+    async fn fsstat(&self, root_fileid: &Self::Handle) -> Result<fsstat3, nfsstat3> {
+        let root_fileid = root_fileid.clone();
+        self.run(move |fs| fs.fsstat(&root_fileid)).await?
+    }
+
+    async fn pathconf(&self, id: &Self::Handle) -> Result<pathconf3, nfsstat3> {
+        let id = id.clone();
+        self.run(move |fs| fs.pathconf(&id)).await?
+    }
+}
+
+impl<T> NfsFileSystem for BlockingAdapter<T>
+where
+    T: BlockingNfsFileSystem,
+{
+    fn capabilities(&self) -> VFSCapabilities {
+        self.fs.capabilities()
+    }
+
+    async fn setattr(&self, id: &Self::Handle, setattr: sattr3) -> Result<fattr3, nfsstat3> {
+        let id = id.clone();
+        self.run(move |fs| fs.setattr(&id, setattr)).await?
+    }
+
+    async fn write(&self, id: &Self::Handle, offset: u64, data: &[u8]) -> Result<fattr3, nfsstat3> {
+        let id = id.clone();
+        let data = data.to_vec();
+        self.run(move |fs| fs.write(&id, offset, &data)).await?
+    }
+
+    fn buffers_writes(&self) -> bool {
+        self.fs.buffers_writes()
+    }
+
+    async fn commit(&self, id: &Self::Handle) -> Result<fattr3, nfsstat3> {
+        let id = id.clone();
+        self.run(move |fs| fs.commit(&id)).await?
+    }
+
+    async fn create(
+        &self,
+        dirid: &Self::Handle,
+        filename: &filename3<'_>,
+        attr: sattr3,
+    ) -> Result<(Self::Handle, fattr3), nfsstat3> {
+        let dirid = dirid.clone();
+        let filename = filename.clone_to_owned();
+        self.run(move |fs| fs.create(&dirid, &filename, attr))
+            .await?
+    }
+
+    async fn create_exclusive(
+        &self,
+        dirid: &Self::Handle,
+        filename: &filename3<'_>,
+        createverf: createverf3,
+    ) -> Result<Self::Handle, nfsstat3> {
+        let dirid = dirid.clone();
+        let filename = filename.clone_to_owned();
+        self.run(move |fs| fs.create_exclusive(&dirid, &filename, createverf))
+            .await?
+    }
+
+    async fn mkdir(
+        &self,
+        dirid: &Self::Handle,
+        dirname: &filename3<'_>,
+    ) -> Result<(Self::Handle, fattr3), nfsstat3> {
+        let dirid = dirid.clone();
+        let dirname = dirname.clone_to_owned();
+        self.run(move |fs| fs.mkdir(&dirid, &dirname)).await?
+    }
+
+    async fn remove(&self, dirid: &Self::Handle, filename: &filename3<'_>) -> Result<(), nfsstat3> {
+        let dirid = dirid.clone();
+        let filename = filename.clone_to_owned();
+        self.run(move |fs| fs.remove(&dirid, &filename)).await?
+    }
+
+    async fn rename<'a>(
+        &self,
+        from_dirid: &Self::Handle,
+        from_filename: &filename3<'a>,
+        to_dirid: &Self::Handle,
+        to_filename: &filename3<'a>,
+    ) -> Result<(), nfsstat3> {
+        let from_dirid = from_dirid.clone();
+        let from_filename = from_filename.clone_to_owned();
+        let to_dirid = to_dirid.clone();
+        let to_filename = to_filename.clone_to_owned();
+        self.run(move |fs| fs.rename(&from_dirid, &from_filename, &to_dirid, &to_filename))
+            .await?
+    }
+
+    async fn symlink<'a>(
+        &self,
+        dirid: &Self::Handle,
+        linkname: &filename3<'a>,
+        symlink: &nfspath3<'a>,
+        attr: &sattr3,
+    ) -> Result<(Self::Handle, fattr3), nfsstat3> {
+        let dirid = dirid.clone();
+        let linkname = linkname.clone_to_owned();
+        let symlink = symlink.clone_to_owned();
+        let attr = attr.clone();
+        self.run(move |fs| fs.symlink(&dirid, &linkname, &symlink, &attr))
+            .await?
+    }
+
+    async fn setacl(&self, id: &Self::Handle, acl: PosixAcl) -> Result<(), nfsstat3> {
+        let id = id.clone();
+        self.run(move |fs| fs.setacl(&id, acl)).await?
+    }
+}
+
+/// A fixed set of threads that execute the jobs from a shared queue
+///
+/// The threads exit when the last clone of the pool is dropped.
+#[derive(Clone)]
+struct BlockingPool {
+    jobs: Sender<Job>,
+}
+
+impl BlockingPool {
+    fn new(threads: usize) -> Self {
+        let (jobs, queue) = channel::<Job>();
+        let queue = Arc::new(Mutex::new(queue));
+        for i in 0..threads {
+            let queue = Arc::clone(&queue);
+            thread::Builder::new()
+                .name(format!("nfs-blocking-{i}"))
+                .spawn(move || Self::worker(&queue))
+                .expect("failed to spawn a thread of the blocking pool");
+        }
+        Self { jobs }
+    }
+
+    fn worker(queue: &Mutex<Receiver<Job>>) {
+        loop {
+            let job = {
+                let Ok(queue) = queue.lock() else {
+                    return;
+                };
+                match queue.recv() {
+                    Ok(job) => job,
+                    Err(_) => return,
+                }
+            };
+            // the caller gets NFS3ERR_SERVERFAULT, the thread keeps serving other calls
+            let _ = catch_unwind(AssertUnwindSafe(job));
+        }
+    }
+
+    /// Runs `f` on the pool and waits for the result
+    ///
+    /// Returns `NFS3ERR_SERVERFAULT` if `f` panics.
+    async fn run<R>(&self, f: impl FnOnce() -> R + Send + 'static) -> Result<R, nfsstat3>
+    where
+        R: Send + 'static,
+    {
+        let (result, receiver) = oneshot::channel();
+        let job: Job = Box::new(move || {
+            let _ = result.send(f());
+        });
+        if self.jobs.send(job).is_err() {
+            error!("blocking pool is stopped");
+            return Err(nfsstat3::NFS3ERR_SERVERFAULT);
+        }
+        receiver.await.map_err(|_| {
+            error!("blocking file system call panicked");
+            nfsstat3::NFS3ERR_SERVERFAULT
+        })
+    }
+}
+
+/// Pulls entries of a blocking iterator on the pool in batches
+struct BlockingIterator<H: FileHandle, I> {
+    // the iterator is moved to the pool while a batch is fetched
+    iter: Mutex<Option<I>>,
+    batch: VecDeque<Result<DirEntryPlus<H>, nfsstat3>>,
+    pool: BlockingPool,
+}
+
+impl<H, I> BlockingIterator<H, I>
+where
+    H: FileHandle + 'static,
+    I: Iterator<Item = Result<DirEntryPlus<H>, nfsstat3>> + Send + 'static,
+{
+    const fn new(iter: I, pool: BlockingPool) -> Self {
+        Self {
+            iter: Mutex::new(Some(iter)),
+            batch: VecDeque::new(),
+            pool,
+        }
+    }
+
+    async fn fetch(&mut self) -> Result<(), nfsstat3> {
+        let iter = self
+            .iter
+            .get_mut()
+            .map_err(|_| nfsstat3::NFS3ERR_SERVERFAULT)?;
+        let Some(mut inner) = iter.take() else {
+            return Ok(());
+        };
+        let (inner, batch) = self
+            .pool
+            .run(move || {
+                let batch = inner.by_ref().take(READDIR_BATCH).collect::<VecDeque<_>>();
+                // a short batch means that the iterator is exhausted
+                let inner = (batch.len() == READDIR_BATCH).then_some(inner);
+                (inner, batch)
+            })
+            .await?;
+        *iter = inner;
+        self.batch = batch;
+        Ok(())
+    }
+}
+
+impl<H, I> ReadDirPlusIterator<H> for BlockingIterator<H, I>
+where
+    H: FileHandle + 'static,
+    I: Iterator<Item = Result<DirEntryPlus<H>, nfsstat3>> + Send + 'static,
+{
+    async fn next(&mut self) -> NextResult<DirEntryPlus<H>> {
+        if self.batch.is_empty() {
+            if let Err(stat) = self.fetch().await {
+                return NextResult::Err(stat);
+            }
+        }
+        match self.batch.pop_front() {
+            Some(Ok(entry)) => NextResult::Ok(entry),
+            Some(Err(stat)) => NextResult::Err(stat),
+            None => NextResult::Eof,
+        }
+    }
+}
@@ -0,0 +1,519 @@
+use std::collections::{HashMap, VecDeque};
+use std::hash::Hash;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+use nfs3_types::nfs3::{
+    FSINFO3resok as fsinfo3, FSSTAT3resok as fsstat3, PATHCONF3resok as pathconf3, cookieverf3,
+    createverf3, fattr3, filename3, nfspath3, nfsstat3, sattr3,
+};
+
+use crate::vfs::{
+    FileHandle, NfsFileSystem, NfsReadFileSystem, PosixAcl, ReadDirIterator, ReadDirPlusIterator,
+    VFSCapabilities,
+};
+
+type LookupKey = (Vec<u8>, Vec<u8>);
+
+/// Caches the results of `getattr`, `lookup` and `readlink` of the wrapped file system.
+///
+/// Entries expire after a fixed time to live, so changes made to the backend by other means
+/// become visible after at most that long. Every mutating call that goes through the adapter
+/// drops the entries it may have affected, whether it succeeds or not.
+///
+/// Each of the three caches holds at most `capacity` entries. When a cache is full, the oldest
+/// entry is evicted. Only successful results are cached.
+///
+/// The hit and miss counters are available through [`stats`](Self::stats).
+pub struct CachingAdapter<T: NfsReadFileSystem> {
+    inner: T,
+    attrs: TtlCache<Vec<u8>, fattr3>,
+    lookups: TtlCache<LookupKey, T::Handle>,
+    links: TtlCache<Vec<u8>, nfspath3<'static>>,
+}
+
+impl<T> CachingAdapter<T>
+where
+    T: NfsReadFileSystem,
+{
+    /// Creates an adapter that keeps entries for `ttl` and holds up to `capacity` entries
+    /// in each cache
+    pub fn new(inner: T, ttl: Duration, capacity: usize) -> Self {
+        Self {
+            inner,
+            attrs: TtlCache::new(ttl, capacity),
+            lookups: TtlCache::new(ttl, capacity),
+            links: TtlCache::new(ttl, capacity),
+        }
+    }
+
+    /// Returns the wrapped file system
+    pub const fn inner(&self) -> &T {
+        &self.inner
+    }
+
+    /// Returns the counters of the caches
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            getattr: self.attrs.counters(),
+            lookup: self.lookups.counters(),
+            readlink: self.links.counters(),
+        }
+    }
+
+    /// Drops all cached entries
+    pub fn clear(&self) {
+        self.attrs.clear();
+        self.lookups.clear();
+        self.links.clear();
+    }
+
+    /// Drops the cached attributes and symlink target of an object
+    fn invalidate(&self, id: &T::Handle) {
+        self.attrs.remove(id.as_bytes());
+        self.links.remove(id.as_bytes());
+    }
+
+    /// Drops a directory entry, the attributes of the directory and of the cached target
+    fn invalidate_entry(&self, dirid: &T::Handle, name: &filename3<'_>) {
+        self.attrs.remove(dirid.as_bytes());
+        let key = lookup_key(dirid, name);
+        if let Some(id) = self.lookups.take(&key) {
+            self.invalidate(&id);
+        }
+    }
+
+    /// Returns the object a name refers to before a call that unlinks or replaces it
+    ///
+    /// The attributes of the object may be cached even if the name was never looked up
+    /// through the adapter, so the wrapped file system is asked when the lookup isn't cached.
+    async fn resolve(&self, dirid: &T::Handle, name: &filename3<'_>) -> Option<T::Handle> {
+        match self.lookups.take(&lookup_key(dirid, name)) {
+            Some(id) => Some(id),
+            None => self.inner.lookup(dirid, name).await.ok(),
+        }
+    }
+}
+
+/// Statistics of a [`CachingAdapter`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    /// Counters of the `getattr` cache
+    pub getattr: CacheCounters,
+    /// Counters of the `lookup` cache
+    pub lookup: CacheCounters,
+    /// Counters of the `readlink` cache
+    pub readlink: CacheCounters,
+}
+
+/// Counters of a single cache of a [`CachingAdapter`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheCounters {
+    /// Number of calls answered from the cache
+    pub hits: u64,
+    /// Number of calls passed to the wrapped file system
+    pub misses: u64,
+    /// Number of entries dropped because the cache was full
+    pub evictions: u64,
+    /// Number of entries currently held, including the expired ones not dropped yet
+    pub entries: usize,
+}
+
+impl<T> NfsReadFileSystem for CachingAdapter<T>
+where
+    T: NfsReadFileSystem,
+{
+    type Handle = T::Handle;
+
+    fn root_dir(&self) -> Self::Handle {
+        self.inner.root_dir()
+    }
+
+    async fn lookup(
+        &self,
+        dirid: &Self::Handle,
+        filename: &filename3<'_>,
+    ) -> Result<Self::Handle, nfsstat3> {
+        let key = lookup_key(dirid, filename);
+        if let Some(id) = self.lookups.get(&key) {
+            return Ok(id);
+        }
+        let ticket = self.lookups.ticket();
+        let id = self.inner.lookup(dirid, filename).await?;
+        self.lookups.insert(ticket, key, id.clone());
+        Ok(id)
+    }
+
+    async fn lookup_by_path(&self, path: &str) -> Result<Self::Handle, nfsstat3> {
+        self.inner.lookup_by_path(path).await
+    }
+
+    async fn getattr(&self, id: &Self::Handle) -> Result<fattr3, nfsstat3> {
+        if let Some(attr) = self.attrs.get(id.as_bytes()) {
+            return Ok(attr);
+        }
+        let ticket = self.attrs.ticket();
+        let attr = self.inner.getattr(id).await?;
+        self.attrs
+            .insert(ticket, id.as_bytes().to_vec(), attr.clone());
+        Ok(attr)
+    }
+
+    async fn read(
+        &self,
+        id: &Self::Handle,
+        offset: u64,
+        count: u32,
+    ) -> Result<(Vec<u8>, bool), nfsstat3> {
+        self.inner.read(id, offset, count).await
+    }
+
+    async fn read_into(
+        &self,
+        id: &Self::Handle,
+        offset: u64,
+        buf: &mut [u8],
+    ) -> Result<(usize, bool), nfsstat3> {
+        self.inner.read_into(id, offset, buf).await
+    }
+
+    async fn readdir(
+        &self,
+        dirid: &Self::Handle,
+        cookie: u64,
+    ) -> Result<impl ReadDirIterator, nfsstat3> {
+        self.inner.readdir(dirid, cookie).await
+    }
+
+    async fn readdirplus(
+        &self,
+        dirid: &Self::Handle,
+        cookie: u64,
+    ) -> Result<impl ReadDirPlusIterator<Self::Handle>, nfsstat3> {
+        self.inner.readdirplus(dirid, cookie).await
+    }
+
+    async fn cookieverf(&self, dirid: &Self::Handle) -> Result<cookieverf3, nfsstat3> {
+        self.inner.cookieverf(dirid).await
+    }
+
+    async fn getacl(&self, id: &Self::Handle) -> Result<PosixAcl, nfsstat3> {
+        self.inner.getacl(id).await
+    }
+
+    async fn readlink(&self, id: &Self::Handle) -> Result<nfspath3<'_>, nfsstat3> {
+        if let Some(path) = self.links.get(id.as_bytes()) {
+            return Ok(path);
+        }
+        let ticket = self.links.ticket();
+        let path = self.inner.readlink(id).await?.clone_to_owned();
+        self.links
+            .insert(ticket, id.as_bytes().to_vec(), path.clone());
+        Ok(path)
+    }
+
+    async fn fsinfo(&self, root_fileid: &Self::Handle) -> Result<fsinfo3, nfsstat3> {
+        self.inner.fsinfo(root_fileid).await
+    }
+
+    async fn fsstat(&self, root_fileid: &Self::Handle) -> Result<fsstat3, nfsstat3> {
+        self.inner.fsstat(root_fileid).await
+    }
+
+    async fn pathconf(&self, id: &Self::Handle) -> Result<pathconf3, nfsstat3> {
+        self.inner.pathconf(id).await
+    }
+}
+
+impl<T> NfsFileSystem for CachingAdapter<T>
+where
+    T: NfsFileSystem,
+{
+    fn capabilities(&self) -> VFSCapabilities {
+        self.inner.capabilities()
+    }
+
+    async fn setattr(&self, id: &Self::Handle, setattr: sattr3) -> Result<fattr3, nfsstat3> {
+        let result = self.inner.setattr(id, setattr).await;
+        self.invalidate(id);
+        result
+    }
+
+    async fn write(&self, id: &Self::Handle, offset: u64, data: &[u8]) -> Result<fattr3, nfsstat3> {
+        let result = self.inner.write(id, offset, data).await;
+        self.invalidate(id);
+        result
+    }
+
+    fn buffers_writes(&self) -> bool {
+        self.inner.buffers_writes()
+    }
+
+    async fn commit(&self, id: &Self::Handle) -> Result<fattr3, nfsstat3> {
+        let result = self.inner.commit(id).await;
+        self.invalidate(id);
+        result
+    }
+
+    async fn create(
+        &self,
+        dirid: &Self::Handle,
+        filename: &filename3<'_>,
+        attr: sattr3,
+    ) -> Result<(Self::Handle, fattr3), nfsstat3> {
+        let result = self.inner.create(dirid, filename, attr).await;
+        self.invalidate_entry(dirid, filename);
+        // an unchecked create truncates an existing file
+        if let Ok((id, _)) = &result {
+            self.invalidate(id);
+        }
+        result
+    }
+
+    async fn create_exclusive(
+        &self,
+        dirid: &Self::Handle,
+        filename: &filename3<'_>,
+        createverf: createverf3,
+    ) -> Result<Self::Handle, nfsstat3> {
+        let result = self
+            .inner
+            .create_exclusive(dirid, filename, createverf)
+            .await;
+        self.invalidate_entry(dirid, filename);
+        result
+    }
+
+    async fn mkdir(
+        &self,
+        dirid: &Self::Handle,
+        dirname: &filename3<'_>,
+    ) -> Result<(Self::Handle, fattr3), nfsstat3> {
+        let result = self.inner.mkdir(dirid, dirname).await;
+        self.invalidate_entry(dirid, dirname);
+        result
+    }
+
+    async fn remove(&self, dirid: &Self::Handle, filename: &filename3<'_>) -> Result<(), nfsstat3> {
+        let target = self.resolve(dirid, filename).await;
+        let result = self.inner.remove(dirid, filename).await;
+        self.invalidate_entry(dirid, filename);
+        if let Some(id) = target {
+            self.invalidate(&id);
+        }
+        result
+    }
+
+    async fn rename<'a>(
+        &self,
+        from_dirid: &Self::Handle,
+        from_filename: &filename3<'a>,
+        to_dirid: &Self::Handle,
+        to_filename: &filename3<'a>,
+    ) -> Result<(), nfsstat3> {
+        let moved = self.resolve(from_dirid, from_filename).await;
+        let replaced = self.resolve(to_dirid, to_filename).await;
+        let result = self
+            .inner
+            .rename(from_dirid, from_filename, to_dirid, to_filename)
+            .await;
+        self.invalidate_entry(from_dirid, from_filename);
+        self.invalidate_entry(to_dirid, to_filename);
+        if let Some(id) = moved {
+            self.invalidate(&id);
+            // ".." of a moved directory points to its new parent
+            self.lookups
+                .remove(&(id.as_bytes().to_vec(), b"..".to_vec()));
+        }
+        if let Some(id) = replaced {
+            self.invalidate(&id);
+        }
+        result
+    }
+
+    async fn symlink<'a>(
+        &self,
+        dirid: &Self::Handle,
+        linkname: &filename3<'a>,
+        symlink: &nfspath3<'a>,
+        attr: &sattr3,
+    ) -> Result<(Self::Handle, fattr3), nfsstat3> {
+        let result = self.inner.symlink(dirid, linkname, symlink, attr).await;
+        self.invalidate_entry(dirid, linkname);
+        result
+    }
+
+    async fn setacl(&self, id: &Self::Handle, acl: PosixAcl) -> Result<(), nfsstat3> {
+        let result = self.inner.setacl(id, acl).await;
+        // the mode bits follow the access ACL
+        self.invalidate(id);
+        result
+    }
+}
+
+fn lookup_key<H: FileHandle>(dirid: &H, name: &filename3<'_>) -> LookupKey {
+    (dirid.as_bytes().to_vec(), name.as_ref().to_vec())
+}
+
+/// A map with a time to live and a bounded number of entries
+///
+/// A value fetched from the backend is stored only if nothing was removed from the cache
+/// since its [`ticket`](Self::ticket) was taken. Otherwise a lookup that raced with a mutation
+/// could store a value that is already outdated.
+struct TtlCache<K, V> {
+    ttl: Duration,
+    capacity: usize,
+    state: Mutex<CacheState<K, V>>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    evictions: AtomicU64,
+}
+
+struct CacheState<K, V> {
+    entries: HashMap<K, CacheEntry<V>>,
+    // insertion order, entries whose `seq` doesn't match anymore are skipped
+    order: VecDeque<(K, u64)>,
+    next_seq: u64,
+    removals: u64,
+}
+
+struct CacheEntry<V> {
+    value: V,
+    expires: Instant,
+    seq: u64,
+}
+
+impl<K, V> TtlCache<K, V>
+where
+    K: Eq + Hash + Clone,
+    V: Clone,
+{
+    fn new(ttl: Duration, capacity: usize) -> Self {
+        Self {
+            ttl,
+            capacity,
+            state: Mutex::new(CacheState {
+                entries: HashMap::new(),
+                order: VecDeque::new(),
+                next_seq: 0,
+                removals: 0,
+            }),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+            evictions: AtomicU64::new(0),
+        }
+    }
+
+    fn state(&self) -> std::sync::MutexGuard<'_, CacheState<K, V>> {
+        self.state
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+    }
+
+    fn get<Q>(&self, key: &Q) -> Option<V>
+    where
+        K: std::borrow::Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        let mut state = self.state();
+        let value = match state.entries.get(key) {
+            Some(entry) if entry.expires > Instant::now() => Some(entry.value.clone()),
+            Some(_) => {
+                state.entries.remove(key);
+                None
+            }
+            None => None,
+        };
+        drop(state);
+
+        let counter = if value.is_some() {
+            &self.hits
+        } else {
+            &self.misses
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+        value
+    }
+
+    /// Returns the value to pass to [`insert`](Self::insert) after fetching a missing entry
+    fn ticket(&self) -> u64 {
+        self.state().removals
+    }
+
+    fn insert(&self, ticket: u64, key: K, value: V) {
+        if self.capacity == 0 {
+            return;
+        }
+        let mut state = self.state();
+        if state.removals != ticket {
+            return;
+        }
+
+        let seq = state.next_seq;
+        state.next_seq += 1;
+        state.order.push_back((key.clone(), seq));
+        state.entries.insert(
+            key,
+            CacheEntry {
+                value,
+                expires: Instant::now() + self.ttl,
+                seq,
+            },
+        );
+
+        while state.entries.len() > self.capacity {
+            let Some((key, seq)) = state.order.pop_front() else {
+                break;
+            };
+            if state
+                .entries
+                .get(&key)
+                .is_some_and(|entry| entry.seq == seq)
+            {
+                state.entries.remove(&key);
+                self.evictions.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+
+        // don't let the removed and replaced entries accumulate in the queue
+        if state.order.len() > 2 * self.capacity {
+            let CacheState { entries, order, .. } = &mut *state;
+            order.retain(|(key, seq)| entries.get(key).is_some_and(|entry| entry.seq == *seq));
+        }
+    }
+
+    fn take<Q>(&self, key: &Q) -> Option<V>
+    where
+        K: std::borrow::Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        let mut state = self.state();
+        state.removals += 1;
+        state.entries.remove(key).map(|entry| entry.value)
+    }
+
+    fn remove<Q>(&self, key: &Q)
+    where
+        K: std::borrow::Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        self.take(key);
+    }
+
+    fn clear(&self) {
+        let mut state = self.state();
+        state.removals += 1;
+        state.entries.clear();
+        state.order.clear();
+    }
+
+    fn counters(&self) -> CacheCounters {
+        CacheCounters {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            evictions: self.evictions.load(Ordering::Relaxed),
+            entries: self.state().entries.len(),
+        }
+    }
+}
@@ -0,0 +1,337 @@
+use nfs3_types::nfs3::{
+    FSINFO3resok as fsinfo3, FSSTAT3resok as fsstat3, PATHCONF3resok as pathconf3, cookieverf3,
+    createverf3, fattr3, filename3, nfspath3, nfsstat3, sattr3,
+};
+use tracing::{debug, warn};
+
+use crate::vfs::{
+    FileHandle, NextResult, NfsFileSystem, NfsReadFileSystem, PosixAcl, ReadDirIterator,
+    ReadDirPlusIterator, VFSCapabilities,
+};
+
+/// How [`CaseInsensitiveAdapter`] compares names
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CaseFolding {
+    /// Only the ASCII letters are folded, the other bytes must be equal
+    #[default]
+    Ascii,
+    /// The names are lowercased as Unicode strings, so `Ä` matches `ä`. Names that are not
+    /// valid UTF-8 are folded as ASCII.
+    Unicode,
+}
+
+impl CaseFolding {
+    fn fold(self, name: &[u8]) -> Vec<u8> {
+        match (self, std::str::from_utf8(name)) {
+            (Self::Unicode, Ok(name)) => name.to_lowercase().into_bytes(),
+            _ => name.to_ascii_lowercase(),
+        }
+    }
+}
+
+/// Resolves names case-insensitively over a case-sensitive file system.
+///
+/// LOOKUP, REMOVE and the source of RENAME find the entry whose name matches the given one
+/// after case folding. Creating an object, or renaming one, onto a name that matches an
+/// existing entry uses the name of that entry, so a directory never gets two entries that
+/// only differ by case. Renaming an entry to another case of its own name changes the
+/// stored name. New names are stored as given, and PATHCONF reports the file system as
+/// case-insensitive and case-preserving.
+///
+/// An exact match is always preferred. If there is none and several entries of the
+/// wrapped file system match, e.g. `README` and `Readme` when looking up `readme`, the
+/// name is ambiguous and the operation fails with `NFS3ERR_INVAL`.
+///
+/// Names that don't match exactly are resolved by listing the directory, so they cost a
+/// READDIR of the wrapped file system.
+pub struct CaseInsensitiveAdapter<T> {
+    inner: T,
+    folding: CaseFolding,
+}
+
+/// The entry of a directory that matches a name
+enum Resolved {
+    /// No entry matches
+    Missing,
+    /// The entry with the given name matches
+    Exact,
+    /// The entry with a different name matches
+    Folded(filename3<'static>),
+}
+
+impl<T> CaseInsensitiveAdapter<T>
+where
+    T: NfsReadFileSystem,
+{
+    /// Creates an adapter that compares names with the given folding
+    pub const fn new(inner: T, folding: CaseFolding) -> Self {
+        Self { inner, folding }
+    }
+
+    /// Returns the wrapped file system
+    pub const fn inner(&self) -> &T {
+        &self.inner
+    }
+
+    async fn resolve(
+        &self,
+        dirid: &T::Handle,
+        filename: &filename3<'_>,
+    ) -> Result<Resolved, nfsstat3> {
+        match self.inner.lookup(dirid, filename).await {
+            Ok(_) => return Ok(Resolved::Exact),
+            Err(nfsstat3::NFS3ERR_NOENT) => {}
+            Err(stat) => return Err(stat),
+        }
+        self.find_folded(dirid, filename).await
+    }
+
+    /// Lists the directory to find the entry that matches `filename` after folding
+    async fn find_folded(
+        &self,
+        dirid: &T::Handle,
+        filename: &filename3<'_>,
+    ) -> Result<Resolved, nfsstat3> {
+        let folded = self.folding.fold(filename.as_ref());
+        let mut found = None;
+        let mut iter = self.inner.readdir(dirid, 0).await?;
+        loop {
+            match iter.next().await {
+                NextResult::Ok(entry) => {
+                    let name = entry.name.as_ref();
+                    if name == b"." || name == b".." || self.folding.fold(name) != folded {
+                        continue;
+                    }
+                    if found.is_some() {
+                        warn!(
+                            "{:?} is ambiguous",
+                            String::from_utf8_lossy(filename.as_ref())
+                        );
+                        return Err(nfsstat3::NFS3ERR_INVAL);
+                    }
+                    found = Some(filename3::from(name.to_vec()));
+                }
+                NextResult::Eof => break,
+                NextResult::Err(stat) => return Err(stat),
+            }
+        }
+        Ok(found.map_or(Resolved::Missing, |name| {
+            debug!(
+                "{:?} resolved to {:?}",
+                String::from_utf8_lossy(filename.as_ref()),
+                String::from_utf8_lossy(name.as_ref())
+            );
+            Resolved::Folded(name)
+        }))
+    }
+
+    /// Returns the name of the existing entry that matches `filename`
+    async fn existing<'a>(
+        &self,
+        dirid: &T::Handle,
+        filename: &'a filename3<'a>,
+    ) -> Result<filename3<'a>, nfsstat3> {
+        match self.resolve(dirid, filename).await? {
+            Resolved::Missing => Err(nfsstat3::NFS3ERR_NOENT),
+            Resolved::Exact => Ok(filename3::from(filename.as_ref())),
+            Resolved::Folded(name) => Ok(name),
+        }
+    }
+
+    /// Returns the name to use for a new entry named `filename`
+    async fn target<'a>(
+        &self,
+        dirid: &T::Handle,
+        filename: &'a filename3<'a>,
+    ) -> Result<filename3<'a>, nfsstat3> {
+        match self.resolve(dirid, filename).await? {
+            Resolved::Missing | Resolved::Exact => Ok(filename3::from(filename.as_ref())),
+            Resolved::Folded(name) => Ok(name),
+        }
+    }
+}
+
+impl<T> NfsReadFileSystem for CaseInsensitiveAdapter<T>
+where
+    T: NfsReadFileSystem,
+{
+    type Handle = T::Handle;
+
+    fn root_dir(&self) -> Self::Handle {
+        self.inner.root_dir()
+    }
+
+    async fn lookup(
+        &self,
+        dirid: &Self::Handle,
+        filename: &filename3<'_>,
+    ) -> Result<Self::Handle, nfsstat3> {
+        match self.inner.lookup(dirid, filename).await {
+            Err(nfsstat3::NFS3ERR_NOENT) => {}
+            result => return result,
+        }
+        match self.find_folded(dirid, filename).await? {
+            Resolved::Folded(name) => self.inner.lookup(dirid, &name).await,
+            Resolved::Exact | Resolved::Missing => Err(nfsstat3::NFS3ERR_NOENT),
+        }
+    }
+
+    // `lookup_by_path` uses the default implementation, which walks the path with `lookup`
+
+    async fn getattr(&self, id: &Self::Handle) -> Result<fattr3, nfsstat3> {
+        self.inner.getattr(id).await
+    }
+
+    async fn read(
+        &self,
+        id: &Self::Handle,
+        offset: u64,
+        count: u32,
+    ) -> Result<(Vec<u8>, bool), nfsstat3> {
+        self.inner.read(id, offset, count).await
+    }
+
+    async fn read_into(
+        &self,
+        id: &Self::Handle,
+        offset: u64,
+        buf: &mut [u8],
+    ) -> Result<(usize, bool), nfsstat3> {
+        self.inner.read_into(id, offset, buf).await
+    }
+
+    async fn readdir(
+        &self,
+        dirid: &Self::Handle,
+        cookie: u64,
+    ) -> Result<impl ReadDirIterator, nfsstat3> {
+        self.inner.readdir(dirid, cookie).await
+    }
+
+    async fn readdirplus(
+        &self,
+        dirid: &Self::Handle,
+        cookie: u64,
+    ) -> Result<impl ReadDirPlusIterator<Self::Handle>, nfsstat3> {
+        self.inner.readdirplus(dirid, cookie).await
+    }
+
+    async fn cookieverf(&self, dirid: &Self::Handle) -> Result<cookieverf3, nfsstat3> {
+        self.inner.cookieverf(dirid).await
+    }
+
+    async fn getacl(&self, id: &Self::Handle) -> Result<PosixAcl, nfsstat3> {
+        self.inner.getacl(id).await
+    }
+
+    async fn readlink(&self, id: &Self::Handle) -> Result<nfspath3<'_>, nfsstat3> {
+        self.inner.readlink(id).await
+    }
+
+    async fn fsinfo(&self, root_fileid: &Self::Handle) -> Result<fsinfo3, nfsstat3> {
+        self.inner.fsinfo(root_fileid).await
+    }
+
+    async fn fsstat(&self, root_fileid: &Self::Handle) -> Result<fsstat3, nfsstat3> {
+        self.inner.fsstat(root_fileid).await
+    }
+
+    async fn pathconf(&self, id: &Self::Handle) -> Result<pathconf3, nfsstat3> {
+        let mut conf = self.inner.pathconf(id).await?;
+        conf.case_insensitive = true;
+        conf.case_preserving = true;
+        Ok(conf)
+    }
+}
+
+impl<T> NfsFileSystem for CaseInsensitiveAdapter<T>
+where
+    T: NfsFileSystem,
+{
+    fn capabilities(&self) -> VFSCapabilities {
+        self.inner.capabilities()
+    }
+
+    async fn setattr(&self, id: &Self::Handle, setattr: sattr3) -> Result<fattr3, nfsstat3> {
+        self.inner.setattr(id, setattr).await
+    }
+
+    async fn write(&self, id: &Self::Handle, offset: u64, data: &[u8]) -> Result<fattr3, nfsstat3> {
+        self.inner.write(id, offset, data).await
+    }
+
+    fn buffers_writes(&self) -> bool {
+        self.inner.buffers_writes()
+    }
+
+    async fn commit(&self, id: &Self::Handle) -> Result<fattr3, nfsstat3> {
+        self.inner.commit(id).await
+    }
+
+    async fn create(
+        &self,
+        dirid: &Self::Handle,
+        filename: &filename3<'_>,
+        attr: sattr3,
+    ) -> Result<(Self::Handle, fattr3), nfsstat3> {
+        let filename = self.target(dirid, filename).await?;
+        self.inner.create(dirid, &filename, attr).await
+    }
+
+    async fn create_exclusive(
+        &self,
+        dirid: &Self::Handle,
+        filename: &filename3<'_>,
+        createverf: createverf3,
+    ) -> Result<Self::Handle, nfsstat3> {
+        let filename = self.target(dirid, filename).await?;
+        self.inner
+            .create_exclusive(dirid, &filename, createverf)
+            .await
+    }
+
+    async fn mkdir(
+        &self,
+        dirid: &Self::Handle,
+        dirname: &filename3<'_>,
+    ) -> Result<(Self::Handle, fattr3), nfsstat3> {
+        let dirname = self.target(dirid, dirname).await?;
+        self.inner.mkdir(dirid, &dirname).await
+    }
+
+    async fn remove(&self, dirid: &Self::Handle, filename: &filename3<'_>) -> Result<(), nfsstat3> {
+        let filename = self.existing(dirid, filename).await?;
+        self.inner.remove(dirid, &filename).await
+    }
+
+    async fn rename<'a>(
+        &self,
+        from_dirid: &Self::Handle,
+        from_filename: &filename3<'a>,
+        to_dirid: &Self::Handle,
+        to_filename: &filename3<'a>,
+    ) -> Result<(), nfsstat3> {
+        let from = self.existing(from_dirid, from_filename).await?;
+        let mut to = self.target(to_dirid, to_filename).await?;
+        if from_dirid.as_bytes() == to_dirid.as_bytes() && from.as_ref() == to.as_ref() {
+            // a change of case only, store the new name
+            to = filename3::from(to_filename.as_ref());
+        }
+        self.inner.rename(from_dirid, &from, to_dirid, &to).await
+    }
+
+    async fn symlink<'a>(
+        &self,
+        dirid: &Self::Handle,
+        linkname: &filename3<'a>,
+        symlink: &nfspath3<'a>,
+        attr: &sattr3,
+    ) -> Result<(Self::Handle, fattr3), nfsstat3> {
+        let linkname = self.target(dirid, linkname).await?;
+        self.inner.symlink(dirid, &linkname, symlink, attr).await
+    }
+
+    async fn setacl(&self, id: &Self::Handle, acl: PosixAcl) -> Result<(), nfsstat3> {
+        self.inner.setacl(id, acl).await
+    }
+}
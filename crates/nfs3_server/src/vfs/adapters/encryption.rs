@@ -0,0 +1,779 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+use hmac::{Hmac, Mac};
+use nfs3_types::nfs3::{
+    FSINFO3resok as fsinfo3, FSSTAT3resok as fsstat3, Nfs3Option, PATHCONF3resok as pathconf3,
+    cookieverf3, createverf3, fattr3, filename3, ftype3, nfspath3, nfsstat3, post_op_attr, sattr3,
+};
+use sha2::Sha256;
+use tokio::sync::{OwnedRwLockReadGuard, OwnedRwLockWriteGuard, RwLock};
+use tracing::{debug, warn};
+
+use crate::vfs::{
+    DirEntry, DirEntryPlus, FileHandle, NextResult, NfsFileSystem, NfsReadFileSystem, PosixAcl,
+    ReadDirIterator, ReadDirPlusIterator, VFSCapabilities,
+};
+
+/// Size of the plaintext blocks
+const BLOCK_LEN: usize = 4096;
+const BLOCK_SIZE: u64 = BLOCK_LEN as u64;
+const NONCE_LEN: usize = 24;
+const TAG_LEN: usize = 16;
+/// Bytes added to each block by the nonce and the authentication tag
+const BLOCK_OVERHEAD: usize = NONCE_LEN + TAG_LEN;
+const STORED_BLOCK_LEN: usize = BLOCK_LEN + BLOCK_OVERHEAD;
+const STORED_BLOCK_SIZE: u64 = STORED_BLOCK_LEN as u64;
+/// Length of the random file id stored in front of the first block
+const FILE_ID_LEN: u32 = 16;
+const HEADER_LEN: u64 = FILE_ID_LEN as u64;
+/// Length of the synthetic IV of an encrypted name
+const NAME_SIV_LEN: usize = 16;
+/// Number of bytes written at once when a file is extended with zeros
+const FILL_LEN: usize = 64 * BLOCK_LEN;
+
+type FileId = [u8; FILE_ID_LEN as usize];
+
+/// Encrypts the contents, and optionally the names, stored in the wrapped file system.
+///
+/// File contents are split into blocks of 4 KiB, each sealed with XChaCha20-Poly1305 under
+/// a random nonce. Every file starts with a random id that is authenticated along with the
+/// block number and a flag marking the last block, so blocks can't be swapped within a file
+/// or between files, and a file can't be cut short, without READ failing with `NFS3ERR_IO`.
+/// Only a file that is emptied completely in the wrapped file system looks like a new, empty
+/// one. Only the blocks touched by a READ or WRITE are read and re-encrypted, and GETATTR
+/// reports the size of the plaintext.
+///
+/// With [`set_name_encryption`][Self::set_name_encryption], names and symbolic link targets are
+/// encrypted deterministically and stored as unpadded URL-safe base64, so LOOKUP doesn't
+/// need to list the directory. Encrypted names are about 4/3 as long as the plaintext plus
+/// 43 characters, which lowers the name length that the wrapped file system accepts.
+/// Entries whose names can't be decrypted are left out of directory listings.
+///
+/// Use a separate key for each export. The keys of the contents and of the names are
+/// derived from it.
+pub struct EncryptionAdapter<T> {
+    inner: T,
+    contents: XChaCha20Poly1305,
+    names: NameCipher,
+    encrypt_names: bool,
+    /// Serializes the read-modify-write cycles of WRITE and SETATTR with each other and
+    /// with READ, by handle
+    file_locks: FileLocks,
+}
+
+type FileLocks = std::sync::Mutex<HashMap<Vec<u8>, Arc<RwLock<()>>>>;
+
+/// Holds the lock of a file and forgets it when nobody else is waiting for it
+struct FileGuard<'a> {
+    locks: &'a FileLocks,
+    key: Vec<u8>,
+    lock: Arc<RwLock<()>>,
+    guard: Option<LockGuard>,
+}
+
+enum LockGuard {
+    Shared(OwnedRwLockReadGuard<()>),
+    Exclusive(OwnedRwLockWriteGuard<()>),
+}
+
+impl Drop for FileGuard<'_> {
+    fn drop(&mut self) {
+        match self.guard.take() {
+            Some(LockGuard::Shared(guard)) => drop(guard),
+            Some(LockGuard::Exclusive(guard)) => drop(guard),
+            None => {}
+        }
+        let mut locks = self.locks.lock().expect("lock is poisoned");
+        // the map and `self.lock` are the only references
+        if Arc::strong_count(&self.lock) == 2 {
+            locks.remove(&self.key);
+        }
+    }
+}
+
+/// Deterministic cipher of names, SIV style
+struct NameCipher {
+    cipher: XChaCha20Poly1305,
+    siv: Hmac<Sha256>,
+}
+
+impl<T> EncryptionAdapter<T>
+where
+    T: NfsReadFileSystem,
+{
+    /// Creates an adapter that encrypts the file contents with `key`
+    pub fn new(inner: T, key: &[u8; 32]) -> Self {
+        Self {
+            inner,
+            contents: XChaCha20Poly1305::new(&derive_key(key, b"contents").into()),
+            names: NameCipher {
+                cipher: XChaCha20Poly1305::new(&derive_key(key, b"names").into()),
+                siv: new_mac(&derive_key(key, b"name siv")),
+            },
+            encrypt_names: false,
+            file_locks: std::sync::Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Sets whether the names and the symbolic link targets are encrypted too
+    ///
+    /// An existing tree must be served with the setting that it was written with.
+    pub const fn set_name_encryption(&mut self, enabled: bool) {
+        self.encrypt_names = enabled;
+    }
+
+    /// Returns the cipher of the names if they are encrypted
+    const fn names(&self) -> Option<&NameCipher> {
+        if self.encrypt_names {
+            Some(&self.names)
+        } else {
+            None
+        }
+    }
+
+    /// Returns the wrapped file system
+    pub const fn inner(&self) -> &T {
+        &self.inner
+    }
+
+    fn encrypt_name<'a>(&self, name: &'a filename3<'a>) -> Result<filename3<'a>, nfsstat3> {
+        match self.names() {
+            Some(names) if !is_dot(name.as_ref()) => {
+                Ok(names.encrypt(name.as_ref())?.into_bytes().into())
+            }
+            _ => Ok(filename3::from(name.as_ref())),
+        }
+    }
+
+    /// Waits until nobody else reads or changes the file `id`
+    async fn lock_file(&self, id: &T::Handle) -> FileGuard<'_> {
+        let mut guard = self.file_guard(id);
+        let lock = Arc::clone(&guard.lock).write_owned().await;
+        guard.guard = Some(LockGuard::Exclusive(lock));
+        guard
+    }
+
+    /// Waits until no WRITE or SETATTR changes the file `id`
+    async fn lock_file_shared(&self, id: &T::Handle) -> FileGuard<'_> {
+        let mut guard = self.file_guard(id);
+        let lock = Arc::clone(&guard.lock).read_owned().await;
+        guard.guard = Some(LockGuard::Shared(lock));
+        guard
+    }
+
+    /// Returns an unlocked guard of the lock of `id`
+    fn file_guard(&self, id: &T::Handle) -> FileGuard<'_> {
+        let key = id.as_bytes().to_vec();
+        let lock = {
+            let mut locks = self.file_locks.lock().expect("lock is poisoned");
+            Arc::clone(locks.entry(key.clone()).or_default())
+        };
+        FileGuard {
+            locks: &self.file_locks,
+            key,
+            lock,
+            guard: None,
+        }
+    }
+
+    /// Returns the id stored at the start of a file, if the file has one
+    async fn file_id(&self, id: &T::Handle) -> Result<Option<FileId>, nfsstat3> {
+        let (data, _) = self.inner.read(id, 0, FILE_ID_LEN).await?;
+        Ok(data.try_into().ok())
+    }
+
+    /// Reads and decrypts the blocks `first..=last` of a file whose last block is `final_block`
+    async fn read_blocks(
+        &self,
+        id: &T::Handle,
+        file_id: &FileId,
+        first: u64,
+        last: u64,
+        final_block: u64,
+    ) -> Result<Vec<u8>, nfsstat3> {
+        let start = block_offset(first);
+        let len = block_offset(last + 1) - start;
+        let mut stored = Vec::new();
+        while (stored.len() as u64) < len {
+            let count = u32::try_from(len - stored.len() as u64).unwrap_or(u32::MAX);
+            let (data, eof) = self
+                .inner
+                .read(id, start + stored.len() as u64, count)
+                .await?;
+            stored.extend_from_slice(&data);
+            if eof || data.is_empty() {
+                break;
+            }
+        }
+
+        let mut plain = Vec::with_capacity(stored.len());
+        for (block, chunk) in (first..).zip(stored.chunks(STORED_BLOCK_LEN)) {
+            if chunk.len() < BLOCK_OVERHEAD {
+                warn!("block {block} is truncated");
+                return Err(nfsstat3::NFS3ERR_IO);
+            }
+            let (nonce, ciphertext) = chunk.split_at(NONCE_LEN);
+            let aad = block_aad(file_id, block, block == final_block);
+            let payload = Payload {
+                msg: ciphertext,
+                aad: &aad,
+            };
+            let data = self
+                .contents
+                .decrypt(XNonce::from_slice(nonce), payload)
+                .map_err(|_| {
+                    warn!("block {block} failed authentication");
+                    nfsstat3::NFS3ERR_IO
+                })?;
+            plain.extend_from_slice(&data);
+        }
+        Ok(plain)
+    }
+
+    /// Encrypts `plain`, which starts at the block `first`, in a file whose last block is
+    /// `final_block`
+    ///
+    /// An empty `plain` is sealed as one empty block, which is how an emptied file ends.
+    fn seal_blocks(
+        &self,
+        file_id: &FileId,
+        first: u64,
+        plain: &[u8],
+        final_block: u64,
+    ) -> Result<Vec<u8>, nfsstat3> {
+        let mut stored = Vec::with_capacity(
+            plain.len() + plain.len().div_ceil(BLOCK_LEN).max(1) * BLOCK_OVERHEAD,
+        );
+        let chunks = plain
+            .chunks(BLOCK_LEN)
+            .chain(plain.is_empty().then_some(plain));
+        for (block, chunk) in (first..).zip(chunks) {
+            let mut nonce = [0; NONCE_LEN];
+            getrandom::fill(&mut nonce).map_err(|err| {
+                warn!("failed to generate a nonce: {err}");
+                nfsstat3::NFS3ERR_IO
+            })?;
+            let aad = block_aad(file_id, block, block == final_block);
+            let payload = Payload {
+                msg: chunk,
+                aad: &aad,
+            };
+            let ciphertext = self
+                .contents
+                .encrypt(XNonce::from_slice(&nonce), payload)
+                .map_err(|_| nfsstat3::NFS3ERR_IO)?;
+            stored.extend_from_slice(&nonce);
+            stored.extend_from_slice(&ciphertext);
+        }
+        Ok(stored)
+    }
+}
+
+impl<T> EncryptionAdapter<T>
+where
+    T: NfsFileSystem,
+{
+    /// Writes `data` at `offset`, which must not be past the end of the file
+    async fn write_plain(
+        &self,
+        id: &T::Handle,
+        offset: u64,
+        data: &[u8],
+    ) -> Result<fattr3, nfsstat3> {
+        let attr = self.inner.getattr(id).await?;
+        let size = plaintext_size(attr.size);
+        if data.is_empty() {
+            return Ok(plain_attr(attr));
+        }
+        let new_size = size.max(offset + data.len() as u64);
+
+        let file_id = if let Some(file_id) = self.file_id(id).await? {
+            file_id
+        } else {
+            let mut file_id = FileId::default();
+            getrandom::fill(&mut file_id).map_err(|err| {
+                warn!("failed to generate a file id: {err}");
+                nfsstat3::NFS3ERR_IO
+            })?;
+            self.inner.write(id, 0, &file_id).await?;
+            file_id
+        };
+
+        let (mut first, mut start) = split_offset(offset);
+        if offset == size && start == 0 && size > 0 {
+            // the current last block isn't the last one anymore and must be sealed again
+            first -= 1;
+            start = BLOCK_LEN;
+        }
+        let mut plain = if first * BLOCK_SIZE < size {
+            let last = (size.min(offset + data.len() as u64) - 1) / BLOCK_SIZE;
+            self.read_blocks(id, &file_id, first, last, final_block(attr.size))
+                .await?
+        } else {
+            Vec::new()
+        };
+        if plain.len() < start + data.len() {
+            plain.resize(start + data.len(), 0);
+        }
+        plain[start..start + data.len()].copy_from_slice(data);
+
+        let stored = self.seal_blocks(&file_id, first, &plain, (new_size - 1) / BLOCK_SIZE)?;
+        let attr = self.inner.write(id, block_offset(first), &stored).await?;
+        Ok(plain_attr(attr))
+    }
+
+    /// Extends the file with zeros from `from` to `to`
+    async fn fill(&self, id: &T::Handle, mut from: u64, to: u64) -> Result<(), nfsstat3> {
+        let zeros = vec![0; FILL_LEN];
+        while from < to {
+            // keep the writes aligned, so that every block is sealed only once
+            let len = (FILL_LEN - split_offset(from).1)
+                .min(usize::try_from(to - from).unwrap_or(usize::MAX));
+            self.write_plain(id, from, &zeros[..len]).await?;
+            from += len as u64;
+        }
+        Ok(())
+    }
+
+    /// Changes the plaintext size of a file
+    async fn truncate(&self, id: &T::Handle, new_size: u64) -> Result<(), nfsstat3> {
+        let stored = self.inner.getattr(id).await?.size;
+        let size = plaintext_size(stored);
+        if new_size >= size {
+            return self.fill(id, size, new_size).await;
+        }
+
+        // the new last block is sealed again, marked as the last one
+        let (block, keep) = match split_offset(new_size) {
+            (block, 0) if block > 0 => (block - 1, BLOCK_LEN),
+            split => split,
+        };
+        let file_id = self.file_id(id).await?.ok_or(nfsstat3::NFS3ERR_IO)?;
+        let mut plain = if keep == 0 {
+            Vec::new()
+        } else {
+            self.read_blocks(id, &file_id, block, block, final_block(stored))
+                .await?
+        };
+        plain.truncate(keep);
+        let tail = self.seal_blocks(&file_id, block, &plain, block)?;
+        let stored_size = sattr3 {
+            size: Nfs3Option::Some(block_offset(block)),
+            ..Default::default()
+        };
+        self.inner.setattr(id, stored_size).await?;
+        self.inner.write(id, block_offset(block), &tail).await?;
+        Ok(())
+    }
+}
+
+impl NameCipher {
+    fn encrypt(&self, name: &[u8]) -> Result<String, nfsstat3> {
+        let mut mac = self.siv.clone();
+        mac.update(name);
+        let mut nonce = [0; NONCE_LEN];
+        nonce[..NAME_SIV_LEN].copy_from_slice(&mac.finalize().into_bytes()[..NAME_SIV_LEN]);
+        let ciphertext = self
+            .cipher
+            .encrypt(XNonce::from_slice(&nonce), name)
+            .map_err(|_| nfsstat3::NFS3ERR_NAMETOOLONG)?;
+
+        let mut stored = nonce[..NAME_SIV_LEN].to_vec();
+        stored.extend_from_slice(&ciphertext);
+        Ok(URL_SAFE_NO_PAD.encode(stored))
+    }
+
+    fn decrypt(&self, name: &[u8]) -> Option<Vec<u8>> {
+        let stored = URL_SAFE_NO_PAD.decode(name).ok()?;
+        if stored.len() < NAME_SIV_LEN {
+            return None;
+        }
+        let (siv, ciphertext) = stored.split_at(NAME_SIV_LEN);
+        let mut nonce = [0; NONCE_LEN];
+        nonce[..NAME_SIV_LEN].copy_from_slice(siv);
+        self.cipher
+            .decrypt(XNonce::from_slice(&nonce), ciphertext)
+            .ok()
+    }
+}
+
+fn new_mac(key: &[u8]) -> Hmac<Sha256> {
+    <Hmac<Sha256> as Mac>::new_from_slice(key).expect("HMAC accepts keys of any size")
+}
+
+fn derive_key(key: &[u8; 32], label: &[u8]) -> [u8; 32] {
+    let mut mac = new_mac(key);
+    mac.update(label);
+    mac.finalize().into_bytes().into()
+}
+
+fn is_dot(name: &[u8]) -> bool {
+    name == b"." || name == b".."
+}
+
+fn block_aad(file_id: &FileId, block: u64, last: bool) -> [u8; FILE_ID_LEN as usize + 9] {
+    let mut aad = [0; FILE_ID_LEN as usize + 9];
+    aad[..FILE_ID_LEN as usize].copy_from_slice(file_id);
+    aad[FILE_ID_LEN as usize..][..8].copy_from_slice(&block.to_le_bytes());
+    aad[FILE_ID_LEN as usize + 8] = u8::from(last);
+    aad
+}
+
+/// Splits a plaintext offset into the block number and the offset in the block
+#[allow(clippy::cast_possible_truncation)] // the offset in the block is less than BLOCK_LEN
+const fn split_offset(offset: u64) -> (u64, usize) {
+    (offset / BLOCK_SIZE, (offset % BLOCK_SIZE) as usize)
+}
+
+const fn block_offset(block: u64) -> u64 {
+    HEADER_LEN + block * STORED_BLOCK_SIZE
+}
+
+/// Returns the number of the last block of a file that takes `stored` bytes
+const fn final_block(stored: u64) -> u64 {
+    stored
+        .saturating_sub(HEADER_LEN)
+        .div_ceil(STORED_BLOCK_SIZE)
+        .saturating_sub(1)
+}
+
+/// Returns true if a file that takes `stored` bytes was cut short in the wrapped file system
+///
+/// A file with an id has at least one block, and every block has a nonce and a tag.
+const fn is_truncated(stored: u64) -> bool {
+    let data = stored.saturating_sub(HEADER_LEN);
+    let rest = data % STORED_BLOCK_SIZE;
+    stored != 0 && (data == 0 || (rest != 0 && rest < BLOCK_OVERHEAD as u64))
+}
+
+/// Returns the plaintext size of a file that takes `stored` bytes
+const fn plaintext_size(stored: u64) -> u64 {
+    let data = stored.saturating_sub(HEADER_LEN);
+    let rest = data % STORED_BLOCK_SIZE;
+    data / STORED_BLOCK_SIZE * BLOCK_SIZE + rest.saturating_sub(BLOCK_OVERHEAD as u64)
+}
+
+fn plain_attr(mut attr: fattr3) -> fattr3 {
+    if attr.type_ == ftype3::NF3REG {
+        attr.size = plaintext_size(attr.size);
+    }
+    attr
+}
+
+fn plain_post_op(attr: &mut post_op_attr) {
+    if let Nfs3Option::Some(attr) = attr {
+        *attr = plain_attr(attr.clone());
+    }
+}
+
+impl<T> NfsReadFileSystem for EncryptionAdapter<T>
+where
+    T: NfsReadFileSystem,
+{
+    type Handle = T::Handle;
+
+    fn root_dir(&self) -> Self::Handle {
+        self.inner.root_dir()
+    }
+
+    async fn lookup(
+        &self,
+        dirid: &Self::Handle,
+        filename: &filename3<'_>,
+    ) -> Result<Self::Handle, nfsstat3> {
+        let filename = self.encrypt_name(filename)?;
+        self.inner.lookup(dirid, &filename).await
+    }
+
+    // `lookup_by_path` uses the default implementation, which walks the path with `lookup`
+
+    async fn getattr(&self, id: &Self::Handle) -> Result<fattr3, nfsstat3> {
+        self.inner.getattr(id).await.map(plain_attr)
+    }
+
+    async fn read(
+        &self,
+        id: &Self::Handle,
+        offset: u64,
+        count: u32,
+    ) -> Result<(Vec<u8>, bool), nfsstat3> {
+        // a concurrent WRITE can leave the last block half written
+        let _guard = self.lock_file_shared(id).await;
+        let stored = self.inner.getattr(id).await?.size;
+        if is_truncated(stored) {
+            warn!("file is truncated");
+            return Err(nfsstat3::NFS3ERR_IO);
+        }
+        let size = plaintext_size(stored);
+        if offset >= size || count == 0 {
+            return Ok((Vec::new(), offset >= size));
+        }
+        let end = size.min(offset + u64::from(count));
+        let file_id = self.file_id(id).await?.ok_or(nfsstat3::NFS3ERR_IO)?;
+        let (first, start) = split_offset(offset);
+        let plain = self
+            .read_blocks(
+                id,
+                &file_id,
+                first,
+                (end - 1) / BLOCK_SIZE,
+                final_block(stored),
+            )
+            .await?;
+        // `end - offset` is at most `count`
+        let len = u32::try_from(end - offset).unwrap_or(count) as usize;
+        if plain.len() < start + len {
+            warn!("file is shorter than its size");
+            return Err(nfsstat3::NFS3ERR_IO);
+        }
+        Ok((plain[start..start + len].to_vec(), end == size))
+    }
+
+    async fn readdir(
+        &self,
+        dirid: &Self::Handle,
+        cookie: u64,
+    ) -> Result<impl ReadDirIterator, nfsstat3> {
+        let inner = self.inner.readdir(dirid, cookie).await?;
+        Ok(DecryptIterator {
+            inner,
+            names: self.names(),
+        })
+    }
+
+    async fn readdirplus(
+        &self,
+        dirid: &Self::Handle,
+        cookie: u64,
+    ) -> Result<impl ReadDirPlusIterator<Self::Handle>, nfsstat3> {
+        let inner = self.inner.readdirplus(dirid, cookie).await?;
+        Ok(DecryptIterator {
+            inner,
+            names: self.names(),
+        })
+    }
+
+    async fn cookieverf(&self, dirid: &Self::Handle) -> Result<cookieverf3, nfsstat3> {
+        self.inner.cookieverf(dirid).await
+    }
+
+    async fn getacl(&self, id: &Self::Handle) -> Result<PosixAcl, nfsstat3> {
+        self.inner.getacl(id).await
+    }
+
+    async fn readlink(&self, id: &Self::Handle) -> Result<nfspath3<'_>, nfsstat3> {
+        let target = self.inner.readlink(id).await?;
+        let Some(names) = self.names() else {
+            return Ok(target);
+        };
+        names
+            .decrypt(target.as_ref())
+            .map(nfspath3::from)
+            .ok_or_else(|| {
+                warn!("failed to decrypt a symbolic link");
+                nfsstat3::NFS3ERR_IO
+            })
+    }
+
+    async fn fsinfo(&self, root_fileid: &Self::Handle) -> Result<fsinfo3, nfsstat3> {
+        let mut info = self.inner.fsinfo(root_fileid).await?;
+        plain_post_op(&mut info.obj_attributes);
+        Ok(info)
+    }
+
+    async fn fsstat(&self, root_fileid: &Self::Handle) -> Result<fsstat3, nfsstat3> {
+        let mut stat = self.inner.fsstat(root_fileid).await?;
+        plain_post_op(&mut stat.obj_attributes);
+        Ok(stat)
+    }
+
+    async fn pathconf(&self, id: &Self::Handle) -> Result<pathconf3, nfsstat3> {
+        let mut conf = self.inner.pathconf(id).await?;
+        plain_post_op(&mut conf.obj_attributes);
+        Ok(conf)
+    }
+}
+
+impl<T> NfsFileSystem for EncryptionAdapter<T>
+where
+    T: NfsFileSystem,
+{
+    fn capabilities(&self) -> VFSCapabilities {
+        self.inner.capabilities()
+    }
+
+    async fn setattr(&self, id: &Self::Handle, mut setattr: sattr3) -> Result<fattr3, nfsstat3> {
+        let _guard = self.lock_file(id).await;
+        if let Nfs3Option::Some(size) = setattr.size {
+            if self.inner.getattr(id).await?.type_ == ftype3::NF3REG {
+                self.truncate(id, size).await?;
+                setattr.size = Nfs3Option::None;
+            }
+        }
+        self.inner.setattr(id, setattr).await.map(plain_attr)
+    }
+
+    async fn write(&self, id: &Self::Handle, offset: u64, data: &[u8]) -> Result<fattr3, nfsstat3> {
+        let _guard = self.lock_file(id).await;
+        let size = plaintext_size(self.inner.getattr(id).await?.size);
+        if offset > size {
+            self.fill(id, size, offset).await?;
+        }
+        self.write_plain(id, offset, data).await
+    }
+
+    fn buffers_writes(&self) -> bool {
+        self.inner.buffers_writes()
+    }
+
+    async fn commit(&self, id: &Self::Handle) -> Result<fattr3, nfsstat3> {
+        self.inner.commit(id).await.map(plain_attr)
+    }
+
+    async fn create(
+        &self,
+        dirid: &Self::Handle,
+        filename: &filename3<'_>,
+        mut attr: sattr3,
+    ) -> Result<(Self::Handle, fattr3), nfsstat3> {
+        let filename = self.encrypt_name(filename)?;
+        let size = std::mem::replace(&mut attr.size, Nfs3Option::None);
+        let (id, attr) = self.inner.create(dirid, &filename, attr).await?;
+        match size {
+            Nfs3Option::Some(size) if size > 0 => {
+                let _guard = self.lock_file(&id).await;
+                self.truncate(&id, size).await?;
+                let attr = self.inner.getattr(&id).await?;
+                Ok((id, plain_attr(attr)))
+            }
+            _ => Ok((id, plain_attr(attr))),
+        }
+    }
+
+    async fn create_exclusive(
+        &self,
+        dirid: &Self::Handle,
+        filename: &filename3<'_>,
+        createverf: createverf3,
+    ) -> Result<Self::Handle, nfsstat3> {
+        let filename = self.encrypt_name(filename)?;
+        self.inner
+            .create_exclusive(dirid, &filename, createverf)
+            .await
+    }
+
+    async fn mkdir(
+        &self,
+        dirid: &Self::Handle,
+        dirname: &filename3<'_>,
+    ) -> Result<(Self::Handle, fattr3), nfsstat3> {
+        let dirname = self.encrypt_name(dirname)?;
+        self.inner.mkdir(dirid, &dirname).await
+    }
+
+    async fn remove(&self, dirid: &Self::Handle, filename: &filename3<'_>) -> Result<(), nfsstat3> {
+        let filename = self.encrypt_name(filename)?;
+        self.inner.remove(dirid, &filename).await
+    }
+
+    async fn rename<'a>(
+        &self,
+        from_dirid: &Self::Handle,
+        from_filename: &filename3<'a>,
+        to_dirid: &Self::Handle,
+        to_filename: &filename3<'a>,
+    ) -> Result<(), nfsstat3> {
+        let from_filename = self.encrypt_name(from_filename)?;
+        let to_filename = self.encrypt_name(to_filename)?;
+        self.inner
+            .rename(from_dirid, &from_filename, to_dirid, &to_filename)
+            .await
+    }
+
+    async fn symlink<'a>(
+        &self,
+        dirid: &Self::Handle,
+        linkname: &filename3<'a>,
+        symlink: &nfspath3<'a>,
+        attr: &sattr3,
+    ) -> Result<(Self::Handle, fattr3), nfsstat3> {
+        let linkname = self.encrypt_name(linkname)?;
+        let symlink = match self.names() {
+            Some(names) => nfspath3::from(names.encrypt(symlink.as_ref())?.into_bytes()),
+            None => nfspath3::from(symlink.as_ref()),
+        };
+        self.inner.symlink(dirid, &linkname, &symlink, attr).await
+    }
+
+    async fn setacl(&self, id: &Self::Handle, acl: PosixAcl) -> Result<(), nfsstat3> {
+        self.inner.setacl(id, acl).await
+    }
+}
+
+/// Decrypts the names and reports the plaintext sizes of a listing
+struct DecryptIterator<'a, I> {
+    inner: I,
+    names: Option<&'a NameCipher>,
+}
+
+impl<I> DecryptIterator<'_, I> {
+    /// Returns the plaintext of `name`, or `None` if the entry should be skipped
+    fn decrypt_name(&self, name: &[u8]) -> Option<Vec<u8>> {
+        match self.names {
+            Some(_) if is_dot(name) => Some(name.to_vec()),
+            Some(names) => {
+                let plain = names.decrypt(name);
+                if plain.is_none() {
+                    debug!("skipping {:?}", String::from_utf8_lossy(name));
+                }
+                plain
+            }
+            None => Some(name.to_vec()),
+        }
+    }
+}
+
+impl<I> ReadDirIterator for DecryptIterator<'_, I>
+where
+    I: ReadDirIterator,
+{
+    async fn next(&mut self) -> NextResult<DirEntry> {
+        loop {
+            match self.inner.next().await {
+                NextResult::Ok(mut entry) => {
+                    if let Some(name) = self.decrypt_name(entry.name.as_ref()) {
+                        entry.name = name.into();
+                        return NextResult::Ok(entry);
+                    }
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+impl<H, I> ReadDirPlusIterator<H> for DecryptIterator<'_, I>
+where
+    H: FileHandle,
+    I: ReadDirPlusIterator<H>,
+{
+    async fn next(&mut self) -> NextResult<DirEntryPlus<H>> {
+        loop {
+            match self.inner.next().await {
+                NextResult::Ok(mut entry) => {
+                    if let Some(name) = self.decrypt_name(entry.name.as_ref()) {
+                        entry.name = name.into();
+                        entry.name_attributes = entry.name_attributes.map(plain_attr);
+                        return NextResult::Ok(entry);
+                    }
+                }
+                result => return result,
+            }
+        }
+    }
+}
@@ -0,0 +1,717 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use nfs3_types::nfs3::{
+    FSINFO3resok as fsinfo3, FSSTAT3resok as fsstat3, PATHCONF3resok as pathconf3, cookieverf3,
+    createverf3, fattr3, filename3, nfspath3, nfsstat3, sattr3,
+};
+use tracing::{debug, error};
+
+use super::pattern::glob_match;
+use crate::runtime::Runtime;
+use crate::vfs::{
+    DirEntryPlus, FileHandle, NextResult, NfsFileSystem, NfsReadFileSystem, PosixAcl,
+    ReadDirIterator, ReadDirPlusIterator, VFSCapabilities,
+};
+
+const DEFAULT_PATH_CAPACITY: usize = 65536;
+
+/// A file system call, e.g. the one that a [`FaultRule`] applies to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FsOperation {
+    Lookup,
+    LookupByPath,
+    Getattr,
+    /// Both `read` and `read_into`
+    Read,
+    Readdir,
+    Readdirplus,
+    Cookieverf,
+    Getacl,
+    Readlink,
+    Fsinfo,
+    Fsstat,
+    Pathconf,
+    Setattr,
+    Write,
+    Commit,
+    Create,
+    CreateExclusive,
+    Mkdir,
+    Remove,
+    Rename,
+    Symlink,
+    Setacl,
+}
+
+/// A fault injected by [`FaultInjectionAdapter`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fault {
+    /// Fails the call with the given status without calling the wrapped file system
+    Error(nfsstat3),
+    /// Delays the call
+    Delay(Duration),
+    /// Returns at most the given number of bytes from a read, other calls are not affected
+    ShortRead(u32),
+}
+
+/// Describes when a [`Fault`] is injected
+///
+/// By default the rule applies to every call. It can be narrowed down to some operations,
+/// to the paths that match a pattern and to a fraction of the calls.
+#[derive(Debug, Clone)]
+pub struct FaultRule {
+    fault: Fault,
+    operations: Vec<FsOperation>,
+    pattern: Option<String>,
+    probability: f64,
+}
+
+impl FaultRule {
+    /// Creates a rule that injects `fault` into every call
+    #[must_use]
+    pub const fn new(fault: Fault) -> Self {
+        Self {
+            fault,
+            operations: Vec::new(),
+            pattern: None,
+            probability: 1.0,
+        }
+    }
+
+    /// Limits the rule to `operation`. Can be called several times to add more operations.
+    #[must_use]
+    pub fn on(mut self, operation: FsOperation) -> Self {
+        self.operations.push(operation);
+        self
+    }
+
+    /// Limits the rule to the objects whose path matches `pattern`
+    ///
+    /// Paths are absolute, e.g. `/dir/file.txt`. In the pattern, `?` and `*` match a character
+    /// and a part of a path component, `**` matches across components. Calls that create or
+    /// remove directory entries are matched by the path of the entry, `rename` by either of its
+    /// paths.
+    #[must_use]
+    pub fn matching(mut self, pattern: impl Into<String>) -> Self {
+        self.pattern = Some(pattern.into());
+        self
+    }
+
+    /// Injects the fault only into a fraction of the matching calls, from 0.0 to 1.0
+    #[must_use]
+    pub const fn with_probability(mut self, probability: f64) -> Self {
+        self.probability = probability;
+        self
+    }
+
+    fn applies_to(&self, operation: FsOperation, paths: &[Option<String>]) -> bool {
+        if !self.operations.is_empty() && !self.operations.contains(&operation) {
+            return false;
+        }
+        self.pattern.as_ref().is_none_or(|pattern| {
+            paths
+                .iter()
+                .flatten()
+                .any(|path| glob_match(pattern.as_bytes(), path.as_bytes()))
+        })
+    }
+}
+
+/// Injects errors, latencies and short reads into the calls to the wrapped file system.
+///
+/// It's meant for testing how clients behave when the server misbehaves. The rules are
+/// checked in the order they were added and all the matching ones apply: the delays add up,
+/// the first error wins and the shortest read limit is used. Delays run before the call.
+///
+/// Rules with a probability below 1.0 use a pseudo-random generator seeded by the caller,
+/// so a run with the same sequence of calls injects the same faults.
+///
+/// To match rules by path, the adapter remembers the path of every handle it has seen in
+/// the results of `lookup`, `readdirplus` and the calls that create objects, as long as some
+/// rule has a path pattern. Handles it hasn't seen, or has forgotten because of the
+/// [path capacity](Self::with_path_capacity), don't match any pattern.
+pub struct FaultInjectionAdapter<T: NfsReadFileSystem> {
+    inner: T,
+    rules: Vec<FaultRule>,
+    rng: Mutex<u64>,
+    paths: Arc<PathMap>,
+    runtime: Option<Arc<dyn Runtime>>,
+    injected: AtomicU64,
+}
+
+impl<T> FaultInjectionAdapter<T>
+where
+    T: NfsReadFileSystem,
+{
+    /// Creates an adapter without rules, `seed` initializes the random generator
+    pub fn new(inner: T, seed: u64) -> Self {
+        let paths = PathMap::new(&inner.root_dir());
+        Self {
+            inner,
+            rules: Vec::new(),
+            rng: Mutex::new(seed),
+            paths: Arc::new(paths),
+            runtime: crate::runtime::default_runtime(),
+            injected: AtomicU64::new(0),
+        }
+    }
+
+    /// Adds a rule
+    pub fn add_rule(&mut self, rule: FaultRule) {
+        if rule.pattern.is_some() {
+            self.paths.enable();
+        }
+        self.rules.push(rule);
+    }
+
+    /// Sets how many paths of handles are remembered for the rules with a path pattern
+    ///
+    /// When the limit is reached, the path recorded first is forgotten, so its handle no
+    /// longer matches any pattern until it's looked up again. Defaults to 65536.
+    pub fn with_path_capacity(&mut self, capacity: usize) {
+        self.paths.set_capacity(capacity);
+    }
+
+    /// Sets the runtime used to wait for the injected delays
+    ///
+    /// By default the runtime is selected in the same way as for the server. Without a
+    /// runtime, calls that should be delayed fail with `NFS3ERR_SERVERFAULT`.
+    pub fn with_runtime(&mut self, runtime: impl Runtime) {
+        self.runtime = Some(Arc::new(runtime));
+    }
+
+    /// Returns the wrapped file system
+    pub const fn inner(&self) -> &T {
+        &self.inner
+    }
+
+    /// Returns the number of injected faults
+    pub fn injected(&self) -> u64 {
+        self.injected.load(Ordering::Relaxed)
+    }
+
+    /// Returns a pseudo-random number in `[0, 1)`
+    fn random(&self) -> f64 {
+        // splitmix64
+        let mut state = self.rng.lock().expect("lock is poisoned");
+        *state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = *state;
+        drop(state);
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^= z >> 31;
+        #[expect(clippy::cast_precision_loss, reason = "53 bits fit into f64")]
+        let value = (z >> 11) as f64 / (1u64 << 53) as f64;
+        value
+    }
+
+    /// Applies the matching rules, returns the read limit if there is one
+    async fn inject(
+        &self,
+        operation: FsOperation,
+        paths: &[Option<String>],
+    ) -> Result<Option<u32>, nfsstat3> {
+        let mut delay = Duration::ZERO;
+        let mut error = None;
+        let mut limit: Option<u32> = None;
+        for rule in &self.rules {
+            if !rule.applies_to(operation, paths) {
+                continue;
+            }
+            if rule.probability < 1.0 && self.random() >= rule.probability {
+                continue;
+            }
+            debug!("injecting {:?} into {operation:?} of {paths:?}", rule.fault);
+            self.injected.fetch_add(1, Ordering::Relaxed);
+            match rule.fault {
+                Fault::Error(stat) => {
+                    error.get_or_insert(stat);
+                }
+                Fault::Delay(duration) => delay += duration,
+                Fault::ShortRead(max) => {
+                    limit = Some(limit.map_or(max, |limit| limit.min(max)));
+                }
+            }
+        }
+
+        if !delay.is_zero() {
+            let Some(runtime) = &self.runtime else {
+                error!("no async runtime is configured to delay {operation:?}");
+                return Err(nfsstat3::NFS3ERR_SERVERFAULT);
+            };
+            runtime.sleep(delay).await;
+        }
+        error.map_or(Ok(limit), Err)
+    }
+
+    async fn inject_obj(&self, operation: FsOperation, id: &T::Handle) -> Result<(), nfsstat3> {
+        if self.rules.is_empty() {
+            return Ok(());
+        }
+        self.inject(operation, &[self.paths.get(id)]).await?;
+        Ok(())
+    }
+
+    async fn inject_entry(
+        &self,
+        operation: FsOperation,
+        dirid: &T::Handle,
+        name: &filename3<'_>,
+    ) -> Result<(), nfsstat3> {
+        if self.rules.is_empty() {
+            return Ok(());
+        }
+        self.inject(operation, &[self.paths.child(dirid, name)])
+            .await?;
+        Ok(())
+    }
+
+    async fn read_limit(&self, id: &T::Handle) -> Result<Option<u32>, nfsstat3> {
+        if self.rules.is_empty() {
+            return Ok(None);
+        }
+        self.inject(FsOperation::Read, &[self.paths.get(id)]).await
+    }
+}
+
+impl<T> NfsReadFileSystem for FaultInjectionAdapter<T>
+where
+    T: NfsReadFileSystem,
+{
+    type Handle = T::Handle;
+
+    fn root_dir(&self) -> Self::Handle {
+        self.inner.root_dir()
+    }
+
+    async fn lookup(
+        &self,
+        dirid: &Self::Handle,
+        filename: &filename3<'_>,
+    ) -> Result<Self::Handle, nfsstat3> {
+        self.inject_entry(FsOperation::Lookup, dirid, filename)
+            .await?;
+        let id = self.inner.lookup(dirid, filename).await?;
+        self.paths.insert_child(dirid, filename, &id);
+        Ok(id)
+    }
+
+    async fn lookup_by_path(&self, path: &str) -> Result<Self::Handle, nfsstat3> {
+        let normalized = normalize(path);
+        if !self.rules.is_empty() {
+            self.inject(FsOperation::LookupByPath, &[Some(normalized.clone())])
+                .await?;
+        }
+        let id = self.inner.lookup_by_path(path).await?;
+        self.paths.insert(&id, normalized);
+        Ok(id)
+    }
+
+    async fn getattr(&self, id: &Self::Handle) -> Result<fattr3, nfsstat3> {
+        self.inject_obj(FsOperation::Getattr, id).await?;
+        self.inner.getattr(id).await
+    }
+
+    async fn read(
+        &self,
+        id: &Self::Handle,
+        offset: u64,
+        count: u32,
+    ) -> Result<(Vec<u8>, bool), nfsstat3> {
+        let limit = self.read_limit(id).await?;
+        let count = limit.map_or(count, |limit| count.min(limit));
+        self.inner.read(id, offset, count).await
+    }
+
+    async fn read_into(
+        &self,
+        id: &Self::Handle,
+        offset: u64,
+        buf: &mut [u8],
+    ) -> Result<(usize, bool), nfsstat3> {
+        let limit = self.read_limit(id).await?;
+        let len = limit.map_or(buf.len(), |limit| buf.len().min(limit as usize));
+        self.inner.read_into(id, offset, &mut buf[..len]).await
+    }
+
+    async fn readdir(
+        &self,
+        dirid: &Self::Handle,
+        cookie: u64,
+    ) -> Result<impl ReadDirIterator, nfsstat3> {
+        self.inject_obj(FsOperation::Readdir, dirid).await?;
+        self.inner.readdir(dirid, cookie).await
+    }
+
+    async fn readdirplus(
+        &self,
+        dirid: &Self::Handle,
+        cookie: u64,
+    ) -> Result<impl ReadDirPlusIterator<Self::Handle>, nfsstat3> {
+        self.inject_obj(FsOperation::Readdirplus, dirid).await?;
+        let inner = self.inner.readdirplus(dirid, cookie).await?;
+        Ok(PathRecorder {
+            inner,
+            dir: self.paths.get(dirid),
+            paths: Arc::clone(&self.paths),
+        })
+    }
+
+    async fn cookieverf(&self, dirid: &Self::Handle) -> Result<cookieverf3, nfsstat3> {
+        self.inject_obj(FsOperation::Cookieverf, dirid).await?;
+        self.inner.cookieverf(dirid).await
+    }
+
+    async fn getacl(&self, id: &Self::Handle) -> Result<PosixAcl, nfsstat3> {
+        self.inject_obj(FsOperation::Getacl, id).await?;
+        self.inner.getacl(id).await
+    }
+
+    async fn readlink(&self, id: &Self::Handle) -> Result<nfspath3<'_>, nfsstat3> {
+        self.inject_obj(FsOperation::Readlink, id).await?;
+        self.inner.readlink(id).await
+    }
+
+    async fn fsinfo(&self, root_fileid: &Self::Handle) -> Result<fsinfo3, nfsstat3> {
+        self.inject_obj(FsOperation::Fsinfo, root_fileid).await?;
+        self.inner.fsinfo(root_fileid).await
+    }
+
+    async fn fsstat(&self, root_fileid: &Self::Handle) -> Result<fsstat3, nfsstat3> {
+        self.inject_obj(FsOperation::Fsstat, root_fileid).await?;
+        self.inner.fsstat(root_fileid).await
+    }
+
+    async fn pathconf(&self, id: &Self::Handle) -> Result<pathconf3, nfsstat3> {
+        self.inject_obj(FsOperation::Pathconf, id).await?;
+        self.inner.pathconf(id).await
+    }
+}
+
+impl<T> NfsFileSystem for FaultInjectionAdapter<T>
+where
+    T: NfsFileSystem,
+{
+    fn capabilities(&self) -> VFSCapabilities {
+        self.inner.capabilities()
+    }
+
+    async fn setattr(&self, id: &Self::Handle, setattr: sattr3) -> Result<fattr3, nfsstat3> {
+        self.inject_obj(FsOperation::Setattr, id).await?;
+        self.inner.setattr(id, setattr).await
+    }
+
+    async fn write(&self, id: &Self::Handle, offset: u64, data: &[u8]) -> Result<fattr3, nfsstat3> {
+        self.inject_obj(FsOperation::Write, id).await?;
+        self.inner.write(id, offset, data).await
+    }
+
+    fn buffers_writes(&self) -> bool {
+        self.inner.buffers_writes()
+    }
+
+    async fn commit(&self, id: &Self::Handle) -> Result<fattr3, nfsstat3> {
+        self.inject_obj(FsOperation::Commit, id).await?;
+        self.inner.commit(id).await
+    }
+
+    async fn create(
+        &self,
+        dirid: &Self::Handle,
+        filename: &filename3<'_>,
+        attr: sattr3,
+    ) -> Result<(Self::Handle, fattr3), nfsstat3> {
+        self.inject_entry(FsOperation::Create, dirid, filename)
+            .await?;
+        let result = self.inner.create(dirid, filename, attr).await?;
+        self.paths.insert_child(dirid, filename, &result.0);
+        Ok(result)
+    }
+
+    async fn create_exclusive(
+        &self,
+        dirid: &Self::Handle,
+        filename: &filename3<'_>,
+        createverf: createverf3,
+    ) -> Result<Self::Handle, nfsstat3> {
+        self.inject_entry(FsOperation::CreateExclusive, dirid, filename)
+            .await?;
+        let id = self
+            .inner
+            .create_exclusive(dirid, filename, createverf)
+            .await?;
+        self.paths.insert_child(dirid, filename, &id);
+        Ok(id)
+    }
+
+    async fn mkdir(
+        &self,
+        dirid: &Self::Handle,
+        dirname: &filename3<'_>,
+    ) -> Result<(Self::Handle, fattr3), nfsstat3> {
+        self.inject_entry(FsOperation::Mkdir, dirid, dirname)
+            .await?;
+        let result = self.inner.mkdir(dirid, dirname).await?;
+        self.paths.insert_child(dirid, dirname, &result.0);
+        Ok(result)
+    }
+
+    async fn remove(&self, dirid: &Self::Handle, filename: &filename3<'_>) -> Result<(), nfsstat3> {
+        self.inject_entry(FsOperation::Remove, dirid, filename)
+            .await?;
+        self.inner.remove(dirid, filename).await?;
+        if let Some(path) = self.paths.child(dirid, filename) {
+            self.paths.remove_tree(&path);
+        }
+        Ok(())
+    }
+
+    async fn rename<'a>(
+        &self,
+        from_dirid: &Self::Handle,
+        from_filename: &filename3<'a>,
+        to_dirid: &Self::Handle,
+        to_filename: &filename3<'a>,
+    ) -> Result<(), nfsstat3> {
+        let from = self.paths.child(from_dirid, from_filename);
+        let to = self.paths.child(to_dirid, to_filename);
+        if !self.rules.is_empty() {
+            self.inject(FsOperation::Rename, &[from.clone(), to.clone()])
+                .await?;
+        }
+        self.inner
+            .rename(from_dirid, from_filename, to_dirid, to_filename)
+            .await?;
+        if let Some(to) = &to {
+            self.paths.remove_tree(to);
+        }
+        match (from, to) {
+            (Some(from), Some(to)) => self.paths.move_tree(&from, &to),
+            (Some(from), None) => self.paths.remove_tree(&from),
+            _ => {}
+        }
+        Ok(())
+    }
+
+    async fn symlink<'a>(
+        &self,
+        dirid: &Self::Handle,
+        linkname: &filename3<'a>,
+        symlink: &nfspath3<'a>,
+        attr: &sattr3,
+    ) -> Result<(Self::Handle, fattr3), nfsstat3> {
+        self.inject_entry(FsOperation::Symlink, dirid, linkname)
+            .await?;
+        let result = self.inner.symlink(dirid, linkname, symlink, attr).await?;
+        self.paths.insert_child(dirid, linkname, &result.0);
+        Ok(result)
+    }
+
+    async fn setacl(&self, id: &Self::Handle, acl: PosixAcl) -> Result<(), nfsstat3> {
+        self.inject_obj(FsOperation::Setacl, id).await?;
+        self.inner.setacl(id, acl).await
+    }
+}
+
+/// Paths of the handles seen by the adapter
+///
+/// Nothing is recorded until a rule with a path pattern is added. The map holds at most
+/// `capacity` handles besides the root, the one recorded first is evicted when it's full.
+struct PathMap(Mutex<PathState>);
+
+struct PathState {
+    enabled: bool,
+    capacity: usize,
+    root: Vec<u8>,
+    /// Path and insertion sequence number of every handle
+    by_handle: HashMap<Vec<u8>, (String, u64)>,
+    /// Handles by path, sorted so that a subtree is a range
+    by_path: BTreeMap<String, Vec<u8>>,
+    /// Handles by insertion sequence number, oldest first
+    by_age: BTreeMap<u64, Vec<u8>>,
+    next_seq: u64,
+}
+
+impl PathMap {
+    fn new<H: FileHandle>(root: &H) -> Self {
+        let root = root.as_bytes().to_vec();
+        let mut state = PathState {
+            enabled: false,
+            capacity: DEFAULT_PATH_CAPACITY,
+            root: root.clone(),
+            by_handle: HashMap::new(),
+            by_path: BTreeMap::new(),
+            by_age: BTreeMap::new(),
+            next_seq: 0,
+        };
+        state.insert(root, "/".to_owned());
+        Self(Mutex::new(state))
+    }
+
+    fn state(&self) -> std::sync::MutexGuard<'_, PathState> {
+        self.0.lock().expect("lock is poisoned")
+    }
+
+    fn enable(&self) {
+        self.state().enabled = true;
+    }
+
+    fn set_capacity(&self, capacity: usize) {
+        let mut state = self.state();
+        state.capacity = capacity;
+        state.evict();
+    }
+
+    fn get<H: FileHandle>(&self, id: &H) -> Option<String> {
+        let state = self.state();
+        state
+            .by_handle
+            .get(id.as_bytes())
+            .map(|(path, _)| path.clone())
+    }
+
+    fn child<H: FileHandle>(&self, dirid: &H, name: &filename3<'_>) -> Option<String> {
+        self.get(dirid).map(|dir| join(&dir, name))
+    }
+
+    fn insert<H: FileHandle>(&self, id: &H, path: String) {
+        let mut state = self.state();
+        if state.enabled {
+            state.insert(id.as_bytes().to_vec(), path);
+            state.evict();
+        }
+    }
+
+    fn insert_child<H: FileHandle>(&self, dirid: &H, name: &filename3<'_>, id: &H) {
+        // "." and ".." refer to objects that are recorded under their own paths
+        if matches!(name.as_ref(), b"." | b"..") {
+            return;
+        }
+        if let Some(path) = self.child(dirid, name) {
+            self.insert(id, path);
+        }
+    }
+
+    fn remove_tree(&self, path: &str) {
+        let mut state = self.state();
+        for (_, id) in state.take_tree(path) {
+            if let Some((_, seq)) = state.by_handle.remove(&id) {
+                state.by_age.remove(&seq);
+            }
+        }
+    }
+
+    fn move_tree(&self, from: &str, to: &str) {
+        let mut state = self.state();
+        for (path, id) in state.take_tree(from) {
+            let path = format!("{to}{}", &path[from.len()..]);
+            state.insert(id, path);
+        }
+    }
+}
+
+impl PathState {
+    fn insert(&mut self, id: Vec<u8>, path: String) {
+        let seq = self.next_seq;
+        self.next_seq += 1;
+        if let Some((old_path, old_seq)) = self.by_handle.remove(&id) {
+            self.by_path.remove(&old_path);
+            self.by_age.remove(&old_seq);
+        }
+        // another handle recorded under this path is outdated
+        if let Some(old_id) = self.by_path.insert(path.clone(), id.clone()) {
+            if let Some((_, old_seq)) = self.by_handle.remove(&old_id) {
+                self.by_age.remove(&old_seq);
+            }
+        }
+        if id != self.root {
+            self.by_age.insert(seq, id.clone());
+        }
+        self.by_handle.insert(id, (path, seq));
+    }
+
+    fn evict(&mut self) {
+        while self.by_age.len() > self.capacity {
+            let Some((_, id)) = self.by_age.pop_first() else {
+                break;
+            };
+            if let Some((path, _)) = self.by_handle.remove(&id) {
+                self.by_path.remove(&path);
+            }
+        }
+    }
+
+    /// Removes `root` and the paths below it from `by_path`
+    fn take_tree(&mut self, root: &str) -> Vec<(String, Vec<u8>)> {
+        let mut tree = Vec::new();
+        if let Some(id) = self.by_path.remove(root) {
+            tree.push((root.to_owned(), id));
+        }
+        let prefix = if root.ends_with('/') {
+            root.to_owned()
+        } else {
+            format!("{root}/")
+        };
+        let below: Vec<String> = self
+            .by_path
+            .range(prefix.clone()..)
+            .map(|(path, _)| path)
+            .take_while(|path| path.starts_with(&prefix))
+            .cloned()
+            .collect();
+        for path in below {
+            if let Some(id) = self.by_path.remove(&path) {
+                tree.push((path, id));
+            }
+        }
+        tree
+    }
+}
+
+fn join(dir: &str, name: &filename3<'_>) -> String {
+    let name = String::from_utf8_lossy(name.as_ref());
+    if dir.ends_with('/') {
+        format!("{dir}{name}")
+    } else {
+        format!("{dir}/{name}")
+    }
+}
+
+fn normalize(path: &str) -> String {
+    let components = path.split('/').filter(|c| !c.is_empty());
+    let mut normalized = String::new();
+    for component in components {
+        normalized.push('/');
+        normalized.push_str(component);
+    }
+    if normalized.is_empty() {
+        normalized.push('/');
+    }
+    normalized
+}
+
+/// Records the paths of the entries returned by `readdirplus`
+struct PathRecorder<I> {
+    inner: I,
+    dir: Option<String>,
+    paths: Arc<PathMap>,
+}
+
+impl<H, I> ReadDirPlusIterator<H> for PathRecorder<I>
+where
+    H: FileHandle,
+    I: ReadDirPlusIterator<H>,
+{
+    async fn next(&mut self) -> NextResult<DirEntryPlus<H>> {
+        let result = self.inner.next().await;
+        if let (NextResult::Ok(entry), Some(dir)) = (&result, &self.dir) {
+            if let Some(id) = &entry.name_handle {
+                self.paths.insert(id, join(dir, &entry.name));
+            }
+        }
+        result
+    }
+}
@@ -0,0 +1,344 @@
+use nfs3_types::nfs3::{
+    FSINFO3resok as fsinfo3, FSSTAT3resok as fsstat3, PATHCONF3resok as pathconf3, cookieverf3,
+    createverf3, fattr3, filename3, nfspath3, nfsstat3, sattr3,
+};
+use tracing::debug;
+
+use super::pattern::glob_match;
+use crate::vfs::{
+    DirEntry, DirEntryPlus, FileHandle, NextResult, NfsFileSystem, NfsReadFileSystem, PosixAcl,
+    ReadDirIterator, ReadDirPlusIterator, VFSCapabilities,
+};
+
+/// A pattern of the names hidden by [`NameFilterAdapter`]
+///
+/// Patterns are matched against a single name, not against the path, so `.git` hides every
+/// `.git` directory of the tree.
+#[derive(Debug, Clone)]
+pub enum NamePattern {
+    /// Shell-style pattern that must match the whole name, `*` matches any number of
+    /// characters and `?` a single one, e.g. `.env*`
+    Glob(String),
+    /// Regular expression that must match a part of the name, e.g. `^secrets?$`
+    #[cfg(feature = "regex")]
+    Regex(regex::bytes::Regex),
+}
+
+impl NamePattern {
+    /// Creates a shell-style pattern
+    pub fn glob(pattern: impl Into<String>) -> Self {
+        Self::Glob(pattern.into())
+    }
+
+    /// Creates a pattern from a regular expression
+    #[cfg(feature = "regex")]
+    pub fn regex(pattern: &str) -> Result<Self, regex::Error> {
+        regex::bytes::Regex::new(pattern).map(Self::Regex)
+    }
+
+    fn matches(&self, name: &[u8]) -> bool {
+        match self {
+            Self::Glob(pattern) => glob_match(pattern.as_bytes(), name),
+            #[cfg(feature = "regex")]
+            Self::Regex(regex) => regex.is_match(name),
+        }
+    }
+}
+
+/// Hides the names that match any of the configured patterns.
+///
+/// Hidden entries are skipped in READDIR and READDIRPLUS replies, LOOKUP and REMOVE of them
+/// fail with `NFS3ERR_NOENT`, and creating or renaming an object onto a hidden name fails
+/// with `NFS3ERR_ACCES`. The listing keeps the cookies of the wrapped file system, so a
+/// listing can resume after any entry that was returned. `.` and `..` are never hidden.
+/// Names that contain `/` are rejected with `NFS3ERR_INVAL`, so that a backend that joins the
+/// name to a path can't be led to a hidden object through `./.git` or `sub/../.git`.
+///
+/// Since the objects under a hidden directory can't be looked up, clients can reach them
+/// only with handles they got elsewhere. Enable
+/// [`NFSServer::with_handle_secret`][1] so that clients can't forge them.
+///
+/// [1]: crate::server::NFSServer::with_handle_secret
+pub struct NameFilterAdapter<T> {
+    inner: T,
+    patterns: Vec<NamePattern>,
+}
+
+impl<T> NameFilterAdapter<T>
+where
+    T: NfsReadFileSystem,
+{
+    /// Creates an adapter that doesn't hide anything yet
+    pub const fn new(inner: T) -> Self {
+        Self {
+            inner,
+            patterns: Vec::new(),
+        }
+    }
+
+    /// Hides the names that match `pattern`
+    pub fn add_pattern(&mut self, pattern: NamePattern) {
+        self.patterns.push(pattern);
+    }
+
+    /// Returns the wrapped file system
+    pub const fn inner(&self) -> &T {
+        &self.inner
+    }
+
+    /// Returns true if `name`, or any component of it if it contains `/`, is hidden
+    pub fn is_hidden(&self, name: &[u8]) -> bool {
+        is_hidden(&self.patterns, name)
+    }
+
+    fn check_visible(&self, name: &filename3<'_>) -> Result<(), nfsstat3> {
+        check_name(name)?;
+        if self.is_hidden(name.as_ref()) {
+            debug!("{:?} is hidden", String::from_utf8_lossy(name.as_ref()));
+            return Err(nfsstat3::NFS3ERR_NOENT);
+        }
+        Ok(())
+    }
+
+    fn check_allowed(&self, name: &filename3<'_>) -> Result<(), nfsstat3> {
+        check_name(name)?;
+        if self.is_hidden(name.as_ref()) {
+            debug!("{:?} is hidden", String::from_utf8_lossy(name.as_ref()));
+            return Err(nfsstat3::NFS3ERR_ACCES);
+        }
+        Ok(())
+    }
+}
+
+fn check_name(name: &filename3<'_>) -> Result<(), nfsstat3> {
+    if name.as_ref().contains(&b'/') {
+        debug!("{:?} is not a name", String::from_utf8_lossy(name.as_ref()));
+        return Err(nfsstat3::NFS3ERR_INVAL);
+    }
+    Ok(())
+}
+
+fn is_hidden(patterns: &[NamePattern], name: &[u8]) -> bool {
+    name.split(|&b| b == b'/').any(|component| {
+        component != b"."
+            && component != b".."
+            && patterns.iter().any(|pattern| pattern.matches(component))
+    })
+}
+
+impl<T> NfsReadFileSystem for NameFilterAdapter<T>
+where
+    T: NfsReadFileSystem,
+{
+    type Handle = T::Handle;
+
+    fn root_dir(&self) -> Self::Handle {
+        self.inner.root_dir()
+    }
+
+    async fn lookup(
+        &self,
+        dirid: &Self::Handle,
+        filename: &filename3<'_>,
+    ) -> Result<Self::Handle, nfsstat3> {
+        self.check_visible(filename)?;
+        self.inner.lookup(dirid, filename).await
+    }
+
+    // `lookup_by_path` uses the default implementation, which walks the path with `lookup`
+
+    async fn getattr(&self, id: &Self::Handle) -> Result<fattr3, nfsstat3> {
+        self.inner.getattr(id).await
+    }
+
+    async fn read(
+        &self,
+        id: &Self::Handle,
+        offset: u64,
+        count: u32,
+    ) -> Result<(Vec<u8>, bool), nfsstat3> {
+        self.inner.read(id, offset, count).await
+    }
+
+    async fn read_into(
+        &self,
+        id: &Self::Handle,
+        offset: u64,
+        buf: &mut [u8],
+    ) -> Result<(usize, bool), nfsstat3> {
+        self.inner.read_into(id, offset, buf).await
+    }
+
+    async fn readdir(
+        &self,
+        dirid: &Self::Handle,
+        cookie: u64,
+    ) -> Result<impl ReadDirIterator, nfsstat3> {
+        let inner = self.inner.readdir(dirid, cookie).await?;
+        Ok(FilterIterator {
+            inner,
+            patterns: &self.patterns,
+        })
+    }
+
+    async fn readdirplus(
+        &self,
+        dirid: &Self::Handle,
+        cookie: u64,
+    ) -> Result<impl ReadDirPlusIterator<Self::Handle>, nfsstat3> {
+        let inner = self.inner.readdirplus(dirid, cookie).await?;
+        Ok(FilterIterator {
+            inner,
+            patterns: &self.patterns,
+        })
+    }
+
+    async fn cookieverf(&self, dirid: &Self::Handle) -> Result<cookieverf3, nfsstat3> {
+        self.inner.cookieverf(dirid).await
+    }
+
+    async fn getacl(&self, id: &Self::Handle) -> Result<PosixAcl, nfsstat3> {
+        self.inner.getacl(id).await
+    }
+
+    async fn readlink(&self, id: &Self::Handle) -> Result<nfspath3<'_>, nfsstat3> {
+        self.inner.readlink(id).await
+    }
+
+    async fn fsinfo(&self, root_fileid: &Self::Handle) -> Result<fsinfo3, nfsstat3> {
+        self.inner.fsinfo(root_fileid).await
+    }
+
+    async fn fsstat(&self, root_fileid: &Self::Handle) -> Result<fsstat3, nfsstat3> {
+        self.inner.fsstat(root_fileid).await
+    }
+
+    async fn pathconf(&self, id: &Self::Handle) -> Result<pathconf3, nfsstat3> {
+        self.inner.pathconf(id).await
+    }
+}
+
+impl<T> NfsFileSystem for NameFilterAdapter<T>
+where
+    T: NfsFileSystem,
+{
+    fn capabilities(&self) -> VFSCapabilities {
+        self.inner.capabilities()
+    }
+
+    async fn setattr(&self, id: &Self::Handle, setattr: sattr3) -> Result<fattr3, nfsstat3> {
+        self.inner.setattr(id, setattr).await
+    }
+
+    async fn write(&self, id: &Self::Handle, offset: u64, data: &[u8]) -> Result<fattr3, nfsstat3> {
+        self.inner.write(id, offset, data).await
+    }
+
+    fn buffers_writes(&self) -> bool {
+        self.inner.buffers_writes()
+    }
+
+    async fn commit(&self, id: &Self::Handle) -> Result<fattr3, nfsstat3> {
+        self.inner.commit(id).await
+    }
+
+    async fn create(
+        &self,
+        dirid: &Self::Handle,
+        filename: &filename3<'_>,
+        attr: sattr3,
+    ) -> Result<(Self::Handle, fattr3), nfsstat3> {
+        self.check_allowed(filename)?;
+        self.inner.create(dirid, filename, attr).await
+    }
+
+    async fn create_exclusive(
+        &self,
+        dirid: &Self::Handle,
+        filename: &filename3<'_>,
+        createverf: createverf3,
+    ) -> Result<Self::Handle, nfsstat3> {
+        self.check_allowed(filename)?;
+        self.inner
+            .create_exclusive(dirid, filename, createverf)
+            .await
+    }
+
+    async fn mkdir(
+        &self,
+        dirid: &Self::Handle,
+        dirname: &filename3<'_>,
+    ) -> Result<(Self::Handle, fattr3), nfsstat3> {
+        self.check_allowed(dirname)?;
+        self.inner.mkdir(dirid, dirname).await
+    }
+
+    async fn remove(&self, dirid: &Self::Handle, filename: &filename3<'_>) -> Result<(), nfsstat3> {
+        self.check_visible(filename)?;
+        self.inner.remove(dirid, filename).await
+    }
+
+    async fn rename<'a>(
+        &self,
+        from_dirid: &Self::Handle,
+        from_filename: &filename3<'a>,
+        to_dirid: &Self::Handle,
+        to_filename: &filename3<'a>,
+    ) -> Result<(), nfsstat3> {
+        self.check_visible(from_filename)?;
+        self.check_allowed(to_filename)?;
+        self.inner
+            .rename(from_dirid, from_filename, to_dirid, to_filename)
+            .await
+    }
+
+    async fn symlink<'a>(
+        &self,
+        dirid: &Self::Handle,
+        linkname: &filename3<'a>,
+        symlink: &nfspath3<'a>,
+        attr: &sattr3,
+    ) -> Result<(Self::Handle, fattr3), nfsstat3> {
+        self.check_allowed(linkname)?;
+        self.inner.symlink(dirid, linkname, symlink, attr).await
+    }
+
+    async fn setacl(&self, id: &Self::Handle, acl: PosixAcl) -> Result<(), nfsstat3> {
+        self.inner.setacl(id, acl).await
+    }
+}
+
+/// Skips the hidden entries of a listing
+struct FilterIterator<'a, I> {
+    inner: I,
+    patterns: &'a [NamePattern],
+}
+
+impl<I> ReadDirIterator for FilterIterator<'_, I>
+where
+    I: ReadDirIterator,
+{
+    async fn next(&mut self) -> NextResult<DirEntry> {
+        loop {
+            match self.inner.next().await {
+                NextResult::Ok(entry) if is_hidden(self.patterns, entry.name.as_ref()) => {}
+                result => return result,
+            }
+        }
+    }
+}
+
+impl<H, I> ReadDirPlusIterator<H> for FilterIterator<'_, I>
+where
+    H: FileHandle,
+    I: ReadDirPlusIterator<H>,
+{
+    async fn next(&mut self) -> NextResult<DirEntryPlus<H>> {
+        loop {
+            match self.inner.next().await {
+                NextResult::Ok(entry) if is_hidden(self.patterns, entry.name.as_ref()) => {}
+                result => return result,
+            }
+        }
+    }
+}
@@ -1,12 +1,40 @@
 //! Adapters VFS types
 
+mod blocking;
+mod caching;
+mod case;
+#[cfg(feature = "encryption")]
+mod encryption;
+mod fault;
+mod filter;
 mod iterator;
-
+mod overlay;
+mod pattern;
+mod quota;
+mod stack;
+mod subtree;
+
+pub use blocking::BlockingAdapter;
+pub use caching::{CacheCounters, CacheStats, CachingAdapter};
+pub use case::{CaseFolding, CaseInsensitiveAdapter};
+#[cfg(feature = "encryption")]
+#[cfg_attr(docsrs, doc(cfg(feature = "encryption")))]
+pub use encryption::EncryptionAdapter;
+pub use fault::{Fault, FaultInjectionAdapter, FaultRule, FsOperation};
+pub use filter::{NameFilterAdapter, NamePattern};
 pub use iterator::ReadDirPlusToReadDir;
-use nfs3_types::nfs3::{fattr3, filename3, nfsstat3, sattr3};
+use nfs3_types::nfs3::{
+    FSSTAT3resok as fsstat3, PATHCONF3resok as pathconf3, cookieverf3, fattr3, filename3, nfsstat3,
+    sattr3,
+};
+use nfs3_types::nfsacl::ACL_WRITE;
+pub use overlay::{OverlayAdapter, OverlayHandle};
+pub use quota::{QuotaAdapter, QuotaLimits, QuotaUsage};
+pub use stack::{FsRequest, HookLayer, Hooked, Hooks, Layer, Stack};
+pub use subtree::SubtreeAdapter;
 
 use super::{
-    DirEntryPlus, NextResult, NfsFileSystem, NfsReadFileSystem, ReadDirIterator,
+    DirEntryPlus, NextResult, NfsFileSystem, NfsReadFileSystem, PosixAcl, ReadDirIterator,
     ReadDirPlusIterator, VFSCapabilities,
 };
 use crate::vfs::FileHandle;
@@ -14,9 +42,9 @@ use crate::vfs::FileHandle;
 /// An internal adapter that allows to reuse the same code with `ReadOnly` filesystems.
 ///
 /// In general, you should not use this adapter directly. Instead, use the
-/// [`NFSTcpListener::bind_ro`][1] method to bind a read-only NFS server.
+/// `NFSTcpListener::bind_ro` or [`NFSServer::new_ro`][1] method to create a read-only NFS server.
 ///
-/// [1]: crate::tcp::NFSTcpListener::bind_ro
+/// [1]: crate::server::NFSServer::new_ro
 pub struct ReadOnlyAdapter<T>(T);
 
 impl<T> ReadOnlyAdapter<T>
@@ -63,6 +91,15 @@ where
         self.0.read(id, offset, count).await
     }
 
+    async fn read_into(
+        &self,
+        id: &Self::Handle,
+        offset: u64,
+        buf: &mut [u8],
+    ) -> Result<(usize, bool), nfsstat3> {
+        self.0.read_into(id, offset, buf).await
+    }
+
     async fn readdir(
         &self,
         dirid: &Self::Handle,
@@ -82,12 +119,32 @@ where
             .map(ReadOnlyIterator)
     }
 
+    async fn cookieverf(&self, dirid: &Self::Handle) -> Result<cookieverf3, nfsstat3> {
+        self.0.cookieverf(dirid).await
+    }
+
+    async fn getacl(&self, id: &Self::Handle) -> Result<PosixAcl, nfsstat3> {
+        let mut acl = self.0.getacl(id).await?;
+        for entry in acl.access.iter_mut().chain(acl.default.iter_mut()) {
+            entry.perm &= !ACL_WRITE;
+        }
+        Ok(acl)
+    }
+
     async fn readlink(
         &self,
         id: &Self::Handle,
     ) -> Result<nfs3_types::nfs3::nfspath3<'_>, nfsstat3> {
         self.0.readlink(id).await
     }
+
+    async fn fsstat(&self, root_fileid: &Self::Handle) -> Result<fsstat3, nfsstat3> {
+        self.0.fsstat(root_fileid).await
+    }
+
+    async fn pathconf(&self, id: &Self::Handle) -> Result<pathconf3, nfsstat3> {
+        self.0.pathconf(id).await
+    }
 }
 
 impl<T> NfsFileSystem for ReadOnlyAdapter<T>
@@ -164,6 +221,10 @@ where
     ) -> Result<(Self::Handle, fattr3), nfsstat3> {
         Err(nfsstat3::NFS3ERR_ROFS)
     }
+
+    async fn setacl(&self, _id: &Self::Handle, _acl: PosixAcl) -> Result<(), nfsstat3> {
+        Err(nfsstat3::NFS3ERR_ROFS)
+    }
 }
 
 #[derive(Debug)]
@@ -0,0 +1,1164 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::Mutex;
+
+use nfs3_types::nfs3::{
+    FSINFO3resok as fsinfo3, FSSTAT3resok as fsstat3, PATHCONF3resok as pathconf3, cookieverf3,
+    createverf3, fattr3, filename3, ftype3, nfspath3, nfsstat3, post_op_attr, sattr3, set_atime,
+    set_gid3, set_mode3, set_mtime, set_uid3,
+};
+use tracing::{debug, error};
+
+use crate::vfs::{
+    DirEntryPlus, FileHandle, NextResult, NfsFileSystem, NfsReadFileSystem, PosixAcl,
+    ReadDirPlusIterator, VFSCapabilities,
+};
+
+/// Names starting with this prefix are reserved for whiteouts in the upper layer
+const WHITEOUT_PREFIX: &[u8] = b".wh.";
+
+/// Marks the cookies of the entries that come from the lower layer
+const LOWER_COOKIE: u64 = 1 << 63;
+
+/// Size of the chunks used to copy a file to the upper layer
+const COPY_CHUNK: u32 = 1024 * 1024;
+
+const FLAG_UPPER: u8 = 1;
+const FLAG_LOWER: u8 = 2;
+
+/// Handle of an [`OverlayAdapter`] object
+///
+/// It contains the handles of the object in the layers it lives in. Directories that exist in
+/// both layers and files that were copied up have both. The encoding is a flags byte, then the
+/// length and the bytes of the upper handle, then the bytes of the lower handle, so the inner
+/// handles together can take up to 53 bytes (less if the server reserves space for itself,
+/// see [`FileHandle`]).
+#[derive(Clone)]
+pub struct OverlayHandle<U, L> {
+    upper: Option<U>,
+    lower: Option<L>,
+    bytes: Vec<u8>,
+}
+
+impl<U: FileHandle, L: FileHandle> OverlayHandle<U, L> {
+    fn new(upper: Option<U>, lower: Option<L>) -> Self {
+        let mut bytes = vec![0];
+        if let Some(upper) = &upper {
+            bytes[0] |= FLAG_UPPER;
+            bytes.push(u8::try_from(upper.len()).expect("handle is too long"));
+            bytes.extend_from_slice(upper.as_bytes());
+        }
+        if let Some(lower) = &lower {
+            bytes[0] |= FLAG_LOWER;
+            bytes.extend_from_slice(lower.as_bytes());
+        }
+        Self {
+            upper,
+            lower,
+            bytes,
+        }
+    }
+
+    /// Returns the handle of the object in the upper layer
+    pub const fn upper(&self) -> Option<&U> {
+        self.upper.as_ref()
+    }
+
+    /// Returns the handle of the object in the lower layer
+    pub const fn lower(&self) -> Option<&L> {
+        self.lower.as_ref()
+    }
+
+    /// Key that stays the same when a lower object is copied up
+    fn identity(&self) -> Vec<u8> {
+        match (&self.upper, &self.lower) {
+            (_, Some(lower)) => [&[FLAG_LOWER], lower.as_bytes()].concat(),
+            (Some(upper), None) => [&[FLAG_UPPER], upper.as_bytes()].concat(),
+            (None, None) => Vec::new(),
+        }
+    }
+}
+
+impl<U: FileHandle, L: FileHandle> std::fmt::Debug for OverlayHandle<U, L> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("OverlayHandle")
+            .field("upper", &self.upper)
+            .field("lower", &self.lower)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<U: FileHandle, L: FileHandle> FileHandle for OverlayHandle<U, L> {
+    fn len(&self) -> usize {
+        self.bytes.len()
+    }
+
+    fn as_bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        let (&flags, mut rest) = bytes.split_first()?;
+        if flags == 0 || flags & !(FLAG_UPPER | FLAG_LOWER) != 0 {
+            return None;
+        }
+        let upper = if flags & FLAG_UPPER == 0 {
+            None
+        } else {
+            let (&len, tail) = rest.split_first()?;
+            let (upper, tail) = tail.split_at_checked(usize::from(len))?;
+            rest = tail;
+            Some(U::from_bytes(upper)?)
+        };
+        let lower = if flags & FLAG_LOWER == 0 {
+            if !rest.is_empty() {
+                return None;
+            }
+            None
+        } else {
+            Some(L::from_bytes(rest)?)
+        };
+        Some(Self {
+            upper,
+            lower,
+            bytes: bytes.to_vec(),
+        })
+    }
+}
+
+/// Combines a read-only lower layer with a writable upper layer.
+///
+/// The directories of both layers are merged, and the upper layer wins when both contain a
+/// file with the same name. Changes are only made to the upper layer:
+///
+/// - writing to a file or changing its attributes copies the file from the lower layer to
+///   the upper one first, together with the missing parent directories
+/// - removing an object that exists in the lower layer creates a whiteout file named
+///   `.wh.<name>` next to it in the upper layer. Names with the `.wh.` prefix are hidden and
+///   can't be created by the clients.
+/// - renaming a directory that exists in the lower layer fails with `NFS3ERR_XDEV`
+///
+/// READDIRPLUS lists the upper directory first and then the entries of the lower one that
+/// aren't hidden. The cookies of the lower entries have the highest bit set, so both layers
+/// must return cookies below 2<sup>63</sup>. The file ids are derived from the ids of the
+/// lower layer if the object exists there and from the upper layer otherwise, shifted left by
+/// one bit so that they don't collide. The top bit of the original id is lost, so both layers
+/// must use file ids below 2<sup>63</sup> as well.
+///
+/// The adapter keeps track of the parents of the objects it has seen, to know where to copy
+/// them, and of the copied objects, so that the handles issued before a copy-up keep working.
+/// This state lives in memory only: after a restart, clients have to look up lower objects
+/// again before changing them. Entries are only dropped when their objects are removed or
+/// renamed, so the memory use grows with the number of distinct objects the clients visit.
+pub struct OverlayAdapter<U, L>
+where
+    U: NfsFileSystem,
+    L: NfsReadFileSystem,
+{
+    upper: U,
+    lower: L,
+    state: Mutex<OverlayState<U::Handle, L::Handle>>,
+    // serializes copy-ups, so that the same object isn't copied twice
+    copy_up: tokio::sync::Mutex<()>,
+}
+
+struct OverlayState<U, L> {
+    /// Upper copies of the lower objects, by lower handle
+    copied: HashMap<Vec<u8>, U>,
+    /// Lower origins of the copied objects, by upper handle
+    origins: HashMap<Vec<u8>, L>,
+    /// Lower objects that were removed
+    removed: HashSet<Vec<u8>>,
+    /// Parent directory and name of the objects, by identity
+    parents: HashMap<Vec<u8>, (OverlayHandle<U, L>, filename3<'static>)>,
+}
+
+/// An overlay handle with the copy-ups applied
+struct Resolved<U, L> {
+    upper: Option<U>,
+    lower: Option<L>,
+}
+
+type Handle<U, L> =
+    OverlayHandle<<U as NfsReadFileSystem>::Handle, <L as NfsReadFileSystem>::Handle>;
+
+impl<U, L> OverlayAdapter<U, L>
+where
+    U: NfsFileSystem,
+    L: NfsReadFileSystem,
+{
+    /// Creates an overlay of `upper` on top of `lower`
+    pub fn new(upper: U, lower: L) -> Self {
+        Self {
+            upper,
+            lower,
+            state: Mutex::new(OverlayState {
+                copied: HashMap::new(),
+                origins: HashMap::new(),
+                removed: HashSet::new(),
+                parents: HashMap::new(),
+            }),
+            copy_up: tokio::sync::Mutex::new(()),
+        }
+    }
+
+    /// Returns the upper layer
+    pub const fn upper(&self) -> &U {
+        &self.upper
+    }
+
+    /// Returns the lower layer
+    pub const fn lower(&self) -> &L {
+        &self.lower
+    }
+
+    fn state(&self) -> std::sync::MutexGuard<'_, OverlayState<U::Handle, L::Handle>> {
+        self.state.lock().expect("lock is poisoned")
+    }
+
+    fn resolve(&self, id: &Handle<U, L>) -> Result<Resolved<U::Handle, L::Handle>, nfsstat3> {
+        let mut upper = id.upper.clone();
+        if let Some(lower) = &id.lower {
+            let state = self.state();
+            if state.removed.contains(lower.as_bytes()) {
+                return Err(nfsstat3::NFS3ERR_STALE);
+            }
+            if upper.is_none() {
+                upper = state.copied.get(lower.as_bytes()).cloned();
+            }
+        }
+        if upper.is_none() && id.lower.is_none() {
+            return Err(nfsstat3::NFS3ERR_BADHANDLE);
+        }
+        Ok(Resolved {
+            upper,
+            lower: id.lower.clone(),
+        })
+    }
+
+    fn remember_parent(&self, id: &Handle<U, L>, dirid: &Handle<U, L>, name: &filename3<'_>) {
+        self.state()
+            .parents
+            .insert(id.identity(), (dirid.clone(), name.clone_to_owned()));
+    }
+
+    fn remember_copy(&self, upper: &U::Handle, lower: &L::Handle) {
+        let mut state = self.state();
+        state
+            .copied
+            .insert(lower.as_bytes().to_vec(), upper.clone());
+        state
+            .origins
+            .insert(upper.as_bytes().to_vec(), lower.clone());
+    }
+
+    fn origin(&self, upper: &U::Handle) -> Option<L::Handle> {
+        self.state().origins.get(upper.as_bytes()).cloned()
+    }
+
+    /// Sets the overlay file id on the attributes of the upper or lower layer
+    async fn finish_attr(
+        &self,
+        mut attr: fattr3,
+        upper: bool,
+        lower: Option<&L::Handle>,
+    ) -> Result<fattr3, nfsstat3> {
+        attr.fileid = match lower {
+            Some(lower) if upper => lower_fileid(self.lower.getattr(lower).await?.fileid),
+            Some(_) => lower_fileid(attr.fileid),
+            None => upper_fileid(attr.fileid),
+        };
+        Ok(attr)
+    }
+
+    /// Returns true if there is a whiteout for `name` in the upper directory
+    async fn is_whiteout(
+        &self,
+        upper_dir: &U::Handle,
+        name: &filename3<'_>,
+    ) -> Result<bool, nfsstat3> {
+        match self.upper.lookup(upper_dir, &whiteout(name)).await {
+            Ok(_) => Ok(true),
+            Err(nfsstat3::NFS3ERR_NOENT) => Ok(false),
+            Err(stat) => Err(stat),
+        }
+    }
+
+    async fn create_whiteout(
+        &self,
+        upper_dir: &U::Handle,
+        name: &filename3<'_>,
+    ) -> Result<(), nfsstat3> {
+        let attr = sattr3 {
+            mode: set_mode3::Some(0),
+            ..sattr3::default()
+        };
+        match self.upper.create(upper_dir, &whiteout(name), attr).await {
+            Ok(_) | Err(nfsstat3::NFS3ERR_EXIST) => Ok(()),
+            Err(stat) => Err(stat),
+        }
+    }
+
+    /// Removes the whiteouts from an upper directory, so that it can be removed
+    async fn remove_whiteouts(&self, upper_dir: &U::Handle) -> Result<(), nfsstat3> {
+        let mut names = Vec::new();
+        let mut iter = self.upper.readdirplus(upper_dir, 0).await?;
+        loop {
+            match iter.next().await {
+                NextResult::Ok(entry) if is_whiteout(&entry.name) => names.push(entry.name),
+                NextResult::Ok(_) => {}
+                NextResult::Eof => break,
+                NextResult::Err(stat) => return Err(stat),
+            }
+        }
+        drop(iter);
+        for name in names {
+            self.upper.remove(upper_dir, &name).await?;
+        }
+        Ok(())
+    }
+
+    /// Returns true if the merged directory has no entries
+    async fn is_empty_dir(&self, dirid: &Handle<U, L>) -> Result<bool, nfsstat3> {
+        let mut iter = self.readdirplus(dirid, 0).await?;
+        loop {
+            match iter.next().await {
+                NextResult::Ok(entry) if is_dot(&entry.name) => {}
+                NextResult::Ok(_) => return Ok(false),
+                NextResult::Eof => return Ok(true),
+                NextResult::Err(stat) => return Err(stat),
+            }
+        }
+    }
+
+    /// Returns the handle of the object in the upper layer, copying it there if needed
+    async fn copy_up(&self, id: &Handle<U, L>) -> Result<U::Handle, nfsstat3> {
+        if let Some(upper) = self.resolve(id)?.upper {
+            return Ok(upper);
+        }
+        let _guard = self.copy_up.lock().await;
+        // someone else could have copied it while we were waiting
+        if let Some(upper) = self.resolve(id)?.upper {
+            return Ok(upper);
+        }
+
+        // the objects to copy, from the nearest one to the farthest ancestor
+        let mut chain = Vec::new();
+        let mut current = id.clone();
+        let mut upper_dir = loop {
+            let Some((parent, name)) = self.state().parents.get(&current.identity()).cloned()
+            else {
+                debug!("unknown parent of {current:?}, can't copy it up");
+                return Err(nfsstat3::NFS3ERR_STALE);
+            };
+            let lower = current.lower.clone().ok_or(nfsstat3::NFS3ERR_STALE)?;
+            chain.push((lower, name));
+            if let Some(upper) = self.resolve(&parent)?.upper {
+                break upper;
+            }
+            current = parent;
+        };
+
+        for (lower, name) in chain.into_iter().rev() {
+            upper_dir = self.copy_object(&upper_dir, &lower, &name).await?;
+            self.remember_copy(&upper_dir, &lower);
+        }
+        Ok(upper_dir)
+    }
+
+    /// Copies a single lower object into an upper directory
+    ///
+    /// If the copy fails after the upper object was created, the object is removed again.
+    async fn copy_object(
+        &self,
+        upper_dir: &U::Handle,
+        lower: &L::Handle,
+        name: &filename3<'_>,
+    ) -> Result<U::Handle, nfsstat3> {
+        let attr = self.lower.getattr(lower).await?;
+        debug!("copying up {name:?} ({:?})", attr.type_);
+        let initial = initial_attr(&attr);
+        let (upper, created) = match attr.type_ {
+            ftype3::NF3DIR => match self.upper.mkdir(upper_dir, name).await {
+                Ok((upper, _)) => (upper, true),
+                // created by the upper layer in the meantime
+                Err(nfsstat3::NFS3ERR_EXIST) => (self.upper.lookup(upper_dir, name).await?, false),
+                Err(stat) => return Err(stat),
+            },
+            ftype3::NF3REG => (self.upper.create(upper_dir, name, initial).await?.0, true),
+            ftype3::NF3LNK => {
+                let target = self.lower.readlink(lower).await?.clone_to_owned();
+                let (upper, _) = self
+                    .upper
+                    .symlink(upper_dir, name, &target, &initial)
+                    .await?;
+                (upper, true)
+            }
+            _ => return Err(nfsstat3::NFS3ERR_NOTSUPP),
+        };
+
+        if let Err(stat) = self.copy_contents(&upper, lower, &attr, created).await {
+            // a partial copy would hide the lower object, so it's removed
+            if created {
+                if let Err(e) = self.upper.remove(upper_dir, name).await {
+                    error!("failed to remove the partial copy of {name:?}: {e:?}");
+                }
+            }
+            return Err(stat);
+        }
+        Ok(upper)
+    }
+
+    /// Copies the data, the ACL and the times of a lower object to its new upper copy
+    async fn copy_contents(
+        &self,
+        upper: &U::Handle,
+        lower: &L::Handle,
+        attr: &fattr3,
+        created: bool,
+    ) -> Result<(), nfsstat3> {
+        match attr.type_ {
+            ftype3::NF3DIR if created => {
+                self.upper.setattr(upper, initial_attr(attr)).await?;
+            }
+            ftype3::NF3REG => {
+                let mut offset = 0;
+                loop {
+                    let (data, eof) = self.lower.read(lower, offset, COPY_CHUNK).await?;
+                    if !data.is_empty() {
+                        self.upper.write(upper, offset, &data).await?;
+                        offset += data.len() as u64;
+                    }
+                    if eof || data.is_empty() {
+                        break;
+                    }
+                }
+            }
+            _ => {}
+        }
+
+        let acl = self.lower.getacl(lower).await?;
+        if acl != PosixAcl::default() {
+            match self.upper.setacl(upper, acl).await {
+                Ok(()) | Err(nfsstat3::NFS3ERR_NOTSUPP) => {}
+                Err(stat) => return Err(stat),
+            }
+        }
+        let times = sattr3 {
+            atime: set_atime::SET_TO_CLIENT_TIME(attr.atime),
+            mtime: set_mtime::SET_TO_CLIENT_TIME(attr.mtime),
+            ..sattr3::default()
+        };
+        if attr.type_ != ftype3::NF3LNK {
+            self.upper.setattr(upper, times).await?;
+        }
+        Ok(())
+    }
+
+    /// Looks up an entry, returns `None` if it doesn't exist
+    async fn find(
+        &self,
+        dirid: &Handle<U, L>,
+        name: &filename3<'_>,
+    ) -> Result<Option<Handle<U, L>>, nfsstat3> {
+        match self.lookup(dirid, name).await {
+            Ok(id) => Ok(Some(id)),
+            Err(nfsstat3::NFS3ERR_NOENT) => Ok(None),
+            Err(stat) => Err(stat),
+        }
+    }
+
+    /// Wraps a new upper object in a handle
+    fn new_upper(
+        &self,
+        dirid: &Handle<U, L>,
+        name: &filename3<'_>,
+        upper: U::Handle,
+        lower: Option<L::Handle>,
+    ) -> Handle<U, L> {
+        let id = OverlayHandle::new(Some(upper), lower);
+        self.remember_parent(&id, dirid, name);
+        id
+    }
+
+    /// Builds the "." or ".." entry of a directory from the handle that LOOKUP returns, so
+    /// that both name the same merged object
+    async fn dot_entry(
+        &self,
+        dir: &ListedDir<U::Handle, L::Handle>,
+        name: filename3<'static>,
+        cookie: u64,
+    ) -> Result<DirEntryPlus<Handle<U, L>>, nfsstat3> {
+        let id = self.lookup(&dir.id, &name).await?;
+        let attr = self.getattr(&id).await?;
+        Ok(DirEntryPlus {
+            fileid: attr.fileid,
+            name,
+            cookie,
+            name_attributes: Some(attr),
+            name_handle: Some(id),
+        })
+    }
+
+    /// Converts an entry of the upper directory
+    async fn upper_entry(
+        &self,
+        dir: &ListedDir<U::Handle, L::Handle>,
+        entry: DirEntryPlus<U::Handle>,
+    ) -> Result<DirEntryPlus<Handle<U, L>>, nfsstat3> {
+        if entry.cookie & LOWER_COOKIE != 0 {
+            error!("cookie {} of the upper layer is too large", entry.cookie);
+            return Err(nfsstat3::NFS3ERR_SERVERFAULT);
+        }
+        if is_dot(&entry.name) {
+            return self.dot_entry(dir, entry.name, entry.cookie).await;
+        }
+        let upper = match (entry.name_handle, &dir.upper) {
+            (Some(upper), _) => upper,
+            (None, Some(upper_dir)) => self.upper.lookup(upper_dir, &entry.name).await?,
+            (None, None) => return Err(nfsstat3::NFS3ERR_SERVERFAULT),
+        };
+
+        let mut lower = self.origin(&upper);
+        if lower.is_none() {
+            if let Some(lower_dir) = &dir.lower {
+                if !dir.upper_names.contains(whiteout(&entry.name).as_ref()) {
+                    lower = match self.lower.lookup(lower_dir, &entry.name).await {
+                        Ok(lower) => Some(lower),
+                        Err(nfsstat3::NFS3ERR_NOENT) => None,
+                        Err(stat) => return Err(stat),
+                    };
+                }
+            }
+        }
+
+        let fileid = match &lower {
+            Some(lower) => lower_fileid(self.lower.getattr(lower).await?.fileid),
+            None => upper_fileid(entry.fileid),
+        };
+        let id = OverlayHandle::new(Some(upper), lower);
+        self.remember_parent(&id, &dir.id, &entry.name);
+        Ok(DirEntryPlus {
+            fileid,
+            name: entry.name,
+            cookie: entry.cookie,
+            name_attributes: entry.name_attributes.map(|mut attr| {
+                attr.fileid = fileid;
+                attr
+            }),
+            name_handle: Some(id),
+        })
+    }
+
+    /// Converts an entry of the lower directory
+    async fn lower_entry(
+        &self,
+        dir: &ListedDir<U::Handle, L::Handle>,
+        entry: DirEntryPlus<L::Handle>,
+    ) -> Result<DirEntryPlus<Handle<U, L>>, nfsstat3> {
+        if entry.cookie & LOWER_COOKIE != 0 {
+            error!("cookie {} of the lower layer is too large", entry.cookie);
+            return Err(nfsstat3::NFS3ERR_SERVERFAULT);
+        }
+        if is_dot(&entry.name) {
+            return self
+                .dot_entry(dir, entry.name, entry.cookie | LOWER_COOKIE)
+                .await;
+        }
+        let fileid = lower_fileid(entry.fileid);
+        let name_handle = entry.name_handle.map(|lower| {
+            let id = OverlayHandle::new(None, Some(lower));
+            self.remember_parent(&id, &dir.id, &entry.name);
+            id
+        });
+        Ok(DirEntryPlus {
+            fileid,
+            name: entry.name,
+            cookie: entry.cookie | LOWER_COOKIE,
+            name_attributes: entry.name_attributes.map(|mut attr| {
+                attr.fileid = fileid;
+                attr
+            }),
+            name_handle,
+        })
+    }
+}
+
+impl<U, L> NfsReadFileSystem for OverlayAdapter<U, L>
+where
+    U: NfsFileSystem,
+    L: NfsReadFileSystem,
+{
+    type Handle = Handle<U, L>;
+
+    fn root_dir(&self) -> Self::Handle {
+        OverlayHandle::new(Some(self.upper.root_dir()), Some(self.lower.root_dir()))
+    }
+
+    async fn lookup(
+        &self,
+        dirid: &Self::Handle,
+        filename: &filename3<'_>,
+    ) -> Result<Self::Handle, nfsstat3> {
+        if is_whiteout(filename) {
+            return Err(nfsstat3::NFS3ERR_NOENT);
+        }
+        if filename.as_ref() == b"." {
+            return Ok(dirid.clone());
+        }
+        if filename.as_ref() == b".." {
+            if dirid.as_bytes() == self.root_dir().as_bytes() {
+                return Ok(dirid.clone());
+            }
+            if let Some((parent, _)) = self.state().parents.get(&dirid.identity()) {
+                return Ok(parent.clone());
+            }
+        }
+
+        let dir = self.resolve(dirid)?;
+        let upper = match &dir.upper {
+            Some(upper_dir) => match self.upper.lookup(upper_dir, filename).await {
+                Ok(upper) => Some(upper),
+                Err(nfsstat3::NFS3ERR_NOENT) => None,
+                Err(stat) => return Err(stat),
+            },
+            None => None,
+        };
+        let mut lower = upper.as_ref().and_then(|upper| self.origin(upper));
+        if lower.is_none() {
+            if let Some(lower_dir) = &dir.lower {
+                let hidden = match &dir.upper {
+                    Some(upper_dir) => self.is_whiteout(upper_dir, filename).await?,
+                    None => false,
+                };
+                if !hidden {
+                    lower = match self.lower.lookup(lower_dir, filename).await {
+                        Ok(lower) => Some(lower),
+                        Err(nfsstat3::NFS3ERR_NOENT) => None,
+                        Err(stat) => return Err(stat),
+                    };
+                }
+            }
+        }
+        if let (Some(upper), Some(lower)) = (&upper, &lower) {
+            self.remember_copy(upper, lower);
+        }
+        if upper.is_none() && lower.is_none() {
+            return Err(nfsstat3::NFS3ERR_NOENT);
+        }
+
+        let id = OverlayHandle::new(upper, lower);
+        if filename.as_ref() != b".." {
+            self.remember_parent(&id, dirid, filename);
+        }
+        Ok(id)
+    }
+
+    async fn getattr(&self, id: &Self::Handle) -> Result<fattr3, nfsstat3> {
+        let resolved = self.resolve(id)?;
+        if let Some(upper) = &resolved.upper {
+            let attr = self.upper.getattr(upper).await?;
+            self.finish_attr(attr, true, resolved.lower.as_ref()).await
+        } else {
+            let lower = resolved.lower.as_ref().ok_or(nfsstat3::NFS3ERR_STALE)?;
+            let attr = self.lower.getattr(lower).await?;
+            self.finish_attr(attr, false, Some(lower)).await
+        }
+    }
+
+    async fn read(
+        &self,
+        id: &Self::Handle,
+        offset: u64,
+        count: u32,
+    ) -> Result<(Vec<u8>, bool), nfsstat3> {
+        match self.resolve(id)? {
+            Resolved {
+                upper: Some(upper), ..
+            } => self.upper.read(&upper, offset, count).await,
+            Resolved {
+                lower: Some(lower), ..
+            } => self.lower.read(&lower, offset, count).await,
+            _ => Err(nfsstat3::NFS3ERR_STALE),
+        }
+    }
+
+    async fn read_into(
+        &self,
+        id: &Self::Handle,
+        offset: u64,
+        buf: &mut [u8],
+    ) -> Result<(usize, bool), nfsstat3> {
+        match self.resolve(id)? {
+            Resolved {
+                upper: Some(upper), ..
+            } => self.upper.read_into(&upper, offset, buf).await,
+            Resolved {
+                lower: Some(lower), ..
+            } => self.lower.read_into(&lower, offset, buf).await,
+            _ => Err(nfsstat3::NFS3ERR_STALE),
+        }
+    }
+
+    async fn readdirplus(
+        &self,
+        dirid: &Self::Handle,
+        cookie: u64,
+    ) -> Result<impl ReadDirPlusIterator<Self::Handle>, nfsstat3> {
+        let resolved = self.resolve(dirid)?;
+        let lower_phase = cookie & LOWER_COOKIE != 0;
+
+        // the names in the upper directory hide the lower entries
+        let mut upper_names = HashSet::new();
+        if let (Some(upper_dir), Some(_)) = (&resolved.upper, &resolved.lower) {
+            let mut iter = self.upper.readdirplus(upper_dir, 0).await?;
+            loop {
+                match iter.next().await {
+                    NextResult::Ok(entry) => {
+                        upper_names.insert(entry.name.as_ref().to_vec());
+                    }
+                    NextResult::Eof => break,
+                    NextResult::Err(stat) => return Err(stat),
+                }
+            }
+        }
+
+        // the iterators borrow the directory handles, so the upper directory of a handle
+        // issued before the copy-up is read in advance
+        let mut upper_copied = VecDeque::new();
+        let upper = match (&dirid.upper, &resolved.upper) {
+            _ if lower_phase => None,
+            (Some(upper_dir), _) => Some(self.upper.readdirplus(upper_dir, cookie).await?),
+            (None, Some(upper_dir)) => {
+                let mut iter = self.upper.readdirplus(upper_dir, cookie).await?;
+                loop {
+                    match iter.next().await {
+                        NextResult::Ok(entry) => upper_copied.push_back(entry),
+                        NextResult::Eof => break,
+                        NextResult::Err(stat) => return Err(stat),
+                    }
+                }
+                None
+            }
+            (None, None) => None,
+        };
+        let lower = match &dirid.lower {
+            Some(lower_dir) => {
+                let cookie = if lower_phase {
+                    cookie & !LOWER_COOKIE
+                } else {
+                    0
+                };
+                Some(self.lower.readdirplus(lower_dir, cookie).await?)
+            }
+            None => None,
+        };
+
+        Ok(OverlayIterator {
+            fs: self,
+            dir: ListedDir {
+                id: dirid.clone(),
+                upper: resolved.upper,
+                lower: resolved.lower,
+                upper_names,
+            },
+            upper,
+            upper_copied,
+            lower,
+        })
+    }
+
+    async fn cookieverf(&self, dirid: &Self::Handle) -> Result<cookieverf3, nfsstat3> {
+        let resolved = self.resolve(dirid)?;
+        let mut verf = [0; 8];
+        if let Some(upper) = &resolved.upper {
+            let upper = self.upper.cookieverf(upper).await?;
+            verf.iter_mut().zip(upper.0).for_each(|(v, u)| *v ^= u);
+        }
+        if let Some(lower) = &resolved.lower {
+            let lower = self.lower.cookieverf(lower).await?;
+            verf.iter_mut()
+                .zip(lower.0.iter().rev())
+                .for_each(|(v, l)| *v ^= l);
+        }
+        Ok(cookieverf3(verf))
+    }
+
+    async fn getacl(&self, id: &Self::Handle) -> Result<PosixAcl, nfsstat3> {
+        match self.resolve(id)? {
+            Resolved {
+                upper: Some(upper), ..
+            } => self.upper.getacl(&upper).await,
+            Resolved {
+                lower: Some(lower), ..
+            } => self.lower.getacl(&lower).await,
+            _ => Err(nfsstat3::NFS3ERR_STALE),
+        }
+    }
+
+    async fn readlink(&self, id: &Self::Handle) -> Result<nfspath3<'_>, nfsstat3> {
+        match self.resolve(id)? {
+            Resolved {
+                upper: Some(upper), ..
+            } => self.upper.readlink(&upper).await,
+            Resolved {
+                lower: Some(lower), ..
+            } => self.lower.readlink(&lower).await,
+            _ => Err(nfsstat3::NFS3ERR_STALE),
+        }
+    }
+
+    async fn fsinfo(&self, root_fileid: &Self::Handle) -> Result<fsinfo3, nfsstat3> {
+        let upper = self.copy_up(root_fileid).await?;
+        let mut info = self.upper.fsinfo(&upper).await?;
+        info.obj_attributes = self
+            .getattr(root_fileid)
+            .await
+            .map_or(post_op_attr::None, post_op_attr::Some);
+        Ok(info)
+    }
+
+    async fn fsstat(&self, root_fileid: &Self::Handle) -> Result<fsstat3, nfsstat3> {
+        // new data goes to the upper layer
+        let mut stat = self.upper.fsstat(&self.upper.root_dir()).await?;
+        stat.obj_attributes = self
+            .getattr(root_fileid)
+            .await
+            .map_or(post_op_attr::None, post_op_attr::Some);
+        Ok(stat)
+    }
+
+    async fn pathconf(&self, id: &Self::Handle) -> Result<pathconf3, nfsstat3> {
+        let mut conf = self.upper.pathconf(&self.upper.root_dir()).await?;
+        conf.obj_attributes = self
+            .getattr(id)
+            .await
+            .map_or(post_op_attr::None, post_op_attr::Some);
+        Ok(conf)
+    }
+}
+
+impl<U, L> NfsFileSystem for OverlayAdapter<U, L>
+where
+    U: NfsFileSystem,
+    L: NfsReadFileSystem,
+{
+    fn capabilities(&self) -> VFSCapabilities {
+        self.upper.capabilities()
+    }
+
+    async fn setattr(&self, id: &Self::Handle, setattr: sattr3) -> Result<fattr3, nfsstat3> {
+        let upper = self.copy_up(id).await?;
+        let attr = self.upper.setattr(&upper, setattr).await?;
+        self.finish_attr(attr, true, id.lower.as_ref()).await
+    }
+
+    async fn write(&self, id: &Self::Handle, offset: u64, data: &[u8]) -> Result<fattr3, nfsstat3> {
+        let upper = self.copy_up(id).await?;
+        let attr = self.upper.write(&upper, offset, data).await?;
+        self.finish_attr(attr, true, id.lower.as_ref()).await
+    }
+
+    fn buffers_writes(&self) -> bool {
+        self.upper.buffers_writes()
+    }
+
+    async fn commit(&self, id: &Self::Handle) -> Result<fattr3, nfsstat3> {
+        // only the upper layer is written to
+        let Some(upper) = self.resolve(id)?.upper else {
+            return self.getattr(id).await;
+        };
+        let attr = self.upper.commit(&upper).await?;
+        self.finish_attr(attr, true, id.lower.as_ref()).await
+    }
+
+    async fn create(
+        &self,
+        dirid: &Self::Handle,
+        filename: &filename3<'_>,
+        attr: sattr3,
+    ) -> Result<(Self::Handle, fattr3), nfsstat3> {
+        check_name(filename)?;
+        let existing = self.find(dirid, filename).await?;
+        // the upper layer decides what happens to an existing file
+        if let Some(existing) = &existing {
+            self.copy_up(existing).await?;
+        }
+        let upper_dir = self.copy_up(dirid).await?;
+        let (upper, attr) = self.upper.create(&upper_dir, filename, attr).await?;
+        let lower = existing.and_then(|existing| existing.lower);
+        let attr = self.finish_attr(attr, true, lower.as_ref()).await?;
+        Ok((self.new_upper(dirid, filename, upper, lower), attr))
+    }
+
+    async fn create_exclusive(
+        &self,
+        dirid: &Self::Handle,
+        filename: &filename3<'_>,
+        createverf: createverf3,
+    ) -> Result<Self::Handle, nfsstat3> {
+        check_name(filename)?;
+        let existing = self.find(dirid, filename).await?;
+        if existing
+            .as_ref()
+            .is_some_and(|existing| existing.lower.is_some())
+        {
+            return Err(nfsstat3::NFS3ERR_EXIST);
+        }
+        let upper_dir = self.copy_up(dirid).await?;
+        let upper = self
+            .upper
+            .create_exclusive(&upper_dir, filename, createverf)
+            .await?;
+        Ok(self.new_upper(dirid, filename, upper, None))
+    }
+
+    async fn mkdir(
+        &self,
+        dirid: &Self::Handle,
+        dirname: &filename3<'_>,
+    ) -> Result<(Self::Handle, fattr3), nfsstat3> {
+        check_name(dirname)?;
+        if self.find(dirid, dirname).await?.is_some() {
+            return Err(nfsstat3::NFS3ERR_EXIST);
+        }
+        let upper_dir = self.copy_up(dirid).await?;
+        let (upper, attr) = self.upper.mkdir(&upper_dir, dirname).await?;
+        let attr = self.finish_attr(attr, true, None).await?;
+        Ok((self.new_upper(dirid, dirname, upper, None), attr))
+    }
+
+    async fn remove(&self, dirid: &Self::Handle, filename: &filename3<'_>) -> Result<(), nfsstat3> {
+        if is_dot(filename) {
+            return Err(nfsstat3::NFS3ERR_INVAL);
+        }
+        let target = self.lookup(dirid, filename).await?;
+        let resolved = self.resolve(&target)?;
+        let is_dir = self.getattr(&target).await?.type_ == ftype3::NF3DIR;
+        if is_dir && !self.is_empty_dir(&target).await? {
+            return Err(nfsstat3::NFS3ERR_NOTEMPTY);
+        }
+
+        let upper_dir = self.copy_up(dirid).await?;
+        if let Some(upper) = &resolved.upper {
+            if is_dir {
+                self.remove_whiteouts(upper).await?;
+            }
+            self.upper.remove(&upper_dir, filename).await?;
+        }
+        if let Some(lower) = &resolved.lower {
+            self.create_whiteout(&upper_dir, filename).await?;
+            let mut state = self.state();
+            state.removed.insert(lower.as_bytes().to_vec());
+            state.copied.remove(lower.as_bytes());
+            if let Some(upper) = &resolved.upper {
+                state.origins.remove(upper.as_bytes());
+            }
+        }
+        self.state().parents.remove(&target.identity());
+        Ok(())
+    }
+
+    async fn rename<'a>(
+        &self,
+        from_dirid: &Self::Handle,
+        from_filename: &filename3<'a>,
+        to_dirid: &Self::Handle,
+        to_filename: &filename3<'a>,
+    ) -> Result<(), nfsstat3> {
+        check_name(to_filename)?;
+        if is_dot(from_filename) {
+            return Err(nfsstat3::NFS3ERR_INVAL);
+        }
+        let source = self.lookup(from_dirid, from_filename).await?;
+        let source_is_dir = self.getattr(&source).await?.type_ == ftype3::NF3DIR;
+        if source_is_dir && source.lower.is_some() {
+            return Err(nfsstat3::NFS3ERR_XDEV);
+        }
+
+        let target = self.find(to_dirid, to_filename).await?;
+        if let Some(target) = &target {
+            if target.identity() == source.identity() {
+                return Ok(());
+            }
+            if self.getattr(target).await?.type_ == ftype3::NF3DIR {
+                if !self.is_empty_dir(target).await? {
+                    return Err(nfsstat3::NFS3ERR_NOTEMPTY);
+                }
+                if let Some(upper) = self.resolve(target)?.upper {
+                    self.remove_whiteouts(&upper).await?;
+                }
+            }
+        }
+
+        self.copy_up(&source).await?;
+        let from_upper = self.copy_up(from_dirid).await?;
+        let to_upper = self.copy_up(to_dirid).await?;
+        self.upper
+            .rename(&from_upper, from_filename, &to_upper, to_filename)
+            .await?;
+
+        if source.lower.is_some() {
+            self.create_whiteout(&from_upper, from_filename).await?;
+        }
+        if let Some(target) = &target {
+            if let Some(lower) = &target.lower {
+                self.create_whiteout(&to_upper, to_filename).await?;
+                let mut state = self.state();
+                state.removed.insert(lower.as_bytes().to_vec());
+                state.copied.remove(lower.as_bytes());
+            }
+            self.state().parents.remove(&target.identity());
+        }
+        self.remember_parent(&source, to_dirid, to_filename);
+        Ok(())
+    }
+
+    async fn symlink<'a>(
+        &self,
+        dirid: &Self::Handle,
+        linkname: &filename3<'a>,
+        symlink: &nfspath3<'a>,
+        attr: &sattr3,
+    ) -> Result<(Self::Handle, fattr3), nfsstat3> {
+        check_name(linkname)?;
+        if self.find(dirid, linkname).await?.is_some() {
+            return Err(nfsstat3::NFS3ERR_EXIST);
+        }
+        let upper_dir = self.copy_up(dirid).await?;
+        let (upper, attr) = self
+            .upper
+            .symlink(&upper_dir, linkname, symlink, attr)
+            .await?;
+        let attr = self.finish_attr(attr, true, None).await?;
+        Ok((self.new_upper(dirid, linkname, upper, None), attr))
+    }
+
+    async fn setacl(&self, id: &Self::Handle, acl: PosixAcl) -> Result<(), nfsstat3> {
+        let upper = self.copy_up(id).await?;
+        self.upper.setacl(&upper, acl).await
+    }
+}
+
+/// The directory listed by an [`OverlayIterator`]
+struct ListedDir<U, L> {
+    id: OverlayHandle<U, L>,
+    upper: Option<U>,
+    lower: Option<L>,
+    /// All names in the upper directory, including the whiteouts. Empty if there is no
+    /// lower directory to merge.
+    upper_names: HashSet<Vec<u8>>,
+}
+
+/// Lists the upper directory and then the visible entries of the lower one
+struct OverlayIterator<'a, U, L, UI, LI>
+where
+    U: NfsFileSystem,
+    L: NfsReadFileSystem,
+{
+    fs: &'a OverlayAdapter<U, L>,
+    dir: ListedDir<U::Handle, L::Handle>,
+    upper: Option<UI>,
+    upper_copied: VecDeque<DirEntryPlus<U::Handle>>,
+    lower: Option<LI>,
+}
+
+impl<U, L, UI, LI> ReadDirPlusIterator<Handle<U, L>> for OverlayIterator<'_, U, L, UI, LI>
+where
+    U: NfsFileSystem,
+    L: NfsReadFileSystem,
+    UI: ReadDirPlusIterator<U::Handle>,
+    LI: ReadDirPlusIterator<L::Handle>,
+{
+    async fn next(&mut self) -> NextResult<DirEntryPlus<Handle<U, L>>> {
+        loop {
+            let entry = if let Some(upper) = &mut self.upper {
+                match upper.next().await {
+                    NextResult::Ok(entry) => entry,
+                    NextResult::Eof => {
+                        self.upper = None;
+                        continue;
+                    }
+                    NextResult::Err(stat) => return NextResult::Err(stat),
+                }
+            } else if let Some(entry) = self.upper_copied.pop_front() {
+                entry
+            } else {
+                break;
+            };
+            if is_whiteout(&entry.name) {
+                continue;
+            }
+            return match self.fs.upper_entry(&self.dir, entry).await {
+                Ok(entry) => NextResult::Ok(entry),
+                Err(stat) => NextResult::Err(stat),
+            };
+        }
+
+        if let Some(lower) = &mut self.lower {
+            loop {
+                match lower.next().await {
+                    NextResult::Ok(entry) => {
+                        let name = entry.name.as_ref();
+                        if self.dir.upper_names.contains(name)
+                            || self
+                                .dir
+                                .upper_names
+                                .contains(whiteout(&entry.name).as_ref())
+                            || (self.dir.upper.is_some() && is_dot(&entry.name))
+                        {
+                            continue;
+                        }
+                        return match self.fs.lower_entry(&self.dir, entry).await {
+                            Ok(entry) => NextResult::Ok(entry),
+                            Err(stat) => NextResult::Err(stat),
+                        };
+                    }
+                    NextResult::Eof => break,
+                    NextResult::Err(stat) => return NextResult::Err(stat),
+                }
+            }
+            self.lower = None;
+        }
+        NextResult::Eof
+    }
+}
+
+const fn upper_fileid(fileid: u64) -> u64 {
+    fileid << 1
+}
+
+const fn lower_fileid(fileid: u64) -> u64 {
+    (fileid << 1) | 1
+}
+
+/// Returns the mode and owner of a lower object, set on its upper copy when it's created
+fn initial_attr(attr: &fattr3) -> sattr3 {
+    sattr3 {
+        mode: set_mode3::Some(attr.mode),
+        uid: set_uid3::Some(attr.uid),
+        gid: set_gid3::Some(attr.gid),
+        ..sattr3::default()
+    }
+}
+
+fn is_dot(name: &filename3<'_>) -> bool {
+    name.as_ref() == b"." || name.as_ref() == b".."
+}
+
+fn is_whiteout(name: &filename3<'_>) -> bool {
+    name.as_ref().starts_with(WHITEOUT_PREFIX)
+}
+
+fn whiteout(name: &filename3<'_>) -> filename3<'static> {
+    [WHITEOUT_PREFIX, name.as_ref()].concat().into()
+}
+
+fn check_name(name: &filename3<'_>) -> Result<(), nfsstat3> {
+    if is_whiteout(name) {
+        return Err(nfsstat3::NFS3ERR_ACCES);
+    }
+    if is_dot(name) {
+        return Err(nfsstat3::NFS3ERR_EXIST);
+    }
+    Ok(())
+}
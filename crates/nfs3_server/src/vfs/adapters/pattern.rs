@@ -0,0 +1,90 @@
+/// Matches a path against a shell-style pattern
+///
+/// - `?` matches a single character other than `/`
+/// - `*` matches any number of characters other than `/`
+/// - `**` matches any number of characters, including `/`
+///
+/// Other characters match themselves. The whole path must match.
+pub fn glob_match(pattern: &[u8], path: &[u8]) -> bool {
+    // matched[j] is true if the part of the pattern seen so far matches path[..j]
+    let mut matched = vec![false; path.len() + 1];
+    matched[0] = true;
+
+    let mut i = 0;
+    while i < pattern.len() {
+        match pattern[i] {
+            b'*' if pattern.get(i + 1) == Some(&b'*') => {
+                // any suffix of a matched prefix matches too
+                let first = matched.iter().position(|m| *m).unwrap_or(matched.len());
+                for m in matched.iter_mut().skip(first) {
+                    *m = true;
+                }
+                i += 2;
+                continue;
+            }
+            b'*' => {
+                for j in 1..=path.len() {
+                    if !matched[j] && matched[j - 1] && path[j - 1] != b'/' {
+                        matched[j] = true;
+                    }
+                }
+            }
+            c => {
+                for j in (1..=path.len()).rev() {
+                    let same = if c == b'?' {
+                        path[j - 1] != b'/'
+                    } else {
+                        path[j - 1] == c
+                    };
+                    matched[j] = matched[j - 1] && same;
+                }
+                matched[0] = false;
+            }
+        }
+        i += 1;
+    }
+    matched[path.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::glob_match;
+
+    fn matches(pattern: &str, path: &str) -> bool {
+        glob_match(pattern.as_bytes(), path.as_bytes())
+    }
+
+    #[test]
+    fn literal() {
+        assert!(matches("/a/b.txt", "/a/b.txt"));
+        assert!(!matches("/a/b.txt", "/a/b.txt2"));
+        assert!(!matches("/a/b.txt", "/a/b.tx"));
+        assert!(matches("", ""));
+        assert!(!matches("", "/"));
+    }
+
+    #[test]
+    fn single_star_stays_in_component() {
+        assert!(matches("/a/*.txt", "/a/b.txt"));
+        assert!(matches("/a/*.txt", "/a/.txt"));
+        assert!(!matches("/a/*.txt", "/a/b/c.txt"));
+        assert!(matches("/*/*", "/a/b"));
+        assert!(matches("*.tmp", "a.b.tmp"));
+    }
+
+    #[test]
+    fn double_star_crosses_components() {
+        assert!(matches("/a/**", "/a/b/c/d"));
+        assert!(matches("/a/**", "/a/"));
+        assert!(!matches("/a/**", "/b/c"));
+        assert!(matches("**/*.log", "/var/log/x.log"));
+        assert!(matches("**", ""));
+    }
+
+    #[test]
+    fn question_mark() {
+        assert!(matches("/file?", "/file1"));
+        assert!(!matches("/file?", "/file"));
+        assert!(!matches("/a?b", "/a/b"));
+    }
+}
@@ -0,0 +1,682 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use nfs3_types::nfs3::{
+    FSINFO3resok as fsinfo3, FSSTAT3resok as fsstat3, PATHCONF3resok as pathconf3, cookieverf3,
+    createverf3, fattr3, filename3, ftype3, nfspath3, nfsstat3, sattr3, set_size3, set_uid3,
+};
+use tracing::debug;
+
+use crate::vfs::{
+    FileHandle, NextResult, NfsFileSystem, NfsReadFileSystem, PosixAcl, ReadDirIterator,
+    ReadDirPlusIterator, VFSCapabilities, current_caller,
+};
+
+/// Limits enforced by a [`QuotaAdapter`], `None` means unlimited
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct QuotaLimits {
+    /// Maximum number of bytes in regular files and symlinks
+    pub bytes: Option<u64>,
+    /// Maximum number of files, directories and symlinks
+    pub inodes: Option<u64>,
+}
+
+/// Usage tracked by a [`QuotaAdapter`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct QuotaUsage {
+    /// Number of bytes in regular files and symlinks
+    pub bytes: u64,
+    /// Number of files, directories and symlinks
+    pub inodes: u64,
+}
+
+/// Enforces quotas per user and per top-level directory of the wrapped file system.
+///
+/// Every object is charged to its owner and to the top-level directory it is in. New objects
+/// are owned by the `AUTH_UNIX` user of the client that created them (see
+/// [`current_caller`](crate::vfs::current_caller)), or by the uid reported by the wrapped file
+/// system if the client didn't send credentials. The root directory and the files directly in
+/// it don't belong to any tree.
+///
+/// WRITE, SETATTR, CREATE, MKDIR and SYMLINK calls that would exceed a limit fail with
+/// `NFS3ERR_DQUOT`. Files can always shrink, so users can get back under the limit.
+/// FSSTAT reports the remaining quota of the caller and of the tree of the object, when it's
+/// lower than the free space of the wrapped file system. Renaming a directory into another tree
+/// fails with `NFS3ERR_XDEV`, as with project quotas on Linux; clients fall back to copying.
+///
+/// The usage is kept in memory. Call [`scan`](Self::scan) at startup to charge the existing
+/// objects to the owners reported by the wrapped file system. Objects the adapter has never
+/// seen, e.g. created by other means after the scan, are not charged.
+pub struct QuotaAdapter<T: NfsReadFileSystem> {
+    inner: T,
+    limits: Limits,
+    ledger: Mutex<Ledger>,
+}
+
+impl<T> QuotaAdapter<T>
+where
+    T: NfsReadFileSystem,
+{
+    /// Creates an adapter without limits
+    pub fn new(inner: T) -> Self {
+        Self {
+            inner,
+            limits: Limits::default(),
+            ledger: Mutex::default(),
+        }
+    }
+
+    /// Returns the wrapped file system
+    pub const fn inner(&self) -> &T {
+        &self.inner
+    }
+
+    /// Sets the limits of `uid`, overriding the default ones
+    pub fn set_uid_limits(&mut self, uid: u32, limits: QuotaLimits) {
+        self.limits.uids.insert(uid, limits);
+    }
+
+    /// Sets the limits of the users without their own limits
+    pub const fn set_default_uid_limits(&mut self, limits: QuotaLimits) {
+        self.limits.default_uid = limits;
+    }
+
+    /// Sets the limits of the top-level directory `name`, overriding the default ones
+    pub fn set_tree_limits(&mut self, name: impl AsRef<[u8]>, limits: QuotaLimits) {
+        self.limits.trees.insert(name.as_ref().to_vec(), limits);
+    }
+
+    /// Sets the limits of the top-level directories without their own limits
+    pub const fn set_default_tree_limits(&mut self, limits: QuotaLimits) {
+        self.limits.default_tree = limits;
+    }
+
+    /// Returns the usage of `uid`
+    pub fn uid_usage(&self, uid: u32) -> QuotaUsage {
+        self.ledger().uids.get(&uid).copied().unwrap_or_default()
+    }
+
+    /// Returns the usage of the top-level directory `name`
+    pub fn tree_usage(&self, name: impl AsRef<[u8]>) -> QuotaUsage {
+        self.ledger()
+            .trees
+            .get(name.as_ref())
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Recomputes the usage by walking the whole file system
+    ///
+    /// The objects are charged to the uid reported by `getattr`. The calls made while
+    /// the scan is running are not accounted for, so it's meant to be called at startup.
+    pub async fn scan(&self) -> Result<(), nfsstat3> {
+        let mut ledger = Ledger::default();
+        let mut dirs = VecDeque::from([(self.inner.root_dir(), None::<Tree>)]);
+        let mut count = 0u64;
+        while let Some((dir, dir_tree)) = dirs.pop_front() {
+            let is_root = self.is_root(&dir);
+            let mut iter = self.inner.readdirplus(&dir, 0).await?;
+            loop {
+                let entry = match iter.next().await {
+                    NextResult::Ok(entry) => entry,
+                    NextResult::Eof => break,
+                    NextResult::Err(stat) => return Err(stat),
+                };
+                if entry.name.as_ref() == b"." || entry.name.as_ref() == b".." {
+                    continue;
+                }
+                let id = match entry.name_handle {
+                    Some(id) => id,
+                    None => self.inner.lookup(&dir, &entry.name).await?,
+                };
+                let attr = match entry.name_attributes {
+                    Some(attr) => attr,
+                    None => self.inner.getattr(&id).await?,
+                };
+                let is_dir = attr.type_ == ftype3::NF3DIR;
+                let tree = if is_root {
+                    is_dir.then(|| Tree::from(entry.name.as_ref()))
+                } else {
+                    dir_tree.clone()
+                };
+                if is_dir {
+                    dirs.push_back((id.clone(), tree.clone()));
+                }
+                ledger.insert(id.as_bytes().to_vec(), Object::new(&attr, attr.uid, tree));
+                count += 1;
+            }
+        }
+        debug!("quota scan charged {count} objects");
+        *self.ledger() = ledger;
+        Ok(())
+    }
+
+    fn ledger(&self) -> MutexGuard<'_, Ledger> {
+        self.ledger.lock().expect("lock is poisoned")
+    }
+
+    fn is_root(&self, id: &T::Handle) -> bool {
+        id.as_bytes() == self.inner.root_dir().as_bytes()
+    }
+
+    /// Returns the tree of a new entry of `dirid`
+    fn child_tree(&self, dirid: &T::Handle, name: &filename3<'_>, is_dir: bool) -> Option<Tree> {
+        if self.is_root(dirid) {
+            is_dir.then(|| Tree::from(name.as_ref()))
+        } else {
+            self.ledger()
+                .objects
+                .get(dirid.as_bytes())
+                .and_then(|dir| dir.tree.clone())
+        }
+    }
+
+    /// Checks that a new object fits into the quotas of the caller and of `tree`
+    fn check_new(&self, tree: Option<&Tree>, bytes: u64) -> Result<(), nfsstat3> {
+        let uid = current_caller().map(|caller| caller.uid);
+        self.limits.check(&self.ledger(), uid, tree, bytes, 1)
+    }
+
+    /// Charges a new object to the caller, or to its owner if there is no caller
+    fn charge_new(&self, id: &T::Handle, attr: &fattr3, tree: Option<Tree>) {
+        let uid = current_caller().map_or(attr.uid, |caller| caller.uid);
+        self.ledger()
+            .insert(id.as_bytes().to_vec(), Object::new(attr, uid, tree));
+    }
+}
+
+impl<T> NfsReadFileSystem for QuotaAdapter<T>
+where
+    T: NfsReadFileSystem,
+{
+    type Handle = T::Handle;
+
+    fn root_dir(&self) -> Self::Handle {
+        self.inner.root_dir()
+    }
+
+    async fn lookup(
+        &self,
+        dirid: &Self::Handle,
+        filename: &filename3<'_>,
+    ) -> Result<Self::Handle, nfsstat3> {
+        self.inner.lookup(dirid, filename).await
+    }
+
+    async fn lookup_by_path(&self, path: &str) -> Result<Self::Handle, nfsstat3> {
+        self.inner.lookup_by_path(path).await
+    }
+
+    async fn getattr(&self, id: &Self::Handle) -> Result<fattr3, nfsstat3> {
+        self.inner.getattr(id).await
+    }
+
+    async fn read(
+        &self,
+        id: &Self::Handle,
+        offset: u64,
+        count: u32,
+    ) -> Result<(Vec<u8>, bool), nfsstat3> {
+        self.inner.read(id, offset, count).await
+    }
+
+    async fn read_into(
+        &self,
+        id: &Self::Handle,
+        offset: u64,
+        buf: &mut [u8],
+    ) -> Result<(usize, bool), nfsstat3> {
+        self.inner.read_into(id, offset, buf).await
+    }
+
+    async fn readdir(
+        &self,
+        dirid: &Self::Handle,
+        cookie: u64,
+    ) -> Result<impl ReadDirIterator, nfsstat3> {
+        self.inner.readdir(dirid, cookie).await
+    }
+
+    async fn readdirplus(
+        &self,
+        dirid: &Self::Handle,
+        cookie: u64,
+    ) -> Result<impl ReadDirPlusIterator<Self::Handle>, nfsstat3> {
+        self.inner.readdirplus(dirid, cookie).await
+    }
+
+    async fn cookieverf(&self, dirid: &Self::Handle) -> Result<cookieverf3, nfsstat3> {
+        self.inner.cookieverf(dirid).await
+    }
+
+    async fn getacl(&self, id: &Self::Handle) -> Result<PosixAcl, nfsstat3> {
+        self.inner.getacl(id).await
+    }
+
+    async fn readlink(&self, id: &Self::Handle) -> Result<nfspath3<'_>, nfsstat3> {
+        self.inner.readlink(id).await
+    }
+
+    async fn fsinfo(&self, root_fileid: &Self::Handle) -> Result<fsinfo3, nfsstat3> {
+        self.inner.fsinfo(root_fileid).await
+    }
+
+    async fn fsstat(&self, root_fileid: &Self::Handle) -> Result<fsstat3, nfsstat3> {
+        let mut stat = self.inner.fsstat(root_fileid).await?;
+        let uid = current_caller().map(|caller| caller.uid);
+        let ledger = self.ledger();
+        if let Some(uid) = uid {
+            let usage = ledger.uids.get(&uid).copied().unwrap_or_default();
+            apply_limits(&mut stat, self.limits.of_uid(uid), usage);
+        }
+        let tree = ledger
+            .objects
+            .get(root_fileid.as_bytes())
+            .and_then(|object| object.tree.as_ref());
+        if let Some(tree) = tree {
+            let usage = ledger.trees.get(tree).copied().unwrap_or_default();
+            apply_limits(&mut stat, self.limits.of_tree(tree), usage);
+        }
+        drop(ledger);
+        Ok(stat)
+    }
+
+    async fn pathconf(&self, id: &Self::Handle) -> Result<pathconf3, nfsstat3> {
+        self.inner.pathconf(id).await
+    }
+}
+
+impl<T> NfsFileSystem for QuotaAdapter<T>
+where
+    T: NfsFileSystem,
+{
+    fn capabilities(&self) -> VFSCapabilities {
+        self.inner.capabilities()
+    }
+
+    async fn setattr(&self, id: &Self::Handle, setattr: sattr3) -> Result<fattr3, nfsstat3> {
+        let key = id.as_bytes();
+        let new_uid = match setattr.uid {
+            set_uid3::Some(uid) => Some(uid),
+            set_uid3::None => None,
+        };
+        let mut growth = 0;
+        if let set_size3::Some(size) = setattr.size {
+            let mut ledger = self.ledger();
+            if let Some(object) = ledger.objects.get(key) {
+                growth = size.saturating_sub(object.bytes);
+                let (uid, tree) = (object.uid, object.tree.clone());
+                self.limits
+                    .check(&ledger, Some(uid), tree.as_ref(), growth, 0)?;
+                ledger.grow(key, growth);
+            }
+        }
+        let result = self.inner.setattr(id, setattr).await;
+        let mut ledger = self.ledger();
+        match &result {
+            Ok(attr) => {
+                ledger.resize(key, attr);
+                if let Some(uid) = new_uid {
+                    ledger.chown(key, uid);
+                }
+            }
+            Err(_) => ledger.shrink(key, growth),
+        }
+        drop(ledger);
+        result
+    }
+
+    async fn write(&self, id: &Self::Handle, offset: u64, data: &[u8]) -> Result<fattr3, nfsstat3> {
+        let key = id.as_bytes();
+        let end = offset.saturating_add(data.len() as u64);
+        let growth = {
+            let mut ledger = self.ledger();
+            match ledger.objects.get(key) {
+                Some(object) => {
+                    let growth = end.saturating_sub(object.bytes);
+                    let (uid, tree) = (object.uid, object.tree.clone());
+                    self.limits
+                        .check(&ledger, Some(uid), tree.as_ref(), growth, 0)?;
+                    ledger.grow(key, growth);
+                    growth
+                }
+                None => 0,
+            }
+        };
+        let result = self.inner.write(id, offset, data).await;
+        match &result {
+            Ok(attr) => self.ledger().resize(key, attr),
+            Err(_) => self.ledger().shrink(key, growth),
+        }
+        result
+    }
+
+    fn buffers_writes(&self) -> bool {
+        self.inner.buffers_writes()
+    }
+
+    async fn commit(&self, id: &Self::Handle) -> Result<fattr3, nfsstat3> {
+        self.inner.commit(id).await
+    }
+
+    async fn create(
+        &self,
+        dirid: &Self::Handle,
+        filename: &filename3<'_>,
+        attr: sattr3,
+    ) -> Result<(Self::Handle, fattr3), nfsstat3> {
+        let tree = self.child_tree(dirid, filename, false);
+        let bytes = match attr.size {
+            set_size3::Some(size) => size,
+            set_size3::None => 0,
+        };
+        self.check_new(tree.as_ref(), bytes)?;
+        let (id, attr) = self.inner.create(dirid, filename, attr).await?;
+        if self.ledger().objects.contains_key(id.as_bytes()) {
+            // an existing file was truncated
+            self.ledger().resize(id.as_bytes(), &attr);
+        } else {
+            self.charge_new(&id, &attr, tree);
+        }
+        Ok((id, attr))
+    }
+
+    async fn create_exclusive(
+        &self,
+        dirid: &Self::Handle,
+        filename: &filename3<'_>,
+        createverf: createverf3,
+    ) -> Result<Self::Handle, nfsstat3> {
+        let tree = self.child_tree(dirid, filename, false);
+        self.check_new(tree.as_ref(), 0)?;
+        let id = self
+            .inner
+            .create_exclusive(dirid, filename, createverf)
+            .await?;
+        if !self.ledger().objects.contains_key(id.as_bytes()) {
+            // a retransmitted request returns the file created before
+            let attr = self.inner.getattr(&id).await?;
+            self.charge_new(&id, &attr, tree);
+        }
+        Ok(id)
+    }
+
+    async fn mkdir(
+        &self,
+        dirid: &Self::Handle,
+        dirname: &filename3<'_>,
+    ) -> Result<(Self::Handle, fattr3), nfsstat3> {
+        let tree = self.child_tree(dirid, dirname, true);
+        self.check_new(tree.as_ref(), 0)?;
+        let (id, attr) = self.inner.mkdir(dirid, dirname).await?;
+        self.charge_new(&id, &attr, tree);
+        Ok((id, attr))
+    }
+
+    async fn remove(&self, dirid: &Self::Handle, filename: &filename3<'_>) -> Result<(), nfsstat3> {
+        let id = self.inner.lookup(dirid, filename).await.ok();
+        self.inner.remove(dirid, filename).await?;
+        if let Some(id) = id {
+            self.ledger().remove(id.as_bytes());
+        }
+        Ok(())
+    }
+
+    async fn rename<'a>(
+        &self,
+        from_dirid: &Self::Handle,
+        from_filename: &filename3<'a>,
+        to_dirid: &Self::Handle,
+        to_filename: &filename3<'a>,
+    ) -> Result<(), nfsstat3> {
+        let source = self.inner.lookup(from_dirid, from_filename).await.ok();
+        let target = self.inner.lookup(to_dirid, to_filename).await.ok();
+
+        let mut moved = None;
+        if let Some(source) = &source {
+            let object = self
+                .ledger()
+                .objects
+                .get(source.as_bytes())
+                .map(|object| (object.is_dir, object.tree.clone(), object.bytes));
+            if let Some((is_dir, tree, bytes)) = object {
+                let new_tree = self.child_tree(to_dirid, to_filename, is_dir);
+                if new_tree != tree {
+                    if is_dir {
+                        // the whole subtree would have to be charged to the new tree
+                        return Err(nfsstat3::NFS3ERR_XDEV);
+                    }
+                    let ledger = self.ledger();
+                    let uid = ledger.objects.get(source.as_bytes()).map(|o| o.uid);
+                    self.limits
+                        .check(&ledger, uid, new_tree.as_ref(), bytes, 1)?;
+                    drop(ledger);
+                    moved = Some(new_tree);
+                }
+            }
+        }
+
+        self.inner
+            .rename(from_dirid, from_filename, to_dirid, to_filename)
+            .await?;
+
+        let mut ledger = self.ledger();
+        if let Some(target) = target {
+            if source
+                .as_ref()
+                .is_none_or(|s| s.as_bytes() != target.as_bytes())
+            {
+                ledger.remove(target.as_bytes());
+            }
+        }
+        if let (Some(source), Some(tree)) = (source, moved) {
+            ledger.move_to_tree(source.as_bytes(), tree);
+        }
+        drop(ledger);
+        Ok(())
+    }
+
+    async fn symlink<'a>(
+        &self,
+        dirid: &Self::Handle,
+        linkname: &filename3<'a>,
+        symlink: &nfspath3<'a>,
+        attr: &sattr3,
+    ) -> Result<(Self::Handle, fattr3), nfsstat3> {
+        let tree = self.child_tree(dirid, linkname, false);
+        self.check_new(tree.as_ref(), symlink.as_ref().len() as u64)?;
+        let (id, attr) = self.inner.symlink(dirid, linkname, symlink, attr).await?;
+        self.charge_new(&id, &attr, tree);
+        Ok((id, attr))
+    }
+
+    async fn setacl(&self, id: &Self::Handle, acl: PosixAcl) -> Result<(), nfsstat3> {
+        self.inner.setacl(id, acl).await
+    }
+}
+
+/// Name of a top-level directory
+type Tree = Arc<[u8]>;
+
+/// The configured limits
+#[derive(Default)]
+struct Limits {
+    uids: HashMap<u32, QuotaLimits>,
+    default_uid: QuotaLimits,
+    trees: HashMap<Vec<u8>, QuotaLimits>,
+    default_tree: QuotaLimits,
+}
+
+impl Limits {
+    fn of_uid(&self, uid: u32) -> QuotaLimits {
+        self.uids.get(&uid).copied().unwrap_or(self.default_uid)
+    }
+
+    fn of_tree(&self, tree: &[u8]) -> QuotaLimits {
+        self.trees.get(tree).copied().unwrap_or(self.default_tree)
+    }
+
+    /// Checks that `bytes` and `inodes` more fit into the quotas of `uid` and `tree`
+    fn check(
+        &self,
+        ledger: &Ledger,
+        uid: Option<u32>,
+        tree: Option<&Tree>,
+        bytes: u64,
+        inodes: u64,
+    ) -> Result<(), nfsstat3> {
+        if let Some(uid) = uid {
+            let usage = ledger.uids.get(&uid).copied().unwrap_or_default();
+            if exceeds(self.of_uid(uid), usage, bytes, inodes) {
+                debug!("uid {uid} is over quota");
+                return Err(nfsstat3::NFS3ERR_DQUOT);
+            }
+        }
+        if let Some(tree) = tree {
+            let usage = ledger.trees.get(tree).copied().unwrap_or_default();
+            if exceeds(self.of_tree(tree), usage, bytes, inodes) {
+                debug!("tree {:?} is over quota", String::from_utf8_lossy(tree));
+                return Err(nfsstat3::NFS3ERR_DQUOT);
+            }
+        }
+        Ok(())
+    }
+}
+
+fn exceeds(limits: QuotaLimits, usage: QuotaUsage, bytes: u64, inodes: u64) -> bool {
+    let over = |limit: Option<u64>, used: u64, more: u64| {
+        more > 0 && limit.is_some_and(|limit| used.saturating_add(more) > limit)
+    };
+    over(limits.bytes, usage.bytes, bytes) || over(limits.inodes, usage.inodes, inodes)
+}
+
+/// Lowers the FSSTAT values to what is left of the quota
+fn apply_limits(stat: &mut fsstat3, limits: QuotaLimits, usage: QuotaUsage) {
+    if let Some(limit) = limits.bytes {
+        let left = limit.saturating_sub(usage.bytes);
+        stat.tbytes = stat.tbytes.min(limit);
+        stat.fbytes = stat.fbytes.min(left);
+        stat.abytes = stat.abytes.min(left);
+    }
+    if let Some(limit) = limits.inodes {
+        let left = limit.saturating_sub(usage.inodes);
+        stat.tfiles = stat.tfiles.min(limit);
+        stat.ffiles = stat.ffiles.min(left);
+        stat.afiles = stat.afiles.min(left);
+    }
+}
+
+/// A charged object
+struct Object {
+    uid: u32,
+    tree: Option<Tree>,
+    bytes: u64,
+    is_dir: bool,
+}
+
+impl Object {
+    fn new(attr: &fattr3, uid: u32, tree: Option<Tree>) -> Self {
+        Self {
+            uid,
+            tree,
+            bytes: charged_bytes(attr),
+            is_dir: attr.type_ == ftype3::NF3DIR,
+        }
+    }
+}
+
+const fn charged_bytes(attr: &fattr3) -> u64 {
+    match attr.type_ {
+        ftype3::NF3REG | ftype3::NF3LNK => attr.size,
+        _ => 0,
+    }
+}
+
+/// The charged objects and the usage derived from them
+#[derive(Default)]
+struct Ledger {
+    objects: HashMap<Vec<u8>, Object>,
+    uids: HashMap<u32, QuotaUsage>,
+    trees: HashMap<Tree, QuotaUsage>,
+}
+
+impl Ledger {
+    fn insert(&mut self, key: Vec<u8>, object: Object) {
+        self.remove(&key);
+        self.add(object.uid, object.tree.clone(), object.bytes, 1);
+        self.objects.insert(key, object);
+    }
+
+    fn remove(&mut self, key: &[u8]) {
+        if let Some(object) = self.objects.remove(key) {
+            self.sub(object.uid, object.tree.as_ref(), object.bytes, 1);
+        }
+    }
+
+    /// Sets the size of an object to the one reported by the file system
+    fn resize(&mut self, key: &[u8], attr: &fattr3) {
+        if let Some(object) = self.objects.get(key) {
+            let bytes = charged_bytes(attr);
+            if bytes > object.bytes {
+                self.grow(key, bytes - object.bytes);
+            } else {
+                self.shrink(key, object.bytes - bytes);
+            }
+        }
+    }
+
+    fn grow(&mut self, key: &[u8], bytes: u64) {
+        if let Some(object) = self.objects.get_mut(key) {
+            object.bytes += bytes;
+            let (uid, tree) = (object.uid, object.tree.clone());
+            self.add(uid, tree, bytes, 0);
+        }
+    }
+
+    fn shrink(&mut self, key: &[u8], bytes: u64) {
+        if let Some(object) = self.objects.get_mut(key) {
+            let bytes = bytes.min(object.bytes);
+            object.bytes -= bytes;
+            let (uid, tree) = (object.uid, object.tree.clone());
+            self.sub(uid, tree.as_ref(), bytes, 0);
+        }
+    }
+
+    fn chown(&mut self, key: &[u8], uid: u32) {
+        if let Some(mut object) = self.objects.remove(key) {
+            self.sub(object.uid, object.tree.as_ref(), object.bytes, 1);
+            object.uid = uid;
+            self.insert(key.to_vec(), object);
+        }
+    }
+
+    fn move_to_tree(&mut self, key: &[u8], tree: Option<Tree>) {
+        if let Some(mut object) = self.objects.remove(key) {
+            self.sub(object.uid, object.tree.as_ref(), object.bytes, 1);
+            object.tree = tree;
+            self.insert(key.to_vec(), object);
+        }
+    }
+
+    fn add(&mut self, uid: u32, tree: Option<Tree>, bytes: u64, inodes: u64) {
+        let mut usages = vec![self.uids.entry(uid).or_default()];
+        if let Some(tree) = tree {
+            usages.push(self.trees.entry(tree).or_default());
+        }
+        for usage in usages {
+            usage.bytes += bytes;
+            usage.inodes += inodes;
+        }
+    }
+
+    fn sub(&mut self, uid: u32, tree: Option<&Tree>, bytes: u64, inodes: u64) {
+        let usages = [
+            self.uids.get_mut(&uid),
+            tree.and_then(|tree| self.trees.get_mut(tree)),
+        ];
+        for usage in usages.into_iter().flatten() {
+            usage.bytes = usage.bytes.saturating_sub(bytes);
+            usage.inodes = usage.inodes.saturating_sub(inodes);
+        }
+    }
+}
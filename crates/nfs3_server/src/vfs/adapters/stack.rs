@@ -0,0 +1,542 @@
+use std::future::Future;
+
+use nfs3_types::nfs3::{
+    FSINFO3resok as fsinfo3, FSSTAT3resok as fsstat3, PATHCONF3resok as pathconf3, cookieverf3,
+    createverf3, fattr3, filename3, nfspath3, nfsstat3, sattr3,
+};
+
+use super::FsOperation;
+use crate::vfs::{
+    FileHandle, NfsFileSystem, NfsReadFileSystem, PosixAcl, ReadDirIterator, ReadDirPlusIterator,
+    VFSCapabilities,
+};
+
+/// Wraps a file system into another one, like the layers of `tower`
+///
+/// Closures that take the file system and return the wrapped one are layers, so any
+/// adapter can be added to a [`Stack`], e.g. `stack.layer(|fs| CachingAdapter::new(fs, ttl,
+/// 1024))`. [`Hooks`] are turned into layers by [`HookLayer`].
+pub trait Layer<S> {
+    /// The wrapped file system
+    type Service;
+
+    /// Wraps `inner`
+    fn layer(self, inner: S) -> Self::Service;
+}
+
+impl<S, F, T> Layer<S> for F
+where
+    F: FnOnce(S) -> T,
+{
+    type Service = T;
+
+    fn layer(self, inner: S) -> Self::Service {
+        self(inner)
+    }
+}
+
+/// Builds a file system from a backend and a list of layers.
+///
+/// Each call to [`layer`][Self::layer] wraps the file system built so far, so the last
+/// layer sees the requests first: in `Stack::new(fs).layer(cache).hooks(audit)`, the audit
+/// hooks run for every request, including the ones answered by the cache.
+///
+/// ```ignore
+/// let fs = Stack::new(backend)
+///     .layer(|fs| CachingAdapter::new(fs, Duration::from_secs(1), 4096))
+///     .hooks(Audit::default())
+///     .into_inner();
+/// ```
+pub struct Stack<S>(S);
+
+impl<S> Stack<S> {
+    /// Starts a stack with the file system that serves the requests in the end
+    pub const fn new(fs: S) -> Self {
+        Self(fs)
+    }
+
+    /// Wraps the stack into `layer`
+    pub fn layer<L>(self, layer: L) -> Stack<L::Service>
+    where
+        L: Layer<S>,
+    {
+        Stack(layer.layer(self.0))
+    }
+
+    /// Runs `hooks` around every request to the stack
+    pub fn hooks<H>(self, hooks: H) -> Stack<Hooked<S, H>> {
+        self.layer(HookLayer(hooks))
+    }
+
+    /// Returns the file system built by the stack
+    pub fn into_inner(self) -> S {
+        self.0
+    }
+}
+
+/// A request to a file system, as seen by [`Hooks`]
+#[derive(Debug)]
+pub enum FsRequest<'a, H> {
+    Lookup {
+        dirid: &'a H,
+        filename: &'a filename3<'a>,
+    },
+    LookupByPath {
+        path: &'a str,
+    },
+    Getattr {
+        id: &'a H,
+    },
+    /// Both `read` and `read_into`
+    Read {
+        id: &'a H,
+        offset: u64,
+        count: u32,
+    },
+    Readdir {
+        dirid: &'a H,
+        cookie: u64,
+    },
+    Readdirplus {
+        dirid: &'a H,
+        cookie: u64,
+    },
+    Cookieverf {
+        dirid: &'a H,
+    },
+    Getacl {
+        id: &'a H,
+    },
+    Readlink {
+        id: &'a H,
+    },
+    Fsinfo {
+        id: &'a H,
+    },
+    Fsstat {
+        id: &'a H,
+    },
+    Pathconf {
+        id: &'a H,
+    },
+    Setattr {
+        id: &'a H,
+        setattr: &'a sattr3,
+    },
+    Write {
+        id: &'a H,
+        offset: u64,
+        data: &'a [u8],
+    },
+    Commit {
+        id: &'a H,
+    },
+    Create {
+        dirid: &'a H,
+        filename: &'a filename3<'a>,
+        attr: &'a sattr3,
+    },
+    CreateExclusive {
+        dirid: &'a H,
+        filename: &'a filename3<'a>,
+        createverf: createverf3,
+    },
+    Mkdir {
+        dirid: &'a H,
+        dirname: &'a filename3<'a>,
+    },
+    Remove {
+        dirid: &'a H,
+        filename: &'a filename3<'a>,
+    },
+    Rename {
+        from_dirid: &'a H,
+        from_filename: &'a filename3<'a>,
+        to_dirid: &'a H,
+        to_filename: &'a filename3<'a>,
+    },
+    Symlink {
+        dirid: &'a H,
+        linkname: &'a filename3<'a>,
+        symlink: &'a nfspath3<'a>,
+        attr: &'a sattr3,
+    },
+    Setacl {
+        id: &'a H,
+        acl: &'a PosixAcl,
+    },
+}
+
+impl<H> FsRequest<'_, H> {
+    /// Returns the operation of the request
+    #[must_use]
+    pub const fn operation(&self) -> FsOperation {
+        match self {
+            Self::Lookup { .. } => FsOperation::Lookup,
+            Self::LookupByPath { .. } => FsOperation::LookupByPath,
+            Self::Getattr { .. } => FsOperation::Getattr,
+            Self::Read { .. } => FsOperation::Read,
+            Self::Readdir { .. } => FsOperation::Readdir,
+            Self::Readdirplus { .. } => FsOperation::Readdirplus,
+            Self::Cookieverf { .. } => FsOperation::Cookieverf,
+            Self::Getacl { .. } => FsOperation::Getacl,
+            Self::Readlink { .. } => FsOperation::Readlink,
+            Self::Fsinfo { .. } => FsOperation::Fsinfo,
+            Self::Fsstat { .. } => FsOperation::Fsstat,
+            Self::Pathconf { .. } => FsOperation::Pathconf,
+            Self::Setattr { .. } => FsOperation::Setattr,
+            Self::Write { .. } => FsOperation::Write,
+            Self::Commit { .. } => FsOperation::Commit,
+            Self::Create { .. } => FsOperation::Create,
+            Self::CreateExclusive { .. } => FsOperation::CreateExclusive,
+            Self::Mkdir { .. } => FsOperation::Mkdir,
+            Self::Remove { .. } => FsOperation::Remove,
+            Self::Rename { .. } => FsOperation::Rename,
+            Self::Symlink { .. } => FsOperation::Symlink,
+            Self::Setacl { .. } => FsOperation::Setacl,
+        }
+    }
+
+    /// Returns true if the request changes the file system
+    #[must_use]
+    pub const fn is_write(&self) -> bool {
+        matches!(
+            self,
+            Self::Setattr { .. }
+                | Self::Write { .. }
+                | Self::Commit { .. }
+                | Self::Create { .. }
+                | Self::CreateExclusive { .. }
+                | Self::Mkdir { .. }
+                | Self::Remove { .. }
+                | Self::Rename { .. }
+                | Self::Symlink { .. }
+                | Self::Setacl { .. }
+        )
+    }
+}
+
+/// Code that runs before and after every request, e.g. for logging, metrics or access
+/// control
+///
+/// Both hooks do nothing by default. For READDIR and READDIRPLUS, the hooks run around
+/// the call that opens the listing, not around each entry.
+pub trait Hooks: Send + Sync {
+    /// Runs before the request
+    ///
+    /// Returning an error fails the request with it, without calling the wrapped file
+    /// system.
+    fn before<H: FileHandle>(
+        &self,
+        request: &FsRequest<'_, H>,
+    ) -> impl Future<Output = Result<(), nfsstat3>> + Send {
+        let _ = request;
+        async { Ok(()) }
+    }
+
+    /// Runs after the request with its status, `NFS3_OK` if it succeeded
+    ///
+    /// It also runs when [`before`][Self::before] failed the request.
+    fn after<H: FileHandle>(
+        &self,
+        request: &FsRequest<'_, H>,
+        status: nfsstat3,
+    ) -> impl Future<Output = ()> + Send {
+        let _ = (request, status);
+        async {}
+    }
+}
+
+/// A [`Layer`] that runs [`Hooks`] around every request
+#[derive(Debug, Clone)]
+pub struct HookLayer<H>(pub H);
+
+impl<S, H> Layer<S> for HookLayer<H> {
+    type Service = Hooked<S, H>;
+
+    fn layer(self, inner: S) -> Self::Service {
+        Hooked {
+            inner,
+            hooks: self.0,
+        }
+    }
+}
+
+/// A file system wrapped by a [`HookLayer`]
+///
+/// `lookup_by_path` is resolved component by component, so the hooks see a `LookupByPath`
+/// request around the `Lookup` requests of its components.
+pub struct Hooked<S, H> {
+    inner: S,
+    hooks: H,
+}
+
+impl<S, H> Hooked<S, H> {
+    /// Returns the wrapped file system
+    pub const fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    /// Returns the hooks
+    pub const fn hooks(&self) -> &H {
+        &self.hooks
+    }
+}
+
+impl<S, H> Hooked<S, H>
+where
+    S: NfsReadFileSystem,
+    H: Hooks,
+{
+    async fn call<R>(
+        &self,
+        request: &FsRequest<'_, S::Handle>,
+        call: impl Future<Output = Result<R, nfsstat3>>,
+    ) -> Result<R, nfsstat3> {
+        let result = match self.hooks.before(request).await {
+            Ok(()) => call.await,
+            Err(stat) => Err(stat),
+        };
+        let status = result.as_ref().err().copied().unwrap_or(nfsstat3::NFS3_OK);
+        self.hooks.after(request, status).await;
+        result
+    }
+}
+
+impl<S, H> NfsReadFileSystem for Hooked<S, H>
+where
+    S: NfsReadFileSystem,
+    H: Hooks,
+{
+    type Handle = S::Handle;
+
+    fn root_dir(&self) -> Self::Handle {
+        self.inner.root_dir()
+    }
+
+    async fn lookup(
+        &self,
+        dirid: &Self::Handle,
+        filename: &filename3<'_>,
+    ) -> Result<Self::Handle, nfsstat3> {
+        let request = FsRequest::Lookup { dirid, filename };
+        self.call(&request, self.inner.lookup(dirid, filename))
+            .await
+    }
+
+    async fn lookup_by_path(&self, path: &str) -> Result<Self::Handle, nfsstat3> {
+        let request = FsRequest::LookupByPath { path };
+        // every component goes through `lookup`, so that its hooks see it
+        let walk = async {
+            let mut id = self.root_dir();
+            for component in path.split('/').filter(|c| !c.is_empty()) {
+                id = self.lookup(&id, &component.as_bytes().into()).await?;
+            }
+            Ok(id)
+        };
+        self.call(&request, walk).await
+    }
+
+    async fn getattr(&self, id: &Self::Handle) -> Result<fattr3, nfsstat3> {
+        let request = FsRequest::Getattr { id };
+        self.call(&request, self.inner.getattr(id)).await
+    }
+
+    async fn read(
+        &self,
+        id: &Self::Handle,
+        offset: u64,
+        count: u32,
+    ) -> Result<(Vec<u8>, bool), nfsstat3> {
+        let request = FsRequest::Read { id, offset, count };
+        self.call(&request, self.inner.read(id, offset, count))
+            .await
+    }
+
+    async fn read_into(
+        &self,
+        id: &Self::Handle,
+        offset: u64,
+        buf: &mut [u8],
+    ) -> Result<(usize, bool), nfsstat3> {
+        let count = u32::try_from(buf.len()).unwrap_or(u32::MAX);
+        let request = FsRequest::Read { id, offset, count };
+        self.call(&request, self.inner.read_into(id, offset, buf))
+            .await
+    }
+
+    async fn readdir(
+        &self,
+        dirid: &Self::Handle,
+        cookie: u64,
+    ) -> Result<impl ReadDirIterator, nfsstat3> {
+        let request = FsRequest::Readdir { dirid, cookie };
+        self.call(&request, self.inner.readdir(dirid, cookie)).await
+    }
+
+    async fn readdirplus(
+        &self,
+        dirid: &Self::Handle,
+        cookie: u64,
+    ) -> Result<impl ReadDirPlusIterator<Self::Handle>, nfsstat3> {
+        let request = FsRequest::Readdirplus { dirid, cookie };
+        self.call(&request, self.inner.readdirplus(dirid, cookie))
+            .await
+    }
+
+    async fn cookieverf(&self, dirid: &Self::Handle) -> Result<cookieverf3, nfsstat3> {
+        let request = FsRequest::Cookieverf { dirid };
+        self.call(&request, self.inner.cookieverf(dirid)).await
+    }
+
+    async fn getacl(&self, id: &Self::Handle) -> Result<PosixAcl, nfsstat3> {
+        let request = FsRequest::Getacl { id };
+        self.call(&request, self.inner.getacl(id)).await
+    }
+
+    async fn readlink(&self, id: &Self::Handle) -> Result<nfspath3<'_>, nfsstat3> {
+        let request = FsRequest::Readlink { id };
+        self.call(&request, self.inner.readlink(id)).await
+    }
+
+    async fn fsinfo(&self, root_fileid: &Self::Handle) -> Result<fsinfo3, nfsstat3> {
+        let request = FsRequest::Fsinfo { id: root_fileid };
+        self.call(&request, self.inner.fsinfo(root_fileid)).await
+    }
+
+    async fn fsstat(&self, root_fileid: &Self::Handle) -> Result<fsstat3, nfsstat3> {
+        let request = FsRequest::Fsstat { id: root_fileid };
+        self.call(&request, self.inner.fsstat(root_fileid)).await
+    }
+
+    async fn pathconf(&self, id: &Self::Handle) -> Result<pathconf3, nfsstat3> {
+        let request = FsRequest::Pathconf { id };
+        self.call(&request, self.inner.pathconf(id)).await
+    }
+}
+
+impl<S, H> NfsFileSystem for Hooked<S, H>
+where
+    S: NfsFileSystem,
+    H: Hooks,
+{
+    fn capabilities(&self) -> VFSCapabilities {
+        self.inner.capabilities()
+    }
+
+    async fn setattr(&self, id: &Self::Handle, setattr: sattr3) -> Result<fattr3, nfsstat3> {
+        let request = FsRequest::Setattr {
+            id,
+            setattr: &setattr,
+        };
+        self.call(&request, self.inner.setattr(id, setattr.clone()))
+            .await
+    }
+
+    async fn write(&self, id: &Self::Handle, offset: u64, data: &[u8]) -> Result<fattr3, nfsstat3> {
+        let request = FsRequest::Write { id, offset, data };
+        self.call(&request, self.inner.write(id, offset, data))
+            .await
+    }
+
+    fn buffers_writes(&self) -> bool {
+        self.inner.buffers_writes()
+    }
+
+    async fn commit(&self, id: &Self::Handle) -> Result<fattr3, nfsstat3> {
+        let request = FsRequest::Commit { id };
+        self.call(&request, self.inner.commit(id)).await
+    }
+
+    async fn create(
+        &self,
+        dirid: &Self::Handle,
+        filename: &filename3<'_>,
+        attr: sattr3,
+    ) -> Result<(Self::Handle, fattr3), nfsstat3> {
+        let request = FsRequest::Create {
+            dirid,
+            filename,
+            attr: &attr,
+        };
+        self.call(&request, self.inner.create(dirid, filename, attr.clone()))
+            .await
+    }
+
+    async fn create_exclusive(
+        &self,
+        dirid: &Self::Handle,
+        filename: &filename3<'_>,
+        createverf: createverf3,
+    ) -> Result<Self::Handle, nfsstat3> {
+        let request = FsRequest::CreateExclusive {
+            dirid,
+            filename,
+            createverf,
+        };
+        self.call(
+            &request,
+            self.inner.create_exclusive(dirid, filename, createverf),
+        )
+        .await
+    }
+
+    async fn mkdir(
+        &self,
+        dirid: &Self::Handle,
+        dirname: &filename3<'_>,
+    ) -> Result<(Self::Handle, fattr3), nfsstat3> {
+        let request = FsRequest::Mkdir { dirid, dirname };
+        self.call(&request, self.inner.mkdir(dirid, dirname)).await
+    }
+
+    async fn remove(&self, dirid: &Self::Handle, filename: &filename3<'_>) -> Result<(), nfsstat3> {
+        let request = FsRequest::Remove { dirid, filename };
+        self.call(&request, self.inner.remove(dirid, filename))
+            .await
+    }
+
+    async fn rename<'a>(
+        &self,
+        from_dirid: &Self::Handle,
+        from_filename: &filename3<'a>,
+        to_dirid: &Self::Handle,
+        to_filename: &filename3<'a>,
+    ) -> Result<(), nfsstat3> {
+        let request = FsRequest::Rename {
+            from_dirid,
+            from_filename,
+            to_dirid,
+            to_filename,
+        };
+        self.call(
+            &request,
+            self.inner
+                .rename(from_dirid, from_filename, to_dirid, to_filename),
+        )
+        .await
+    }
+
+    async fn symlink<'a>(
+        &self,
+        dirid: &Self::Handle,
+        linkname: &filename3<'a>,
+        symlink: &nfspath3<'a>,
+        attr: &sattr3,
+    ) -> Result<(Self::Handle, fattr3), nfsstat3> {
+        let request = FsRequest::Symlink {
+            dirid,
+            linkname,
+            symlink,
+            attr,
+        };
+        self.call(&request, self.inner.symlink(dirid, linkname, symlink, attr))
+            .await
+    }
+
+    async fn setacl(&self, id: &Self::Handle, acl: PosixAcl) -> Result<(), nfsstat3> {
+        let request = FsRequest::Setacl { id, acl: &acl };
+        self.call(&request, self.inner.setacl(id, acl.clone()))
+            .await
+    }
+}
@@ -0,0 +1,295 @@
+use nfs3_types::nfs3::{
+    FSINFO3resok as fsinfo3, FSSTAT3resok as fsstat3, PATHCONF3resok as pathconf3, cookieverf3,
+    createverf3, fattr3, filename3, ftype3, nfspath3, nfsstat3, sattr3,
+};
+use tracing::debug;
+
+use crate::vfs::{
+    DirEntryPlus, FileHandle, NextResult, NfsFileSystem, NfsReadFileSystem, PosixAcl,
+    ReadDirPlusIterator, VFSCapabilities,
+};
+
+/// Exposes a directory of the wrapped file system as the root of the export.
+///
+/// The handles are the ones of the wrapped file system. `lookup` of `..` in the new root
+/// returns the root itself, like `chroot` does, and so does the `..` entry of its listing.
+/// The adapter answers `.` itself and replaces every `..` that leads to the new root, by file
+/// id, with the root handle, so the clamp holds even if the wrapped file system hands out
+/// several handles for the same directory. `lookup_by_path` walks from the new root, so MOUNT
+/// requests resolve paths inside the subtree, and FSINFO and FSSTAT are answered for the new
+/// root whatever handle the client passes. The sizes they report are the ones of the wrapped
+/// file system, which the subtree shares. Names containing `/` are rejected with
+/// `NFS3ERR_INVAL`, so that a wrapped file system that accepts paths can't be led outside.
+///
+/// The adapter only hands out handles of objects inside the subtree, but it doesn't check the
+/// handles it receives. Enable [`NFSServer::with_handle_secret`][1] so that clients can't
+/// forge handles of objects outside of it.
+///
+/// [1]: crate::server::NFSServer::with_handle_secret
+pub struct SubtreeAdapter<T: NfsReadFileSystem> {
+    inner: T,
+    root: T::Handle,
+}
+
+impl<T> SubtreeAdapter<T>
+where
+    T: NfsReadFileSystem,
+{
+    /// Creates an adapter rooted at the directory `root` of `inner`
+    pub const fn new(inner: T, root: T::Handle) -> Self {
+        Self { inner, root }
+    }
+
+    /// Creates an adapter rooted at the directory `path` of `inner`
+    ///
+    /// Returns `NFS3ERR_NOTDIR` if `path` is not a directory.
+    pub async fn with_path(inner: T, path: &str) -> Result<Self, nfsstat3> {
+        let root = inner.lookup_by_path(path).await?;
+        if inner.getattr(&root).await?.type_ != ftype3::NF3DIR {
+            return Err(nfsstat3::NFS3ERR_NOTDIR);
+        }
+        Ok(Self::new(inner, root))
+    }
+
+    /// Returns the wrapped file system
+    pub const fn inner(&self) -> &T {
+        &self.inner
+    }
+
+    fn is_root(&self, id: &T::Handle) -> bool {
+        id.as_bytes() == self.root.as_bytes()
+    }
+
+    /// Replaces another handle of the new root with the root handle
+    async fn canonical(&self, id: T::Handle) -> Result<T::Handle, nfsstat3> {
+        if self.is_root(&id) {
+            return Ok(id);
+        }
+        let root_id = self.inner.getattr(&self.root).await?.fileid;
+        if self.inner.getattr(&id).await?.fileid == root_id {
+            Ok(self.root.clone())
+        } else {
+            Ok(id)
+        }
+    }
+}
+
+impl<T> NfsReadFileSystem for SubtreeAdapter<T>
+where
+    T: NfsReadFileSystem,
+{
+    type Handle = T::Handle;
+
+    fn root_dir(&self) -> Self::Handle {
+        self.root.clone()
+    }
+
+    async fn lookup(
+        &self,
+        dirid: &Self::Handle,
+        filename: &filename3<'_>,
+    ) -> Result<Self::Handle, nfsstat3> {
+        check_name(filename)?;
+        match filename.as_ref() {
+            b"." => Ok(dirid.clone()),
+            b".." if self.is_root(dirid) => Ok(self.root.clone()),
+            b".." => {
+                let parent = self.inner.lookup(dirid, filename).await?;
+                self.canonical(parent).await
+            }
+            _ => self.inner.lookup(dirid, filename).await,
+        }
+    }
+
+    async fn getattr(&self, id: &Self::Handle) -> Result<fattr3, nfsstat3> {
+        self.inner.getattr(id).await
+    }
+
+    async fn read(
+        &self,
+        id: &Self::Handle,
+        offset: u64,
+        count: u32,
+    ) -> Result<(Vec<u8>, bool), nfsstat3> {
+        self.inner.read(id, offset, count).await
+    }
+
+    async fn read_into(
+        &self,
+        id: &Self::Handle,
+        offset: u64,
+        buf: &mut [u8],
+    ) -> Result<(usize, bool), nfsstat3> {
+        self.inner.read_into(id, offset, buf).await
+    }
+
+    async fn readdirplus(
+        &self,
+        dirid: &Self::Handle,
+        cookie: u64,
+    ) -> Result<impl ReadDirPlusIterator<Self::Handle>, nfsstat3> {
+        let root_attr = self.inner.getattr(&self.root).await?;
+        let inner = self.inner.readdirplus(dirid, cookie).await?;
+        Ok(SubtreeIterator {
+            inner,
+            dir: dirid.clone(),
+            in_root: self.is_root(dirid),
+            root: (self.root.clone(), root_attr),
+        })
+    }
+
+    async fn cookieverf(&self, dirid: &Self::Handle) -> Result<cookieverf3, nfsstat3> {
+        self.inner.cookieverf(dirid).await
+    }
+
+    async fn getacl(&self, id: &Self::Handle) -> Result<PosixAcl, nfsstat3> {
+        self.inner.getacl(id).await
+    }
+
+    async fn readlink(&self, id: &Self::Handle) -> Result<nfspath3<'_>, nfsstat3> {
+        self.inner.readlink(id).await
+    }
+
+    async fn fsinfo(&self, _root_fileid: &Self::Handle) -> Result<fsinfo3, nfsstat3> {
+        self.inner.fsinfo(&self.root).await
+    }
+
+    async fn fsstat(&self, _root_fileid: &Self::Handle) -> Result<fsstat3, nfsstat3> {
+        self.inner.fsstat(&self.root).await
+    }
+
+    async fn pathconf(&self, id: &Self::Handle) -> Result<pathconf3, nfsstat3> {
+        self.inner.pathconf(id).await
+    }
+}
+
+impl<T> NfsFileSystem for SubtreeAdapter<T>
+where
+    T: NfsFileSystem,
+{
+    fn capabilities(&self) -> VFSCapabilities {
+        self.inner.capabilities()
+    }
+
+    async fn setattr(&self, id: &Self::Handle, setattr: sattr3) -> Result<fattr3, nfsstat3> {
+        self.inner.setattr(id, setattr).await
+    }
+
+    async fn write(&self, id: &Self::Handle, offset: u64, data: &[u8]) -> Result<fattr3, nfsstat3> {
+        self.inner.write(id, offset, data).await
+    }
+
+    fn buffers_writes(&self) -> bool {
+        self.inner.buffers_writes()
+    }
+
+    async fn commit(&self, id: &Self::Handle) -> Result<fattr3, nfsstat3> {
+        self.inner.commit(id).await
+    }
+
+    async fn create(
+        &self,
+        dirid: &Self::Handle,
+        filename: &filename3<'_>,
+        attr: sattr3,
+    ) -> Result<(Self::Handle, fattr3), nfsstat3> {
+        check_name(filename)?;
+        self.inner.create(dirid, filename, attr).await
+    }
+
+    async fn create_exclusive(
+        &self,
+        dirid: &Self::Handle,
+        filename: &filename3<'_>,
+        createverf: createverf3,
+    ) -> Result<Self::Handle, nfsstat3> {
+        check_name(filename)?;
+        self.inner
+            .create_exclusive(dirid, filename, createverf)
+            .await
+    }
+
+    async fn mkdir(
+        &self,
+        dirid: &Self::Handle,
+        dirname: &filename3<'_>,
+    ) -> Result<(Self::Handle, fattr3), nfsstat3> {
+        check_name(dirname)?;
+        self.inner.mkdir(dirid, dirname).await
+    }
+
+    async fn remove(&self, dirid: &Self::Handle, filename: &filename3<'_>) -> Result<(), nfsstat3> {
+        check_name(filename)?;
+        self.inner.remove(dirid, filename).await
+    }
+
+    async fn rename<'a>(
+        &self,
+        from_dirid: &Self::Handle,
+        from_filename: &filename3<'a>,
+        to_dirid: &Self::Handle,
+        to_filename: &filename3<'a>,
+    ) -> Result<(), nfsstat3> {
+        check_name(from_filename)?;
+        check_name(to_filename)?;
+        self.inner
+            .rename(from_dirid, from_filename, to_dirid, to_filename)
+            .await
+    }
+
+    async fn symlink<'a>(
+        &self,
+        dirid: &Self::Handle,
+        linkname: &filename3<'a>,
+        symlink: &nfspath3<'a>,
+        attr: &sattr3,
+    ) -> Result<(Self::Handle, fattr3), nfsstat3> {
+        check_name(linkname)?;
+        self.inner.symlink(dirid, linkname, symlink, attr).await
+    }
+
+    async fn setacl(&self, id: &Self::Handle, acl: PosixAcl) -> Result<(), nfsstat3> {
+        self.inner.setacl(id, acl).await
+    }
+}
+
+/// Rejects names that would reach past the directory, e.g. `../outside`
+fn check_name(name: &filename3<'_>) -> Result<(), nfsstat3> {
+    if name.as_ref().contains(&b'/') {
+        debug!("{:?} is not a name", String::from_utf8_lossy(name.as_ref()));
+        return Err(nfsstat3::NFS3ERR_INVAL);
+    }
+    Ok(())
+}
+
+/// Points the `.` entries to the listed directory and the `..` entries that lead to the new
+/// root to the root handle
+struct SubtreeIterator<H, I> {
+    inner: I,
+    dir: H,
+    in_root: bool,
+    root: (H, fattr3),
+}
+
+impl<H, I> ReadDirPlusIterator<H> for SubtreeIterator<H, I>
+where
+    H: FileHandle,
+    I: ReadDirPlusIterator<H>,
+{
+    async fn next(&mut self) -> NextResult<DirEntryPlus<H>> {
+        let mut result = self.inner.next().await;
+        if let NextResult::Ok(entry) = &mut result {
+            let (root, root_attr) = &self.root;
+            match entry.name.as_ref() {
+                b"." => entry.name_handle = Some(self.dir.clone()),
+                b".." if self.in_root || entry.fileid == root_attr.fileid => {
+                    entry.fileid = root_attr.fileid;
+                    entry.name_attributes = Some(root_attr.clone());
+                    entry.name_handle = Some(root.clone());
+                }
+                _ => {}
+            }
+        }
+        result
+    }
+}
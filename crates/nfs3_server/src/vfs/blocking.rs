@@ -0,0 +1,184 @@
+//! Synchronous versions of the file system traits
+//!
+//! Backends built on top of blocking libraries can implement these traits instead of the async
+//! ones and be served through [`BlockingAdapter`](super::adapters::BlockingAdapter), which runs
+//! every call on a pool of threads. The methods have the same meaning as in
+//! [`NfsReadFileSystem`](super::NfsReadFileSystem) and [`NfsFileSystem`](super::NfsFileSystem).
+
+use super::{
+    DirEntryPlus, FileHandle, PosixAcl, VFSCapabilities, default_fsinfo, default_fsstat,
+    default_pathconf,
+};
+use crate::nfs3_types::nfs3::{
+    FSINFO3resok as fsinfo3, FSSTAT3resok as fsstat3, PATHCONF3resok as pathconf3, cookieverf3,
+    createverf3, fattr3, filename3, nfspath3, nfsstat3, post_op_attr, sattr3,
+};
+
+/// Blocking read-only file system interface
+///
+/// See [`NfsReadFileSystem`](super::NfsReadFileSystem) for the description of the methods.
+pub trait BlockingNfsReadFileSystem: Send + Sync + 'static {
+    /// Type that can be used to indentify a file or folder in the file system.
+    type Handle: FileHandle + 'static;
+
+    /// Returns the ID the of the root directory "/"
+    fn root_dir(&self) -> Self::Handle;
+
+    /// Look up the id of a path in a directory
+    fn lookup(
+        &self,
+        dirid: &Self::Handle,
+        filename: &filename3<'_>,
+    ) -> Result<Self::Handle, nfsstat3>;
+
+    /// This method is used when the client tries to mount a subdirectory.
+    /// The default implementation walks the directory structure with [`lookup`](Self::lookup).
+    fn lookup_by_path(&self, path: &str) -> Result<Self::Handle, nfsstat3> {
+        let mut fid = self.root_dir();
+        for component in path.split('/').filter(|c| !c.is_empty()) {
+            fid = self.lookup(&fid, &component.as_bytes().into())?;
+        }
+        Ok(fid)
+    }
+
+    /// Returns the attributes of an id.
+    fn getattr(&self, id: &Self::Handle) -> Result<fattr3, nfsstat3>;
+
+    /// Reads the contents of a file returning (bytes, EOF)
+    fn read(&self, id: &Self::Handle, offset: u64, count: u32)
+    -> Result<(Vec<u8>, bool), nfsstat3>;
+
+    /// Returns the contents of a directory starting after `cookie`
+    ///
+    /// The iterator is consumed on the threads of the pool in batches, so it can't borrow
+    /// the file system. Backends that hold a borrowed cursor should collect the entries first.
+    fn readdirplus(
+        &self,
+        dirid: &Self::Handle,
+        cookie: u64,
+    ) -> Result<
+        impl Iterator<Item = Result<DirEntryPlus<Self::Handle>, nfsstat3>> + Send + use<Self>,
+        nfsstat3,
+    >;
+
+    /// Returns the cookie verifier of a directory
+    ///
+    /// The default implementation returns zero, meaning that cookies are always valid.
+    fn cookieverf(&self, dirid: &Self::Handle) -> Result<cookieverf3, nfsstat3> {
+        let _ = dirid;
+        Ok(cookieverf3::default())
+    }
+
+    /// Returns the POSIX ACLs of an object
+    ///
+    /// The default implementation returns no ACLs.
+    fn getacl(&self, id: &Self::Handle) -> Result<PosixAcl, nfsstat3> {
+        let _ = id;
+        Ok(PosixAcl::default())
+    }
+
+    /// Reads a symlink
+    fn readlink(&self, id: &Self::Handle) -> Result<nfspath3<'static>, nfsstat3>;
+
+    /// Get static file system Information
+    fn fsinfo(&self, root_fileid: &Self::Handle) -> Result<fsinfo3, nfsstat3> {
+        let dir_attr = self
+            .getattr(root_fileid)
+            .map_or(post_op_attr::None, post_op_attr::Some);
+        Ok(default_fsinfo(dir_attr))
+    }
+
+    /// Get dynamic file system information
+    fn fsstat(&self, root_fileid: &Self::Handle) -> Result<fsstat3, nfsstat3> {
+        let dir_attr = self
+            .getattr(root_fileid)
+            .map_or(post_op_attr::None, post_op_attr::Some);
+        Ok(default_fsstat(dir_attr))
+    }
+
+    /// Get the POSIX information of an object
+    fn pathconf(&self, id: &Self::Handle) -> Result<pathconf3, nfsstat3> {
+        let obj_attr = self
+            .getattr(id)
+            .map_or(post_op_attr::None, post_op_attr::Some);
+        Ok(default_pathconf(obj_attr))
+    }
+}
+
+/// Blocking write file system interface
+///
+/// See [`NfsFileSystem`](super::NfsFileSystem) for the description of the methods.
+pub trait BlockingNfsFileSystem: BlockingNfsReadFileSystem {
+    /// Returns the set of capabilities supported
+    fn capabilities(&self) -> VFSCapabilities {
+        VFSCapabilities::ReadWrite
+    }
+
+    /// Sets the attributes of an id
+    fn setattr(&self, id: &Self::Handle, setattr: sattr3) -> Result<fattr3, nfsstat3>;
+
+    /// Writes the contents of a file
+    fn write(&self, id: &Self::Handle, offset: u64, data: &[u8]) -> Result<fattr3, nfsstat3>;
+
+    /// Returns `true` if `write` may keep the data in a volatile buffer
+    fn buffers_writes(&self) -> bool {
+        false
+    }
+
+    /// Flushes the buffered data of a file to stable storage
+    fn commit(&self, id: &Self::Handle) -> Result<fattr3, nfsstat3> {
+        self.getattr(id)
+    }
+
+    /// Creates a file with the following attributes.
+    fn create(
+        &self,
+        dirid: &Self::Handle,
+        filename: &filename3<'_>,
+        attr: sattr3,
+    ) -> Result<(Self::Handle, fattr3), nfsstat3>;
+
+    /// Creates a file if it does not already exist.
+    fn create_exclusive(
+        &self,
+        dirid: &Self::Handle,
+        filename: &filename3<'_>,
+        createverf: createverf3,
+    ) -> Result<Self::Handle, nfsstat3>;
+
+    /// Makes a directory with the following attributes.
+    fn mkdir(
+        &self,
+        dirid: &Self::Handle,
+        dirname: &filename3<'_>,
+    ) -> Result<(Self::Handle, fattr3), nfsstat3>;
+
+    /// Removes a file.
+    fn remove(&self, dirid: &Self::Handle, filename: &filename3<'_>) -> Result<(), nfsstat3>;
+
+    /// Renames a file.
+    fn rename(
+        &self,
+        from_dirid: &Self::Handle,
+        from_filename: &filename3<'_>,
+        to_dirid: &Self::Handle,
+        to_filename: &filename3<'_>,
+    ) -> Result<(), nfsstat3>;
+
+    /// Makes a symlink with the following attributes.
+    fn symlink(
+        &self,
+        dirid: &Self::Handle,
+        linkname: &filename3<'_>,
+        symlink: &nfspath3<'_>,
+        attr: &sattr3,
+    ) -> Result<(Self::Handle, fattr3), nfsstat3>;
+
+    /// Sets the POSIX ACLs of an object
+    ///
+    /// The default implementation returns `Err(nfsstat3::NFS3ERR_NOTSUPP)`.
+    fn setacl(&self, id: &Self::Handle, acl: PosixAcl) -> Result<(), nfsstat3> {
+        let _ = (id, acl);
+        Err(nfsstat3::NFS3ERR_NOTSUPP)
+    }
+}
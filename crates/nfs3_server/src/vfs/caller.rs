@@ -0,0 +1,86 @@
+//! Credentials of the client whose request is being served
+
+use std::cell::RefCell;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use crate::nfs3_types::rpc::auth_unix;
+
+thread_local! {
+    static CALLER: RefCell<Option<Arc<auth_unix>>> = const { RefCell::new(None) };
+}
+
+/// Returns the `AUTH_UNIX` credentials of the client whose request is being served
+///
+/// The server sets them for the duration of every call, so the file system methods can use
+/// them, e.g. to check permissions or to charge quotas. Returns `None` outside of a call
+/// and for clients that don't send `AUTH_UNIX` credentials.
+///
+/// Tasks spawned by the file system don't inherit the credentials.
+/// [`BlockingAdapter`](super::adapters::BlockingAdapter) passes them to its worker threads.
+#[must_use]
+pub fn current_caller() -> Option<Arc<auth_unix>> {
+    CALLER.with(|caller| caller.borrow().clone())
+}
+
+/// Runs `future` with `caller` returned by [`current_caller`]
+///
+/// It's useful to call the file system directly on behalf of a client, e.g. in tests.
+pub fn with_caller<F>(caller: auth_unix, future: F) -> impl Future<Output = F::Output>
+where
+    F: Future,
+{
+    scope_future(Some(Arc::new(caller)), future)
+}
+
+/// Runs `future` with `caller` returned by [`current_caller`], `None` for unauthenticated calls
+pub fn scope_future<F>(caller: Option<Arc<auth_unix>>, future: F) -> impl Future<Output = F::Output>
+where
+    F: Future,
+{
+    WithCaller {
+        caller,
+        future: Box::pin(future),
+    }
+}
+
+/// Runs `f` with `caller` returned by [`current_caller`]
+pub fn scope<R>(caller: Option<Arc<auth_unix>>, f: impl FnOnce() -> R) -> R {
+    let _guard = Guard::enter(caller);
+    f()
+}
+
+struct WithCaller<F> {
+    caller: Option<Arc<auth_unix>>,
+    future: Pin<Box<F>>,
+}
+
+impl<F> Future for WithCaller<F>
+where
+    F: Future,
+{
+    type Output = F::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        let _guard = Guard::enter(this.caller.clone());
+        this.future.as_mut().poll(cx)
+    }
+}
+
+/// Restores the previous caller when dropped, even if the scoped code panics
+struct Guard(Option<Arc<auth_unix>>);
+
+impl Guard {
+    fn enter(caller: Option<Arc<auth_unix>>) -> Self {
+        Self(CALLER.with(|current| current.replace(caller)))
+    }
+}
+
+impl Drop for Guard {
+    fn drop(&mut self) {
+        let previous = self.0.take();
+        CALLER.with(|current| *current.borrow_mut() = previous);
+    }
+}
@@ -1,5 +1,9 @@
-use nfs3_types::nfs3::{nfs_fh3, nfsstat3, writeverf3};
+use std::sync::Arc;
+
+use hmac::{Hmac, Mac};
+use nfs3_types::nfs3::{NFS3_FHSIZE, nfs_fh3, nfsstat3, writeverf3};
 use nfs3_types::xdr_codec::Opaque;
+use sha2::Sha256;
 
 /// Represents a file handle
 ///
@@ -9,8 +13,17 @@ use nfs3_types::xdr_codec::Opaque;
 /// the first 8 bytes of the handle for its own use, while the remaining
 /// 56 bytes can be freely used by the implementation.
 ///
+/// When handle authentication is enabled with
+/// [`NFSServer::with_handle_secret`][3], the server also appends an
+/// 8-byte authentication tag, so only 48 bytes are left for the implementation.
+/// [`NFSServer::with_subtree_check`][4] reserves another 8 bytes for the
+/// mount scope, which leaves 40 bytes. Longer handles are refused with
+/// `NFS3ERR_SERVERFAULT` when the server converts them.
+///
 /// [1]: crate::vfs::NfsReadFileSystem
 /// [2]: crate::vfs::NfsFileSystem
+/// [3]: crate::server::NFSServer::with_handle_secret
+/// [4]: crate::server::NFSServer::with_subtree_check
 #[expect(clippy::len_without_is_empty)]
 pub trait FileHandle: std::fmt::Debug + Clone + Send + Sync {
     /// The length of the handle in bytes
@@ -94,33 +107,194 @@ impl PartialEq<u64> for FileHandleU64 {
     }
 }
 
-#[derive(Debug, Clone, Copy)]
+/// Length of the truncated HMAC appended to authenticated handles
+const HANDLE_TAG_LEN: usize = 8;
+/// Length of the mount scope stored after the generation number in subtree check mode
+const HANDLE_SCOPE_LEN: usize = 8;
+
+/// Signs and verifies file handles with an HMAC keyed by a server secret.
+///
+/// The export name is part of the signed data, so a handle issued for one
+/// export is rejected by a server exporting a different path with the same secret.
+#[derive(Clone)]
+struct HandleAuth {
+    /// HMAC that has already consumed the export name
+    mac: Hmac<Sha256>,
+}
+
+impl HandleAuth {
+    fn new(secret: &[u8], export_name: &str) -> Self {
+        let mut mac =
+            Hmac::<Sha256>::new_from_slice(secret).expect("HMAC accepts keys of any size");
+        // length prefix keeps the export name and the handle bytes apart
+        mac.update(&(export_name.len() as u64).to_le_bytes());
+        mac.update(export_name.as_bytes());
+        Self { mac }
+    }
+
+    fn tag(&self, data: &[u8]) -> [u8; HANDLE_TAG_LEN] {
+        let mut mac = self.mac.clone();
+        mac.update(data);
+        let mut tag = [0; HANDLE_TAG_LEN];
+        tag.copy_from_slice(&mac.finalize().into_bytes()[..HANDLE_TAG_LEN]);
+        tag
+    }
+
+    /// Derives the scope id of a mount root from its backend handle
+    fn scope(&self, root: &[u8]) -> [u8; HANDLE_SCOPE_LEN] {
+        let mut mac = self.mac.clone();
+        mac.update(b"scope");
+        mac.update(root);
+        let mut scope = [0; HANDLE_SCOPE_LEN];
+        scope.copy_from_slice(&mac.finalize().into_bytes()[..HANDLE_SCOPE_LEN]);
+        scope
+    }
+
+    fn verify(&self, data: &[u8], tag: &[u8]) -> bool {
+        let mut mac = self.mac.clone();
+        mac.update(data);
+        mac.verify_truncated_left(tag).is_ok()
+    }
+}
+
+impl std::fmt::Debug for HandleAuth {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("HandleAuth")
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct FileHandleConverter {
     generation_number: u64,
     generation_number_le: [u8; 8],
+    boot_verf: [u8; 8],
+    auth: Option<Arc<HandleAuth>>,
+    subtree_check: bool,
 }
 
 impl FileHandleConverter {
     #[allow(clippy::cast_possible_truncation)] // it's ok to truncate the generation number
     pub(crate) fn new() -> Self {
-        let generation_number = std::time::SystemTime::now()
+        let boot_time = std::time::SystemTime::now()
             .duration_since(std::time::UNIX_EPOCH)
             .expect("failed to get system time")
             .as_millis() as u64;
 
         Self {
-            generation_number,
-            generation_number_le: generation_number.to_le_bytes(),
+            generation_number: boot_time,
+            generation_number_le: boot_time.to_le_bytes(),
+            boot_verf: boot_time.to_le_bytes(),
+            auth: None,
+            subtree_check: false,
+        }
+    }
+
+    /// Replaces the generation number derived from the startup time with a fixed
+    /// server instance id, so that handles stay valid across restarts.
+    ///
+    /// The write verifier is not affected and still changes on every restart.
+    pub(crate) const fn set_instance_id(&mut self, instance_id: u64) {
+        self.generation_number = instance_id;
+        self.generation_number_le = instance_id.to_le_bytes();
+    }
+
+    /// Enables handle authentication. Every handle gets a truncated HMAC over its
+    /// bytes and the export name, keyed by `secret`. Passing `None` disables it.
+    pub(crate) fn set_secret(&mut self, secret: Option<&[u8]>, export_name: &str) {
+        self.auth = secret.map(|secret| Arc::new(HandleAuth::new(secret, export_name)));
+    }
+
+    /// Enables the subtree check mode. Every handle carries the scope of the mount
+    /// it was reached from, so the server can keep a client inside the mounted subtree.
+    ///
+    /// The scope is only trustworthy in signed handles, so a secret must be set as well.
+    pub(crate) const fn set_subtree_check(&mut self, enabled: bool) {
+        self.subtree_check = enabled;
+    }
+
+    /// Converts a mount root into a handle. In subtree check mode the handle starts a new scope.
+    pub(crate) fn fh_to_nfs(&self, id: &impl FileHandle) -> Result<nfs_fh3, nfsstat3> {
+        let scope = self.root_scope(id);
+        self.build_handle(scope.as_ref().map(<[u8; HANDLE_SCOPE_LEN]>::as_slice), id)
+    }
+
+    /// Converts an object reached from `parent` into a handle. In subtree check mode the
+    /// handle inherits the scope of `parent`, which must be a valid handle.
+    pub(crate) fn fh_to_nfs_child(
+        &self,
+        parent: &nfs_fh3,
+        id: &impl FileHandle,
+    ) -> Result<nfs_fh3, nfsstat3> {
+        self.build_handle(self.handle_scope(parent), id)
+    }
+
+    /// Returns `true` if `fh` is the root of the mount it was issued for, that is,
+    /// the client must not go any higher from it.
+    pub(crate) fn is_scope_root(&self, fh: &nfs_fh3, id: &impl FileHandle) -> bool {
+        self.handle_scope(fh)
+            .is_some_and(|scope| self.root_scope(id).is_some_and(|root| root == scope))
+    }
+
+    /// Returns `true` if both handles were issued for the same mount
+    pub(crate) fn same_scope(&self, fh1: &nfs_fh3, fh2: &nfs_fh3) -> bool {
+        self.handle_scope(fh1) == self.handle_scope(fh2)
+    }
+
+    fn root_scope(&self, id: &impl FileHandle) -> Option<[u8; HANDLE_SCOPE_LEN]> {
+        if !self.subtree_check {
+            return None;
         }
+        let auth = self
+            .auth
+            .as_ref()
+            .expect("subtree check requires handle authentication");
+        Some(auth.scope(id.as_bytes()))
     }
 
-    pub(crate) fn fh_to_nfs(&self, id: &impl FileHandle) -> nfs_fh3 {
-        let mut ret: Vec<u8> = Vec::with_capacity(8 + id.len());
+    fn handle_scope<'a>(&self, fh: &'a nfs_fh3) -> Option<&'a [u8]> {
+        if self.subtree_check {
+            fh.data.get(8..8 + HANDLE_SCOPE_LEN)
+        } else {
+            None
+        }
+    }
+
+    const fn header_len(&self) -> usize {
+        if self.subtree_check {
+            8 + HANDLE_SCOPE_LEN
+        } else {
+            8
+        }
+    }
+
+    fn build_handle(
+        &self,
+        scope: Option<&[u8]>,
+        id: &impl FileHandle,
+    ) -> Result<nfs_fh3, nfsstat3> {
+        let tag_len = if self.auth.is_some() {
+            HANDLE_TAG_LEN
+        } else {
+            0
+        };
+        let len = self.header_len() + id.len() + tag_len;
+        if len > NFS3_FHSIZE {
+            tracing::error!("file handle of {len} bytes exceeds NFS3_FHSIZE: {id:?}");
+            return Err(nfsstat3::NFS3ERR_SERVERFAULT);
+        }
+        let mut ret: Vec<u8> = Vec::with_capacity(len);
         ret.extend_from_slice(&self.generation_number_le);
+        if let Some(scope) = scope {
+            ret.extend_from_slice(scope);
+        }
         ret.extend_from_slice(id.as_bytes());
-        nfs_fh3 {
-            data: Opaque::owned(ret),
+        if let Some(auth) = &self.auth {
+            let tag = auth.tag(&ret);
+            ret.extend_from_slice(&tag);
         }
+        Ok(nfs_fh3 {
+            data: Opaque::owned(ret),
+        })
     }
 
     pub(crate) fn fh_from_nfs<FH>(&self, id: &nfs_fh3) -> Result<FH, nfsstat3>
@@ -129,7 +303,24 @@ impl FileHandleConverter {
     {
         self.check_handle(id)?;
 
-        FH::from_bytes(&id.data[8..]).ok_or(nfsstat3::NFS3ERR_BADHANDLE)
+        let data = match &self.auth {
+            None => &id.data[..],
+            Some(auth) => {
+                if id.data.len() < self.header_len() + HANDLE_TAG_LEN {
+                    return Err(nfsstat3::NFS3ERR_BADHANDLE);
+                }
+                let (data, tag) = id.data.split_at(id.data.len() - HANDLE_TAG_LEN);
+                if !auth.verify(data, tag) {
+                    tracing::warn!("rejecting file handle with invalid authentication tag");
+                    return Err(nfsstat3::NFS3ERR_BADHANDLE);
+                }
+                data
+            }
+        };
+
+        data.get(self.header_len()..)
+            .and_then(FH::from_bytes)
+            .ok_or(nfsstat3::NFS3ERR_BADHANDLE)
     }
 
     fn check_handle(&self, id: &nfs_fh3) -> Result<(), nfsstat3> {
@@ -159,7 +350,7 @@ impl FileHandleConverter {
     /// unique between instances of the NFS version 3 protocol
     /// server where uncommitted data may be lost.
     pub const fn verf(&self) -> writeverf3 {
-        writeverf3(self.generation_number_le)
+        writeverf3(self.boot_verf)
     }
 }
 
@@ -201,7 +392,7 @@ mod tests {
     fn test_file_handle_converter_3bytes() {
         let converter = FileHandleConverter::new();
         let handle = TestHandle { id: [1, 2, 3] };
-        let nfs_handle = converter.fh_to_nfs(&handle);
+        let nfs_handle = converter.fh_to_nfs(&handle).unwrap();
         assert_eq!(nfs_handle.data.len(), 11);
         assert_eq!(nfs_handle.data[0..8], converter.generation_number_le);
         assert_eq!(&nfs_handle.data[8..], handle.as_bytes());
@@ -218,7 +409,7 @@ mod tests {
                 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18, 19,
             ],
         };
-        let nfs_handle = converter.fh_to_nfs(&handle);
+        let nfs_handle = converter.fh_to_nfs(&handle).unwrap();
         assert_eq!(nfs_handle.data.len(), 27);
         assert_eq!(nfs_handle.data[0..8], converter.generation_number_le);
         assert_eq!(&nfs_handle.data[8..], handle.as_bytes());
@@ -228,4 +419,168 @@ mod tests {
             .unwrap();
         assert_eq!(converted_handle, handle);
     }
+
+    #[test]
+    fn test_file_handle_converter_instance_id() {
+        let mut converter = FileHandleConverter::new();
+        converter.set_instance_id(42);
+        let handle = FileHandleU64::new(7);
+        let nfs_handle = converter.fh_to_nfs(&handle).unwrap();
+        assert_eq!(nfs_handle.data[0..8], 42u64.to_le_bytes());
+
+        // a restarted server with the same instance id accepts the handle
+        let mut restarted = FileHandleConverter::new();
+        restarted.set_instance_id(42);
+        let converted_handle = restarted.fh_from_nfs::<FileHandleU64>(&nfs_handle).unwrap();
+        assert_eq!(converted_handle, handle);
+
+        // a newer instance id makes the old handles stale
+        restarted.set_instance_id(43);
+        assert!(matches!(
+            restarted.fh_from_nfs::<FileHandleU64>(&nfs_handle),
+            Err(nfsstat3::NFS3ERR_STALE)
+        ));
+    }
+
+    #[test]
+    fn test_file_handle_converter_signed() {
+        let mut converter = FileHandleConverter::new();
+        converter.set_secret(Some(b"secret"), "/export");
+        let handle = FileHandleU64::new(7);
+        let nfs_handle = converter.fh_to_nfs(&handle).unwrap();
+        assert_eq!(nfs_handle.data.len(), 8 + 8 + HANDLE_TAG_LEN);
+        assert_eq!(&nfs_handle.data[8..16], handle.as_bytes());
+
+        let converted_handle = converter.fh_from_nfs::<FileHandleU64>(&nfs_handle).unwrap();
+        assert_eq!(converted_handle, handle);
+    }
+
+    #[test]
+    fn test_file_handle_converter_rejects_forged_handles() {
+        let mut converter = FileHandleConverter::new();
+        converter.set_secret(Some(b"secret"), "/export");
+        let nfs_handle = converter.fh_to_nfs(&FileHandleU64::new(7)).unwrap();
+
+        // a guessed handle without a tag
+        let mut unsigned = FileHandleConverter::new();
+        unsigned.generation_number = converter.generation_number;
+        unsigned.generation_number_le = converter.generation_number_le;
+        let forged = unsigned.fh_to_nfs(&FileHandleU64::new(8)).unwrap();
+        assert!(matches!(
+            converter.fh_from_nfs::<FileHandleU64>(&forged),
+            Err(nfsstat3::NFS3ERR_BADHANDLE)
+        ));
+
+        // a valid handle with a modified id
+        let mut tampered = nfs_handle.data.to_vec();
+        tampered[8] ^= 1;
+        let tampered = nfs_handle3(tampered);
+        assert!(matches!(
+            converter.fh_from_nfs::<FileHandleU64>(&tampered),
+            Err(nfsstat3::NFS3ERR_BADHANDLE)
+        ));
+
+        // a different secret or export name
+        let mut other = converter.clone();
+        other.set_secret(Some(b"other secret"), "/export");
+        assert!(matches!(
+            other.fh_from_nfs::<FileHandleU64>(&nfs_handle),
+            Err(nfsstat3::NFS3ERR_BADHANDLE)
+        ));
+        other.set_secret(Some(b"secret"), "/other");
+        assert!(matches!(
+            other.fh_from_nfs::<FileHandleU64>(&nfs_handle),
+            Err(nfsstat3::NFS3ERR_BADHANDLE)
+        ));
+    }
+
+    fn nfs_handle3(data: Vec<u8>) -> nfs_fh3 {
+        nfs_fh3 {
+            data: Opaque::owned(data),
+        }
+    }
+
+    #[test]
+    fn test_file_handle_converter_subtree_check() {
+        let mut converter = FileHandleConverter::new();
+        converter.set_secret(Some(b"secret"), "/export");
+        converter.set_subtree_check(true);
+
+        let mount_root = FileHandleU64::new(2);
+        let child = FileHandleU64::new(3);
+        let root_fh = converter.fh_to_nfs(&mount_root).unwrap();
+        assert_eq!(
+            root_fh.data.len(),
+            8 + HANDLE_SCOPE_LEN + 8 + HANDLE_TAG_LEN
+        );
+        assert!(converter.is_scope_root(&root_fh, &mount_root));
+
+        // children inherit the scope of the mount root
+        let child_fh = converter.fh_to_nfs_child(&root_fh, &child).unwrap();
+        assert!(!converter.is_scope_root(&child_fh, &child));
+        assert!(converter.same_scope(&root_fh, &child_fh));
+        assert_eq!(
+            converter.fh_from_nfs::<FileHandleU64>(&child_fh).unwrap(),
+            child
+        );
+
+        // the same object reached from another mount belongs to another scope
+        let other_root_fh = converter.fh_to_nfs(&FileHandleU64::new(1)).unwrap();
+        let other_child_fh = converter.fh_to_nfs_child(&other_root_fh, &child).unwrap();
+        assert!(!converter.same_scope(&child_fh, &other_child_fh));
+        assert!(!converter.is_scope_root(&other_root_fh, &mount_root));
+
+        // a client can't move a handle into another scope
+        let mut moved = child_fh.data.to_vec();
+        moved[8..8 + HANDLE_SCOPE_LEN]
+            .copy_from_slice(&other_root_fh.data[8..8 + HANDLE_SCOPE_LEN]);
+        assert!(matches!(
+            converter.fh_from_nfs::<FileHandleU64>(&nfs_handle3(moved)),
+            Err(nfsstat3::NFS3ERR_BADHANDLE)
+        ));
+
+        // handles too short to hold a scope are rejected, even without a secret
+        converter.set_secret(None, "/export");
+        let short = root_fh.data[..12].to_vec();
+        assert!(matches!(
+            converter.fh_from_nfs::<TestHandle<0>>(&nfs_handle3(short)),
+            Err(nfsstat3::NFS3ERR_BADHANDLE)
+        ));
+    }
+
+    #[test]
+    fn test_file_handle_converter_rejects_large_handles() {
+        let mut converter = FileHandleConverter::new();
+        let handle = TestHandle { id: [7; 56] };
+        assert_eq!(
+            converter.fh_to_nfs(&handle).unwrap().data.len(),
+            NFS3_FHSIZE
+        );
+        let too_large = TestHandle { id: [7; 57] };
+        assert!(matches!(
+            converter.fh_to_nfs(&too_large),
+            Err(nfsstat3::NFS3ERR_SERVERFAULT)
+        ));
+
+        // the tag and the scope leave less room for the backend handle
+        converter.set_secret(Some(b"secret"), "/export");
+        assert!(matches!(
+            converter.fh_to_nfs(&handle),
+            Err(nfsstat3::NFS3ERR_SERVERFAULT)
+        ));
+        let signed = TestHandle { id: [7; 48] };
+        let root_fh = converter.fh_to_nfs(&signed).unwrap();
+        converter.set_subtree_check(true);
+        assert!(matches!(
+            converter.fh_to_nfs_child(&root_fh, &signed),
+            Err(nfsstat3::NFS3ERR_SERVERFAULT)
+        ));
+        let scoped = TestHandle { id: [7; 40] };
+        let root_fh = converter.fh_to_nfs(&scoped).unwrap();
+        assert_eq!(root_fh.data.len(), NFS3_FHSIZE);
+        assert_eq!(
+            converter.fh_from_nfs::<TestHandle<40>>(&root_fh).unwrap(),
+            scoped
+        );
+    }
 }
@@ -8,31 +8,48 @@
 //!    handle expires when the NFS server restarts)
 //!  - The 64-bit file id
 //!
+//! The generation number can be replaced with a fixed server instance id, see
+//! [`NFSServer::with_instance_id`](crate::server::NFSServer::with_instance_id). In that
+//! case the file system must hand out handles that stay valid across restarts too, otherwise
+//! clients would silently access the wrong objects.
+//!
 //! readdir pagination
 //! ------------------
-//! We do not use cookie verifier. We just use the `start_after`.  The
-//! implementation should allow startat to start at any position. That is,
-//! the next query to readdir may be the last entry in the previous readdir
-//! response.
+//! Pagination is driven by the cookies returned from the directory iterators.
+//! The implementation should allow the listing to resume after any cookie it
+//! has handed out. That is, the next query to readdir may be the last entry
+//! in the previous readdir response.
+//!
+//! The cookie verifier is provided by [`NfsReadFileSystem::cookieverf`]. By
+//! default it is zero, which tells the client that cookies are always valid.
+//! Implementations that can invalidate cookies should return a verifier that
+//! changes whenever the previously issued cookies can no longer be interpreted.
 //!
 //! Other requirements
 //! ------------------
-//!  getattr needs to be fast. NFS uses that a lot
+//!  getattr needs to be fast. NFS uses that a lot. Slow backends can be wrapped in
+//!  [`CachingAdapter`](adapters::CachingAdapter)
 //!
 //!  The 0 fileid is reserved and should not be used
 
 pub mod adapters;
+mod blocking;
+pub(crate) mod caller;
 pub(crate) mod handle;
 mod iterator;
 
+pub use blocking::{BlockingNfsFileSystem, BlockingNfsReadFileSystem};
+pub use caller::{current_caller, with_caller};
 pub use handle::{FileHandle, FileHandleU64};
 pub use iterator::*;
 
 use crate::nfs3_types::nfs3::{
-    FSF3_CANSETTIME, FSF3_HOMOGENEOUS, FSF3_SYMLINK, FSINFO3resok as fsinfo3, createverf3, fattr3,
+    FSF3_CANSETTIME, FSF3_HOMOGENEOUS, FSF3_SYMLINK, FSINFO3resok as fsinfo3,
+    FSSTAT3resok as fsstat3, PATHCONF3resok as pathconf3, cookieverf3, createverf3, fattr3,
     filename3, nfspath3, nfsstat3, nfstime3, post_op_attr, sattr3,
 };
-use crate::units::{GIBIBYTE, MEBIBYTE};
+use crate::nfs3_types::nfsacl::{ACL_GROUP_OBJ, ACL_MASK, ACL_OTHER, ACL_USER_OBJ, aclent};
+use crate::units::{GIBIBYTE, MEBIBYTE, TEBIBYTE};
 use crate::vfs::adapters::ReadDirPlusToReadDir;
 
 /// What capabilities are supported
@@ -42,6 +59,38 @@ pub enum VFSCapabilities {
     ReadWrite,
 }
 
+/// POSIX ACLs of a file system object
+///
+/// The entries use the NFSACL representation, see [`crate::nfs3_types::nfsacl`].
+/// The types of the default ACL entries don't carry the `NFS_ACL_DEFAULT` flag.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PosixAcl {
+    /// Access ACL. Empty if the permissions are described by the mode bits only.
+    pub access: Vec<aclent>,
+    /// Default ACL of a directory, inherited by new objects. Empty if not set.
+    pub default: Vec<aclent>,
+}
+
+impl PosixAcl {
+    /// Returns the permission bits of the mode that correspond to the access ACL
+    ///
+    /// The group bits are taken from the mask entry if there is one. Returns `None` if the
+    /// access ACL is empty.
+    #[must_use]
+    pub fn access_mode(&self) -> Option<u32> {
+        let perm = |type_| {
+            self.access
+                .iter()
+                .find(|entry| entry.type_ == type_)
+                .map(|entry| entry.perm & 0o7)
+        };
+        let user = perm(ACL_USER_OBJ)?;
+        let group = perm(ACL_MASK).or_else(|| perm(ACL_GROUP_OBJ))?;
+        let other = perm(ACL_OTHER)?;
+        Some((user << 6) | (group << 3) | other)
+    }
+}
+
 /// Read-only file system interface
 ///
 /// This should be enough to implement a read-only NFS server.
@@ -62,6 +111,10 @@ pub trait NfsReadFileSystem: Send + Sync {
     /// this may call `lookup(id_of("dir/"), "a.txt")`
     /// and this should return the id of the file `dir/a.txt`
     ///
+    /// `..` must return the same id as any other lookup of the parent directory,
+    /// and `..` of the root directory is the root directory itself. The subtree
+    /// check of the server relies on it to keep clients inside a mounted directory.
+    ///
     /// This method should be fast as it is used very frequently.
     fn lookup(
         &self,
@@ -103,6 +156,28 @@ pub trait NfsReadFileSystem: Send + Sync {
         count: u32,
     ) -> impl Future<Output = Result<(Vec<u8>, bool), nfsstat3>> + Send;
 
+    /// Reads the contents of a file into `buf` returning (number of bytes, EOF)
+    ///
+    /// The server passes a part of the reply buffer, so an implementation that fills `buf`
+    /// directly avoids copying the data on the READ path. Otherwise the same rules as for
+    /// [`read`](Self::read) apply, with `buf.len()` as the count.
+    ///
+    /// The default implementation calls `read` and copies the result into `buf`.
+    fn read_into(
+        &self,
+        id: &Self::Handle,
+        offset: u64,
+        buf: &mut [u8],
+    ) -> impl Future<Output = Result<(usize, bool), nfsstat3>> + Send {
+        async move {
+            let count = u32::try_from(buf.len()).unwrap_or(u32::MAX);
+            let (data, eof) = self.read(id, offset, count).await?;
+            let len = data.len().min(buf.len());
+            buf[..len].copy_from_slice(&data[..len]);
+            Ok((len, eof && len == data.len()))
+        }
+    }
+
     /// Simple version of readdir. Only need to return filename and id
     ///
     /// By default it uses `readdirplus` method to create an iterator
@@ -131,6 +206,33 @@ pub trait NfsReadFileSystem: Send + Sync {
         cookie: u64,
     ) -> impl Future<Output = Result<impl ReadDirPlusIterator<Self::Handle>, nfsstat3>> + Send;
 
+    /// Returns the cookie verifier of a directory
+    ///
+    /// The verifier is sent to the client with every READDIR and READDIRPLUS reply.
+    /// When the client resumes a listing with a verifier that doesn't match the current
+    /// one, the server rejects the request with `NFS3ERR_BAD_COOKIE`. The verifier should
+    /// change only when the cookies issued before can no longer be reliably interpreted.
+    ///
+    /// The default implementation returns zero, meaning that cookies are always valid.
+    fn cookieverf(
+        &self,
+        dirid: &Self::Handle,
+    ) -> impl Future<Output = Result<cookieverf3, nfsstat3>> + Send {
+        let _ = dirid;
+        async { Ok(cookieverf3::default()) }
+    }
+
+    /// Returns the POSIX ACLs of an object
+    ///
+    /// It's used by the NFSACL protocol, e.g. by `getfacl` on Linux clients. An empty access
+    /// ACL is reported to the clients as the minimal ACL derived from the mode bits.
+    ///
+    /// The default implementation returns no ACLs.
+    fn getacl(&self, id: &Self::Handle) -> impl Future<Output = Result<PosixAcl, nfsstat3>> + Send {
+        let _ = id;
+        async { Ok(PosixAcl::default()) }
+    }
+
     /// Reads a symlink
     fn readlink(
         &self,
@@ -138,6 +240,9 @@ pub trait NfsReadFileSystem: Send + Sync {
     ) -> impl Future<Output = Result<nfspath3<'_>, nfsstat3>> + Send;
 
     /// Get static file system Information
+    ///
+    /// The values can be overridden per listener with
+    /// [`NFSServer::with_fsinfo`](crate::server::NFSServer::with_fsinfo).
     fn fsinfo(
         &self,
         root_fileid: &Self::Handle,
@@ -147,28 +252,91 @@ pub trait NfsReadFileSystem: Send + Sync {
                 .getattr(root_fileid)
                 .await
                 .map_or(post_op_attr::None, post_op_attr::Some);
+            Ok(default_fsinfo(dir_attr))
+        }
+    }
+
+    /// Get dynamic file system information, i.e. the total and free space and file slots
+    ///
+    /// The default implementation reports a file system of 1 TiB with 1 Gi files that is
+    /// always empty.
+    fn fsstat(
+        &self,
+        root_fileid: &Self::Handle,
+    ) -> impl Future<Output = Result<fsstat3, nfsstat3>> + Send {
+        async move {
+            let dir_attr = self
+                .getattr(root_fileid)
+                .await
+                .map_or(post_op_attr::None, post_op_attr::Some);
+            Ok(default_fsstat(dir_attr))
+        }
+    }
 
-            let res = fsinfo3 {
-                obj_attributes: dir_attr,
-                rtmax: MEBIBYTE,
-                rtpref: MEBIBYTE,
-                rtmult: MEBIBYTE,
-                wtmax: MEBIBYTE,
-                wtpref: MEBIBYTE,
-                wtmult: MEBIBYTE,
-                dtpref: MEBIBYTE,
-                maxfilesize: 128u64 * GIBIBYTE,
-                time_delta: nfstime3 {
-                    seconds: 0,
-                    nseconds: 1_000_000,
-                },
-                properties: FSF3_SYMLINK | FSF3_HOMOGENEOUS | FSF3_CANSETTIME,
-            };
-            Ok(res)
+    /// Get the POSIX information of an object, e.g. the maximum name length
+    ///
+    /// The default implementation reports a case-sensitive file system without hard links.
+    fn pathconf(
+        &self,
+        id: &Self::Handle,
+    ) -> impl Future<Output = Result<pathconf3, nfsstat3>> + Send {
+        async move {
+            let obj_attr = self
+                .getattr(id)
+                .await
+                .map_or(post_op_attr::None, post_op_attr::Some);
+            Ok(default_pathconf(obj_attr))
         }
     }
 }
 
+/// Returns the FSINFO values used by the default implementations of `fsinfo`
+pub(crate) const fn default_fsinfo(obj_attributes: post_op_attr) -> fsinfo3 {
+    fsinfo3 {
+        obj_attributes,
+        rtmax: MEBIBYTE,
+        rtpref: MEBIBYTE,
+        rtmult: MEBIBYTE,
+        wtmax: MEBIBYTE,
+        wtpref: MEBIBYTE,
+        wtmult: MEBIBYTE,
+        dtpref: MEBIBYTE,
+        maxfilesize: 128u64 * GIBIBYTE,
+        time_delta: nfstime3 {
+            seconds: 0,
+            nseconds: 1_000_000,
+        },
+        properties: FSF3_SYMLINK | FSF3_HOMOGENEOUS | FSF3_CANSETTIME,
+    }
+}
+
+/// Returns the FSSTAT values used by the default implementations of `fsstat`
+pub(crate) const fn default_fsstat(obj_attributes: post_op_attr) -> fsstat3 {
+    fsstat3 {
+        obj_attributes,
+        tbytes: TEBIBYTE,
+        fbytes: TEBIBYTE,
+        abytes: TEBIBYTE,
+        tfiles: GIBIBYTE,
+        ffiles: GIBIBYTE,
+        afiles: GIBIBYTE,
+        invarsec: u32::MAX,
+    }
+}
+
+/// Returns the PATHCONF values used by the default implementations of `pathconf`
+pub(crate) const fn default_pathconf(obj_attributes: post_op_attr) -> pathconf3 {
+    pathconf3 {
+        obj_attributes,
+        linkmax: 0,
+        name_max: 32768,
+        no_trunc: true,
+        chown_restricted: true,
+        case_insensitive: false,
+        case_preserving: true,
+    }
+}
+
 /// Write file system interface
 ///
 /// This is the interface to implement if you want to provide a writable NFS server.
@@ -205,6 +373,23 @@ pub trait NfsFileSystem: NfsReadFileSystem {
         data: &[u8],
     ) -> impl Future<Output = Result<fattr3, nfsstat3>> + Send;
 
+    /// Returns `true` if [`write`](Self::write) may keep the data in a volatile buffer
+    ///
+    /// The server then replies to unstable writes as such, so clients send a COMMIT before they
+    /// consider the data safe. Writes that are requested to be stable are followed by a call to
+    /// [`commit`](Self::commit). The default implementation returns `false`.
+    fn buffers_writes(&self) -> bool {
+        false
+    }
+
+    /// Flushes the buffered data of a file to stable storage, returning its attributes
+    ///
+    /// File systems that don't buffer writes don't need to implement this, the default
+    /// implementation returns the attributes of the file.
+    fn commit(&self, id: &Self::Handle) -> impl Future<Output = Result<fattr3, nfsstat3>> + Send {
+        self.getattr(id)
+    }
+
     /// Creates a file with the following attributes.
     /// If not supported due to readonly file system
     /// this should return `Err(nfsstat3::NFS3ERR_ROFS)`
@@ -281,4 +466,19 @@ pub trait NfsFileSystem: NfsReadFileSystem {
         symlink: &nfspath3<'a>,
         attr: &sattr3,
     ) -> impl Future<Output = Result<(Self::Handle, fattr3), nfsstat3>> + Send;
+
+    /// Sets the POSIX ACLs of an object
+    ///
+    /// An empty access or default ACL removes it. The ACLs are validated by the server,
+    /// but the file system should also update the mode bits to match the access ACL.
+    ///
+    /// The default implementation returns `Err(nfsstat3::NFS3ERR_NOTSUPP)`.
+    fn setacl(
+        &self,
+        id: &Self::Handle,
+        acl: PosixAcl,
+    ) -> impl Future<Output = Result<(), nfsstat3>> + Send {
+        let _ = (id, acl);
+        async { Err(nfsstat3::NFS3ERR_NOTSUPP) }
+    }
 }
@@ -37,6 +37,10 @@ impl TestContext {
         self.config.temp_dir.path()
     }
 
+    pub const fn port(&self) -> u16 {
+        self.config.bind_port
+    }
+
     pub fn root_fh(&self) -> nfs_fh3 {
         self.client.root_nfs_fh3()
     }
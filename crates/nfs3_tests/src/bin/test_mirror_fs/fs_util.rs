@@ -38,15 +38,11 @@ pub fn assert_attributes_match(
 
     // Verify type matches filesystem
     match expected_type {
-        ftype3::NF3DIR => {
-            if !metadata.is_dir() {
-                bail!("NFS reports directory but filesystem shows file");
-            }
+        ftype3::NF3DIR if !metadata.is_dir() => {
+            bail!("NFS reports directory but filesystem shows file");
         }
-        ftype3::NF3REG => {
-            if !metadata.is_file() {
-                bail!("NFS reports file but filesystem shows directory");
-            }
+        ftype3::NF3REG if !metadata.is_file() => {
+            bail!("NFS reports file but filesystem shows directory");
         }
         _ => {}
     }
@@ -79,6 +79,7 @@ async fn run_readonly_tests() {
     test!(ctx, readonly::lookup_existing_file);
     test!(ctx, readonly::lookup_non_existing_file);
     test!(ctx, readonly::lookup_in_subdirectory);
+    test!(ctx, readonly::lookup_dot_entries);
 
     println!();
     println!("  Access Operations");
@@ -96,6 +97,7 @@ async fn run_readonly_tests() {
     test!(ctx, readonly::readdir_multiple_files);
     test!(ctx, readonly::readdir_empty_directory);
     test!(ctx, readonly::readdir_many_files);
+    test!(ctx, readonly::readdir_nfs2);
     test!(ctx, readonly::readdirplus_basic);
 
     println!();
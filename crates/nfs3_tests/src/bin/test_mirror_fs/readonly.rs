@@ -144,6 +144,25 @@ pub async fn lookup_existing_file(ctx: &mut TestContext, subdir: PathBuf, subdir
     .await;
 }
 
+pub async fn lookup_dot_entries(ctx: &mut TestContext, subdir: PathBuf, subdir_fh: nfs_fh3) {
+    fs::create_dir(subdir.join("nested")).expect("failed to create subdirectory");
+
+    // "." and ".." resolve to the same handles as the directories they name
+    let dot = ctx.just_lookup(&subdir_fh, ".").await.unwrap();
+    assert_eq!(dot, subdir_fh);
+    let nested = ctx.just_lookup(&subdir_fh, "nested").await.unwrap();
+    let parent = ctx.just_lookup(&nested, "..").await.unwrap();
+    assert_eq!(parent, subdir_fh);
+    let dot = ctx.just_lookup(&nested, ".").await.unwrap();
+    assert_eq!(ctx.just_lookup(&dot, "..").await.unwrap(), subdir_fh);
+
+    // ".." can't leave the exported directory
+    let root = ctx.root_fh();
+    assert_eq!(ctx.just_lookup(&root, "..").await.unwrap(), root);
+    let dot = ctx.just_lookup(&root, ".").await.unwrap();
+    assert_eq!(ctx.just_lookup(&dot, "..").await.unwrap(), root);
+}
+
 pub async fn lookup_non_existing_file(ctx: &mut TestContext, subdir: PathBuf, subdir_fh: nfs_fh3) {
     const NAME: &str = "non_existing_file.txt";
 
@@ -431,6 +450,57 @@ pub async fn readdir_many_files(ctx: &mut TestContext, subdir: PathBuf, subdir_f
     assert_eq!(found_files, 50, "Should find all 50 created files");
 }
 
+pub async fn readdir_nfs2(ctx: &mut TestContext, subdir: PathBuf, subdir_fh: nfs_fh3) {
+    use nfs3_client::nfs3_types::nfs2::{
+        NFS_PROGRAM, NFS2_COOKIESIZE, NFS2_FHSIZE, PROGRAM, READDIR2args, READDIR2res, fhandle2,
+    };
+
+    for i in 1..=50 {
+        let file_path = subdir.join(format!("v2_{i:03}.txt"));
+        fs::write(file_path, format!("content {i}")).expect("failed to write test file");
+    }
+
+    // the NFSv2 handle holds the length of the NFSv3 handle and the handle itself
+    let mut dir = [0; NFS2_FHSIZE];
+    dir[0] = u8::try_from(subdir_fh.data.len()).unwrap();
+    dir[1..=subdir_fh.data.len()].copy_from_slice(&subdir_fh.data);
+    let dir = fhandle2(dir);
+
+    let stream = tokio::net::TcpStream::connect(("127.0.0.1", ctx.port()))
+        .await
+        .expect("failed to connect");
+    let mut client = nfs3_client::rpc::RpcClient::new(nfs3_client::tokio::TokioIo::new(stream));
+
+    // the mirror hands out 64-bit cookies, which are mapped to 32-bit ones
+    let mut names = Vec::new();
+    let mut cookie = [0; NFS2_COOKIESIZE];
+    loop {
+        let args = READDIR2args {
+            dir,
+            cookie,
+            count: 256,
+        };
+        let res: READDIR2res = client
+            .call(PROGRAM, 2, NFS_PROGRAM::NFSPROC_READDIR as u32, &args)
+            .await
+            .expect("readdir call failed");
+        let resok = res.unwrap();
+        for entry in &resok.entries.0 {
+            names.push(String::from_utf8_lossy(&entry.name.0).into_owned());
+            cookie = entry.cookie;
+        }
+        if resok.eof {
+            break;
+        }
+    }
+    names.retain(|name| name != "." && name != "..");
+    names.sort();
+    let expected = (1..=50)
+        .map(|i| format!("v2_{i:03}.txt"))
+        .collect::<Vec<_>>();
+    assert_eq!(names, expected);
+}
+
 pub async fn readdirplus_basic(ctx: &mut TestContext, subdir: PathBuf, subdir_fh: nfs_fh3) {
     // Create files in the provided subdirectory
     for i in 1..=3 {
@@ -1,13 +1,17 @@
 mod just_client;
 pub mod rpc_tests;
+pub mod s3;
 mod server;
+pub mod vfs;
 
 use std::ops::{Deref, DerefMut};
 
 pub use just_client::{JustClient, JustClientExt};
 use nfs3_client::nfs3_types::nfs3::nfs_fh3;
+use nfs3_client::nfs3_types::rpc::{auth_unix, opaque_auth};
 use nfs3_client::tokio::TokioIo;
 use nfs3_server::memfs::{MemFs, MemFsConfig};
+use nfs3_server::vfs::NfsFileSystem;
 use nfs3_server::vfs::adapters::ReadOnlyAdapter;
 pub use rpc_tests::RpcTestContext;
 pub use server::Server;
@@ -28,6 +32,41 @@ impl TestContext<TokioIo<DuplexStream>> {
         Self::setup_with_config(Self::config(), true, tracing::Level::DEBUG)
     }
 
+    pub fn setup_subtree_check() -> Self {
+        Self::setup_inner(Self::config(), false, true, tracing::Level::DEBUG)
+    }
+
+    /// Serves `fs` to a client without credentials
+    pub fn serve<FS: NfsFileSystem + 'static>(fs: FS) -> Self {
+        Self::serve_with_credential(fs, opaque_auth::default())
+    }
+
+    /// Serves `fs` to a client that sends the AUTH_UNIX credentials `auth`
+    pub fn serve_as<FS: NfsFileSystem + 'static>(fs: FS, auth: &auth_unix) -> Self {
+        Self::serve_with_credential(fs, opaque_auth::auth_unix(auth))
+    }
+
+    fn serve_with_credential<FS: NfsFileSystem + 'static>(
+        fs: FS,
+        credential: opaque_auth<'static>,
+    ) -> Self {
+        let (server, client) = duplex(1024 * 1024);
+        let server = Server::new(server, fs).unwrap();
+        let root_dir = server.root_dir();
+        let server_handle = tokio::task::spawn(server.run());
+        let client = nfs3_client::Nfs3Client::new_with_auth(
+            TokioIo::new(client),
+            credential,
+            opaque_auth::default(),
+        );
+
+        Self {
+            server_handle,
+            client,
+            root_dir,
+        }
+    }
+
     fn config() -> MemFsConfig {
         let mut config = MemFsConfig::default();
 
@@ -43,6 +82,15 @@ impl TestContext<TokioIo<DuplexStream>> {
         fs_config: MemFsConfig,
         readonly: bool,
         log_level: tracing::Level,
+    ) -> Self {
+        Self::setup_inner(fs_config, readonly, false, log_level)
+    }
+
+    fn setup_inner(
+        fs_config: MemFsConfig,
+        readonly: bool,
+        subtree_check: bool,
+        log_level: tracing::Level,
     ) -> Self {
         init_logging(log_level);
 
@@ -55,7 +103,10 @@ impl TestContext<TokioIo<DuplexStream>> {
             let server_handle = tokio::task::spawn(server.run());
             (root_dir, server_handle)
         } else {
-            let server = Server::new(server, memfs).unwrap();
+            let mut server = Server::new(server, memfs).unwrap();
+            if subtree_check {
+                server = server.with_subtree_check();
+            }
             let root_dir = server.root_dir();
             let server_handle = tokio::task::spawn(server.run());
             (root_dir, server_handle)
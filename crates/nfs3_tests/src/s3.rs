@@ -0,0 +1,479 @@
+//! An in-process stand-in for an S3-compatible object store
+//!
+//! It serves one bucket over plain HTTP with path-style requests and supports the operations
+//! used by `S3Fs`. Requests must carry a signature header, but the signature isn't verified.
+
+use std::collections::{BTreeMap, HashMap};
+use std::convert::Infallible;
+use std::fmt::Write;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+
+use chrono::{DateTime, SecondsFormat, Utc};
+use http_body_util::{BodyExt, Full};
+use hyper::body::{Bytes, Incoming};
+use hyper::server::conn::http1;
+use hyper::service::service_fn;
+use hyper::{Method, Request, Response, StatusCode};
+use hyper_util::rt::TokioIo;
+use tokio::net::TcpListener;
+use tokio::task::JoinHandle;
+
+/// The smallest size of all parts but the last one
+const MIN_PART_SIZE: usize = 5 * 1024 * 1024;
+
+struct Object {
+    data: Bytes,
+    modified: DateTime<Utc>,
+}
+
+#[derive(Default)]
+struct State {
+    bucket: String,
+    objects: BTreeMap<String, Object>,
+    uploads: HashMap<String, BTreeMap<u32, Bytes>>,
+    next_upload: u64,
+    /// Number of requests per operation
+    operations: HashMap<&'static str, usize>,
+}
+
+pub struct FakeS3 {
+    addr: SocketAddr,
+    state: Arc<Mutex<State>>,
+    task: JoinHandle<()>,
+}
+
+impl FakeS3 {
+    pub async fn start(bucket: &str) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let state = Arc::new(Mutex::new(State {
+            bucket: bucket.to_string(),
+            ..State::default()
+        }));
+        let shared = state.clone();
+        let task = tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let state = shared.clone();
+                let service = service_fn(move |request| handle(state.clone(), request));
+                tokio::spawn(http1::Builder::new().serve_connection(TokioIo::new(stream), service));
+            }
+        });
+        Self { addr, state, task }
+    }
+
+    pub fn endpoint(&self) -> String {
+        format!("http://{}", self.addr)
+    }
+
+    pub fn put(&self, key: &str, data: &[u8]) {
+        let mut state = self.state.lock().unwrap();
+        state.objects.insert(
+            key.to_string(),
+            Object {
+                data: Bytes::copy_from_slice(data),
+                modified: Utc::now(),
+            },
+        );
+    }
+
+    pub fn get(&self, key: &str) -> Option<Vec<u8>> {
+        let state = self.state.lock().unwrap();
+        state.objects.get(key).map(|object| object.data.to_vec())
+    }
+
+    pub fn keys(&self) -> Vec<String> {
+        let state = self.state.lock().unwrap();
+        state.objects.keys().cloned().collect()
+    }
+
+    /// Returns the number of requests of an operation, e.g. `UploadPart`
+    pub fn count(&self, operation: &str) -> usize {
+        let state = self.state.lock().unwrap();
+        state.operations.get(operation).copied().unwrap_or_default()
+    }
+
+    /// Returns the number of multipart uploads that were neither completed nor aborted
+    pub fn pending_uploads(&self) -> usize {
+        self.state.lock().unwrap().uploads.len()
+    }
+}
+
+impl Drop for FakeS3 {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+async fn handle(
+    state: Arc<Mutex<State>>,
+    request: Request<Incoming>,
+) -> Result<Response<Full<Bytes>>, Infallible> {
+    let (parts, body) = request.into_parts();
+    let body = body.collect().await.unwrap().to_bytes();
+    let authorized = parts
+        .headers
+        .get("authorization")
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("AWS4-HMAC-SHA256 Credential="));
+    if !authorized {
+        return Ok(error(StatusCode::FORBIDDEN, "AccessDenied"));
+    }
+
+    let path = decode(parts.uri.path().trim_start_matches('/'));
+    let (bucket, key) = path.split_once('/').unwrap_or((&path, ""));
+    let query = parts
+        .uri
+        .query()
+        .unwrap_or_default()
+        .split('&')
+        .filter(|param| !param.is_empty())
+        .map(|param| {
+            let (name, value) = param.split_once('=').unwrap_or((param, ""));
+            (decode(name), decode(value))
+        })
+        .collect::<HashMap<_, _>>();
+    let header = |name: &str| {
+        parts
+            .headers
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string)
+    };
+
+    let mut state = state.lock().unwrap();
+    if bucket != state.bucket {
+        return Ok(error(StatusCode::NOT_FOUND, "NoSuchBucket"));
+    }
+    let upload_id = query.get("uploadId").cloned();
+    let response = match (&parts.method, key.is_empty()) {
+        (&Method::GET, true) => state.list(&query),
+        (&Method::HEAD, false) => state.head(key),
+        (&Method::GET, false) => state.get(key, header("range")),
+        (&Method::PUT, false) => {
+            if let Some(source) = header("x-amz-copy-source") {
+                state.copy(&decode(&source), key)
+            } else if let Some(upload_id) = upload_id {
+                let number = query["partNumber"].parse().unwrap();
+                state.upload_part(&upload_id, number, body)
+            } else {
+                state.count("PutObject");
+                state.store(key, body);
+                Response::new(Full::default())
+            }
+        }
+        (&Method::DELETE, false) => {
+            if let Some(upload_id) = upload_id {
+                state.count("AbortMultipartUpload");
+                state.uploads.remove(&upload_id);
+            } else {
+                state.count("DeleteObject");
+                state.objects.remove(key);
+            }
+            status(StatusCode::NO_CONTENT)
+        }
+        (&Method::POST, false) if query.contains_key("uploads") => {
+            state.count("CreateMultipartUpload");
+            state.next_upload += 1;
+            let upload_id = format!("upload-{}", state.next_upload);
+            state.uploads.insert(upload_id.clone(), BTreeMap::new());
+            xml(&format!(
+                "<InitiateMultipartUploadResult><Bucket>{bucket}</Bucket><Key>{key}</Key>\
+                 <UploadId>{upload_id}</UploadId></InitiateMultipartUploadResult>"
+            ))
+        }
+        (&Method::POST, false) if upload_id.is_some() => {
+            state.complete(key, &upload_id.unwrap_or_default(), &body)
+        }
+        _ => error(StatusCode::NOT_IMPLEMENTED, "NotImplemented"),
+    };
+    Ok(response)
+}
+
+impl State {
+    fn count(&mut self, operation: &'static str) {
+        *self.operations.entry(operation).or_default() += 1;
+    }
+
+    fn store(&mut self, key: &str, data: Bytes) {
+        let object = Object {
+            data,
+            modified: Utc::now(),
+        };
+        self.objects.insert(key.to_string(), object);
+    }
+
+    fn head(&mut self, key: &str) -> Response<Full<Bytes>> {
+        self.count("HeadObject");
+        let Some(object) = self.objects.get(key) else {
+            return status(StatusCode::NOT_FOUND);
+        };
+        Response::builder()
+            .header("content-length", object.data.len())
+            .header("last-modified", http_date(object.modified))
+            .body(Full::default())
+            .unwrap()
+    }
+
+    fn get(&mut self, key: &str, range: Option<String>) -> Response<Full<Bytes>> {
+        let Some(object) = self.objects.get(key) else {
+            return error(StatusCode::NOT_FOUND, "NoSuchKey");
+        };
+        let data = object.data.clone();
+        let modified = http_date(object.modified);
+        let Some(range) = range else {
+            self.count("GetObject");
+            return Response::builder()
+                .header("last-modified", modified)
+                .body(Full::new(data))
+                .unwrap();
+        };
+
+        self.count("GetObjectRange");
+        let (first, last) = range
+            .strip_prefix("bytes=")
+            .and_then(|range| range.split_once('-'))
+            .unwrap();
+        let first = first.parse::<usize>().unwrap();
+        if first >= data.len() {
+            return Response::builder()
+                .status(StatusCode::RANGE_NOT_SATISFIABLE)
+                .header("content-range", format!("bytes */{}", data.len()))
+                .body(Full::default())
+                .unwrap();
+        }
+        let last = last.parse::<usize>().unwrap().min(data.len() - 1);
+        Response::builder()
+            .status(StatusCode::PARTIAL_CONTENT)
+            .header(
+                "content-range",
+                format!("bytes {first}-{last}/{}", data.len()),
+            )
+            .header("last-modified", modified)
+            .body(Full::new(data.slice(first..=last)))
+            .unwrap()
+    }
+
+    fn copy(&mut self, source: &str, key: &str) -> Response<Full<Bytes>> {
+        self.count("CopyObject");
+        let source = source.trim_start_matches('/');
+        let Some(source) = source
+            .strip_prefix(self.bucket.as_str())
+            .and_then(|source| source.strip_prefix('/'))
+        else {
+            return error(StatusCode::NOT_FOUND, "NoSuchBucket");
+        };
+        let Some(object) = self.objects.get(source) else {
+            return error(StatusCode::NOT_FOUND, "NoSuchKey");
+        };
+        let data = object.data.clone();
+        self.store(key, data);
+        xml("<CopyObjectResult><ETag>\"copy\"</ETag></CopyObjectResult>")
+    }
+
+    fn upload_part(&mut self, upload_id: &str, number: u32, data: Bytes) -> Response<Full<Bytes>> {
+        self.count("UploadPart");
+        let Some(upload) = self.uploads.get_mut(upload_id) else {
+            return error(StatusCode::NOT_FOUND, "NoSuchUpload");
+        };
+        upload.insert(number, data);
+        Response::builder()
+            .header("etag", etag(number))
+            .body(Full::default())
+            .unwrap()
+    }
+
+    fn complete(&mut self, key: &str, upload_id: &str, body: &[u8]) -> Response<Full<Bytes>> {
+        self.count("CompleteMultipartUpload");
+        let Some(upload) = self.uploads.remove(upload_id) else {
+            return error(StatusCode::NOT_FOUND, "NoSuchUpload");
+        };
+        let body = std::str::from_utf8(body).unwrap();
+        let numbers = elements(body, "PartNumber");
+        let etags = elements(body, "ETag");
+        let mut data = Vec::new();
+        for (index, (number, tag)) in numbers.iter().zip(&etags).enumerate() {
+            let number = number.parse().unwrap();
+            let Some(part) = upload.get(&number) else {
+                return error(StatusCode::BAD_REQUEST, "InvalidPart");
+            };
+            if tag.replace("&quot;", "\"") != etag(number) {
+                return error(StatusCode::BAD_REQUEST, "InvalidPart");
+            }
+            if index + 1 < numbers.len() && part.len() < MIN_PART_SIZE {
+                return error(StatusCode::BAD_REQUEST, "EntityTooSmall");
+            }
+            data.extend_from_slice(part);
+        }
+        self.store(key, data.into());
+        xml(&format!(
+            "<CompleteMultipartUploadResult><Key>{key}</Key></CompleteMultipartUploadResult>"
+        ))
+    }
+
+    fn list(&mut self, query: &HashMap<String, String>) -> Response<Full<Bytes>> {
+        self.count("ListObjectsV2");
+        let prefix = query.get("prefix").map_or("", String::as_str);
+        let delimiter = query.get("delimiter").map(String::as_str);
+        let max_keys = query
+            .get("max-keys")
+            .map_or(1000, |max| max.parse().unwrap());
+        let after = match query.get("continuation-token") {
+            Some(token) => unhex(token),
+            None => query.get("start-after").cloned().unwrap_or_default(),
+        };
+
+        let mut contents = String::new();
+        let mut prefixes = String::new();
+        let mut count = 0;
+        let mut last = None;
+        let mut last_common = false;
+        let mut truncated = false;
+        let keys = self
+            .objects
+            .range::<String, _>((
+                std::ops::Bound::Excluded(&after),
+                std::ops::Bound::Unbounded,
+            ))
+            .filter(|(key, _)| key.starts_with(prefix));
+        for (key, object) in keys {
+            let common = delimiter.and_then(|delimiter| {
+                let pos = key[prefix.len()..].find(delimiter)?;
+                Some(&key[..prefix.len() + pos + delimiter.len()])
+            });
+            if let (Some(common), Some(last)) = (common, &last) {
+                if common == last {
+                    continue;
+                }
+            }
+            if count == max_keys {
+                truncated = true;
+                break;
+            }
+            count += 1;
+            if let Some(common) = common {
+                let _ = write!(
+                    prefixes,
+                    "<CommonPrefixes><Prefix>{}</Prefix></CommonPrefixes>",
+                    escape(common)
+                );
+                last = Some(common.to_string());
+                last_common = true;
+            } else {
+                let _ = write!(
+                    contents,
+                    "<Contents><Key>{}</Key><LastModified>{}</LastModified>\
+                     <Size>{}</Size></Contents>",
+                    escape(key),
+                    object.modified.to_rfc3339_opts(SecondsFormat::Millis, true),
+                    object.data.len()
+                );
+                last = Some(key.clone());
+                last_common = false;
+            }
+        }
+
+        let mut body = format!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<ListBucketResult>\
+             <Name>{}</Name><Prefix>{}</Prefix><KeyCount>{count}</KeyCount>\
+             <IsTruncated>{truncated}</IsTruncated>",
+            self.bucket,
+            escape(prefix)
+        );
+        if truncated {
+            // continue after the keys of the last common prefix
+            let mut token = last.unwrap_or_default();
+            if last_common {
+                token.push(char::MAX);
+            }
+            let _ = write!(
+                body,
+                "<NextContinuationToken>{}</NextContinuationToken>",
+                hex(&token)
+            );
+        }
+        body += &contents;
+        body += &prefixes;
+        body += "</ListBucketResult>";
+        xml(&body)
+    }
+}
+
+fn status(status: StatusCode) -> Response<Full<Bytes>> {
+    Response::builder()
+        .status(status)
+        .body(Full::default())
+        .unwrap()
+}
+
+fn xml(body: &str) -> Response<Full<Bytes>> {
+    Response::builder()
+        .header("content-type", "application/xml")
+        .body(Full::new(Bytes::from(body.to_string())))
+        .unwrap()
+}
+
+fn error(status: StatusCode, code: &str) -> Response<Full<Bytes>> {
+    Response::builder()
+        .status(status)
+        .header("content-type", "application/xml")
+        .body(Full::new(Bytes::from(format!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<Error><Code>{code}</Code></Error>"
+        ))))
+        .unwrap()
+}
+
+fn etag(number: u32) -> String {
+    format!("\"part-{number}\"")
+}
+
+fn http_date(time: DateTime<Utc>) -> String {
+    time.format("%a, %d %b %Y %H:%M:%S GMT").to_string()
+}
+
+/// Returns the text of all elements with a name, without unescaping it
+fn elements<'a>(body: &'a str, name: &str) -> Vec<&'a str> {
+    let (open, close) = (format!("<{name}>"), format!("</{name}>"));
+    body.split(open.as_str())
+        .skip(1)
+        .filter_map(|rest| rest.split_once(close.as_str()).map(|(text, _)| text))
+        .collect()
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+fn decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' && i + 2 < bytes.len() {
+            let hex = std::str::from_utf8(&bytes[i + 1..i + 3]).unwrap();
+            decoded.push(u8::from_str_radix(hex, 16).unwrap());
+            i += 3;
+        } else {
+            decoded.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8(decoded).unwrap()
+}
+
+fn hex(text: &str) -> String {
+    text.bytes().fold(String::new(), |mut hex, byte| {
+        let _ = write!(hex, "{byte:02x}");
+        hex
+    })
+}
+
+fn unhex(hex: &str) -> String {
+    let bytes = (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap())
+        .collect();
+    String::from_utf8(bytes).unwrap()
+}
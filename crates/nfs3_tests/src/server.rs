@@ -1,7 +1,9 @@
 use std::sync::Arc;
 
 use nfs3_client::nfs3_types::nfs3::nfs_fh3;
+use nfs3_server::server::FsInfoConfig;
 use nfs3_server::test_reexports::RPCContext;
+use nfs3_server::tokio::TokioIo;
 use nfs3_server::vfs::NfsFileSystem;
 
 pub struct Server<IO, FS: NfsFileSystem> {
@@ -11,7 +13,7 @@ pub struct Server<IO, FS: NfsFileSystem> {
 
 impl<IO, FS> Server<IO, FS>
 where
-    IO: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin + 'static,
+    IO: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin + Send + 'static,
     FS: NfsFileSystem + 'static,
 {
     pub fn new(io: IO, memfs: FS) -> anyhow::Result<Self> {
@@ -20,11 +22,22 @@ where
         Ok(this)
     }
 
+    #[must_use]
+    pub fn with_subtree_check(mut self) -> Self {
+        self.context.enable_subtree_check();
+        self
+    }
+
+    pub fn with_fsinfo(mut self, config: FsInfoConfig) -> anyhow::Result<Self> {
+        self.context.set_fsinfo(config)?;
+        Ok(self)
+    }
+
     pub fn root_dir(&self) -> nfs_fh3 {
         self.context.root_dir()
     }
 
     pub async fn run(self) -> Result<(), anyhow::Error> {
-        nfs3_server::test_reexports::process_socket(self.io, self.context).await
+        nfs3_server::test_reexports::process_socket(TokioIo::new(self.io), self.context).await
     }
 }
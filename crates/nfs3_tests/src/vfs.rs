@@ -0,0 +1,64 @@
+//! Helpers for tests that call a file system or an adapter directly
+
+use nfs3_client::nfs3_types::nfs3::{filename3, nfsstat3};
+use nfs3_server::vfs::{NextResult, NfsReadFileSystem, ReadDirPlusIterator};
+
+/// Converts a string to a file name
+pub fn name(name: &str) -> filename3<'_> {
+    name.as_bytes().into()
+}
+
+/// Looks up `path` component by component from the root
+///
+/// Unlike `lookup_by_path`, every component goes through `lookup` of `fs`.
+pub async fn walk<T: NfsReadFileSystem>(fs: &T, path: &str) -> Result<T::Handle, nfsstat3> {
+    let mut id = fs.root_dir();
+    for component in path.split('/').filter(|c| !c.is_empty()) {
+        id = fs.lookup(&id, &name(component)).await?;
+    }
+    Ok(id)
+}
+
+/// Returns the first kilobyte of the file at `path`
+pub async fn contents<T: NfsReadFileSystem>(fs: &T, path: &str) -> Vec<u8> {
+    let id = fs.lookup_by_path(path).await.unwrap();
+    fs.read(&id, 0, 1024).await.unwrap().0
+}
+
+/// Returns the names and cookies of the entries of `dir` after `cookie`
+pub async fn list<T: NfsReadFileSystem>(
+    fs: &T,
+    dir: &T::Handle,
+    cookie: u64,
+) -> Vec<(String, u64)> {
+    let mut iter = fs.readdirplus(dir, cookie).await.unwrap();
+    let mut entries = Vec::new();
+    loop {
+        match iter.next().await {
+            NextResult::Ok(entry) => {
+                let name = String::from_utf8(entry.name.as_ref().to_vec()).unwrap();
+                entries.push((name, entry.cookie));
+            }
+            NextResult::Eof => break,
+            NextResult::Err(stat) => panic!("readdirplus failed: {stat:?}"),
+        }
+    }
+    entries
+}
+
+/// Returns the sorted names of the entries of the directory at `path`
+pub async fn names<T: NfsReadFileSystem>(fs: &T, path: &str) -> Vec<String> {
+    let dir = fs.lookup_by_path(path).await.unwrap();
+    let mut names: Vec<_> = list(fs, &dir, 0)
+        .await
+        .into_iter()
+        .map(|(name, _)| name)
+        .collect();
+    names.sort();
+    names
+}
+
+/// Returns `len` bytes that don't repeat within a block, different for every `seed`
+pub fn pattern(len: usize, seed: u8) -> Vec<u8> {
+    (0..len).map(|i| (i % 251) as u8 ^ seed).collect()
+}
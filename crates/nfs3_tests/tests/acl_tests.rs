@@ -0,0 +1,365 @@
+use nfs3_client::nfs3_types::nfs3::{
+    self, CREATE3args, CREATE3res, LOOKUP3args, LOOKUP3res, NFS_PROGRAM, Nfs3Option, Nfs3Result,
+    SETATTR3args, SETATTR3res, createhow3, diropargs3, fattr3, filename3, nfs_fh3, nfsstat3,
+    sattr3, sattrguard3, set_mode3,
+};
+use nfs3_client::nfs3_types::nfsacl::*;
+use nfs3_client::rpc::RpcClient;
+use nfs3_client::tokio::TokioIo;
+use nfs3_server::memfs::{MemFs, MemFsConfig};
+use nfs3_server::vfs::NfsFileSystem;
+use nfs3_server::vfs::adapters::ReadOnlyAdapter;
+use nfs3_tests::Server;
+use tokio::io::{DuplexStream, duplex};
+
+type Client = RpcClient<TokioIo<DuplexStream>>;
+
+fn config() -> MemFsConfig {
+    let mut config = MemFsConfig::default();
+    config.add_file("/a.txt", b"hello world\n");
+    config.add_dir("/dir");
+    config
+}
+
+fn start<FS: NfsFileSystem + 'static>(fs: FS) -> (Client, nfs_fh3) {
+    let (server_io, client_io) = duplex(1024 * 1024);
+    let server = Server::new(server_io, fs).unwrap();
+    let root = server.root_dir();
+    tokio::spawn(server.run());
+    (RpcClient::new(TokioIo::new(client_io)), root)
+}
+
+async fn lookup(client: &mut Client, dir: &nfs_fh3, name: &str) -> nfs_fh3 {
+    let args = LOOKUP3args {
+        what: diropargs3 {
+            dir: dir.clone(),
+            name: filename3::from(name.as_bytes().to_vec()),
+        },
+    };
+    let res: LOOKUP3res = client
+        .call(
+            nfs3::PROGRAM,
+            nfs3::VERSION,
+            NFS_PROGRAM::NFSPROC3_LOOKUP as u32,
+            &args,
+        )
+        .await
+        .unwrap();
+    res.unwrap().object
+}
+
+async fn getacl(client: &mut Client, fh: &nfs_fh3, mask: u32) -> GETACL3res {
+    let args = GETACL3args {
+        fh: fh.clone(),
+        mask,
+    };
+    client
+        .call(
+            PROGRAM,
+            VERSION,
+            NFSACL_PROGRAM::ACLPROC3_GETACL as u32,
+            &args,
+        )
+        .await
+        .unwrap()
+}
+
+async fn setacl(client: &mut Client, fh: &nfs_fh3, acl: secattr) -> SETACL3res {
+    let args = SETACL3args {
+        fh: fh.clone(),
+        acl,
+    };
+    client
+        .call(
+            PROGRAM,
+            VERSION,
+            NFSACL_PROGRAM::ACLPROC3_SETACL as u32,
+            &args,
+        )
+        .await
+        .unwrap()
+}
+
+async fn chmod(client: &mut Client, fh: &nfs_fh3, mode: u32) -> fattr3 {
+    let args = SETATTR3args {
+        object: fh.clone(),
+        new_attributes: sattr3 {
+            mode: set_mode3::Some(mode),
+            ..Default::default()
+        },
+        guard: sattrguard3::None,
+    };
+    let res: SETATTR3res = client
+        .call(
+            nfs3::PROGRAM,
+            nfs3::VERSION,
+            NFS_PROGRAM::NFSPROC3_SETATTR as u32,
+            &args,
+        )
+        .await
+        .unwrap();
+    attr(res.unwrap().obj_wcc.after)
+}
+
+async fn create(client: &mut Client, dir: &nfs_fh3, name: &str, mode: u32) -> nfs_fh3 {
+    let args = CREATE3args {
+        where_: diropargs3 {
+            dir: dir.clone(),
+            name: filename3::from(name.as_bytes().to_vec()),
+        },
+        how: createhow3::UNCHECKED(sattr3 {
+            mode: set_mode3::Some(mode),
+            ..Default::default()
+        }),
+    };
+    let res: CREATE3res = client
+        .call(
+            nfs3::PROGRAM,
+            nfs3::VERSION,
+            NFS_PROGRAM::NFSPROC3_CREATE as u32,
+            &args,
+        )
+        .await
+        .unwrap();
+    res.unwrap().obj.unwrap()
+}
+
+fn perms(acl: &[aclent]) -> Vec<(u32, u32)> {
+    acl.iter().map(|e| (e.type_, e.perm)).collect()
+}
+
+const fn entry(type_: u32, id: u32, perm: u32) -> aclent {
+    aclent { type_, id, perm }
+}
+
+fn attr(attr: Nfs3Option<fattr3>) -> fattr3 {
+    match attr {
+        Nfs3Option::Some(attr) => attr,
+        Nfs3Option::None => panic!("no attributes"),
+    }
+}
+
+fn access_acl(entries: Vec<aclent>) -> secattr {
+    #[allow(clippy::cast_possible_truncation)]
+    let aclcnt = entries.len() as u32;
+    secattr {
+        mask: NFS_ACL | NFS_ACLCNT,
+        aclcnt,
+        aclent: entries,
+        ..Default::default()
+    }
+}
+
+#[tokio::test]
+async fn getacl_from_mode() {
+    let (mut client, root) = start(MemFs::new(config()).unwrap());
+    let fh = lookup(&mut client, &root, "a.txt").await;
+
+    let resok = getacl(&mut client, &fh, NFS_ACL | NFS_ACLCNT | NFS_DFACL)
+        .await
+        .unwrap();
+    let attr = attr(resok.attr);
+    assert_eq!(resok.acl.aclcnt, 3);
+    assert_eq!(
+        resok.acl.aclent,
+        vec![
+            entry(ACL_USER_OBJ, attr.uid, (attr.mode >> 6) & 0o7),
+            entry(ACL_GROUP_OBJ, attr.gid, (attr.mode >> 3) & 0o7),
+            entry(ACL_OTHER, 0, attr.mode & 0o7),
+        ]
+    );
+    assert!(resok.acl.dfaclent.is_empty());
+
+    // only the count was requested
+    let resok = getacl(&mut client, &fh, NFS_ACLCNT).await.unwrap();
+    assert_eq!(resok.acl.aclcnt, 3);
+    assert!(resok.acl.aclent.is_empty());
+}
+
+#[tokio::test]
+async fn setacl_named_entries() {
+    let (mut client, root) = start(MemFs::new(config()).unwrap());
+    let fh = lookup(&mut client, &root, "a.txt").await;
+
+    let entries = vec![
+        entry(ACL_USER_OBJ, 0, 6),
+        entry(ACL_USER, 1000, 6),
+        entry(ACL_GROUP_OBJ, 0, 4),
+        entry(ACL_GROUP, 2000, 4),
+        entry(ACL_MASK, 0, 6),
+        entry(ACL_OTHER, 0, 0),
+    ];
+    let resok = setacl(&mut client, &fh, access_acl(entries.clone()))
+        .await
+        .unwrap();
+    // the group bits of the mode follow the mask entry
+    assert_eq!(attr(resok.attr).mode & 0o777, 0o660);
+
+    let resok = getacl(&mut client, &fh, NFS_ACL | NFS_ACLCNT)
+        .await
+        .unwrap();
+    let attr = attr(resok.attr);
+    let named = |e: &aclent| matches!(e.type_, ACL_USER | ACL_GROUP);
+    assert_eq!(resok.acl.aclcnt, 6);
+    assert_eq!(
+        resok
+            .acl
+            .aclent
+            .iter()
+            .filter(|e| named(e))
+            .collect::<Vec<_>>(),
+        entries.iter().filter(|e| named(e)).collect::<Vec<_>>()
+    );
+    assert!(resok.acl.aclent.contains(&entry(ACL_USER_OBJ, attr.uid, 6)));
+}
+
+#[tokio::test]
+async fn setacl_default_acl() {
+    let (mut client, root) = start(MemFs::new(config()).unwrap());
+    let dir = lookup(&mut client, &root, "dir").await;
+    let file = lookup(&mut client, &root, "a.txt").await;
+
+    let default = vec![
+        entry(ACL_USER_OBJ | NFS_ACL_DEFAULT, 0, 7),
+        entry(ACL_GROUP_OBJ | NFS_ACL_DEFAULT, 0, 5),
+        entry(ACL_OTHER | NFS_ACL_DEFAULT, 0, 0),
+    ];
+    let acl = secattr {
+        mask: NFS_DFACL | NFS_DFACLCNT,
+        dfaclcnt: 3,
+        dfaclent: default.clone(),
+        ..Default::default()
+    };
+    setacl(&mut client, &dir, acl.clone()).await.unwrap();
+
+    let resok = getacl(&mut client, &dir, NFS_DFACL | NFS_DFACLCNT)
+        .await
+        .unwrap();
+    assert_eq!(resok.acl.dfaclcnt, 3);
+    assert_eq!(
+        resok
+            .acl
+            .dfaclent
+            .iter()
+            .map(|e| (e.type_, e.perm))
+            .collect::<Vec<_>>(),
+        default
+            .iter()
+            .map(|e| (e.type_, e.perm))
+            .collect::<Vec<_>>()
+    );
+
+    // files can't have a default ACL
+    let Nfs3Result::Err((stat, _)) = setacl(&mut client, &file, acl).await else {
+        panic!("setacl must fail");
+    };
+    assert_eq!(stat, nfsstat3::NFS3ERR_INVAL);
+}
+
+#[tokio::test]
+async fn setacl_invalid_acl() {
+    let (mut client, root) = start(MemFs::new(config()).unwrap());
+    let fh = lookup(&mut client, &root, "a.txt").await;
+
+    // named entries without a mask entry
+    let acl = access_acl(vec![
+        entry(ACL_USER_OBJ, 0, 6),
+        entry(ACL_USER, 1000, 6),
+        entry(ACL_GROUP_OBJ, 0, 4),
+        entry(ACL_OTHER, 0, 0),
+    ]);
+    let Nfs3Result::Err((stat, _)) = setacl(&mut client, &fh, acl).await else {
+        panic!("setacl must fail");
+    };
+    assert_eq!(stat, nfsstat3::NFS3ERR_INVAL);
+
+    let Nfs3Result::Err((stat, _)) = getacl(&mut client, &fh, 0x100).await else {
+        panic!("getacl must fail");
+    };
+    assert_eq!(stat, nfsstat3::NFS3ERR_INVAL);
+}
+
+#[tokio::test]
+async fn setacl_read_only() {
+    let (mut client, root) = start(ReadOnlyAdapter::new(MemFs::new(config()).unwrap()));
+    let fh = lookup(&mut client, &root, "a.txt").await;
+
+    let acl = access_acl(vec![
+        entry(ACL_USER_OBJ, 0, 6),
+        entry(ACL_GROUP_OBJ, 0, 4),
+        entry(ACL_OTHER, 0, 0),
+    ]);
+    let Nfs3Result::Err((stat, _)) = setacl(&mut client, &fh, acl).await else {
+        panic!("setacl must fail");
+    };
+    assert_eq!(stat, nfsstat3::NFS3ERR_ROFS);
+
+    let resok = getacl(&mut client, &fh, NFS_ACL).await.unwrap();
+    assert!(resok.acl.aclent.iter().all(|e| e.perm & ACL_WRITE == 0));
+}
+
+#[tokio::test]
+async fn chmod_updates_mask() {
+    let (mut client, root) = start(MemFs::new(config()).unwrap());
+    let fh = lookup(&mut client, &root, "a.txt").await;
+
+    let entries = vec![
+        entry(ACL_USER_OBJ, 0, 6),
+        entry(ACL_USER, 1000, 6),
+        entry(ACL_GROUP_OBJ, 0, 6),
+        entry(ACL_MASK, 0, 6),
+        entry(ACL_OTHER, 0, 4),
+    ];
+    setacl(&mut client, &fh, access_acl(entries)).await.unwrap();
+
+    let attr = chmod(&mut client, &fh, 0o740).await;
+    assert_eq!(attr.mode & 0o777, 0o740);
+
+    // the group bits go to the mask, the owning group keeps its entry
+    let resok = getacl(&mut client, &fh, NFS_ACL).await.unwrap();
+    assert_eq!(
+        perms(&resok.acl.aclent),
+        vec![
+            (ACL_USER_OBJ, 7),
+            (ACL_USER, 6),
+            (ACL_GROUP_OBJ, 6),
+            (ACL_MASK, 4),
+            (ACL_OTHER, 0),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn inherited_acl_follows_create_mode() {
+    let (mut client, root) = start(MemFs::new(config()).unwrap());
+    let dir = lookup(&mut client, &root, "dir").await;
+
+    let default = vec![
+        entry(ACL_USER_OBJ | NFS_ACL_DEFAULT, 0, 7),
+        entry(ACL_USER | NFS_ACL_DEFAULT, 1000, 7),
+        entry(ACL_GROUP_OBJ | NFS_ACL_DEFAULT, 0, 5),
+        entry(ACL_MASK | NFS_ACL_DEFAULT, 0, 7),
+        entry(ACL_OTHER | NFS_ACL_DEFAULT, 0, 5),
+    ];
+    let acl = secattr {
+        mask: NFS_DFACL | NFS_DFACLCNT,
+        dfaclcnt: 5,
+        dfaclent: default,
+        ..Default::default()
+    };
+    setacl(&mut client, &dir, acl).await.unwrap();
+
+    let fh = create(&mut client, &dir, "new.txt", 0o640).await;
+    let resok = getacl(&mut client, &fh, NFS_ACL).await.unwrap();
+    assert_eq!(attr(resok.attr).mode & 0o777, 0o640);
+    assert_eq!(
+        perms(&resok.acl.aclent),
+        vec![
+            (ACL_USER_OBJ, 6),
+            (ACL_USER, 7),
+            (ACL_GROUP_OBJ, 5),
+            (ACL_MASK, 4),
+            (ACL_OTHER, 0),
+        ]
+    );
+}
@@ -0,0 +1,332 @@
+use std::io::{Cursor, Write};
+
+use flate2::Compression;
+use flate2::write::GzEncoder;
+use nfs3_client::nfs3_types::nfs3::*;
+use nfs3_server::archivefs::{ArchiveFormat, ArchiveFs};
+use nfs3_server::vfs::BlockingNfsReadFileSystem;
+use nfs3_server::vfs::adapters::{BlockingAdapter, ReadOnlyAdapter};
+use nfs3_tests::{JustClientExt, TestContext};
+use ruzstd::encoding::{CompressionLevel, compress_to_vec};
+use tar::{EntryType, Header};
+use tempfile::NamedTempFile;
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, DateTime, ZipWriter};
+
+const BIG: usize = 1_500_123;
+const INTERVAL: u64 = 128 * 1024;
+
+/// Compressible data that is different at every offset
+fn contents(len: usize) -> Vec<u8> {
+    let mut state = 1u32;
+    (0..len)
+        .map(|i| {
+            state = state.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+            if i % 5 == 0 {
+                (state >> 24) as u8
+            } else {
+                b"archive"[i % 7]
+            }
+        })
+        .collect()
+}
+
+fn tarball() -> Vec<u8> {
+    let mut builder = tar::Builder::new(Vec::new());
+    let mut entry = |path: &str, type_: EntryType, mode: u32, data: &[u8]| {
+        let mut header = Header::new_gnu();
+        header.set_entry_type(type_);
+        header.set_mode(mode);
+        header.set_uid(1000);
+        header.set_gid(100);
+        header.set_mtime(1_700_000_000);
+        header.set_size(data.len() as u64);
+        builder.append_data(&mut header, path, data).unwrap();
+    };
+    entry("./", EntryType::Directory, 0o750, b"");
+    entry("docs/", EntryType::Directory, 0o700, b"");
+    entry("docs/readme.txt", EntryType::Regular, 0o644, b"read me");
+    entry("bin/tool", EntryType::Regular, 0o755, b"#!/bin/sh\n");
+    entry(
+        "deep/nested/big.bin",
+        EntryType::Regular,
+        0o600,
+        &contents(BIG),
+    );
+    entry("docs/readme.txt", EntryType::Regular, 0o640, b"newer");
+
+    let mut link = |path: &str, type_: EntryType, target: &str| {
+        let mut header = Header::new_gnu();
+        header.set_entry_type(type_);
+        header.set_mode(0o777);
+        header.set_size(0);
+        builder.append_link(&mut header, path, target).unwrap();
+    };
+    link("latest", EntryType::Symlink, "deep/nested/big.bin");
+    link("bin/alias", EntryType::Link, "bin/tool");
+    builder.into_inner().unwrap()
+}
+
+fn gzip(chunks: &[&[u8]]) -> Vec<u8> {
+    let mut out = Vec::new();
+    for chunk in chunks {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(chunk).unwrap();
+        out.extend(encoder.finish().unwrap());
+    }
+    out
+}
+
+fn zstd(chunks: &[&[u8]]) -> Vec<u8> {
+    // a skippable frame first
+    let mut out = vec![0x50, 0x2a, 0x4d, 0x18, 4, 0, 0, 0, 1, 2, 3, 4];
+    for chunk in chunks {
+        out.extend(compress_to_vec(*chunk, CompressionLevel::Fastest));
+    }
+    out
+}
+
+fn zipfile() -> Vec<u8> {
+    let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+    let time = DateTime::from_date_and_time(2024, 5, 17, 12, 30, 0).unwrap();
+    let stored = SimpleFileOptions::default()
+        .compression_method(CompressionMethod::Stored)
+        .last_modified_time(time)
+        .unix_permissions(0o640);
+    let deflated = stored.compression_method(CompressionMethod::Deflated);
+
+    writer.add_directory("docs/", stored).unwrap();
+    writer.start_file("docs/readme.txt", stored).unwrap();
+    writer.write_all(b"read me").unwrap();
+    writer.start_file("deep/nested/big.bin", deflated).unwrap();
+    writer.write_all(&contents(BIG)).unwrap();
+    writer
+        .add_symlink("latest", "deep/nested/big.bin", stored)
+        .unwrap();
+    writer.finish().unwrap().into_inner()
+}
+
+fn open(data: &[u8]) -> (NamedTempFile, ArchiveFs) {
+    let mut file = NamedTempFile::new().unwrap();
+    file.write_all(data).unwrap();
+    let fs = ArchiveFs::with_seek_interval(file.path(), INTERVAL).unwrap();
+    (file, fs)
+}
+
+fn names(fs: &ArchiveFs, path: &str) -> Vec<String> {
+    let dir = fs.lookup_by_path(path).unwrap();
+    let mut names = fs
+        .readdirplus(&dir, 0)
+        .unwrap()
+        .map(|entry| String::from_utf8(entry.unwrap().name.as_ref().to_vec()).unwrap())
+        .collect::<Vec<_>>();
+    names.sort();
+    names
+}
+
+fn read(fs: &ArchiveFs, path: &str, offset: u64, count: u32) -> (Vec<u8>, bool) {
+    let id = fs.lookup_by_path(path).unwrap();
+    fs.read(&id, offset, count).unwrap()
+}
+
+/// Reads the big file forwards, backwards and across seek points
+fn check_random_access(fs: &ArchiveFs) {
+    let expected = contents(BIG);
+    for (offset, count) in [
+        (0, 70_000),
+        (1_200_000, 65_536),
+        (600_000, 100),
+        (600_100, 300_000),
+        (100, 1),
+        (BIG as u64 - 10, 1000),
+        (131_068, 10),
+        (BIG as u64 + 5, 10),
+    ] {
+        let (data, eof) = read(fs, "/deep/nested/big.bin", offset, count);
+        let start = (offset as usize).min(BIG);
+        let end = (start + count as usize).min(BIG);
+        assert_eq!(data, expected[start..end], "{offset} {count}");
+        assert_eq!(eof, end == BIG);
+    }
+}
+
+fn check_tarball(fs: &ArchiveFs) {
+    assert_eq!(names(fs, "/"), ["bin", "deep", "docs", "latest"]);
+    assert_eq!(names(fs, "/bin"), ["alias", "tool"]);
+
+    let root = fs.getattr(&fs.root_dir()).unwrap();
+    assert_eq!(root.mode, 0o750);
+    assert_eq!(root.nlink, 5);
+    let docs = fs.getattr(&fs.lookup_by_path("/docs").unwrap()).unwrap();
+    assert_eq!((docs.type_, docs.mode), (ftype3::NF3DIR, 0o700));
+    // directories that are missing from the archive
+    let deep = fs
+        .getattr(&fs.lookup_by_path("/deep/nested").unwrap())
+        .unwrap();
+    assert_eq!((deep.type_, deep.mode), (ftype3::NF3DIR, 0o755));
+
+    // the last entry with the same path wins
+    let readme = fs.lookup_by_path("/docs/readme.txt").unwrap();
+    let attr = fs.getattr(&readme).unwrap();
+    assert_eq!(
+        (attr.mode, attr.size, attr.uid, attr.gid),
+        (0o640, 5, 1000, 100)
+    );
+    assert_eq!(attr.mtime.seconds, 1_700_000_000);
+    assert_eq!(
+        read(fs, "/docs/readme.txt", 0, 100),
+        (b"newer".to_vec(), true)
+    );
+    assert_eq!(
+        fs.lookup_by_path("/docs/readme.txt/..").err(),
+        Some(nfsstat3::NFS3ERR_NOTDIR)
+    );
+
+    // hard links share the file
+    let tool = fs.lookup_by_path("/bin/tool").unwrap();
+    let alias = fs.lookup_by_path("/bin/alias").unwrap();
+    assert_eq!(tool, alias);
+    let attr = fs.getattr(&tool).unwrap();
+    assert_eq!((attr.mode, attr.nlink), (0o755, 2));
+
+    let latest = fs.lookup_by_path("/latest").unwrap();
+    assert_eq!(fs.getattr(&latest).unwrap().type_, ftype3::NF3LNK);
+    assert_eq!(
+        fs.readlink(&latest).unwrap().as_ref(),
+        b"deep/nested/big.bin"
+    );
+    assert_eq!(fs.read(&latest, 0, 10).err(), Some(nfsstat3::NFS3ERR_INVAL));
+
+    let big = fs.lookup_by_path("/deep/nested/big.bin").unwrap();
+    let attr = fs.getattr(&big).unwrap();
+    assert_eq!((attr.mode, attr.size), (0o600, BIG as u64));
+    check_random_access(fs);
+}
+
+#[test]
+fn plain_tar() {
+    let (_file, fs) = open(&tarball());
+    assert_eq!(fs.format(), ArchiveFormat::Tar);
+    check_tarball(&fs);
+}
+
+#[test]
+fn gzip_tar() {
+    let tar = tarball();
+    let (_file, fs) = open(&gzip(&[&tar]));
+    assert_eq!(fs.format(), ArchiveFormat::TarGzip);
+    check_tarball(&fs);
+
+    // several members, split in the middle of an entry
+    let (_file, fs) = open(&gzip(&[&tar[..700_003], &tar[700_003..]]));
+    check_tarball(&fs);
+}
+
+#[test]
+fn zstd_tar() {
+    let tar = tarball();
+    let (_file, fs) = open(&zstd(&[&tar]));
+    assert_eq!(fs.format(), ArchiveFormat::TarZstd);
+    check_tarball(&fs);
+
+    let chunks = tar.chunks(200_000).collect::<Vec<_>>();
+    let (_file, fs) = open(&zstd(&chunks));
+    check_tarball(&fs);
+}
+
+#[test]
+fn zip_archive() {
+    let (_file, fs) = open(&zipfile());
+    assert_eq!(fs.format(), ArchiveFormat::Zip);
+    assert_eq!(names(&fs, "/"), ["deep", "docs", "latest"]);
+
+    let readme = fs.lookup_by_path("/docs/readme.txt").unwrap();
+    let attr = fs.getattr(&readme).unwrap();
+    assert_eq!((attr.mode, attr.size), (0o640, 7));
+    assert_eq!(attr.mtime.seconds, 1_715_949_000);
+    assert_eq!(
+        read(&fs, "/docs/readme.txt", 2, 100),
+        (b"ad me".to_vec(), true)
+    );
+
+    let latest = fs.lookup_by_path("/latest").unwrap();
+    assert_eq!(
+        fs.readlink(&latest).unwrap().as_ref(),
+        b"deep/nested/big.bin"
+    );
+    check_random_access(&fs);
+}
+
+#[test]
+fn truncated_archive() {
+    let data = gzip(&[&tarball()]);
+    let mut file = NamedTempFile::new().unwrap();
+    file.write_all(&data[..data.len() / 2]).unwrap();
+    assert!(ArchiveFs::open(file.path()).is_err());
+}
+
+#[test]
+fn bad_hard_links_are_skipped() {
+    let mut builder = tar::Builder::new(Vec::new());
+    let mut header = Header::new_gnu();
+    header.set_mode(0o755);
+    header.set_entry_type(EntryType::Directory);
+    header.set_size(0);
+    builder.append_data(&mut header, "dir/", &b""[..]).unwrap();
+    header.set_entry_type(EntryType::Symlink);
+    builder.append_link(&mut header, "symlink", "dir").unwrap();
+    for (path, target) in [
+        ("empty", ""),
+        ("root", "./"),
+        ("missing", "nope"),
+        ("to_dir", "dir"),
+        ("to_symlink", "symlink"),
+    ] {
+        let mut header = Header::new_gnu();
+        header.set_entry_type(EntryType::Link);
+        header.set_size(0);
+        // `append_link` rejects an empty target
+        header.as_old_mut().linkname[..target.len()].copy_from_slice(target.as_bytes());
+        builder.append_data(&mut header, path, &b""[..]).unwrap();
+    }
+    let (_file, fs) = open(&builder.into_inner().unwrap());
+
+    assert_eq!(names(&fs, "/"), ["dir", "symlink"]);
+    let dir = fs.lookup_by_path("/dir").unwrap();
+    assert_eq!(fs.getattr(&dir).unwrap().nlink, 2);
+    assert_eq!(fs.getattr(&fs.root_dir()).unwrap().nlink, 3);
+}
+
+#[tokio::test]
+async fn serve_archive() {
+    let (_file, fs) = open(&gzip(&[&tarball()]));
+    let fs = ReadOnlyAdapter::new(BlockingAdapter::with_threads(fs, 2));
+    let mut client = TestContext::serve(fs);
+    let root = client.root_dir().clone();
+
+    let docs = client.just_lookup(&root, "docs").await.unwrap();
+    let readme = client.just_lookup(&docs, "readme.txt").await.unwrap();
+    let res = client
+        .read(&READ3args {
+            file: readme.clone(),
+            offset: 0,
+            count: 100,
+        })
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(res.data.as_ref(), b"newer");
+    assert!(res.eof);
+
+    let res = client
+        .write(&WRITE3args {
+            file: readme,
+            offset: 0,
+            count: 3,
+            stable: stable_how::FILE_SYNC,
+            data: b"abc".as_slice().into(),
+        })
+        .await
+        .unwrap();
+    assert!(matches!(res, Nfs3Result::Err((nfsstat3::NFS3ERR_ROFS, _))));
+}
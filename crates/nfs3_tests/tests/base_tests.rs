@@ -1,6 +1,9 @@
 use nfs3_client::nfs3_types::nfs3::*;
 use nfs3_client::nfs3_types::xdr_codec::Opaque;
-use nfs3_tests::{JustClientExt, TestContext};
+use nfs3_client::tokio::TokioIo;
+use nfs3_server::memfs::{MemFs, MemFsConfig};
+use nfs3_server::server::FsInfoConfig;
+use nfs3_tests::{JustClientExt, Server, TestContext};
 
 #[tokio::test]
 async fn lookup_root() -> Result<(), anyhow::Error> {
@@ -223,6 +226,72 @@ async fn test_write() -> Result<(), anyhow::Error> {
     client.shutdown().await
 }
 
+#[tokio::test]
+async fn test_read_unaligned() -> Result<(), anyhow::Error> {
+    let mut client = TestContext::setup();
+    let root = client.root_dir().clone();
+
+    let create = client
+        .create(&CREATE3args {
+            where_: diropargs3 {
+                dir: root.clone(),
+                name: b"unaligned.txt".as_slice().into(),
+            },
+            how: createhow3::UNCHECKED(sattr3::default()),
+        })
+        .await?
+        .unwrap();
+    let file_handle = create.obj.unwrap();
+    client
+        .write(&WRITE3args {
+            file: file_handle.clone(),
+            offset: 0,
+            count: 7,
+            stable: stable_how::FILE_SYNC,
+            data: Opaque::borrowed(b"abcdefg"),
+        })
+        .await?
+        .unwrap();
+
+    // the data is padded to a multiple of 4 bytes in the reply
+    let read = client
+        .read(&READ3args {
+            file: file_handle.clone(),
+            offset: 2,
+            count: 100,
+        })
+        .await?
+        .unwrap();
+    assert_eq!(read.count, 5);
+    assert_eq!(read.data.as_ref(), b"cdefg");
+    assert!(read.eof);
+    assert_eq!(read.file_attributes.unwrap().size, 7);
+
+    let read = client
+        .read(&READ3args {
+            file: file_handle.clone(),
+            offset: 0,
+            count: 3,
+        })
+        .await?
+        .unwrap();
+    assert_eq!(read.data.as_ref(), b"abc");
+    assert!(!read.eof);
+
+    let read = client
+        .read(&READ3args {
+            file: file_handle,
+            offset: 100,
+            count: 10,
+        })
+        .await?
+        .unwrap();
+    assert!(read.data.is_empty());
+    assert!(read.eof);
+
+    client.shutdown().await
+}
+
 #[tokio::test]
 async fn test_create_unchecked() -> Result<(), anyhow::Error> {
     let mut client = TestContext::setup();
@@ -653,6 +722,102 @@ async fn test_readdirplus_maxcount_too_small() -> Result<(), anyhow::Error> {
     client.shutdown().await
 }
 
+#[tokio::test]
+async fn test_readdir_stale_cookieverf() -> Result<(), anyhow::Error> {
+    let mut client = TestContext::setup();
+    let root = client.root_dir().clone();
+
+    let first = client
+        .readdir(&READDIR3args {
+            dir: root.clone(),
+            cookie: 0,
+            cookieverf: cookieverf3::default(),
+            count: 1024 * 1024,
+        })
+        .await?
+        .unwrap();
+    let cookie = first.reply.entries.0.last().unwrap().cookie;
+
+    // the same verifier is accepted while the directory is unchanged
+    let resumed = client
+        .readdir(&READDIR3args {
+            dir: root.clone(),
+            cookie,
+            cookieverf: first.cookieverf,
+            count: 1024 * 1024,
+        })
+        .await?
+        .unwrap();
+    assert_eq!(resumed.cookieverf, first.cookieverf);
+
+    client
+        .just_create(&root, "new_file.txt", b"")
+        .await
+        .unwrap();
+
+    let stale = client
+        .readdir(&READDIR3args {
+            dir: root.clone(),
+            cookie,
+            cookieverf: first.cookieverf,
+            count: 1024 * 1024,
+        })
+        .await?;
+    assert!(matches!(
+        stale,
+        Nfs3Result::Err((nfsstat3::NFS3ERR_BAD_COOKIE, _))
+    ));
+
+    let restarted = client
+        .readdir(&READDIR3args {
+            dir: root.clone(),
+            cookie: 0,
+            cookieverf: first.cookieverf,
+            count: 1024 * 1024,
+        })
+        .await?
+        .unwrap();
+    assert_ne!(restarted.cookieverf, first.cookieverf);
+
+    client.shutdown().await
+}
+
+#[tokio::test]
+async fn test_readdirplus_stale_cookieverf() -> Result<(), anyhow::Error> {
+    let mut client = TestContext::setup();
+    let root = client.root_dir().clone();
+
+    let first = client
+        .readdirplus(&READDIRPLUS3args {
+            dir: root.clone(),
+            cookie: 0,
+            cookieverf: cookieverf3::default(),
+            dircount: 1024 * 1024,
+            maxcount: 1024 * 1024,
+        })
+        .await?
+        .unwrap();
+    let cookie = first.reply.entries.0.first().unwrap().cookie;
+
+    client.just_mkdir(&root, "new_dir").await.unwrap();
+
+    let stale = client
+        .readdirplus(&READDIRPLUS3args {
+            dir: root.clone(),
+            cookie,
+            cookieverf: first.cookieverf,
+            dircount: 1024 * 1024,
+            maxcount: 1024 * 1024,
+        })
+        .await?;
+    assert!(matches!(
+        stale,
+        Nfs3Result::Err((nfsstat3::NFS3ERR_BAD_COOKIE, _))
+    ));
+
+    client.shutdown().await
+}
+
 #[tokio::test]
 async fn test_fsstat() -> Result<(), anyhow::Error> {
     let mut client = TestContext::setup();
@@ -703,24 +868,285 @@ async fn test_pathconf() -> Result<(), anyhow::Error> {
 
 #[tokio::test]
 async fn test_commit() -> Result<(), anyhow::Error> {
-    use nfs3_client::error::*;
-
     let mut client = TestContext::setup();
     let root = client.root_dir().clone();
+    let file = client.just_lookup(&root, "a.txt").await.unwrap();
+
+    let write = client
+        .write(&WRITE3args {
+            file: file.clone(),
+            offset: 0,
+            count: 5,
+            stable: stable_how::UNSTABLE,
+            data: Opaque::borrowed(b"HELLO"),
+        })
+        .await?
+        .unwrap();
+    // memfs doesn't buffer writes, so they are always stable
+    assert!(matches!(write.committed, stable_how::FILE_SYNC));
 
     let commit = client
         .commit(&COMMIT3args {
-            file: root.clone(),
+            file,
             offset: 0,
             count: 1024,
         })
+        .await?
+        .unwrap();
+    assert_eq!(commit.verf, write.verf);
+    assert!(matches!(commit.file_wcc.after, Nfs3Option::Some(ref attr) if attr.size == 12));
+
+    client.shutdown().await
+}
+
+#[tokio::test]
+async fn test_subtree_check_parent_of_root() -> Result<(), anyhow::Error> {
+    let mut client = TestContext::setup_subtree_check();
+    let root = client.root_dir().clone();
+
+    let result = client.just_lookup(&root, "..").await;
+    assert_eq!(result, Err(nfsstat3::NFS3ERR_ACCES));
+
+    // "." keeps the handle of the mount root
+    let dot = client.just_lookup(&root, ".").await.unwrap();
+    assert_eq!(dot, root);
+    let result = client.just_lookup(&dot, "..").await;
+    assert_eq!(result, Err(nfsstat3::NFS3ERR_ACCES));
+
+    // `..` is still allowed below the mount root
+    let dir = client.just_lookup(&root, "another_dir").await.unwrap();
+    let parent = client.just_lookup(&dir, "..").await.unwrap();
+    assert_eq!(parent, root);
+
+    client.shutdown().await
+}
+
+#[tokio::test]
+async fn test_subtree_check_forged_handle() -> Result<(), anyhow::Error> {
+    let mut client = TestContext::setup_subtree_check();
+    let root = client.root_dir().clone();
+
+    let mut forged = root.data.to_vec();
+    let last = forged.len() - 9;
+    forged[last] ^= 1;
+    let result = client
+        .just_getattr(&nfs_fh3 {
+            data: Opaque::owned(forged),
+        })
         .await;
+    assert!(matches!(result, Err(nfsstat3::NFS3ERR_BADHANDLE)));
 
-    if let Err(Error::Rpc(RpcError::ProcUnavail)) = commit {
-        tracing::info!("Server does not support COMMIT yet");
-    } else {
-        panic!("Expected ProcUnavail error");
-    }
+    client.shutdown().await
+}
+
+#[tokio::test]
+async fn test_read_above_rtmax() -> Result<(), anyhow::Error> {
+    let mut client = TestContext::setup();
+    let root = client.root_dir().clone();
+
+    let fsinfo = client
+        .fsinfo(&FSINFO3args {
+            fsroot: root.clone(),
+        })
+        .await?
+        .unwrap();
+    let file = client.just_lookup(&root, "a.txt").await.unwrap();
+
+    let read = client
+        .read(&READ3args {
+            file,
+            offset: 0,
+            count: fsinfo.rtmax + 1,
+        })
+        .await?;
+    assert!(matches!(
+        read,
+        Nfs3Result::Err((nfsstat3::NFS3ERR_INVAL, _))
+    ));
 
     client.shutdown().await
 }
+
+#[tokio::test]
+async fn test_write_above_wtmax() -> Result<(), anyhow::Error> {
+    let mut client = TestContext::setup();
+    let root = client.root_dir().clone();
+
+    let fsinfo = client
+        .fsinfo(&FSINFO3args {
+            fsroot: root.clone(),
+        })
+        .await?
+        .unwrap();
+    let file = client.just_lookup(&root, "a.txt").await.unwrap();
+
+    let data = vec![0u8; fsinfo.wtmax as usize + 1];
+    let write = client
+        .write(&WRITE3args {
+            file,
+            offset: 0,
+            count: data.len() as u32,
+            stable: stable_how::FILE_SYNC,
+            data: Opaque::borrowed(&data),
+        })
+        .await?;
+    assert!(matches!(
+        write,
+        Nfs3Result::Err((nfsstat3::NFS3ERR_INVAL, _))
+    ));
+
+    client.shutdown().await
+}
+
+#[tokio::test]
+async fn test_names_with_slashes() -> Result<(), anyhow::Error> {
+    let mut client = TestContext::setup();
+    let root = client.root_dir().clone();
+    let dir = client.just_lookup(&root, "another_dir").await.unwrap();
+
+    let result = client.just_lookup(&dir, "../a.txt").await;
+    assert_eq!(result, Err(nfsstat3::NFS3ERR_INVAL));
+    let result = client.just_lookup(&dir, "").await;
+    assert_eq!(result, Err(nfsstat3::NFS3ERR_INVAL));
+    let result = client.just_create(&dir, "../x", b"").await;
+    assert_eq!(result, Err(nfsstat3::NFS3ERR_INVAL));
+    let result = client.just_mkdir(&dir, "../x").await;
+    assert_eq!(result, Err(nfsstat3::NFS3ERR_INVAL));
+
+    let symlink = client
+        .symlink(&SYMLINK3args {
+            where_: diropargs3 {
+                dir: dir.clone(),
+                name: b"../x".as_slice().into(),
+            },
+            symlink: symlinkdata3 {
+                symlink_attributes: sattr3::default(),
+                symlink_data: b"a.txt".as_slice().into(),
+            },
+        })
+        .await?;
+    assert!(matches!(
+        symlink,
+        Nfs3Result::Err((nfsstat3::NFS3ERR_INVAL, _))
+    ));
+
+    let rename = client
+        .rename(&RENAME3args {
+            from: diropargs3 {
+                dir: dir.clone(),
+                name: b"thisworks.txt".as_slice().into(),
+            },
+            to: diropargs3 {
+                dir: dir.clone(),
+                name: b"../x".as_slice().into(),
+            },
+        })
+        .await?;
+    assert!(matches!(
+        rename,
+        Nfs3Result::Err((nfsstat3::NFS3ERR_INVAL, _))
+    ));
+
+    let remove = client
+        .remove(&REMOVE3args {
+            object: diropargs3 {
+                dir: dir.clone(),
+                name: b"../a.txt".as_slice().into(),
+            },
+        })
+        .await?;
+    assert!(matches!(
+        remove,
+        Nfs3Result::Err((nfsstat3::NFS3ERR_INVAL, _))
+    ));
+
+    // nothing was touched outside of the directory
+    client.just_lookup(&root, "a.txt").await.unwrap();
+    assert_eq!(
+        client.just_lookup(&root, "x").await,
+        Err(nfsstat3::NFS3ERR_NOENT)
+    );
+    client.just_lookup(&dir, "thisworks.txt").await.unwrap();
+
+    client.shutdown().await
+}
+
+#[tokio::test]
+async fn test_fsinfo_overrides() -> Result<(), anyhow::Error> {
+    let mut config = MemFsConfig::default();
+    config.add_file("/a.txt", b"hello world\n");
+    let (server_io, client_io) = tokio::io::duplex(1024 * 1024);
+    let server =
+        Server::new(server_io, MemFs::new(config).unwrap())?.with_fsinfo(FsInfoConfig {
+            rtmax: Some(4),
+            wtmax: Some(8),
+            dtpref: Some(512),
+            maxfilesize: Some(4096),
+            ..Default::default()
+        })?;
+    let root = server.root_dir();
+    let server_handle = tokio::spawn(server.run());
+    let mut client = nfs3_client::Nfs3Client::new(TokioIo::new(client_io));
+
+    let fsinfo = client
+        .fsinfo(&FSINFO3args {
+            fsroot: root.clone(),
+        })
+        .await?
+        .unwrap();
+    assert_eq!(fsinfo.rtmax, 4);
+    assert_eq!(fsinfo.rtpref, 4);
+    assert_eq!(fsinfo.wtmax, 8);
+    assert_eq!(fsinfo.wtpref, 8);
+    assert_eq!(fsinfo.dtpref, 512);
+    assert_eq!(fsinfo.maxfilesize, 4096);
+
+    let lookup = client
+        .lookup(&LOOKUP3args {
+            what: diropargs3 {
+                dir: root.clone(),
+                name: b"a.txt".as_slice().into(),
+            },
+        })
+        .await?
+        .unwrap();
+    let file = lookup.object;
+
+    let read = client
+        .read(&READ3args {
+            file: file.clone(),
+            offset: 0,
+            count: 4,
+        })
+        .await?
+        .unwrap();
+    assert_eq!(read.data.as_ref(), b"hell");
+    let read = client
+        .read(&READ3args {
+            file: file.clone(),
+            offset: 0,
+            count: 5,
+        })
+        .await?;
+    assert!(matches!(
+        read,
+        Nfs3Result::Err((nfsstat3::NFS3ERR_INVAL, _))
+    ));
+
+    let write = client
+        .write(&WRITE3args {
+            file,
+            offset: 0,
+            count: 9,
+            stable: stable_how::FILE_SYNC,
+            data: Opaque::borrowed(b"123456789"),
+        })
+        .await?;
+    assert!(matches!(
+        write,
+        Nfs3Result::Err((nfsstat3::NFS3ERR_INVAL, _))
+    ));
+
+    drop(client);
+    server_handle.await?
+}
@@ -0,0 +1,316 @@
+use std::sync::Mutex;
+
+use nfs3_client::Nfs3Client;
+use nfs3_client::nfs3_types::nfs3::*;
+use nfs3_client::nfs3_types::xdr_codec::Opaque;
+use nfs3_client::tokio::TokioIo;
+use nfs3_server::vfs::adapters::BlockingAdapter;
+use nfs3_server::vfs::{
+    BlockingNfsFileSystem, BlockingNfsReadFileSystem, DirEntryPlus, FileHandleU64,
+};
+use nfs3_tests::Server;
+use tokio::io::{DuplexStream, duplex};
+
+const FILES: u64 = 150;
+const ROOT: u64 = 1;
+
+/// A flat directory of files kept behind a mutex, like a typical synchronous backend
+struct BlockingFs {
+    files: Mutex<Vec<Vec<u8>>>,
+}
+
+impl BlockingFs {
+    fn new() -> Self {
+        let files = (0..FILES).map(|i| format!("file {i}\n").into_bytes());
+        Self {
+            files: Mutex::new(files.collect()),
+        }
+    }
+
+    fn index(id: &FileHandleU64) -> Result<usize, nfsstat3> {
+        let id = u64::from(*id);
+        if (ROOT + 1..=ROOT + FILES).contains(&id) {
+            Ok(usize::try_from(id - ROOT - 1).unwrap())
+        } else {
+            Err(nfsstat3::NFS3ERR_STALE)
+        }
+    }
+
+    fn attr(fileid: u64, type_: ftype3, size: u64) -> fattr3 {
+        fattr3 {
+            type_,
+            mode: 0o755,
+            nlink: 1,
+            uid: 0,
+            gid: 0,
+            size,
+            used: size,
+            rdev: specdata3::default(),
+            fsid: 0,
+            fileid,
+            atime: nfstime3::default(),
+            mtime: nfstime3::default(),
+            ctime: nfstime3::default(),
+        }
+    }
+}
+
+impl BlockingNfsReadFileSystem for BlockingFs {
+    type Handle = FileHandleU64;
+
+    fn root_dir(&self) -> Self::Handle {
+        ROOT.into()
+    }
+
+    fn lookup(
+        &self,
+        dirid: &Self::Handle,
+        filename: &filename3<'_>,
+    ) -> Result<Self::Handle, nfsstat3> {
+        if u64::from(*dirid) != ROOT {
+            return Err(nfsstat3::NFS3ERR_NOTDIR);
+        }
+        if filename.as_ref() == b"panic" {
+            panic!("the backend panicked");
+        }
+        let name = std::str::from_utf8(filename.as_ref()).map_err(|_| nfsstat3::NFS3ERR_NOENT)?;
+        let index = name
+            .strip_prefix("file")
+            .and_then(|i| i.parse::<u64>().ok())
+            .filter(|i| *i < FILES)
+            .ok_or(nfsstat3::NFS3ERR_NOENT)?;
+        Ok((ROOT + 1 + index).into())
+    }
+
+    fn getattr(&self, id: &Self::Handle) -> Result<fattr3, nfsstat3> {
+        let fileid = u64::from(*id);
+        if fileid == ROOT {
+            return Ok(Self::attr(fileid, ftype3::NF3DIR, 0));
+        }
+        let index = Self::index(id)?;
+        let size = self.files.lock().unwrap()[index].len() as u64;
+        Ok(Self::attr(fileid, ftype3::NF3REG, size))
+    }
+
+    fn read(
+        &self,
+        id: &Self::Handle,
+        offset: u64,
+        count: u32,
+    ) -> Result<(Vec<u8>, bool), nfsstat3> {
+        let index = Self::index(id)?;
+        let files = self.files.lock().unwrap();
+        let data = &files[index];
+        let start = usize::try_from(offset).unwrap().min(data.len());
+        let end = (start + count as usize).min(data.len());
+        Ok((data[start..end].to_vec(), end == data.len()))
+    }
+
+    fn readdirplus(
+        &self,
+        dirid: &Self::Handle,
+        cookie: u64,
+    ) -> Result<
+        impl Iterator<Item = Result<DirEntryPlus<Self::Handle>, nfsstat3>> + Send + use<>,
+        nfsstat3,
+    > {
+        if u64::from(*dirid) != ROOT {
+            return Err(nfsstat3::NFS3ERR_NOTDIR);
+        }
+        Ok((cookie..FILES).map(|index| {
+            let fileid = ROOT + 1 + index;
+            Ok(DirEntryPlus {
+                fileid,
+                name: format!("file{index}").into_bytes().into(),
+                cookie: index + 1,
+                name_attributes: None,
+                name_handle: Some(fileid.into()),
+            })
+        }))
+    }
+
+    fn readlink(&self, _id: &Self::Handle) -> Result<nfspath3<'static>, nfsstat3> {
+        Err(nfsstat3::NFS3ERR_INVAL)
+    }
+}
+
+impl BlockingNfsFileSystem for BlockingFs {
+    fn setattr(&self, id: &Self::Handle, _setattr: sattr3) -> Result<fattr3, nfsstat3> {
+        self.getattr(id)
+    }
+
+    fn write(&self, id: &Self::Handle, offset: u64, data: &[u8]) -> Result<fattr3, nfsstat3> {
+        let index = Self::index(id)?;
+        {
+            let mut files = self.files.lock().unwrap();
+            let file = &mut files[index];
+            let offset = usize::try_from(offset).unwrap();
+            if file.len() < offset + data.len() {
+                file.resize(offset + data.len(), 0);
+            }
+            file[offset..offset + data.len()].copy_from_slice(data);
+        }
+        self.getattr(id)
+    }
+
+    fn create(
+        &self,
+        _dirid: &Self::Handle,
+        _filename: &filename3<'_>,
+        _attr: sattr3,
+    ) -> Result<(Self::Handle, fattr3), nfsstat3> {
+        Err(nfsstat3::NFS3ERR_NOTSUPP)
+    }
+
+    fn create_exclusive(
+        &self,
+        _dirid: &Self::Handle,
+        _filename: &filename3<'_>,
+        _createverf: createverf3,
+    ) -> Result<Self::Handle, nfsstat3> {
+        Err(nfsstat3::NFS3ERR_NOTSUPP)
+    }
+
+    fn mkdir(
+        &self,
+        _dirid: &Self::Handle,
+        _dirname: &filename3<'_>,
+    ) -> Result<(Self::Handle, fattr3), nfsstat3> {
+        Err(nfsstat3::NFS3ERR_NOTSUPP)
+    }
+
+    fn remove(&self, _dirid: &Self::Handle, _filename: &filename3<'_>) -> Result<(), nfsstat3> {
+        Err(nfsstat3::NFS3ERR_NOTSUPP)
+    }
+
+    fn rename(
+        &self,
+        _from_dirid: &Self::Handle,
+        _from_filename: &filename3<'_>,
+        _to_dirid: &Self::Handle,
+        _to_filename: &filename3<'_>,
+    ) -> Result<(), nfsstat3> {
+        Err(nfsstat3::NFS3ERR_NOTSUPP)
+    }
+
+    fn symlink(
+        &self,
+        _dirid: &Self::Handle,
+        _linkname: &filename3<'_>,
+        _symlink: &nfspath3<'_>,
+        _attr: &sattr3,
+    ) -> Result<(Self::Handle, fattr3), nfsstat3> {
+        Err(nfsstat3::NFS3ERR_NOTSUPP)
+    }
+}
+
+fn start() -> (Nfs3Client<TokioIo<DuplexStream>>, nfs_fh3) {
+    let (server_io, client_io) = duplex(1024 * 1024);
+    let server = Server::new(
+        server_io,
+        BlockingAdapter::with_threads(BlockingFs::new(), 4),
+    )
+    .unwrap();
+    let root = server.root_dir();
+    tokio::spawn(server.run());
+    (Nfs3Client::new(TokioIo::new(client_io)), root)
+}
+
+async fn lookup(
+    client: &mut Nfs3Client<TokioIo<DuplexStream>>,
+    dir: &nfs_fh3,
+    name: &str,
+) -> LOOKUP3res {
+    client
+        .lookup(&LOOKUP3args {
+            what: diropargs3 {
+                dir: dir.clone(),
+                name: name.as_bytes().into(),
+            },
+        })
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn readdir_lists_all_batches() {
+    let (mut client, root) = start();
+
+    let mut names = Vec::new();
+    let mut cookie = 0;
+    let mut cookieverf = cookieverf3::default();
+    loop {
+        let resok = client
+            .readdir(&READDIR3args {
+                dir: root.clone(),
+                cookie,
+                cookieverf,
+                count: 1024,
+            })
+            .await
+            .unwrap()
+            .unwrap();
+        cookieverf = resok.cookieverf;
+        for entry in resok.reply.entries.0 {
+            names.push(String::from_utf8(entry.name.0.to_vec()).unwrap());
+            cookie = entry.cookie;
+        }
+        if resok.reply.eof {
+            break;
+        }
+    }
+
+    let expected = (0..FILES).map(|i| format!("file{i}")).collect::<Vec<_>>();
+    assert_eq!(names, expected);
+}
+
+#[tokio::test]
+async fn read_and_write() {
+    let (mut client, root) = start();
+
+    let file = lookup(&mut client, &root, "file7").await.unwrap().object;
+    let write = client
+        .write(&WRITE3args {
+            file: file.clone(),
+            offset: 5,
+            count: 5,
+            stable: stable_how::FILE_SYNC,
+            data: Opaque::borrowed(b"seven"),
+        })
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(write.count, 5);
+
+    let read = client
+        .read(&READ3args {
+            file,
+            offset: 0,
+            count: 100,
+        })
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(read.data.as_ref(), b"file seven");
+    assert!(read.eof);
+
+    let res = lookup(&mut client, &root, "file1000").await;
+    assert!(matches!(res, Nfs3Result::Err((nfsstat3::NFS3ERR_NOENT, _))));
+}
+
+#[tokio::test]
+async fn panic_is_reported_as_server_fault() {
+    let (mut client, root) = start();
+
+    let res = lookup(&mut client, &root, "panic").await;
+    assert!(matches!(
+        res,
+        Nfs3Result::Err((nfsstat3::NFS3ERR_SERVERFAULT, _))
+    ));
+
+    // the pool keeps serving after a panic
+    for _ in 0..8 {
+        let res = lookup(&mut client, &root, "file1").await;
+        assert!(matches!(res, Nfs3Result::Ok(_)));
+    }
+}
@@ -0,0 +1,163 @@
+use std::time::Duration;
+
+use nfs3_client::nfs3_types::nfs3::{nfsstat3, sattr3, set_size3};
+use nfs3_server::memfs::{MemFs, MemFsConfig};
+use nfs3_server::vfs::adapters::{CacheCounters, CachingAdapter};
+use nfs3_server::vfs::{FileHandleU64, NfsFileSystem, NfsReadFileSystem};
+use nfs3_tests::vfs::name;
+
+const TTL: Duration = Duration::from_secs(60);
+
+fn cached(ttl: Duration, capacity: usize) -> CachingAdapter<MemFs> {
+    let mut config = MemFsConfig::default();
+    config.add_file("/a.txt", b"hello world\n");
+    config.add_file("/b.txt", b"hello\n");
+    config.add_dir("/dir");
+    CachingAdapter::new(MemFs::new(config).unwrap(), ttl, capacity)
+}
+
+async fn lookup(fs: &CachingAdapter<MemFs>, path: &str) -> Result<FileHandleU64, nfsstat3> {
+    fs.lookup(&fs.root_dir(), &name(path)).await
+}
+
+#[tokio::test]
+async fn getattr_is_invalidated_by_write() {
+    let fs = cached(TTL, 100);
+    let file = lookup(&fs, "a.txt").await.unwrap();
+
+    assert_eq!(fs.getattr(&file).await.unwrap().size, 12);
+    assert_eq!(fs.getattr(&file).await.unwrap().size, 12);
+    assert_eq!(
+        fs.stats().getattr,
+        CacheCounters {
+            hits: 1,
+            misses: 1,
+            evictions: 0,
+            entries: 1,
+        }
+    );
+
+    fs.write(&file, 12, b"more\n").await.unwrap();
+    assert_eq!(fs.getattr(&file).await.unwrap().size, 17);
+    assert_eq!(fs.stats().getattr.misses, 2);
+
+    fs.setattr(
+        &file,
+        sattr3 {
+            size: set_size3::Some(3),
+            ..Default::default()
+        },
+    )
+    .await
+    .unwrap();
+    assert_eq!(fs.getattr(&file).await.unwrap().size, 3);
+}
+
+#[tokio::test]
+async fn lookup_is_invalidated_by_namespace_changes() {
+    let fs = cached(TTL, 100);
+    let root = fs.root_dir();
+    let dir = lookup(&fs, "dir").await.unwrap();
+
+    let a = lookup(&fs, "a.txt").await.unwrap();
+    assert_eq!(lookup(&fs, "a.txt").await, Ok(a));
+    assert_eq!(fs.stats().lookup.hits, 1);
+
+    fs.rename(&root, &name("a.txt"), &dir, &name("c.txt"))
+        .await
+        .unwrap();
+    assert_eq!(lookup(&fs, "a.txt").await, Err(nfsstat3::NFS3ERR_NOENT));
+    assert_eq!(fs.lookup(&dir, &name("c.txt")).await, Ok(a));
+
+    lookup(&fs, "b.txt").await.unwrap();
+    fs.remove(&root, &name("b.txt")).await.unwrap();
+    assert_eq!(lookup(&fs, "b.txt").await, Err(nfsstat3::NFS3ERR_NOENT));
+
+    let (created, _) = fs
+        .create(&root, &name("b.txt"), sattr3::default())
+        .await
+        .unwrap();
+    assert_eq!(lookup(&fs, "b.txt").await, Ok(created));
+}
+
+#[tokio::test]
+async fn attributes_are_invalidated_without_a_cached_lookup() {
+    let fs = cached(TTL, 100);
+    let root = fs.root_dir();
+    // the handles come from the backend, only their attributes are cached
+    let a = fs.inner().lookup(&root, &name("a.txt")).await.unwrap();
+    let b = fs.inner().lookup(&root, &name("b.txt")).await.unwrap();
+    fs.getattr(&a).await.unwrap();
+    fs.getattr(&b).await.unwrap();
+
+    fs.rename(&root, &name("a.txt"), &root, &name("b.txt"))
+        .await
+        .unwrap();
+    assert!(fs.getattr(&b).await.is_err());
+
+    fs.remove(&root, &name("b.txt")).await.unwrap();
+    assert!(fs.getattr(&a).await.is_err());
+}
+
+#[tokio::test]
+async fn parent_of_moved_directory_is_invalidated() {
+    let fs = cached(TTL, 100);
+    let root = fs.root_dir();
+    let dir = lookup(&fs, "dir").await.unwrap();
+    let (sub, _) = fs.mkdir(&dir, &name("sub")).await.unwrap();
+    assert_eq!(fs.lookup(&sub, &name("..")).await, Ok(dir));
+
+    fs.rename(&dir, &name("sub"), &root, &name("sub"))
+        .await
+        .unwrap();
+    assert_eq!(fs.lookup(&sub, &name("..")).await, Ok(root));
+}
+
+#[tokio::test]
+async fn directory_attributes_follow_its_entries() {
+    let fs = cached(TTL, 100);
+    let root = fs.root_dir();
+
+    fs.getattr(&root).await.unwrap();
+    fs.mkdir(&root, &name("new_dir")).await.unwrap();
+    fs.getattr(&root).await.unwrap();
+
+    let stats = fs.stats().getattr;
+    assert_eq!((stats.hits, stats.misses), (0, 2));
+}
+
+#[tokio::test]
+async fn entries_expire() {
+    let fs = cached(Duration::from_millis(20), 100);
+    let file = lookup(&fs, "a.txt").await.unwrap();
+
+    fs.getattr(&file).await.unwrap();
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    fs.getattr(&file).await.unwrap();
+
+    let stats = fs.stats().getattr;
+    assert_eq!((stats.hits, stats.misses), (0, 2));
+}
+
+#[tokio::test]
+async fn capacity_is_bounded() {
+    let fs = cached(TTL, 2);
+    let root = fs.root_dir();
+
+    for path in ["a.txt", "b.txt", "dir"] {
+        let id = lookup(&fs, path).await.unwrap();
+        fs.getattr(&id).await.unwrap();
+    }
+    let stats = fs.stats();
+    assert_eq!(stats.lookup.entries, 2);
+    assert_eq!(stats.lookup.evictions, 1);
+    assert_eq!(stats.getattr.entries, 2);
+
+    // the oldest entry is gone, the newest one is still there
+    fs.getattr(&root).await.unwrap();
+    lookup(&fs, "dir").await.unwrap();
+    assert_eq!(fs.stats().lookup.hits, 1);
+
+    fs.clear();
+    assert_eq!(fs.stats().getattr.entries, 0);
+}
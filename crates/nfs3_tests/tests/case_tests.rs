@@ -0,0 +1,105 @@
+use nfs3_client::nfs3_types::nfs3::*;
+use nfs3_server::memfs::{MemFs, MemFsConfig};
+use nfs3_server::vfs::adapters::{CaseFolding, CaseInsensitiveAdapter};
+use nfs3_server::vfs::{NfsFileSystem, NfsReadFileSystem};
+use nfs3_tests::vfs::{contents, name, names};
+use nfs3_tests::{JustClientExt, TestContext};
+
+fn adapter(folding: CaseFolding) -> CaseInsensitiveAdapter<MemFs> {
+    let mut config = MemFsConfig::default();
+    config.add_dir("/Docs");
+    config.add_file("/Docs/Report.TXT", b"report");
+    config.add_file("/Docs/Ärger.md", b"umlaut");
+    config.add_file("/README", b"upper");
+    config.add_file("/Readme", b"mixed");
+    CaseInsensitiveAdapter::new(MemFs::new(config).unwrap(), folding)
+}
+
+#[tokio::test]
+async fn lookup_ignores_case() {
+    let fs = adapter(CaseFolding::Ascii);
+    assert_eq!(contents(&fs, "/docs/report.txt").await, b"report");
+    assert_eq!(contents(&fs, "/DOCS/REPORT.txt").await, b"report");
+
+    // exact matches win, other spellings are ambiguous
+    assert_eq!(contents(&fs, "/README").await, b"upper");
+    assert_eq!(contents(&fs, "/Readme").await, b"mixed");
+    assert_eq!(
+        fs.lookup_by_path("/readme").await.err(),
+        Some(nfsstat3::NFS3ERR_INVAL)
+    );
+    assert_eq!(
+        fs.lookup_by_path("/docs/missing").await.err(),
+        Some(nfsstat3::NFS3ERR_NOENT)
+    );
+
+    // only ASCII letters are folded
+    assert_eq!(
+        fs.lookup_by_path("/docs/ärger.md").await.err(),
+        Some(nfsstat3::NFS3ERR_NOENT)
+    );
+    let fs = adapter(CaseFolding::Unicode);
+    assert_eq!(contents(&fs, "/docs/ärger.MD").await, b"umlaut");
+}
+
+#[tokio::test]
+async fn changes_use_the_existing_name() {
+    let fs = adapter(CaseFolding::Ascii);
+    let docs = fs.lookup_by_path("/docs").await.unwrap();
+
+    // MemFs refuses to create an existing file
+    assert_eq!(
+        fs.create(&docs, &name("REPORT.txt"), sattr3::default())
+            .await
+            .err(),
+        Some(nfsstat3::NFS3ERR_EXIST)
+    );
+    assert_eq!(names(&fs, "/Docs").await, ["Report.TXT", "Ärger.md"]);
+
+    fs.mkdir(&docs, &name("Drafts")).await.unwrap();
+
+    // a change of case renames the entry
+    fs.rename(&docs, &name("drafts"), &docs, &name("DRAFTS"))
+        .await
+        .unwrap();
+    assert_eq!(
+        names(&fs, "/Docs").await,
+        ["DRAFTS", "Report.TXT", "Ärger.md"]
+    );
+
+    // renaming onto another spelling replaces the existing entry
+    assert_eq!(
+        fs.rename(&docs, &name("ärger.md"), &docs, &name("report.txt"))
+            .await,
+        Err(nfsstat3::NFS3ERR_NOENT)
+    );
+    let fs = adapter(CaseFolding::Unicode);
+    let docs = fs.lookup_by_path("/docs").await.unwrap();
+    fs.rename(&docs, &name("ärger.md"), &docs, &name("report.txt"))
+        .await
+        .unwrap();
+    assert_eq!(names(&fs, "/Docs").await, ["Report.TXT"]);
+    assert_eq!(contents(&fs, "/docs/report.txt").await, b"umlaut");
+
+    fs.remove(&docs, &name("REPORT.TXT")).await.unwrap();
+    assert!(names(&fs, "/Docs").await.is_empty());
+    assert_eq!(
+        fs.remove(&fs.root_dir(), &name("readme")).await,
+        Err(nfsstat3::NFS3ERR_INVAL)
+    );
+}
+
+#[tokio::test]
+async fn pathconf_reports_case_insensitive() {
+    let mut client = TestContext::serve(adapter(CaseFolding::Ascii));
+    let root = client.root_dir().clone();
+
+    let docs = client.just_lookup(&root, "DOCS").await.unwrap();
+    let pathconf = client
+        .pathconf(&PATHCONF3args { object: docs })
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(pathconf.case_insensitive);
+    assert!(pathconf.case_preserving);
+}
@@ -0,0 +1,295 @@
+use std::sync::Mutex;
+
+use nfs3_client::Nfs3Client;
+use nfs3_client::nfs3_types::mount::{self, MOUNT_PROGRAM, dirpath, fhstatus};
+use nfs3_client::nfs3_types::nfs2::{
+    DIROP2res, NFS_PROGRAM, PROGRAM, READ2args, READ2res, VERSION, WRITE2args, attrstat2,
+    diropargs2, filename2,
+};
+use nfs3_client::nfs3_types::nfs3::*;
+use nfs3_client::nfs3_types::xdr_codec::Opaque;
+use nfs3_client::rpc::RpcClient;
+use nfs3_client::tokio::TokioIo;
+use nfs3_server::memfs::{MemFs, MemFsConfig};
+use nfs3_server::vfs::{FileHandleU64, NfsFileSystem, NfsReadFileSystem, ReadDirPlusIterator};
+use nfs3_tests::{JustClient, JustClientExt, Server};
+use tokio::io::{DuplexStream, duplex};
+
+/// Keeps the written data in memory until the file is committed
+struct Buffered {
+    inner: MemFs,
+    pending: Mutex<Vec<(FileHandleU64, u64, Vec<u8>)>>,
+}
+
+impl Buffered {
+    fn new() -> Self {
+        let mut config = MemFsConfig::default();
+        config.add_file("/a.txt", b"hello world\n");
+        Self {
+            inner: MemFs::new(config).unwrap(),
+            pending: Mutex::new(Vec::new()),
+        }
+    }
+}
+
+impl NfsReadFileSystem for Buffered {
+    type Handle = FileHandleU64;
+
+    fn root_dir(&self) -> Self::Handle {
+        self.inner.root_dir()
+    }
+
+    async fn lookup(
+        &self,
+        dirid: &Self::Handle,
+        filename: &filename3<'_>,
+    ) -> Result<Self::Handle, nfsstat3> {
+        self.inner.lookup(dirid, filename).await
+    }
+
+    async fn getattr(&self, id: &Self::Handle) -> Result<fattr3, nfsstat3> {
+        self.inner.getattr(id).await
+    }
+
+    async fn read(
+        &self,
+        id: &Self::Handle,
+        offset: u64,
+        count: u32,
+    ) -> Result<(Vec<u8>, bool), nfsstat3> {
+        self.inner.read(id, offset, count).await
+    }
+
+    async fn readdirplus(
+        &self,
+        dirid: &Self::Handle,
+        cookie: u64,
+    ) -> Result<impl ReadDirPlusIterator<Self::Handle>, nfsstat3> {
+        self.inner.readdirplus(dirid, cookie).await
+    }
+
+    async fn readlink(&self, id: &Self::Handle) -> Result<nfspath3<'_>, nfsstat3> {
+        self.inner.readlink(id).await
+    }
+}
+
+impl NfsFileSystem for Buffered {
+    async fn setattr(&self, id: &Self::Handle, setattr: sattr3) -> Result<fattr3, nfsstat3> {
+        self.inner.setattr(id, setattr).await
+    }
+
+    async fn write(&self, id: &Self::Handle, offset: u64, data: &[u8]) -> Result<fattr3, nfsstat3> {
+        self.pending
+            .lock()
+            .unwrap()
+            .push((*id, offset, data.to_vec()));
+        self.inner.getattr(id).await
+    }
+
+    fn buffers_writes(&self) -> bool {
+        true
+    }
+
+    async fn commit(&self, id: &Self::Handle) -> Result<fattr3, nfsstat3> {
+        let writes = {
+            let mut pending = self.pending.lock().unwrap();
+            let (writes, rest) = pending.drain(..).partition(|(file, ..)| file == id);
+            *pending = rest;
+            writes
+        };
+        for (_, offset, data) in writes {
+            self.inner.write(id, offset, &data).await?;
+        }
+        self.inner.getattr(id).await
+    }
+
+    async fn create(
+        &self,
+        dirid: &Self::Handle,
+        filename: &filename3<'_>,
+        attr: sattr3,
+    ) -> Result<(Self::Handle, fattr3), nfsstat3> {
+        self.inner.create(dirid, filename, attr).await
+    }
+
+    async fn create_exclusive(
+        &self,
+        dirid: &Self::Handle,
+        filename: &filename3<'_>,
+        createverf: createverf3,
+    ) -> Result<Self::Handle, nfsstat3> {
+        self.inner
+            .create_exclusive(dirid, filename, createverf)
+            .await
+    }
+
+    async fn mkdir(
+        &self,
+        dirid: &Self::Handle,
+        dirname: &filename3<'_>,
+    ) -> Result<(Self::Handle, fattr3), nfsstat3> {
+        self.inner.mkdir(dirid, dirname).await
+    }
+
+    async fn remove(&self, dirid: &Self::Handle, filename: &filename3<'_>) -> Result<(), nfsstat3> {
+        self.inner.remove(dirid, filename).await
+    }
+
+    async fn rename<'a>(
+        &self,
+        from_dirid: &Self::Handle,
+        from_filename: &filename3<'a>,
+        to_dirid: &Self::Handle,
+        to_filename: &filename3<'a>,
+    ) -> Result<(), nfsstat3> {
+        self.inner
+            .rename(from_dirid, from_filename, to_dirid, to_filename)
+            .await
+    }
+
+    async fn symlink<'a>(
+        &self,
+        dirid: &Self::Handle,
+        linkname: &filename3<'a>,
+        symlink: &nfspath3<'a>,
+        attr: &sattr3,
+    ) -> Result<(Self::Handle, fattr3), nfsstat3> {
+        self.inner.symlink(dirid, linkname, symlink, attr).await
+    }
+}
+
+struct Client(Nfs3Client<TokioIo<DuplexStream>>);
+
+impl JustClient for Client {
+    type IO = TokioIo<DuplexStream>;
+
+    fn client(&mut self) -> &mut Nfs3Client<Self::IO> {
+        &mut self.0
+    }
+}
+
+async fn read(client: &mut Client, file: &nfs_fh3) -> Vec<u8> {
+    let res = client
+        .0
+        .read(&READ3args {
+            file: file.clone(),
+            offset: 0,
+            count: 100,
+        })
+        .await
+        .unwrap()
+        .unwrap();
+    res.data.0.into_owned()
+}
+
+async fn write(client: &mut Client, file: &nfs_fh3, stable: stable_how) -> WRITE3resok {
+    client
+        .0
+        .write(&WRITE3args {
+            file: file.clone(),
+            offset: 0,
+            count: 5,
+            stable,
+            data: Opaque::borrowed(b"HELLO"),
+        })
+        .await
+        .unwrap()
+        .unwrap()
+}
+
+#[tokio::test]
+async fn unstable_writes_wait_for_commit() {
+    let (server_io, client_io) = duplex(1024 * 1024);
+    let server = Server::new(server_io, Buffered::new()).unwrap();
+    let root = server.root_dir();
+    tokio::spawn(server.run());
+    let mut client = Client(Nfs3Client::new(TokioIo::new(client_io)));
+    let file = client.just_lookup(&root, "a.txt").await.unwrap();
+
+    let res = write(&mut client, &file, stable_how::UNSTABLE).await;
+    assert!(matches!(res.committed, stable_how::UNSTABLE));
+    assert_eq!(read(&mut client, &file).await, b"hello world\n");
+
+    let commit = client
+        .0
+        .commit(&COMMIT3args {
+            file: file.clone(),
+            offset: 0,
+            count: 0,
+        })
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(commit.verf, res.verf);
+    assert_eq!(read(&mut client, &file).await, b"HELLO world\n");
+}
+
+#[tokio::test]
+async fn stable_writes_are_committed() {
+    let (server_io, client_io) = duplex(1024 * 1024);
+    let server = Server::new(server_io, Buffered::new()).unwrap();
+    let root = server.root_dir();
+    tokio::spawn(server.run());
+    let mut client = Client(Nfs3Client::new(TokioIo::new(client_io)));
+    let file = client.just_lookup(&root, "a.txt").await.unwrap();
+
+    let res = write(&mut client, &file, stable_how::FILE_SYNC).await;
+    assert!(matches!(res.committed, stable_how::FILE_SYNC));
+    assert_eq!(read(&mut client, &file).await, b"HELLO world\n");
+}
+
+#[tokio::test]
+async fn nfs2_writes_are_committed() {
+    let (server_io, client_io) = duplex(1024 * 1024);
+    let server = Server::new(server_io, Buffered::new()).unwrap();
+    tokio::spawn(server.run());
+    let mut client = RpcClient::new(TokioIo::new(client_io));
+
+    let res: fhstatus = client
+        .call(
+            mount::PROGRAM,
+            mount::VERSION_1,
+            MOUNT_PROGRAM::MOUNTPROC3_MNT as u32,
+            &dirpath(Opaque::borrowed(b"/mnt")),
+        )
+        .await
+        .unwrap();
+    let fhstatus::Ok(root) = res else {
+        panic!("mount failed: {res:?}");
+    };
+    let args = diropargs2 {
+        dir: root,
+        name: filename2(Opaque::borrowed(b"a.txt")),
+    };
+    let res: DIROP2res = client
+        .call(PROGRAM, VERSION, NFS_PROGRAM::NFSPROC_LOOKUP as u32, &args)
+        .await
+        .unwrap();
+    let file = res.unwrap().file;
+
+    let args = WRITE2args {
+        file,
+        beginoffset: 0,
+        offset: 0,
+        totalcount: 0,
+        data: Opaque::borrowed(b"HELLO"),
+    };
+    let res: attrstat2 = client
+        .call(PROGRAM, VERSION, NFS_PROGRAM::NFSPROC_WRITE as u32, &args)
+        .await
+        .unwrap();
+    res.unwrap();
+
+    // NFSv2 writes are stable, so the data is read back right away
+    let args = READ2args {
+        file,
+        offset: 0,
+        count: 100,
+        totalcount: 0,
+    };
+    let res: READ2res = client
+        .call(PROGRAM, VERSION, NFS_PROGRAM::NFSPROC_READ as u32, &args)
+        .await
+        .unwrap();
+    assert_eq!(res.unwrap().data.as_ref(), b"HELLO world\n");
+}
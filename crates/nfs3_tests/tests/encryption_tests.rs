@@ -0,0 +1,277 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use nfs3_client::nfs3_types::nfs3::{Nfs3Option, nfsstat3, sattr3};
+use nfs3_server::memfs::{MemFs, MemFsConfig};
+use nfs3_server::vfs::adapters::{
+    EncryptionAdapter, Fault, FaultInjectionAdapter, FaultRule, FsOperation,
+};
+use nfs3_server::vfs::{NfsFileSystem, NfsReadFileSystem};
+use nfs3_tests::vfs::{name, names, pattern};
+
+const KEY: [u8; 32] = [7; 32];
+
+fn adapter() -> EncryptionAdapter<MemFs> {
+    let mut config = MemFsConfig::default();
+    config.add_dir("/docs");
+    EncryptionAdapter::new(MemFs::new(config).unwrap(), &KEY)
+}
+
+#[tokio::test]
+async fn random_access_matches_plaintext() {
+    let fs = adapter();
+    let docs = fs.lookup_by_path("/docs").await.unwrap();
+    let (file, _) = fs
+        .create(&docs, &name("data.bin"), sattr3::default())
+        .await
+        .unwrap();
+
+    let mut expected = Vec::new();
+    for (offset, len, seed) in [
+        (0, 10_000, 1),
+        (4090, 20, 2),
+        (8192, 4096, 3),
+        (9000, 5000, 4),
+        (1, 1, 5),
+    ] {
+        let data = pattern(len, seed);
+        let attr = fs.write(&file, offset as u64, &data).await.unwrap();
+        if expected.len() < offset + len {
+            expected.resize(offset + len, 0);
+        }
+        expected[offset..offset + len].copy_from_slice(&data);
+        assert_eq!(attr.size, expected.len() as u64);
+    }
+    assert_eq!(fs.getattr(&file).await.unwrap().size, 14_000);
+
+    for (offset, count) in [
+        (0, 14_000),
+        (4095, 2),
+        (1, 8192),
+        (13_999, 100),
+        (7000, 3000),
+    ] {
+        let (data, eof) = fs.read(&file, offset, count).await.unwrap();
+        let end = (offset as usize + count as usize).min(expected.len());
+        assert_eq!(data, expected[offset as usize..end], "{offset} {count}");
+        assert_eq!(eof, end == expected.len());
+    }
+    let (data, eof) = fs.read(&file, 20_000, 10).await.unwrap();
+    assert!(data.is_empty() && eof);
+
+    // the wrapped file system only sees ciphertext
+    let stored = fs.inner().lookup_by_path("/docs/data.bin").await.unwrap();
+    let stored_size = fs.inner().getattr(&stored).await.unwrap().size;
+    assert!(stored_size > 14_000);
+    let (ciphertext, _) = fs.inner().read(&stored, 0, 1 << 20).await.unwrap();
+    assert!(!ciphertext.windows(16).any(|w| w == &expected[9000..9016]));
+}
+
+#[tokio::test]
+async fn holes_and_truncation() {
+    let fs = adapter();
+    let root = fs.root_dir();
+    let (file, _) = fs
+        .create(&root, &name("sparse"), sattr3::default())
+        .await
+        .unwrap();
+
+    // writing past the end fills the gap with zeros
+    fs.write(&file, 10_000, b"tail").await.unwrap();
+    let (data, _) = fs.read(&file, 0, 20_000).await.unwrap();
+    assert_eq!(data.len(), 10_004);
+    assert!(data[..10_000].iter().all(|&b| b == 0));
+    assert_eq!(&data[10_000..], b"tail");
+
+    let truncate = |size| sattr3 {
+        size: Nfs3Option::Some(size),
+        ..Default::default()
+    };
+    fs.write(&file, 0, &pattern(10_000, 9)).await.unwrap();
+    let attr = fs.setattr(&file, truncate(5000)).await.unwrap();
+    assert_eq!(attr.size, 5000);
+    let (data, eof) = fs.read(&file, 0, 20_000).await.unwrap();
+    assert_eq!(data, pattern(5000, 9));
+    assert!(eof);
+
+    let attr = fs.setattr(&file, truncate(6000)).await.unwrap();
+    assert_eq!(attr.size, 6000);
+    let (data, _) = fs.read(&file, 4990, 1010).await.unwrap();
+    assert_eq!(data[..10], pattern(5000, 9)[4990..]);
+    assert!(data[10..].iter().all(|&b| b == 0));
+
+    fs.setattr(&file, truncate(0)).await.unwrap();
+    assert_eq!(fs.getattr(&file).await.unwrap().size, 0);
+    fs.write(&file, 0, b"again").await.unwrap();
+    assert_eq!(fs.read(&file, 0, 100).await.unwrap().0, b"again");
+
+    let (file, attr) = fs
+        .create(&root, &name("sized"), truncate(100))
+        .await
+        .unwrap();
+    assert_eq!(attr.size, 100);
+    assert_eq!(fs.read(&file, 0, 200).await.unwrap().0, [0; 100]);
+}
+
+#[tokio::test]
+async fn tampering_is_detected() {
+    let fs = adapter();
+    let root = fs.root_dir();
+    let (file, _) = fs
+        .create(&root, &name("a"), sattr3::default())
+        .await
+        .unwrap();
+    fs.write(&file, 0, &pattern(9000, 1)).await.unwrap();
+
+    // flip a byte of the second block
+    let (mut stored, _) = fs.inner().read(&file, 5000, 1).await.unwrap();
+    stored[0] ^= 1;
+    fs.inner().write(&file, 5000, &stored).await.unwrap();
+    assert!(fs.read(&file, 0, 4096).await.is_ok());
+    assert_eq!(
+        fs.read(&file, 4096, 10).await.err(),
+        Some(nfsstat3::NFS3ERR_IO)
+    );
+
+    // another key can't read the contents
+    let (file, _) = fs
+        .create(&root, &name("b"), sattr3::default())
+        .await
+        .unwrap();
+    fs.write(&file, 0, b"secret").await.unwrap();
+    let (stored, _) = fs.inner().read(&file, 0, 1000).await.unwrap();
+    let other = EncryptionAdapter::new(MemFs::new(MemFsConfig::default()).unwrap(), &[8; 32]);
+    let root = other.root_dir();
+    let (copy, _) = other
+        .inner()
+        .create(&root, &name("b"), sattr3::default())
+        .await
+        .unwrap();
+    other.inner().write(&copy, 0, &stored).await.unwrap();
+    assert_eq!(other.getattr(&copy).await.unwrap().size, 6);
+    assert_eq!(
+        other.read(&copy, 0, 6).await.err(),
+        Some(nfsstat3::NFS3ERR_IO)
+    );
+}
+
+#[tokio::test]
+async fn truncation_is_detected() {
+    const STORED_BLOCK: u64 = 4096 + 40;
+    let fs = adapter();
+    let root = fs.root_dir();
+    let (file, _) = fs
+        .create(&root, &name("a"), sattr3::default())
+        .await
+        .unwrap();
+    // the second write extends a file that ends at a block boundary
+    let data = pattern(12288, 3);
+    fs.write(&file, 0, &data[..8192]).await.unwrap();
+    fs.write(&file, 8192, &data[8192..]).await.unwrap();
+    assert_eq!(fs.read(&file, 0, 20000).await.unwrap().0, data);
+
+    let cut = |size| sattr3 {
+        size: Nfs3Option::Some(size),
+        ..Default::default()
+    };
+    // drop the last block in the wrapped file system
+    fs.inner()
+        .setattr(&file, cut(16 + 2 * STORED_BLOCK))
+        .await
+        .unwrap();
+    assert_eq!(fs.getattr(&file).await.unwrap().size, 8192);
+    assert!(fs.read(&file, 0, 4096).await.is_ok());
+    assert_eq!(
+        fs.read(&file, 4096, 4096).await.err(),
+        Some(nfsstat3::NFS3ERR_IO)
+    );
+    // and all of them
+    fs.inner().setattr(&file, cut(16)).await.unwrap();
+    assert_eq!(
+        fs.read(&file, 0, 4096).await.err(),
+        Some(nfsstat3::NFS3ERR_IO)
+    );
+
+    // truncating through the adapter keeps the file readable
+    let (file, _) = fs
+        .create(&root, &name("b"), sattr3::default())
+        .await
+        .unwrap();
+    fs.write(&file, 0, &data).await.unwrap();
+    fs.setattr(&file, cut(4096)).await.unwrap();
+    assert_eq!(fs.read(&file, 0, 20000).await.unwrap().0, data[..4096]);
+    fs.setattr(&file, cut(0)).await.unwrap();
+    assert_eq!(fs.read(&file, 0, 20000).await.unwrap(), (Vec::new(), true));
+    fs.write(&file, 0, b"again").await.unwrap();
+    assert_eq!(fs.read(&file, 0, 100).await.unwrap().0, b"again");
+}
+
+#[tokio::test]
+async fn names_are_encrypted() {
+    let mut fs = adapter();
+    fs.set_name_encryption(true);
+    let root = fs.root_dir();
+
+    let (dir, _) = fs.mkdir(&root, &name("Private")).await.unwrap();
+    let (file, _) = fs
+        .create(&dir, &name("passwords.txt"), sattr3::default())
+        .await
+        .unwrap();
+    fs.write(&file, 0, b"hunter2").await.unwrap();
+
+    // `docs` was stored without encryption, so it's not listed
+    assert_eq!(names(&fs, "/").await, ["Private"]);
+    assert_eq!(names(&fs, "/Private").await, ["passwords.txt"]);
+
+    fs.rename(&dir, &name("passwords.txt"), &root, &name("moved.txt"))
+        .await
+        .unwrap();
+    let moved = fs.lookup_by_path("/moved.txt").await.unwrap();
+    assert_eq!(fs.read(&moved, 0, 100).await.unwrap().0, b"hunter2");
+    assert_eq!(
+        fs.lookup_by_path("/Private/passwords.txt").await.err(),
+        Some(nfsstat3::NFS3ERR_NOENT)
+    );
+    assert_eq!(names(&fs, "/").await, ["Private", "moved.txt"]);
+
+    // the wrapped file system doesn't see the names
+    let stored = names(fs.inner(), "/").await;
+    assert_eq!(stored.len(), 3);
+    for stored in &stored {
+        assert!(!stored.contains("Private") && !stored.contains("moved"));
+    }
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn reads_see_whole_appends() {
+    const CHUNK: usize = 1000;
+    const CHUNKS: usize = 50;
+
+    // slow reads widen the window between the size and the contents of a read
+    let mut inner = FaultInjectionAdapter::new(MemFs::new(MemFsConfig::default()).unwrap(), 0);
+    inner.add_rule(FaultRule::new(Fault::Delay(Duration::from_millis(1))).on(FsOperation::Read));
+    let fs = Arc::new(EncryptionAdapter::new(inner, &KEY));
+    let (file, _) = fs
+        .create(&fs.root_dir(), &name("log"), sattr3::default())
+        .await
+        .unwrap();
+    let expected = pattern(CHUNK * CHUNKS, 5);
+
+    let writer = {
+        let fs = Arc::clone(&fs);
+        let expected = expected.clone();
+        tokio::spawn(async move {
+            for (i, chunk) in expected.chunks(CHUNK).enumerate() {
+                fs.write(&file, (i * CHUNK) as u64, chunk).await.unwrap();
+            }
+        })
+    };
+    // appends extend the last block, a read must never see it half written
+    while !writer.is_finished() {
+        let (data, _) = fs.read(&file, 0, u32::MAX).await.unwrap();
+        assert_eq!(data.len() % CHUNK, 0);
+        assert_eq!(data, expected[..data.len()]);
+    }
+    writer.await.unwrap();
+    assert_eq!(fs.read(&file, 0, u32::MAX).await.unwrap().0, expected);
+}
@@ -0,0 +1,181 @@
+use std::time::{Duration, Instant};
+
+use nfs3_client::nfs3_types::nfs3::*;
+use nfs3_client::tokio::TokioIo;
+use nfs3_server::memfs::{MemFs, MemFsConfig};
+use nfs3_server::vfs::adapters::{Fault, FaultInjectionAdapter, FaultRule, FsOperation};
+use nfs3_server::vfs::{NfsFileSystem, NfsReadFileSystem};
+use nfs3_tests::vfs::name;
+use nfs3_tests::{JustClientExt, TestContext};
+use tokio::io::DuplexStream;
+
+type Client = TestContext<TokioIo<DuplexStream>>;
+
+fn memfs() -> MemFs {
+    let mut config = MemFsConfig::default();
+    config.add_file("/a.txt", b"hello world\n");
+    config.add_dir("/data");
+    config.add_file("/data/b.txt", b"0123456789");
+    config.add_file("/data/c.log", b"log");
+    MemFs::new(config).unwrap()
+}
+
+async fn read(
+    client: &mut Client,
+    file: &nfs_fh3,
+    count: u32,
+) -> Result<(Vec<u8>, bool), nfsstat3> {
+    let res = client
+        .read(&READ3args {
+            file: file.clone(),
+            offset: 0,
+            count,
+        })
+        .await
+        .unwrap();
+    match res {
+        Nfs3Result::Ok(ok) => Ok((ok.data.as_ref().to_vec(), ok.eof)),
+        Nfs3Result::Err((stat, _)) => Err(stat),
+    }
+}
+
+#[tokio::test]
+async fn error_by_operation_and_path() {
+    let mut fs = FaultInjectionAdapter::new(memfs(), 0);
+    fs.add_rule(
+        FaultRule::new(Fault::Error(nfsstat3::NFS3ERR_JUKEBOX))
+            .on(FsOperation::Read)
+            .matching("/data/*.txt"),
+    );
+    fs.add_rule(FaultRule::new(Fault::Error(nfsstat3::NFS3ERR_NOSPC)).on(FsOperation::Create));
+    let mut client = TestContext::serve(fs);
+    let root = client.root_dir().clone();
+
+    let a = client.just_lookup(&root, "a.txt").await.unwrap();
+    assert_eq!(
+        read(&mut client, &a, 100).await.unwrap().0,
+        b"hello world\n"
+    );
+
+    let data = client.just_lookup(&root, "data").await.unwrap();
+    let b = client.just_lookup(&data, "b.txt").await.unwrap();
+    assert_eq!(
+        read(&mut client, &b, 100).await,
+        Err(nfsstat3::NFS3ERR_JUKEBOX)
+    );
+    let c = client.just_lookup(&data, "c.log").await.unwrap();
+    assert_eq!(read(&mut client, &c, 100).await.unwrap().0, b"log");
+
+    let res = client
+        .create(&CREATE3args {
+            where_: diropargs3 {
+                dir: data,
+                name: b"new.txt".as_slice().into(),
+            },
+            how: createhow3::UNCHECKED(sattr3::default()),
+        })
+        .await
+        .unwrap();
+    assert!(matches!(res, Nfs3Result::Err((nfsstat3::NFS3ERR_NOSPC, _))));
+}
+
+#[tokio::test]
+async fn short_reads() {
+    let mut fs = FaultInjectionAdapter::new(memfs(), 0);
+    fs.add_rule(FaultRule::new(Fault::ShortRead(4)));
+    let mut client = TestContext::serve(fs);
+    let root = client.root_dir().clone();
+
+    let data = client.just_lookup(&root, "data").await.unwrap();
+    let b = client.just_lookup(&data, "b.txt").await.unwrap();
+    let (bytes, eof) = read(&mut client, &b, 100).await.unwrap();
+    assert_eq!(bytes, b"0123");
+    assert!(!eof);
+}
+
+#[tokio::test]
+async fn delays() {
+    let mut fs = FaultInjectionAdapter::new(memfs(), 0);
+    fs.add_rule(FaultRule::new(Fault::Delay(Duration::from_millis(100))).on(FsOperation::Getattr));
+    let mut client = TestContext::serve(fs);
+    let root = client.root_dir().clone();
+
+    let started = Instant::now();
+    let res = client
+        .getattr(&GETATTR3args { object: root })
+        .await
+        .unwrap();
+    assert!(matches!(res, Nfs3Result::Ok(_)));
+    assert!(started.elapsed() >= Duration::from_millis(100));
+}
+
+#[tokio::test]
+async fn seeded_faults_are_reproducible() {
+    async fn run(seed: u64) -> Vec<bool> {
+        let mut fs = FaultInjectionAdapter::new(memfs(), seed);
+        fs.add_rule(FaultRule::new(Fault::Error(nfsstat3::NFS3ERR_IO)).with_probability(0.3));
+        let root = fs.root_dir();
+        let mut outcomes = Vec::new();
+        for _ in 0..100 {
+            outcomes.push(fs.getattr(&root).await.is_err());
+        }
+        assert_eq!(
+            fs.injected(),
+            outcomes.iter().filter(|failed| **failed).count() as u64
+        );
+        outcomes
+    }
+
+    let first = run(42).await;
+    assert_eq!(first, run(42).await);
+    assert_ne!(first, run(43).await);
+
+    let failures = first.iter().filter(|failed| **failed).count();
+    assert!((10..=50).contains(&failures), "{failures} failures");
+}
+
+#[tokio::test]
+async fn paths_follow_renames() {
+    let mut fs = FaultInjectionAdapter::new(memfs(), 0);
+    fs.add_rule(
+        FaultRule::new(Fault::Error(nfsstat3::NFS3ERR_IO))
+            .on(FsOperation::Getattr)
+            .matching("/moved/*.txt"),
+    );
+    let root = fs.root_dir();
+    let data = fs.lookup(&root, &name("data")).await.unwrap();
+    let b = fs.lookup(&data, &name("b.txt")).await.unwrap();
+    assert!(fs.getattr(&b).await.is_ok());
+
+    fs.rename(&root, &name("data"), &root, &name("moved"))
+        .await
+        .unwrap();
+    assert_eq!(fs.getattr(&b).await.err(), Some(nfsstat3::NFS3ERR_IO));
+
+    fs.remove(&data, &name("b.txt")).await.unwrap();
+    let (new, _) = fs
+        .create(&data, &name("b.txt"), sattr3::default())
+        .await
+        .unwrap();
+    assert_eq!(fs.getattr(&new).await.err(), Some(nfsstat3::NFS3ERR_IO));
+}
+
+#[tokio::test]
+async fn forgotten_paths_do_not_match() {
+    let mut fs = FaultInjectionAdapter::new(memfs(), 0);
+    fs.add_rule(
+        FaultRule::new(Fault::Error(nfsstat3::NFS3ERR_IO))
+            .on(FsOperation::Getattr)
+            .matching("/a.txt"),
+    );
+    fs.with_path_capacity(1);
+    let root = fs.root_dir();
+    let a = fs.lookup(&root, &name("a.txt")).await.unwrap();
+    assert_eq!(fs.getattr(&a).await.err(), Some(nfsstat3::NFS3ERR_IO));
+
+    // the root is kept, "a.txt" is evicted
+    fs.lookup(&root, &name("data")).await.unwrap();
+    assert!(fs.getattr(&a).await.is_ok());
+    fs.lookup(&root, &name("a.txt")).await.unwrap();
+    assert_eq!(fs.getattr(&a).await.err(), Some(nfsstat3::NFS3ERR_IO));
+}
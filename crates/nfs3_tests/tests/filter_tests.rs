@@ -0,0 +1,135 @@
+use nfs3_client::nfs3_types::nfs3::{nfsstat3, sattr3};
+use nfs3_server::memfs::{MemFs, MemFsConfig};
+use nfs3_server::vfs::adapters::{NameFilterAdapter, NamePattern};
+use nfs3_server::vfs::{NextResult, NfsFileSystem, NfsReadFileSystem, ReadDirIterator};
+use nfs3_tests::vfs::{list, name};
+
+fn filtered() -> NameFilterAdapter<MemFs> {
+    let mut config = MemFsConfig::default();
+    config.add_dir("/.git");
+    config.add_file("/.git/config", b"");
+    config.add_file("/.env", b"KEY=1");
+    config.add_dir("/src");
+    config.add_file("/src/.env.local", b"KEY=2");
+    config.add_file("/src/main.rs", b"fn main() {}");
+    config.add_dir("/src/secrets");
+    config.add_file("/src/secrets/key", b"");
+    for i in 0..10 {
+        config.add_file(&format!("/file{i}"), b"");
+        config.add_file(&format!("/file{i}.env"), b"");
+    }
+    let mut fs = NameFilterAdapter::new(MemFs::new(config).unwrap());
+    fs.add_pattern(NamePattern::glob(".git"));
+    fs.add_pattern(NamePattern::glob(".env*"));
+    fs.add_pattern(NamePattern::regex(r"^secrets?$").unwrap());
+    fs.add_pattern(NamePattern::regex(r"\.env$").unwrap());
+    fs
+}
+
+#[tokio::test]
+async fn hidden_entries_are_not_listed() {
+    let fs = filtered();
+    let root = list(&fs, &fs.root_dir(), 0).await;
+    let names = root.iter().map(|(n, _)| n.as_str()).collect::<Vec<_>>();
+    assert!(names.contains(&"src"));
+    assert!(names.contains(&"file3"));
+    for hidden in [".git", ".env", "file3.env"] {
+        assert!(!names.contains(&hidden), "{hidden} in {names:?}");
+    }
+
+    let src = list(&fs, &fs.lookup_by_path("/src").await.unwrap(), 0).await;
+    let names = src.iter().map(|(n, _)| n.as_str()).collect::<Vec<_>>();
+    assert_eq!(names, ["main.rs"]);
+
+    // readdir filters the same entries
+    let dir = fs.lookup_by_path("/src").await.unwrap();
+    let mut iter = fs.readdir(&dir, 0).await.unwrap();
+    let mut names = Vec::new();
+    while let NextResult::Ok(entry) = iter.next().await {
+        names.push(entry.name.as_ref().to_vec());
+    }
+    assert_eq!(names, [b"main.rs".to_vec()]);
+}
+
+#[tokio::test]
+async fn listing_resumes_at_any_cookie() {
+    let fs = filtered();
+    let full = list(&fs, &fs.root_dir(), 0).await;
+    for (i, (_, cookie)) in full.iter().enumerate() {
+        assert_eq!(list(&fs, &fs.root_dir(), *cookie).await, full[i + 1..]);
+    }
+}
+
+#[tokio::test]
+async fn hidden_names_cannot_be_used() {
+    let fs = filtered();
+    let root = fs.root_dir();
+
+    for path in ["/.git", "/.git/config", "/src/secrets/key", "/file1.env"] {
+        assert_eq!(
+            fs.lookup_by_path(path).await.err(),
+            Some(nfsstat3::NFS3ERR_NOENT),
+            "{path}"
+        );
+    }
+    assert_eq!(
+        fs.remove(&root, &name(".env")).await,
+        Err(nfsstat3::NFS3ERR_NOENT)
+    );
+
+    assert_eq!(
+        fs.create(&root, &name(".env.prod"), sattr3::default())
+            .await
+            .err(),
+        Some(nfsstat3::NFS3ERR_ACCES)
+    );
+    assert_eq!(
+        fs.mkdir(&root, &name("secret")).await.err(),
+        Some(nfsstat3::NFS3ERR_ACCES)
+    );
+    assert_eq!(
+        fs.rename(&root, &name("file1"), &root, &name(".git")).await,
+        Err(nfsstat3::NFS3ERR_ACCES)
+    );
+    assert_eq!(
+        fs.rename(&root, &name(".env"), &root, &name("env")).await,
+        Err(nfsstat3::NFS3ERR_NOENT)
+    );
+
+    // names that only contain a pattern are fine
+    fs.mkdir(&root, &name("my_secrets")).await.unwrap();
+    fs.rename(&root, &name("file1"), &root, &name("file1.envelope"))
+        .await
+        .unwrap();
+    assert!(fs.lookup_by_path("/file1.envelope").await.is_ok());
+}
+
+#[tokio::test]
+async fn names_with_slashes_are_rejected() {
+    let fs = filtered();
+    let root = fs.root_dir();
+
+    // a backend that joins the name to a path would resolve these to the hidden objects
+    for path in ["./.git", "src/../.git", ".git/config", "src/main.rs"] {
+        assert_eq!(
+            fs.lookup(&root, &name(path)).await.err(),
+            Some(nfsstat3::NFS3ERR_INVAL),
+            "{path}"
+        );
+    }
+    assert_eq!(
+        fs.create(&root, &name("src/../.env"), sattr3::default())
+            .await
+            .err(),
+        Some(nfsstat3::NFS3ERR_INVAL)
+    );
+    assert_eq!(
+        fs.rename(&root, &name("file1"), &root, &name("./.git"))
+            .await,
+        Err(nfsstat3::NFS3ERR_INVAL)
+    );
+
+    assert!(fs.is_hidden(b"./.git"));
+    assert!(fs.is_hidden(b"src/../secrets/key"));
+    assert!(!fs.is_hidden(b"src/../main.rs"));
+}
@@ -0,0 +1,336 @@
+use nfs3_client::nfs3_types::mount::{self, MOUNT_PROGRAM, dirpath, fhstatus};
+use nfs3_client::nfs3_types::nfs2::*;
+use nfs3_client::nfs3_types::portmap::{self, PMAP_PROG, mapping};
+use nfs3_client::nfs3_types::xdr_codec::{Opaque, Pack, Unpack, Void};
+use nfs3_client::rpc::RpcClient;
+use nfs3_client::tokio::TokioIo;
+use nfs3_server::memfs::{MemFs, MemFsConfig};
+use nfs3_server::vfs::NfsFileSystem;
+use nfs3_server::vfs::adapters::ReadOnlyAdapter;
+use nfs3_tests::Server;
+use tokio::io::{DuplexStream, duplex};
+
+type Client = RpcClient<TokioIo<DuplexStream>>;
+
+fn config() -> MemFsConfig {
+    let mut config = MemFsConfig::default();
+    config.add_file("/a.txt", b"hello world\n");
+    config.add_file("/big.bin", [0xab; 10_000]);
+    config.add_dir("/dir");
+    config.add_file("/dir/b.txt", b"");
+    config
+}
+
+fn start<FS: NfsFileSystem + 'static>(fs: FS) -> Client {
+    let (server_io, client_io) = duplex(1024 * 1024);
+    let server = Server::new(server_io, fs).unwrap();
+    tokio::spawn(server.run());
+    RpcClient::new(TokioIo::new(client_io))
+}
+
+async fn call<C, R>(client: &mut Client, proc: NFS_PROGRAM, args: &C) -> R
+where
+    C: Pack + Send + Sync,
+    R: Unpack,
+{
+    client
+        .call(PROGRAM, VERSION, proc as u32, args)
+        .await
+        .unwrap()
+}
+
+async fn mount(client: &mut Client) -> fhandle2 {
+    let res: fhstatus = client
+        .call(
+            mount::PROGRAM,
+            mount::VERSION_1,
+            MOUNT_PROGRAM::MOUNTPROC3_MNT as u32,
+            &dirpath(Opaque::borrowed(b"/mnt")),
+        )
+        .await
+        .unwrap();
+    let fhstatus::Ok(root) = res else {
+        panic!("mount failed: {res:?}");
+    };
+    root
+}
+
+async fn lookup(client: &mut Client, dir: fhandle2, name: &str) -> DIROP2res {
+    let args = diropargs2 {
+        dir,
+        name: filename2(Opaque::borrowed(name.as_bytes())),
+    };
+    call(client, NFS_PROGRAM::NFSPROC_LOOKUP, &args).await
+}
+
+async fn read(client: &mut Client, file: fhandle2, offset: u32, count: u32) -> READ2res<'static> {
+    let args = READ2args {
+        file,
+        offset,
+        count,
+        totalcount: 0,
+    };
+    call(client, NFS_PROGRAM::NFSPROC_READ, &args).await
+}
+
+async fn readdir_names(client: &mut Client, dir: fhandle2, count: u32) -> Vec<String> {
+    let mut names = Vec::new();
+    let mut cookie = [0; NFS2_COOKIESIZE];
+    loop {
+        let args = READDIR2args { dir, cookie, count };
+        let res: READDIR2res = call(client, NFS_PROGRAM::NFSPROC_READDIR, &args).await;
+        let resok = res.unwrap();
+        for entry in &resok.entries.0 {
+            names.push(String::from_utf8_lossy(&entry.name.0).into_owned());
+            cookie = entry.cookie;
+        }
+        if resok.eof {
+            break;
+        }
+    }
+    names.sort();
+    names
+}
+
+#[tokio::test]
+async fn mount_and_getattr() {
+    let mut client = start(MemFs::new(config()).unwrap());
+    let root = mount(&mut client).await;
+
+    let res: attrstat2 = call(&mut client, NFS_PROGRAM::NFSPROC_GETATTR, &root).await;
+    let attr = res.unwrap();
+    assert_eq!(attr.type_, ftype2::NFDIR);
+    assert_eq!(attr.mode & 0o170_000, 0o040_000);
+
+    let res: Void = call(&mut client, NFS_PROGRAM::NFSPROC_NULL, &Void).await;
+    assert_eq!(res, Void);
+}
+
+#[tokio::test]
+async fn mount_unknown_export() {
+    let mut client = start(MemFs::new(config()).unwrap());
+    let res: fhstatus = client
+        .call(
+            mount::PROGRAM,
+            mount::VERSION_1,
+            MOUNT_PROGRAM::MOUNTPROC3_MNT as u32,
+            &dirpath(Opaque::borrowed(b"/other")),
+        )
+        .await
+        .unwrap();
+    assert!(matches!(
+        res,
+        fhstatus::Err(mount::mountstat3::MNT3ERR_NOENT)
+    ));
+}
+
+#[tokio::test]
+async fn lookup_and_read() {
+    let mut client = start(MemFs::new(config()).unwrap());
+    let root = mount(&mut client).await;
+
+    let file = lookup(&mut client, root, "a.txt").await.unwrap();
+    assert_eq!(file.attributes.type_, ftype2::NFREG);
+    assert_eq!(file.attributes.size, 12);
+
+    let res = read(&mut client, file.file, 6, 100).await.unwrap();
+    assert_eq!(res.data.as_ref(), b"world\n");
+    assert_eq!(res.attributes.fileid, file.attributes.fileid);
+
+    let res = lookup(&mut client, root, "missing").await;
+    assert_eq!(res.status(), nfsstat2::NFSERR_NOENT);
+}
+
+#[tokio::test]
+async fn read_is_limited_to_maxdata() {
+    let mut client = start(MemFs::new(config()).unwrap());
+    let root = mount(&mut client).await;
+
+    let file = lookup(&mut client, root, "big.bin").await.unwrap();
+    let res = read(&mut client, file.file, 0, 65536).await.unwrap();
+    assert_eq!(res.data.len(), NFS2_MAXDATA as usize);
+}
+
+#[tokio::test]
+async fn create_write_and_list() {
+    let mut client = start(MemFs::new(config()).unwrap());
+    let root = mount(&mut client).await;
+
+    let args = CREATE2args {
+        where_: diropargs2 {
+            dir: root,
+            name: filename2(Opaque::borrowed(b"new.txt")),
+        },
+        attributes: sattr2 {
+            mode: 0o644,
+            ..sattr2::default()
+        },
+    };
+    let res: DIROP2res = call(&mut client, NFS_PROGRAM::NFSPROC_CREATE, &args).await;
+    let created = res.unwrap();
+
+    let args = WRITE2args {
+        file: created.file,
+        beginoffset: 0,
+        offset: 0,
+        totalcount: 0,
+        data: Opaque::borrowed(b"v2 data"),
+    };
+    let res: attrstat2 = call(&mut client, NFS_PROGRAM::NFSPROC_WRITE, &args).await;
+    assert_eq!(res.unwrap().size, 7);
+
+    let args = CREATE2args {
+        where_: diropargs2 {
+            dir: root,
+            name: filename2(Opaque::borrowed(b"sub")),
+        },
+        attributes: sattr2::default(),
+    };
+    let res: DIROP2res = call(&mut client, NFS_PROGRAM::NFSPROC_MKDIR, &args).await;
+    assert_eq!(res.unwrap().attributes.type_, ftype2::NFDIR);
+
+    // a small count forces the listing to be split into several replies
+    let names = readdir_names(&mut client, root, 64).await;
+    assert_eq!(names, ["a.txt", "big.bin", "dir", "new.txt", "sub"]);
+
+    let args = diropargs2 {
+        dir: root,
+        name: filename2(Opaque::borrowed(b"new.txt")),
+    };
+    let res: nfsstat2 = call(&mut client, NFS_PROGRAM::NFSPROC_REMOVE, &args).await;
+    assert_eq!(res, nfsstat2::NFS_OK);
+    let res = lookup(&mut client, root, "new.txt").await;
+    assert_eq!(res.status(), nfsstat2::NFSERR_NOENT);
+}
+
+#[tokio::test]
+async fn setattr_truncates() {
+    let mut client = start(MemFs::new(config()).unwrap());
+    let root = mount(&mut client).await;
+    let file = lookup(&mut client, root, "a.txt").await.unwrap();
+
+    let args = SETATTR2args {
+        file: file.file,
+        attributes: sattr2 {
+            size: 5,
+            ..sattr2::default()
+        },
+    };
+    let res: attrstat2 = call(&mut client, NFS_PROGRAM::NFSPROC_SETATTR, &args).await;
+    assert_eq!(res.unwrap().size, 5);
+
+    let res = read(&mut client, file.file, 0, 100).await.unwrap();
+    assert_eq!(res.data.as_ref(), b"hello");
+}
+
+#[tokio::test]
+async fn names_with_slashes_are_rejected() {
+    let mut client = start(MemFs::new(config()).unwrap());
+    let root = mount(&mut client).await;
+    let dir = lookup(&mut client, root, "dir").await.unwrap().file;
+    let name = |name: &'static [u8]| diropargs2 {
+        dir,
+        name: filename2(Opaque::borrowed(name)),
+    };
+
+    let res = lookup(&mut client, dir, "../a.txt").await;
+    assert_eq!(res.status(), nfsstat2::NFSERR_ACCES);
+    let res = lookup(&mut client, dir, "").await;
+    assert_eq!(res.status(), nfsstat2::NFSERR_ACCES);
+
+    let args = CREATE2args {
+        where_: name(b"../x"),
+        attributes: sattr2::default(),
+    };
+    let res: DIROP2res = call(&mut client, NFS_PROGRAM::NFSPROC_CREATE, &args).await;
+    assert_eq!(res.status(), nfsstat2::NFSERR_ACCES);
+    let res: DIROP2res = call(&mut client, NFS_PROGRAM::NFSPROC_MKDIR, &args).await;
+    assert_eq!(res.status(), nfsstat2::NFSERR_ACCES);
+
+    let args = SYMLINK2args {
+        from: name(b"../x"),
+        to: nfspath2(Opaque::borrowed(b"a.txt")),
+        attributes: sattr2::default(),
+    };
+    let res: nfsstat2 = call(&mut client, NFS_PROGRAM::NFSPROC_SYMLINK, &args).await;
+    assert_eq!(res, nfsstat2::NFSERR_ACCES);
+
+    let args = RENAME2args {
+        from: name(b"b.txt"),
+        to: name(b"../x"),
+    };
+    let res: nfsstat2 = call(&mut client, NFS_PROGRAM::NFSPROC_RENAME, &args).await;
+    assert_eq!(res, nfsstat2::NFSERR_ACCES);
+
+    let res: nfsstat2 = call(&mut client, NFS_PROGRAM::NFSPROC_REMOVE, &name(b"../a.txt")).await;
+    assert_eq!(res, nfsstat2::NFSERR_ACCES);
+
+    // nothing was touched outside of the directory
+    lookup(&mut client, root, "a.txt").await.unwrap();
+    let res = lookup(&mut client, root, "x").await;
+    assert_eq!(res.status(), nfsstat2::NFSERR_NOENT);
+    lookup(&mut client, dir, "b.txt").await.unwrap();
+}
+
+#[tokio::test]
+async fn invalid_handle_is_stale() {
+    let mut client = start(MemFs::new(config()).unwrap());
+    let res: attrstat2 = call(
+        &mut client,
+        NFS_PROGRAM::NFSPROC_GETATTR,
+        &fhandle2([0; NFS2_FHSIZE]),
+    )
+    .await;
+    assert_eq!(res.status(), nfsstat2::NFSERR_STALE);
+
+    let mut data = [0xff; NFS2_FHSIZE];
+    data[0] = 16;
+    let res: attrstat2 = call(&mut client, NFS_PROGRAM::NFSPROC_GETATTR, &fhandle2(data)).await;
+    assert_eq!(res.status(), nfsstat2::NFSERR_STALE);
+}
+
+#[tokio::test]
+async fn read_only_file_system() {
+    let mut client = start(ReadOnlyAdapter::new(MemFs::new(config()).unwrap()));
+    let root = mount(&mut client).await;
+
+    let args = diropargs2 {
+        dir: root,
+        name: filename2(Opaque::borrowed(b"a.txt")),
+    };
+    let res: nfsstat2 = call(&mut client, NFS_PROGRAM::NFSPROC_REMOVE, &args).await;
+    assert_eq!(res, nfsstat2::NFSERR_ROFS);
+}
+
+#[tokio::test]
+async fn statfs() {
+    let mut client = start(MemFs::new(config()).unwrap());
+    let root = mount(&mut client).await;
+    let res: STATFS2res = call(&mut client, NFS_PROGRAM::NFSPROC_STATFS, &root).await;
+    let res = res.unwrap();
+    assert_eq!(res.tsize, NFS2_MAXDATA);
+    assert!(res.bfree <= res.blocks);
+}
+
+#[tokio::test]
+async fn portmap_reports_v2() {
+    let mut client = start(MemFs::new(config()).unwrap());
+    for (prog, vers) in [(PROGRAM, VERSION), (mount::PROGRAM, mount::VERSION_1)] {
+        let args = mapping {
+            prog,
+            vers,
+            prot: portmap::IPPROTO_TCP,
+            port: 0,
+        };
+        let port: u32 = client
+            .call(
+                portmap::PROGRAM,
+                portmap::VERSION,
+                PMAP_PROG::PMAPPROC_GETPORT as u32,
+                &args,
+            )
+            .await
+            .unwrap();
+        assert_ne!(port, 0, "program {prog} version {vers} is not registered");
+    }
+}
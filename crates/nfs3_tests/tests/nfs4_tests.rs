@@ -0,0 +1,652 @@
+use std::io::Write;
+
+use nfs3_client::nfs3_types::nfs3;
+use nfs3_client::nfs3_types::nfs4::*;
+use nfs3_client::nfs3_types::xdr_codec::{Opaque, Pack, Void};
+use nfs3_client::rpc::RpcClient;
+use nfs3_client::tokio::TokioIo;
+use nfs3_server::memfs::{MemFs, MemFsConfig};
+use nfs3_server::vfs::NfsFileSystem;
+use nfs3_server::vfs::adapters::ReadOnlyAdapter;
+use nfs3_tests::Server;
+use tokio::io::{DuplexStream, duplex};
+
+type Client = RpcClient<TokioIo<DuplexStream>>;
+
+fn config() -> MemFsConfig {
+    let mut config = MemFsConfig::default();
+    config.add_file("/a.txt", b"hello world\n");
+    config.add_dir("/dir");
+    config.add_file("/dir/b.txt", b"");
+    config.add_dir("/empty");
+    config
+}
+
+fn start<FS: NfsFileSystem + 'static>(fs: FS) -> Client {
+    let (server_io, client_io) = duplex(1024 * 1024);
+    let server = Server::new(server_io, fs).unwrap();
+    tokio::spawn(server.run());
+    RpcClient::new(TokioIo::new(client_io))
+}
+
+fn start_memfs() -> Client {
+    start(MemFs::new(config()).unwrap())
+}
+
+async fn compound(client: &mut Client, argarray: Vec<nfs_argop4<'_>>) -> COMPOUND4res<'static> {
+    let args = COMPOUND4args {
+        tag: Opaque::borrowed(b"test"),
+        minorversion: 0,
+        argarray,
+    };
+    let res: COMPOUND4res = client
+        .call(
+            nfs3::PROGRAM,
+            VERSION,
+            NFS4_PROGRAM::NFSPROC4_COMPOUND as u32,
+            &args,
+        )
+        .await
+        .unwrap();
+    assert_eq!(res.tag.as_ref(), b"test");
+    res
+}
+
+fn lookup(name: &str) -> nfs_argop4<'_> {
+    nfs_argop4::OP_LOOKUP(LOOKUP4args {
+        objname: Opaque::borrowed(name.as_bytes()),
+    })
+}
+
+fn no_attrs() -> fattr4<'static> {
+    fattr4 {
+        attrmask: bitmap4::new(),
+        attr_vals: Opaque::borrowed(&[]),
+    }
+}
+
+fn bitmap(attrs: &[u32]) -> bitmap4 {
+    let words = attrs.iter().max().map_or(0, |attr| attr / 32 + 1);
+    let mut bitmap = vec![0; words as usize];
+    for attr in attrs {
+        bitmap[(attr / 32) as usize] |= 1 << (attr % 32);
+    }
+    bitmap
+}
+
+async fn register_client(client: &mut Client) -> clientid4 {
+    let args = SETCLIENTID4args {
+        client: nfs_client_id4 {
+            verifier: [1; 8],
+            id: Opaque::borrowed(b"test client"),
+        },
+        callback: cb_client4 {
+            cb_program: 0x4000_0000,
+            cb_location: netaddr4 {
+                na_r_netid: Opaque::borrowed(b"tcp"),
+                na_r_addr: Opaque::borrowed(b"127.0.0.1.0.0"),
+            },
+        },
+        callback_ident: 1,
+    };
+    let res = compound(client, vec![nfs_argop4::OP_SETCLIENTID(args)]).await;
+    let nfs_resop4::OP_SETCLIENTID(Nfs4Result::Ok(resok)) = &res.resarray[0] else {
+        panic!("unexpected result: {res:?}");
+    };
+    let confirm = SETCLIENTID_CONFIRM4args {
+        clientid: resok.clientid,
+        setclientid_confirm: resok.setclientid_confirm,
+    };
+    let res = compound(client, vec![nfs_argop4::OP_SETCLIENTID_CONFIRM(confirm)]).await;
+    assert_eq!(res.status, nfsstat4::NFS4_OK);
+    resok.clientid
+}
+
+fn open<'a>(clientid: clientid4, name: &'a str, share_access: u32) -> nfs_argop4<'a> {
+    nfs_argop4::OP_OPEN(OPEN4args {
+        seqid: 0,
+        share_access,
+        share_deny: OPEN4_SHARE_DENY_NONE,
+        owner: open_owner4 {
+            clientid,
+            owner: Opaque::borrowed(b"owner"),
+        },
+        openhow: openflag4::OPEN4_CREATE(createhow4::UNCHECKED4(no_attrs())),
+        claim: open_claim4::CLAIM_NULL(Opaque::borrowed(name.as_bytes())),
+    })
+}
+
+fn read(stateid: stateid4) -> nfs_argop4<'static> {
+    nfs_argop4::OP_READ(READ4args {
+        stateid,
+        offset: 0,
+        count: 1024,
+    })
+}
+
+fn read_result(res: &nfs_resop4<'_>) -> (Vec<u8>, bool) {
+    let nfs_resop4::OP_READ(Nfs4Result::Ok(resok)) = res else {
+        panic!("unexpected result: {res:?}");
+    };
+    (resok.data.to_vec(), resok.eof)
+}
+
+async fn readdir(
+    client: &mut Client,
+    cookie: u64,
+    cookieverf: verifier4,
+    maxcount: u32,
+) -> READDIR4res<'static> {
+    let args = READDIR4args {
+        cookie,
+        cookieverf,
+        dircount: maxcount,
+        maxcount,
+        attr_request: bitmap(&[FATTR4_TYPE]),
+    };
+    let res = compound(
+        client,
+        vec![nfs_argop4::OP_PUTROOTFH(Void), nfs_argop4::OP_READDIR(args)],
+    )
+    .await;
+    let Some(nfs_resop4::OP_READDIR(res)) = res.resarray.into_iter().nth(1) else {
+        panic!("READDIR is not executed");
+    };
+    res
+}
+
+#[tokio::test]
+async fn null() {
+    let mut client = start_memfs();
+    let _: Void = client
+        .call(
+            nfs3::PROGRAM,
+            VERSION,
+            NFS4_PROGRAM::NFSPROC4_NULL as u32,
+            &Void,
+        )
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn root_attributes() {
+    let mut client = start_memfs();
+    let res = compound(
+        &mut client,
+        vec![
+            nfs_argop4::OP_PUTROOTFH(Void),
+            nfs_argop4::OP_GETFH(Void),
+            nfs_argop4::OP_GETATTR(GETATTR4args {
+                attr_request: bitmap(&[FATTR4_TYPE, FATTR4_ACL, FATTR4_MAXREAD]),
+            }),
+        ],
+    )
+    .await;
+    assert_eq!(res.status, nfsstat4::NFS4_OK);
+    assert_eq!(res.resarray.len(), 3);
+
+    let nfs_resop4::OP_GETFH(Nfs4Result::Ok(fh)) = &res.resarray[1] else {
+        panic!("unexpected result: {res:?}");
+    };
+    assert!(!fh.object.is_empty());
+
+    let nfs_resop4::OP_GETATTR(Nfs4Result::Ok(attrs)) = &res.resarray[2] else {
+        panic!("unexpected result: {res:?}");
+    };
+    // ACL isn't supported
+    assert_eq!(
+        attrs.obj_attributes.attrmask,
+        bitmap(&[FATTR4_TYPE, FATTR4_MAXREAD])
+    );
+    let mut expected = Vec::new();
+    (nfs_ftype4::NF4DIR as u32).pack(&mut expected).unwrap();
+    1_048_576u64.pack(&mut expected).unwrap();
+    assert_eq!(attrs.obj_attributes.attr_vals.as_ref(), expected);
+}
+
+#[tokio::test]
+async fn lookup_and_read() {
+    let mut client = start_memfs();
+    let res = compound(
+        &mut client,
+        vec![
+            nfs_argop4::OP_PUTROOTFH(Void),
+            lookup("a.txt"),
+            read(stateid4::default()),
+        ],
+    )
+    .await;
+    assert_eq!(res.status, nfsstat4::NFS4_OK);
+    assert_eq!(
+        read_result(&res.resarray[2]),
+        (b"hello world\n".to_vec(), true)
+    );
+
+    let res = compound(
+        &mut client,
+        vec![
+            nfs_argop4::OP_PUTROOTFH(Void),
+            nfs_argop4::OP_GETFH(Void),
+            lookup("dir"),
+            nfs_argop4::OP_LOOKUPP(Void),
+            nfs_argop4::OP_GETFH(Void),
+            read(stateid4::default()),
+        ],
+    )
+    .await;
+    assert_eq!(res.status, nfsstat4::NFS4ERR_ISDIR);
+    assert_eq!(res.resarray.len(), 6);
+    assert_eq!(
+        format!("{:?}", res.resarray[1]),
+        format!("{:?}", res.resarray[4])
+    );
+
+    let res = compound(
+        &mut client,
+        vec![
+            nfs_argop4::OP_PUTROOTFH(Void),
+            lookup("missing"),
+            nfs_argop4::OP_GETFH(Void),
+        ],
+    )
+    .await;
+    assert_eq!(res.status, nfsstat4::NFS4ERR_NOENT);
+    assert_eq!(res.resarray.len(), 2);
+
+    let res = compound(
+        &mut client,
+        vec![nfs_argop4::OP_PUTROOTFH(Void), lookup("..")],
+    )
+    .await;
+    assert_eq!(res.status, nfsstat4::NFS4ERR_BADNAME);
+
+    let res = compound(
+        &mut client,
+        vec![nfs_argop4::OP_PUTROOTFH(Void), nfs_argop4::OP_LOOKUPP(Void)],
+    )
+    .await;
+    assert_eq!(res.status, nfsstat4::NFS4ERR_NOENT);
+}
+
+#[tokio::test]
+async fn no_file_handle() {
+    let mut client = start_memfs();
+    let res = compound(&mut client, vec![nfs_argop4::OP_GETFH(Void)]).await;
+    assert_eq!(res.status, nfsstat4::NFS4ERR_NOFILEHANDLE);
+
+    let res = compound(
+        &mut client,
+        vec![nfs_argop4::OP_PUTFH(PUTFH4args {
+            object: Opaque::borrowed(&[0; 16]),
+        })],
+    )
+    .await;
+    assert!(matches!(
+        res.status,
+        nfsstat4::NFS4ERR_BADHANDLE | nfsstat4::NFS4ERR_STALE
+    ));
+}
+
+#[tokio::test]
+async fn open_write_read_close() {
+    let mut client = start_memfs();
+    let clientid = register_client(&mut client).await;
+
+    let res = compound(
+        &mut client,
+        vec![
+            nfs_argop4::OP_PUTROOTFH(Void),
+            open(clientid, "new.txt", OPEN4_SHARE_ACCESS_BOTH),
+            nfs_argop4::OP_GETFH(Void),
+        ],
+    )
+    .await;
+    assert_eq!(res.status, nfsstat4::NFS4_OK);
+    let nfs_resop4::OP_OPEN(Nfs4Result::Ok(opened)) = &res.resarray[1] else {
+        panic!("unexpected result: {res:?}");
+    };
+    assert_eq!(opened.rflags & OPEN4_RESULT_CONFIRM, 0);
+    let nfs_resop4::OP_GETFH(Nfs4Result::Ok(fh)) = &res.resarray[2] else {
+        panic!("unexpected result: {res:?}");
+    };
+    let stateid = opened.stateid;
+
+    let res = compound(
+        &mut client,
+        vec![
+            nfs_argop4::OP_PUTFH(PUTFH4args {
+                object: fh.object.clone(),
+            }),
+            nfs_argop4::OP_WRITE(WRITE4args {
+                stateid,
+                offset: 0,
+                stable: stable_how4::UNSTABLE4,
+                data: Opaque::borrowed(b"data"),
+            }),
+            read(stateid),
+            nfs_argop4::OP_COMMIT(COMMIT4args {
+                offset: 0,
+                count: 0,
+            }),
+        ],
+    )
+    .await;
+    assert_eq!(res.status, nfsstat4::NFS4_OK);
+    let nfs_resop4::OP_WRITE(Nfs4Result::Ok(written)) = &res.resarray[1] else {
+        panic!("unexpected result: {res:?}");
+    };
+    assert_eq!(written.count, 4);
+    assert_eq!(written.committed, stable_how4::FILE_SYNC4);
+    assert_eq!(read_result(&res.resarray[2]), (b"data".to_vec(), true));
+
+    let res = compound(
+        &mut client,
+        vec![
+            nfs_argop4::OP_PUTFH(PUTFH4args {
+                object: fh.object.clone(),
+            }),
+            nfs_argop4::OP_CLOSE(CLOSE4args {
+                seqid: 1,
+                open_stateid: stateid,
+            }),
+            read(stateid),
+        ],
+    )
+    .await;
+    assert_eq!(res.status, nfsstat4::NFS4ERR_BAD_STATEID);
+    assert_eq!(res.resarray[1].status(), nfsstat4::NFS4_OK);
+}
+
+#[tokio::test]
+async fn open_checks_access() {
+    let mut client = start_memfs();
+    let clientid = register_client(&mut client).await;
+
+    let res = compound(
+        &mut client,
+        vec![
+            nfs_argop4::OP_PUTROOTFH(Void),
+            open(clientid, "a.txt", OPEN4_SHARE_ACCESS_READ),
+        ],
+    )
+    .await;
+    let nfs_resop4::OP_OPEN(Nfs4Result::Ok(opened)) = &res.resarray[1] else {
+        panic!("unexpected result: {res:?}");
+    };
+    let res = compound(
+        &mut client,
+        vec![
+            nfs_argop4::OP_PUTROOTFH(Void),
+            lookup("a.txt"),
+            nfs_argop4::OP_WRITE(WRITE4args {
+                stateid: opened.stateid,
+                offset: 0,
+                stable: stable_how4::FILE_SYNC4,
+                data: Opaque::borrowed(b"data"),
+            }),
+        ],
+    )
+    .await;
+    assert_eq!(res.status, nfsstat4::NFS4ERR_OPENMODE);
+
+    // the stateid belongs to another file
+    let res = compound(
+        &mut client,
+        vec![
+            nfs_argop4::OP_PUTROOTFH(Void),
+            lookup("dir"),
+            lookup("b.txt"),
+            read(opened.stateid),
+        ],
+    )
+    .await;
+    assert_eq!(res.status, nfsstat4::NFS4ERR_BAD_STATEID);
+
+    let res = compound(
+        &mut client,
+        vec![
+            nfs_argop4::OP_PUTROOTFH(Void),
+            open(clientid, "empty", OPEN4_SHARE_ACCESS_READ),
+        ],
+    )
+    .await;
+    assert_eq!(res.status, nfsstat4::NFS4ERR_ISDIR);
+
+    let res = compound(
+        &mut client,
+        vec![
+            nfs_argop4::OP_PUTROOTFH(Void),
+            open(12345, "a.txt", OPEN4_SHARE_ACCESS_READ),
+        ],
+    )
+    .await;
+    assert_eq!(res.status, nfsstat4::NFS4ERR_STALE_CLIENTID);
+}
+
+#[tokio::test]
+async fn readdir_pages() {
+    let mut client = start_memfs();
+    let Nfs4Result::Ok(all) = readdir(&mut client, 0, [0; 8], 4096).await else {
+        panic!("READDIR failed");
+    };
+    assert!(all.reply.eof);
+    let mut names: Vec<_> = all
+        .reply
+        .entries
+        .0
+        .iter()
+        .map(|entry| String::from_utf8(entry.name.to_vec()).unwrap())
+        .collect();
+    let dir = names.iter().position(|name| name == "dir").unwrap();
+    names.sort();
+    assert_eq!(names, ["a.txt", "dir", "empty"]);
+    assert!(all.reply.entries.0.iter().all(|entry| entry.cookie > 2));
+    assert_eq!(
+        all.reply.entries.0[dir].attrs.attr_vals.as_ref(),
+        (nfs_ftype4::NF4DIR as u32).to_be_bytes()
+    );
+
+    // every page has a single entry
+    let mut cookie = 0;
+    let mut cookieverf = [0; 8];
+    let mut paged = Vec::new();
+    loop {
+        let Nfs4Result::Ok(page) = readdir(&mut client, cookie, cookieverf, 64).await else {
+            panic!("READDIR failed");
+        };
+        assert!(page.reply.entries.0.len() <= 1);
+        cookieverf = page.cookieverf;
+        if let Some(entry) = page.reply.entries.0.last() {
+            cookie = entry.cookie;
+            paged.push(entry.name.to_vec());
+        }
+        if page.reply.eof {
+            break;
+        }
+    }
+    assert_eq!(paged.len(), 3);
+
+    assert_eq!(
+        readdir(&mut client, 0, [0; 8], 8).await.status(),
+        nfsstat4::NFS4ERR_TOOSMALL
+    );
+    assert_eq!(
+        readdir(&mut client, 1, [0; 8], 4096).await.status(),
+        nfsstat4::NFS4ERR_BAD_COOKIE
+    );
+}
+
+#[tokio::test]
+async fn create_rename_remove() {
+    let mut client = start_memfs();
+    let res = compound(
+        &mut client,
+        vec![
+            nfs_argop4::OP_PUTROOTFH(Void),
+            nfs_argop4::OP_CREATE(CREATE4args {
+                objtype: createtype4::NF4DIR,
+                objname: Opaque::borrowed(b"new"),
+                createattrs: no_attrs(),
+            }),
+            nfs_argop4::OP_GETATTR(GETATTR4args {
+                attr_request: bitmap(&[FATTR4_TYPE]),
+            }),
+            nfs_argop4::OP_LOOKUPP(Void),
+            // MemFs doesn't support symlinks
+            nfs_argop4::OP_CREATE(CREATE4args {
+                objtype: createtype4::NF4LNK(Opaque::borrowed(b"a.txt")),
+                objname: Opaque::borrowed(b"link"),
+                createattrs: no_attrs(),
+            }),
+        ],
+    )
+    .await;
+    assert_eq!(res.status, nfsstat4::NFS4ERR_NOTSUPP);
+    assert_eq!(res.resarray.len(), 5);
+    let nfs_resop4::OP_GETATTR(Nfs4Result::Ok(attrs)) = &res.resarray[2] else {
+        panic!("unexpected result: {res:?}");
+    };
+    assert_eq!(
+        attrs.obj_attributes.attr_vals.as_ref(),
+        (nfs_ftype4::NF4DIR as u32).to_be_bytes()
+    );
+
+    let res = compound(
+        &mut client,
+        vec![
+            nfs_argop4::OP_PUTROOTFH(Void),
+            nfs_argop4::OP_CREATE(CREATE4args {
+                objtype: createtype4::NF4FIFO,
+                objname: Opaque::borrowed(b"fifo"),
+                createattrs: no_attrs(),
+            }),
+        ],
+    )
+    .await;
+    assert_eq!(res.status, nfsstat4::NFS4ERR_BADTYPE);
+
+    let res = compound(
+        &mut client,
+        vec![
+            nfs_argop4::OP_PUTROOTFH(Void),
+            nfs_argop4::OP_SAVEFH(Void),
+            lookup("new"),
+            nfs_argop4::OP_RENAME(RENAME4args {
+                oldname: Opaque::borrowed(b"a.txt"),
+                newname: Opaque::borrowed(b"moved.txt"),
+            }),
+            lookup("moved.txt"),
+            read(stateid4::default()),
+        ],
+    )
+    .await;
+    assert_eq!(res.status, nfsstat4::NFS4_OK);
+    assert_eq!(read_result(&res.resarray[5]).0, b"hello world\n");
+
+    let res = compound(
+        &mut client,
+        vec![
+            nfs_argop4::OP_PUTROOTFH(Void),
+            lookup("dir"),
+            nfs_argop4::OP_REMOVE(REMOVE4args {
+                target: Opaque::borrowed(b"b.txt"),
+            }),
+            lookup("b.txt"),
+        ],
+    )
+    .await;
+    assert_eq!(res.status, nfsstat4::NFS4ERR_NOENT);
+    assert_eq!(res.resarray[2].status(), nfsstat4::NFS4_OK);
+}
+
+#[tokio::test]
+async fn read_only_file_system() {
+    let mut client = start(ReadOnlyAdapter::new(MemFs::new(config()).unwrap()));
+    let clientid = register_client(&mut client).await;
+    let res = compound(
+        &mut client,
+        vec![
+            nfs_argop4::OP_PUTROOTFH(Void),
+            open(clientid, "a.txt", OPEN4_SHARE_ACCESS_WRITE),
+        ],
+    )
+    .await;
+    assert_eq!(res.status, nfsstat4::NFS4ERR_ROFS);
+
+    let res = compound(
+        &mut client,
+        vec![
+            nfs_argop4::OP_PUTROOTFH(Void),
+            nfs_argop4::OP_REMOVE(REMOVE4args {
+                target: Opaque::borrowed(b"a.txt"),
+            }),
+        ],
+    )
+    .await;
+    assert_eq!(res.status, nfsstat4::NFS4ERR_ROFS);
+}
+
+/// A request that is sent as is
+struct RawArgs(Vec<u8>);
+
+impl Pack for RawArgs {
+    fn packed_size(&self) -> usize {
+        self.0.len()
+    }
+
+    fn pack(&self, out: &mut impl Write) -> nfs3_client::nfs3_types::xdr_codec::Result<usize> {
+        out.write_all(&self.0).unwrap();
+        Ok(self.0.len())
+    }
+}
+
+#[tokio::test]
+async fn unsupported_operation() {
+    let mut client = start_memfs();
+    let mut args = Vec::new();
+    Opaque::borrowed(b"").pack(&mut args).unwrap();
+    0u32.pack(&mut args).unwrap();
+    3u32.pack(&mut args).unwrap();
+    (nfs_opnum4::OP_PUTROOTFH as u32).pack(&mut args).unwrap();
+    (nfs_opnum4::OP_LOCKT as u32).pack(&mut args).unwrap();
+    args.extend([0; 40]); // arguments of LOCKT
+    (nfs_opnum4::OP_GETFH as u32).pack(&mut args).unwrap();
+
+    let res: COMPOUND4res = client
+        .call(
+            nfs3::PROGRAM,
+            VERSION,
+            NFS4_PROGRAM::NFSPROC4_COMPOUND as u32,
+            &RawArgs(args),
+        )
+        .await
+        .unwrap();
+    assert_eq!(res.status, nfsstat4::NFS4ERR_NOTSUPP);
+    assert_eq!(res.resarray.len(), 2);
+    assert!(matches!(
+        res.resarray[1],
+        nfs_resop4::Unsupported(nfs_opnum4::OP_LOCKT, nfsstat4::NFS4ERR_NOTSUPP)
+    ));
+}
+
+#[tokio::test]
+async fn minor_version_mismatch() {
+    let mut client = start_memfs();
+    let args = COMPOUND4args {
+        tag: Opaque::borrowed(b""),
+        minorversion: 1,
+        argarray: vec![nfs_argop4::OP_PUTROOTFH(Void)],
+    };
+    let res: COMPOUND4res = client
+        .call(
+            nfs3::PROGRAM,
+            VERSION,
+            NFS4_PROGRAM::NFSPROC4_COMPOUND as u32,
+            &args,
+        )
+        .await
+        .unwrap();
+    assert_eq!(res.status, nfsstat4::NFS4ERR_MINOR_VERS_MISMATCH);
+    assert!(res.resarray.is_empty());
+}
@@ -0,0 +1,323 @@
+use nfs3_client::nfs3_types::mount::dirpath;
+use nfs3_client::nfs3_types::nfs3::{LOOKUP3args, diropargs3, filename3, nfs_fh3};
+use nfs3_client::nfs3_types::nlm::{
+    self, fsh4_access, fsh4_mode, nlm4_lock, nlm4_lockargs, nlm4_notify, nlm4_share,
+    nlm4_shareargs, nlm4_stats, nlm4_testargs, nlm4_testrply, nlm4_unlockargs,
+};
+use nfs3_client::nfs3_types::nsm::{self, stat_chge};
+use nfs3_client::nfs3_types::xdr_codec::Opaque;
+use nfs3_client::tokio::TokioIo;
+use nfs3_client::{MountClient, Nfs3Client, NlmClient, NsmClient, PortmapperClient};
+use nfs3_server::memfs::{MemFs, MemFsConfig};
+use nfs3_server::server::NFSServer;
+use tokio::io::{DuplexStream, duplex};
+
+type Io = TokioIo<DuplexStream>;
+
+fn server() -> NFSServer<MemFs> {
+    let mut config = MemFsConfig::default();
+    config.add_file("/a.txt", b"hello world\n");
+    let mut server = NFSServer::new(MemFs::new(config).unwrap());
+    server.set_local_port(2049);
+    server
+}
+
+fn connect(server: &NFSServer<MemFs>, name: &str) -> Io {
+    let (server_io, client_io) = duplex(1024 * 1024);
+    tokio::spawn(server.serve_connection(nfs3_server::tokio::TokioIo::new(server_io), name));
+    TokioIo::new(client_io)
+}
+
+async fn file_handle(server: &NFSServer<MemFs>) -> nfs_fh3 {
+    let mut mount = MountClient::new(connect(server, "mount"));
+    let resok = mount.mnt(dirpath(Opaque::borrowed(b"/"))).await.unwrap();
+    let root = nfs_fh3 {
+        data: resok.fhandle.0,
+    };
+
+    let mut nfs = Nfs3Client::new(connect(server, "nfs"));
+    let args = LOOKUP3args {
+        what: diropargs3 {
+            dir: root,
+            name: filename3(Opaque::borrowed(b"a.txt")),
+        },
+    };
+    nfs.lookup(&args).await.unwrap().unwrap().object
+}
+
+fn alock(
+    host: &str,
+    svid: i32,
+    fh: &nfs_fh3,
+    offset: u64,
+    len: u64,
+) -> nlm4_lock<'static, 'static, 'static> {
+    nlm4_lock {
+        caller_name: Opaque::owned(host.as_bytes().to_vec()),
+        fh: Opaque::owned(fh.data.to_vec()),
+        oh: Opaque::owned(format!("{svid}@{host}").into_bytes()),
+        svid,
+        l_offset: offset,
+        l_len: len,
+    }
+}
+
+fn lockargs(
+    alock: nlm4_lock<'static, 'static, 'static>,
+    exclusive: bool,
+    block: bool,
+) -> nlm4_lockargs<'static, 'static, 'static, 'static> {
+    nlm4_lockargs {
+        cookie: Opaque::owned(vec![1, 2, 3, 4]),
+        block,
+        exclusive,
+        alock,
+        reclaim: false,
+        state: 1,
+    }
+}
+
+async fn lock(client: &mut NlmClient<Io>, args: nlm4_lockargs<'_, '_, '_, '_>) -> nlm4_stats {
+    let res = client.lock(&args).await.unwrap();
+    assert_eq!(res.cookie, args.cookie);
+    res.stat.stat
+}
+
+#[tokio::test]
+async fn nlm_lock_conflicts() {
+    let server = server();
+    let fh = file_handle(&server).await;
+    let mut alice = NlmClient::new(connect(&server, "alice"));
+    let mut bob = NlmClient::new(connect(&server, "bob"));
+
+    let stat = lock(
+        &mut alice,
+        lockargs(alock("alice", 1, &fh, 0, 100), true, false),
+    )
+    .await;
+    assert_eq!(stat, nlm4_stats::NLM4_GRANTED);
+
+    // the conflicting lock is reported by TEST
+    let test = nlm4_testargs {
+        cookie: Opaque::owned(vec![5]),
+        exclusive: false,
+        alock: alock("bob", 2, &fh, 50, 0),
+    };
+    let res = bob.test(&test).await.unwrap();
+    let nlm4_testrply::Denied(holder) = res.stat else {
+        panic!("unexpected result: {:?}", res.stat);
+    };
+    assert!(holder.exclusive);
+    assert_eq!(holder.svid, 1);
+    assert_eq!((holder.l_offset, holder.l_len), (0, 100));
+
+    let stat = lock(
+        &mut bob,
+        lockargs(alock("bob", 2, &fh, 50, 0), false, false),
+    )
+    .await;
+    assert_eq!(stat, nlm4_stats::NLM4_DENIED);
+    // blocking requests wait for the lock
+    let stat = lock(&mut bob, lockargs(alock("bob", 2, &fh, 50, 0), false, true)).await;
+    assert_eq!(stat, nlm4_stats::NLM4_BLOCKED);
+    // locks outside of the locked range are granted
+    let stat = lock(
+        &mut bob,
+        lockargs(alock("bob", 2, &fh, 100, 0), true, false),
+    )
+    .await;
+    assert_eq!(stat, nlm4_stats::NLM4_GRANTED);
+
+    let unlock = nlm4_unlockargs {
+        cookie: Opaque::owned(vec![6]),
+        alock: alock("alice", 1, &fh, 0, 0),
+    };
+    let res = alice.unlock(&unlock).await.unwrap();
+    assert_eq!(res.stat.stat, nlm4_stats::NLM4_GRANTED);
+    // the blocked request is granted when the client polls again
+    let stat = lock(&mut bob, lockargs(alock("bob", 2, &fh, 50, 0), false, true)).await;
+    assert_eq!(stat, nlm4_stats::NLM4_GRANTED);
+    let stat = lock(
+        &mut bob,
+        lockargs(alock("bob", 2, &fh, 0, 100), true, false),
+    )
+    .await;
+    assert_eq!(stat, nlm4_stats::NLM4_GRANTED);
+
+    // a handle that wasn't issued by the server
+    let stale = nfs_fh3 {
+        data: Opaque::owned(vec![0; 16]),
+    };
+    let stat = lock(
+        &mut alice,
+        lockargs(alock("alice", 1, &stale, 0, 0), true, false),
+    )
+    .await;
+    assert_eq!(stat, nlm4_stats::NLM4_STALE_FH);
+}
+
+#[tokio::test]
+async fn nlm_share_and_free_all() {
+    let server = server();
+    let fh = file_handle(&server).await;
+    let mut client = NlmClient::new(connect(&server, "192.0.2.1:700"));
+
+    let share = |host: &str, mode, access| nlm4_shareargs {
+        cookie: Opaque::owned(vec![]),
+        share: nlm4_share {
+            caller_name: Opaque::owned(host.as_bytes().to_vec()),
+            fh: Opaque::owned(fh.data.to_vec()),
+            oh: Opaque::owned(b"oh".to_vec()),
+            mode,
+            access,
+        },
+        reclaim: false,
+    };
+
+    let res = client
+        .share(&share("alice", fsh4_mode::fsm_DW, fsh4_access::fsa_R))
+        .await
+        .unwrap();
+    assert_eq!(res.stat, nlm4_stats::NLM4_GRANTED);
+    let res = client
+        .share(&share("bob", fsh4_mode::fsm_DN, fsh4_access::fsa_RW))
+        .await
+        .unwrap();
+    assert_eq!(res.stat, nlm4_stats::NLM4_DENIED);
+
+    client
+        .free_all(&nlm4_notify {
+            name: Opaque::borrowed(b"alice"),
+            state: 2,
+        })
+        .await
+        .unwrap();
+    let res = client
+        .share(&share("bob", fsh4_mode::fsm_DN, fsh4_access::fsa_RW))
+        .await
+        .unwrap();
+    assert_eq!(res.stat, nlm4_stats::NLM4_GRANTED);
+    let res = client
+        .unshare(&share("bob", fsh4_mode::fsm_DN, fsh4_access::fsa_RW))
+        .await
+        .unwrap();
+    assert_eq!(res.stat, nlm4_stats::NLM4_GRANTED);
+}
+
+#[tokio::test]
+async fn nsm_notify_releases_locks() {
+    let server = server();
+    let fh = file_handle(&server).await;
+    let mut nlm = NlmClient::new(connect(&server, "192.0.2.1:700"));
+    let mut nsm = NsmClient::new(connect(&server, "192.0.2.1:800"));
+
+    let stat = lock(
+        &mut nlm,
+        lockargs(alock("alice", 1, &fh, 0, 0), true, false),
+    )
+    .await;
+    assert_eq!(stat, nlm4_stats::NLM4_GRANTED);
+
+    let res = nsm
+        .stat(&nsm::sm_name {
+            mon_name: Opaque::borrowed(b"alice"),
+        })
+        .await
+        .unwrap();
+    assert_eq!(res.res_stat, nsm::res::STAT_SUCC);
+    assert_eq!(res.state % 2, 1, "the server is up");
+
+    // alice rebooted
+    nsm.notify(&stat_chge {
+        mon_name: Opaque::borrowed(b"alice"),
+        state: 3,
+    })
+    .await
+    .unwrap();
+    let stat = lock(&mut nlm, lockargs(alock("bob", 2, &fh, 0, 0), true, false)).await;
+    assert_eq!(stat, nlm4_stats::NLM4_GRANTED);
+}
+
+#[tokio::test]
+async fn nsm_notify_checks_the_sender() {
+    let server = server();
+    let fh = file_handle(&server).await;
+    let mut nlm = NlmClient::new(connect(&server, "192.0.2.1:700"));
+
+    let stat = lock(
+        &mut nlm,
+        lockargs(alock("alice", 1, &fh, 0, 0), true, false),
+    )
+    .await;
+    assert_eq!(stat, nlm4_stats::NLM4_GRANTED);
+
+    // another host can't release the locks of alice
+    let notify = stat_chge {
+        mon_name: Opaque::borrowed(b"alice"),
+        state: 3,
+    };
+    let mut mallory = NsmClient::new(connect(&server, "192.0.2.2:800"));
+    mallory.notify(&notify).await.unwrap();
+    let mut mallory = NlmClient::new(connect(&server, "192.0.2.2:801"));
+    mallory
+        .free_all(&nlm4_notify {
+            name: Opaque::borrowed(b"alice"),
+            state: 3,
+        })
+        .await
+        .unwrap();
+    let stat = lock(&mut nlm, lockargs(alock("bob", 2, &fh, 0, 0), true, false)).await;
+    assert_eq!(stat, nlm4_stats::NLM4_DENIED);
+
+    // neither can a local process, nor a client that doesn't connect over IP
+    let mut local = NsmClient::new(connect(&server, "127.0.0.1:800"));
+    local.notify(&notify).await.unwrap();
+    let mut local = NsmClient::new(connect(&server, "local"));
+    local.notify(&notify).await.unwrap();
+    let stat = lock(&mut nlm, lockargs(alock("bob", 2, &fh, 0, 0), true, false)).await;
+    assert_eq!(stat, nlm4_stats::NLM4_DENIED);
+
+    // alice itself can, from any port
+    let mut nsm = NsmClient::new(connect(&server, "192.0.2.1:900"));
+    nsm.notify(&notify).await.unwrap();
+    let stat = lock(&mut nlm, lockargs(alock("bob", 2, &fh, 0, 0), true, false)).await;
+    assert_eq!(stat, nlm4_stats::NLM4_GRANTED);
+}
+
+#[tokio::test]
+async fn portmapper_registrations() {
+    let server = server();
+    let mut portmapper = PortmapperClient::new(connect(&server, "portmapper"));
+
+    assert_eq!(
+        portmapper
+            .getport(nlm::PROGRAM, nlm::VERSION)
+            .await
+            .unwrap(),
+        2049
+    );
+    assert_eq!(
+        portmapper
+            .getport(nsm::PROGRAM, nsm::VERSION)
+            .await
+            .unwrap(),
+        2049
+    );
+    let result = portmapper.getport(nlm::PROGRAM, 1).await;
+    assert!(
+        matches!(
+            result,
+            Err(nfs3_client::error::Error::Portmap(
+                nfs3_client::error::PortmapError::ProgramUnavailable
+            ))
+        ),
+        "unexpected result: {result:?}"
+    );
+
+    let mappings = portmapper.dump().await.unwrap();
+    assert!(
+        mappings
+            .iter()
+            .any(|m| m.prog == nlm::PROGRAM && m.vers == nlm::VERSION && m.port == 2049)
+    );
+}
@@ -0,0 +1,369 @@
+use std::collections::HashSet;
+
+use nfs3_client::nfs3_types::nfs3::{fattr3, filename3, ftype3, nfspath3, nfsstat3, sattr3};
+use nfs3_server::memfs::{MemFs, MemFsConfig};
+use nfs3_server::vfs::adapters::{
+    Fault, FaultInjectionAdapter, FaultRule, FsOperation, OverlayAdapter, OverlayHandle,
+};
+use nfs3_server::vfs::{
+    DirEntryPlus, FileHandle, FileHandleU64, NextResult, NfsFileSystem, NfsReadFileSystem,
+    ReadDirPlusIterator,
+};
+use nfs3_tests::vfs::{list, name, walk};
+
+type Overlay = OverlayAdapter<MemFs, MemFs>;
+type Handle = OverlayHandle<FileHandleU64, FileHandleU64>;
+
+fn overlay() -> Overlay {
+    let mut lower = MemFsConfig::default();
+    lower.add_file("/a.txt", b"lower a\n");
+    lower.add_dir("/dir");
+    lower.add_file("/dir/b.txt", b"lower b\n");
+    lower.add_dir("/dir/sub");
+    lower.add_file("/dir/sub/c.txt", b"lower c\n");
+    lower.add_dir("/many");
+    for i in 0..10 {
+        lower.add_file(&format!("/many/file{i}"), b"");
+    }
+    let mut upper = MemFsConfig::default();
+    upper.add_file("/u.txt", b"upper u\n");
+    OverlayAdapter::new(MemFs::new(upper).unwrap(), MemFs::new(lower).unwrap())
+}
+
+async fn read_all(fs: &Overlay, id: &Handle) -> Vec<u8> {
+    fs.read(id, 0, 1024).await.unwrap().0
+}
+
+#[tokio::test]
+async fn handles_record_the_layer() {
+    let fs = overlay();
+    let upper_file = walk(&fs, "/u.txt").await.unwrap();
+    assert!(upper_file.upper().is_some() && upper_file.lower().is_none());
+    let lower_file = walk(&fs, "/a.txt").await.unwrap();
+    assert!(lower_file.upper().is_none() && lower_file.lower().is_some());
+
+    let parsed = Handle::from_bytes(lower_file.as_bytes()).unwrap();
+    assert_eq!(parsed.as_bytes(), lower_file.as_bytes());
+    assert!(Handle::from_bytes(&[0]).is_none());
+
+    // the layers use the same ids, the overlay keeps them apart
+    let upper_id = fs.getattr(&upper_file).await.unwrap().fileid;
+    let lower_id = fs.getattr(&lower_file).await.unwrap().fileid;
+    assert_ne!(upper_id, lower_id);
+}
+
+#[tokio::test]
+async fn write_copies_up() {
+    let fs = overlay();
+    let file = walk(&fs, "/dir/sub/c.txt").await.unwrap();
+    let fileid = fs.getattr(&file).await.unwrap().fileid;
+
+    fs.write(&file, 0, b"UPPER").await.unwrap();
+    assert_eq!(read_all(&fs, &file).await, b"UPPER c\n");
+    assert_eq!(fs.getattr(&file).await.unwrap().fileid, fileid);
+
+    // the parents were created in the upper layer, the lower layer is intact
+    let upper = fs.upper();
+    let mut id = upper.root_dir();
+    for component in ["dir", "sub", "c.txt"] {
+        id = upper.lookup(&id, &name(component)).await.unwrap();
+    }
+    assert_eq!(upper.read(&id, 0, 100).await.unwrap().0, b"UPPER c\n");
+    let lower = fs.lower().lookup_by_path("/dir/sub/c.txt").await.unwrap();
+    assert_eq!(
+        fs.lower().read(&lower, 0, 100).await.unwrap().0,
+        b"lower c\n"
+    );
+
+    // a new lookup returns the copy with the same identity
+    let again = walk(&fs, "/dir/sub/c.txt").await.unwrap();
+    assert!(again.upper().is_some());
+    assert_eq!(fs.getattr(&again).await.unwrap().fileid, fileid);
+}
+
+#[tokio::test]
+async fn failed_copy_up_is_removed() {
+    let mut lower = MemFsConfig::default();
+    lower.add_file("/a.txt", b"lower a\n");
+    let mut upper = FaultInjectionAdapter::new(MemFs::new(MemFsConfig::default()).unwrap(), 0);
+    upper.add_rule(FaultRule::new(Fault::Error(nfsstat3::NFS3ERR_NOSPC)).on(FsOperation::Write));
+    let fs = OverlayAdapter::new(upper, MemFs::new(lower).unwrap());
+    let root = fs.root_dir();
+    let file = fs.lookup(&root, &name("a.txt")).await.unwrap();
+
+    assert_eq!(
+        fs.write(&file, 0, b"UPPER").await.err(),
+        Some(nfsstat3::NFS3ERR_NOSPC)
+    );
+    let upper = fs.upper().inner();
+    assert_eq!(
+        upper.lookup(&upper.root_dir(), &name("a.txt")).await,
+        Err(nfsstat3::NFS3ERR_NOENT)
+    );
+    let again = fs.lookup(&root, &name("a.txt")).await.unwrap();
+    assert!(again.upper().is_none());
+    assert_eq!(fs.read(&again, 0, 100).await.unwrap().0, b"lower a\n");
+}
+
+#[tokio::test]
+async fn setattr_copies_up() {
+    let fs = overlay();
+    let file = walk(&fs, "/a.txt").await.unwrap();
+    let attr = fs
+        .setattr(
+            &file,
+            sattr3 {
+                uid: nfs3_client::nfs3_types::nfs3::set_uid3::Some(1234),
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+    assert_eq!(attr.uid, 1234);
+    assert_eq!(
+        fs.getattr(&walk(&fs, "/a.txt").await.unwrap())
+            .await
+            .unwrap()
+            .uid,
+        1234
+    );
+    assert_eq!(read_all(&fs, &file).await, b"lower a\n");
+}
+
+#[tokio::test]
+async fn remove_leaves_a_whiteout() {
+    let fs = overlay();
+    let root = fs.root_dir();
+    fs.remove(&root, &name("a.txt")).await.unwrap();
+
+    assert_eq!(
+        walk(&fs, "/a.txt").await.err(),
+        Some(nfsstat3::NFS3ERR_NOENT)
+    );
+    assert_eq!(
+        walk(&fs, "/.wh.a.txt").await.err(),
+        Some(nfsstat3::NFS3ERR_NOENT)
+    );
+    let names = list(&fs, &root, 0).await;
+    assert!(
+        names
+            .iter()
+            .all(|(n, _)| n != "a.txt" && !n.starts_with(".wh."))
+    );
+    assert!(
+        fs.upper()
+            .lookup(&fs.upper().root_dir(), &name(".wh.a.txt"))
+            .await
+            .is_ok()
+    );
+
+    let res = fs.create(&root, &name(".wh.x"), sattr3::default()).await;
+    assert_eq!(res.err(), Some(nfsstat3::NFS3ERR_ACCES));
+
+    // a new file with the same name doesn't show the old content
+    let (file, _) = fs
+        .create(&root, &name("a.txt"), sattr3::default())
+        .await
+        .unwrap();
+    assert_eq!(read_all(&fs, &file).await, b"");
+    let file = walk(&fs, "/a.txt").await.unwrap();
+    assert_eq!(read_all(&fs, &file).await, b"");
+}
+
+#[tokio::test]
+async fn remove_merged_directory() {
+    let fs = overlay();
+    let dir = walk(&fs, "/dir").await.unwrap();
+    let sub = walk(&fs, "/dir/sub").await.unwrap();
+
+    assert_eq!(
+        fs.remove(&dir, &name("sub")).await,
+        Err(nfsstat3::NFS3ERR_NOTEMPTY)
+    );
+    fs.remove(&sub, &name("c.txt")).await.unwrap();
+    fs.remove(&dir, &name("sub")).await.unwrap();
+    assert_eq!(
+        walk(&fs, "/dir/sub").await.err(),
+        Some(nfsstat3::NFS3ERR_NOENT)
+    );
+
+    // the new directory doesn't show the lower content
+    let (sub, _) = fs.mkdir(&dir, &name("sub")).await.unwrap();
+    assert!(list(&fs, &sub, 0).await.is_empty());
+    assert_eq!(
+        walk(&fs, "/dir/sub/c.txt").await.err(),
+        Some(nfsstat3::NFS3ERR_NOENT)
+    );
+}
+
+#[tokio::test]
+async fn rename() {
+    let fs = overlay();
+    let root = fs.root_dir();
+    let dir = walk(&fs, "/dir").await.unwrap();
+    let fileid = fs
+        .getattr(&walk(&fs, "/a.txt").await.unwrap())
+        .await
+        .unwrap()
+        .fileid;
+
+    fs.rename(&root, &name("a.txt"), &dir, &name("b.txt"))
+        .await
+        .unwrap();
+    assert_eq!(
+        walk(&fs, "/a.txt").await.err(),
+        Some(nfsstat3::NFS3ERR_NOENT)
+    );
+    let moved = walk(&fs, "/dir/b.txt").await.unwrap();
+    assert_eq!(read_all(&fs, &moved).await, b"lower a\n");
+    assert_eq!(fs.getattr(&moved).await.unwrap().fileid, fileid);
+
+    assert_eq!(
+        fs.rename(&root, &name("dir"), &root, &name("dir2")).await,
+        Err(nfsstat3::NFS3ERR_XDEV)
+    );
+    let (new_dir, _) = fs.mkdir(&root, &name("new_dir")).await.unwrap();
+    fs.rename(&root, &name("new_dir"), &root, &name("new_dir2"))
+        .await
+        .unwrap();
+    let renamed = walk(&fs, "/new_dir2").await.unwrap();
+    assert_eq!(renamed.as_bytes(), new_dir.as_bytes());
+    assert_eq!(fs.getattr(&renamed).await.unwrap().type_, ftype3::NF3DIR);
+}
+
+#[tokio::test]
+async fn merged_listing_resumes_at_any_cookie() {
+    let fs = overlay();
+    let root = fs.root_dir();
+    fs.create(&root, &name("new.txt"), sattr3::default())
+        .await
+        .unwrap();
+    // copy up a lower file, it must be listed once
+    let a = walk(&fs, "/a.txt").await.unwrap();
+    fs.write(&a, 0, b"A").await.unwrap();
+
+    let full = list(&fs, &root, 0).await;
+    let names = full.iter().map(|(n, _)| n.as_str()).collect::<Vec<_>>();
+    let unique = names.iter().collect::<HashSet<_>>();
+    assert_eq!(names.len(), unique.len(), "{names:?}");
+    for expected in ["u.txt", "new.txt", "a.txt", "dir", "many"] {
+        assert!(names.contains(&expected), "{expected} in {names:?}");
+    }
+
+    for (i, (_, cookie)) in full.iter().enumerate() {
+        let rest = list(&fs, &root, *cookie).await;
+        assert_eq!(rest, full[i + 1..]);
+    }
+}
+
+/// A lower layer that lists "." and ".." like a real file system
+struct Dotted(MemFs);
+
+struct DottedIterator<I> {
+    dots: Vec<DirEntryPlus<FileHandleU64>>,
+    inner: I,
+}
+
+impl<I: ReadDirPlusIterator<FileHandleU64>> ReadDirPlusIterator<FileHandleU64>
+    for DottedIterator<I>
+{
+    async fn next(&mut self) -> NextResult<DirEntryPlus<FileHandleU64>> {
+        match self.dots.pop() {
+            Some(entry) => NextResult::Ok(entry),
+            None => self.inner.next().await,
+        }
+    }
+}
+
+impl NfsReadFileSystem for Dotted {
+    type Handle = FileHandleU64;
+
+    fn root_dir(&self) -> Self::Handle {
+        self.0.root_dir()
+    }
+
+    async fn lookup(
+        &self,
+        dirid: &Self::Handle,
+        filename: &filename3<'_>,
+    ) -> Result<Self::Handle, nfsstat3> {
+        self.0.lookup(dirid, filename).await
+    }
+
+    async fn getattr(&self, id: &Self::Handle) -> Result<fattr3, nfsstat3> {
+        self.0.getattr(id).await
+    }
+
+    async fn read(
+        &self,
+        id: &Self::Handle,
+        offset: u64,
+        count: u32,
+    ) -> Result<(Vec<u8>, bool), nfsstat3> {
+        self.0.read(id, offset, count).await
+    }
+
+    async fn readdirplus(
+        &self,
+        dirid: &Self::Handle,
+        cookie: u64,
+    ) -> Result<impl ReadDirPlusIterator<Self::Handle>, nfsstat3> {
+        let mut dots = Vec::new();
+        if cookie == 0 {
+            for dot in ["..", "."] {
+                let id = self.0.lookup(dirid, &name(dot)).await?;
+                let attr = self.0.getattr(&id).await?;
+                dots.push(DirEntryPlus {
+                    fileid: attr.fileid,
+                    name: filename3::from(dot.as_bytes()).clone_to_owned(),
+                    cookie: 0,
+                    name_attributes: Some(attr),
+                    name_handle: Some(id),
+                });
+            }
+        }
+        let inner = self.0.readdirplus(dirid, cookie).await?;
+        Ok(DottedIterator { dots, inner })
+    }
+
+    async fn readlink(&self, id: &Self::Handle) -> Result<nfspath3<'_>, nfsstat3> {
+        self.0.readlink(id).await
+    }
+}
+
+#[tokio::test]
+async fn dot_entries_match_lookup() {
+    let mut lower = MemFsConfig::default();
+    lower.add_dir("/dir");
+    lower.add_dir("/dir/sub");
+    let fs = OverlayAdapter::new(
+        MemFs::new(MemFsConfig::default()).unwrap(),
+        Dotted(MemFs::new(lower).unwrap()),
+    );
+    let root = fs.root_dir();
+    let dir = fs.lookup(&root, &name("dir")).await.unwrap();
+    // copy up "dir", "sub" stays in the lower layer only
+    fs.create(&dir, &name("new.txt"), sattr3::default())
+        .await
+        .unwrap();
+    let dir = fs.lookup(&root, &name("dir")).await.unwrap();
+    assert!(dir.upper().is_some() && dir.lower().is_some());
+    let sub = fs.lookup(&dir, &name("sub")).await.unwrap();
+    assert!(sub.upper().is_none());
+
+    let mut iter = fs.readdirplus(&sub, 0).await.unwrap();
+    let mut dots = Vec::new();
+    while let NextResult::Ok(entry) = iter.next().await {
+        dots.push(entry);
+    }
+    assert_eq!(dots.len(), 2);
+    for entry in dots {
+        let expected = fs.lookup(&sub, &entry.name).await.unwrap();
+        let handle = entry.name_handle.unwrap();
+        assert_eq!(handle.as_bytes(), expected.as_bytes());
+        assert_eq!(entry.fileid, fs.getattr(&expected).await.unwrap().fileid);
+    }
+    // ".." is the merged parent, not the bare lower directory
+    let parent = fs.lookup(&sub, &name("..")).await.unwrap();
+    assert_eq!(parent.as_bytes(), dir.as_bytes());
+}
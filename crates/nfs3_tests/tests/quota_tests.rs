@@ -0,0 +1,207 @@
+use nfs3_client::nfs3_types::nfs3::*;
+use nfs3_client::nfs3_types::rpc::auth_unix;
+use nfs3_server::memfs::{MemFs, MemFsConfig};
+use nfs3_server::vfs::adapters::{QuotaAdapter, QuotaLimits, QuotaUsage};
+use nfs3_server::vfs::{NfsFileSystem, NfsReadFileSystem, with_caller};
+use nfs3_tests::vfs::name;
+use nfs3_tests::{JustClientExt, TestContext};
+
+fn user(uid: u32) -> auth_unix {
+    auth_unix {
+        uid,
+        gid: uid,
+        ..Default::default()
+    }
+}
+
+fn memfs() -> MemFs {
+    let mut config = MemFsConfig::default();
+    config.add_dir("/scratch");
+    config.add_file("/scratch/old.bin", [0; 100]);
+    config.add_dir("/scratch/sub");
+    config.add_file("/scratch/sub/new.bin", [0; 20]);
+    config.add_dir("/home");
+    config.add_file("/top.txt", b"top");
+    MemFs::new(config).unwrap()
+}
+
+#[tokio::test]
+async fn user_limits_over_the_wire() {
+    let mut fs = QuotaAdapter::new(MemFs::new(MemFsConfig::default()).unwrap());
+    fs.set_uid_limits(
+        1000,
+        QuotaLimits {
+            bytes: Some(10),
+            inodes: Some(3),
+        },
+    );
+    let mut client = TestContext::serve_as(fs, &user(1000));
+    let root = client.root_dir().clone();
+
+    let file = client.just_create(&root, "a.bin", &[]).await.unwrap();
+
+    let mut write = WRITE3args {
+        file,
+        offset: 0,
+        count: 8,
+        stable: stable_how::FILE_SYNC,
+        data: vec![1; 8].into(),
+    };
+    assert!(matches!(
+        client.write(&write).await.unwrap(),
+        Nfs3Result::Ok(_)
+    ));
+    write.offset = 8;
+    write.count = 4;
+    write.data = vec![2; 4].into();
+    assert!(matches!(
+        client.write(&write).await.unwrap(),
+        Nfs3Result::Err((nfsstat3::NFS3ERR_DQUOT, _))
+    ));
+
+    let fsstat = client
+        .fsstat(&FSSTAT3args { fsroot: root })
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!((fsstat.tbytes, fsstat.fbytes, fsstat.abytes), (10, 2, 2));
+    assert_eq!((fsstat.tfiles, fsstat.ffiles, fsstat.afiles), (3, 2, 2));
+}
+
+#[tokio::test]
+async fn users_are_charged_separately() {
+    let mut fs = QuotaAdapter::new(MemFs::new(MemFsConfig::default()).unwrap());
+    fs.set_default_uid_limits(QuotaLimits {
+        bytes: None,
+        inodes: Some(1),
+    });
+    fs.set_uid_limits(0, QuotaLimits::default());
+    let root = fs.root_dir();
+
+    with_caller(user(1000), async {
+        fs.mkdir(&root, &name("alice")).await.unwrap();
+        let res = fs.create(&root, &name("a"), sattr3::default()).await;
+        assert_eq!(res.err(), Some(nfsstat3::NFS3ERR_DQUOT));
+    })
+    .await;
+    with_caller(user(2000), async {
+        fs.mkdir(&root, &name("bob")).await.unwrap();
+    })
+    .await;
+    with_caller(user(0), async {
+        for i in 0..3 {
+            fs.mkdir(&root, &name(&format!("root{i}"))).await.unwrap();
+        }
+    })
+    .await;
+
+    let usage = QuotaUsage {
+        bytes: 0,
+        inodes: 1,
+    };
+    assert_eq!(fs.uid_usage(1000), usage);
+    assert_eq!(fs.uid_usage(2000), usage);
+    assert_eq!(fs.uid_usage(0).inodes, 3);
+
+    // removing frees the quota
+    fs.remove(&root, &name("alice")).await.unwrap();
+    assert_eq!(fs.uid_usage(1000), QuotaUsage::default());
+    with_caller(user(1000), async {
+        fs.create(&root, &name("a"), sattr3::default())
+            .await
+            .unwrap();
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn tree_limits() {
+    let mut fs = QuotaAdapter::new(memfs());
+    fs.set_tree_limits(
+        "scratch",
+        QuotaLimits {
+            bytes: Some(150),
+            inodes: Some(5),
+        },
+    );
+    fs.scan().await.unwrap();
+    assert_eq!(
+        fs.tree_usage("scratch"),
+        QuotaUsage {
+            bytes: 120,
+            inodes: 4,
+        }
+    );
+    assert_eq!(
+        fs.tree_usage("home"),
+        QuotaUsage {
+            bytes: 0,
+            inodes: 1
+        }
+    );
+    let uid = fs
+        .getattr(&fs.lookup_by_path("/top.txt").await.unwrap())
+        .await
+        .unwrap()
+        .uid;
+    assert_eq!(fs.uid_usage(uid).inodes, 6);
+
+    let root = fs.root_dir();
+    let scratch = fs.lookup_by_path("/scratch").await.unwrap();
+    let sub = fs.lookup_by_path("/scratch/sub").await.unwrap();
+    let (file, _) = fs
+        .create(&sub, &name("c.bin"), sattr3::default())
+        .await
+        .unwrap();
+    assert_eq!(
+        fs.create(&scratch, &name("d.bin"), sattr3::default())
+            .await
+            .err(),
+        Some(nfsstat3::NFS3ERR_DQUOT)
+    );
+    fs.write(&file, 0, &[1; 30]).await.unwrap();
+    assert_eq!(
+        fs.write(&file, 30, &[1; 1]).await.err(),
+        Some(nfsstat3::NFS3ERR_DQUOT)
+    );
+    // overwriting doesn't need more space
+    fs.write(&file, 0, &[2; 30]).await.unwrap();
+
+    // truncating frees the space
+    let old = fs.lookup_by_path("/scratch/old.bin").await.unwrap();
+    fs.setattr(
+        &old,
+        sattr3 {
+            size: set_size3::Some(0),
+            ..Default::default()
+        },
+    )
+    .await
+    .unwrap();
+    assert_eq!(fs.tree_usage("scratch").bytes, 50);
+
+    // a file moved into the tree is charged to it, a directory can't be moved
+    assert_eq!(
+        fs.rename(&root, &name("top.txt"), &scratch, &name("top.txt"))
+            .await,
+        Err(nfsstat3::NFS3ERR_DQUOT)
+    );
+    fs.remove(&scratch, &name("old.bin")).await.unwrap();
+    fs.rename(&root, &name("top.txt"), &scratch, &name("top.txt"))
+        .await
+        .unwrap();
+    assert_eq!(
+        fs.tree_usage("scratch"),
+        QuotaUsage {
+            bytes: 53,
+            inodes: 5,
+        }
+    );
+    assert_eq!(
+        fs.rename(&scratch, &name("sub"), &root, &name("sub")).await,
+        Err(nfsstat3::NFS3ERR_XDEV)
+    );
+
+    let fsstat = fs.fsstat(&sub).await.unwrap();
+    assert_eq!((fsstat.fbytes, fsstat.ffiles), (97, 0));
+}
@@ -288,8 +288,9 @@ async fn invalid_nfs_version() -> anyhow::Result<()> {
     assert!(matches!(
         accepted.reply_data,
         nfs3_types::rpc::accept_stat_data::PROG_MISMATCH {
-            low: nfs3_types::nfs3::VERSION,
-            high: nfs3_types::nfs3::VERSION
+            low: nfs3_types::nfs2::VERSION,
+            // the tests enable the `nfs4` feature of the server
+            high: nfs3_types::nfs4::VERSION
         }
     ));
     assert!(body.is_none());
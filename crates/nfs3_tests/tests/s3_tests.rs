@@ -0,0 +1,307 @@
+use nfs3_client::nfs3_types::nfs3::*;
+use nfs3_server::s3fs::{Credentials, MIN_PART_SIZE, S3Config, S3Fs};
+use nfs3_server::vfs::{NfsFileSystem, NfsReadFileSystem};
+use nfs3_tests::s3::FakeS3;
+use nfs3_tests::vfs::{list, name, names, pattern};
+use nfs3_tests::{JustClientExt, TestContext};
+use tempfile::TempDir;
+
+const BUCKET: &str = "exports";
+
+async fn setup(objects: &[(&str, &[u8])]) -> (FakeS3, TempDir, S3Fs) {
+    let s3 = FakeS3::start(BUCKET).await;
+    for (key, data) in objects {
+        s3.put(key, data);
+    }
+    let staging = TempDir::new().unwrap();
+    let config = S3Config::new(s3.endpoint(), BUCKET)
+        .with_credentials(Credentials::new("AKIDEXAMPLE", "secret"))
+        .with_part_size(MIN_PART_SIZE)
+        .with_staging_dir(staging.path());
+    let fs = S3Fs::new(config).unwrap();
+    (s3, staging, fs)
+}
+
+#[tokio::test]
+async fn prefixes_as_directories() {
+    let (s3, _staging, fs) = setup(&[
+        ("docs/readme.txt", b"read me"),
+        ("docs/sub/deep.txt", b"deep"),
+        ("empty/", b""),
+        ("top.bin", &pattern(100_000, 1)),
+    ])
+    .await;
+
+    assert_eq!(names(&fs, "/").await, ["docs", "empty", "top.bin"]);
+    assert_eq!(names(&fs, "/docs").await, ["readme.txt", "sub"]);
+    assert!(names(&fs, "/empty").await.is_empty());
+
+    let root = fs.root_dir();
+    let docs = fs.lookup(&root, &name("docs")).await.unwrap();
+    assert!(matches!(
+        fs.getattr(&docs).await.unwrap().type_,
+        ftype3::NF3DIR
+    ));
+    assert_eq!(fs.lookup(&docs, &name("..")).await.unwrap(), root);
+    assert_eq!(
+        fs.lookup(&root, &name("missing")).await,
+        Err(nfsstat3::NFS3ERR_NOENT)
+    );
+
+    // reads fetch only the requested range
+    let top = fs.lookup(&root, &name("top.bin")).await.unwrap();
+    assert_eq!(fs.getattr(&top).await.unwrap().size, 100_000);
+    let (data, eof) = fs.read(&top, 99_000, 4096).await.unwrap();
+    assert_eq!(data, pattern(100_000, 1)[99_000..]);
+    assert!(eof);
+    let (data, eof) = fs.read(&top, 10, 20).await.unwrap();
+    assert_eq!(data, pattern(100_000, 1)[10..30]);
+    assert!(!eof);
+    assert_eq!(s3.count("GetObjectRange"), 2);
+    assert_eq!(s3.count("GetObject"), 0);
+
+    // the handles are derived from the keys
+    let (other_s3, _other_staging, other) = setup(&[("top.bin", b"")]).await;
+    let same = other
+        .lookup(&other.root_dir(), &name("top.bin"))
+        .await
+        .unwrap();
+    assert_eq!(same, top);
+    drop(other_s3);
+
+    let (new, _) = fs.mkdir(&root, &name("new")).await.unwrap();
+    fs.create(&new, &name("a.txt"), sattr3::default())
+        .await
+        .unwrap();
+    assert_eq!(s3.get("new/"), Some(Vec::new()));
+    assert_eq!(s3.get("new/a.txt"), Some(Vec::new()));
+    assert!(matches!(
+        fs.mkdir(&root, &name("top.bin")).await,
+        Err(nfsstat3::NFS3ERR_EXIST)
+    ));
+    assert_eq!(
+        fs.remove(&root, &name("docs")).await,
+        Err(nfsstat3::NFS3ERR_NOTEMPTY)
+    );
+    fs.remove(&root, &name("empty")).await.unwrap();
+
+    fs.rename(&root, &name("docs"), &new, &name("moved"))
+        .await
+        .unwrap();
+    fs.remove(&new, &name("a.txt")).await.unwrap();
+    assert_eq!(
+        s3.keys(),
+        [
+            "new/",
+            "new/moved/readme.txt",
+            "new/moved/sub/deep.txt",
+            "top.bin"
+        ]
+    );
+    assert_eq!(names(&fs, "/new/moved/sub").await, ["deep.txt"]);
+}
+
+#[tokio::test]
+async fn names_with_slashes_are_rejected() {
+    let (s3, _staging, fs) = setup(&[("docs/readme.txt", b"read me")]).await;
+    let root = fs.root_dir();
+    let inval = Some(nfsstat3::NFS3ERR_INVAL);
+
+    assert_eq!(
+        fs.lookup(&root, &name("docs/readme.txt")).await.err(),
+        inval
+    );
+    assert_eq!(
+        fs.create(&root, &name("docs/new.txt"), sattr3::default())
+            .await
+            .err(),
+        inval
+    );
+    assert_eq!(fs.mkdir(&root, &name("docs/sub")).await.err(), inval);
+    assert_eq!(
+        fs.remove(&root, &name("docs/readme.txt")).await,
+        Err(nfsstat3::NFS3ERR_INVAL)
+    );
+    assert_eq!(
+        fs.rename(&root, &name("docs"), &root, &name("docs/moved"))
+            .await,
+        Err(nfsstat3::NFS3ERR_INVAL)
+    );
+    assert_eq!(
+        fs.rename(&root, &name("docs/readme.txt"), &root, &name("moved.txt"))
+            .await,
+        Err(nfsstat3::NFS3ERR_INVAL)
+    );
+    assert_eq!(s3.keys(), ["docs/readme.txt"]);
+}
+
+#[tokio::test]
+async fn listing_pages() {
+    // more keys than fit in a page, with directories and a file that hides a directory
+    let mut objects = (0..1500)
+        .map(|i| (format!("many/f{i:04}"), Vec::new()))
+        .collect::<Vec<_>>();
+    for i in 0..20 {
+        for j in 0..60 {
+            objects.push((format!("many/f{:04}d/{j}", i * 70), Vec::new()));
+        }
+    }
+    objects.push(("many/hidden/x".to_string(), Vec::new()));
+    objects.push(("many/hidden".to_string(), b"file".to_vec()));
+    let objects = objects
+        .iter()
+        .map(|(key, data)| (key.as_str(), data.as_slice()))
+        .collect::<Vec<_>>();
+    let (_s3, _staging, fs) = setup(&objects).await;
+
+    let many = fs.lookup_by_path("/many").await.unwrap();
+    let names = list(&fs, &many, 0)
+        .await
+        .into_iter()
+        .map(|(name, _)| name)
+        .collect::<Vec<_>>();
+    assert_eq!(names.len(), 1500 + 20 + 1);
+    let mut sorted = names.clone();
+    sorted.sort();
+    sorted.dedup();
+    assert_eq!(sorted.len(), names.len());
+    let hidden = fs.lookup(&many, &name("hidden")).await.unwrap();
+    assert!(matches!(
+        fs.getattr(&hidden).await.unwrap().type_,
+        ftype3::NF3REG
+    ));
+
+    // resume after a file and after a directory
+    for resume in ["f0699", "f0700d"] {
+        let position = names.iter().position(|name| name == resume).unwrap();
+        let id = fs.lookup(&many, &name(resume)).await.unwrap();
+        let rest = list(&fs, &many, id.as_u64()).await;
+        let rest = rest.into_iter().map(|(name, _)| name).collect::<Vec<_>>();
+        assert_eq!(rest, names[position + 1..]);
+    }
+}
+
+#[tokio::test]
+async fn writes_are_staged_until_commit() {
+    let old = pattern(1000, 3);
+    let (s3, staging, fs) = setup(&[("data.bin", &old), ("small.txt", b"hello world")]).await;
+    let root = fs.root_dir();
+    let file = fs.lookup(&root, &name("data.bin")).await.unwrap();
+
+    // the existing contents are kept and the object is unchanged until the commit
+    let len = 2 * MIN_PART_SIZE as usize + 12345;
+    let mut expected = old.clone();
+    expected.resize(len, 0);
+    for offset in (500..len).step_by(1 << 20) {
+        let data = pattern((1 << 20).min(len - offset), 7);
+        let attr = fs.write(&file, offset as u64, &data).await.unwrap();
+        expected[offset..offset + data.len()].copy_from_slice(&data);
+        assert_eq!(attr.size, (offset + data.len()) as u64);
+    }
+    assert_eq!(s3.get("data.bin"), Some(old));
+    assert_eq!(fs.getattr(&file).await.unwrap().size, len as u64);
+    let (data, _) = fs.read(&file, 400, 200).await.unwrap();
+    assert_eq!(data, expected[400..600]);
+    assert_eq!(std::fs::read_dir(staging.path()).unwrap().count(), 1);
+
+    fs.commit(&file).await.unwrap();
+    assert_eq!(s3.get("data.bin"), Some(expected));
+    assert_eq!(s3.count("CreateMultipartUpload"), 1);
+    assert_eq!(s3.count("UploadPart"), 3);
+    assert_eq!(s3.count("CompleteMultipartUpload"), 1);
+    assert_eq!(s3.pending_uploads(), 0);
+    assert_eq!(std::fs::read_dir(staging.path()).unwrap().count(), 0);
+
+    // small files are uploaded with a single request, setting the size uploads right away
+    let small = fs.lookup(&root, &name("small.txt")).await.unwrap();
+    fs.write(&small, 6, b"there").await.unwrap();
+    fs.commit(&small).await.unwrap();
+    assert_eq!(s3.get("small.txt").unwrap(), b"hello there");
+    let attr = fs
+        .setattr(
+            &small,
+            sattr3 {
+                size: Nfs3Option::Some(5),
+                ..sattr3::default()
+            },
+        )
+        .await
+        .unwrap();
+    assert_eq!(attr.size, 5);
+    assert_eq!(s3.get("small.txt").unwrap(), b"hello");
+    assert_eq!(s3.count("CreateMultipartUpload"), 1);
+    let res = fs
+        .setattr(
+            &small,
+            sattr3 {
+                mode: Nfs3Option::Some(0o600),
+                ..sattr3::default()
+            },
+        )
+        .await;
+    assert!(matches!(res, Err(nfsstat3::NFS3ERR_NOTSUPP)));
+}
+
+#[tokio::test]
+async fn unstable_writes_over_nfs() {
+    let (s3, _staging, fs) = setup(&[]).await;
+    let mut client = TestContext::serve(fs);
+    let root = client.root_dir().clone();
+
+    let file = client.just_create(&root, "log.txt", &[]).await.unwrap();
+
+    let res = client
+        .write(&WRITE3args {
+            file: file.clone(),
+            offset: 0,
+            count: 6,
+            stable: stable_how::UNSTABLE,
+            data: b"first\n".as_slice().into(),
+        })
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(matches!(res.committed, stable_how::UNSTABLE));
+    assert_eq!(s3.get("log.txt"), Some(Vec::new()));
+
+    let res = client
+        .commit(&COMMIT3args {
+            file: file.clone(),
+            offset: 0,
+            count: 0,
+        })
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(s3.get("log.txt").unwrap(), b"first\n");
+    let Nfs3Option::Some(attr) = res.file_wcc.after else {
+        panic!("no attributes after the commit");
+    };
+    assert_eq!(attr.size, 6);
+
+    let res = client
+        .write(&WRITE3args {
+            file: file.clone(),
+            offset: 6,
+            count: 7,
+            stable: stable_how::FILE_SYNC,
+            data: b"second\n".as_slice().into(),
+        })
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(matches!(res.committed, stable_how::FILE_SYNC));
+    assert_eq!(s3.get("log.txt").unwrap(), b"first\nsecond\n");
+
+    let res = client
+        .read(&READ3args {
+            file,
+            offset: 3,
+            count: 100,
+        })
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(res.data.as_ref(), b"st\nsecond\n");
+    assert!(res.eof);
+}
@@ -0,0 +1,156 @@
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use nfs3_client::nfs3_types::nfs3::*;
+use nfs3_server::memfs::{MemFs, MemFsConfig};
+use nfs3_server::vfs::adapters::{CachingAdapter, FsOperation, FsRequest, HookLayer, Hooks, Stack};
+use nfs3_server::vfs::{FileHandle, NfsFileSystem, NfsReadFileSystem};
+use nfs3_tests::vfs::name;
+use nfs3_tests::{JustClientExt, TestContext};
+
+/// Records every request with its status
+#[derive(Default, Clone)]
+struct Audit(Arc<Mutex<Vec<(FsOperation, nfsstat3)>>>);
+
+impl Audit {
+    fn take(&self) -> Vec<(FsOperation, nfsstat3)> {
+        std::mem::take(&mut self.0.lock().unwrap())
+    }
+}
+
+impl Hooks for Audit {
+    async fn after<H: FileHandle>(&self, request: &FsRequest<'_, H>, status: nfsstat3) {
+        self.0.lock().unwrap().push((request.operation(), status));
+    }
+}
+
+/// Denies the changes of names that start with `locked`
+struct Lock;
+
+impl Hooks for Lock {
+    async fn before<H: FileHandle>(&self, request: &FsRequest<'_, H>) -> Result<(), nfsstat3> {
+        let name = match request {
+            FsRequest::Create { filename, .. } | FsRequest::Remove { filename, .. } => filename,
+            FsRequest::Mkdir { dirname, .. } => dirname,
+            FsRequest::Rename { from_filename, .. } => from_filename,
+            _ => return Ok(()),
+        };
+        if name.as_ref().starts_with(b"locked") {
+            return Err(nfsstat3::NFS3ERR_ACCES);
+        }
+        Ok(())
+    }
+}
+
+fn memfs() -> MemFs {
+    let mut config = MemFsConfig::default();
+    config.add_file("/locked.txt", b"keep");
+    config.add_file("/a.txt", b"hello");
+    MemFs::new(config).unwrap()
+}
+
+#[tokio::test]
+async fn hooks_run_in_stack_order() {
+    let outer = Audit::default();
+    let inner = Audit::default();
+    let fs = Stack::new(memfs())
+        .hooks(inner.clone())
+        .layer(|fs| CachingAdapter::new(fs, Duration::from_secs(60), 16))
+        .layer(HookLayer(Lock))
+        .hooks(outer.clone())
+        .into_inner();
+    let root = fs.root_dir();
+
+    let file = fs.lookup(&root, &name("a.txt")).await.unwrap();
+    fs.getattr(&file).await.unwrap();
+    fs.getattr(&file).await.unwrap();
+    assert_eq!(
+        outer.take(),
+        [
+            (FsOperation::Lookup, nfsstat3::NFS3_OK),
+            (FsOperation::Getattr, nfsstat3::NFS3_OK),
+            (FsOperation::Getattr, nfsstat3::NFS3_OK),
+        ]
+    );
+    // the cache answered the second GETATTR
+    let calls = inner.take();
+    assert_eq!(
+        calls
+            .iter()
+            .filter(|(op, _)| *op == FsOperation::Getattr)
+            .count(),
+        1,
+        "{calls:?}"
+    );
+
+    // the lock fails the request before it reaches the cache and the backend
+    assert_eq!(
+        fs.remove(&root, &name("locked.txt")).await,
+        Err(nfsstat3::NFS3ERR_ACCES)
+    );
+    assert_eq!(
+        outer.take(),
+        [(FsOperation::Remove, nfsstat3::NFS3ERR_ACCES)]
+    );
+    assert!(inner.take().is_empty());
+
+    fs.remove(&root, &name("a.txt")).await.unwrap();
+    assert_eq!(
+        fs.lookup(&root, &name("a.txt")).await.err(),
+        Some(nfsstat3::NFS3ERR_NOENT)
+    );
+    assert_eq!(
+        outer.take(),
+        [
+            (FsOperation::Remove, nfsstat3::NFS3_OK),
+            (FsOperation::Lookup, nfsstat3::NFS3ERR_NOENT),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn path_lookups_run_the_lookup_hooks() {
+    let audit = Audit::default();
+    let fs = Stack::new(memfs()).hooks(audit.clone()).into_inner();
+
+    fs.lookup_by_path("/a.txt").await.unwrap();
+    assert_eq!(
+        fs.lookup_by_path("/a.txt/b").await.err(),
+        Some(nfsstat3::NFS3ERR_NOTDIR)
+    );
+    assert_eq!(
+        audit.take(),
+        [
+            (FsOperation::Lookup, nfsstat3::NFS3_OK),
+            (FsOperation::LookupByPath, nfsstat3::NFS3_OK),
+            (FsOperation::Lookup, nfsstat3::NFS3_OK),
+            (FsOperation::Lookup, nfsstat3::NFS3ERR_NOTDIR),
+            (FsOperation::LookupByPath, nfsstat3::NFS3ERR_NOTDIR),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn stack_over_the_wire() {
+    let audit = Audit::default();
+    let fs = Stack::new(memfs())
+        .layer(HookLayer(Lock))
+        .hooks(audit.clone())
+        .into_inner();
+    let mut client = TestContext::serve(fs);
+    let root = client.root_dir().clone();
+
+    assert_eq!(
+        client.just_mkdir(&root, "locked-dir").await.err(),
+        Some(nfsstat3::NFS3ERR_ACCES)
+    );
+    assert!(client.just_mkdir(&root, "dir").await.is_ok());
+
+    let mkdirs = audit
+        .take()
+        .into_iter()
+        .filter(|(op, _)| *op == FsOperation::Mkdir)
+        .map(|(_, status)| status)
+        .collect::<Vec<_>>();
+    assert_eq!(mkdirs, [nfsstat3::NFS3ERR_ACCES, nfsstat3::NFS3_OK]);
+}
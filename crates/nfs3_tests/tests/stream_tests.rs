@@ -0,0 +1,97 @@
+use nfs3_client::nfs3_types::mount::dirpath;
+use nfs3_client::nfs3_types::nfs3::{GETATTR3args, Nfs3Result, nfs_fh3};
+use nfs3_client::nfs3_types::xdr_codec::Opaque;
+use nfs3_client::tokio::TokioIo;
+use nfs3_client::{MountClient, Nfs3Client};
+use nfs3_server::memfs::{MemFs, MemFsConfig};
+use nfs3_server::server::NFSServer;
+use nfs3_server::tokio::TokioIo as ServerIo;
+use tokio::io::{AsyncRead, AsyncWrite, duplex};
+
+fn server() -> NFSServer<MemFs> {
+    let mut config = MemFsConfig::default();
+    config.add_file("/a.txt", b"hello world\n");
+    NFSServer::new(MemFs::new(config).unwrap())
+}
+
+async fn mount<IO>(io: IO) -> nfs_fh3
+where
+    IO: AsyncRead + AsyncWrite + Unpin + Send,
+{
+    let mut mount = MountClient::new(TokioIo::new(io));
+    let resok = mount.mnt(dirpath(Opaque::borrowed(b"/"))).await.unwrap();
+    nfs_fh3 {
+        data: resok.fhandle.0,
+    }
+}
+
+async fn getattr<IO>(io: IO, object: nfs_fh3)
+where
+    IO: AsyncRead + AsyncWrite + Unpin + Send,
+{
+    let mut client = Nfs3Client::new(TokioIo::new(io));
+    let result = client.getattr(&GETATTR3args { object }).await.unwrap();
+    assert!(matches!(result, Nfs3Result::Ok(_)));
+}
+
+#[tokio::test]
+async fn serve_connection_over_duplex() {
+    let server = server();
+
+    let (server_io, client_io) = duplex(1024 * 1024);
+    let connection = tokio::spawn(server.serve_connection(ServerIo::new(server_io), "duplex-1"));
+    let root = mount(client_io).await;
+    connection.await.unwrap().unwrap();
+
+    // handles stay valid on other connections of the same server
+    let (server_io, client_io) = duplex(1024 * 1024);
+    let connection = tokio::spawn(server.serve_connection(ServerIo::new(server_io), "duplex-2"));
+    getattr(client_io, root).await;
+    connection.await.unwrap().unwrap();
+}
+
+#[test]
+fn serve_connection_on_smol() {
+    use nfs3_client::smol::SmolIo;
+    use nfs3_server::smol::{SmolIo as ServerIo, SmolRuntime};
+    use smol::net::{TcpListener, TcpStream};
+
+    smol::block_on(async {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let mut server = server();
+        server.with_runtime(SmolRuntime::new());
+        let connection = smol::spawn(async move {
+            let (stream, peer_addr) = listener.accept().await.unwrap();
+            let connection = server.serve_connection(ServerIo::new(stream), peer_addr.to_string());
+            connection.await
+        });
+
+        let stream = TcpStream::connect(addr).await.unwrap();
+        let mut mount = MountClient::new(SmolIo::new(stream));
+        let resok = mount.mnt(dirpath(Opaque::borrowed(b"/"))).await.unwrap();
+        assert!(!resok.fhandle.0.is_empty());
+        drop(mount);
+
+        connection.await.unwrap();
+    });
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn unix_listener() {
+    use nfs3_server::unix::NFSUnixListener;
+    use tokio::net::UnixStream;
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("nfs.sock");
+    let listener = NFSUnixListener::bind(&path, server()).unwrap();
+    let server = tokio::spawn(async move { listener.handle_forever().await });
+
+    let root = mount(UnixStream::connect(&path).await.unwrap()).await;
+    getattr(UnixStream::connect(&path).await.unwrap(), root).await;
+
+    server.abort();
+    let _ = server.await;
+    assert!(!path.exists(), "socket file is removed with the listener");
+}
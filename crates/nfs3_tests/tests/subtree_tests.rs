@@ -0,0 +1,274 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use nfs3_client::MountClient;
+use nfs3_client::nfs3_types::mount::dirpath;
+use nfs3_client::nfs3_types::nfs3::*;
+use nfs3_client::nfs3_types::xdr_codec::Opaque;
+use nfs3_client::tokio::TokioIo;
+use nfs3_server::memfs::{MemFs, MemFsConfig};
+use nfs3_server::server::NFSServer;
+use nfs3_server::tokio::TokioIo as ServerIo;
+use nfs3_server::vfs::adapters::SubtreeAdapter;
+use nfs3_server::vfs::{
+    DirEntryPlus, FileHandle, FileHandleU64, NextResult, NfsFileSystem, NfsReadFileSystem,
+    ReadDirPlusIterator,
+};
+use nfs3_tests::vfs::name;
+use nfs3_tests::{JustClientExt, TestContext};
+use tokio::io::duplex;
+
+fn tenants() -> MemFs {
+    let mut config = MemFsConfig::default();
+    config.add_dir("/tenants");
+    config.add_dir("/tenants/alice");
+    config.add_file("/tenants/alice/a.txt", b"alice\n");
+    config.add_dir("/tenants/alice/docs");
+    config.add_file("/tenants/alice/docs/b.txt", b"docs\n");
+    config.add_dir("/tenants/bob");
+    config.add_file("/tenants/bob/secret.txt", b"bob\n");
+    MemFs::new(config).unwrap()
+}
+
+async fn alice() -> SubtreeAdapter<MemFs> {
+    SubtreeAdapter::with_path(tenants(), "/tenants/alice")
+        .await
+        .unwrap()
+}
+
+/// A handle with a serial number, so that every lookup returns a new handle
+#[derive(Debug, Clone)]
+struct MintedHandle([u8; 16]);
+
+impl MintedHandle {
+    fn id(&self) -> FileHandleU64 {
+        FileHandleU64::from_bytes(&self.0[..8]).unwrap()
+    }
+}
+
+impl FileHandle for MintedHandle {
+    fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        bytes.try_into().ok().map(Self)
+    }
+}
+
+/// A file system that hands out a new handle for every lookup
+struct Minting {
+    inner: MemFs,
+    serial: AtomicU64,
+}
+
+impl Minting {
+    fn mint(&self, id: &FileHandleU64) -> MintedHandle {
+        let serial = self.serial.fetch_add(1, Ordering::Relaxed);
+        let mut bytes = [0; 16];
+        bytes[..8].copy_from_slice(id.as_bytes());
+        bytes[8..].copy_from_slice(&serial.to_be_bytes());
+        MintedHandle(bytes)
+    }
+}
+
+impl NfsReadFileSystem for Minting {
+    type Handle = MintedHandle;
+
+    fn root_dir(&self) -> Self::Handle {
+        MintedHandle::from_bytes(&[self.inner.root_dir().as_bytes(), &[0; 8]].concat()).unwrap()
+    }
+
+    async fn lookup(
+        &self,
+        dirid: &Self::Handle,
+        filename: &filename3<'_>,
+    ) -> Result<Self::Handle, nfsstat3> {
+        let id = self.inner.lookup(&dirid.id(), filename).await?;
+        Ok(self.mint(&id))
+    }
+
+    async fn getattr(&self, id: &Self::Handle) -> Result<fattr3, nfsstat3> {
+        self.inner.getattr(&id.id()).await
+    }
+
+    async fn read(
+        &self,
+        id: &Self::Handle,
+        offset: u64,
+        count: u32,
+    ) -> Result<(Vec<u8>, bool), nfsstat3> {
+        self.inner.read(&id.id(), offset, count).await
+    }
+
+    async fn readdirplus(
+        &self,
+        _dirid: &Self::Handle,
+        _cookie: u64,
+    ) -> Result<impl ReadDirPlusIterator<Self::Handle>, nfsstat3> {
+        Err::<MintingIterator, _>(nfsstat3::NFS3ERR_NOTSUPP)
+    }
+
+    async fn readlink(&self, id: &Self::Handle) -> Result<nfspath3<'_>, nfsstat3> {
+        self.inner.readlink(&id.id()).await
+    }
+}
+
+struct MintingIterator;
+
+impl ReadDirPlusIterator<MintedHandle> for MintingIterator {
+    async fn next(&mut self) -> NextResult<DirEntryPlus<MintedHandle>> {
+        NextResult::Eof
+    }
+}
+
+#[tokio::test]
+async fn lookup_by_path_starts_at_the_new_root() {
+    let fs = alice().await;
+    let root = fs.root_dir();
+    let root_id = fs.getattr(&root).await.unwrap().fileid;
+    let expected = fs.inner().lookup_by_path("/tenants/alice").await.unwrap();
+    assert_eq!(root.as_u64(), expected.as_u64());
+
+    let b = fs.lookup_by_path("/docs/b.txt").await.unwrap();
+    assert_eq!(fs.read(&b, 0, 100).await.unwrap().0, b"docs\n");
+
+    // `..` can't leave the subtree
+    for path in ["/..", "/../..", "/docs/../.."] {
+        let id = fs.lookup_by_path(path).await.unwrap();
+        assert_eq!(fs.getattr(&id).await.unwrap().fileid, root_id, "{path}");
+    }
+    assert_eq!(
+        fs.lookup_by_path("/../bob/secret.txt").await.err(),
+        Some(nfsstat3::NFS3ERR_NOENT)
+    );
+}
+
+#[tokio::test]
+async fn names_with_slashes_are_rejected() {
+    let fs = alice().await;
+    let root = fs.root_dir();
+
+    for path in ["../bob/secret.txt", "docs/b.txt"] {
+        assert_eq!(
+            fs.lookup(&root, &name(path)).await.err(),
+            Some(nfsstat3::NFS3ERR_INVAL),
+            "{path}"
+        );
+    }
+    assert_eq!(
+        fs.create(&root, &name("../bob/new.txt"), sattr3::default())
+            .await
+            .err(),
+        Some(nfsstat3::NFS3ERR_INVAL)
+    );
+    assert_eq!(
+        fs.mkdir(&root, &name("../bob/dir")).await.err(),
+        Some(nfsstat3::NFS3ERR_INVAL)
+    );
+    assert_eq!(
+        fs.remove(&root, &name("../bob/secret.txt")).await,
+        Err(nfsstat3::NFS3ERR_INVAL)
+    );
+    let bob = fs.inner().lookup_by_path("/tenants/bob").await.unwrap();
+    assert!(fs.inner().lookup(&bob, &name("secret.txt")).await.is_ok());
+    assert!(fs.inner().lookup(&bob, &name("new.txt")).await.is_err());
+}
+
+#[tokio::test]
+async fn dot_entries_stay_inside_with_new_handles() {
+    let fs = SubtreeAdapter::with_path(
+        Minting {
+            inner: tenants(),
+            serial: AtomicU64::new(1),
+        },
+        "/tenants/alice",
+    )
+    .await
+    .unwrap();
+    let root = fs.root_dir();
+    let root_id = fs.getattr(&root).await.unwrap().fileid;
+
+    let dot = fs.lookup(&root, &name(".")).await.unwrap();
+    assert_eq!(dot.as_bytes(), root.as_bytes());
+    for path in ["/./..", "/docs/..", "/docs/../..", "/docs/./../.."] {
+        let id = fs.lookup_by_path(path).await.unwrap();
+        assert_eq!(id.as_bytes(), root.as_bytes(), "{path}");
+        assert_eq!(fs.getattr(&id).await.unwrap().fileid, root_id, "{path}");
+    }
+    assert_eq!(
+        fs.lookup_by_path("/docs/../../bob/secret.txt").await.err(),
+        Some(nfsstat3::NFS3ERR_NOENT)
+    );
+
+    // FSSTAT and FSINFO describe the subtree, whatever object they are asked about
+    let docs = fs.lookup_by_path("/docs").await.unwrap();
+    let fsstat = fs.fsstat(&docs).await.unwrap();
+    assert!(matches!(fsstat.obj_attributes, post_op_attr::Some(attr) if attr.fileid == root_id));
+    let fsinfo = fs.fsinfo(&docs).await.unwrap();
+    assert!(matches!(fsinfo.obj_attributes, post_op_attr::Some(attr) if attr.fileid == root_id));
+}
+
+#[tokio::test]
+async fn root_must_be_a_directory() {
+    let mut config = MemFsConfig::default();
+    config.add_file("/a.txt", b"");
+    let fs = MemFs::new(config).unwrap();
+    let res = SubtreeAdapter::with_path(fs, "/a.txt").await;
+    assert!(matches!(res, Err(nfsstat3::NFS3ERR_NOTDIR)));
+}
+
+#[tokio::test]
+async fn served_subtree() {
+    let fs = alice().await;
+    let root_id = fs.getattr(&fs.root_dir()).await.unwrap().fileid;
+
+    let mut client = TestContext::serve(fs);
+    let root = client.root_dir().clone();
+
+    let parent = client.just_lookup(&root, "..").await.unwrap();
+    assert_eq!(client.just_getattr(&parent).await.unwrap().fileid, root_id);
+    assert!(client.just_lookup(&root, "a.txt").await.is_ok());
+    assert_eq!(
+        client.just_lookup(&parent, "bob").await,
+        Err(nfsstat3::NFS3ERR_NOENT)
+    );
+
+    let fsstat = client
+        .fsstat(&FSSTAT3args {
+            fsroot: root.clone(),
+        })
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(matches!(fsstat.obj_attributes, post_op_attr::Some(attr) if attr.fileid == root_id));
+
+    let fsinfo = client
+        .fsinfo(&FSINFO3args { fsroot: root })
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(matches!(fsinfo.obj_attributes, post_op_attr::Some(attr) if attr.fileid == root_id));
+}
+
+#[tokio::test]
+async fn mount_inside_the_subtree() {
+    let server = NFSServer::new(alice().await);
+
+    let (server_io, client_io) = duplex(1024 * 1024);
+    let connection = tokio::spawn(server.serve_connection(ServerIo::new(server_io), "duplex"));
+    let mut mount = MountClient::new(TokioIo::new(client_io));
+    let docs = mount
+        .mnt(dirpath(Opaque::borrowed(b"/docs")))
+        .await
+        .unwrap();
+    let outside = mount.mnt(dirpath(Opaque::borrowed(b"/../bob"))).await;
+    drop(mount);
+    connection.await.unwrap().unwrap();
+
+    assert!(!docs.fhandle.0.is_empty());
+    assert!(outside.is_err());
+}
@@ -0,0 +1,368 @@
+use std::sync::{Arc, Mutex};
+
+use nfs3_client::Nfs3ConnectionBuilder;
+use nfs3_client::nfs3_types::nfs3::{CREATE3args, GETATTR3args, createhow3, diropargs3};
+use nfs3_client::nfs3_types::rpc::opaque_auth;
+use nfs3_client::tls::TlsConnector;
+use nfs3_client::tokio::TokioConnector;
+use nfs3_server::memfs::{MemFs, MemFsConfig};
+use nfs3_server::nfs3_types::nfs3::{
+    createverf3, fattr3, filename3, nfspath3, nfsstat3, sattr3, set_gid3, set_uid3,
+};
+use nfs3_server::nfs3_types::rpc::auth_unix;
+use nfs3_server::tcp::{NFSTcp, NFSTcpListener};
+use nfs3_server::tls::{NFSTlsListener, TlsConfig};
+use nfs3_server::vfs::{
+    FileHandleU64, NfsFileSystem, NfsReadFileSystem, ReadDirPlusIterator, current_caller,
+};
+use rcgen::{BasicConstraints, CertificateParams, CertifiedIssuer, IsCa, KeyPair};
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, PrivatePkcs8KeyDer, ServerName};
+use rustls::server::WebPkiClientVerifier;
+use rustls::{ClientConfig, RootCertStore, ServerConfig};
+
+struct Pki {
+    ca: CertifiedIssuer<'static, KeyPair>,
+}
+
+impl Pki {
+    fn new() -> Self {
+        let mut params = CertificateParams::new(Vec::<String>::new()).unwrap();
+        params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+        let ca = CertifiedIssuer::self_signed(params, KeyPair::generate().unwrap()).unwrap();
+        Self { ca }
+    }
+
+    fn issue(&self, name: &str) -> (CertificateDer<'static>, PrivateKeyDer<'static>) {
+        let key = KeyPair::generate().unwrap();
+        let cert = CertificateParams::new(vec![name.to_owned()])
+            .unwrap()
+            .signed_by(&key, &self.ca)
+            .unwrap();
+        let key = PrivateKeyDer::Pkcs8(PrivatePkcs8KeyDer::from(key.serialize_der()));
+        (cert.der().clone(), key)
+    }
+
+    fn roots(&self) -> Arc<RootCertStore> {
+        let mut roots = RootCertStore::empty();
+        roots.add(self.ca.der().clone()).unwrap();
+        Arc::new(roots)
+    }
+}
+
+fn provider() -> Arc<rustls::crypto::CryptoProvider> {
+    Arc::new(rustls::crypto::ring::default_provider())
+}
+
+fn server_config(pki: &Pki, client_auth: bool) -> Arc<ServerConfig> {
+    let (cert, key) = pki.issue("localhost");
+    let builder = ServerConfig::builder_with_provider(provider())
+        .with_safe_default_protocol_versions()
+        .unwrap();
+    let builder = if client_auth {
+        let verifier = WebPkiClientVerifier::builder_with_provider(pki.roots(), provider())
+            .build()
+            .unwrap();
+        builder.with_client_cert_verifier(verifier)
+    } else {
+        builder.with_no_client_auth()
+    };
+    Arc::new(builder.with_single_cert(vec![cert], key).unwrap())
+}
+
+fn client_connector(
+    pki: &Pki,
+    client_cert: Option<(CertificateDer<'static>, PrivateKeyDer<'static>)>,
+) -> TlsConnector {
+    let builder = ClientConfig::builder_with_provider(provider())
+        .with_safe_default_protocol_versions()
+        .unwrap()
+        .with_root_certificates(pki.roots());
+    let config = match client_cert {
+        Some((cert, key)) => builder.with_client_auth_cert(vec![cert], key).unwrap(),
+        None => builder.with_no_client_auth(),
+    };
+    let server_name = ServerName::try_from("localhost").unwrap();
+    TlsConnector::new(Arc::new(config), server_name)
+}
+
+/// Gives the created files to the caller and remembers who the callers were
+struct Owned {
+    inner: MemFs,
+    callers: Arc<Mutex<Vec<(u32, u32)>>>,
+}
+
+impl NfsReadFileSystem for Owned {
+    type Handle = FileHandleU64;
+
+    fn root_dir(&self) -> Self::Handle {
+        self.inner.root_dir()
+    }
+
+    async fn lookup(
+        &self,
+        dirid: &Self::Handle,
+        filename: &filename3<'_>,
+    ) -> Result<Self::Handle, nfsstat3> {
+        self.inner.lookup(dirid, filename).await
+    }
+
+    async fn getattr(&self, id: &Self::Handle) -> Result<fattr3, nfsstat3> {
+        self.inner.getattr(id).await
+    }
+
+    async fn read(
+        &self,
+        id: &Self::Handle,
+        offset: u64,
+        count: u32,
+    ) -> Result<(Vec<u8>, bool), nfsstat3> {
+        self.inner.read(id, offset, count).await
+    }
+
+    async fn readdirplus(
+        &self,
+        dirid: &Self::Handle,
+        cookie: u64,
+    ) -> Result<impl ReadDirPlusIterator<Self::Handle>, nfsstat3> {
+        self.inner.readdirplus(dirid, cookie).await
+    }
+
+    async fn readlink(&self, id: &Self::Handle) -> Result<nfspath3<'_>, nfsstat3> {
+        self.inner.readlink(id).await
+    }
+}
+
+impl NfsFileSystem for Owned {
+    async fn setattr(&self, id: &Self::Handle, setattr: sattr3) -> Result<fattr3, nfsstat3> {
+        self.inner.setattr(id, setattr).await
+    }
+
+    async fn write(&self, id: &Self::Handle, offset: u64, data: &[u8]) -> Result<fattr3, nfsstat3> {
+        self.inner.write(id, offset, data).await
+    }
+
+    async fn create(
+        &self,
+        dirid: &Self::Handle,
+        filename: &filename3<'_>,
+        attr: sattr3,
+    ) -> Result<(Self::Handle, fattr3), nfsstat3> {
+        let caller = current_caller().ok_or(nfsstat3::NFS3ERR_ACCES)?;
+        self.callers.lock().unwrap().push((caller.uid, caller.gid));
+        let (id, _) = self.inner.create(dirid, filename, attr).await?;
+        let owner = sattr3 {
+            uid: set_uid3::Some(caller.uid),
+            gid: set_gid3::Some(caller.gid),
+            ..Default::default()
+        };
+        let attr = self.inner.setattr(&id, owner).await?;
+        Ok((id, attr))
+    }
+
+    async fn create_exclusive(
+        &self,
+        dirid: &Self::Handle,
+        filename: &filename3<'_>,
+        createverf: createverf3,
+    ) -> Result<Self::Handle, nfsstat3> {
+        self.inner
+            .create_exclusive(dirid, filename, createverf)
+            .await
+    }
+
+    async fn mkdir(
+        &self,
+        dirid: &Self::Handle,
+        dirname: &filename3<'_>,
+    ) -> Result<(Self::Handle, fattr3), nfsstat3> {
+        self.inner.mkdir(dirid, dirname).await
+    }
+
+    async fn remove(&self, dirid: &Self::Handle, filename: &filename3<'_>) -> Result<(), nfsstat3> {
+        self.inner.remove(dirid, filename).await
+    }
+
+    async fn rename<'a>(
+        &self,
+        from_dirid: &Self::Handle,
+        from_filename: &filename3<'a>,
+        to_dirid: &Self::Handle,
+        to_filename: &filename3<'a>,
+    ) -> Result<(), nfsstat3> {
+        self.inner
+            .rename(from_dirid, from_filename, to_dirid, to_filename)
+            .await
+    }
+
+    async fn symlink<'a>(
+        &self,
+        dirid: &Self::Handle,
+        linkname: &filename3<'a>,
+        symlink: &nfspath3<'a>,
+        attr: &sattr3,
+    ) -> Result<(Self::Handle, fattr3), nfsstat3> {
+        self.inner.symlink(dirid, linkname, symlink, attr).await
+    }
+}
+
+async fn start_tls_server(config: TlsConfig) -> u16 {
+    start_tls_server_with(MemFs::new(memfs_config()).unwrap(), config).await
+}
+
+async fn start_tls_server_with<T: NfsFileSystem + 'static>(fs: T, config: TlsConfig) -> u16 {
+    let listener = NFSTcpListener::bind("127.0.0.1:0", fs).await.unwrap();
+    let listener = NFSTlsListener::new(listener, config);
+    let port = listener.get_listen_port();
+    tokio::spawn(async move { listener.handle_forever().await });
+    port
+}
+
+fn memfs_config() -> MemFsConfig {
+    let mut config = MemFsConfig::default();
+    config.add_file("/a.txt", b"hello world\n");
+    config
+}
+
+async fn mount<C, S>(connector: C, port: u16) -> Result<(), nfs3_client::error::Error>
+where
+    C: nfs3_client::net::Connector<Connection = S>,
+    S: nfs3_client::io::AsyncRead + nfs3_client::io::AsyncWrite + Send,
+{
+    let mut connection = Nfs3ConnectionBuilder::new(connector, "127.0.0.1", "/")
+        .connect_from_privileged_port(false)
+        .mount_port(port)
+        .nfs3_port(port)
+        .mount()
+        .await?;
+    let root = connection.root_nfs_fh3();
+    connection
+        .getattr(&GETATTR3args { object: root })
+        .await?
+        .unwrap();
+    connection.unmount().await
+}
+
+#[tokio::test]
+async fn tls_mount() {
+    let pki = Pki::new();
+    let port = start_tls_server(TlsConfig::new(server_config(&pki, false))).await;
+
+    mount(client_connector(&pki, None), port).await.unwrap();
+}
+
+#[tokio::test]
+async fn tls_rejects_plaintext_client() {
+    let pki = Pki::new();
+    let port = start_tls_server(TlsConfig::new(server_config(&pki, false))).await;
+
+    let result = mount(TokioConnector, port).await;
+    assert!(
+        matches!(
+            result,
+            Err(nfs3_client::error::Error::Rpc(
+                nfs3_client::error::RpcError::Auth
+            ))
+        ),
+        "unexpected result: {result:?}"
+    );
+}
+
+#[tokio::test]
+async fn tls_client_rejects_plaintext_server() {
+    let pki = Pki::new();
+    let listener = NFSTcpListener::bind("127.0.0.1:0", MemFs::new(memfs_config()).unwrap())
+        .await
+        .unwrap();
+    let port = listener.get_listen_port();
+    tokio::spawn(async move { listener.handle_forever().await });
+
+    let result = mount(client_connector(&pki, None), port).await;
+    assert!(
+        matches!(&result, Err(nfs3_client::error::Error::Io(e)) if e.kind() == std::io::ErrorKind::Unsupported),
+        "unexpected result: {result:?}"
+    );
+}
+
+#[tokio::test]
+async fn tls_mutual_auth_maps_identity() {
+    let pki = Pki::new();
+    let (client_cert, client_key) = pki.issue("alice");
+
+    let expected_cert = client_cert.clone();
+    let config = TlsConfig::new(server_config(&pki, true)).with_identity_mapper(move |cert| {
+        (*cert == expected_cert).then(|| auth_unix {
+            uid: 1000,
+            gid: 1001,
+            ..Default::default()
+        })
+    });
+    let callers = Arc::new(Mutex::new(Vec::new()));
+    let fs = Owned {
+        inner: MemFs::new(memfs_config()).unwrap(),
+        callers: Arc::clone(&callers),
+    };
+    let port = start_tls_server_with(fs, config).await;
+
+    // the credentials sent by the client are replaced with the mapped identity
+    let root_user = auth_unix {
+        uid: 0,
+        gid: 0,
+        ..Default::default()
+    };
+    let mut connection = Nfs3ConnectionBuilder::new(
+        client_connector(&pki, Some((client_cert, client_key))),
+        "127.0.0.1",
+        "/",
+    )
+    .connect_from_privileged_port(false)
+    .mount_port(port)
+    .nfs3_port(port)
+    .credential(opaque_auth::auth_unix(&root_user))
+    .mount()
+    .await
+    .unwrap();
+    let root = connection.root_nfs_fh3();
+    let created = connection
+        .create(&CREATE3args {
+            where_: diropargs3 {
+                dir: root,
+                name: b"owned.txt".as_slice().into(),
+            },
+            how: createhow3::UNCHECKED(sattr3::default()),
+        })
+        .await
+        .unwrap()
+        .unwrap();
+    let attr = created.obj_attributes.unwrap();
+    assert_eq!((attr.uid, attr.gid), (1000, 1001));
+    assert_eq!(*callers.lock().unwrap(), [(1000, 1001)]);
+
+    let object = created.obj.unwrap();
+    let attr = connection
+        .getattr(&GETATTR3args { object })
+        .await
+        .unwrap()
+        .unwrap()
+        .obj_attributes;
+    assert_eq!((attr.uid, attr.gid), (1000, 1001));
+    connection.unmount().await.unwrap();
+
+    // a client without a certificate fails the handshake
+    let result = mount(client_connector(&pki, None), port).await;
+    assert!(result.is_err());
+}
+
+#[tokio::test]
+async fn tls_mutual_auth_unknown_identity() {
+    let pki = Pki::new();
+    let (client_cert, client_key) = pki.issue("mallory");
+    let config = TlsConfig::new(server_config(&pki, true)).with_identity_mapper(|_| None);
+    let port = start_tls_server(config).await;
+
+    let result = mount(
+        client_connector(&pki, Some((client_cert, client_key))),
+        port,
+    )
+    .await;
+    assert!(result.is_err());
+}
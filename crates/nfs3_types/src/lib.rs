@@ -1,9 +1,17 @@
+#![cfg_attr(docsrs, feature(doc_cfg))]
 #![doc = include_str!("../README.md")]
 
 extern crate self as nfs3_types;
 
 pub mod mount;
+pub mod nfs2;
 pub mod nfs3;
+#[cfg(feature = "nfs4")]
+#[cfg_attr(docsrs, doc(cfg(feature = "nfs4")))]
+pub mod nfs4;
+pub mod nfsacl;
+pub mod nlm;
+pub mod nsm;
 pub mod portmap;
 pub mod rpc;
 pub mod xdr_codec;
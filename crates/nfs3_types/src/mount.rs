@@ -5,13 +5,19 @@
 )]
 
 //! This module contains the definitions of the MOUNT3 protocol as defined in RFC 1813.
+//!
+//! Version 1 of the protocol, used together with `NFSv2`, is described in RFC 1094. It has
+//! the same procedures, only `MNT` returns [`fhstatus`] with a fixed size file handle.
 
 use std::io::{Read, Write};
 
+use crate::nfs2::fhandle2;
 use crate::xdr_codec::{List, Opaque, Pack, Unpack, XdrCodec};
 
 pub const PROGRAM: u32 = 100_005;
 pub const VERSION: u32 = 3;
+/// The version of the protocol used by `NFSv2` clients
+pub const VERSION_1: u32 = 1;
 pub const MNTPATHLEN: usize = 1024;
 pub const MNTNAMLEN: usize = 255;
 pub const FHSIZE3: usize = 64;
@@ -123,6 +129,45 @@ impl Unpack for mountres3<'_> {
     }
 }
 
+/// The result of `MNT` in version 1 of the protocol. The error codes are the same as
+/// in version 3.
+#[derive(Debug)]
+pub enum fhstatus {
+    Ok(fhandle2),
+    Err(mountstat3),
+}
+
+impl Pack for fhstatus {
+    fn packed_size(&self) -> usize {
+        match self {
+            Self::Ok(fhandle) => mountstat3::MNT3_OK.packed_size() + fhandle.packed_size(),
+            Self::Err(err) => err.packed_size(),
+        }
+    }
+
+    fn pack(&self, output: &mut impl Write) -> crate::xdr_codec::Result<usize> {
+        let len = match self {
+            Self::Ok(fhandle) => mountstat3::MNT3_OK.pack(output)? + fhandle.pack(output)?,
+            Self::Err(err) => err.pack(output)?,
+        };
+        Ok(len)
+    }
+}
+
+impl Unpack for fhstatus {
+    fn unpack(input: &mut impl Read) -> crate::xdr_codec::Result<(Self, usize)> {
+        let (stat, len) = mountstat3::unpack(input)?;
+        let (res, res_len) = match stat {
+            mountstat3::MNT3_OK => {
+                let (fhandle, fhandle_len) = fhandle2::unpack(input)?;
+                (Self::Ok(fhandle), fhandle_len)
+            }
+            _ => (Self::Err(stat), 0),
+        };
+        Ok((res, len + res_len))
+    }
+}
+
 #[derive(Debug, XdrCodec)]
 pub struct mountbody<'a, 'b> {
     pub ml_hostname: name<'a>,
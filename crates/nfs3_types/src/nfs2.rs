@@ -0,0 +1,443 @@
+#![allow(
+    non_camel_case_types,
+    clippy::large_enum_variant,
+    clippy::upper_case_acronyms
+)]
+
+//! This module contains the definitions of the NFS version 2 protocol as defined in RFC 1094.
+//!
+//! All sizes, offsets and file ids are 32-bit values, file handles have a fixed size of
+//! 32 bytes and directory cookies are 4 bytes long. The type names follow the RFC with
+//! a `2` suffix, the same way the `NFSv3` types carry a `3` suffix.
+
+use std::io::{Read, Write};
+
+use nfs3_macros::XdrCodec;
+
+use crate::nfs3::{ftype3, nfsstat3, nfstime3};
+use crate::xdr_codec::{List, Opaque, Pack, Unpack};
+
+pub const PROGRAM: u32 = 100_003;
+pub const VERSION: u32 = 2;
+
+/// The maximum number of bytes of data in a READ or WRITE request
+pub const NFS2_MAXDATA: u32 = 8192;
+/// The maximum number of bytes in a pathname argument
+pub const NFS2_MAXPATHLEN: usize = 1024;
+/// The maximum number of bytes in a file name argument
+pub const NFS2_MAXNAMLEN: usize = 255;
+/// The size in bytes of the opaque cookie passed between READDIR calls
+pub const NFS2_COOKIESIZE: usize = 4;
+/// The size in bytes of the opaque file handle
+pub const NFS2_FHSIZE: usize = 32;
+
+/// Value of a `sattr2` field that must not be changed
+pub const NFS2_SATTR_UNSET: u32 = u32::MAX;
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, XdrCodec)]
+#[repr(u32)]
+pub enum nfsstat2 {
+    #[default]
+    NFS_OK = 0,
+    NFSERR_PERM = 1,
+    NFSERR_NOENT = 2,
+    NFSERR_IO = 5,
+    NFSERR_NXIO = 6,
+    NFSERR_ACCES = 13,
+    NFSERR_EXIST = 17,
+    NFSERR_NODEV = 19,
+    NFSERR_NOTDIR = 20,
+    NFSERR_ISDIR = 21,
+    NFSERR_FBIG = 27,
+    NFSERR_NOSPC = 28,
+    NFSERR_ROFS = 30,
+    NFSERR_NAMETOOLONG = 63,
+    NFSERR_NOTEMPTY = 66,
+    NFSERR_DQUOT = 69,
+    NFSERR_STALE = 70,
+    NFSERR_WFLUSH = 99,
+}
+
+impl std::fmt::Display for nfsstat2 {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        std::fmt::Debug::fmt(self, f)
+    }
+}
+
+impl From<nfsstat3> for nfsstat2 {
+    fn from(stat: nfsstat3) -> Self {
+        match stat {
+            nfsstat3::NFS3_OK => Self::NFS_OK,
+            nfsstat3::NFS3ERR_PERM => Self::NFSERR_PERM,
+            nfsstat3::NFS3ERR_NOENT => Self::NFSERR_NOENT,
+            nfsstat3::NFS3ERR_NXIO => Self::NFSERR_NXIO,
+            nfsstat3::NFS3ERR_ACCES | nfsstat3::NFS3ERR_XDEV => Self::NFSERR_ACCES,
+            nfsstat3::NFS3ERR_EXIST => Self::NFSERR_EXIST,
+            nfsstat3::NFS3ERR_NODEV => Self::NFSERR_NODEV,
+            nfsstat3::NFS3ERR_NOTDIR => Self::NFSERR_NOTDIR,
+            nfsstat3::NFS3ERR_ISDIR => Self::NFSERR_ISDIR,
+            nfsstat3::NFS3ERR_FBIG => Self::NFSERR_FBIG,
+            nfsstat3::NFS3ERR_NOSPC => Self::NFSERR_NOSPC,
+            nfsstat3::NFS3ERR_ROFS => Self::NFSERR_ROFS,
+            nfsstat3::NFS3ERR_NAMETOOLONG => Self::NFSERR_NAMETOOLONG,
+            nfsstat3::NFS3ERR_NOTEMPTY => Self::NFSERR_NOTEMPTY,
+            nfsstat3::NFS3ERR_DQUOT => Self::NFSERR_DQUOT,
+            nfsstat3::NFS3ERR_STALE | nfsstat3::NFS3ERR_BADHANDLE => Self::NFSERR_STALE,
+            nfsstat3::NFS3ERR_IO
+            | nfsstat3::NFS3ERR_INVAL
+            | nfsstat3::NFS3ERR_MLINK
+            | nfsstat3::NFS3ERR_REMOTE
+            | nfsstat3::NFS3ERR_NOT_SYNC
+            | nfsstat3::NFS3ERR_BAD_COOKIE
+            | nfsstat3::NFS3ERR_NOTSUPP
+            | nfsstat3::NFS3ERR_TOOSMALL
+            | nfsstat3::NFS3ERR_SERVERFAULT
+            | nfsstat3::NFS3ERR_BADTYPE
+            | nfsstat3::NFS3ERR_JUKEBOX => Self::NFSERR_IO,
+        }
+    }
+}
+
+/// The result of a procedure which carries data only on success
+#[derive(Debug, PartialEq, Eq)]
+pub enum Nfs2Result<T> {
+    Ok(T),
+    Err(nfsstat2),
+}
+
+impl<T: std::fmt::Debug> Nfs2Result<T> {
+    /// Returns the status of the procedure
+    pub const fn status(&self) -> nfsstat2 {
+        match self {
+            Self::Ok(_) => nfsstat2::NFS_OK,
+            Self::Err(stat) => *stat,
+        }
+    }
+
+    /// Returns the contained value, consuming the result.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the result is an `Err`.
+    pub fn unwrap(self) -> T {
+        match self {
+            Self::Ok(val) => val,
+            Self::Err(stat) => panic!("NFS2 error: {stat}"),
+        }
+    }
+}
+
+impl<T> From<Result<T, nfsstat2>> for Nfs2Result<T> {
+    fn from(result: Result<T, nfsstat2>) -> Self {
+        match result {
+            Ok(val) => Self::Ok(val),
+            Err(stat) => Self::Err(stat),
+        }
+    }
+}
+
+impl<T: Pack> Pack for Nfs2Result<T> {
+    fn packed_size(&self) -> usize {
+        match self {
+            Self::Ok(v) => nfsstat2::NFS_OK.packed_size() + v.packed_size(),
+            Self::Err(stat) => stat.packed_size(),
+        }
+    }
+
+    fn pack(&self, out: &mut impl Write) -> crate::xdr_codec::Result<usize> {
+        let len = match self {
+            Self::Ok(v) => nfsstat2::NFS_OK.pack(out)? + v.pack(out)?,
+            Self::Err(stat) => stat.pack(out)?,
+        };
+        Ok(len)
+    }
+}
+
+impl<T: Unpack> Unpack for Nfs2Result<T> {
+    fn unpack(input: &mut impl Read) -> crate::xdr_codec::Result<(Self, usize)> {
+        let (stat, len) = nfsstat2::unpack(input)?;
+        if stat == nfsstat2::NFS_OK {
+            let (val, val_len) = T::unpack(input)?;
+            Ok((Self::Ok(val), len + val_len))
+        } else {
+            Ok((Self::Err(stat), len))
+        }
+    }
+}
+
+/// File types. `NFSOCK`, `NFBAD` and `NFFIFO` are not part of RFC 1094, but they are
+/// used by the later revisions of the protocol description.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, XdrCodec)]
+#[repr(u32)]
+pub enum ftype2 {
+    NFNON = 0,
+    #[default]
+    NFREG = 1,
+    NFDIR = 2,
+    NFBLK = 3,
+    NFCHR = 4,
+    NFLNK = 5,
+    NFSOCK = 6,
+    NFBAD = 7,
+    NFFIFO = 8,
+}
+
+impl From<ftype3> for ftype2 {
+    fn from(ftype: ftype3) -> Self {
+        match ftype {
+            ftype3::NF3REG => Self::NFREG,
+            ftype3::NF3DIR => Self::NFDIR,
+            ftype3::NF3BLK => Self::NFBLK,
+            ftype3::NF3CHR => Self::NFCHR,
+            ftype3::NF3LNK => Self::NFLNK,
+            ftype3::NF3SOCK => Self::NFSOCK,
+            ftype3::NF3FIFO => Self::NFFIFO,
+        }
+    }
+}
+
+/// The file handle passed between the server and the client
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, XdrCodec)]
+pub struct fhandle2(pub [u8; NFS2_FHSIZE]);
+
+/// Time in seconds and microseconds since midnight January 1, 1970
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, XdrCodec)]
+pub struct timeval2 {
+    pub seconds: u32,
+    pub useconds: u32,
+}
+
+impl From<nfstime3> for timeval2 {
+    fn from(time: nfstime3) -> Self {
+        Self {
+            seconds: time.seconds,
+            useconds: time.nseconds / 1000,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, XdrCodec)]
+pub struct fattr2 {
+    pub type_: ftype2,
+    pub mode: u32,
+    pub nlink: u32,
+    pub uid: u32,
+    pub gid: u32,
+    pub size: u32,
+    pub blocksize: u32,
+    pub rdev: u32,
+    pub blocks: u32,
+    pub fsid: u32,
+    pub fileid: u32,
+    pub atime: timeval2,
+    pub mtime: timeval2,
+    pub ctime: timeval2,
+}
+
+/// Settable attributes. A field set to [`NFS2_SATTR_UNSET`] is not changed.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, XdrCodec)]
+pub struct sattr2 {
+    pub mode: u32,
+    pub uid: u32,
+    pub gid: u32,
+    pub size: u32,
+    pub atime: timeval2,
+    pub mtime: timeval2,
+}
+
+impl Default for sattr2 {
+    fn default() -> Self {
+        let unset = timeval2 {
+            seconds: NFS2_SATTR_UNSET,
+            useconds: NFS2_SATTR_UNSET,
+        };
+        Self {
+            mode: NFS2_SATTR_UNSET,
+            uid: NFS2_SATTR_UNSET,
+            gid: NFS2_SATTR_UNSET,
+            size: NFS2_SATTR_UNSET,
+            atime: unset,
+            mtime: unset,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, XdrCodec)]
+pub struct filename2<'a>(pub Opaque<'a>);
+
+#[derive(Clone, Debug, PartialEq, Eq, XdrCodec)]
+pub struct nfspath2<'a>(pub Opaque<'a>);
+
+pub type nfscookie2 = [u8; NFS2_COOKIESIZE];
+
+pub type attrstat2 = Nfs2Result<fattr2>;
+
+#[derive(Debug, XdrCodec)]
+pub struct SETATTR2args {
+    pub file: fhandle2,
+    pub attributes: sattr2,
+}
+
+#[derive(Debug, XdrCodec)]
+pub struct diropargs2<'a> {
+    pub dir: fhandle2,
+    pub name: filename2<'a>,
+}
+
+#[derive(Debug, XdrCodec)]
+pub struct DIROP2resok {
+    pub file: fhandle2,
+    pub attributes: fattr2,
+}
+
+pub type DIROP2res = Nfs2Result<DIROP2resok>;
+
+pub type READLINK2res<'a> = Nfs2Result<nfspath2<'a>>;
+
+#[derive(Debug, XdrCodec)]
+pub struct READ2args {
+    pub file: fhandle2,
+    pub offset: u32,
+    pub count: u32,
+    /// Unused
+    pub totalcount: u32,
+}
+
+#[derive(Debug, XdrCodec)]
+pub struct READ2resok<'a> {
+    pub attributes: fattr2,
+    pub data: Opaque<'a>,
+}
+
+pub type READ2res<'a> = Nfs2Result<READ2resok<'a>>;
+
+#[derive(Debug, XdrCodec)]
+pub struct WRITE2args<'a> {
+    pub file: fhandle2,
+    /// Unused
+    pub beginoffset: u32,
+    pub offset: u32,
+    /// Unused
+    pub totalcount: u32,
+    pub data: Opaque<'a>,
+}
+
+#[derive(Debug, XdrCodec)]
+pub struct CREATE2args<'a> {
+    pub where_: diropargs2<'a>,
+    pub attributes: sattr2,
+}
+
+#[derive(Debug, XdrCodec)]
+pub struct RENAME2args<'a, 'b> {
+    pub from: diropargs2<'a>,
+    pub to: diropargs2<'b>,
+}
+
+#[derive(Debug, XdrCodec)]
+pub struct LINK2args<'a> {
+    pub from: fhandle2,
+    pub to: diropargs2<'a>,
+}
+
+#[derive(Debug, XdrCodec)]
+pub struct SYMLINK2args<'a, 'b> {
+    pub from: diropargs2<'a>,
+    pub to: nfspath2<'b>,
+    pub attributes: sattr2,
+}
+
+#[derive(Debug, XdrCodec)]
+pub struct READDIR2args {
+    pub dir: fhandle2,
+    pub cookie: nfscookie2,
+    pub count: u32,
+}
+
+#[derive(Debug, XdrCodec)]
+pub struct entry2<'a> {
+    pub fileid: u32,
+    pub name: filename2<'a>,
+    pub cookie: nfscookie2,
+}
+
+#[derive(Debug, XdrCodec)]
+pub struct READDIR2resok<'a> {
+    pub entries: List<entry2<'a>>,
+    pub eof: bool,
+}
+
+pub type READDIR2res<'a> = Nfs2Result<READDIR2resok<'a>>;
+
+#[derive(Debug, XdrCodec)]
+pub struct STATFS2resok {
+    /// The optimum transfer size of the server in bytes
+    pub tsize: u32,
+    /// The block size in bytes of the file system
+    pub bsize: u32,
+    /// The total number of `bsize` blocks on the file system
+    pub blocks: u32,
+    /// The number of free `bsize` blocks
+    pub bfree: u32,
+    /// The number of `bsize` blocks available to non-privileged users
+    pub bavail: u32,
+}
+
+pub type STATFS2res = Nfs2Result<STATFS2resok>;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, XdrCodec)]
+#[repr(u32)]
+pub enum NFS_PROGRAM {
+    NFSPROC_NULL = 0,
+    NFSPROC_GETATTR = 1,
+    NFSPROC_SETATTR = 2,
+    NFSPROC_ROOT = 3,
+    NFSPROC_LOOKUP = 4,
+    NFSPROC_READLINK = 5,
+    NFSPROC_READ = 6,
+    NFSPROC_WRITECACHE = 7,
+    NFSPROC_WRITE = 8,
+    NFSPROC_CREATE = 9,
+    NFSPROC_REMOVE = 10,
+    NFSPROC_RENAME = 11,
+    NFSPROC_LINK = 12,
+    NFSPROC_SYMLINK = 13,
+    NFSPROC_MKDIR = 14,
+    NFSPROC_RMDIR = 15,
+    NFSPROC_READDIR = 16,
+    NFSPROC_STATFS = 17,
+}
+
+impl std::convert::TryFrom<u32> for NFS_PROGRAM {
+    type Error = crate::xdr_codec::Error;
+
+    fn try_from(value: u32) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(Self::NFSPROC_NULL),
+            1 => Ok(Self::NFSPROC_GETATTR),
+            2 => Ok(Self::NFSPROC_SETATTR),
+            3 => Ok(Self::NFSPROC_ROOT),
+            4 => Ok(Self::NFSPROC_LOOKUP),
+            5 => Ok(Self::NFSPROC_READLINK),
+            6 => Ok(Self::NFSPROC_READ),
+            7 => Ok(Self::NFSPROC_WRITECACHE),
+            8 => Ok(Self::NFSPROC_WRITE),
+            9 => Ok(Self::NFSPROC_CREATE),
+            10 => Ok(Self::NFSPROC_REMOVE),
+            11 => Ok(Self::NFSPROC_RENAME),
+            12 => Ok(Self::NFSPROC_LINK),
+            13 => Ok(Self::NFSPROC_SYMLINK),
+            14 => Ok(Self::NFSPROC_MKDIR),
+            15 => Ok(Self::NFSPROC_RMDIR),
+            16 => Ok(Self::NFSPROC_READDIR),
+            17 => Ok(Self::NFSPROC_STATFS),
+            _ => Err(crate::xdr_codec::Error::InvalidEnumValue(value)),
+        }
+    }
+}
+
+impl std::fmt::Display for NFS_PROGRAM {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        std::fmt::Debug::fmt(self, f)
+    }
+}
@@ -191,7 +191,7 @@ pub struct COMMIT3args {
     pub count: count3,
 }
 
-#[derive(Debug, XdrCodec)]
+#[derive(Debug, Default, XdrCodec)]
 pub struct COMMIT3resfail {
     pub file_wcc: wcc_data,
 }
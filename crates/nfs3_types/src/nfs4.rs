@@ -0,0 +1,1124 @@
+#![allow(
+    non_camel_case_types,
+    clippy::large_enum_variant,
+    clippy::upper_case_acronyms
+)]
+
+//! This module contains the definitions of the NFS version 4.0 protocol.
+//!
+//! The protocol is described in RFC 7530 and its XDR description in RFC 7531. All requests
+//! are sent as a COMPOUND of operations. Only the operations needed to access files and to
+//! manage the client state are defined. Other operations are decoded as
+//! [`nfs_argop4::Unsupported`] without their arguments, which ends the decoding of the
+//! COMPOUND, so a server can still reply with `NFS4ERR_NOTSUPP`.
+
+use std::io::{Read, Write};
+
+use nfs3_macros::XdrCodec;
+
+use crate::nfs3::nfsstat3;
+use crate::xdr_codec::{Error, List, Opaque, Pack, Unpack, Void};
+
+pub const PROGRAM: u32 = 100_003;
+pub const VERSION: u32 = 4;
+
+pub const NFS4_FHSIZE: usize = 128;
+pub const NFS4_VERIFIER_SIZE: usize = 8;
+pub const NFS4_OTHER_SIZE: usize = 12;
+pub const NFS4_OPAQUE_LIMIT: usize = 1024;
+
+// Bits of `ACCESS4args::access`
+pub const ACCESS4_READ: u32 = 0x0001;
+pub const ACCESS4_LOOKUP: u32 = 0x0002;
+pub const ACCESS4_MODIFY: u32 = 0x0004;
+pub const ACCESS4_EXTEND: u32 = 0x0008;
+pub const ACCESS4_DELETE: u32 = 0x0010;
+pub const ACCESS4_EXECUTE: u32 = 0x0020;
+
+// Values of `OPEN4args::share_access` and `OPEN4args::share_deny`
+pub const OPEN4_SHARE_ACCESS_READ: u32 = 0x0001;
+pub const OPEN4_SHARE_ACCESS_WRITE: u32 = 0x0002;
+pub const OPEN4_SHARE_ACCESS_BOTH: u32 = 0x0003;
+pub const OPEN4_SHARE_DENY_NONE: u32 = 0x0000;
+pub const OPEN4_SHARE_DENY_READ: u32 = 0x0001;
+pub const OPEN4_SHARE_DENY_WRITE: u32 = 0x0002;
+pub const OPEN4_SHARE_DENY_BOTH: u32 = 0x0003;
+
+// Bits of `OPEN4resok::rflags`
+pub const OPEN4_RESULT_CONFIRM: u32 = 0x0002;
+pub const OPEN4_RESULT_LOCKTYPE_POSIX: u32 = 0x0004;
+
+// Values of the `fh_expire_type` attribute
+pub const FH4_PERSISTENT: u32 = 0x00;
+pub const FH4_NOEXPIRE_WITH_OPEN: u32 = 0x01;
+pub const FH4_VOLATILE_ANY: u32 = 0x02;
+pub const FH4_VOL_MIGRATION: u32 = 0x04;
+pub const FH4_VOL_RENAME: u32 = 0x08;
+
+// Attribute numbers, i.e. bits of `bitmap4`
+pub const FATTR4_SUPPORTED_ATTRS: u32 = 0;
+pub const FATTR4_TYPE: u32 = 1;
+pub const FATTR4_FH_EXPIRE_TYPE: u32 = 2;
+pub const FATTR4_CHANGE: u32 = 3;
+pub const FATTR4_SIZE: u32 = 4;
+pub const FATTR4_LINK_SUPPORT: u32 = 5;
+pub const FATTR4_SYMLINK_SUPPORT: u32 = 6;
+pub const FATTR4_NAMED_ATTR: u32 = 7;
+pub const FATTR4_FSID: u32 = 8;
+pub const FATTR4_UNIQUE_HANDLES: u32 = 9;
+pub const FATTR4_LEASE_TIME: u32 = 10;
+pub const FATTR4_RDATTR_ERROR: u32 = 11;
+pub const FATTR4_ACL: u32 = 12;
+pub const FATTR4_ACLSUPPORT: u32 = 13;
+pub const FATTR4_ARCHIVE: u32 = 14;
+pub const FATTR4_CANSETTIME: u32 = 15;
+pub const FATTR4_CASE_INSENSITIVE: u32 = 16;
+pub const FATTR4_CASE_PRESERVING: u32 = 17;
+pub const FATTR4_CHOWN_RESTRICTED: u32 = 18;
+pub const FATTR4_FILEHANDLE: u32 = 19;
+pub const FATTR4_FILEID: u32 = 20;
+pub const FATTR4_FILES_AVAIL: u32 = 21;
+pub const FATTR4_FILES_FREE: u32 = 22;
+pub const FATTR4_FILES_TOTAL: u32 = 23;
+pub const FATTR4_FS_LOCATIONS: u32 = 24;
+pub const FATTR4_HIDDEN: u32 = 25;
+pub const FATTR4_HOMOGENEOUS: u32 = 26;
+pub const FATTR4_MAXFILESIZE: u32 = 27;
+pub const FATTR4_MAXLINK: u32 = 28;
+pub const FATTR4_MAXNAME: u32 = 29;
+pub const FATTR4_MAXREAD: u32 = 30;
+pub const FATTR4_MAXWRITE: u32 = 31;
+pub const FATTR4_MIMETYPE: u32 = 32;
+pub const FATTR4_MODE: u32 = 33;
+pub const FATTR4_NO_TRUNC: u32 = 34;
+pub const FATTR4_NUMLINKS: u32 = 35;
+pub const FATTR4_OWNER: u32 = 36;
+pub const FATTR4_OWNER_GROUP: u32 = 37;
+pub const FATTR4_QUOTA_AVAIL_HARD: u32 = 38;
+pub const FATTR4_QUOTA_AVAIL_SOFT: u32 = 39;
+pub const FATTR4_QUOTA_USED: u32 = 40;
+pub const FATTR4_RAWDEV: u32 = 41;
+pub const FATTR4_SPACE_AVAIL: u32 = 42;
+pub const FATTR4_SPACE_FREE: u32 = 43;
+pub const FATTR4_SPACE_TOTAL: u32 = 44;
+pub const FATTR4_SPACE_USED: u32 = 45;
+pub const FATTR4_SYSTEM: u32 = 46;
+pub const FATTR4_TIME_ACCESS: u32 = 47;
+pub const FATTR4_TIME_ACCESS_SET: u32 = 48;
+pub const FATTR4_TIME_BACKUP: u32 = 49;
+pub const FATTR4_TIME_CREATE: u32 = 50;
+pub const FATTR4_TIME_DELTA: u32 = 51;
+pub const FATTR4_TIME_METADATA: u32 = 52;
+pub const FATTR4_TIME_MODIFY: u32 = 53;
+pub const FATTR4_TIME_MODIFY_SET: u32 = 54;
+pub const FATTR4_MOUNTED_ON_FILEID: u32 = 55;
+
+/// A set of attributes. Attribute `n` is bit `n % 32` of word `n / 32`.
+pub type bitmap4 = Vec<u32>;
+pub type attrlist4<'a> = Opaque<'a>;
+pub type nfs_fh4<'a> = Opaque<'a>;
+pub type component4<'a> = Opaque<'a>;
+pub type linktext4<'a> = Opaque<'a>;
+pub type utf8str_cs<'a> = Opaque<'a>;
+pub type verifier4 = [u8; NFS4_VERIFIER_SIZE];
+pub type changeid4 = u64;
+pub type clientid4 = u64;
+pub type count4 = u32;
+pub type length4 = u64;
+pub type nfs_cookie4 = u64;
+pub type offset4 = u64;
+pub type seqid4 = u32;
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, XdrCodec)]
+#[repr(u32)]
+pub enum nfsstat4 {
+    #[default]
+    NFS4_OK = 0,
+    NFS4ERR_PERM = 1,
+    NFS4ERR_NOENT = 2,
+    NFS4ERR_IO = 5,
+    NFS4ERR_NXIO = 6,
+    NFS4ERR_ACCESS = 13,
+    NFS4ERR_EXIST = 17,
+    NFS4ERR_XDEV = 18,
+    NFS4ERR_NOTDIR = 20,
+    NFS4ERR_ISDIR = 21,
+    NFS4ERR_INVAL = 22,
+    NFS4ERR_FBIG = 27,
+    NFS4ERR_NOSPC = 28,
+    NFS4ERR_ROFS = 30,
+    NFS4ERR_MLINK = 31,
+    NFS4ERR_NAMETOOLONG = 63,
+    NFS4ERR_NOTEMPTY = 66,
+    NFS4ERR_DQUOT = 69,
+    NFS4ERR_STALE = 70,
+    NFS4ERR_BADHANDLE = 10001,
+    NFS4ERR_BAD_COOKIE = 10003,
+    NFS4ERR_NOTSUPP = 10004,
+    NFS4ERR_TOOSMALL = 10005,
+    NFS4ERR_SERVERFAULT = 10006,
+    NFS4ERR_BADTYPE = 10007,
+    NFS4ERR_DELAY = 10008,
+    NFS4ERR_SAME = 10009,
+    NFS4ERR_DENIED = 10010,
+    NFS4ERR_EXPIRED = 10011,
+    NFS4ERR_LOCKED = 10012,
+    NFS4ERR_GRACE = 10013,
+    NFS4ERR_FHEXPIRED = 10014,
+    NFS4ERR_SHARE_DENIED = 10015,
+    NFS4ERR_WRONGSEC = 10016,
+    NFS4ERR_CLID_INUSE = 10017,
+    NFS4ERR_RESOURCE = 10018,
+    NFS4ERR_MOVED = 10019,
+    NFS4ERR_NOFILEHANDLE = 10020,
+    NFS4ERR_MINOR_VERS_MISMATCH = 10021,
+    NFS4ERR_STALE_CLIENTID = 10022,
+    NFS4ERR_STALE_STATEID = 10023,
+    NFS4ERR_OLD_STATEID = 10024,
+    NFS4ERR_BAD_STATEID = 10025,
+    NFS4ERR_BAD_SEQID = 10026,
+    NFS4ERR_NOT_SAME = 10027,
+    NFS4ERR_LOCK_RANGE = 10028,
+    NFS4ERR_SYMLINK = 10029,
+    NFS4ERR_RESTOREFH = 10030,
+    NFS4ERR_LEASE_MOVED = 10031,
+    NFS4ERR_ATTRNOTSUPP = 10032,
+    NFS4ERR_NO_GRACE = 10033,
+    NFS4ERR_RECLAIM_BAD = 10034,
+    NFS4ERR_RECLAIM_CONFLICT = 10035,
+    NFS4ERR_BADXDR = 10036,
+    NFS4ERR_LOCKS_HELD = 10037,
+    NFS4ERR_OPENMODE = 10038,
+    NFS4ERR_BADOWNER = 10039,
+    NFS4ERR_BADCHAR = 10040,
+    NFS4ERR_BADNAME = 10041,
+    NFS4ERR_BAD_RANGE = 10042,
+    NFS4ERR_LOCK_NOTSUPP = 10043,
+    NFS4ERR_OP_ILLEGAL = 10044,
+    NFS4ERR_DEADLOCK = 10045,
+    NFS4ERR_FILE_OPEN = 10046,
+    NFS4ERR_ADMIN_REVOKED = 10047,
+    NFS4ERR_CB_PATH_DOWN = 10048,
+}
+
+impl std::fmt::Display for nfsstat4 {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        std::fmt::Debug::fmt(self, f)
+    }
+}
+
+impl From<nfsstat3> for nfsstat4 {
+    fn from(stat: nfsstat3) -> Self {
+        match stat {
+            nfsstat3::NFS3_OK => Self::NFS4_OK,
+            nfsstat3::NFS3ERR_PERM => Self::NFS4ERR_PERM,
+            nfsstat3::NFS3ERR_NOENT => Self::NFS4ERR_NOENT,
+            nfsstat3::NFS3ERR_IO | nfsstat3::NFS3ERR_REMOTE => Self::NFS4ERR_IO,
+            nfsstat3::NFS3ERR_NXIO | nfsstat3::NFS3ERR_NODEV => Self::NFS4ERR_NXIO,
+            nfsstat3::NFS3ERR_ACCES => Self::NFS4ERR_ACCESS,
+            nfsstat3::NFS3ERR_EXIST => Self::NFS4ERR_EXIST,
+            nfsstat3::NFS3ERR_XDEV => Self::NFS4ERR_XDEV,
+            nfsstat3::NFS3ERR_NOTDIR => Self::NFS4ERR_NOTDIR,
+            nfsstat3::NFS3ERR_ISDIR => Self::NFS4ERR_ISDIR,
+            nfsstat3::NFS3ERR_INVAL | nfsstat3::NFS3ERR_NOT_SYNC => Self::NFS4ERR_INVAL,
+            nfsstat3::NFS3ERR_FBIG => Self::NFS4ERR_FBIG,
+            nfsstat3::NFS3ERR_NOSPC => Self::NFS4ERR_NOSPC,
+            nfsstat3::NFS3ERR_ROFS => Self::NFS4ERR_ROFS,
+            nfsstat3::NFS3ERR_MLINK => Self::NFS4ERR_MLINK,
+            nfsstat3::NFS3ERR_NAMETOOLONG => Self::NFS4ERR_NAMETOOLONG,
+            nfsstat3::NFS3ERR_NOTEMPTY => Self::NFS4ERR_NOTEMPTY,
+            nfsstat3::NFS3ERR_DQUOT => Self::NFS4ERR_DQUOT,
+            nfsstat3::NFS3ERR_STALE => Self::NFS4ERR_STALE,
+            nfsstat3::NFS3ERR_BADHANDLE => Self::NFS4ERR_BADHANDLE,
+            nfsstat3::NFS3ERR_BAD_COOKIE => Self::NFS4ERR_BAD_COOKIE,
+            nfsstat3::NFS3ERR_NOTSUPP => Self::NFS4ERR_NOTSUPP,
+            nfsstat3::NFS3ERR_TOOSMALL => Self::NFS4ERR_TOOSMALL,
+            nfsstat3::NFS3ERR_SERVERFAULT => Self::NFS4ERR_SERVERFAULT,
+            nfsstat3::NFS3ERR_BADTYPE => Self::NFS4ERR_BADTYPE,
+            nfsstat3::NFS3ERR_JUKEBOX => Self::NFS4ERR_DELAY,
+        }
+    }
+}
+
+/// The result of an operation which carries data only on success
+#[derive(Debug, PartialEq, Eq)]
+pub enum Nfs4Result<T> {
+    Ok(T),
+    Err(nfsstat4),
+}
+
+impl<T: std::fmt::Debug> Nfs4Result<T> {
+    /// Returns the status of the operation
+    pub const fn status(&self) -> nfsstat4 {
+        match self {
+            Self::Ok(_) => nfsstat4::NFS4_OK,
+            Self::Err(stat) => *stat,
+        }
+    }
+
+    /// Returns the contained value, consuming the result.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the result is an `Err`.
+    pub fn unwrap(self) -> T {
+        match self {
+            Self::Ok(val) => val,
+            Self::Err(stat) => panic!("NFS4 error: {stat}"),
+        }
+    }
+}
+
+impl<T> From<Result<T, nfsstat4>> for Nfs4Result<T> {
+    fn from(result: Result<T, nfsstat4>) -> Self {
+        match result {
+            Ok(val) => Self::Ok(val),
+            Err(stat) => Self::Err(stat),
+        }
+    }
+}
+
+impl<T: Pack> Pack for Nfs4Result<T> {
+    fn packed_size(&self) -> usize {
+        match self {
+            Self::Ok(v) => nfsstat4::NFS4_OK.packed_size() + v.packed_size(),
+            Self::Err(stat) => stat.packed_size(),
+        }
+    }
+
+    fn pack(&self, out: &mut impl Write) -> crate::xdr_codec::Result<usize> {
+        let len = match self {
+            Self::Ok(v) => nfsstat4::NFS4_OK.pack(out)? + v.pack(out)?,
+            Self::Err(stat) => stat.pack(out)?,
+        };
+        Ok(len)
+    }
+}
+
+impl<T: Unpack> Unpack for Nfs4Result<T> {
+    fn unpack(input: &mut impl Read) -> crate::xdr_codec::Result<(Self, usize)> {
+        let (stat, len) = nfsstat4::unpack(input)?;
+        if stat == nfsstat4::NFS4_OK {
+            let (val, val_len) = T::unpack(input)?;
+            Ok((Self::Ok(val), len + val_len))
+        } else {
+            Ok((Self::Err(stat), len))
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, XdrCodec)]
+#[repr(u32)]
+pub enum nfs_ftype4 {
+    #[default]
+    NF4REG = 1,
+    NF4DIR = 2,
+    NF4BLK = 3,
+    NF4CHR = 4,
+    NF4LNK = 5,
+    NF4SOCK = 6,
+    NF4FIFO = 7,
+    NF4ATTRDIR = 8,
+    NF4NAMEDATTR = 9,
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, XdrCodec)]
+pub struct specdata4 {
+    pub specdata1: u32,
+    pub specdata2: u32,
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, XdrCodec)]
+pub struct fsid4 {
+    pub major: u64,
+    pub minor: u64,
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, XdrCodec)]
+pub struct nfstime4 {
+    pub seconds: i64,
+    pub nseconds: u32,
+}
+
+/// The value of the `time_access_set` and `time_modify_set` attributes
+#[derive(Copy, Clone, Debug, PartialEq, Eq, XdrCodec)]
+pub enum settime4 {
+    #[xdr(0)]
+    SET_TO_SERVER_TIME4,
+    #[xdr(1)]
+    SET_TO_CLIENT_TIME4(nfstime4),
+}
+
+/// A set of attributes and their values, encoded in the order of the attribute numbers
+#[derive(Clone, Debug, PartialEq, Eq, XdrCodec)]
+pub struct fattr4<'a> {
+    pub attrmask: bitmap4,
+    pub attr_vals: attrlist4<'a>,
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, XdrCodec)]
+pub struct change_info4 {
+    pub atomic: bool,
+    pub before: changeid4,
+    pub after: changeid4,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, XdrCodec)]
+pub struct netaddr4<'a> {
+    pub na_r_netid: Opaque<'a>,
+    pub na_r_addr: Opaque<'a>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, XdrCodec)]
+pub struct cb_client4<'a> {
+    pub cb_program: u32,
+    pub cb_location: netaddr4<'a>,
+}
+
+/// Identifies the open files and the locks. The special stateids are all zeros and all ones.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash, XdrCodec)]
+pub struct stateid4 {
+    pub seqid: u32,
+    pub other: [u8; NFS4_OTHER_SIZE],
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, XdrCodec)]
+pub struct nfs_client_id4<'a> {
+    pub verifier: verifier4,
+    pub id: Opaque<'a>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, XdrCodec)]
+pub struct open_owner4<'a> {
+    pub clientid: clientid4,
+    pub owner: Opaque<'a>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, XdrCodec)]
+pub struct lock_owner4<'a> {
+    pub clientid: clientid4,
+    pub owner: Opaque<'a>,
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, XdrCodec)]
+#[repr(u32)]
+pub enum stable_how4 {
+    #[default]
+    UNSTABLE4 = 0,
+    DATA_SYNC4 = 1,
+    FILE_SYNC4 = 2,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, XdrCodec)]
+pub struct ACCESS4args {
+    pub access: u32,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, XdrCodec)]
+pub struct ACCESS4resok {
+    pub supported: u32,
+    pub access: u32,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, XdrCodec)]
+pub struct CLOSE4args {
+    pub seqid: seqid4,
+    pub open_stateid: stateid4,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, XdrCodec)]
+pub struct COMMIT4args {
+    pub offset: offset4,
+    pub count: count4,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, XdrCodec)]
+pub struct COMMIT4resok {
+    pub writeverf: verifier4,
+}
+
+/// The type of the object created by CREATE. Regular files are created with OPEN.
+#[derive(Clone, Debug, PartialEq, Eq, XdrCodec)]
+pub enum createtype4<'a> {
+    #[xdr(2)]
+    NF4DIR,
+    #[xdr(3)]
+    NF4BLK(specdata4),
+    #[xdr(4)]
+    NF4CHR(specdata4),
+    #[xdr(5)]
+    NF4LNK(linktext4<'a>),
+    #[xdr(6)]
+    NF4SOCK,
+    #[xdr(7)]
+    NF4FIFO,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, XdrCodec)]
+pub struct CREATE4args<'a> {
+    pub objtype: createtype4<'a>,
+    pub objname: component4<'a>,
+    pub createattrs: fattr4<'a>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, XdrCodec)]
+pub struct CREATE4resok {
+    pub cinfo: change_info4,
+    pub attrset: bitmap4,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, XdrCodec)]
+pub struct GETATTR4args {
+    pub attr_request: bitmap4,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, XdrCodec)]
+pub struct GETATTR4resok<'a> {
+    pub obj_attributes: fattr4<'a>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, XdrCodec)]
+pub struct GETFH4resok<'a> {
+    pub object: nfs_fh4<'a>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, XdrCodec)]
+pub struct LOOKUP4args<'a> {
+    pub objname: component4<'a>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, XdrCodec)]
+pub enum createhow4<'a> {
+    #[xdr(0)]
+    UNCHECKED4(fattr4<'a>),
+    #[xdr(1)]
+    GUARDED4(fattr4<'a>),
+    #[xdr(2)]
+    EXCLUSIVE4(verifier4),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, XdrCodec)]
+pub enum openflag4<'a> {
+    #[xdr(0)]
+    OPEN4_NOCREATE,
+    #[xdr(1)]
+    OPEN4_CREATE(createhow4<'a>),
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, XdrCodec)]
+#[repr(u32)]
+pub enum open_delegation_type4 {
+    #[default]
+    OPEN_DELEGATE_NONE = 0,
+    OPEN_DELEGATE_READ = 1,
+    OPEN_DELEGATE_WRITE = 2,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, XdrCodec)]
+pub struct open_claim_delegate_cur4<'a> {
+    pub delegate_stateid: stateid4,
+    pub file: component4<'a>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, XdrCodec)]
+pub enum open_claim4<'a> {
+    /// Opens the file by name in the current directory
+    #[xdr(0)]
+    CLAIM_NULL(component4<'a>),
+    /// Reclaims the open of the current file after a server restart
+    #[xdr(1)]
+    CLAIM_PREVIOUS(open_delegation_type4),
+    #[xdr(2)]
+    CLAIM_DELEGATE_CUR(open_claim_delegate_cur4<'a>),
+    #[xdr(3)]
+    CLAIM_DELEGATE_PREV(component4<'a>),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, XdrCodec)]
+pub struct OPEN4args<'a> {
+    pub seqid: seqid4,
+    pub share_access: u32,
+    pub share_deny: u32,
+    pub owner: open_owner4<'a>,
+    pub openhow: openflag4<'a>,
+    pub claim: open_claim4<'a>,
+}
+
+/// The delegation granted by OPEN. Delegations are never granted, so read and write
+/// delegations aren't defined.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, XdrCodec)]
+#[repr(u32)]
+pub enum open_delegation4 {
+    #[default]
+    OPEN_DELEGATE_NONE = 0,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, XdrCodec)]
+pub struct OPEN4resok {
+    pub stateid: stateid4,
+    pub cinfo: change_info4,
+    pub rflags: u32,
+    pub attrset: bitmap4,
+    pub delegation: open_delegation4,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, XdrCodec)]
+pub struct OPEN_CONFIRM4args {
+    pub open_stateid: stateid4,
+    pub seqid: seqid4,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, XdrCodec)]
+pub struct OPEN_CONFIRM4resok {
+    pub open_stateid: stateid4,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, XdrCodec)]
+pub struct PUTFH4args<'a> {
+    pub object: nfs_fh4<'a>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, XdrCodec)]
+pub struct READ4args {
+    pub stateid: stateid4,
+    pub offset: offset4,
+    pub count: count4,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, XdrCodec)]
+pub struct READ4resok<'a> {
+    pub eof: bool,
+    pub data: Opaque<'a>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, XdrCodec)]
+pub struct READDIR4args {
+    pub cookie: nfs_cookie4,
+    pub cookieverf: verifier4,
+    pub dircount: count4,
+    pub maxcount: count4,
+    pub attr_request: bitmap4,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, XdrCodec)]
+pub struct entry4<'a> {
+    pub cookie: nfs_cookie4,
+    pub name: component4<'a>,
+    pub attrs: fattr4<'a>,
+}
+
+#[derive(Debug, XdrCodec)]
+pub struct dirlist4<'a> {
+    pub entries: List<entry4<'a>>,
+    pub eof: bool,
+}
+
+#[derive(Debug, XdrCodec)]
+pub struct READDIR4resok<'a> {
+    pub cookieverf: verifier4,
+    pub reply: dirlist4<'a>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, XdrCodec)]
+pub struct READLINK4resok<'a> {
+    pub link: linktext4<'a>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, XdrCodec)]
+pub struct REMOVE4args<'a> {
+    pub target: component4<'a>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, XdrCodec)]
+pub struct REMOVE4resok {
+    pub cinfo: change_info4,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, XdrCodec)]
+pub struct RENAME4args<'a> {
+    pub oldname: component4<'a>,
+    pub newname: component4<'a>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, XdrCodec)]
+pub struct RENAME4resok {
+    pub source_cinfo: change_info4,
+    pub target_cinfo: change_info4,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, XdrCodec)]
+pub struct RENEW4args {
+    pub clientid: clientid4,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, XdrCodec)]
+pub struct SETATTR4args<'a> {
+    pub stateid: stateid4,
+    pub obj_attributes: fattr4<'a>,
+}
+
+/// The result of SETATTR. Unlike other results, it carries the attributes that were set
+/// even on failure.
+#[derive(Clone, Debug, PartialEq, Eq, XdrCodec)]
+pub struct SETATTR4res {
+    pub status: nfsstat4,
+    pub attrsset: bitmap4,
+}
+
+impl SETATTR4res {
+    /// Returns the status of the operation
+    #[must_use]
+    pub const fn status(&self) -> nfsstat4 {
+        self.status
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, XdrCodec)]
+pub struct SETCLIENTID4args<'a> {
+    pub client: nfs_client_id4<'a>,
+    pub callback: cb_client4<'a>,
+    pub callback_ident: u32,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, XdrCodec)]
+pub struct SETCLIENTID4resok {
+    pub clientid: clientid4,
+    pub setclientid_confirm: verifier4,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, XdrCodec)]
+pub struct SETCLIENTID_CONFIRM4args {
+    pub clientid: clientid4,
+    pub setclientid_confirm: verifier4,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, XdrCodec)]
+pub struct WRITE4args<'a> {
+    pub stateid: stateid4,
+    pub offset: offset4,
+    pub stable: stable_how4,
+    pub data: Opaque<'a>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, XdrCodec)]
+pub struct WRITE4resok {
+    pub count: count4,
+    pub committed: stable_how4,
+    pub writeverf: verifier4,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, XdrCodec)]
+pub struct RELEASE_LOCKOWNER4args<'a> {
+    pub lock_owner: lock_owner4<'a>,
+}
+
+pub type ACCESS4res = Nfs4Result<ACCESS4resok>;
+pub type CLOSE4res = Nfs4Result<stateid4>;
+pub type COMMIT4res = Nfs4Result<COMMIT4resok>;
+pub type CREATE4res = Nfs4Result<CREATE4resok>;
+pub type GETATTR4res<'a> = Nfs4Result<GETATTR4resok<'a>>;
+pub type GETFH4res<'a> = Nfs4Result<GETFH4resok<'a>>;
+pub type LOOKUP4res = Nfs4Result<Void>;
+pub type LOOKUPP4res = Nfs4Result<Void>;
+pub type OPEN4res = Nfs4Result<OPEN4resok>;
+pub type OPEN_CONFIRM4res = Nfs4Result<OPEN_CONFIRM4resok>;
+pub type PUTFH4res = Nfs4Result<Void>;
+pub type PUTPUBFH4res = Nfs4Result<Void>;
+pub type PUTROOTFH4res = Nfs4Result<Void>;
+pub type READ4res<'a> = Nfs4Result<READ4resok<'a>>;
+pub type READDIR4res<'a> = Nfs4Result<READDIR4resok<'a>>;
+pub type READLINK4res<'a> = Nfs4Result<READLINK4resok<'a>>;
+pub type REMOVE4res = Nfs4Result<REMOVE4resok>;
+pub type RENAME4res = Nfs4Result<RENAME4resok>;
+pub type RENEW4res = Nfs4Result<Void>;
+pub type RESTOREFH4res = Nfs4Result<Void>;
+pub type SAVEFH4res = Nfs4Result<Void>;
+/// `NFS4ERR_CLID_INUSE` carries the address of the client using the id, which isn't
+/// decoded. The server never returns it.
+pub type SETCLIENTID4res = Nfs4Result<SETCLIENTID4resok>;
+pub type SETCLIENTID_CONFIRM4res = Nfs4Result<Void>;
+pub type WRITE4res = Nfs4Result<WRITE4resok>;
+pub type RELEASE_LOCKOWNER4res = Nfs4Result<Void>;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, XdrCodec)]
+#[repr(u32)]
+pub enum nfs_opnum4 {
+    OP_ACCESS = 3,
+    OP_CLOSE = 4,
+    OP_COMMIT = 5,
+    OP_CREATE = 6,
+    OP_DELEGPURGE = 7,
+    OP_DELEGRETURN = 8,
+    OP_GETATTR = 9,
+    OP_GETFH = 10,
+    OP_LINK = 11,
+    OP_LOCK = 12,
+    OP_LOCKT = 13,
+    OP_LOCKU = 14,
+    OP_LOOKUP = 15,
+    OP_LOOKUPP = 16,
+    OP_NVERIFY = 17,
+    OP_OPEN = 18,
+    OP_OPENATTR = 19,
+    OP_OPEN_CONFIRM = 20,
+    OP_OPEN_DOWNGRADE = 21,
+    OP_PUTFH = 22,
+    OP_PUTPUBFH = 23,
+    OP_PUTROOTFH = 24,
+    OP_READ = 25,
+    OP_READDIR = 26,
+    OP_READLINK = 27,
+    OP_REMOVE = 28,
+    OP_RENAME = 29,
+    OP_RENEW = 30,
+    OP_RESTOREFH = 31,
+    OP_SAVEFH = 32,
+    OP_SECINFO = 33,
+    OP_SETATTR = 34,
+    OP_SETCLIENTID = 35,
+    OP_SETCLIENTID_CONFIRM = 36,
+    OP_VERIFY = 37,
+    OP_WRITE = 38,
+    OP_RELEASE_LOCKOWNER = 39,
+    OP_ILLEGAL = 10044,
+}
+
+impl std::convert::TryFrom<u32> for nfs_opnum4 {
+    type Error = crate::xdr_codec::Error;
+
+    fn try_from(value: u32) -> Result<Self, Self::Error> {
+        let op = match value {
+            3 => Self::OP_ACCESS,
+            4 => Self::OP_CLOSE,
+            5 => Self::OP_COMMIT,
+            6 => Self::OP_CREATE,
+            7 => Self::OP_DELEGPURGE,
+            8 => Self::OP_DELEGRETURN,
+            9 => Self::OP_GETATTR,
+            10 => Self::OP_GETFH,
+            11 => Self::OP_LINK,
+            12 => Self::OP_LOCK,
+            13 => Self::OP_LOCKT,
+            14 => Self::OP_LOCKU,
+            15 => Self::OP_LOOKUP,
+            16 => Self::OP_LOOKUPP,
+            17 => Self::OP_NVERIFY,
+            18 => Self::OP_OPEN,
+            19 => Self::OP_OPENATTR,
+            20 => Self::OP_OPEN_CONFIRM,
+            21 => Self::OP_OPEN_DOWNGRADE,
+            22 => Self::OP_PUTFH,
+            23 => Self::OP_PUTPUBFH,
+            24 => Self::OP_PUTROOTFH,
+            25 => Self::OP_READ,
+            26 => Self::OP_READDIR,
+            27 => Self::OP_READLINK,
+            28 => Self::OP_REMOVE,
+            29 => Self::OP_RENAME,
+            30 => Self::OP_RENEW,
+            31 => Self::OP_RESTOREFH,
+            32 => Self::OP_SAVEFH,
+            33 => Self::OP_SECINFO,
+            34 => Self::OP_SETATTR,
+            35 => Self::OP_SETCLIENTID,
+            36 => Self::OP_SETCLIENTID_CONFIRM,
+            37 => Self::OP_VERIFY,
+            38 => Self::OP_WRITE,
+            39 => Self::OP_RELEASE_LOCKOWNER,
+            10044 => Self::OP_ILLEGAL,
+            _ => return Err(crate::xdr_codec::Error::InvalidEnumValue(value)),
+        };
+        Ok(op)
+    }
+}
+
+impl std::fmt::Display for nfs_opnum4 {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        std::fmt::Debug::fmt(self, f)
+    }
+}
+
+/// Defines `nfs_argop4` and `nfs_resop4` for the supported operations
+macro_rules! nfs_operations {
+    ($($op:ident($args:ty) -> $res:ty;)*) => {
+        /// An operation of a COMPOUND request
+        #[derive(Debug, PartialEq, Eq)]
+        pub enum nfs_argop4<'a> {
+            $($op($args),)*
+            /// An operation whose arguments can't be decoded. It must be the last one.
+            Unsupported(nfs_opnum4),
+        }
+
+        impl<'a> nfs_argop4<'a> {
+            /// Returns the number of the operation
+            pub const fn opnum(&self) -> nfs_opnum4 {
+                match self {
+                    $(Self::$op(_) => nfs_opnum4::$op,)*
+                    Self::Unsupported(op) => *op,
+                }
+            }
+        }
+
+        impl<'a> Pack for nfs_argop4<'a> {
+            fn packed_size(&self) -> usize {
+                4 + match self {
+                    $(Self::$op(args) => args.packed_size(),)*
+                    Self::Unsupported(_) => 0,
+                }
+            }
+
+            fn pack(&self, out: &mut impl Write) -> crate::xdr_codec::Result<usize> {
+                let len = (self.opnum() as u32).pack(out)?;
+                let args_len = match self {
+                    $(Self::$op(args) => args.pack(out)?,)*
+                    Self::Unsupported(op) => return Err(Error::InvalidEnumValue(*op as u32)),
+                };
+                Ok(len + args_len)
+            }
+        }
+
+        impl<'a> Unpack for nfs_argop4<'a> {
+            /// Decodes an operation. The arguments of an unsupported operation are left in
+            /// the input.
+            fn unpack(input: &mut impl Read) -> crate::xdr_codec::Result<(Self, usize)> {
+                let (op, len) = u32::unpack(input)?;
+                let op = nfs_opnum4::try_from(op).unwrap_or(nfs_opnum4::OP_ILLEGAL);
+                let (args, args_len) = match op {
+                    $(nfs_opnum4::$op => {
+                        let (args, args_len) = <$args>::unpack(input)?;
+                        (Self::$op(args), args_len)
+                    })*
+                    _ => (Self::Unsupported(op), 0),
+                };
+                Ok((args, len + args_len))
+            }
+        }
+
+        /// The result of an operation of a COMPOUND request
+        #[derive(Debug)]
+        pub enum nfs_resop4<'a> {
+            $($op($res),)*
+            /// The error of an operation that isn't supported, e.g. `NFS4ERR_NOTSUPP`
+            Unsupported(nfs_opnum4, nfsstat4),
+        }
+
+        impl<'a> nfs_resop4<'a> {
+            /// Returns the number of the operation
+            pub const fn opnum(&self) -> nfs_opnum4 {
+                match self {
+                    $(Self::$op(_) => nfs_opnum4::$op,)*
+                    Self::Unsupported(op, _) => *op,
+                }
+            }
+
+            /// Returns the status of the operation
+            pub const fn status(&self) -> nfsstat4 {
+                match self {
+                    $(Self::$op(res) => res.status(),)*
+                    Self::Unsupported(_, stat) => *stat,
+                }
+            }
+        }
+
+        impl<'a> Pack for nfs_resop4<'a> {
+            fn packed_size(&self) -> usize {
+                4 + match self {
+                    $(Self::$op(res) => res.packed_size(),)*
+                    Self::Unsupported(_, stat) => stat.packed_size(),
+                }
+            }
+
+            fn pack(&self, out: &mut impl Write) -> crate::xdr_codec::Result<usize> {
+                let len = (self.opnum() as u32).pack(out)?;
+                let res_len = match self {
+                    $(Self::$op(res) => res.pack(out)?,)*
+                    Self::Unsupported(_, stat) => stat.pack(out)?,
+                };
+                Ok(len + res_len)
+            }
+        }
+
+        impl<'a> Unpack for nfs_resop4<'a> {
+            fn unpack(input: &mut impl Read) -> crate::xdr_codec::Result<(Self, usize)> {
+                let (op, len) = u32::unpack(input)?;
+                let op = nfs_opnum4::try_from(op)?;
+                let (res, res_len) = match op {
+                    $(nfs_opnum4::$op => {
+                        let (res, res_len) = <$res>::unpack(input)?;
+                        (Self::$op(res), res_len)
+                    })*
+                    _ => {
+                        let (stat, stat_len) = nfsstat4::unpack(input)?;
+                        (Self::Unsupported(op, stat), stat_len)
+                    }
+                };
+                Ok((res, len + res_len))
+            }
+        }
+    };
+}
+
+nfs_operations! {
+    OP_ACCESS(ACCESS4args) -> ACCESS4res;
+    OP_CLOSE(CLOSE4args) -> CLOSE4res;
+    OP_COMMIT(COMMIT4args) -> COMMIT4res;
+    OP_CREATE(CREATE4args<'a>) -> CREATE4res;
+    OP_GETATTR(GETATTR4args) -> GETATTR4res<'a>;
+    OP_GETFH(Void) -> GETFH4res<'a>;
+    OP_LOOKUP(LOOKUP4args<'a>) -> LOOKUP4res;
+    OP_LOOKUPP(Void) -> LOOKUPP4res;
+    OP_OPEN(OPEN4args<'a>) -> OPEN4res;
+    OP_OPEN_CONFIRM(OPEN_CONFIRM4args) -> OPEN_CONFIRM4res;
+    OP_PUTFH(PUTFH4args<'a>) -> PUTFH4res;
+    OP_PUTPUBFH(Void) -> PUTPUBFH4res;
+    OP_PUTROOTFH(Void) -> PUTROOTFH4res;
+    OP_READ(READ4args) -> READ4res<'a>;
+    OP_READDIR(READDIR4args) -> READDIR4res<'a>;
+    OP_READLINK(Void) -> READLINK4res<'a>;
+    OP_REMOVE(REMOVE4args<'a>) -> REMOVE4res;
+    OP_RENAME(RENAME4args<'a>) -> RENAME4res;
+    OP_RENEW(RENEW4args) -> RENEW4res;
+    OP_RESTOREFH(Void) -> RESTOREFH4res;
+    OP_SAVEFH(Void) -> SAVEFH4res;
+    OP_SETATTR(SETATTR4args<'a>) -> SETATTR4res;
+    OP_SETCLIENTID(SETCLIENTID4args<'a>) -> SETCLIENTID4res;
+    OP_SETCLIENTID_CONFIRM(SETCLIENTID_CONFIRM4args) -> SETCLIENTID_CONFIRM4res;
+    OP_WRITE(WRITE4args<'a>) -> WRITE4res;
+    OP_RELEASE_LOCKOWNER(RELEASE_LOCKOWNER4args<'a>) -> RELEASE_LOCKOWNER4res;
+}
+
+fn pack_array<T: Pack>(items: &[T], out: &mut impl Write) -> crate::xdr_codec::Result<usize> {
+    let mut len = u32::try_from(items.len())
+        .map_err(|_| Error::ObjectTooLarge(items.len()))?
+        .pack(out)?;
+    for item in items {
+        len += item.pack(out)?;
+    }
+    Ok(len)
+}
+
+fn array_packed_size<T: Pack>(items: &[T]) -> usize {
+    4 + items.iter().map(Pack::packed_size).sum::<usize>()
+}
+
+#[derive(Debug)]
+pub struct COMPOUND4args<'a> {
+    pub tag: utf8str_cs<'a>,
+    pub minorversion: u32,
+    pub argarray: Vec<nfs_argop4<'a>>,
+}
+
+impl Pack for COMPOUND4args<'_> {
+    fn packed_size(&self) -> usize {
+        self.tag.packed_size() + self.minorversion.packed_size() + array_packed_size(&self.argarray)
+    }
+
+    fn pack(&self, out: &mut impl Write) -> crate::xdr_codec::Result<usize> {
+        let mut len = self.tag.pack(out)?;
+        len += self.minorversion.pack(out)?;
+        len += pack_array(&self.argarray, out)?;
+        Ok(len)
+    }
+}
+
+impl Unpack for COMPOUND4args<'_> {
+    /// Decodes the request up to the first unsupported operation. The rest of the input
+    /// is consumed, as well as everything after the minor version if it isn't 0.
+    fn unpack(input: &mut impl Read) -> crate::xdr_codec::Result<(Self, usize)> {
+        let (tag, mut len) = Opaque::unpack(input)?;
+        let (minorversion, size) = u32::unpack(input)?;
+        len += size;
+        let mut argarray = Vec::new();
+        if minorversion == 0 {
+            let (count, size) = u32::unpack(input)?;
+            len += size;
+            for _ in 0..count {
+                let (op, size) = nfs_argop4::unpack(input)?;
+                len += size;
+                let is_unsupported = matches!(op, nfs_argop4::Unsupported(_));
+                argarray.push(op);
+                if is_unsupported {
+                    break;
+                }
+            }
+        }
+        len += input.read_to_end(&mut Vec::new())?;
+        Ok((
+            Self {
+                tag,
+                minorversion,
+                argarray,
+            },
+            len,
+        ))
+    }
+}
+
+#[derive(Debug)]
+pub struct COMPOUND4res<'a> {
+    pub status: nfsstat4,
+    pub tag: utf8str_cs<'a>,
+    pub resarray: Vec<nfs_resop4<'a>>,
+}
+
+impl Pack for COMPOUND4res<'_> {
+    fn packed_size(&self) -> usize {
+        self.status.packed_size() + self.tag.packed_size() + array_packed_size(&self.resarray)
+    }
+
+    fn pack(&self, out: &mut impl Write) -> crate::xdr_codec::Result<usize> {
+        let mut len = self.status.pack(out)?;
+        len += self.tag.pack(out)?;
+        len += pack_array(&self.resarray, out)?;
+        Ok(len)
+    }
+}
+
+impl Unpack for COMPOUND4res<'_> {
+    fn unpack(input: &mut impl Read) -> crate::xdr_codec::Result<(Self, usize)> {
+        let (status, mut len) = nfsstat4::unpack(input)?;
+        let (tag, size) = Opaque::unpack(input)?;
+        len += size;
+        let (count, size) = u32::unpack(input)?;
+        len += size;
+        let mut resarray = Vec::new();
+        for _ in 0..count {
+            let (res, size) = nfs_resop4::unpack(input)?;
+            len += size;
+            resarray.push(res);
+        }
+        Ok((
+            Self {
+                status,
+                tag,
+                resarray,
+            },
+            len,
+        ))
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, XdrCodec)]
+#[repr(u32)]
+pub enum NFS4_PROGRAM {
+    NFSPROC4_NULL = 0,
+    NFSPROC4_COMPOUND = 1,
+}
+
+impl std::convert::TryFrom<u32> for NFS4_PROGRAM {
+    type Error = crate::xdr_codec::Error;
+
+    fn try_from(value: u32) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(Self::NFSPROC4_NULL),
+            1 => Ok(Self::NFSPROC4_COMPOUND),
+            _ => Err(crate::xdr_codec::Error::InvalidEnumValue(value)),
+        }
+    }
+}
+
+impl std::fmt::Display for NFS4_PROGRAM {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        std::fmt::Debug::fmt(self, f)
+    }
+}
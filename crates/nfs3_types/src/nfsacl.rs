@@ -0,0 +1,201 @@
+#![allow(
+    non_camel_case_types,
+    clippy::large_enum_variant,
+    clippy::upper_case_acronyms
+)]
+
+//! This module contains the definitions of the NFSACL protocol version 3.
+//!
+//! The protocol isn't described by an RFC. It originates from Solaris and is used by Linux
+//! NFS clients to transfer POSIX ACLs (`getfacl`/`setfacl`) over `NFSv3`.
+
+use std::io::{Read, Write};
+
+use nfs3_macros::XdrCodec;
+
+use crate::nfs3::{Nfs3Result, nfs_fh3, post_op_attr};
+use crate::xdr_codec::{Error, Pack, Unpack};
+
+pub const PROGRAM: u32 = 100_227;
+pub const VERSION: u32 = 3;
+
+/// Maximum number of entries in a single ACL
+pub const NFS_ACL_MAX_ENTRIES: usize = 1024;
+
+// Bits of `GETACL3args::mask` and `secattr::mask`
+pub const NFS_ACL: u32 = 0x0001;
+pub const NFS_ACLCNT: u32 = 0x0002;
+pub const NFS_DFACL: u32 = 0x0004;
+pub const NFS_DFACLCNT: u32 = 0x0008;
+pub const NFS_ACL_MASK: u32 = NFS_ACL | NFS_ACLCNT | NFS_DFACL | NFS_DFACLCNT;
+
+// Values of `aclent::type_`
+pub const ACL_USER_OBJ: u32 = 0x01;
+pub const ACL_USER: u32 = 0x02;
+pub const ACL_GROUP_OBJ: u32 = 0x04;
+pub const ACL_GROUP: u32 = 0x08;
+pub const ACL_MASK: u32 = 0x10;
+pub const ACL_OTHER: u32 = 0x20;
+/// Flag set in `aclent::type_` of the entries of a default ACL
+pub const NFS_ACL_DEFAULT: u32 = 0x1000;
+
+// Bits of `aclent::perm`
+pub const ACL_READ: u32 = 0x04;
+pub const ACL_WRITE: u32 = 0x02;
+pub const ACL_EXECUTE: u32 = 0x01;
+
+pub type GETACL3res = Nfs3Result<GETACL3resok, GETACL3resfail>;
+pub type SETACL3res = Nfs3Result<SETACL3resok, SETACL3resfail>;
+
+/// A single ACL entry
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, XdrCodec)]
+pub struct aclent {
+    pub type_: u32,
+    pub id: u32,
+    pub perm: u32,
+}
+
+/// Access and default ACLs of a file system object
+///
+/// `mask` tells which parts are present. `aclcnt` and `dfaclcnt` are the total number of
+/// entries, which can be sent without the entries themselves.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct secattr {
+    pub mask: u32,
+    pub aclcnt: u32,
+    pub aclent: Vec<aclent>,
+    pub dfaclcnt: u32,
+    pub dfaclent: Vec<aclent>,
+}
+
+fn pack_entries(entries: &[aclent], out: &mut impl Write) -> crate::xdr_codec::Result<usize> {
+    if entries.len() > NFS_ACL_MAX_ENTRIES {
+        return Err(Error::ObjectTooLarge(entries.len()));
+    }
+    #[allow(clippy::cast_possible_truncation)] // checked above
+    let mut len = (entries.len() as u32).pack(out)?;
+    for entry in entries {
+        len += entry.pack(out)?;
+    }
+    Ok(len)
+}
+
+fn unpack_entries(input: &mut impl Read) -> crate::xdr_codec::Result<(Vec<aclent>, usize)> {
+    let (count, mut len) = u32::unpack(input)?;
+    let count = count as usize;
+    if count > NFS_ACL_MAX_ENTRIES {
+        return Err(Error::InvalidLength(count));
+    }
+    let mut entries = Vec::with_capacity(count);
+    for _ in 0..count {
+        let (entry, entry_len) = aclent::unpack(input)?;
+        len += entry_len;
+        entries.push(entry);
+    }
+    Ok((entries, len))
+}
+
+impl Pack for secattr {
+    fn packed_size(&self) -> usize {
+        let entry_size = aclent::default().packed_size();
+        4 * 5 + entry_size * (self.aclent.len() + self.dfaclent.len())
+    }
+
+    fn pack(&self, out: &mut impl Write) -> crate::xdr_codec::Result<usize> {
+        let mut len = self.mask.pack(out)?;
+        len += self.aclcnt.pack(out)?;
+        len += pack_entries(&self.aclent, out)?;
+        len += self.dfaclcnt.pack(out)?;
+        len += pack_entries(&self.dfaclent, out)?;
+        Ok(len)
+    }
+}
+
+impl Unpack for secattr {
+    fn unpack(input: &mut impl Read) -> crate::xdr_codec::Result<(Self, usize)> {
+        let (mask, mut len) = u32::unpack(input)?;
+        let (aclcnt, size) = u32::unpack(input)?;
+        len += size;
+        let (access, size) = unpack_entries(input)?;
+        len += size;
+        let (dfaclcnt, size) = u32::unpack(input)?;
+        len += size;
+        let (default, size) = unpack_entries(input)?;
+        len += size;
+        Ok((
+            Self {
+                mask,
+                aclcnt,
+                aclent: access,
+                dfaclcnt,
+                dfaclent: default,
+            },
+            len,
+        ))
+    }
+}
+
+#[derive(Debug, XdrCodec)]
+pub struct GETACL3args {
+    pub fh: nfs_fh3,
+    pub mask: u32,
+}
+
+#[derive(Debug, XdrCodec)]
+pub struct GETACL3resok {
+    pub attr: post_op_attr,
+    pub acl: secattr,
+}
+
+#[derive(Debug, Default, XdrCodec)]
+pub struct GETACL3resfail {
+    pub attr: post_op_attr,
+}
+
+#[derive(Debug, XdrCodec)]
+pub struct SETACL3args {
+    pub fh: nfs_fh3,
+    pub acl: secattr,
+}
+
+#[derive(Debug, XdrCodec)]
+pub struct SETACL3resok {
+    pub attr: post_op_attr,
+}
+
+#[derive(Debug, Default, XdrCodec)]
+pub struct SETACL3resfail {
+    pub attr: post_op_attr,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, XdrCodec)]
+#[repr(u32)]
+pub enum NFSACL_PROGRAM {
+    ACLPROC3_NULL = 0,
+    ACLPROC3_GETACL = 1,
+    ACLPROC3_SETACL = 2,
+}
+
+impl std::convert::TryFrom<u32> for NFSACL_PROGRAM {
+    type Error = crate::xdr_codec::Error;
+
+    fn try_from(value: u32) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(Self::ACLPROC3_NULL),
+            1 => Ok(Self::ACLPROC3_GETACL),
+            2 => Ok(Self::ACLPROC3_SETACL),
+            _ => Err(crate::xdr_codec::Error::InvalidEnumValue(value)),
+        }
+    }
+}
+
+impl std::fmt::Display for NFSACL_PROGRAM {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Self::ACLPROC3_NULL => "ACLPROC3_NULL",
+            Self::ACLPROC3_GETACL => "ACLPROC3_GETACL",
+            Self::ACLPROC3_SETACL => "ACLPROC3_SETACL",
+        };
+        write!(f, "{name}")
+    }
+}
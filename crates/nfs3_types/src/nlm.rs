@@ -0,0 +1,303 @@
+#![allow(
+    non_camel_case_types,
+    clippy::large_enum_variant,
+    clippy::upper_case_acronyms
+)]
+
+//! This module contains the definitions of the Network Lock Manager protocol version 4.
+//!
+//! The protocol is described in the X/Open "Protocols for X/Open PC Interworking: XNFS"
+//! specification. Version 4 is the one used together with `NFSv3`.
+
+use std::io::{Read, Write};
+
+use crate::xdr_codec::{Opaque, Pack, Unpack, XdrCodec};
+
+pub const PROGRAM: u32 = 100_021;
+pub const VERSION: u32 = 4;
+
+/// Maximum length of `caller_name`
+pub const LM_MAXSTRLEN: usize = 1024;
+/// Maximum length of a `netobj`
+pub const MAXNETOBJ_SZ: usize = 1024;
+
+/// Opaque object, such as a cookie, a file handle or a lock owner
+pub type netobj<'a> = Opaque<'a>;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, XdrCodec)]
+#[repr(u32)]
+pub enum nlm4_stats {
+    NLM4_GRANTED = 0,
+    NLM4_DENIED = 1,
+    NLM4_DENIED_NOLOCKS = 2,
+    NLM4_BLOCKED = 3,
+    NLM4_DENIED_GRACE_PERIOD = 4,
+    NLM4_DEADLCK = 5,
+    NLM4_ROFS = 6,
+    NLM4_STALE_FH = 7,
+    NLM4_FBIG = 8,
+    NLM4_FAILED = 9,
+}
+
+impl std::fmt::Display for nlm4_stats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Self::NLM4_GRANTED => "NLM4_GRANTED",
+            Self::NLM4_DENIED => "NLM4_DENIED",
+            Self::NLM4_DENIED_NOLOCKS => "NLM4_DENIED_NOLOCKS",
+            Self::NLM4_BLOCKED => "NLM4_BLOCKED",
+            Self::NLM4_DENIED_GRACE_PERIOD => "NLM4_DENIED_GRACE_PERIOD",
+            Self::NLM4_DEADLCK => "NLM4_DEADLCK",
+            Self::NLM4_ROFS => "NLM4_ROFS",
+            Self::NLM4_STALE_FH => "NLM4_STALE_FH",
+            Self::NLM4_FBIG => "NLM4_FBIG",
+            Self::NLM4_FAILED => "NLM4_FAILED",
+        };
+        write!(f, "{name}")
+    }
+}
+
+/// Description of a lock that conflicts with the tested one
+#[derive(Clone, Debug, PartialEq, Eq, XdrCodec)]
+pub struct nlm4_holder<'a> {
+    pub exclusive: bool,
+    pub svid: i32,
+    pub oh: netobj<'a>,
+    pub l_offset: u64,
+    pub l_len: u64,
+}
+
+/// Result of `NLMPROC4_TEST`. Only `NLM4_DENIED` carries the conflicting lock.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum nlm4_testrply<'a> {
+    Denied(nlm4_holder<'a>),
+    Stat(nlm4_stats),
+}
+
+impl Pack for nlm4_testrply<'_> {
+    fn packed_size(&self) -> usize {
+        match self {
+            Self::Denied(holder) => nlm4_stats::NLM4_DENIED.packed_size() + holder.packed_size(),
+            Self::Stat(stat) => stat.packed_size(),
+        }
+    }
+
+    fn pack(&self, output: &mut impl Write) -> crate::xdr_codec::Result<usize> {
+        let len = match self {
+            Self::Denied(holder) => {
+                let mut len = nlm4_stats::NLM4_DENIED.pack(output)?;
+                len += holder.pack(output)?;
+                len
+            }
+            Self::Stat(stat) => stat.pack(output)?,
+        };
+        Ok(len)
+    }
+}
+
+impl Unpack for nlm4_testrply<'_> {
+    fn unpack(input: &mut impl Read) -> crate::xdr_codec::Result<(Self, usize)> {
+        let (stat, len) = nlm4_stats::unpack(input)?;
+        let (res, res_len) = match stat {
+            nlm4_stats::NLM4_DENIED => {
+                let (holder, holder_len) = nlm4_holder::unpack(input)?;
+                (Self::Denied(holder), holder_len)
+            }
+            _ => (Self::Stat(stat), 0),
+        };
+        Ok((res, len + res_len))
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, XdrCodec)]
+pub struct nlm4_stat {
+    pub stat: nlm4_stats,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, XdrCodec)]
+pub struct nlm4_res<'a> {
+    pub cookie: netobj<'a>,
+    pub stat: nlm4_stat,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, XdrCodec)]
+pub struct nlm4_testres<'a, 'b> {
+    pub cookie: netobj<'a>,
+    pub stat: nlm4_testrply<'b>,
+}
+
+/// A byte range lock. `l_len` of zero means "up to the end of the file".
+#[derive(Clone, Debug, PartialEq, Eq, XdrCodec)]
+pub struct nlm4_lock<'a, 'b, 'c> {
+    pub caller_name: Opaque<'a>,
+    pub fh: netobj<'b>,
+    pub oh: netobj<'c>,
+    pub svid: i32,
+    pub l_offset: u64,
+    pub l_len: u64,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, XdrCodec)]
+pub struct nlm4_lockargs<'a, 'b, 'c, 'd> {
+    pub cookie: netobj<'a>,
+    pub block: bool,
+    pub exclusive: bool,
+    pub alock: nlm4_lock<'b, 'c, 'd>,
+    pub reclaim: bool,
+    pub state: i32,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, XdrCodec)]
+pub struct nlm4_cancargs<'a, 'b, 'c, 'd> {
+    pub cookie: netobj<'a>,
+    pub block: bool,
+    pub exclusive: bool,
+    pub alock: nlm4_lock<'b, 'c, 'd>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, XdrCodec)]
+pub struct nlm4_testargs<'a, 'b, 'c, 'd> {
+    pub cookie: netobj<'a>,
+    pub exclusive: bool,
+    pub alock: nlm4_lock<'b, 'c, 'd>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, XdrCodec)]
+pub struct nlm4_unlockargs<'a, 'b, 'c, 'd> {
+    pub cookie: netobj<'a>,
+    pub alock: nlm4_lock<'b, 'c, 'd>,
+}
+
+/// Access the holder of a share reservation denies to others
+#[derive(Copy, Clone, Debug, PartialEq, Eq, XdrCodec)]
+#[repr(u32)]
+pub enum fsh4_mode {
+    fsm_DN = 0,
+    fsm_DR = 1,
+    fsm_DW = 2,
+    fsm_DRW = 3,
+}
+
+/// Access the holder of a share reservation requests
+#[derive(Copy, Clone, Debug, PartialEq, Eq, XdrCodec)]
+#[repr(u32)]
+pub enum fsh4_access {
+    fsa_NONE = 0,
+    fsa_R = 1,
+    fsa_W = 2,
+    fsa_RW = 3,
+}
+
+/// A DOS-style share reservation
+#[derive(Clone, Debug, PartialEq, Eq, XdrCodec)]
+pub struct nlm4_share<'a, 'b, 'c> {
+    pub caller_name: Opaque<'a>,
+    pub fh: netobj<'b>,
+    pub oh: netobj<'c>,
+    pub mode: fsh4_mode,
+    pub access: fsh4_access,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, XdrCodec)]
+pub struct nlm4_shareargs<'a, 'b, 'c, 'd> {
+    pub cookie: netobj<'a>,
+    pub share: nlm4_share<'b, 'c, 'd>,
+    pub reclaim: bool,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, XdrCodec)]
+pub struct nlm4_shareres<'a> {
+    pub cookie: netobj<'a>,
+    pub stat: nlm4_stats,
+    pub sequence: i32,
+}
+
+/// Arguments of `NLMPROC4_FREE_ALL`: the host whose locks are released
+#[derive(Clone, Debug, PartialEq, Eq, XdrCodec)]
+pub struct nlm4_notify<'a> {
+    pub name: Opaque<'a>,
+    pub state: i32,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, XdrCodec)]
+#[repr(u32)]
+pub enum NLM_PROGRAM {
+    NLMPROC4_NULL = 0,
+    NLMPROC4_TEST = 1,
+    NLMPROC4_LOCK = 2,
+    NLMPROC4_CANCEL = 3,
+    NLMPROC4_UNLOCK = 4,
+    NLMPROC4_GRANTED = 5,
+    NLMPROC4_TEST_MSG = 6,
+    NLMPROC4_LOCK_MSG = 7,
+    NLMPROC4_CANCEL_MSG = 8,
+    NLMPROC4_UNLOCK_MSG = 9,
+    NLMPROC4_GRANTED_MSG = 10,
+    NLMPROC4_TEST_RES = 11,
+    NLMPROC4_LOCK_RES = 12,
+    NLMPROC4_CANCEL_RES = 13,
+    NLMPROC4_UNLOCK_RES = 14,
+    NLMPROC4_GRANTED_RES = 15,
+    NLMPROC4_SHARE = 20,
+    NLMPROC4_UNSHARE = 21,
+    NLMPROC4_NM_LOCK = 22,
+    NLMPROC4_FREE_ALL = 23,
+}
+
+impl std::convert::TryFrom<u32> for NLM_PROGRAM {
+    type Error = crate::xdr_codec::Error;
+
+    fn try_from(value: u32) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(Self::NLMPROC4_NULL),
+            1 => Ok(Self::NLMPROC4_TEST),
+            2 => Ok(Self::NLMPROC4_LOCK),
+            3 => Ok(Self::NLMPROC4_CANCEL),
+            4 => Ok(Self::NLMPROC4_UNLOCK),
+            5 => Ok(Self::NLMPROC4_GRANTED),
+            6 => Ok(Self::NLMPROC4_TEST_MSG),
+            7 => Ok(Self::NLMPROC4_LOCK_MSG),
+            8 => Ok(Self::NLMPROC4_CANCEL_MSG),
+            9 => Ok(Self::NLMPROC4_UNLOCK_MSG),
+            10 => Ok(Self::NLMPROC4_GRANTED_MSG),
+            11 => Ok(Self::NLMPROC4_TEST_RES),
+            12 => Ok(Self::NLMPROC4_LOCK_RES),
+            13 => Ok(Self::NLMPROC4_CANCEL_RES),
+            14 => Ok(Self::NLMPROC4_UNLOCK_RES),
+            15 => Ok(Self::NLMPROC4_GRANTED_RES),
+            20 => Ok(Self::NLMPROC4_SHARE),
+            21 => Ok(Self::NLMPROC4_UNSHARE),
+            22 => Ok(Self::NLMPROC4_NM_LOCK),
+            23 => Ok(Self::NLMPROC4_FREE_ALL),
+            _ => Err(crate::xdr_codec::Error::InvalidEnumValue(value)),
+        }
+    }
+}
+
+impl std::fmt::Display for NLM_PROGRAM {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Self::NLMPROC4_NULL => "NLMPROC4_NULL",
+            Self::NLMPROC4_TEST => "NLMPROC4_TEST",
+            Self::NLMPROC4_LOCK => "NLMPROC4_LOCK",
+            Self::NLMPROC4_CANCEL => "NLMPROC4_CANCEL",
+            Self::NLMPROC4_UNLOCK => "NLMPROC4_UNLOCK",
+            Self::NLMPROC4_GRANTED => "NLMPROC4_GRANTED",
+            Self::NLMPROC4_TEST_MSG => "NLMPROC4_TEST_MSG",
+            Self::NLMPROC4_LOCK_MSG => "NLMPROC4_LOCK_MSG",
+            Self::NLMPROC4_CANCEL_MSG => "NLMPROC4_CANCEL_MSG",
+            Self::NLMPROC4_UNLOCK_MSG => "NLMPROC4_UNLOCK_MSG",
+            Self::NLMPROC4_GRANTED_MSG => "NLMPROC4_GRANTED_MSG",
+            Self::NLMPROC4_TEST_RES => "NLMPROC4_TEST_RES",
+            Self::NLMPROC4_LOCK_RES => "NLMPROC4_LOCK_RES",
+            Self::NLMPROC4_CANCEL_RES => "NLMPROC4_CANCEL_RES",
+            Self::NLMPROC4_UNLOCK_RES => "NLMPROC4_UNLOCK_RES",
+            Self::NLMPROC4_GRANTED_RES => "NLMPROC4_GRANTED_RES",
+            Self::NLMPROC4_SHARE => "NLMPROC4_SHARE",
+            Self::NLMPROC4_UNSHARE => "NLMPROC4_UNSHARE",
+            Self::NLMPROC4_NM_LOCK => "NLMPROC4_NM_LOCK",
+            Self::NLMPROC4_FREE_ALL => "NLMPROC4_FREE_ALL",
+        };
+        write!(f, "{name}")
+    }
+}
@@ -0,0 +1,114 @@
+#![allow(
+    non_camel_case_types,
+    clippy::large_enum_variant,
+    clippy::upper_case_acronyms
+)]
+
+//! This module contains the definitions of the Network Status Monitor protocol version 1.
+//!
+//! The status monitor tells lock managers about reboots of the hosts they hold locks for.
+//! It's described in the X/Open "Protocols for X/Open PC Interworking: XNFS" specification.
+
+use crate::xdr_codec::{Opaque, XdrCodec};
+
+pub const PROGRAM: u32 = 100_024;
+pub const VERSION: u32 = 1;
+
+/// Maximum length of a host name
+pub const SM_MAXSTRLEN: usize = 1024;
+
+#[derive(Clone, Debug, PartialEq, Eq, XdrCodec)]
+pub struct sm_name<'a> {
+    pub mon_name: Opaque<'a>,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, XdrCodec)]
+#[repr(u32)]
+pub enum res {
+    STAT_SUCC = 0,
+    STAT_FAIL = 1,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, XdrCodec)]
+pub struct sm_stat_res {
+    pub res_stat: res,
+    pub state: i32,
+}
+
+/// The state number of a host. It's odd while the host is up and even while it's down.
+#[derive(Clone, Debug, PartialEq, Eq, XdrCodec)]
+pub struct sm_stat {
+    pub state: i32,
+}
+
+/// The RPC procedure to call back when the monitored host changes state
+#[derive(Clone, Debug, PartialEq, Eq, XdrCodec)]
+pub struct my_id<'a> {
+    pub my_name: Opaque<'a>,
+    pub my_prog: i32,
+    pub my_vers: i32,
+    pub my_proc: i32,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, XdrCodec)]
+pub struct mon_id<'a, 'b> {
+    pub mon_name: Opaque<'a>,
+    pub my_id: my_id<'b>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, XdrCodec)]
+pub struct mon<'a, 'b> {
+    pub mon_id: mon_id<'a, 'b>,
+    pub priv_: [u8; 16],
+}
+
+/// Arguments of `SM_NOTIFY`: the host that changed state and its new state
+#[derive(Clone, Debug, PartialEq, Eq, XdrCodec)]
+pub struct stat_chge<'a> {
+    pub mon_name: Opaque<'a>,
+    pub state: i32,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, XdrCodec)]
+#[repr(u32)]
+pub enum SM_PROGRAM {
+    SM_NULL = 0,
+    SM_STAT = 1,
+    SM_MON = 2,
+    SM_UNMON = 3,
+    SM_UNMON_ALL = 4,
+    SM_SIMU_CRASH = 5,
+    SM_NOTIFY = 6,
+}
+
+impl std::convert::TryFrom<u32> for SM_PROGRAM {
+    type Error = crate::xdr_codec::Error;
+
+    fn try_from(value: u32) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(Self::SM_NULL),
+            1 => Ok(Self::SM_STAT),
+            2 => Ok(Self::SM_MON),
+            3 => Ok(Self::SM_UNMON),
+            4 => Ok(Self::SM_UNMON_ALL),
+            5 => Ok(Self::SM_SIMU_CRASH),
+            6 => Ok(Self::SM_NOTIFY),
+            _ => Err(crate::xdr_codec::Error::InvalidEnumValue(value)),
+        }
+    }
+}
+
+impl std::fmt::Display for SM_PROGRAM {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Self::SM_NULL => "SM_NULL",
+            Self::SM_STAT => "SM_STAT",
+            Self::SM_MON => "SM_MON",
+            Self::SM_UNMON => "SM_UNMON",
+            Self::SM_UNMON_ALL => "SM_UNMON_ALL",
+            Self::SM_SIMU_CRASH => "SM_SIMU_CRASH",
+            Self::SM_NOTIFY => "SM_NOTIFY",
+        };
+        write!(f, "{name}")
+    }
+}
@@ -113,9 +113,15 @@ pub enum auth_flavor {
     AUTH_UNIX = 1,
     AUTH_SHORT = 2,
     AUTH_DES = 3,
+    /// RPC-with-TLS probe (RFC 9289)
+    AUTH_TLS = 7,
     // and more to be defined
 }
 
+/// Verifier body a server returns in reply to an `AUTH_TLS` probe when it supports
+/// RPC-with-TLS (RFC 9289). The client starts a TLS handshake after receiving it.
+pub const STARTTLS_VERIFIER: &[u8; 8] = b"STARTTLS";
+
 #[derive(Clone, Debug, XdrCodec)]
 pub struct opaque_auth<'a> {
     pub flavor: auth_flavor,
@@ -62,6 +62,26 @@ impl Unpack for u32 {
     }
 }
 
+impl Pack for i32 {
+    fn packed_size(&self) -> usize {
+        4
+    }
+
+    fn pack(&self, out: &mut impl Write) -> Result<usize> {
+        let bytes = self.to_be_bytes();
+        out.write_all(&bytes).map_err(Error::Io)?;
+        Ok(4)
+    }
+}
+
+impl Unpack for i32 {
+    fn unpack(input: &mut impl Read) -> Result<(Self, usize)> {
+        let mut bytes = [0u8; 4];
+        input.read_exact(&mut bytes).map_err(Error::Io)?;
+        Ok((Self::from_be_bytes(bytes), 4))
+    }
+}
+
 impl Pack for u64 {
     fn packed_size(&self) -> usize {
         8
@@ -82,6 +102,26 @@ impl Unpack for u64 {
     }
 }
 
+impl Pack for i64 {
+    fn packed_size(&self) -> usize {
+        8
+    }
+
+    fn pack(&self, out: &mut impl Write) -> Result<usize> {
+        let bytes = self.to_be_bytes();
+        out.write_all(&bytes).map_err(Error::Io)?;
+        Ok(8)
+    }
+}
+
+impl Unpack for i64 {
+    fn unpack(input: &mut impl Read) -> Result<(Self, usize)> {
+        let mut bytes = [0u8; 8];
+        input.read_exact(&mut bytes).map_err(Error::Io)?;
+        Ok((Self::from_be_bytes(bytes), 8))
+    }
+}
+
 impl Pack for bool {
     fn packed_size(&self) -> usize {
         4
@@ -0,0 +1,122 @@
+// Tests for NFSv2 and MOUNT v1 protocol types
+#![allow(clippy::unwrap_used)]
+
+use std::io::Cursor;
+
+use nfs3_types::mount::{fhstatus, mountstat3};
+use nfs3_types::nfs2::{
+    NFS_PROGRAM, READDIR2res, READDIR2resok, attrstat2, entry2, fattr2, fhandle2, filename2,
+    ftype2, nfsstat2, sattr2,
+};
+use nfs3_types::nfs3::nfsstat3;
+use nfs3_types::xdr_codec::{List, Opaque, Pack, Unpack};
+
+fn pack(value: &impl Pack) -> Vec<u8> {
+    let mut buf = Vec::new();
+    let len = value.pack(&mut buf).unwrap();
+    assert_eq!(len, value.packed_size());
+    assert_eq!(len, buf.len());
+    buf
+}
+
+#[test]
+fn fhandle_has_fixed_size() {
+    let fh = fhandle2([7; 32]);
+    let buf = pack(&fh);
+    assert_eq!(buf.len(), 32);
+    let (unpacked, len) = fhandle2::unpack(&mut Cursor::new(&buf)).unwrap();
+    assert_eq!(len, 32);
+    assert_eq!(unpacked, fh);
+}
+
+#[test]
+fn attrstat_roundtrip() {
+    let attr = fattr2 {
+        type_: ftype2::NFDIR,
+        mode: 0o040_755,
+        nlink: 2,
+        size: 4096,
+        fileid: 42,
+        ..fattr2::default()
+    };
+    let buf = pack(&attrstat2::Ok(attr.clone()));
+    // status + 14 words of attributes + 3 times of 2 words
+    assert_eq!(buf.len(), 4 + 68);
+    let (unpacked, _) = attrstat2::unpack(&mut Cursor::new(&buf)).unwrap();
+    assert_eq!(unpacked, attrstat2::Ok(attr));
+
+    let buf = pack(&attrstat2::Err(nfsstat2::NFSERR_STALE));
+    assert_eq!(buf, [0, 0, 0, 70]);
+}
+
+#[test]
+fn sattr_default_is_unset() {
+    let buf = pack(&sattr2::default());
+    assert_eq!(buf.len(), 32);
+    assert!(buf.iter().all(|&b| b == 0xff));
+}
+
+#[test]
+fn readdir_res_roundtrip() {
+    let res = READDIR2res::Ok(READDIR2resok {
+        entries: List(vec![
+            entry2 {
+                fileid: 1,
+                name: filename2(Opaque::borrowed(b".")),
+                cookie: 1u32.to_be_bytes(),
+            },
+            entry2 {
+                fileid: 2,
+                name: filename2(Opaque::borrowed(b"file")),
+                cookie: 2u32.to_be_bytes(),
+            },
+        ]),
+        eof: true,
+    });
+    let buf = pack(&res);
+    let (unpacked, len) = READDIR2res::unpack(&mut Cursor::new(&buf)).unwrap();
+    assert_eq!(len, buf.len());
+    let resok = unpacked.unwrap();
+    assert!(resok.eof);
+    assert_eq!(resok.entries.0.len(), 2);
+    assert_eq!(resok.entries.0[1].name.0.as_ref(), b"file");
+    assert_eq!(resok.entries.0[1].cookie, [0, 0, 0, 2]);
+}
+
+#[test]
+fn fhstatus_roundtrip() {
+    let buf = pack(&fhstatus::Ok(fhandle2([1; 32])));
+    assert_eq!(buf.len(), 36);
+    let (unpacked, _) = fhstatus::unpack(&mut Cursor::new(&buf)).unwrap();
+    assert!(matches!(unpacked, fhstatus::Ok(fh) if fh == fhandle2([1; 32])));
+
+    let buf = pack(&fhstatus::Err(mountstat3::MNT3ERR_NOENT));
+    assert_eq!(buf, [0, 0, 0, 2]);
+    let (unpacked, _) = fhstatus::unpack(&mut Cursor::new(&buf)).unwrap();
+    assert!(matches!(unpacked, fhstatus::Err(mountstat3::MNT3ERR_NOENT)));
+}
+
+#[test]
+fn status_from_nfs3() {
+    assert_eq!(
+        nfsstat2::from(nfsstat3::NFS3ERR_NOENT),
+        nfsstat2::NFSERR_NOENT
+    );
+    assert_eq!(
+        nfsstat2::from(nfsstat3::NFS3ERR_BADHANDLE),
+        nfsstat2::NFSERR_STALE
+    );
+    assert_eq!(
+        nfsstat2::from(nfsstat3::NFS3ERR_NOTSUPP),
+        nfsstat2::NFSERR_IO
+    );
+}
+
+#[test]
+fn procedure_numbers() {
+    assert!(matches!(
+        NFS_PROGRAM::try_from(17),
+        Ok(NFS_PROGRAM::NFSPROC_STATFS)
+    ));
+    assert!(NFS_PROGRAM::try_from(18).is_err());
+}
@@ -0,0 +1,126 @@
+// Tests for NFSv4 protocol types
+#![cfg(feature = "nfs4")]
+#![allow(clippy::unwrap_used)]
+
+use std::io::Cursor;
+
+use nfs3_types::nfs4::{
+    COMPOUND4args, COMPOUND4res, GETATTR4args, GETFH4resok, LOOKUP4args, Nfs4Result, nfs_argop4,
+    nfs_opnum4, nfs_resop4, nfsstat4,
+};
+use nfs3_types::xdr_codec::{Opaque, Pack, Unpack, Void};
+
+fn pack(value: &impl Pack) -> Vec<u8> {
+    let mut buf = Vec::new();
+    let len = value.pack(&mut buf).unwrap();
+    assert_eq!(len, value.packed_size());
+    assert_eq!(len, buf.len());
+    buf
+}
+
+#[test]
+fn compound_args_roundtrip() {
+    let args = COMPOUND4args {
+        tag: Opaque::borrowed(b"tag"),
+        minorversion: 0,
+        argarray: vec![
+            nfs_argop4::OP_PUTROOTFH(Void),
+            nfs_argop4::OP_LOOKUP(LOOKUP4args {
+                objname: Opaque::borrowed(b"file"),
+            }),
+            nfs_argop4::OP_GETATTR(GETATTR4args {
+                attr_request: vec![0x0010_011a, 0x00b0_a23a],
+            }),
+        ],
+    };
+
+    let buf = pack(&args);
+    let (unpacked, len) = COMPOUND4args::unpack(&mut Cursor::new(&buf)).unwrap();
+    assert_eq!(len, buf.len());
+    assert_eq!(unpacked.tag.as_ref(), b"tag");
+    assert_eq!(unpacked.argarray, args.argarray);
+}
+
+#[test]
+fn compound_res_roundtrip() {
+    let res = COMPOUND4res {
+        status: nfsstat4::NFS4ERR_NOENT,
+        tag: Opaque::borrowed(b""),
+        resarray: vec![
+            nfs_resop4::OP_PUTROOTFH(Nfs4Result::Ok(Void)),
+            nfs_resop4::OP_GETFH(Nfs4Result::Ok(GETFH4resok {
+                object: Opaque::borrowed(&[1, 2, 3, 4]),
+            })),
+            nfs_resop4::OP_LOOKUP(Nfs4Result::Err(nfsstat4::NFS4ERR_NOENT)),
+        ],
+    };
+
+    let buf = pack(&res);
+    let (unpacked, len) = COMPOUND4res::unpack(&mut Cursor::new(&buf)).unwrap();
+    assert_eq!(len, buf.len());
+    assert_eq!(unpacked.status, nfsstat4::NFS4ERR_NOENT);
+    assert_eq!(
+        format!("{:?}", unpacked.resarray),
+        format!("{:?}", res.resarray)
+    );
+    assert_eq!(unpacked.resarray[2].status(), nfsstat4::NFS4ERR_NOENT);
+}
+
+#[test]
+fn compound_args_stop_at_unsupported_operation() {
+    let mut buf = pack(&Opaque::borrowed(b""));
+    buf.extend(pack(&0u32)); // minor version
+    buf.extend(pack(&3u32)); // number of operations
+    buf.extend(pack(&(nfs_opnum4::OP_PUTROOTFH as u32)));
+    buf.extend(pack(&(nfs_opnum4::OP_LOCK as u32)));
+    buf.extend([0xde, 0xad, 0xbe, 0xef]); // arguments of LOCK
+    buf.extend(pack(&(nfs_opnum4::OP_GETFH as u32)));
+
+    let (args, len) = COMPOUND4args::unpack(&mut Cursor::new(&buf)).unwrap();
+    assert_eq!(len, buf.len());
+    assert_eq!(
+        args.argarray,
+        vec![
+            nfs_argop4::OP_PUTROOTFH(Void),
+            nfs_argop4::Unsupported(nfs_opnum4::OP_LOCK)
+        ]
+    );
+}
+
+#[test]
+fn compound_args_illegal_operation() {
+    let mut buf = pack(&Opaque::borrowed(b""));
+    buf.extend(pack(&0u32));
+    buf.extend(pack(&1u32));
+    buf.extend(pack(&1u32)); // operation 1 doesn't exist
+
+    let (args, _) = COMPOUND4args::unpack(&mut Cursor::new(&buf)).unwrap();
+    assert_eq!(
+        args.argarray,
+        vec![nfs_argop4::Unsupported(nfs_opnum4::OP_ILLEGAL)]
+    );
+}
+
+#[test]
+fn compound_args_other_minor_version() {
+    let mut buf = pack(&Opaque::borrowed(b"v1"));
+    buf.extend(pack(&1u32));
+    buf.extend(pack(&1u32));
+    buf.extend(pack(&53u32)); // SEQUENCE
+    buf.extend([0; 32]);
+
+    let (args, len) = COMPOUND4args::unpack(&mut Cursor::new(&buf)).unwrap();
+    assert_eq!(len, buf.len());
+    assert_eq!(args.minorversion, 1);
+    assert!(args.argarray.is_empty());
+}
+
+#[test]
+fn unsupported_result_roundtrip() {
+    let res = nfs_resop4::Unsupported(nfs_opnum4::OP_LOCK, nfsstat4::NFS4ERR_NOTSUPP);
+    let buf = pack(&res);
+    assert_eq!(buf.len(), 8);
+    let (unpacked, _) = nfs_resop4::unpack(&mut Cursor::new(&buf)).unwrap();
+    assert_eq!(format!("{unpacked:?}"), format!("{res:?}"));
+    assert_eq!(unpacked.status(), nfsstat4::NFS4ERR_NOTSUPP);
+}
@@ -0,0 +1,70 @@
+// Tests for NFSACL protocol types
+#![allow(clippy::unwrap_used)]
+
+use std::io::Cursor;
+
+use nfs3_types::nfsacl::{
+    ACL_GROUP_OBJ, ACL_OTHER, ACL_USER_OBJ, NFS_ACL, NFS_ACL_DEFAULT, NFS_ACL_MAX_ENTRIES,
+    NFS_ACLCNT, NFS_DFACL, NFS_DFACLCNT, aclent, secattr,
+};
+use nfs3_types::xdr_codec::{Pack, Unpack};
+
+const fn entry(type_: u32, id: u32, perm: u32) -> aclent {
+    aclent { type_, id, perm }
+}
+
+#[test]
+fn secattr_roundtrip() {
+    let acl = secattr {
+        mask: NFS_ACL | NFS_ACLCNT | NFS_DFACL | NFS_DFACLCNT,
+        aclcnt: 3,
+        aclent: vec![
+            entry(ACL_USER_OBJ, 1000, 6),
+            entry(ACL_GROUP_OBJ, 1000, 4),
+            entry(ACL_OTHER, 0, 4),
+        ],
+        dfaclcnt: 1,
+        dfaclent: vec![entry(ACL_OTHER | NFS_ACL_DEFAULT, 0, 0)],
+    };
+
+    let mut buf = Vec::new();
+    let len = acl.pack(&mut buf).unwrap();
+    assert_eq!(len, acl.packed_size());
+    // mask, aclcnt, array length, 3 entries, dfaclcnt, array length, 1 entry
+    assert_eq!(len, 4 * 3 + 12 * 3 + 4 * 2 + 12);
+    assert_eq!(&buf[8..12], &3u32.to_be_bytes());
+
+    let (unpacked, unpacked_len) = secattr::unpack(&mut Cursor::new(&buf)).unwrap();
+    assert_eq!(unpacked_len, len);
+    assert_eq!(unpacked, acl);
+}
+
+#[test]
+fn secattr_counts_without_entries() {
+    let acl = secattr {
+        mask: NFS_ACLCNT,
+        aclcnt: 5,
+        ..Default::default()
+    };
+
+    let mut buf = Vec::new();
+    acl.pack(&mut buf).unwrap();
+    let (unpacked, _) = secattr::unpack(&mut Cursor::new(&buf)).unwrap();
+    assert_eq!(unpacked.aclcnt, 5);
+    assert!(unpacked.aclent.is_empty());
+}
+
+#[test]
+fn secattr_too_many_entries() {
+    let acl = secattr {
+        aclent: vec![aclent::default(); NFS_ACL_MAX_ENTRIES + 1],
+        ..Default::default()
+    };
+    assert!(acl.pack(&mut Vec::new()).is_err());
+
+    let mut buf = Vec::new();
+    0u32.pack(&mut buf).unwrap();
+    0u32.pack(&mut buf).unwrap();
+    u32::MAX.pack(&mut buf).unwrap();
+    assert!(secattr::unpack(&mut Cursor::new(&buf)).is_err());
+}
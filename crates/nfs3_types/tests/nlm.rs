@@ -0,0 +1,78 @@
+// Tests for NLM and NSM protocol types
+#![allow(clippy::unwrap_used)]
+
+use std::io::Cursor;
+
+use nfs3_types::nlm::{nlm4_holder, nlm4_lock, nlm4_lockargs, nlm4_stats, nlm4_testrply};
+use nfs3_types::nsm::{mon, mon_id, my_id};
+use nfs3_types::xdr_codec::{Opaque, Pack, Unpack};
+
+fn roundtrip<T: Pack + Unpack + PartialEq + std::fmt::Debug>(value: &T) -> Vec<u8> {
+    let mut buf = Vec::new();
+    let len = value.pack(&mut buf).unwrap();
+    assert_eq!(len, value.packed_size());
+    assert_eq!(len, buf.len());
+
+    let (unpacked, unpacked_len) = T::unpack(&mut Cursor::new(&buf)).unwrap();
+    assert_eq!(unpacked_len, len);
+    assert_eq!(&unpacked, value);
+    buf
+}
+
+#[test]
+fn lockargs_roundtrip() {
+    let args = nlm4_lockargs {
+        cookie: Opaque::owned(vec![1, 2, 3, 4]),
+        block: true,
+        exclusive: false,
+        alock: nlm4_lock {
+            caller_name: Opaque::owned(b"client".to_vec()),
+            fh: Opaque::owned(vec![0xaa; 16]),
+            oh: Opaque::owned(b"42@client".to_vec()),
+            svid: -1,
+            l_offset: 100,
+            l_len: 0,
+        },
+        reclaim: false,
+        state: 3,
+    };
+    let buf = roundtrip(&args);
+    // cookie, block, exclusive, caller_name (padded), fh, oh (padded), svid, offset, len, ...
+    assert_eq!(buf.len(), 8 + 4 + 4 + 12 + 20 + 16 + 4 + 8 + 8 + 4 + 4);
+    assert_eq!(&buf[64..68], &[0xff; 4]);
+}
+
+#[test]
+fn testrply_roundtrip() {
+    let granted = nlm4_testrply::Stat(nlm4_stats::NLM4_GRANTED);
+    assert_eq!(roundtrip(&granted), 0u32.to_be_bytes());
+
+    let denied = nlm4_testrply::Denied(nlm4_holder {
+        exclusive: true,
+        svid: 7,
+        oh: Opaque::owned(b"owner".to_vec()),
+        l_offset: 0,
+        l_len: 10,
+    });
+    let buf = roundtrip(&denied);
+    assert_eq!(&buf[..4], &1u32.to_be_bytes());
+    assert_eq!(buf.len(), 4 + 4 + 4 + 12 + 8 + 8);
+}
+
+#[test]
+fn mon_roundtrip() {
+    let args = mon {
+        mon_id: mon_id {
+            mon_name: Opaque::owned(b"server".to_vec()),
+            my_id: my_id {
+                my_name: Opaque::owned(b"localhost".to_vec()),
+                my_prog: 100_021,
+                my_vers: 4,
+                my_proc: 16,
+            },
+        },
+        priv_: [5; 16],
+    };
+    let buf = roundtrip(&args);
+    assert_eq!(&buf[buf.len() - 16..], &[5; 16]);
+}
@@ -281,6 +281,7 @@ fn auth_flavors() {
         auth_flavor::AUTH_UNIX,
         auth_flavor::AUTH_SHORT,
         auth_flavor::AUTH_DES,
+        auth_flavor::AUTH_TLS,
     ];
 
     for flavor in auth_flavors {